@@ -2,7 +2,8 @@
 //!
 //! Reads metadata from Calibre's metadata.db SQLite database
 
-use crate::db::{Database, NewBook};
+use crate::db::{Database, NewAuthor, NewBook};
+use crate::epub::Format;
 use crate::AppResult;
 use rusqlite::Connection;
 use std::path::Path;
@@ -23,8 +24,11 @@ pub struct CalibreBook {
     pub id: i64,
     pub title: String,
     pub sort_title: Option<String>,
+    /// The first linked author, for display
     pub author: Option<String>,
     pub author_sort: Option<String>,
+    /// Every linked author, in the order Calibre lists them
+    pub authors: Vec<String>,
     pub series: Option<String>,
     pub series_index: Option<f64>,
     pub description: Option<String>,
@@ -33,8 +37,11 @@ pub struct CalibreBook {
     pub language: Option<String>,
     pub publisher: Option<String>,
     pub pubdate: Option<String>,
-    pub rating: Option<i32>,    // 0-10 in Calibre
+    /// Stars (0.5-5); Calibre stores 0-10, so odd values are half stars
+    pub rating: Option<f64>,
     pub tags: Vec<String>,
+    /// When the book last changed in Calibre (unix seconds)
+    pub last_modified: Option<i64>,
 }
 
 /// Calibre importer
@@ -87,22 +94,25 @@ impl CalibreImporter {
                 b.pubdate,
                 (SELECT name FROM authors a 
                  JOIN books_authors_link bal ON a.id = bal.author 
-                 WHERE bal.book = b.id LIMIT 1) as author,
+                 WHERE bal.book = b.id ORDER BY bal.rowid LIMIT 1) as author,
                 (SELECT sort FROM authors a 
                  JOIN books_authors_link bal ON a.id = bal.author 
-                 WHERE bal.book = b.id LIMIT 1) as author_sort,
+                 WHERE bal.book = b.id ORDER BY bal.rowid LIMIT 1) as author_sort,
                 (SELECT name FROM series s 
                  JOIN books_series_link bsl ON s.id = bsl.series 
                  WHERE bsl.book = b.id LIMIT 1) as series,
                 b.series_index,
                 (SELECT text FROM comments WHERE book = b.id) as description,
-                (SELECT rating FROM ratings WHERE book = b.id) as rating,
-                (SELECT lang_code FROM languages l 
+                (SELECT r.rating FROM ratings r
+                 JOIN books_ratings_link brl ON r.id = brl.rating
+                 WHERE brl.book = b.id LIMIT 1) as rating,
+                (SELECT l.lang_code FROM languages l 
                  JOIN books_languages_link bll ON l.id = bll.lang_code 
                  WHERE bll.book = b.id LIMIT 1) as language,
                 (SELECT name FROM publishers p 
                  JOIN books_publishers_link bpl ON p.id = bpl.publisher 
-                 WHERE bpl.book = b.id LIMIT 1) as publisher
+                 WHERE bpl.book = b.id LIMIT 1) as publisher,
+                CAST(strftime('%s', b.last_modified) AS INTEGER) as last_modified
              FROM books b"
         )?;
 
@@ -117,25 +127,33 @@ impl CalibreImporter {
                     pubdate: row.get(5)?,
                     author: row.get(6)?,
                     author_sort: row.get(7)?,
+                    authors: vec![], // Loaded separately
                     series: row.get(8)?,
                     series_index: row.get(9)?,
                     description: row.get(10)?,
-                    rating: row.get::<_, Option<i32>>(11)?.map(|r| r / 2), // Convert 0-10 to 0-5
+                    rating: row
+                        .get::<_, Option<i32>>(11)?
+                        .filter(|&r| r > 0) // 0 means unrated
+                        .map(|r| r.min(10) as f64 / 2.0),
                     language: row.get(12)?,
                     publisher: row.get(13)?,
                     tags: vec![], // Loaded separately
+                    last_modified: row.get(14)?,
                 })
             })?
             .filter_map(|r| r.ok())
             .collect();
 
-        // Load tags for each book
+        // Load tags and authors for each book
         let books_with_tags: Vec<CalibreBook> = books
             .into_iter()
             .map(|mut book| {
                 if let Ok(tags) = self.load_tags(&conn, book.id) {
                     book.tags = tags;
                 }
+                if let Ok(authors) = self.load_authors(&conn, book.id) {
+                    book.authors = authors;
+                }
                 book
             })
             .collect();
@@ -159,6 +177,23 @@ impl CalibreImporter {
         Ok(tags)
     }
 
+    /// Load a book's authors in link order
+    fn load_authors(&self, conn: &Connection, book_id: i64) -> AppResult<Vec<String>> {
+        let mut stmt = conn.prepare(
+            "SELECT a.name FROM authors a
+             JOIN books_authors_link bal ON a.id = bal.author
+             WHERE bal.book = ?
+             ORDER BY bal.rowid"
+        )?;
+
+        let authors = stmt
+            .query_map([book_id], |row| row.get(0))?
+            .filter_map(|r| r.ok())
+            .collect();
+
+        Ok(authors)
+    }
+
     /// Find the EPUB file path for a Calibre book
     pub fn find_epub_path(&self, book: &CalibreBook) -> Option<String> {
         let book_dir = Path::new(&self.library_path).join(&book.path);
@@ -169,7 +204,7 @@ impl CalibreImporter {
         if let Ok(entries) = std::fs::read_dir(&book_dir) {
             for entry in entries.flatten() {
                 let path = entry.path();
-                if path.extension().map_or(false, |e| e == "epub") {
+                if path.extension().is_some_and(|e| e == "epub") {
                     return Some(path.to_string_lossy().to_string());
                 }
             }
@@ -192,32 +227,37 @@ impl CalibreImporter {
 
     /// Convert Calibre books to NewBook format for database insertion
     pub fn to_new_books(&self, calibre_books: &[CalibreBook]) -> Vec<NewBook> {
-        calibre_books
-            .iter()
-            .filter_map(|cb| {
-                let epub_path = self.find_epub_path(cb)?;
-                let cover_path = self.find_cover_path(cb);
-
-                Some(NewBook {
-                    path: epub_path,
-                    cover_path,
-                    file_size: 0, // Will be calculated during processing
-                    file_hash: None,
-                    title: cb.title.clone(),
-                    sort_title: cb.sort_title.clone(),
-                    author: cb.author.clone(),
-                    author_sort: cb.author_sort.clone(),
-                    series: cb.series.clone(),
-                    series_index: cb.series_index,
-                    description: cb.description.clone(),
-                    language: cb.language.clone(),
-                    publisher: cb.publisher.clone(),
-                    publish_date: cb.pubdate.clone(),
-                    isbn: cb.isbn.clone(),
-                    source: "calibre".to_string(),
-                })
-            })
-            .collect()
+        calibre_books.iter().filter_map(|cb| self.to_new_book(cb)).collect()
+    }
+
+    /// Convert a Calibre book to NewBook format, if it has an EPUB
+    fn to_new_book(&self, cb: &CalibreBook) -> Option<NewBook> {
+        let epub_path = self.find_epub_path(cb)?;
+        let cover_path = self.find_cover_path(cb);
+
+        Some(NewBook {
+            path: epub_path,
+            cover_path,
+            file_size: 0, // Will be calculated during processing
+            file_hash: None,
+            title: cb.title.clone(),
+            sort_title: cb.sort_title.clone(),
+            author: cb.author.clone(),
+            author_sort: cb.author_sort.clone(),
+            series: cb.series.clone(),
+            series_index: cb.series_index,
+            description: cb.description.clone(),
+            language: cb.language.clone(),
+            publisher: cb.publisher.clone(),
+            publish_date: cb.pubdate.clone(),
+            isbn: cb.isbn.clone(),
+            source: "calibre".to_string(),
+            container_path: None,
+            word_count: None,
+            format: Format::Epub,
+            tags: cb.tags.clone(),
+            authors: new_authors(cb),
+        })
     }
 
     /// Import Calibre library into our database
@@ -228,14 +268,29 @@ impl CalibreImporter {
         let total = new_books.len();
         let inserted = db.insert_books_batch(&new_books)?;
         
-        // Import ratings
+        // Import ratings and tags
         let mut ratings_imported = 0;
+        let mut errors = Vec::new();
         for cb in &calibre_books {
-            if let (Some(rating), Some(epub_path)) = (cb.rating, self.find_epub_path(cb)) {
-                if let Ok(Some(book)) = db.get_book_by_path(&epub_path) {
-                    if let Ok(()) = db.set_rating(book.id, rating) {
-                        ratings_imported += 1;
-                    }
+            let Some(book) = self
+                .find_epub_path(cb)
+                .and_then(|epub_path| db.get_book_by_path(&epub_path).ok().flatten())
+            else {
+                continue;
+            };
+            if let Some(rating) = cb.rating {
+                if let Ok(()) = db.set_rating(book.id, rating) {
+                    ratings_imported += 1;
+                }
+            }
+            if !cb.tags.is_empty() {
+                if let Err(e) = db.set_book_tags(book.id, &cb.tags) {
+                    errors.push(format!("{}: {}", cb.title, e));
+                }
+            }
+            if !cb.authors.is_empty() {
+                if let Err(e) = db.set_book_authors(book.id, &new_authors(cb)) {
+                    errors.push(format!("{}: {}", cb.title, e));
                 }
             }
         }
@@ -244,9 +299,93 @@ impl CalibreImporter {
             books_found: total,
             books_imported: inserted.len(),
             ratings_imported,
-            errors: vec![],
+            errors,
         })
     }
+
+    /// Bring the database up to date with the Calibre library
+    ///
+    /// Books are matched on `calibre_id`, then on file path for books that
+    /// were imported or scanned before being linked. A linked book is only
+    /// rewritten when Calibre changed it after we last did, so local edits
+    /// aren't undone by a stale copy; books new to Calibre are added. Calibre's
+    /// rating is taken when it has one; read status, notes and the rest of
+    /// the user's data are left alone.
+    pub fn sync_to_database(&self, db: &Database) -> AppResult<SyncResult> {
+        let calibre_books = self.import_books()?;
+        let links = db.get_calibre_links(&self.library_path)?;
+        let mut result = SyncResult {
+            books_found: calibre_books.len(),
+            ..Default::default()
+        };
+
+        let mut added = Vec::new();
+        for cb in &calibre_books {
+            let Some(new_book) = self.to_new_book(cb) else {
+                continue;
+            };
+            let existing = match links.get(&cb.id) {
+                Some(&(book_id, date_modified)) => Some((book_id, Some(date_modified))),
+                None => db.get_book_by_path(&new_book.path)?.map(|book| (book.id, None)),
+            };
+            let Some((book_id, date_modified)) = existing else {
+                added.push((cb, new_book));
+                continue;
+            };
+
+            // Unlinked books, and books without a Calibre timestamp, are always refreshed
+            let changed = match (date_modified, cb.last_modified) {
+                (Some(ours), Some(theirs)) => theirs > ours,
+                _ => true,
+            };
+            if !changed {
+                result.books_unchanged += 1;
+                continue;
+            }
+            if let Err(e) = db.update_from_calibre(book_id, cb.id, &new_book) {
+                result.errors.push(format!("{}: {}", cb.title, e));
+                continue;
+            }
+            if let Some(rating) = cb.rating {
+                let _ = db.set_rating(book_id, rating);
+            }
+            result.books_updated += 1;
+        }
+
+        // New books go in as one batch, then get linked
+        let new_books: Vec<NewBook> = added.iter().map(|(_, book)| book.clone()).collect();
+        db.insert_books_batch(&new_books)?;
+        let mut new_links = Vec::with_capacity(added.len());
+        for (cb, new_book) in &added {
+            let Some(book) = db.get_book_by_path(&new_book.path)? else {
+                continue;
+            };
+            new_links.push((book.id, cb.id));
+            if let Some(rating) = cb.rating {
+                let _ = db.set_rating(book.id, rating);
+            }
+        }
+        db.link_calibre_books(&new_links)?;
+        result.books_added = new_links.len();
+
+        Ok(result)
+    }
+}
+
+/// A Calibre book's authors to link; Calibre doesn't record roles
+fn new_authors(book: &CalibreBook) -> Vec<NewAuthor> {
+    book.authors.iter().map(|name| NewAuthor::author(name)).collect()
+}
+
+/// Result of a Calibre sync
+#[derive(Debug, Clone, Default, serde::Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SyncResult {
+    pub books_found: usize,
+    pub books_added: usize,
+    pub books_updated: usize,
+    pub books_unchanged: usize,
+    pub errors: Vec<String>,
 }
 
 /// Result of Calibre import
@@ -270,4 +409,138 @@ mod tests {
         let path = PathBuf::from("/tmp/not_a_library");
         assert!(!CalibreImporter::is_calibre_library(&path));
     }
+
+    /// The parts of Calibre's schema the importer reads
+    const CALIBRE_SCHEMA: &str = "
+        CREATE TABLE books (id INTEGER PRIMARY KEY, title TEXT, sort TEXT, path TEXT,
+                            isbn TEXT, pubdate TEXT, series_index REAL, last_modified TIMESTAMP);
+        CREATE TABLE authors (id INTEGER PRIMARY KEY, name TEXT, sort TEXT);
+        CREATE TABLE books_authors_link (id INTEGER PRIMARY KEY, book INTEGER, author INTEGER);
+        CREATE TABLE series (id INTEGER PRIMARY KEY, name TEXT);
+        CREATE TABLE books_series_link (book INTEGER, series INTEGER);
+        CREATE TABLE comments (book INTEGER, text TEXT);
+        CREATE TABLE ratings (id INTEGER PRIMARY KEY, rating INTEGER);
+        CREATE TABLE books_ratings_link (book INTEGER, rating INTEGER);
+        CREATE TABLE languages (id INTEGER PRIMARY KEY, lang_code TEXT);
+        CREATE TABLE books_languages_link (book INTEGER, lang_code INTEGER);
+        CREATE TABLE publishers (id INTEGER PRIMARY KEY, name TEXT);
+        CREATE TABLE books_publishers_link (book INTEGER, publisher INTEGER);
+        CREATE TABLE tags (id INTEGER PRIMARY KEY, name TEXT);
+        CREATE TABLE books_tags_link (book INTEGER, tag INTEGER);";
+
+    #[test]
+    fn test_import_keeps_half_star_ratings() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let calibre = Connection::open(dir.path().join("metadata.db")).unwrap();
+        calibre.execute_batch(CALIBRE_SCHEMA).unwrap();
+        calibre
+            .execute_batch(
+                "INSERT INTO books (id, title, path) VALUES
+                    (1, 'Half', 'Author/Half (1)'), (2, 'Whole', 'Author/Whole (2)'), (3, 'Unrated', 'Author/Unrated (3)');
+                 INSERT INTO ratings (id, rating) VALUES (1, 7), (2, 10), (3, 0);
+                 INSERT INTO books_ratings_link (book, rating) VALUES (1, 1), (2, 2), (3, 3);",
+            )
+            .unwrap();
+        for folder in ["Half (1)", "Whole (2)", "Unrated (3)"] {
+            let book_dir = dir.path().join("Author").join(folder);
+            std::fs::create_dir_all(&book_dir).unwrap();
+            std::fs::write(book_dir.join("book.epub"), b"epub").unwrap();
+        }
+
+        let importer = CalibreImporter::new(dir.path().to_str().unwrap());
+        let ratings: Vec<Option<f64>> = importer.import_books().unwrap().iter().map(|b| b.rating).collect();
+        assert_eq!(ratings, vec![Some(3.5), Some(5.0), None]);
+
+        let db = Database::new_in_memory().unwrap();
+        let result = importer.import_to_database(&db).unwrap();
+        assert_eq!(result.ratings_imported, 2);
+        let half_path = dir.path().join("Author").join("Half (1)").join("book.epub");
+        let half = db.get_book_by_path(half_path.to_str().unwrap()).unwrap().unwrap();
+        assert_eq!(half.rating, Some(3.5));
+    }
+
+    #[test]
+    fn test_import_links_all_authors() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let calibre = Connection::open(dir.path().join("metadata.db")).unwrap();
+        calibre.execute_batch(CALIBRE_SCHEMA).unwrap();
+        calibre
+            .execute_batch(
+                "INSERT INTO books (id, title, path) VALUES (1, 'Good Omens', 'Pratchett/Good Omens (1)');
+                 INSERT INTO authors (id, name, sort) VALUES (1, 'Neil Gaiman', 'Gaiman, Neil'),
+                                                             (2, 'Terry Pratchett', 'Pratchett, Terry');
+                 INSERT INTO books_authors_link (book, author) VALUES (1, 2), (1, 1);",
+            )
+            .unwrap();
+        let book_dir = dir.path().join("Pratchett").join("Good Omens (1)");
+        std::fs::create_dir_all(&book_dir).unwrap();
+        std::fs::write(book_dir.join("book.epub"), b"epub").unwrap();
+
+        let importer = CalibreImporter::new(dir.path().to_str().unwrap());
+        let books = importer.import_books().unwrap();
+        assert_eq!(books[0].author.as_deref(), Some("Terry Pratchett"));
+        assert_eq!(books[0].author_sort.as_deref(), Some("Pratchett, Terry"));
+        assert_eq!(books[0].authors, vec!["Terry Pratchett", "Neil Gaiman"]);
+
+        let db = Database::new_in_memory().unwrap();
+        importer.import_to_database(&db).unwrap();
+        // The co-author finds the book too
+        let query = crate::db::BookQuery { author: Some("Neil Gaiman".to_string()), ..Default::default() };
+        let found = db.query_books(&query).unwrap();
+        assert_eq!(found.items.len(), 1);
+        assert_eq!(found.items[0].author.as_deref(), Some("Terry Pratchett"));
+    }
+
+    #[test]
+    fn test_sync_updates_changed_books_and_keeps_user_data() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let calibre = Connection::open(dir.path().join("metadata.db")).unwrap();
+        calibre.execute_batch(CALIBRE_SCHEMA).unwrap();
+        calibre
+            .execute_batch(
+                "INSERT INTO books (id, title, path, last_modified) VALUES
+                    (1, 'Dune', 'Herbert/Dune (1)', '2001-01-01 00:00:00.000000+00:00'),
+                    (2, 'Emma', 'Austen/Emma (2)', '2001-01-01 00:00:00.000000+00:00');",
+            )
+            .unwrap();
+        let add_file = |folder: &str| {
+            let book_dir = dir.path().join(folder);
+            std::fs::create_dir_all(&book_dir).unwrap();
+            let path = book_dir.join("book.epub");
+            std::fs::write(&path, b"epub").unwrap();
+            path.to_string_lossy().to_string()
+        };
+        let dune_path = add_file("Herbert/Dune (1)");
+        add_file("Austen/Emma (2)");
+
+        let db = Database::new_in_memory().unwrap();
+        let importer = CalibreImporter::new(dir.path().to_str().unwrap());
+        let first = importer.sync_to_database(&db).unwrap();
+        assert_eq!((first.books_added, first.books_updated, first.books_unchanged), (2, 0, 0));
+        let dune = db.get_book_by_path(&dune_path).unwrap().unwrap();
+        assert_eq!(dune.calibre_id, Some(1));
+        db.set_read_status(dune.id, "reading").unwrap();
+
+        // Calibre edits Dune's series and rating, and gains a book
+        calibre
+            .execute_batch(
+                "INSERT INTO series (id, name) VALUES (1, 'Dune Chronicles');
+                 INSERT INTO books_series_link (book, series) VALUES (1, 1);
+                 INSERT INTO ratings (id, rating) VALUES (1, 8);
+                 INSERT INTO books_ratings_link (book, rating) VALUES (1, 1);
+                 UPDATE books SET series_index = 1, last_modified = '2999-01-01 00:00:00+00:00' WHERE id = 1;
+                 INSERT INTO books (id, title, path, last_modified) VALUES
+                    (3, 'Ubik', 'Dick/Ubik (3)', '2001-01-01 00:00:00+00:00');",
+            )
+            .unwrap();
+        add_file("Dick/Ubik (3)");
+
+        let second = importer.sync_to_database(&db).unwrap();
+        assert_eq!((second.books_added, second.books_updated, second.books_unchanged), (1, 1, 1));
+        let dune = db.get_book_by_path(&dune_path).unwrap().unwrap();
+        assert_eq!(dune.series.as_deref(), Some("Dune Chronicles"));
+        assert_eq!(dune.series_index, Some(1.0));
+        assert_eq!(dune.rating, Some(4.0));
+        assert_eq!(dune.read_status.as_deref(), Some("reading"));
+    }
 }
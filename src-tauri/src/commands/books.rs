@@ -1,18 +1,27 @@
 //! Book query and management commands
 
-use crate::db::{Book, BookQuery, BookUpdate, PagedResult};
-use crate::epub::EpubParser;
+use crate::db::{
+    Book, BookQuery, BookUpdate, Database, DeletedBook, DuplicateGroup, PagedResult, SyncDelta, READ_STATUSES,
+};
+use crate::epub::{cover_data_url, detect_image_mime, mime_from_extension};
+use crate::scanner::{
+    ensure_cover_extracted, CoverExtractionProgress, CoverExtractionResult, CoverImportResult, CoverRefetchResult,
+    Scanner,
+};
 use crate::state::AppState;
+use crate::{AppError, AppResult};
+use super::CommandError;
+use std::path::Path;
 use std::sync::Arc;
-use tauri::State;
+use tauri::{Emitter, State};
 
 /// Query books with filtering and pagination
 #[tauri::command]
 pub async fn query_books(
     state: State<'_, Arc<AppState>>,
     query: BookQuery,
-) -> Result<PagedResult<Book>, String> {
-    state.db.query_books(&query).map_err(|e| e.to_string())
+) -> Result<PagedResult<Book>, CommandError> {
+    state.db.query_books(&query).map_err(CommandError::from)
 }
 
 /// Get a single book by ID
@@ -20,8 +29,8 @@ pub async fn query_books(
 pub async fn get_book(
     state: State<'_, Arc<AppState>>,
     id: i64,
-) -> Result<Book, String> {
-    state.db.get_book(id).map_err(|e| e.to_string())
+) -> Result<Book, CommandError> {
+    state.db.get_book(id).map_err(CommandError::from)
 }
 
 /// Update book metadata
@@ -30,8 +39,8 @@ pub async fn update_book(
     state: State<'_, Arc<AppState>>,
     id: i64,
     updates: BookUpdate,
-) -> Result<(), String> {
-    state.db.update_book(id, &updates).map_err(|e| e.to_string())
+) -> Result<(), CommandError> {
+    state.db.update_book(id, &updates).map_err(CommandError::from)
 }
 
 /// Delete a book from the database (does not delete the file)
@@ -39,21 +48,121 @@ pub async fn update_book(
 pub async fn delete_book(
     state: State<'_, Arc<AppState>>,
     id: i64,
-) -> Result<(), String> {
-    state.db.delete_book(id).map_err(|e| e.to_string())
+) -> Result<(), CommandError> {
+    state.db.delete_book(id).map_err(CommandError::from)
 }
 
-/// Set book rating (1-5)
+/// Get books added or changed since a sync cursor (unix seconds)
+#[tauri::command]
+pub async fn get_books_modified_since(
+    state: State<'_, Arc<AppState>>,
+    timestamp: i64,
+) -> Result<SyncDelta<Book>, CommandError> {
+    state.db.get_books_modified_since(timestamp).map_err(CommandError::from)
+}
+
+/// Get books deleted since a sync cursor (unix seconds)
+#[tauri::command]
+pub async fn get_deleted_since(
+    state: State<'_, Arc<AppState>>,
+    timestamp: i64,
+) -> Result<SyncDelta<DeletedBook>, CommandError> {
+    state.db.get_deleted_since(timestamp).map_err(CommandError::from)
+}
+
+/// Get books with no known cover image
+#[tauri::command]
+pub async fn get_books_without_cover(
+    state: State<'_, Arc<AppState>>,
+    limit: Option<i64>,
+) -> Result<Vec<Book>, CommandError> {
+    let limit = limit.unwrap_or(100).min(1000);
+    state.db.get_books_without_cover(limit).map_err(CommandError::from)
+}
+
+/// Look again for covers of coverless books (new files next to the EPUB,
+/// then the embedded cover)
+#[tauri::command]
+pub async fn refetch_covers(
+    state: State<'_, Arc<AppState>>,
+    limit: Option<i64>,
+) -> Result<CoverRefetchResult, CommandError> {
+    let limit = limit.unwrap_or(500).min(5000);
+    let state = state.inner().clone();
+    tokio::task::spawn_blocking(move || {
+        Scanner::new().refetch_covers(&state.db, &state.cover_dir(), limit)
+    })
+    .await?
+    .map_err(CommandError::from)
+}
+
+/// Set covers from a folder of images named by ISBN
+#[tauri::command]
+pub async fn import_covers_by_isbn(
+    state: State<'_, Arc<AppState>>,
+    dir: String,
+) -> Result<CoverImportResult, CommandError> {
+    let dir = std::path::PathBuf::from(dir);
+    if !dir.is_dir() {
+        return Err(AppError::InvalidInput(format!("{} is not a directory", dir.display())).into());
+    }
+    let state = state.inner().clone();
+    tokio::task::spawn_blocking(move || Scanner::new().import_covers_by_isbn(&state.db, &dir))
+        .await?
+        .map_err(CommandError::from)
+}
+
+/// Find books that look like different editions of the same work
+#[tauri::command]
+pub async fn find_duplicate_editions(
+    state: State<'_, Arc<AppState>>,
+) -> Result<Vec<DuplicateGroup>, CommandError> {
+    state.db.find_duplicate_editions().map_err(CommandError::from)
+}
+
+/// Likely duplicate copies, and how many books still await a file hash
+#[derive(Debug, Clone, serde::Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DuplicatesResult {
+    pub groups: Vec<DuplicateGroup>,
+    /// Books without a file hash, now queued for hashing; until they are
+    /// hashed they can only match on title and author
+    pub unhashed: usize,
+}
+
+/// Find likely duplicate copies by file hash, then by title and author
+///
+/// Books that haven't been hashed yet are queued for background hashing.
+/// Each group can be resolved with `merge_duplicate_editions`.
+#[tauri::command]
+pub async fn find_duplicates(
+    state: State<'_, Arc<AppState>>,
+) -> Result<DuplicatesResult, CommandError> {
+    let unhashed = state.queue_missing_hashes()?;
+    Ok(DuplicatesResult {
+        groups: state.db.find_duplicates()?,
+        unhashed,
+    })
+}
+
+/// Merge duplicate editions into one book, keeping its user data
+#[tauri::command]
+pub async fn merge_duplicate_editions(
+    state: State<'_, Arc<AppState>>,
+    keep_id: i64,
+    duplicate_ids: Vec<i64>,
+) -> Result<usize, CommandError> {
+    state.db.merge_duplicate_editions(keep_id, &duplicate_ids).map_err(CommandError::from)
+}
+
+/// Set book rating (0.5-5 in half-star steps)
 #[tauri::command]
 pub async fn set_rating(
     state: State<'_, Arc<AppState>>,
     book_id: i64,
-    rating: i32,
-) -> Result<(), String> {
-    if !(1..=5).contains(&rating) {
-        return Err("Rating must be between 1 and 5".to_string());
-    }
-    state.db.set_rating(book_id, rating).map_err(|e| e.to_string())
+    rating: f64,
+) -> Result<(), CommandError> {
+    state.db.set_rating(book_id, rating).map_err(CommandError::from)
 }
 
 /// Set read status
@@ -62,54 +171,178 @@ pub async fn set_read_status(
     state: State<'_, Arc<AppState>>,
     book_id: i64,
     status: String,
-) -> Result<(), String> {
-    let valid_statuses = ["unread", "want", "reading", "finished", "abandoned"];
-    if !valid_statuses.contains(&status.as_str()) {
-        return Err(format!("Invalid status. Must be one of: {:?}", valid_statuses));
+) -> Result<(), CommandError> {
+    if !READ_STATUSES.contains(&status.as_str()) {
+        return Err(AppError::InvalidInput(format!("Invalid status. Must be one of: {:?}", READ_STATUSES)).into());
     }
-    state.db.set_read_status(book_id, &status).map_err(|e| e.to_string())
+    state.db.set_read_status(book_id, &status)?;
+    if status == "finished" {
+        state.db.update_user_wpm()?;
+    }
+    Ok(())
+}
+
+/// Rate several books at once; returns how many were rated
+#[tauri::command]
+pub async fn set_ratings_batch(
+    state: State<'_, Arc<AppState>>,
+    book_ids: Vec<i64>,
+    rating: f64,
+) -> Result<usize, CommandError> {
+    state.db.set_ratings_batch(&book_ids, rating).map_err(CommandError::from)
+}
+
+/// Set the read status of several books at once; returns how many were updated
+#[tauri::command]
+pub async fn set_read_status_batch(
+    state: State<'_, Arc<AppState>>,
+    book_ids: Vec<i64>,
+    status: String,
+) -> Result<usize, CommandError> {
+    let updated = state.db.set_read_status_batch(&book_ids, &status)?;
+    if status == "finished" && updated > 0 {
+        state.db.update_user_wpm()?;
+    }
+    Ok(updated)
+}
+
+/// Set or clear free-form notes for a book
+#[tauri::command]
+pub async fn set_book_notes(
+    state: State<'_, Arc<AppState>>,
+    book_id: i64,
+    notes: Option<String>,
+) -> Result<(), CommandError> {
+    state.db.set_book_notes(book_id, notes.as_deref()).map_err(CommandError::from)
+}
+
+/// Full-text search over book notes
+#[tauri::command]
+pub async fn search_book_notes(
+    state: State<'_, Arc<AppState>>,
+    query: String,
+    limit: Option<i64>,
+) -> Result<Vec<Book>, CommandError> {
+    state.db.search_book_notes(&query, limit.unwrap_or(50)).map_err(CommandError::from)
 }
 
 /// Get cover image for a book (returns base64 encoded image data)
+///
+/// Embedded covers are extracted to the cover cache on first access and
+/// served from disk afterwards.
 #[tauri::command]
 pub async fn get_cover_image(
     state: State<'_, Arc<AppState>>,
     book_id: i64,
-) -> Result<Option<String>, String> {
-    let book = state.db.get_book(book_id).map_err(|e| e.to_string())?;
-
-    // First, try external cover file
-    if let Some(ref cover_path) = book.cover_path {
-        let path = std::path::PathBuf::from(cover_path);
-        if path.exists() {
-            let data = std::fs::read(&path).map_err(|e| e.to_string())?;
-            let mime = match path.extension().and_then(|e| e.to_str()) {
-                Some("jpg") | Some("jpeg") => "image/jpeg",
-                Some("png") => "image/png",
-                _ => "image/jpeg",
-            };
-            use ::base64::Engine;
-            let base64_str = ::base64::engine::general_purpose::STANDARD.encode(&data);
-            return Ok(Some(format!("data:{};base64,{}", mime, base64_str)));
+) -> Result<Option<String>, CommandError> {
+    let cache_dir = state.cover_dir();
+    let state = state.inner().clone();
+    tokio::task::spawn_blocking(move || load_cover_image(&state.db, &cache_dir, book_id))
+        .await?
+        .map_err(CommandError::from)
+}
+
+/// Extract the embedded covers of all coverless books to the cover cache,
+/// emitting `covers:progress` events
+#[tauri::command]
+pub async fn extract_all_covers(
+    state: State<'_, Arc<AppState>>,
+    app: tauri::AppHandle,
+) -> Result<CoverExtractionResult, CommandError> {
+    let cache_dir = state.cover_dir();
+    let state = state.inner().clone();
+    tokio::task::spawn_blocking(move || {
+        crate::scanner::extract_all_covers(&state.db, &cache_dir, |processed, total| {
+            // Throttle events for large libraries
+            if processed == total || processed % 50 == 0 {
+                let _ = app.emit("covers:progress", CoverExtractionProgress { processed, total });
+            }
+        })
+    })
+    .await?
+    .map_err(CommandError::from)
+}
+
+/// Read a book's cover as a data URL, extracting it to disk if needed
+fn load_cover_image(db: &Database, cache_dir: &Path, book_id: i64) -> AppResult<Option<String>> {
+    let cover = match ensure_cover_extracted(db, cache_dir, book_id) {
+        Ok(cover) => cover,
+        Err(e) if e.is_transient() => {
+            tracing::debug!("Cover unavailable for book {}: {}", book_id, e);
+            None
         }
+        Err(e) => return Err(e),
+    };
+    let Some(path) = cover else {
+        return Ok(None);
+    };
+
+    let data = std::fs::read(&path)?;
+    let mime = detect_image_mime(&data)
+        .or_else(|| path.extension().and_then(|e| e.to_str()).and_then(mime_from_extension))
+        .unwrap_or("image/jpeg");
+    Ok(Some(cover_data_url(&data, mime)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::epub::test_epub_bytes_with_cover;
+
+    const PNG: &[u8] = &[0x89, 0x50, 0x4E, 0x47, 0x0D, 0x0A, 0x1A, 0x0A, 1, 2, 3];
+
+    fn insert_book(db: &Database, id: i64, path: &Path) {
+        db.with_conn(|conn| {
+            conn.execute(
+                "INSERT INTO books (id, path, title) VALUES (?, ?, 'Book')",
+                rusqlite::params![id, path.to_string_lossy()],
+            )?;
+            Ok(())
+        })
+        .unwrap();
     }
 
-    // Try to extract from EPUB
-    let parser = EpubParser::new();
-    let epub_path = std::path::PathBuf::from(&book.path);
-
-    if let Ok(Some(cover_data)) = parser.extract_cover(&epub_path) {
-        // Detect image type from magic bytes
-        let mime = if cover_data.starts_with(&[0x89, 0x50, 0x4E, 0x47]) {
-            "image/png"
-        } else {
-            "image/jpeg"
-        };
-        use ::base64::Engine;
-        let base64_str = ::base64::engine::general_purpose::STANDARD.encode(&cover_data);
-        return Ok(Some(format!("data:{};base64,{}", mime, base64_str)));
+    #[test]
+    fn test_cover_is_served_from_disk_after_first_access() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let epub = dir.path().join("book.epub");
+        std::fs::write(&epub, test_epub_bytes_with_cover("Book", "Author", Some(PNG))).unwrap();
+        let cache_dir = dir.path().join("covers");
+
+        let db = Database::new_in_memory().unwrap();
+        insert_book(&db, 1, &epub);
+
+        let first = load_cover_image(&db, &cache_dir, 1).unwrap().unwrap();
+        assert!(first.starts_with("data:image/png"));
+        let cached = cache_dir.join("1.png");
+        assert_eq!(db.get_book(1).unwrap().cover_path, Some(cached.to_string_lossy().to_string()));
+
+        // The EPUB is no longer readable, so this must come from the cache
+        std::fs::write(&epub, b"not an epub anymore").unwrap();
+        assert_eq!(load_cover_image(&db, &cache_dir, 1).unwrap(), Some(first));
     }
 
-    Ok(None)
+    #[test]
+    fn test_missing_cover_is_not_retried() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let epub = dir.path().join("plain.epub");
+        std::fs::write(&epub, test_epub_bytes_with_cover("Plain", "Author", None)).unwrap();
+        let cache_dir = dir.path().join("covers");
+
+        let db = Database::new_in_memory().unwrap();
+        insert_book(&db, 1, &epub);
+
+        assert_eq!(load_cover_image(&db, &cache_dir, 1).unwrap(), None);
+        assert!(cache_dir.join("1.none").exists());
+
+        // Even a cover added to the file later is ignored until a refetch
+        std::fs::write(&epub, test_epub_bytes_with_cover("Plain", "Author", Some(PNG))).unwrap();
+        assert_eq!(load_cover_image(&db, &cache_dir, 1).unwrap(), None);
+
+        let result = Scanner::new().refetch_covers(&db, &cache_dir, 10).unwrap();
+        assert_eq!(result.extracted, 1);
+        assert!(!cache_dir.join("1.none").exists());
+        assert!(load_cover_image(&db, &cache_dir, 1).unwrap().is_some());
+    }
 }
 
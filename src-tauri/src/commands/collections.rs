@@ -0,0 +1,64 @@
+//! Collection (shelf) commands
+
+use crate::db::Collection;
+use crate::state::AppState;
+use super::CommandError;
+use std::sync::Arc;
+use tauri::State;
+
+/// Get all collections, by name
+#[tauri::command]
+pub async fn get_collections(state: State<'_, Arc<AppState>>) -> Result<Vec<Collection>, CommandError> {
+    state.db.get_collections().map_err(CommandError::from)
+}
+
+/// Get the collections a book belongs to
+#[tauri::command]
+pub async fn get_book_collections(
+    book_id: i64,
+    state: State<'_, Arc<AppState>>,
+) -> Result<Vec<Collection>, CommandError> {
+    state.db.get_book_collections(book_id).map_err(CommandError::from)
+}
+
+/// Create an empty collection
+#[tauri::command]
+pub async fn create_collection(name: String, state: State<'_, Arc<AppState>>) -> Result<Collection, CommandError> {
+    state.db.create_collection(&name).map_err(CommandError::from)
+}
+
+/// Rename a collection
+#[tauri::command]
+pub async fn rename_collection(
+    collection_id: i64,
+    name: String,
+    state: State<'_, Arc<AppState>>,
+) -> Result<Collection, CommandError> {
+    state.db.rename_collection(collection_id, &name).map_err(CommandError::from)
+}
+
+/// Delete a collection, keeping its books
+#[tauri::command]
+pub async fn delete_collection(collection_id: i64, state: State<'_, Arc<AppState>>) -> Result<(), CommandError> {
+    state.db.delete_collection(collection_id).map_err(CommandError::from)
+}
+
+/// Add books to a collection; returns how many were added
+#[tauri::command]
+pub async fn add_books_to_collection(
+    collection_id: i64,
+    book_ids: Vec<i64>,
+    state: State<'_, Arc<AppState>>,
+) -> Result<usize, CommandError> {
+    state.db.add_books_to_collection(collection_id, &book_ids).map_err(CommandError::from)
+}
+
+/// Remove books from a collection; returns how many were removed
+#[tauri::command]
+pub async fn remove_books_from_collection(
+    collection_id: i64,
+    book_ids: Vec<i64>,
+    state: State<'_, Arc<AppState>>,
+) -> Result<usize, CommandError> {
+    state.db.remove_books_from_collection(collection_id, &book_ids).map_err(CommandError::from)
+}
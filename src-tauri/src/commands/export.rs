@@ -1,20 +1,42 @@
 //! Export and backup commands
 
-use crate::db::Book;
+use crate::db::{check_schema_compatibility, schema_version, strip_root, Book, Database, Library};
+use crate::epub::Format;
 use crate::state::AppState;
+use crate::{AppError, AppResult};
+use super::CommandError;
 use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
 use std::fs::File;
 use std::io::{BufReader, BufWriter};
-use std::path::Path;
+use std::path::{Path, PathBuf};
 use std::sync::Arc;
 use tauri::State;
 
+/// Export file format written by this app, as `major.minor`
+///
+/// Minor versions only add fields, so any 1.x file can be read; a new major
+/// version means an incompatible layout.
+///
+/// - 1.0: whole-star ratings
+/// - 1.1: half-star ratings
+pub const EXPORT_FORMAT_VERSION: &str = "1.1";
+
 /// Exported library data
 #[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct ExportData {
     pub version: String,
     pub exported_at: i64,
+    /// Version of the app that wrote the export
+    #[serde(default)]
+    pub app_version: Option<String>,
+    /// Database schema version of the exporting app
+    #[serde(default)]
+    pub schema_version: Option<i32>,
+    /// Paths are relative to their library root
+    #[serde(default)]
+    pub portable: bool,
     pub books: Vec<ExportedBook>,
     pub ratings: Vec<ExportedRating>,
 }
@@ -24,6 +46,9 @@ pub struct ExportData {
 #[serde(rename_all = "camelCase")]
 pub struct ExportedBook {
     pub path: String,
+    /// Library name the path is relative to (portable exports only)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub library: Option<String>,
     pub file_hash: Option<String>,
     pub title: String,
     pub author: Option<String>,
@@ -40,7 +65,10 @@ pub struct ExportedBook {
 #[serde(rename_all = "camelCase")]
 pub struct ExportedRating {
     pub book_path: String,
-    pub rating: Option<i32>,
+    /// Library name the path is relative to (portable exports only)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub library: Option<String>,
+    pub rating: Option<f64>,
     pub read_status: Option<String>,
 }
 
@@ -48,6 +76,7 @@ impl From<&Book> for ExportedBook {
     fn from(book: &Book) -> Self {
         Self {
             path: book.path.clone(),
+            library: None,
             file_hash: book.file_hash.clone(),
             title: book.title.clone(),
             author: book.author.clone(),
@@ -61,41 +90,135 @@ impl From<&Book> for ExportedBook {
     }
 }
 
-/// Export library to JSON file
-#[tauri::command]
-pub async fn export_library(
-    state: State<'_, Arc<AppState>>,
-    path: String,
-) -> Result<ExportStats, String> {
-    let db = &state.db;
+/// Split an absolute book path into the name of the library containing it
+/// and the path relative to that library's root
+///
+/// The deepest matching library wins when library roots are nested.
+fn library_relative_path(libraries: &[Library], path: &str) -> Option<(String, String)> {
+    libraries
+        .iter()
+        .filter_map(|library| {
+            let relative = strip_root(Path::new(path), Path::new(&library.path))?;
+            let relative: Vec<_> = relative.iter().map(|c| c.to_string_lossy()).collect();
+            Some((library, relative.join("/")))
+        })
+        .max_by_key(|(library, _)| library.path.len())
+        .map(|(library, relative)| (library.name.clone(), relative))
+}
+
+/// Rebuild an absolute path from a library name and a relative path
+fn resolve_portable_path(
+    library_roots: &HashMap<String, String>,
+    library: &str,
+    relative: &str,
+) -> Option<String> {
+    let root = library_roots.get(library)?;
+    let path = relative
+        .split('/')
+        .fold(PathBuf::from(root), |path, part| path.join(part));
+    Some(path.to_string_lossy().to_string())
+}
 
+/// Build export data for every book in the database
+pub fn build_export(db: &Database, portable: bool) -> AppResult<ExportData> {
     // Query all books
     let query = crate::db::BookQuery {
         limit: Some(100000), // High limit to get all
         ..Default::default()
     };
-    let result = db.query_books(&query).map_err(|e| e.to_string())?;
-
-    // Build export data
-    let books: Vec<ExportedBook> = result.items.iter().map(ExportedBook::from).collect();
+    let result = db.query_books(&query)?;
+    let libraries = if portable { db.get_libraries()? } else { Vec::new() };
+
+    let mut books = Vec::with_capacity(result.items.len());
+    let mut ratings = Vec::new();
+    for book in &result.items {
+        let mut exported = ExportedBook::from(book);
+        if portable {
+            if let Some((library, relative)) = library_relative_path(&libraries, &book.path) {
+                exported.path = relative;
+                exported.library = Some(library);
+            }
+        }
 
-    let ratings: Vec<ExportedRating> = result
-        .items
-        .iter()
-        .filter(|b| b.rating.is_some() || b.read_status.is_some())
-        .map(|b| ExportedRating {
-            book_path: b.path.clone(),
-            rating: b.rating,
-            read_status: b.read_status.clone(),
-        })
-        .collect();
+        if book.rating.is_some() || book.read_status.is_some() {
+            ratings.push(ExportedRating {
+                book_path: exported.path.clone(),
+                library: exported.library.clone(),
+                rating: book.rating,
+                read_status: book.read_status.clone(),
+            });
+        }
+        books.push(exported);
+    }
 
-    let export_data = ExportData {
-        version: "1.0".to_string(),
+    Ok(ExportData {
+        version: EXPORT_FORMAT_VERSION.to_string(),
         exported_at: chrono::Utc::now().timestamp(),
-        books: books.clone(),
-        ratings: ratings.clone(),
-    };
+        app_version: Some(env!("CARGO_PKG_VERSION").to_string()),
+        schema_version: Some(db.schema_version()?),
+        portable,
+        books,
+        ratings,
+    })
+}
+
+/// Split a `major.minor` format version
+fn parse_format_version(version: &str) -> Option<(u32, u32)> {
+    let (major, minor) = version.trim().split_once('.')?;
+    Some((major.parse().ok()?, minor.parse().ok()?))
+}
+
+/// Read export JSON in any supported format, upgraded to the current one
+///
+/// The version is checked before the rest of the file is parsed, so an
+/// export from a newer app fails with a clear error instead of a confusing
+/// parse error or a partial import.
+pub fn read_export(json: serde_json::Value) -> AppResult<ExportData> {
+    let version = json.get("version").and_then(|v| v.as_str()).unwrap_or_default();
+    let (current_major, _) = parse_format_version(EXPORT_FORMAT_VERSION).expect("valid export format version");
+    let (major, minor) = parse_format_version(version)
+        .ok_or_else(|| AppError::InvalidInput(format!("Unrecognized export format version: {:?}", version)))?;
+    if major > current_major {
+        return Err(AppError::InvalidInput(format!(
+            "Export format {} is newer than this app supports ({}); please update the app",
+            version, EXPORT_FORMAT_VERSION
+        )));
+    }
+    if major < current_major {
+        return Err(AppError::InvalidInput(format!("Export format {} is no longer supported", version)));
+    }
+
+    let mut export_data: ExportData = serde_json::from_value(json)?;
+    if minor == 0 {
+        upgrade_from_1_0(&mut export_data);
+    }
+    export_data.version = EXPORT_FORMAT_VERSION.to_string();
+    Ok(export_data)
+}
+
+/// 1.0 to 1.1: ratings were whole stars from 1 to 5
+///
+/// They carry over unchanged; anything else was never a valid 1.0 rating
+/// and is dropped rather than misread as a half-star value.
+fn upgrade_from_1_0(export_data: &mut ExportData) {
+    for exported in &mut export_data.ratings {
+        if let Some(rating) = exported.rating {
+            if rating.fract() != 0.0 || !(1.0..=5.0).contains(&rating) {
+                tracing::warn!("Dropping invalid rating {} for {}", rating, exported.book_path);
+                exported.rating = None;
+            }
+        }
+    }
+}
+
+/// Export library to JSON file
+#[tauri::command]
+pub async fn export_library(
+    state: State<'_, Arc<AppState>>,
+    path: String,
+    portable: Option<bool>,
+) -> Result<ExportStats, CommandError> {
+    let export_data = build_export(&state.db, portable.unwrap_or(false))?;
 
     // Write to file
     let file = File::create(&path).map_err(|e| format!("Failed to create file: {}", e))?;
@@ -103,39 +226,56 @@ pub async fn export_library(
     serde_json::to_writer_pretty(writer, &export_data)
         .map_err(|e| format!("Failed to write JSON: {}", e))?;
 
-    tracing::info!("Exported {} books to {}", books.len(), path);
+    tracing::info!("Exported {} books to {}", export_data.books.len(), path);
 
     Ok(ExportStats {
-        books_exported: books.len(),
-        ratings_exported: ratings.len(),
+        books_exported: export_data.books.len(),
+        ratings_exported: export_data.ratings.len(),
         file_path: path,
     })
 }
 
-/// Import library from JSON file
-#[tauri::command]
-pub async fn import_library(
-    state: State<'_, Arc<AppState>>,
-    path: String,
-    merge_mode: String, // "replace", "skip", "merge"
-) -> Result<ImportStats, String> {
-    let db = &state.db;
+/// Apply export data to the database
+///
+/// Library-relative paths are resolved against `library_roots` (library name
+/// to local root directory), falling back to the current library with the
+/// same name. Books whose library can't be resolved are skipped.
+pub fn apply_import(
+    db: &Database,
+    export_data: &ExportData,
+    merge_mode: &str,
+    library_roots: &HashMap<String, String>,
+) -> AppResult<ImportStats> {
+    if let Some(version) = export_data.schema_version {
+        check_schema_compatibility(version)?;
+    }
 
-    // Read file
-    let file = File::open(&path).map_err(|e| format!("Failed to open file: {}", e))?;
-    let reader = BufReader::new(file);
-    let export_data: ExportData =
-        serde_json::from_reader(reader).map_err(|e| format!("Failed to parse JSON: {}", e))?;
+    let mut roots: HashMap<String, String> = db
+        .get_libraries()?
+        .into_iter()
+        .map(|library| (library.name, library.path))
+        .collect();
+    roots.extend(library_roots.iter().map(|(k, v)| (k.clone(), v.clone())));
+
+    let resolve = |path: &str, library: &Option<String>| match library {
+        Some(library) => resolve_portable_path(&roots, library, path),
+        None => Some(path.to_string()),
+    };
 
     let mut books_imported = 0;
     let mut books_skipped = 0;
     let mut ratings_imported = 0;
 
     for exported_book in &export_data.books {
+        let Some(book_path) = resolve(&exported_book.path, &exported_book.library) else {
+            books_skipped += 1;
+            continue;
+        };
+
         // Check if book already exists
-        let existing = db.get_book_by_path(&exported_book.path).ok().flatten();
+        let existing = db.get_book_by_path(&book_path).ok().flatten();
 
-        match (&existing, merge_mode.as_str()) {
+        match (&existing, merge_mode) {
             (Some(_), "skip") => {
                 books_skipped += 1;
             }
@@ -165,11 +305,11 @@ pub async fn import_library(
             }
             (None, _) => {
                 // Only import if file exists
-                if Path::new(&exported_book.path).exists() {
+                if Path::new(&book_path).exists() {
                     let new_book = crate::db::NewBook {
-                        path: exported_book.path.clone(),
+                        path: book_path.clone(),
                         cover_path: None,
-                        file_size: std::fs::metadata(&exported_book.path)
+                        file_size: std::fs::metadata(&book_path)
                             .map(|m| m.len() as i64)
                             .unwrap_or(0),
                         file_hash: exported_book.file_hash.clone(),
@@ -185,6 +325,11 @@ pub async fn import_library(
                         publish_date: None,
                         isbn: exported_book.isbn.clone(),
                         source: "import".to_string(),
+                        container_path: None,
+                        word_count: None,
+                        format: Format::from_path(Path::new(&book_path)).unwrap_or(Format::Epub),
+                        tags: Vec::new(),
+                        authors: Vec::new(),
                     };
                     if db.insert_book(&new_book).is_ok() {
                         books_imported += 1;
@@ -198,7 +343,10 @@ pub async fn import_library(
 
     // Import ratings
     for exported_rating in &export_data.ratings {
-        if let Ok(Some(book)) = db.get_book_by_path(&exported_rating.book_path) {
+        let Some(book_path) = resolve(&exported_rating.book_path, &exported_rating.library) else {
+            continue;
+        };
+        if let Ok(Some(book)) = db.get_book_by_path(&book_path) {
             if let Some(rating) = exported_rating.rating {
                 if db.set_rating(book.id, rating).is_ok() {
                     ratings_imported += 1;
@@ -224,6 +372,46 @@ pub async fn import_library(
     })
 }
 
+/// Import library from JSON file
+///
+/// `library_roots` remaps library names in a portable export to local
+/// directories, for libraries that were renamed or moved.
+#[tauri::command]
+pub async fn import_library(
+    state: State<'_, Arc<AppState>>,
+    path: String,
+    merge_mode: String, // "replace", "skip", "merge"
+    library_roots: Option<HashMap<String, String>>,
+) -> Result<ImportStats, CommandError> {
+    // Read file
+    let file = File::open(&path).map_err(|e| format!("Failed to open file: {}", e))?;
+    let reader = BufReader::new(file);
+    let json: serde_json::Value =
+        serde_json::from_reader(reader).map_err(|e| format!("Failed to parse JSON: {}", e))?;
+    let export_data = read_export(json)?;
+
+    apply_import(&state.db, &export_data, &merge_mode, &library_roots.unwrap_or_default())
+        .map_err(CommandError::from)
+}
+
+/// Export all embeddings to a binary file with a JSON sidecar
+///
+/// See `VectorStore::export_embeddings` for the file format.
+#[tauri::command]
+pub async fn export_embeddings(
+    state: State<'_, Arc<AppState>>,
+    path: String,
+) -> Result<usize, CommandError> {
+    let count = state
+        .vector_store
+        .export_embeddings(Path::new(&path))
+        .map_err(|e| format!("Failed to export embeddings: {}", e))?;
+
+    tracing::info!("Exported {} embeddings to {}", count, path);
+
+    Ok(count)
+}
+
 /// Export statistics
 #[derive(Debug, Clone, Serialize)]
 #[serde(rename_all = "camelCase")]
@@ -247,11 +435,12 @@ pub struct ImportStats {
 pub async fn create_backup(
     state: State<'_, Arc<AppState>>,
     backup_path: String,
-) -> Result<String, String> {
-    // Copy the database file
-    let db_path = state.data_dir.join("library.db");
-
-    std::fs::copy(&db_path, &backup_path)
+) -> Result<String, CommandError> {
+    // The open database may not be the one in the data directory
+    let state = state.inner().clone();
+    let dest = PathBuf::from(&backup_path);
+    tokio::task::spawn_blocking(move || write_backup(&state.db, &dest))
+        .await?
         .map_err(|e| format!("Failed to create backup: {}", e))?;
 
     tracing::info!("Created database backup at {}", backup_path);
@@ -259,17 +448,38 @@ pub async fn create_backup(
     Ok(backup_path)
 }
 
+/// Write a consistent copy of the database to `dest`
+///
+/// The copy is staged next to `dest` and replaces an existing backup only
+/// once it is complete. A staging file left by an interrupted backup is
+/// discarded first.
+fn write_backup(db: &Database, dest: &Path) -> AppResult<()> {
+    let staging = PathBuf::from(format!("{}.new", dest.display()));
+    if staging.exists() {
+        std::fs::remove_file(&staging)?;
+    }
+    db.copy_to(&staging)?;
+    std::fs::rename(&staging, dest)?;
+    Ok(())
+}
+
 /// Restore database from backup
 #[tauri::command]
 pub async fn restore_backup(
     state: State<'_, Arc<AppState>>,
     backup_path: String,
-) -> Result<(), String> {
-    let db_path = state.data_dir.join("library.db");
+) -> Result<(), CommandError> {
+    let db_path = PathBuf::from(state.db.path());
 
-    // Verify backup is valid SQLite
-    let _ = rusqlite::Connection::open(&backup_path)
+    // Verify backup is valid SQLite from a compatible app version
+    let backup = rusqlite::Connection::open(&backup_path)
         .map_err(|e| format!("Invalid backup file: {}", e))?;
+    let version = schema_version(&backup).map_err(|e| format!("Invalid backup file: {}", e))?;
+    check_schema_compatibility(version)?;
+    if version < crate::db::SCHEMA_VERSION {
+        tracing::warn!("Backup uses schema version {}; it will be migrated on next start", version);
+    }
+    drop(backup);
 
     // Copy backup to database path
     std::fs::copy(&backup_path, &db_path)
@@ -279,3 +489,135 @@ pub async fn restore_backup(
 
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_portable_export_round_trip_with_moved_library() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let old_root = dir.path().join("old");
+        let new_root = dir.path().join("moved");
+        for root in [&old_root, &new_root] {
+            std::fs::create_dir_all(root.join("Author")).unwrap();
+            std::fs::write(root.join("Author").join("book.epub"), b"epub").unwrap();
+        }
+
+        let source = Database::new(&dir.path().join("source.db")).unwrap();
+        source.add_library("Books", old_root.to_str().unwrap(), false, None).unwrap();
+        let books = crate::scanner::Scanner::new().fast_scan(&old_root).unwrap();
+        let ids = source.insert_books_batch(&books).unwrap();
+        source.set_rating(ids[0], 5.0).unwrap();
+
+        let export = build_export(&source, true).unwrap();
+        assert_eq!(export.schema_version, Some(crate::db::SCHEMA_VERSION));
+        assert_eq!(export.books[0].path, "Author/book.epub");
+        assert_eq!(export.books[0].library.as_deref(), Some("Books"));
+        let export: ExportData =
+            serde_json::from_str(&serde_json::to_string(&export).unwrap()).unwrap();
+
+        // The same library name on the new machine resolves automatically
+        let target = Database::new(&dir.path().join("target.db")).unwrap();
+        target.add_library("Books", new_root.to_str().unwrap(), false, None).unwrap();
+        let stats = apply_import(&target, &export, "merge", &HashMap::new()).unwrap();
+        assert_eq!(stats.books_imported, 1);
+        assert_eq!(stats.ratings_imported, 1);
+
+        let moved_path = new_root.join("Author").join("book.epub");
+        let book = target.get_book_by_path(moved_path.to_str().unwrap()).unwrap().unwrap();
+        assert_eq!(book.rating, Some(5.0));
+
+        // An explicit remap covers libraries that were renamed
+        let renamed = Database::new(&dir.path().join("renamed.db")).unwrap();
+        let roots = HashMap::from([("Books".to_string(), new_root.to_str().unwrap().to_string())]);
+        let stats = apply_import(&renamed, &export, "merge", &roots).unwrap();
+        assert_eq!(stats.books_imported, 1);
+        assert!(renamed.get_book_by_path(moved_path.to_str().unwrap()).unwrap().is_some());
+    }
+
+    #[test]
+    fn test_library_relative_path_matches_whole_components() {
+        let library = |name: &str, path: &str| Library {
+            id: 0,
+            name: name.to_string(),
+            path: crate::db::normalize_path(path),
+            is_calibre: false,
+            calibre_db_path: None,
+            last_scan: None,
+            watch_enabled: true,
+            book_count: 0,
+            accessible: true,
+        };
+        let libraries = [library("Books", "/lib/Books/"), library("Extra", "/lib/Books-extra")];
+        let relative = |path: &str| library_relative_path(&libraries, &crate::db::normalize_path(path));
+
+        assert_eq!(relative("/lib/Books/Author/a.epub"), Some(("Books".to_string(), "Author/a.epub".to_string())));
+        assert_eq!(relative("/lib/Books-extra/b.epub"), Some(("Extra".to_string(), "b.epub".to_string())));
+        assert_eq!(relative("/elsewhere/c.epub"), None);
+    }
+
+    #[test]
+    fn test_backup_replaces_previous_and_survives_stale_staging() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let db = Database::new(&dir.path().join("library.db")).unwrap();
+        let dest = dir.path().join("backup.db");
+
+        // An earlier backup and the staging file of one that crashed
+        std::fs::write(&dest, b"old backup").unwrap();
+        std::fs::write(dir.path().join("backup.db.new"), b"partial").unwrap();
+
+        write_backup(&db, &dest).unwrap();
+        let backup = rusqlite::Connection::open(&dest).unwrap();
+        assert_eq!(schema_version(&backup).unwrap(), crate::db::SCHEMA_VERSION);
+        assert!(!dir.path().join("backup.db.new").exists());
+    }
+
+    fn export_json(version: &str, rating: serde_json::Value) -> serde_json::Value {
+        serde_json::json!({
+            "version": version,
+            "exportedAt": 0,
+            "books": [{ "path": "/b/1.epub", "fileHash": null, "title": "One", "author": null,
+                        "series": null, "seriesIndex": null, "description": null, "language": null,
+                        "publisher": null, "isbn": null }],
+            "ratings": [{ "bookPath": "/b/1.epub", "rating": rating, "readStatus": "finished" }]
+        })
+    }
+
+    #[test]
+    fn test_read_export_current_version() {
+        let export = read_export(export_json(EXPORT_FORMAT_VERSION, serde_json::json!(3.5))).unwrap();
+        assert_eq!(export.version, EXPORT_FORMAT_VERSION);
+        assert_eq!(export.books.len(), 1);
+        assert_eq!(export.ratings[0].rating, Some(3.5));
+
+        // A round trip through the writer reads back the same way
+        let db = Database::new_in_memory().unwrap();
+        let written = serde_json::to_value(build_export(&db, false).unwrap()).unwrap();
+        assert!(read_export(written).is_ok());
+    }
+
+    #[test]
+    fn test_read_export_rejects_newer_major_version() {
+        // The layout may have changed, so even the books list isn't trusted
+        let json = serde_json::json!({ "version": "2.0", "library": { "books": [] } });
+        let err = read_export(json).unwrap_err();
+        assert_eq!(err.kind(), "invalidInput");
+        assert!(err.to_string().contains("newer than this app supports"));
+
+        assert!(read_export(serde_json::json!({ "version": "latest" })).is_err());
+        assert!(read_export(serde_json::json!({})).is_err());
+    }
+
+    #[test]
+    fn test_read_export_upgrades_1_0() {
+        let export = read_export(export_json("1.0", serde_json::json!(4))).unwrap();
+        assert_eq!(export.version, EXPORT_FORMAT_VERSION);
+        assert_eq!(export.ratings[0].rating, Some(4.0));
+        assert_eq!(export.ratings[0].read_status.as_deref(), Some("finished"));
+
+        // 1.0 had no half stars
+        let export = read_export(export_json("1.0", serde_json::json!(4.5))).unwrap();
+        assert_eq!(export.ratings[0].rating, None);
+    }
+}
@@ -1,19 +1,26 @@
 //! Library management commands
 
-use crate::db::Library;
-use crate::epub::EpubParser;
-use crate::scanner::{ScanProgress, ScanResult, Scanner};
+use crate::calibre::{CalibreImporter, SyncResult};
+use crate::db::{Book, Database, Library, LibraryCoverage, ScanRecord, TagDistribution};
+use crate::enrich::{self, EnrichmentResult};
+use crate::epub::{EpubParser, Format};
+use crate::ollama::RateLimiter;
+use crate::scanner::{
+    hash_files, parse_files, HashProgress, HashResult, ScanProgress, ScanResult, Scanner, ScannerConfig,
+};
 use crate::state::AppState;
+use crate::{AppError, AppResult};
+use super::CommandError;
+use std::collections::HashMap;
 use std::path::Path;
 use std::sync::Arc;
 use std::time::{Duration, Instant};
 use tauri::{Emitter, State};
-use tokio::time::timeout;
 
 /// Get all libraries with accessibility status
 #[tauri::command]
-pub async fn get_libraries(state: State<'_, Arc<AppState>>) -> Result<Vec<Library>, String> {
-    let mut libraries = state.db.get_libraries().map_err(|e| e.to_string())?;
+pub async fn get_libraries(state: State<'_, Arc<AppState>>) -> Result<Vec<Library>, CommandError> {
+    let mut libraries = state.db.get_libraries()?;
 
     // Check if each library path is accessible
     for library in &mut libraries {
@@ -29,14 +36,14 @@ pub async fn add_library(
     state: State<'_, Arc<AppState>>,
     path: String,
     name: Option<String>,
-) -> Result<Library, String> {
+) -> Result<Library, CommandError> {
     // Validate path exists
     let path_buf = std::path::PathBuf::from(&path);
     if !path_buf.exists() {
-        return Err(format!("Path does not exist: {}", path));
+        return Err(AppError::InvalidInput(format!("Path does not exist: {}", path)).into());
     }
     if !path_buf.is_dir() {
-        return Err(format!("Path is not a directory: {}", path));
+        return Err(AppError::InvalidInput(format!("Path is not a directory: {}", path)).into());
     }
 
     // Check for Calibre database
@@ -56,9 +63,13 @@ pub async fn add_library(
             .unwrap_or_else(|| "Library".to_string())
     });
 
-    state.db
-        .add_library(&name, &path, is_calibre, calibre_db_path.as_deref())
-        .map_err(|e| e.to_string())
+    let library = state.db.add_library(&name, &path, is_calibre, calibre_db_path.as_deref())?;
+    if !is_calibre {
+        if let Err(e) = state.watcher.watch_path(&path_buf) {
+            tracing::warn!("Failed to watch library {}: {}", library.name, e);
+        }
+    }
+    Ok(library)
 }
 
 /// Remove a library
@@ -66,8 +77,11 @@ pub async fn add_library(
 pub async fn remove_library(
     state: State<'_, Arc<AppState>>,
     id: i64,
-) -> Result<(), String> {
-    state.db.remove_library(id).map_err(|e| e.to_string())
+) -> Result<(), CommandError> {
+    if let Some(library) = state.db.get_libraries()?.into_iter().find(|l| l.id == id) {
+        state.watcher.unwatch_path(Path::new(&library.path))?;
+    }
+    state.db.remove_library(id).map_err(CommandError::from)
 }
 
 /// Scan a library for books
@@ -76,18 +90,130 @@ pub async fn scan_library(
     state: State<'_, Arc<AppState>>,
     app: tauri::AppHandle,
     id: i64,
-) -> Result<ScanResult, String> {
+) -> Result<ScanResult, CommandError> {
     let start = Instant::now();
 
     // Get library path
-    let libraries = state.db.get_libraries().map_err(|e| e.to_string())?;
+    let libraries = state.db.get_libraries()?;
     let library = libraries
         .into_iter()
         .find(|l| l.id == id)
-        .ok_or_else(|| format!("Library {} not found", id))?;
+        .ok_or_else(|| AppError::NotFound(format!("Library {}", id)))?;
 
     tracing::info!("Scanning library: {} at {}", library.name, library.path);
 
+    // Record the scan in history, completing it even if the scan fails
+    let record_id = state.db.start_scan_record(id)?;
+    let result = run_library_scan(&state, &app, &library, start).await;
+
+    let (found, added, updated, errors) = match &result {
+        Ok(r) => (r.books_found, r.books_added, r.books_updated, r.errors.clone()),
+        Err(e) => (0, 0, 0, vec![e.to_string()]),
+    };
+    if let Err(e) = state.db.complete_scan_record(record_id, found as i64, added as i64, updated as i64, &errors) {
+        tracing::warn!("Failed to record scan history for library {}: {}", id, e);
+    }
+
+    result
+}
+
+/// Update a Calibre library's books from its metadata.db
+///
+/// Books changed in Calibre since we last changed them are updated and new
+/// ones added; ratings are taken from Calibre and other user data is kept.
+#[tauri::command]
+pub async fn sync_calibre_library(
+    state: State<'_, Arc<AppState>>,
+    id: i64,
+) -> Result<SyncResult, CommandError> {
+    let library = state
+        .db
+        .get_libraries()?
+        .into_iter()
+        .find(|l| l.id == id)
+        .ok_or_else(|| AppError::NotFound(format!("Library {}", id)))?;
+    if !library.is_calibre {
+        return Err(AppError::InvalidInput(format!("{} is not a Calibre library", library.name)).into());
+    }
+
+    tracing::info!("Syncing Calibre library: {} at {}", library.name, library.path);
+    let db = state.db.clone();
+    let result = tokio::task::spawn_blocking(move || CalibreImporter::new(&library.path).sync_to_database(&db))
+        .await??;
+    state.db.update_library_scan_time(id)?;
+
+    tracing::info!(
+        "Calibre sync complete: {} added, {} updated, {} unchanged",
+        result.books_added,
+        result.books_updated,
+        result.books_unchanged
+    );
+    Ok(result)
+}
+
+/// Hash a library's unhashed book files in parallel for deduplication
+///
+/// Emits `hash:progress` events and stores hashes in batched transactions.
+#[tauri::command]
+pub async fn hash_library(
+    state: State<'_, Arc<AppState>>,
+    app: tauri::AppHandle,
+    library_id: i64,
+) -> Result<HashResult, CommandError> {
+    let start = Instant::now();
+
+    let library = state
+        .db
+        .get_libraries()?
+        .into_iter()
+        .find(|l| l.id == library_id)
+        .ok_or_else(|| AppError::NotFound(format!("Library {}", library_id)))?;
+
+    let files = state.db.get_unhashed_books(Some(&library.path))?;
+    let total = files.len();
+    tracing::info!("Hashing {} files in library {}", total, library.name);
+
+    let threads = std::thread::available_parallelism().map(|n| n.get()).unwrap_or(4);
+    let progress_app = app.clone();
+    let hashes = tokio::task::spawn_blocking(move || {
+        hash_files(&files, threads, |processed, total| {
+            // Throttle events for large libraries
+            if processed == total || processed % 50 == 0 {
+                let _ = progress_app.emit("hash:progress", HashProgress { processed, total });
+            }
+        })
+    })
+    .await??;
+
+    const BATCH_SIZE: usize = 500;
+    for chunk in hashes.chunks(BATCH_SIZE) {
+        state.db.set_file_hashes_batch(chunk)?;
+    }
+
+    Ok(HashResult {
+        total,
+        hashed: hashes.len(),
+        failed: total - hashes.len(),
+        duration_ms: start.elapsed().as_millis() as u64,
+    })
+}
+
+/// Hash every book without a file hash in the background
+///
+/// Queues throttled `ComputeHash` jobs, which emit `hash:progress` events as
+/// they complete. Returns the number of books without a hash.
+#[tauri::command]
+pub async fn compute_missing_hashes(state: State<'_, Arc<AppState>>) -> Result<usize, CommandError> {
+    state.queue_missing_hashes().map_err(CommandError::from)
+}
+
+/// Discover and insert a library's books, emitting progress events
+async fn run_library_scan(
+    state: &State<'_, Arc<AppState>>,
+    app: &tauri::AppHandle,
+    library: &Library,
+    start: Instant,
+) -> Result<ScanResult, CommandError> {
     // Emit start event
     let _ = app.emit("scan:start", &library.name);
 
@@ -101,10 +227,14 @@ pub async fn scan_library(
         eta_seconds: None,
     });
 
-    let scanner = Scanner::new();
+    let settings = state.db.get_settings()?;
+    let scanner = Scanner::with_config(ScannerConfig {
+        descend_archives: settings.scan_archives,
+        ..Default::default()
+    });
     let path = std::path::PathBuf::from(&library.path);
 
-    let books = scanner.fast_scan(&path).map_err(|e| e.to_string())?;
+    let books = scanner.fast_scan(&path)?;
     let books_found = books.len();
 
     tracing::info!("Fast scan found {} books, inserting into database", books_found);
@@ -120,22 +250,24 @@ pub async fn scan_library(
     });
 
     const BATCH_SIZE: usize = 100; // Smaller batches for more frequent updates
+    let mut total_processed = 0;
     let mut total_inserted = 0;
     let insert_start = Instant::now();
 
     for (batch_idx, chunk) in books.chunks(BATCH_SIZE).enumerate() {
         let batch_start = Instant::now();
-        let inserted = state.db.insert_books_batch(chunk).map_err(|e| e.to_string())?;
+        let inserted = state.db.insert_books_batch(chunk)?;
+        total_processed += chunk.len();
         total_inserted += inserted.len();
 
         // Calculate ETA based on current progress
         let elapsed_secs = insert_start.elapsed().as_secs_f64();
         let rate = if elapsed_secs > 0.0 {
-            total_inserted as f64 / elapsed_secs
+            total_processed as f64 / elapsed_secs
         } else {
             1000.0
         };
-        let remaining = books_found - total_inserted;
+        let remaining = books_found - total_processed;
         let eta_secs = if rate > 0.0 {
             (remaining as f64 / rate) as u64
         } else {
@@ -146,12 +278,13 @@ pub async fn scan_library(
         let _ = app.emit("scan:progress", ScanProgress {
             phase: "inserting".to_string(),
             found: books_found,
-            processed: total_inserted,
+            processed: total_processed,
             total: books_found,
             current: Some(format!(
-                "Inserted {}/{} books ({:.0}/sec)",
-                total_inserted,
+                "Processed {}/{} books, {} new ({:.0}/sec)",
+                total_processed,
                 books_found,
+                total_inserted,
                 rate
             )),
             eta_seconds: Some(eta_secs),
@@ -161,9 +294,9 @@ pub async fn scan_library(
         if batch_idx % 10 == 0 {
             tracing::info!(
                 "Progress: {}/{} books ({:.1}%), batch took {:?}",
-                total_inserted,
+                total_processed,
                 books_found,
-                (total_inserted as f64 / books_found as f64) * 100.0,
+                (total_processed as f64 / books_found as f64) * 100.0,
                 batch_start.elapsed()
             );
         }
@@ -173,7 +306,7 @@ pub async fn scan_library(
     }
 
     // Update library scan time
-    state.db.update_library_scan_time(id).map_err(|e| e.to_string())?;
+    state.db.update_library_scan_time(library.id)?;
 
     // Emit completion event
     let _ = app.emit("scan:complete", ());
@@ -185,7 +318,7 @@ pub async fn scan_library(
         books_found,
         total_inserted,
         duration_ms,
-        (total_inserted as f64) / (duration_ms as f64 / 1000.0)
+        (books_found as f64) / (duration_ms as f64 / 1000.0)
     );
 
     Ok(ScanResult {
@@ -197,6 +330,109 @@ pub async fn scan_library(
     })
 }
 
+/// Index an EPUB file or folder that isn't a registered library
+///
+/// Runs the same fast scan as `scan_library` (metadata is parsed later by
+/// `parse_metadata_batch`). Books already in the database are left alone.
+#[tauri::command]
+pub async fn scan_path(
+    state: State<'_, Arc<AppState>>,
+    path: String,
+) -> Result<ScanResult, CommandError> {
+    let settings = state.db.get_settings()?;
+    let scanner = Scanner::with_config(ScannerConfig {
+        descend_archives: settings.scan_archives,
+        ..Default::default()
+    });
+    scan_path_into(&state.db, &scanner, Path::new(&path)).map_err(CommandError::from)
+}
+
+/// Fast-scan `path` and insert the books that aren't known yet
+pub(crate) fn scan_path_into(db: &Database, scanner: &Scanner, path: &Path) -> AppResult<ScanResult> {
+    let start = Instant::now();
+    if !path.exists() {
+        return Err(AppError::InvalidInput(format!("Path does not exist: {}", path.display())));
+    }
+
+    let books = scanner.fast_scan(path)?;
+    let books_found = books.len();
+    let books_added = db.insert_books_batch(&books)?.len();
+
+    tracing::info!("Scanned {}: {} found, {} added", path.display(), books_found, books_added);
+    Ok(ScanResult {
+        books_found,
+        books_added,
+        books_updated: 0,
+        errors: vec![],
+        duration_ms: start.elapsed().as_millis() as u64,
+    })
+}
+
+/// Parse one EPUB fully and add it to the database
+///
+/// Unlike a scan the book is ready for embedding right away. A file that is
+/// already indexed is returned as is.
+#[tauri::command]
+pub async fn add_single_book(
+    state: State<'_, Arc<AppState>>,
+    path: String,
+) -> Result<Book, CommandError> {
+    let state = state.inner().clone();
+    tokio::task::spawn_blocking(move || add_book_from_file(&state.db, Path::new(&path)))
+        .await?
+        .map_err(CommandError::from)
+}
+
+/// Parse and insert a single EPUB, queueing it for embedding when it has a description
+pub(crate) fn add_book_from_file(db: &Database, path: &Path) -> AppResult<Book> {
+    if !crate::epub::source_exists(path) {
+        return Err(AppError::InvalidInput(format!("File does not exist: {}", path.display())));
+    }
+    if let Some(existing) = db.get_book_by_path(&path.to_string_lossy())? {
+        return Ok(existing);
+    }
+
+    let mut book = EpubParser::new().parse(path)?;
+    book.cover_path = Scanner::new()
+        .find_cover(path)
+        .map(|p| p.to_string_lossy().to_string());
+    let book_id = db.insert_book(&book)?;
+    let status = if book.description.is_some() { "pending" } else { "no_description" };
+    db.update_embedding_status(book_id, status)?;
+
+    tracing::info!("Added {} (id: {})", book.title, book_id);
+    db.get_book(book_id)
+}
+
+/// Get past scans of a library, most recent first
+#[tauri::command]
+pub async fn get_scan_history(
+    state: State<'_, Arc<AppState>>,
+    library_id: i64,
+    limit: Option<i64>,
+) -> Result<Vec<ScanRecord>, CommandError> {
+    let limit = limit.unwrap_or(20).min(100);
+    state.db.get_scan_history(library_id, limit).map_err(CommandError::from)
+}
+
+/// Get embedding progress for each library
+#[tauri::command]
+pub async fn get_embedding_coverage(
+    state: State<'_, Arc<AppState>>,
+) -> Result<Vec<LibraryCoverage>, CommandError> {
+    state.db.get_embedding_coverage().map_err(CommandError::from)
+}
+
+/// Get the most common tags and the tag hierarchy ("library at a glance")
+#[tauri::command]
+pub async fn get_tag_distribution(
+    state: State<'_, Arc<AppState>>,
+    top_n: Option<usize>,
+) -> Result<TagDistribution, CommandError> {
+    let top_n = top_n.unwrap_or(20).clamp(1, 500);
+    state.db.get_tag_distribution(top_n).map_err(CommandError::from)
+}
+
 /// Result of metadata parsing batch
 #[derive(Debug, Clone, serde::Serialize)]
 #[serde(rename_all = "camelCase")]
@@ -204,117 +440,146 @@ pub struct MetadataParsingResult {
     pub processed: i64,
     pub success: i64,
     pub failed: i64,
+    /// `failed`, broken down by file format
+    pub failed_by_format: HashMap<Format, i64>,
+    /// Failures that looked transient; these books stay queued
+    pub retrying: i64,
     pub remaining: i64,
     pub duration_ms: u64,
 }
 
+/// Transient parse failures allowed before a book is skipped
+pub const MAX_METADATA_RETRIES: i64 = 3;
+
+/// Record a failed metadata parse
+///
+/// Transient errors (a file locked during a sync, a timeout) leave the book
+/// queued until it has failed [`MAX_METADATA_RETRIES`] times; anything else
+/// (bad zip, DRM) marks it skipped straight away. Returns whether the book
+/// will be retried.
+fn record_parse_failure(db: &Database, book_id: i64, error: &AppError) -> AppResult<bool> {
+    if error.is_transient() {
+        let attempts = db.record_metadata_retry(book_id, &error.to_string())?;
+        if attempts < MAX_METADATA_RETRIES {
+            tracing::debug!("Parse of book {} failed ({}), will retry: {}", book_id, attempts, error);
+            return Ok(true);
+        }
+        tracing::warn!("Giving up on book {} after {} attempts: {}", book_id, attempts, error);
+    }
+    db.update_embedding_status(book_id, "skipped")?;
+    Ok(false)
+}
+
+/// Requeue books that metadata parsing skipped (e.g. after fixing files)
+#[tauri::command]
+pub async fn retry_skipped_metadata(state: State<'_, Arc<AppState>>) -> Result<i64, CommandError> {
+    state.db.retry_skipped_metadata().map_err(CommandError::from)
+}
+
+/// Fill in missing descriptions, series and tags from an online provider
+///
+/// Looks up books with an ISBN but no description; requires
+/// `enrichment_enabled`. Lookups are limited to one per second.
+#[tauri::command]
+pub async fn enrich_metadata(
+    state: State<'_, Arc<AppState>>,
+    limit: Option<i64>,
+) -> Result<EnrichmentResult, CommandError> {
+    let settings = state.db.get_settings()?;
+    if !settings.enrichment_enabled {
+        return Err(AppError::Config("Metadata enrichment is disabled in settings".to_string()).into());
+    }
+
+    let provider = enrich::provider_for(&settings.enrichment_endpoint);
+    let rate_limiter = RateLimiter::new(enrich::ENRICHMENT_REQUESTS_PER_SECOND);
+    enrich::enrich_books(&state.db, provider.as_ref(), &rate_limiter, limit.unwrap_or(50))
+        .await
+        .map_err(CommandError::from)
+}
+
 /// Parse metadata for books that are missing descriptions
-/// This extracts full EPUB metadata including descriptions for embedding generation
+/// This extracts full EPUB metadata including descriptions for embedding generation.
+/// `concurrency` files are parsed at once (default 1).
 #[tauri::command]
 pub async fn parse_metadata_batch(
     state: State<'_, Arc<AppState>>,
     _app: tauri::AppHandle,
     batch_size: Option<i64>,
-) -> Result<MetadataParsingResult, String> {
+    concurrency: Option<usize>,
+) -> Result<MetadataParsingResult, CommandError> {
     let batch_size = batch_size.unwrap_or(20);
-    let start = Instant::now();
 
     // Get books needing metadata
-    let books_to_parse = state.db.get_books_needing_metadata(batch_size)
-        .map_err(|e| e.to_string())?;
-
-    if books_to_parse.is_empty() {
-        let stats = state.db.get_stats().map_err(|e| e.to_string())?;
-        return Ok(MetadataParsingResult {
-            processed: 0,
-            success: 0,
-            failed: 0,
-            remaining: stats.books_needing_metadata,
-            duration_ms: 0,
-        });
-    }
+    let books_to_parse = state.db.get_books_needing_metadata(batch_size)?;
 
-    let mut success = 0;
-    let mut failed = 0;
+    parse_books_metadata(&state.db, &books_to_parse, concurrency.unwrap_or(1))
+        .await
+        .map_err(CommandError::from)
+}
 
-    // Timeout for parsing each file (10 seconds max)
-    let parse_timeout = Duration::from_secs(10);
+/// Time allowed for parsing a single file
+const METADATA_PARSE_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Parse full metadata for each book and queue those with a description
+/// for embedding
+///
+/// Up to `concurrency` files are parsed at once; the results are then
+/// stored in a single transaction.
+pub(crate) async fn parse_books_metadata(
+    db: &Database,
+    books_to_parse: &[(i64, String)],
+    concurrency: usize,
+) -> AppResult<MetadataParsingResult> {
+    let start = Instant::now();
 
-    for (book_id, book_path) in &books_to_parse {
-        let path_str = book_path.clone();
-        let book_id = *book_id;
+    let mut failed = 0;
+    let mut failed_by_format: HashMap<Format, i64> = HashMap::new();
+    let mut retrying = 0;
+    let format_of = |path: &str| Format::from_path(Path::new(path)).unwrap_or(Format::Epub);
 
+    let mut to_parse = Vec::with_capacity(books_to_parse.len());
+    for (book_id, book_path) in books_to_parse {
         // Check if file exists first - mark as permanently failed if missing
-        if !Path::new(&path_str).exists() {
-            tracing::warn!("Book file not found, marking as skipped: {}", path_str);
+        if !crate::epub::source_exists(Path::new(book_path)) {
+            tracing::warn!("Book file not found, marking as skipped: {}", book_path);
             // Use "skipped" status for files that don't exist
-            state.db.update_embedding_status(book_id, "skipped").map_err(|e| e.to_string())?;
+            db.update_embedding_status(*book_id, "skipped")?;
             failed += 1;
+            *failed_by_format.entry(format_of(book_path)).or_default() += 1;
             continue;
         }
+        to_parse.push((*book_id, book_path.clone()));
+    }
 
-        // Parse with timeout using spawn_blocking to avoid blocking the async runtime
-        let parse_result = timeout(parse_timeout, tokio::task::spawn_blocking(move || {
-            let parser = EpubParser::new();
-            let path = Path::new(&path_str);
-            parser.parse(path)
-        })).await;
-
-        match parse_result {
-            Ok(Ok(Ok(parsed))) => {
-                // Update book with parsed metadata
-                if let Err(e) = state.db.update_book_metadata(
-                    book_id,
-                    Some(&parsed.title),
-                    parsed.author.as_deref(),
-                    parsed.author_sort.as_deref(),
-                    parsed.description.as_deref(),
-                    parsed.series.as_deref(),
-                    parsed.series_index,
-                    parsed.language.as_deref(),
-                    parsed.publisher.as_deref(),
-                    parsed.publish_date.as_deref(),
-                    parsed.isbn.as_deref(),
-                ) {
-                    tracing::warn!("Failed to update metadata for book {}: {}", book_id, e);
-                    state.db.update_embedding_status(book_id, "skipped").ok();
-                    failed += 1;
+    let paths: Vec<String> = to_parse.iter().map(|(_, path)| path.clone()).collect();
+    let results = parse_files(&paths, concurrency, METADATA_PARSE_TIMEOUT).await;
+
+    let mut parsed = Vec::with_capacity(results.len());
+    for ((book_id, book_path), result) in to_parse.into_iter().zip(results) {
+        match result {
+            Ok(book) => parsed.push((book_id, book)),
+            Err(error) => {
+                if record_parse_failure(db, book_id, &error)? {
+                    retrying += 1;
                 } else {
-                    // If we got a description, mark it for embedding processing
-                    if parsed.description.is_some() {
-                        state.db.update_embedding_status(book_id, "pending").ok();
-                    } else {
-                        // No description in EPUB - mark as skipped
-                        state.db.update_embedding_status(book_id, "no_description").ok();
-                    }
-                    success += 1;
+                    failed += 1;
+                    *failed_by_format.entry(format_of(&book_path)).or_default() += 1;
                 }
             }
-            Ok(Ok(Err(_e))) => {
-                // EPUB parsing failed - mark as skipped so it won't be retried
-                state.db.update_embedding_status(book_id, "skipped").map_err(|e| e.to_string())?;
-                failed += 1;
-            }
-            Ok(Err(_e)) => {
-                // Task panic - mark as skipped
-                state.db.update_embedding_status(book_id, "skipped").map_err(|e| e.to_string())?;
-                failed += 1;
-            }
-            Err(_) => {
-                // Timeout - mark as skipped
-                state.db.update_embedding_status(book_id, "skipped").map_err(|e| e.to_string())?;
-                failed += 1;
-            }
         }
     }
+    db.apply_parsed_metadata(&parsed)?;
+    let success = parsed.len() as i64;
 
     // Get remaining count
-    let stats = state.db.get_stats().map_err(|e| e.to_string())?;
+    let stats = db.get_stats()?;
 
     Ok(MetadataParsingResult {
         processed: books_to_parse.len() as i64,
         success,
         failed,
+        failed_by_format,
+        retrying,
         remaining: stats.books_needing_metadata,
         duration_ms: start.elapsed().as_millis() as u64,
     })
@@ -333,18 +598,18 @@ pub struct CleanupOrphanedResult {
 #[tauri::command]
 pub async fn cleanup_orphaned_books(
     state: State<'_, Arc<AppState>>,
-) -> Result<CleanupOrphanedResult, String> {
+) -> Result<CleanupOrphanedResult, CommandError> {
     let start = Instant::now();
 
     // Get all book paths from database
-    let all_books = state.db.get_all_book_paths().map_err(|e| e.to_string())?;
+    let all_books = state.db.get_all_book_paths()?;
     let total = all_books.len() as i64;
 
     let mut removed = 0;
 
     for (book_id, book_path) in all_books {
         let path = Path::new(&book_path);
-        if !path.exists() {
+        if !crate::epub::source_exists(path) {
             tracing::info!("Removing orphaned book (file missing): {}", book_path);
             if let Err(e) = state.db.delete_book(book_id) {
                 tracing::warn!("Failed to delete orphaned book {}: {}", book_id, e);
@@ -362,3 +627,152 @@ pub async fn cleanup_orphaned_books(
         duration_ms: start.elapsed().as_millis() as u64,
     })
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_add_single_book_parses_and_queues() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let path = dir.path().join("emma.epub");
+        let bytes = crate::epub::test_epub_bytes_with_description("Emma", "Jane Austen", "Handsome, clever and rich.");
+        std::fs::write(&path, bytes).unwrap();
+        let db = Database::new_in_memory().unwrap();
+
+        let book = add_book_from_file(&db, &path).unwrap();
+        assert_eq!(book.title, "Emma");
+        assert_eq!(book.author.as_deref(), Some("Jane Austen"));
+        assert_eq!(book.description.as_deref(), Some("Handsome, clever and rich."));
+        assert_eq!(book.embedding_status, "pending");
+        assert!(db.get_books_needing_metadata(10).unwrap().is_empty());
+
+        // Adding the same file again returns the existing book
+        assert_eq!(add_book_from_file(&db, &path).unwrap().id, book.id);
+        assert!(add_book_from_file(&db, &dir.path().join("missing.epub")).is_err());
+    }
+
+    #[test]
+    fn test_scan_path_indexes_ad_hoc_folder() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let nested = dir.path().join("nested");
+        std::fs::create_dir(&nested).unwrap();
+        std::fs::write(dir.path().join("a.epub"), crate::epub::test_epub_bytes("A", "Author")).unwrap();
+        std::fs::write(nested.join("b.epub"), crate::epub::test_epub_bytes("B", "Author")).unwrap();
+        std::fs::write(dir.path().join("notes.txt"), b"not a book").unwrap();
+        let db = Database::new_in_memory().unwrap();
+        let scanner = Scanner::new();
+
+        let result = scan_path_into(&db, &scanner, dir.path()).unwrap();
+        assert_eq!((result.books_found, result.books_added), (2, 2));
+        assert!(db.get_libraries().unwrap().is_empty());
+        assert_eq!(db.get_books_needing_metadata(10).unwrap().len(), 2);
+
+        // Rescanning, or scanning one of the files directly, adds nothing new
+        let again = scan_path_into(&db, &scanner, dir.path()).unwrap();
+        assert_eq!((again.books_found, again.books_added), (2, 0));
+        let single = scan_path_into(&db, &scanner, &nested.join("b.epub")).unwrap();
+        assert_eq!((single.books_found, single.books_added), (1, 0));
+    }
+
+    #[test]
+    fn test_locked_file_retries_and_corrupt_file_skips() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let corrupt = dir.path().join("corrupt.epub");
+        std::fs::write(&corrupt, b"definitely not a zip archive").unwrap();
+
+        let db = Database::new_in_memory().unwrap();
+        db.with_conn(|conn| {
+            conn.execute(
+                "INSERT INTO books (id, path, title, embedding_status) VALUES
+                    (1, '/sync/locked.epub', 'Locked', ''),
+                    (2, ?, 'Corrupt', '')",
+                [corrupt.to_string_lossy()],
+            )?;
+            Ok(())
+        })
+        .unwrap();
+        let queued = |id: i64| db.get_books_needing_metadata(10).unwrap().iter().any(|(b, _)| *b == id);
+
+        // A sharing violation while a sync client holds the file is retried
+        let locked = AppError::Io(std::io::Error::new(
+            std::io::ErrorKind::PermissionDenied,
+            "file is being used by another process",
+        ));
+        for _ in 1..MAX_METADATA_RETRIES {
+            assert!(record_parse_failure(&db, 1, &locked).unwrap());
+            assert!(queued(1));
+        }
+        assert!(!record_parse_failure(&db, 1, &locked).unwrap());
+        assert_eq!(db.get_book(1).unwrap().embedding_status, "skipped");
+
+        // A broken archive is skipped on the first failure
+        let error = EpubParser::new().parse(&corrupt).unwrap_err();
+        assert!(!error.is_transient());
+        assert!(!record_parse_failure(&db, 2, &error).unwrap());
+        assert_eq!(db.get_book(2).unwrap().embedding_status, "skipped");
+        assert!(!queued(2));
+
+        // Both come back with a fresh retry budget
+        assert_eq!(db.retry_skipped_metadata().unwrap(), 2);
+        assert!(queued(1) && queued(2));
+        assert!(record_parse_failure(&db, 1, &locked).unwrap());
+    }
+
+    #[tokio::test]
+    async fn test_parse_failures_counted_by_format() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let good = dir.path().join("good.mobi");
+        std::fs::write(&good, crate::epub::test_mobi_bytes("Good", "Author", &[], None)).unwrap();
+        let broken = dir.path().join("broken.azw3");
+        std::fs::write(&broken, b"not a kindle book").unwrap();
+        let missing = dir.path().join("missing.epub");
+
+        let db = Database::new_in_memory().unwrap();
+        let books: Vec<(i64, String)> = [good, broken, missing]
+            .iter()
+            .enumerate()
+            .map(|(i, path)| (i as i64 + 1, path.to_string_lossy().to_string()))
+            .collect();
+        db.with_conn(|conn| {
+            for (id, path) in &books {
+                conn.execute(
+                    "INSERT INTO books (id, path, title, embedding_status) VALUES (?, ?, 'Book', '')",
+                    rusqlite::params![id, path],
+                )?;
+            }
+            Ok(())
+        })
+        .unwrap();
+
+        let result = parse_books_metadata(&db, &books, 2).await.unwrap();
+        assert_eq!((result.success, result.failed, result.retrying), (1, 2, 0));
+        assert_eq!(result.failed_by_format, HashMap::from([(Format::Azw3, 1), (Format::Epub, 1)]));
+        assert_eq!(db.get_book(1).unwrap().title, "Good");
+    }
+
+    #[tokio::test]
+    async fn test_subjects_fill_in_missing_tags() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let db = Database::new_in_memory().unwrap();
+        let mut books = Vec::new();
+        for (id, name) in [(1, "untagged"), (2, "tagged")] {
+            let path = dir.path().join(format!("{}.epub", name));
+            std::fs::write(&path, crate::epub::test_epub_bytes_with_subjects(name, "Author", &["Fantasy"])).unwrap();
+            db.with_conn(|conn| {
+                conn.execute(
+                    "INSERT INTO books (id, path, title, embedding_status) VALUES (?, ?, ?, '')",
+                    rusqlite::params![id, path.to_string_lossy(), name],
+                )?;
+                Ok(())
+            })
+            .unwrap();
+            books.push((id, path.to_string_lossy().to_string()));
+        }
+        db.set_book_tags(2, &["To Read".to_string()]).unwrap();
+
+        parse_books_metadata(&db, &books, 2).await.unwrap();
+        assert_eq!(db.get_book_tags(1).unwrap(), vec!["Fantasy"]);
+        assert_eq!(db.get_book_tags(2).unwrap(), vec!["To Read"]);
+    }
+}
@@ -1,9 +1,86 @@
 //! Tauri command handlers
 
 pub mod books;
+pub mod collections;
 pub mod export;
 pub mod library;
 pub mod ollama;
+pub mod pipeline;
 pub mod recommendations;
 pub mod settings;
 pub mod upnext;
+
+use crate::AppError;
+
+/// Error returned to the frontend by every command
+///
+/// `kind` is the [`AppError`] variant in camelCase (e.g. `notFound`,
+/// `ollamaUnavailable`), or `internal` for failures outside the app's own
+/// error type.
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CommandError {
+    pub kind: String,
+    pub message: String,
+}
+
+impl std::fmt::Display for CommandError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&self.message)
+    }
+}
+
+impl From<AppError> for CommandError {
+    fn from(e: AppError) -> Self {
+        Self {
+            kind: e.kind().to_string(),
+            message: e.to_string(),
+        }
+    }
+}
+
+impl From<String> for CommandError {
+    fn from(message: String) -> Self {
+        Self {
+            kind: "internal".to_string(),
+            message,
+        }
+    }
+}
+
+impl From<std::io::Error> for CommandError {
+    fn from(e: std::io::Error) -> Self {
+        AppError::from(e).into()
+    }
+}
+
+impl From<serde_json::Error> for CommandError {
+    fn from(e: serde_json::Error) -> Self {
+        AppError::from(e).into()
+    }
+}
+
+impl From<tokio::task::JoinError> for CommandError {
+    fn from(e: tokio::task::JoinError) -> Self {
+        e.to_string().into()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_command_error_kind() {
+        let error = CommandError::from(AppError::NotFound("Book 7".to_string()));
+        assert_eq!(error.kind, "notFound");
+        assert_eq!(error.message, "Not found: Book 7");
+        assert_eq!(
+            serde_json::to_value(&error).unwrap(),
+            serde_json::json!({ "kind": "notFound", "message": "Not found: Book 7" })
+        );
+
+        assert_eq!(CommandError::from(AppError::OllamaUnavailable("down".to_string())).kind, "ollamaUnavailable");
+        assert_eq!(CommandError::from("boom".to_string()).kind, "internal");
+    }
+}
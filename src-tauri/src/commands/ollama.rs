@@ -2,6 +2,13 @@
 
 use crate::ollama::{OllamaStatus, ProcessingStatus};
 use crate::state::AppState;
+use crate::worker::{
+    process_all_pending as drain_pending, process_embedding_batch, DrainResult, EmbeddingContext, EmbeddingProgress,
+    EventSink,
+    STALL_THRESHOLD_SECS,
+};
+use super::CommandError;
+use std::sync::atomic::Ordering;
 use std::sync::Arc;
 use tauri::State;
 
@@ -9,7 +16,7 @@ use tauri::State;
 #[tauri::command]
 pub async fn get_ollama_status(
     state: State<'_, Arc<AppState>>,
-) -> Result<OllamaStatus, String> {
+) -> Result<OllamaStatus, CommandError> {
     // Clone the endpoint and model before the async operation
     // to avoid holding the lock across await points
     let (endpoint, model) = {
@@ -19,7 +26,7 @@ pub async fn get_ollama_status(
 
     // Create a temporary client for the health check
     let temp_client = crate::ollama::OllamaClient::new(endpoint, model);
-    temp_client.health_check().await.map_err(|e| e.to_string())
+    temp_client.health_check().await.map_err(CommandError::from)
 }
 
 /// Configure Ollama endpoint and model
@@ -28,16 +35,17 @@ pub async fn configure_ollama(
     state: State<'_, Arc<AppState>>,
     endpoint: String,
     model: String,
-) -> Result<(), String> {
+) -> Result<(), CommandError> {
     // Update Ollama client
     {
         let mut ollama = state.ollama.write();
         ollama.configure(endpoint.clone(), model.clone());
     }
+    state.vector_store.set_active_model(&model);
     
     // Persist to settings
-    state.db.update_setting("ollama_endpoint", &endpoint).map_err(|e| e.to_string())?;
-    state.db.update_setting("ollama_model", &model).map_err(|e| e.to_string())?;
+    state.db.update_setting("ollama_endpoint", &endpoint)?;
+    state.db.update_setting("ollama_model", &model)?;
     
     Ok(())
 }
@@ -46,25 +54,41 @@ pub async fn configure_ollama(
 #[tauri::command]
 pub async fn get_processing_status(
     state: State<'_, Arc<AppState>>,
-) -> Result<ProcessingStatus, String> {
-    let stats = state.db.get_stats().map_err(|e| e.to_string())?;
+) -> Result<ProcessingStatus, CommandError> {
+    let stats = state.db.get_stats()?;
+    let progress = state.processing_progress.read().clone();
+    let is_paused = state.is_processing_paused();
+    let work_remains = !is_paused && stats.pending_embeddings > 0;
+    let now = chrono::Utc::now().timestamp();
 
     Ok(ProcessingStatus {
         total_books: stats.total_books,
         processed: stats.books_with_embeddings,
         pending: stats.pending_embeddings,
-        current_book: None, // TODO: track in state
-        is_paused: state.is_processing_paused(),
-        estimated_time_remaining: None, // TODO: calculate based on rate
+        current_book: progress.as_ref().map(|p| p.title.clone()),
+        is_paused,
+        estimated_time_remaining: progress.and_then(|p| p.eta_seconds).map(|eta| eta as i64),
         books_needing_metadata: stats.books_needing_metadata,
+        last_heartbeat: state.heartbeat.last(),
+        stalled: state.heartbeat.is_stalled(now, STALL_THRESHOLD_SECS, work_remains),
     })
 }
 
+/// Replace the background worker, e.g. after `get_processing_status` reports it stalled
+#[tauri::command]
+pub async fn restart_worker(
+    state: State<'_, Arc<AppState>>,
+    app: tauri::AppHandle,
+) -> Result<(), CommandError> {
+    state.spawn_worker(Arc::new(app));
+    Ok(())
+}
+
 /// Pause background embedding processing
 #[tauri::command]
 pub async fn pause_processing(
     state: State<'_, Arc<AppState>>,
-) -> Result<(), String> {
+) -> Result<(), CommandError> {
     state.pause_processing();
     Ok(())
 }
@@ -73,144 +97,158 @@ pub async fn pause_processing(
 #[tauri::command]
 pub async fn resume_processing(
     state: State<'_, Arc<AppState>>,
-) -> Result<(), String> {
+) -> Result<(), CommandError> {
     state.resume_processing();
     Ok(())
 }
 
+/// Stop a running `process_all_pending` job after the books in flight
+#[tauri::command]
+pub async fn cancel_processing(
+    state: State<'_, Arc<AppState>>,
+) -> Result<(), CommandError> {
+    state.cancel_processing();
+    Ok(())
+}
+
 /// Prioritize embedding generation for a specific book
+///
+/// The book is queued in the database first, so it survives a restart.
 #[tauri::command]
 pub async fn prioritize_book(
     state: State<'_, Arc<AppState>>,
     book_id: i64,
-) -> Result<(), String> {
+) -> Result<(), CommandError> {
     use crate::state::BackgroundJob;
 
-    state.queue_job(BackgroundJob::GenerateEmbedding {
-        book_id,
-        priority: 100, // High priority
-    });
+    state.db.enqueue_embedding_jobs(&[book_id], 100)?; // High priority
+    state.queue_job(BackgroundJob::ProcessEmbeddingQueue);
 
     Ok(())
 }
 
-/// Process a batch of pending embeddings
+/// Queue every embedding job that ran out of retries again
+/// Returns the number of jobs queued
+#[tauri::command]
+pub async fn retry_failed_jobs(
+    state: State<'_, Arc<AppState>>,
+) -> Result<usize, CommandError> {
+    use crate::state::BackgroundJob;
+
+    let retried = state.db.retry_failed_embedding_jobs()?;
+    if retried > 0 {
+        state.queue_job(BackgroundJob::ProcessEmbeddingQueue);
+    }
+    Ok(retried)
+}
+
+/// Forwards events to the frontend while recording progress for
+/// `get_processing_status`
+struct TrackingSink<'a> {
+    app: tauri::AppHandle,
+    state: &'a AppState,
+}
+
+impl EventSink for TrackingSink<'_> {
+    fn emit_event(&self, event: &str, payload: serde_json::Value) {
+        if event == "embeddings:progress" {
+            if let Ok(progress) = serde_json::from_value::<EmbeddingProgress>(payload.clone()) {
+                *self.state.processing_progress.write() = Some(progress);
+            }
+        }
+        self.app.emit_event(event, payload);
+    }
+}
+
+/// Process a batch of pending embeddings, `concurrency` requests at a time
 /// Returns the number of embeddings processed
+///
+/// If Ollama stops answering mid-batch, the batch ends early with
+/// `backendUnavailable` set and the remaining books left pending. Pausing
+/// stops new requests the same way. Emits `embeddings:progress` after each
+/// book.
 #[tauri::command]
 pub async fn process_embeddings_batch(
     state: State<'_, Arc<AppState>>,
-    _app: tauri::AppHandle,
+    app: tauri::AppHandle,
     batch_size: Option<i64>,
-) -> Result<ProcessingResult, String> {
+    concurrency: Option<usize>,
+) -> Result<ProcessingResult, CommandError> {
     use crate::ollama::OllamaClient;
     use std::time::Instant;
 
     let batch_size = batch_size.unwrap_or(10) as usize;
+    let concurrency = concurrency.unwrap_or(1).clamp(1, 8);
     let start = Instant::now();
 
-    // Get pending books
-    let pending_books = state.db.get_pending_embedding_books(batch_size as i64)
-        .map_err(|e| e.to_string())?;
-
-    if pending_books.is_empty() {
-        return Ok(ProcessingResult {
-            processed: 0,
-            failed: 0,
-            remaining: 0,
-            duration_ms: 0,
-        });
-    }
-
     // Get Ollama config
-    let (endpoint, model) = {
+    let client = {
         let ollama = state.ollama.read();
-        (ollama.endpoint().to_string(), ollama.model().to_string())
+        OllamaClient::new(ollama.endpoint().to_string(), ollama.model().to_string())
     };
 
-    let client = OllamaClient::new(endpoint, model.clone());
-
-    let mut processed = 0;
-    let mut failed = 0;
-
-    for book_id in &pending_books {
-        // Check if already has embedding
-        if state.vector_store.has_embedding(*book_id) {
-            state.db.update_embedding_status(*book_id, "complete").ok();
-            processed += 1;
-            continue;
-        }
-
-        // Get book and generate embedding
-        if let Ok(book) = state.db.get_book(*book_id) {
-            // PROTECTION: Skip books without description - embeddings from titles only are meaningless
-            if book.description.is_none() || book.description.as_ref().map(|d| d.trim().is_empty()).unwrap_or(true) {
-                // Mark as "needs_metadata" so it's not retried until metadata is parsed
-                state.db.update_embedding_status(*book_id, "needs_metadata").ok();
-                tracing::debug!("Skipping book {} - no description available", book.title);
-                continue;
-            }
-
-            let text = crate::ollama::book_to_embedding_text(
-                &book.title,
-                book.author.as_deref(),
-                book.description.as_deref(),
-                book.series.as_deref(),
-            );
-
-            match client.embed(&text).await {
-                Ok(embedding) => {
-                    if state.vector_store.store_embedding(*book_id, &embedding, &model, None).is_ok() {
-                        state.db.update_embedding_status(*book_id, "complete").ok();
-                        processed += 1;
-                        tracing::info!("Generated embedding for: {}", book.title);
-
-                        // Create graph edges to similar books
-                        let similar = state.vector_store.find_similar_to_book(*book_id, 20);
-                        if !similar.is_empty() {
-                            let mut edges_to_insert = Vec::new();
-                            for (target_id, similarity) in similar {
-                                if similarity < 0.3 {
-                                    continue;
-                                }
-                                if let Ok(target_book) = state.db.get_book(target_id) {
-                                    let (weight, edge_type) = crate::graph::compute_edge_weight(
-                                        &book,
-                                        &target_book,
-                                        Some(similarity),
-                                    );
-                                    if weight >= 0.3 {
-                                        edges_to_insert.push((*book_id, target_id, edge_type, weight));
-                                    }
-                                }
-                            }
-                            if let Err(e) = state.db.insert_edges_batch(&edges_to_insert) {
-                                tracing::warn!("Failed to insert edges for book {}: {}", book_id, e);
-                            }
-                        }
-                    } else {
-                        failed += 1;
-                    }
-                }
-                Err(e) => {
-                    tracing::warn!("Embedding failed for book {}: {}", book_id, e);
-                    state.db.update_embedding_status(*book_id, "failed").ok();
-                    failed += 1;
-                }
-            }
-        }
-    }
+    let ctx = EmbeddingContext {
+        db: &state.db,
+        vector_store: &state.vector_store,
+        backend: &client,
+        rate_limiter: &state.rate_limiter,
+        paused: &state.processing_paused,
+        cancelled: &state.processing_cancelled,
+        concurrency,
+        events: &TrackingSink { app, state: &state },
+    };
+    let outcome = process_embedding_batch(&ctx, batch_size).await;
+    *state.processing_progress.write() = None;
+    let outcome = outcome?;
 
     // Get remaining count
-    let stats = state.db.get_stats().map_err(|e| e.to_string())?;
+    let stats = state.db.get_stats()?;
 
     Ok(ProcessingResult {
-        processed,
-        failed,
+        processed: outcome.processed as i64,
+        failed: outcome.failed as i64,
         remaining: stats.pending_embeddings,
+        backend_unavailable: outcome.backend_unavailable,
         duration_ms: start.elapsed().as_millis() as u64,
     })
 }
 
+/// Embed every pending book in one call
+///
+/// Runs until the queue is empty, paused, or cancelled, emitting
+/// `embeddings:progress` after each book so the UI only has to listen.
+#[tauri::command]
+pub async fn process_all_pending(
+    state: State<'_, Arc<AppState>>,
+    app: tauri::AppHandle,
+    concurrency: Option<usize>,
+) -> Result<DrainResult, CommandError> {
+    use crate::ollama::OllamaClient;
+
+    let concurrency = concurrency.unwrap_or(1).clamp(1, 8);
+    let client = {
+        let ollama = state.ollama.read();
+        OllamaClient::new(ollama.endpoint().to_string(), ollama.model().to_string())
+    };
+
+    state.processing_cancelled.store(false, Ordering::Relaxed);
+    let sink = TrackingSink { app, state: &state };
+    let ctx = EmbeddingContext {
+        db: &state.db,
+        vector_store: &state.vector_store,
+        backend: &client,
+        rate_limiter: &state.rate_limiter,
+        paused: &state.processing_paused,
+        cancelled: &state.processing_cancelled,
+        concurrency,
+        events: &sink,
+    };
+    let result = drain_pending(&ctx).await;
+    *state.processing_progress.write() = None;
+
+    result.map_err(CommandError::from)
+}
+
 /// Result of batch embedding processing
 #[derive(Debug, Clone, serde::Serialize)]
 #[serde(rename_all = "camelCase")]
@@ -218,5 +256,16 @@ pub struct ProcessingResult {
     pub processed: i64,
     pub failed: i64,
     pub remaining: i64,
+    /// Ollama stopped answering; remaining books are still pending
+    pub backend_unavailable: bool,
     pub duration_ms: u64,
 }
+
+/// Get the raw embedding vector for a book
+#[tauri::command]
+pub async fn get_embedding(
+    state: State<'_, Arc<AppState>>,
+    book_id: i64,
+) -> Result<Option<Vec<f32>>, CommandError> {
+    Ok(state.vector_store.get_embedding(book_id))
+}
@@ -0,0 +1,269 @@
+//! Library processing pipeline: metadata, then embeddings, then edges
+
+use crate::ollama::EMBEDDING_TEXT_VERSION;
+use crate::state::AppState;
+use crate::worker::{process_all_pending, DrainResult, EmbeddingContext, EmbeddingProgress, EventSink};
+use crate::AppResult;
+use super::library::parse_books_metadata;
+use super::CommandError;
+use std::collections::HashSet;
+use std::sync::atomic::Ordering;
+use std::sync::Arc;
+use std::time::Instant;
+use tauri::State;
+
+/// Books parsed per metadata batch
+const METADATA_BATCH: usize = 20;
+
+/// Stage of the library pipeline
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize)]
+#[serde(rename_all = "camelCase")]
+pub enum PipelinePhase {
+    Metadata,
+    Embeddings,
+    Edges,
+}
+
+/// Progress payload for `pipeline:progress`
+#[derive(Debug, Clone, serde::Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PipelineProgress {
+    pub phase: PipelinePhase,
+    pub done: usize,
+    pub total: usize,
+}
+
+/// Outcome of [`run_pipeline`]
+#[derive(Debug, Clone, Default, serde::Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PipelineResult {
+    /// Books whose metadata was parsed successfully
+    pub metadata_parsed: i64,
+    pub metadata_failed: i64,
+    pub embeddings: DrainResult,
+    /// Books whose edges were computed in the edge phase
+    pub books_linked: usize,
+    pub edges_created: usize,
+    /// Stopped early by pause or cancel; running again picks up where it left off
+    pub interrupted: bool,
+    pub duration_ms: u64,
+}
+
+/// Forwards embedding events and mirrors their progress as `pipeline:progress`
+struct PhaseSink<'a> {
+    events: &'a dyn EventSink,
+}
+
+impl EventSink for PhaseSink<'_> {
+    fn emit_event(&self, event: &str, payload: serde_json::Value) {
+        if event == "embeddings:progress" {
+            if let Ok(progress) = serde_json::from_value::<EmbeddingProgress>(payload.clone()) {
+                let done = progress.processed + progress.failed;
+                emit_progress(self.events, PipelinePhase::Embeddings, done, done + progress.remaining);
+            }
+        }
+        self.events.emit_event(event, payload);
+    }
+}
+
+fn emit_progress(events: &dyn EventSink, phase: PipelinePhase, done: usize, total: usize) {
+    match serde_json::to_value(PipelineProgress { phase, done, total }) {
+        Ok(value) => events.emit_event("pipeline:progress", value),
+        Err(e) => tracing::warn!("Failed to serialize pipeline progress: {}", e),
+    }
+}
+
+/// Take the library from freshly scanned files to a built graph
+///
+/// Parses metadata for books without a description, embeds every pending
+/// book, then computes edges for embedded books that have none. Each phase
+/// only picks up work that isn't done yet, so an interrupted run can simply
+/// be started again. `paused` and `cancelled` are checked between books.
+pub async fn run_pipeline(ctx: &EmbeddingContext<'_>) -> AppResult<PipelineResult> {
+    let EmbeddingContext { db, vector_store, paused, cancelled, concurrency, events, .. } = *ctx;
+    let start = Instant::now();
+    let stopped = || paused.load(Ordering::Relaxed) || cancelled.load(Ordering::Relaxed);
+    let mut result = PipelineResult::default();
+
+    // Metadata: each book is attempted once per run so transient failures
+    // can't loop forever
+    let total = db.get_stats()?.books_needing_metadata.max(0) as usize;
+    emit_progress(events, PipelinePhase::Metadata, 0, total);
+    let mut attempted: HashSet<i64> = HashSet::new();
+    while !stopped() {
+        let batch: Vec<(i64, String)> = db
+            .get_books_needing_metadata((attempted.len() + METADATA_BATCH) as i64)?
+            .into_iter()
+            .filter(|(id, _)| !attempted.contains(id))
+            .take(METADATA_BATCH)
+            .collect();
+        if batch.is_empty() {
+            break;
+        }
+        attempted.extend(batch.iter().map(|(id, _)| *id));
+
+        let parsed = parse_books_metadata(db, &batch, concurrency).await?;
+        result.metadata_parsed += parsed.success;
+        result.metadata_failed += parsed.failed;
+        emit_progress(events, PipelinePhase::Metadata, attempted.len(), total.max(attempted.len()));
+    }
+
+    // Embeddings: storing each one also links the book into the graph
+    if !stopped() {
+        let total = db.get_stats()?.pending_embeddings.max(0) as usize;
+        emit_progress(events, PipelinePhase::Embeddings, 0, total);
+        let sink = PhaseSink { events };
+        result.embeddings = process_all_pending(&EmbeddingContext { events: &sink, ..*ctx }).await?;
+    }
+
+    // Edges: only books that are embedded but still unlinked
+    if !stopped() {
+        let unlinked = db.get_unlinked_embedded_book_ids(EMBEDDING_TEXT_VERSION)?;
+        emit_progress(events, PipelinePhase::Edges, 0, unlinked.len());
+        for (idx, &book_id) in unlinked.iter().enumerate() {
+            if stopped() {
+                break;
+            }
+            match crate::graph::recompute_book_edges(db, vector_store, book_id) {
+                Ok(edges) => {
+                    result.books_linked += 1;
+                    result.edges_created += edges;
+                }
+                Err(e) => tracing::warn!("Failed to compute edges for book {}: {}", book_id, e),
+            }
+            emit_progress(events, PipelinePhase::Edges, idx + 1, unlinked.len());
+        }
+    }
+
+    result.interrupted = stopped();
+    result.duration_ms = start.elapsed().as_millis() as u64;
+    tracing::info!(
+        "Pipeline finished: {} parsed, {} embedded, {} books linked{}",
+        result.metadata_parsed,
+        result.embeddings.processed,
+        result.books_linked,
+        if result.interrupted { " (interrupted)" } else { "" }
+    );
+    Ok(result)
+}
+
+/// Process the whole library in one call: metadata, embeddings, then edges
+///
+/// Emits `pipeline:progress` for every phase (plus the usual
+/// `embeddings:progress`) and honours `pause_processing`/`cancel_processing`.
+#[tauri::command]
+pub async fn rebuild_all(
+    state: State<'_, Arc<AppState>>,
+    app: tauri::AppHandle,
+    concurrency: Option<usize>,
+) -> Result<PipelineResult, CommandError> {
+    use crate::ollama::OllamaClient;
+
+    let concurrency = concurrency.unwrap_or(1).clamp(1, 8);
+    let client = {
+        let ollama = state.ollama.read();
+        OllamaClient::new(ollama.endpoint().to_string(), ollama.model().to_string())
+    };
+
+    state.processing_cancelled.store(false, Ordering::Relaxed);
+    let ctx = EmbeddingContext {
+        db: &state.db,
+        vector_store: &state.vector_store,
+        backend: &client,
+        rate_limiter: &state.rate_limiter,
+        paused: &state.processing_paused,
+        cancelled: &state.processing_cancelled,
+        concurrency,
+        events: &app,
+    };
+    run_pipeline(&ctx).await.map_err(CommandError::from)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::Database;
+    use crate::ollama::{EmbeddingBackend, RateLimiter};
+    use crate::scanner::Scanner;
+    use crate::vector::{VectorStore, EMBEDDING_DIM};
+    use futures::future::BoxFuture;
+    use parking_lot::Mutex;
+    use std::sync::atomic::AtomicBool;
+
+    #[derive(Default)]
+    struct CapturedEvents(Mutex<Vec<(String, serde_json::Value)>>);
+
+    impl EventSink for CapturedEvents {
+        fn emit_event(&self, event: &str, payload: serde_json::Value) {
+            self.0.lock().push((event.to_string(), payload));
+        }
+    }
+
+    struct ConstantBackend;
+
+    impl EmbeddingBackend for ConstantBackend {
+        fn model(&self) -> &str {
+            "mock"
+        }
+
+        fn embed<'a>(&'a self, _text: &'a str) -> BoxFuture<'a, AppResult<Vec<f32>>> {
+            Box::pin(async { Ok(vec![0.5f32; EMBEDDING_DIM]) })
+        }
+    }
+
+    #[tokio::test]
+    async fn test_pipeline_processes_fixture_library() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let library = dir.path().join("books");
+        std::fs::create_dir(&library).unwrap();
+        for (title, author) in [("Emma", "Jane Austen"), ("Persuasion", "Jane Austen"), ("Middlemarch", "George Eliot")] {
+            let bytes = crate::epub::test_epub_bytes_with_description(title, author, "A novel of manners and marriage.");
+            std::fs::write(library.join(format!("{}.epub", title)), bytes).unwrap();
+        }
+
+        let db_path = dir.path().join("test.db");
+        let db = Database::new(&db_path).unwrap();
+        let vector_store = VectorStore::new(db_path.to_str().unwrap()).unwrap();
+        for book in Scanner::new().fast_scan(&library).unwrap() {
+            db.insert_book(&book).unwrap();
+        }
+
+        let (paused, cancelled) = (AtomicBool::new(false), AtomicBool::new(false));
+        let rate_limiter = RateLimiter::default();
+        let events = CapturedEvents::default();
+        let ctx = EmbeddingContext {
+            db: &db,
+            vector_store: &vector_store,
+            backend: &ConstantBackend,
+            rate_limiter: &rate_limiter,
+            paused: &paused,
+            cancelled: &cancelled,
+            concurrency: 2,
+            events: &events,
+        };
+        let run = || run_pipeline(&ctx);
+
+        let first = run().await.unwrap();
+        assert_eq!(first.metadata_parsed, 3);
+        assert_eq!(first.embeddings.processed, 3);
+        assert!(!first.interrupted);
+        assert!(db.get_unlinked_embedded_book_ids(EMBEDDING_TEXT_VERSION).unwrap().is_empty());
+        let stats = db.get_stats().unwrap();
+        assert_eq!((stats.books_needing_metadata, stats.pending_embeddings), (0, 0));
+
+        let phases: HashSet<String> = events
+            .0
+            .lock()
+            .iter()
+            .filter(|(event, _)| event == "pipeline:progress")
+            .map(|(_, payload)| payload["phase"].as_str().unwrap().to_string())
+            .collect();
+        assert_eq!(phases.len(), 3);
+
+        // A second run finds nothing left to do
+        let second = run().await.unwrap();
+        assert_eq!(second.metadata_parsed, 0);
+        assert_eq!(second.embeddings.processed, 0);
+        assert_eq!(second.books_linked, 0);
+    }
+}
@@ -1,11 +1,20 @@
 //! Recommendation commands
 
-use crate::db::Book;
+use crate::db::{Book, Database};
 use crate::state::AppState;
+use crate::vector::VectorStore;
+use crate::{AppError, AppResult};
+use super::CommandError;
 use serde::Serialize;
+use std::collections::{BinaryHeap, HashMap, HashSet};
 use std::sync::Arc;
+use std::time::{Duration, Instant};
 use tauri::State;
 
+/// Extra time blocking work gets past its deadline to hand back a partial
+/// result before the command gives up with a timeout error
+const DEADLINE_GRACE: Duration = Duration::from_millis(500);
+
 /// A book recommendation with score and reasons
 #[derive(Debug, Clone, Serialize)]
 #[serde(rename_all = "camelCase")]
@@ -31,6 +40,19 @@ pub enum RecommendationReason {
     ReadersAlsoLiked { based_on: String },
     #[serde(rename_all = "camelCase")]
     NextInSeries { previous: String },
+    #[serde(rename_all = "camelCase")]
+    RelatedAuthor { author: String, related_to: String },
+    /// Linked by hand with `add_manual_edge`
+    ManuallyLinked,
+}
+
+/// An unread book that fits in a reading-time budget
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TimedRecommendation {
+    pub book: Book,
+    pub reading_minutes: f64,
+    pub score: f64,
 }
 
 /// Graph data for visualization
@@ -39,6 +61,8 @@ pub enum RecommendationReason {
 pub struct GraphData {
     pub nodes: Vec<GraphNode>,
     pub edges: Vec<GraphEdge>,
+    /// More neighbors are available past this page
+    pub has_more: bool,
 }
 
 #[derive(Debug, Clone, Serialize)]
@@ -48,7 +72,7 @@ pub struct GraphNode {
     pub title: String,
     pub author: Option<String>,
     pub cover_path: Option<String>,
-    pub rating: Option<i32>,
+    pub rating: Option<f64>,
 }
 
 #[derive(Debug, Clone, Serialize)]
@@ -62,12 +86,24 @@ pub struct GraphEdge {
 
 /// Get recommendations similar to a specific book
 #[tauri::command]
+///
+/// `edge_types` limits recommendations to those relationships (e.g.
+/// `["series"]`); without it every edge type is considered. `sources`
+/// likewise restricts candidates to books from those import sources (e.g.
+/// `["calibre"]`). `diversity` (0-1) trades relevance for variety, see
+/// [`diversify`]; the default 0 keeps the plain weight order.
 pub async fn get_recommendations(
     state: State<'_, Arc<AppState>>,
     book_id: Option<i64>,
     limit: Option<i64>,
-) -> Result<Vec<Recommendation>, String> {
+    edge_types: Option<Vec<String>>,
+    sources: Option<Vec<String>>,
+    diversity: Option<f64>,
+) -> Result<Vec<Recommendation>, CommandError> {
     let limit = limit.unwrap_or(20).min(100);
+    let diversity = diversity.unwrap_or(0.0).clamp(0.0, 1.0);
+    // Diversifying needs a wider pool to choose from
+    let pool = if diversity > 0.0 { limit * DIVERSITY_POOL_FACTOR } else { limit };
     
     let book_id = match book_id {
         Some(id) => id,
@@ -75,31 +111,158 @@ pub async fn get_recommendations(
     };
     
     // Get the source book
-    let source_book = state.db.get_book(book_id).map_err(|e| e.to_string())?;
+    let source_book = state.db.get_book(book_id)?;
     
-    // Get edges from this book
-    let edges = state.db.get_edges(book_id, 0.3).map_err(|e| e.to_string())?;
+    let recommendations = edge_recommendations(
+        &state.db,
+        &source_book,
+        pool,
+        edge_types.as_deref(),
+        sources.as_deref(),
+    )?;
 
-    tracing::debug!("get_recommendations: book_id={}, found {} edges", book_id, edges.len());
+    tracing::debug!("get_recommendations: book_id={}, found {} edges", book_id, recommendations.len());
 
-    if edges.is_empty() {
+    if recommendations.is_empty() && edge_types.is_none() {
         // No graph edges yet, fall back to simple matching
         tracing::debug!("get_recommendations: falling back to simple matching");
-        return get_simple_recommendations(&state, &source_book, limit);
+        let mut recommendations = get_simple_recommendations(&state, &source_book, pool)?;
+        recommendations.retain(|rec| source_allowed(&rec.book, sources.as_deref()));
+        return Ok(diversify(&state.vector_store, recommendations, diversity, limit as usize));
     }
     
-    // Build recommendations from edges
+    Ok(diversify(&state.vector_store, recommendations, diversity, limit as usize))
+}
+
+/// How many candidates per requested result are considered when diversifying
+const DIVERSITY_POOL_FACTOR: i64 = 3;
+
+/// Re-rank recommendations with maximal marginal relevance
+///
+/// `diversity` 0 keeps the relevance order, 1 picks purely by dissimilarity
+/// (embedding similarity) to the books already chosen, which breaks up runs
+/// of near-identical books such as one author's catalogue. Books listed more
+/// than once (one entry per edge type) are merged first.
+fn diversify(
+    vector_store: &VectorStore,
+    recommendations: Vec<Recommendation>,
+    diversity: f64,
+    limit: usize,
+) -> Vec<Recommendation> {
+    if diversity <= 0.0 {
+        let mut recommendations = recommendations;
+        recommendations.truncate(limit);
+        return recommendations;
+    }
+
+    let mut by_id: HashMap<i64, Recommendation> = HashMap::new();
+    let mut order = Vec::new();
+    for rec in recommendations {
+        match by_id.get_mut(&rec.book.id) {
+            Some(existing) => {
+                existing.score = existing.score.max(rec.score);
+                existing.reasons.extend(rec.reasons);
+            }
+            None => {
+                order.push(rec.book.id);
+                by_id.insert(rec.book.id, rec);
+            }
+        }
+    }
+
+    let candidates: Vec<crate::graph::TraversalCandidate> = order
+        .iter()
+        .map(|id| crate::graph::TraversalCandidate {
+            book_id: *id,
+            score: by_id[id].score,
+            path: vec![*id],
+            edge_types: vec![],
+        })
+        .collect();
+    let embeddings: HashMap<i64, Vec<f32>> = order
+        .iter()
+        .filter_map(|&id| vector_store.get_embedding(id).map(|e| (id, e)))
+        .collect();
+    let metric = vector_store.metric();
+
+    crate::graph::maximal_marginal_relevance(
+        &candidates,
+        |a, b| match (embeddings.get(&a), embeddings.get(&b)) {
+            (Some(a), Some(b)) => metric.normalize(metric.similarity(a, b)),
+            _ => 0.0,
+        },
+        1.0 - diversity,
+        limit,
+    )
+    .into_iter()
+    .filter_map(|c| by_id.remove(&c.book_id))
+    .collect()
+}
+
+/// Time budget from the `recommendation_timeout_ms` setting (`None` = unbounded)
+fn recommendation_timeout(db: &Database) -> AppResult<Option<Duration>> {
+    let timeout_ms = db.get_settings()?.recommendation_timeout_ms;
+    Ok((timeout_ms > 0).then(|| Duration::from_millis(timeout_ms)))
+}
+
+/// Run blocking recommendation work within a time budget
+///
+/// `work` is given the deadline by which it should return its best partial
+/// result. If it still hasn't returned shortly after, the command fails with
+/// a timeout error rather than hanging the UI.
+async fn run_with_deadline<T, F>(timeout: Option<Duration>, work: F) -> Result<T, CommandError>
+where
+    T: Send + 'static,
+    F: FnOnce(Option<Instant>) -> AppResult<T> + Send + 'static,
+{
+    let deadline = timeout.map(|timeout| Instant::now() + timeout);
+    let task = tokio::task::spawn_blocking(move || work(deadline));
+    let Some(timeout) = timeout else {
+        return task.await?.map_err(CommandError::from);
+    };
+
+    match tokio::time::timeout(timeout + DEADLINE_GRACE, task).await {
+        Ok(joined) => joined?.map_err(CommandError::from),
+        Err(_) => Err(AppError::Timeout(format!(
+            "Recommendations took longer than {} ms",
+            timeout.as_millis()
+        ))
+        .into()),
+    }
+}
+
+/// Whether a book passes an optional import-source filter
+fn source_allowed(book: &Book, sources: Option<&[String]>) -> bool {
+    sources.map_or(true, |sources| sources.contains(&book.source))
+}
+
+/// Recommendations from a book's stored graph edges, strongest first
+fn edge_recommendations(
+    db: &Database,
+    source_book: &Book,
+    limit: i64,
+    edge_types: Option<&[String]>,
+    sources: Option<&[String]>,
+) -> AppResult<Vec<Recommendation>> {
+    let edges = db.get_edges(source_book.id, 0.3, edge_types)?;
+
     let mut recommendations = Vec::new();
     
-    for edge in edges.iter().take(limit as usize) {
-        let target_id = if edge.source_id == book_id {
+    for edge in &edges {
+        if recommendations.len() >= limit as usize {
+            break;
+        }
+        let target_id = if edge.source_id == source_book.id {
             edge.target_id
         } else {
             edge.source_id
         };
         
-        if let Ok(book) = state.db.get_book(target_id) {
-            let reasons = build_reasons(&source_book, &book, &edge.edge_type, edge.weight);
+        if let Ok(book) = db.get_book(target_id) {
+            if !source_allowed(&book, sources) {
+                continue;
+            }
+            let reasons = build_reasons(db, source_book, &book, &edge.edge_type, edge.weight);
             recommendations.push(Recommendation {
                 book,
                 score: edge.weight,
@@ -108,231 +271,707 @@ pub async fn get_recommendations(
         }
     }
     
-    // Sort by score descending
-    recommendations.sort_by(|a, b| b.score.partial_cmp(&a.score).unwrap_or(std::cmp::Ordering::Equal));
+    // Sort by score descending, ties by book id
+    recommendations.sort_by(|a, b| {
+        b.score
+            .partial_cmp(&a.score)
+            .unwrap_or(std::cmp::Ordering::Equal)
+            .then(a.book.id.cmp(&b.book.id))
+    });
     
     Ok(recommendations)
 }
 
-/// Get personalized recommendations based on user's ratings
+/// Get recommendations for several books taken together
+///
+/// Seeds are combined through their average embedding and a multi-hop
+/// traversal from all of them; the seed books themselves are excluded.
+#[tauri::command]
+pub async fn get_recommendations_for_set(
+    state: State<'_, Arc<AppState>>,
+    book_ids: Vec<i64>,
+    limit: Option<i64>,
+) -> Result<Vec<Recommendation>, CommandError> {
+    let limit = limit.unwrap_or(20).clamp(1, 100) as usize;
+
+    let candidates =
+        crate::graph::recommendations_for_set(&state.vector_store, &*state.book_graph()?, &book_ids, limit)?;
+
+    let seeds: std::collections::HashMap<i64, Book> = book_ids
+        .iter()
+        .filter_map(|&id| state.db.get_book(id).ok().map(|book| (id, book)))
+        .collect();
+    let mut recommendations = Vec::with_capacity(candidates.len());
+    for candidate in candidates {
+        let Ok(book) = state.db.get_book(candidate.book_id) else {
+            continue;
+        };
+
+        let mut reasons = Vec::new();
+        if let (Some(&seed_id), Some(edge_type)) = (candidate.path.first(), candidate.edge_types.first()) {
+            if let Some(seed) = seeds.get(&seed_id) {
+                reasons = build_reasons(&state.db, seed, &book, edge_type, candidate.score);
+            }
+        }
+        if reasons.is_empty() {
+            if let Some(similarity) = candidate.similarity {
+                reasons.push(RecommendationReason::SimilarContent { similarity });
+            }
+        }
+
+        recommendations.push(Recommendation {
+            book,
+            score: candidate.score,
+            reasons,
+        });
+    }
+
+    Ok(recommendations)
+}
+
+/// Get the books whose embeddings are nearest a book's ("more like this")
+///
+/// Read from the similar-books cache filled at embedding time (see the
+/// `similar_cache_size` setting); books without cached entries are looked up
+/// in the vector store instead.
+#[tauri::command]
+pub async fn get_similar_books(
+    state: State<'_, Arc<AppState>>,
+    book_id: i64,
+    limit: Option<i64>,
+) -> Result<Vec<Recommendation>, CommandError> {
+    let limit = limit.unwrap_or(10).clamp(1, 100);
+    let mut similar = state.db.get_similar_cache(book_id, limit)?;
+    if similar.is_empty() {
+        similar = state.vector_store.find_similar_to_book(book_id, limit as usize);
+    }
+
+    Ok(similar
+        .into_iter()
+        .filter_map(|(target_id, similarity)| {
+            let book = state.db.get_book(target_id).ok()?;
+            Some(Recommendation {
+                book,
+                score: similarity,
+                reasons: vec![RecommendationReason::SimilarContent { similarity }],
+            })
+        })
+        .collect())
+}
+
+/// Get recommendations from the full hybrid pipeline (candidate expansion,
+/// personalized PageRank, MMR diversity)
+///
+/// `strategy` selects candidate expansion: `"traversal"` (default) or
+/// `"randomWalk"`, which samples `walks` weighted walks of `walk_length`
+/// steps. Passing the same `seed` reproduces the same walks. The pipeline
+/// runs within `recommendation_timeout_ms` and returns what it has by then.
+#[tauri::command]
+pub async fn get_advanced_recommendations(
+    state: State<'_, Arc<AppState>>,
+    book_id: i64,
+    limit: Option<i64>,
+    strategy: Option<String>,
+    walks: Option<usize>,
+    walk_length: Option<usize>,
+    seed: Option<u64>,
+) -> Result<Vec<Recommendation>, CommandError> {
+    use crate::graph::{generate_recommendations, CandidateStrategy};
+
+    let limit = limit.unwrap_or(20).clamp(1, 100) as usize;
+    let strategy = match strategy.as_deref() {
+        None | Some("traversal") => CandidateStrategy::Traversal,
+        Some("randomWalk") => CandidateStrategy::RandomWalk {
+            walks: walks.unwrap_or(1000).clamp(1, 100_000),
+            walk_length: walk_length.unwrap_or(4).clamp(1, 20),
+            seed: seed.unwrap_or_else(|| chrono::Utc::now().timestamp_nanos_opt().unwrap_or(0) as u64),
+        },
+        Some(other) => return Err(AppError::InvalidInput(format!("Unknown recommendation strategy: {}", other)).into()),
+    };
+
+    let source = state.db.get_book(book_id)?;
+    let liked_rating = state.db.get_settings()?.liked_rating_threshold;
+    let highly_rated: Vec<i64> = state
+        .db
+        .get_preference_signals()?
+        .into_iter()
+        .filter(|s| s.rating.is_some_and(|r| r >= liked_rating))
+        .map(|s| s.book_id)
+        .collect();
+
+    let timeout = recommendation_timeout(&state.db)?;
+    let app = state.inner().clone();
+    let scored = run_with_deadline(timeout, move |deadline| {
+        let graph = app.book_graph()?;
+        Ok(generate_recommendations(&graph, book_id, &highly_rated, &strategy, limit, deadline))
+    })
+    .await?;
+
+    let mut recommendations = Vec::with_capacity(scored.len());
+    for rec in scored {
+        let Ok(book) = state.db.get_book(rec.book_id) else {
+            continue;
+        };
+        // Direct neighbours get edge-specific reasons; longer paths fall back
+        // to "readers also liked" the source
+        let reasons = match rec.edge_types.as_slice() {
+            [edge_type] => build_reasons(&state.db, &source, &book, edge_type, rec.traversal_score),
+            _ => vec![RecommendationReason::ReadersAlsoLiked {
+                based_on: source.title.clone(),
+            }],
+        };
+        recommendations.push(Recommendation {
+            book,
+            score: rec.combined_score,
+            reasons,
+        });
+    }
+
+    Ok(recommendations)
+}
+
+/// Get personalized recommendations based on user's ratings and reading history
 #[tauri::command]
 pub async fn get_personalized_recommendations(
     state: State<'_, Arc<AppState>>,
     limit: Option<i64>,
-) -> Result<Vec<Recommendation>, String> {
-    let limit = limit.unwrap_or(20).min(100);
-    
-    // For now, return recommendations based on highly-rated books
-    // In Phase 4, this will use the full graph traversal algorithm
-    
-    // Query books the user rated highly
-    let query = crate::db::BookQuery {
-        min_rating: Some(4),
-        limit: Some(10),
-        sort_by: Some("rating".to_string()),
-        sort_order: Some("desc".to_string()),
-        ..Default::default()
+) -> Result<Vec<Recommendation>, CommandError> {
+    use crate::graph::{
+        build_preference_vector, recent_reads_cutoff, weighted_personalized_pagerank,
+        PageRankConfig,
     };
+
+    let limit = limit.unwrap_or(20).min(100);
     
-    let rated_books = state.db.query_books(&query).map_err(|e| e.to_string())?;
+    // Build the preference vector from ratings and read statuses inside the
+    // configured recent-reads window
+    let settings = state.db.get_settings()?;
+    let now = chrono::Utc::now().timestamp();
+    let seeds = state
+        .db
+        .get_preference_signals_since(recent_reads_cutoff(settings.recent_reads_days, now))?;
+    let preferences = build_preference_vector(&seeds, now, settings.liked_rating_threshold);
+
+    let mut liked: Vec<(i64, f64)> = preferences
+        .iter()
+        .filter(|&&(_, weight)| weight > 0.0)
+        .copied()
+        .collect();
+    liked.sort_by(|a, b| b.1.partial_cmp(&a.1).unwrap_or(std::cmp::Ordering::Equal).then(a.0.cmp(&b.0)));
+    liked.truncate(10);
     
-    if rated_books.items.is_empty() {
-        // No rated books, return recent additions
+    if liked.is_empty() {
+        // No reading history, return recent additions
         let query = crate::db::BookQuery {
             limit: Some(limit),
             sort_by: Some("dateAdded".to_string()),
             sort_order: Some("desc".to_string()),
             ..Default::default()
         };
-        let recent = state.db.query_books(&query).map_err(|e| e.to_string())?;
+        let recent = state.db.query_books(&query)?;
         return Ok(recent.items.into_iter().map(|book| Recommendation {
             book,
             score: 0.5,
             reasons: vec![],
         }).collect());
     }
+
+    // Global relevance from the user's weighted preferences, pushed away
+    // from low-rated books and their neighborhoods
+    let dislikes = state.db.get_disliked_book_ids(crate::db::DISLIKED_RATING)?;
+    let graph = state.book_graph()?;
+    let pagerank =
+        weighted_personalized_pagerank(&graph, &[], &preferences, &dislikes, &PageRankConfig::default());
+    let max_pagerank = pagerank.values().copied().fold(0.0, f64::max);
+    let max_preference = liked[0].1;
+
+    // Books the user already knows about are never recommended, even if
+    // they fall outside the window
+    let signals = state.db.get_preference_signals()?;
+    let known: std::collections::HashSet<i64> = signals.iter().map(|s| s.book_id).collect();
     
-    // Aggregate recommendations from each rated book
+    // Aggregate recommendations from each preferred book, stopping with what
+    // has been gathered once the time budget runs out
+    let deadline = recommendation_timeout(&state.db)?.map(|timeout| Instant::now() + timeout);
     let mut all_recs: Vec<Recommendation> = Vec::new();
     
-    for rated_book in &rated_books.items {
-        if let Ok(recs) = get_recommendations(state.clone(), Some(rated_book.id), Some(5)).await {
-            for rec in recs {
-                // Skip books already rated
-                if rated_books.items.iter().any(|b| b.id == rec.book.id) {
+    for &(book_id, weight) in &liked {
+        let recs = get_recommendations(state.clone(), Some(book_id), Some(5), None, None, None);
+        let recs = match deadline {
+            Some(deadline) => {
+                let remaining = deadline.saturating_duration_since(Instant::now());
+                match tokio::time::timeout(remaining, recs).await {
+                    Ok(recs) => recs,
+                    Err(_) => {
+                        tracing::debug!("Personalized recommendations hit their deadline");
+                        break;
+                    }
+                }
+            }
+            None => recs.await,
+        };
+        if let Ok(recs) = recs {
+            for mut rec in recs {
+                if known.contains(&rec.book.id) {
                     continue;
                 }
+                let pagerank_score = if max_pagerank > 0.0 {
+                    pagerank.get(&rec.book.id).copied().unwrap_or(0.0) / max_pagerank
+                } else {
+                    0.0
+                };
+                rec.score = 0.7 * rec.score * (weight / max_preference) + 0.3 * pagerank_score;
                 all_recs.push(rec);
             }
         }
     }
     
-    // Deduplicate and sort
-    all_recs.sort_by(|a, b| b.score.partial_cmp(&a.score).unwrap_or(std::cmp::Ordering::Equal));
-    all_recs.dedup_by(|a, b| a.book.id == b.book.id);
+    // Deduplicate (keeping the best score) and sort
+    all_recs.sort_by(|a, b| {
+        b.score
+            .partial_cmp(&a.score)
+            .unwrap_or(std::cmp::Ordering::Equal)
+            .then(a.book.id.cmp(&b.book.id))
+    });
+    let mut seen = std::collections::HashSet::new();
+    all_recs.retain(|rec| seen.insert(rec.book.id));
     all_recs.truncate(limit as usize);
     
     Ok(all_recs)
 }
 
+/// Get unread books that can be finished within a time budget ("quick reads")
+/// Books without a word count are left out; times use the personal reading
+/// speed once enough books have been finished
+#[tauri::command]
+pub async fn get_books_under_time(
+    state: State<'_, Arc<AppState>>,
+    max_minutes: i64,
+    limit: Option<i64>,
+) -> Result<Vec<TimedRecommendation>, CommandError> {
+    use crate::epub::estimate_reading_time;
+
+    let limit = limit.unwrap_or(20).min(100);
+    let wpm = state.db.get_settings()?.words_per_minute();
+
+    let ranked = crate::graph::books_under_time(
+        &state.db,
+        &*state.book_graph()?,
+        max_minutes as f64,
+        wpm,
+        limit as usize,
+    )?;
+
+    Ok(ranked
+        .into_iter()
+        .map(|(book, score)| TimedRecommendation {
+            reading_minutes: estimate_reading_time(book.word_count.unwrap_or(0), wpm),
+            book,
+            score,
+        })
+        .collect())
+}
+
+/// Get books that are dead ends in the graph (no edge at or above
+/// `min_weight`); check `embeddingStatus` to tell unembedded books from
+/// ones that just don't connect
+#[tauri::command]
+pub async fn get_isolated_books(
+    state: State<'_, Arc<AppState>>,
+    min_weight: Option<f64>,
+    limit: Option<i64>,
+) -> Result<Vec<Book>, CommandError> {
+    let min_weight = min_weight.unwrap_or(0.3);
+    let limit = limit.unwrap_or(100).min(1000);
+    state.db.get_isolated_books(min_weight, limit).map_err(CommandError::from)
+}
+
+/// "Surprise me": books moderately similar to the user's taste profile
+///
+/// Skips the most obvious matches in favour of related-but-different picks.
+#[tauri::command]
+pub async fn get_serendipitous_recommendations(
+    state: State<'_, Arc<AppState>>,
+    limit: Option<i64>,
+) -> Result<Vec<Recommendation>, CommandError> {
+    let limit = limit.unwrap_or(10).clamp(1, 50) as usize;
+
+    let results = crate::graph::serendipitous_recommendations(
+        &state.db,
+        &state.vector_store,
+        &crate::graph::SerendipityConfig::default(),
+        limit,
+    )?;
+
+    Ok(results
+        .into_iter()
+        .map(|(book, similarity)| Recommendation {
+            book,
+            score: similarity,
+            reasons: vec![RecommendationReason::SimilarContent { similarity }],
+        })
+        .collect())
+}
+
+/// "Authors like the ones you love": books by unread authors whose work
+/// is close to the user's favorite authors
+#[tauri::command]
+pub async fn get_author_network_recommendations(
+    state: State<'_, Arc<AppState>>,
+    limit: Option<i64>,
+) -> Result<Vec<Recommendation>, CommandError> {
+    let limit = limit.unwrap_or(20).clamp(1, 100) as usize;
+
+    let results = crate::graph::author_network_recommendations(
+        &state.db,
+        &crate::graph::AuthorNetworkConfig::default(),
+        limit,
+    )?;
+
+    Ok(results
+        .into_iter()
+        .map(|candidate| Recommendation {
+            book: candidate.book,
+            score: candidate.score,
+            reasons: vec![RecommendationReason::RelatedAuthor {
+                author: candidate.author,
+                related_to: candidate.via_author,
+            }],
+        })
+        .collect())
+}
+
+/// One book on a connection path
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ConnectionStep {
+    pub book_id: i64,
+    pub title: String,
+    /// Type of the edge leading to this book (`None` for the starting book)
+    pub edge_type: Option<String>,
+    /// `edge_type` for display, e.g. "shared author"
+    pub label: Option<String>,
+}
+
+/// Explain how two books are connected in the recommendation graph
+///
+/// Returns the strongest path from `source_id` to `target_id` (see
+/// `BookGraph::shortest_path`), or `None` when they aren't connected.
+#[tauri::command]
+pub async fn get_connection_path(
+    state: State<'_, Arc<AppState>>,
+    source_id: i64,
+    target_id: i64,
+) -> Result<Option<Vec<ConnectionStep>>, CommandError> {
+    let graph = state.book_graph()?;
+    let Some(path) = graph.shortest_path(source_id, target_id) else {
+        return Ok(None);
+    };
+
+    let steps = path
+        .into_iter()
+        .map(|(book_id, edge_type)| {
+            let title = state.db.get_book(book_id)?.title;
+            let edge_type = (!edge_type.is_empty()).then_some(edge_type);
+            Ok(ConnectionStep {
+                book_id,
+                title,
+                label: edge_type.as_deref().map(edge_type_label),
+                edge_type,
+            })
+        })
+        .collect::<AppResult<Vec<_>>>()?;
+    Ok(Some(steps))
+}
+
+/// Describe an edge type the way a reader would
+fn edge_type_label(edge_type: &str) -> String {
+    match edge_type {
+        "content" => "similar content".to_string(),
+        "author" => "shared author".to_string(),
+        "series" => "same series".to_string(),
+        "tag" => "shared tags".to_string(),
+        crate::db::MANUAL_EDGE_TYPE => "linked by you".to_string(),
+        other => other.replace('_', " "),
+    }
+}
+
 /// Get graph data for visualization centered on a book
+///
+/// Nodes are selected strongest-edge first (see `build_book_graph`), so
+/// repeated calls return the same graph. `offset` pages through neighbors
+/// beyond the first `max_nodes`. `exclude_read` hides finished and abandoned
+/// books other than the center.
 #[tauri::command]
 pub async fn get_book_graph(
     state: State<'_, Arc<AppState>>,
     center_id: i64,
     depth: Option<i32>,
     max_nodes: Option<i32>,
-) -> Result<GraphData, String> {
-    let depth = depth.unwrap_or(2).min(3);
-    let max_nodes = max_nodes.unwrap_or(50).min(200) as usize;
+    offset: Option<i32>,
+    exclude_read: Option<bool>,
+) -> Result<GraphData, CommandError> {
+    let depth = depth.unwrap_or(2).clamp(0, 3);
+    let max_nodes = max_nodes.unwrap_or(50).clamp(1, 200) as usize;
+    let offset = offset.unwrap_or(0).max(0) as usize;
 
-    let mut nodes: Vec<GraphNode> = Vec::new();
-    let mut edges: Vec<GraphEdge> = Vec::new();
-    let mut visited: std::collections::HashSet<i64> = std::collections::HashSet::new();
-    let mut frontier: Vec<i64> = vec![center_id];
+    build_book_graph(
+        &state.db,
+        &state.vector_store,
+        center_id,
+        depth,
+        max_nodes,
+        offset,
+        exclude_read.unwrap_or(false),
+    )
+    .map_err(CommandError::from)
+}
+
+/// Node waiting to be added to the neighborhood
+///
+/// Ordered by the weight of the edge that reached it, ties broken by lower
+/// book ID, so expansion order never depends on query or hash order.
+struct Expansion {
+    weight: f64,
+    book_id: i64,
+    depth: i32,
+    /// Book whose edge reached this one
+    parent: Option<i64>,
+}
+
+impl PartialEq for Expansion {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == std::cmp::Ordering::Equal
+    }
+}
 
+impl Eq for Expansion {}
+
+impl PartialOrd for Expansion {
+    fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Expansion {
+    fn cmp(&self, other: &Self) -> std::cmp::Ordering {
+        self.weight
+            .total_cmp(&other.weight)
+            .then_with(|| other.book_id.cmp(&self.book_id))
+    }
+}
+
+/// Build the neighborhood graph around a book
+///
+/// Starting from the center, the unvisited book reachable over the strongest
+/// edge is added next (best-first), up to `depth` hops away. The center is
+/// always returned, followed by the ranked neighbors
+/// `offset..offset + max_nodes - 1`; edges are those between returned nodes.
+///
+/// With `exclude_read`, finished and abandoned books are still traversed but
+/// left out of the result. A returned book reached through hidden ones gets
+/// a `bridged` edge to its nearest returned ancestor, weighted by the
+/// weakest edge on that path, so the graph stays connected.
+pub fn build_book_graph(
+    db: &Database,
+    vector_store: &VectorStore,
+    center_id: i64,
+    depth: i32,
+    max_nodes: usize,
+    offset: usize,
+    exclude_read: bool,
+) -> AppResult<GraphData> {
     // Check if we have any edges for this book (use same threshold as recommendations)
-    let has_stored_edges = state.db.get_edges(center_id, 0.3)
-        .map(|e| !e.is_empty())
-        .unwrap_or(false);
+    let has_stored_edges = !db.get_edges(center_id, 0.3, None)?.is_empty();
 
     tracing::debug!("get_book_graph: center_id={}, has_stored_edges={}", center_id, has_stored_edges);
 
-    for current_depth in 0..depth {
-        let mut next_frontier = Vec::new();
+    let wanted = offset + max_nodes.max(1);
+    let mut ranked: Vec<Book> = Vec::new();
+    let mut visited: HashSet<i64> = HashSet::new();
+    let mut candidate_edges: Vec<GraphEdge> = Vec::new();
+    let mut heap = BinaryHeap::new();
+    let mut has_more = false;
+    // How each traversed book was reached: (parent, edge weight)
+    let mut reached_from: HashMap<i64, (Option<i64>, f64)> = HashMap::new();
+    let mut hidden: HashSet<i64> = HashSet::new();
 
-        for book_id in frontier {
-            if visited.contains(&book_id) || nodes.len() >= max_nodes {
-                continue;
-            }
-            visited.insert(book_id);
-
-            // Add node
-            if let Ok(book) = state.db.get_book(book_id) {
-                nodes.push(GraphNode {
-                    id: book.id,
-                    title: book.title.clone(),
-                    author: book.author.clone(),
-                    cover_path: book.cover_path.clone(),
-                    rating: book.rating,
-                });
+    heap.push(Expansion { weight: f64::INFINITY, book_id: center_id, depth: 0, parent: None });
 
-                // Try to get stored edges first (use 0.3 threshold like recommendations)
-                let book_edges = state.db.get_edges(book_id, 0.3).unwrap_or_default();
-
-                tracing::debug!("get_book_graph: book_id={}, found {} stored edges", book_id, book_edges.len());
-
-                if !book_edges.is_empty() {
-                    // Use stored edges
-                    for edge in book_edges {
-                        let target_id = if edge.source_id == book_id {
-                            edge.target_id
-                        } else {
-                            edge.source_id
-                        };
-
-                        edges.push(GraphEdge {
-                            source: edge.source_id,
-                            target: edge.target_id,
-                            weight: edge.weight,
-                            edge_type: edge.edge_type,
-                        });
-
-                        if !visited.contains(&target_id) {
-                            next_frontier.push(target_id);
-                        }
-                    }
-                } else if !has_stored_edges && current_depth == 0 {
-                    // No stored edges anywhere - fallback to vector similarity search
-                    // Only do this for the center node to avoid expensive searches
-                    let similar = state.vector_store.find_similar_to_book(book_id, 20);
-
-                    for (target_id, similarity) in similar {
-                        if similarity < 0.3 || visited.contains(&target_id) {
-                            continue;
-                        }
-
-                        if let Ok(target_book) = state.db.get_book(target_id) {
-                            let (weight, edge_type) = crate::graph::compute_edge_weight(
-                                &book,
-                                &target_book,
-                                Some(similarity),
-                            );
-
-                            if weight >= 0.3 {
-                                edges.push(GraphEdge {
-                                    source: book_id,
-                                    target: target_id,
-                                    weight,
-                                    edge_type,
-                                });
-                                next_frontier.push(target_id);
-                            }
-                        }
-                    }
+    while let Some(next) = heap.pop() {
+        if visited.contains(&next.book_id) {
+            continue;
+        }
+        if ranked.len() >= wanted {
+            has_more = true;
+            break;
+        }
+        visited.insert(next.book_id);
 
-                    // Also add same author/series as fallback
-                    if let Some(ref author) = book.author {
-                        let query = crate::db::BookQuery {
-                            author: Some(author.clone()),
-                            limit: Some(10),
-                            ..Default::default()
-                        };
-                        if let Ok(result) = state.db.query_books(&query) {
-                            for other in result.items {
-                                if other.id != book_id && !visited.contains(&other.id) {
-                                    edges.push(GraphEdge {
-                                        source: book_id,
-                                        target: other.id,
-                                        weight: 0.7,
-                                        edge_type: "author".to_string(),
-                                    });
-                                    next_frontier.push(other.id);
-                                }
-                            }
-                        }
-                    }
+        let Ok(book) = db.get_book(next.book_id) else {
+            continue;
+        };
+        reached_from.insert(book.id, (next.parent, next.weight));
 
-                    if let Some(ref series) = book.series {
-                        let query = crate::db::BookQuery {
-                            series: Some(series.clone()),
-                            limit: Some(10),
-                            ..Default::default()
-                        };
-                        if let Ok(result) = state.db.query_books(&query) {
-                            for other in result.items {
-                                if other.id != book_id && !visited.contains(&other.id) {
-                                    // Check if edge already exists
-                                    if !edges.iter().any(|e|
-                                        (e.source == book_id && e.target == other.id) ||
-                                        (e.source == other.id && e.target == book_id)
-                                    ) {
-                                        edges.push(GraphEdge {
-                                            source: book_id,
-                                            target: other.id,
-                                            weight: 0.9,
-                                            edge_type: "series".to_string(),
-                                        });
-                                        next_frontier.push(other.id);
-                                    }
-                                }
-                            }
-                        }
-                    }
+        if next.depth < depth {
+            let use_fallback = !has_stored_edges && next.book_id == center_id;
+            for edge in graph_neighbors(db, vector_store, &book, use_fallback)? {
+                let other = if edge.source == book.id { edge.target } else { edge.source };
+                if !visited.contains(&other) {
+                    heap.push(Expansion {
+                        weight: edge.weight,
+                        book_id: other,
+                        depth: next.depth + 1,
+                        parent: Some(book.id),
+                    });
                 }
+                candidate_edges.push(edge);
             }
         }
 
-        frontier = next_frontier;
+        let is_read = matches!(book.read_status.as_deref(), Some("finished" | "abandoned"));
+        if exclude_read && is_read && book.id != center_id {
+            hidden.insert(book.id);
+        } else {
+            ranked.push(book);
+        }
     }
 
-    // Deduplicate edges
+    // Keep the center plus the requested page of ranked neighbors
+    let mut selected: Vec<Book> = ranked.drain(..ranked.len().min(1)).collect();
+    selected.extend(ranked.into_iter().skip(offset));
+    let ids: HashSet<i64> = selected.iter().map(|b| b.id).collect();
+
+    let nodes = selected
+        .into_iter()
+        .map(|book| GraphNode {
+            id: book.id,
+            title: book.title,
+            author: book.author,
+            cover_path: book.cover_path,
+            rating: book.rating,
+        })
+        .collect();
+
+    // Deduplicate edges, keeping the strongest per direction
+    let mut edges: Vec<GraphEdge> = candidate_edges
+        .into_iter()
+        .filter(|e| ids.contains(&e.source) && ids.contains(&e.target))
+        .collect();
     edges.sort_by(|a, b| {
-        (a.source, a.target).cmp(&(b.source, b.target))
+        (a.source, a.target)
+            .cmp(&(b.source, b.target))
+            .then_with(|| b.weight.total_cmp(&a.weight))
+            .then_with(|| a.edge_type.cmp(&b.edge_type))
     });
     edges.dedup_by(|a, b| a.source == b.source && a.target == b.target);
 
-    Ok(GraphData { nodes, edges })
+    // Reconnect books that were reached through hidden ones
+    for &id in &ids {
+        let Some(&(mut parent, mut weight)) = reached_from.get(&id) else {
+            continue;
+        };
+        let mut bridged = false;
+        while let Some(ancestor) = parent.filter(|p| hidden.contains(p)) {
+            bridged = true;
+            let (next_parent, ancestor_weight) = reached_from[&ancestor];
+            weight = weight.min(ancestor_weight);
+            parent = next_parent;
+        }
+        if let Some(ancestor) = parent.filter(|p| bridged && ids.contains(p)) {
+            edges.push(GraphEdge { source: ancestor, target: id, weight, edge_type: "bridged".to_string() });
+        }
+    }
+    edges.sort_by_key(|e| (e.source, e.target));
+
+    Ok(GraphData { nodes, edges, has_more })
+}
+
+/// Edges from a book for graph visualization
+///
+/// Uses stored edges; `use_fallback` computes similarity, author and series
+/// edges on the fly for a center book that has no stored edges yet.
+fn graph_neighbors(
+    db: &Database,
+    vector_store: &VectorStore,
+    book: &Book,
+    use_fallback: bool,
+) -> AppResult<Vec<GraphEdge>> {
+    // Try to get stored edges first (use 0.3 threshold like recommendations)
+    let stored = db.get_edges(book.id, 0.3, None)?;
+
+    tracing::debug!("get_book_graph: book_id={}, found {} stored edges", book.id, stored.len());
+
+    if !stored.is_empty() || !use_fallback {
+        return Ok(stored
+            .into_iter()
+            .map(|edge| GraphEdge {
+                source: edge.source_id,
+                target: edge.target_id,
+                weight: edge.weight,
+                edge_type: edge.edge_type,
+            })
+            .collect());
+    }
+
+    // No stored edges anywhere - fallback to vector similarity search
+    let mut edges = Vec::new();
+    let similar = vector_store.find_similar_to_book(book.id, 20);
+    let mut tag_ids: Vec<i64> = similar.iter().map(|&(id, _)| id).collect();
+    tag_ids.push(book.id);
+    let tags = db.get_tag_sets(Some(&tag_ids))?;
+    for (target_id, similarity) in similar {
+        if similarity < 0.3 {
+            continue;
+        }
+
+        if let Ok(target_book) = db.get_book(target_id) {
+            let (weight, edge_type) = crate::graph::compute_edge_weight(book, &target_book, Some(similarity), &tags);
+
+            if weight >= 0.3 {
+                edges.push(GraphEdge { source: book.id, target: target_id, weight, edge_type });
+            }
+        }
+    }
+
+    // Also add same author/series as fallback
+    if let Some(ref author) = book.author {
+        let query = crate::db::BookQuery {
+            author: Some(author.clone()),
+            limit: Some(10),
+            ..Default::default()
+        };
+        for other in db.query_books(&query)?.items {
+            if other.id != book.id {
+                edges.push(GraphEdge {
+                    source: book.id,
+                    target: other.id,
+                    weight: 0.7,
+                    edge_type: "author".to_string(),
+                });
+            }
+        }
+    }
+
+    if let Some(ref series) = book.series {
+        let query = crate::db::BookQuery {
+            series: Some(series.clone()),
+            limit: Some(10),
+            ..Default::default()
+        };
+        for other in db.query_books(&query)?.items {
+            if other.id != book.id {
+                edges.push(GraphEdge {
+                    source: book.id,
+                    target: other.id,
+                    weight: 0.9,
+                    edge_type: "series".to_string(),
+                });
+            }
+        }
+    }
+
+    Ok(edges)
 }
 
 /// Simple recommendations based on author/series matching
@@ -340,7 +979,7 @@ fn get_simple_recommendations(
     state: &State<'_, Arc<AppState>>,
     source: &Book,
     limit: i64,
-) -> Result<Vec<Recommendation>, String> {
+) -> Result<Vec<Recommendation>, CommandError> {
     let mut recommendations = Vec::new();
     
     // Find books by same author
@@ -382,11 +1021,7 @@ fn get_simple_recommendations(
                         continue;
                     }
                     
-                    let position = if book.series_index > source.series_index {
-                        "later".to_string()
-                    } else {
-                        "earlier".to_string()
-                    };
+                    let position = series_position(source.series_index, book.series_index);
                     
                     recommendations.push(Recommendation {
                         score: 0.9,
@@ -401,14 +1036,31 @@ fn get_simple_recommendations(
         }
     }
     
-    recommendations.sort_by(|a, b| b.score.partial_cmp(&a.score).unwrap_or(std::cmp::Ordering::Equal));
+    recommendations.sort_by(|a, b| {
+        b.score
+            .partial_cmp(&a.score)
+            .unwrap_or(std::cmp::Ordering::Equal)
+            .then(a.book.id.cmp(&b.book.id))
+    });
     recommendations.truncate(limit as usize);
     
     Ok(recommendations)
 }
 
+/// Where a target book sits in a series relative to the source book
+///
+/// Returns `"next"` or `"previous"` when both indices are known and differ,
+/// and `"in series"` when either index is missing or they are equal.
+fn series_position(source_index: Option<f64>, target_index: Option<f64>) -> String {
+    match (source_index, target_index) {
+        (Some(src), Some(tgt)) if tgt > src => "next".to_string(),
+        (Some(src), Some(tgt)) if tgt < src => "previous".to_string(),
+        _ => "in series".to_string(),
+    }
+}
+
 /// Build recommendation reasons from edge data
-fn build_reasons(source: &Book, target: &Book, edge_type: &str, weight: f64) -> Vec<RecommendationReason> {
+fn build_reasons(db: &Database, source: &Book, target: &Book, edge_type: &str, weight: f64) -> Vec<RecommendationReason> {
     let mut reasons = Vec::new();
     
     match edge_type {
@@ -426,11 +1078,7 @@ fn build_reasons(source: &Book, target: &Book, edge_type: &str, weight: f64) ->
         }
         "series" => {
             if let Some(ref series) = target.series {
-                let position = match (source.series_index, target.series_index) {
-                    (Some(src), Some(tgt)) if tgt > src => "next".to_string(),
-                    (Some(src), Some(tgt)) if tgt < src => "previous".to_string(),
-                    _ => "in series".to_string(),
-                };
+                let position = series_position(source.series_index, target.series_index);
                 reasons.push(RecommendationReason::SameSeries {
                     series: series.clone(),
                     position,
@@ -439,9 +1087,12 @@ fn build_reasons(source: &Book, target: &Book, edge_type: &str, weight: f64) ->
         }
         "tag" => {
             reasons.push(RecommendationReason::TagOverlap {
-                tags: vec![], // TODO: include actual overlapping tags
+                tags: db.get_shared_tags(source.id, target.id).unwrap_or_default(),
             });
         }
+        crate::db::MANUAL_EDGE_TYPE => {
+            reasons.push(RecommendationReason::ManuallyLinked);
+        }
         _ => {
             reasons.push(RecommendationReason::SimilarContent {
                 similarity: weight,
@@ -451,3 +1102,175 @@ fn build_reasons(source: &Book, target: &Book, edge_type: &str, weight: f64) ->
     
     reasons
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn graph_fixture() -> (Database, VectorStore) {
+        let db = Database::new_in_memory().unwrap();
+        db.with_conn(|conn| {
+            for id in 1..=6 {
+                conn.execute(
+                    "INSERT INTO books (id, path, title) VALUES (?, ?, ?)",
+                    rusqlite::params![id, format!("/b/{}.epub", id), format!("Book {}", id)],
+                )?;
+            }
+            Ok(())
+        })
+        .unwrap();
+        let edge = |s: i64, t: i64, w: f64| (s, t, "content".to_string(), w);
+        db.insert_edges_batch(&[
+            edge(1, 2, 0.9),
+            edge(1, 3, 0.5),
+            edge(1, 4, 0.7),
+            edge(2, 5, 0.8),
+            edge(3, 6, 0.95),
+        ])
+        .unwrap();
+        let vector_store = VectorStore::new(db.path()).unwrap();
+        (db, vector_store)
+    }
+
+    #[test]
+    fn test_tied_edges_sort_by_book_id() {
+        let (db, _) = graph_fixture();
+        db.insert_edges_batch(&[
+            (1, 6, "series".to_string(), 0.8),
+            (5, 1, "series".to_string(), 0.8),
+            (1, 3, "author".to_string(), 0.8),
+        ])
+        .unwrap();
+        let source = db.get_book(1).unwrap();
+
+        // Three 0.8 edges compete for the last two places
+        let recs = edge_recommendations(&db, &source, 3, None, None).unwrap();
+        let ids: Vec<i64> = recs.iter().map(|r| r.book.id).collect();
+        assert_eq!(ids, vec![2, 3, 5]);
+    }
+
+    #[test]
+    fn test_edge_type_filter() {
+        let (db, _) = graph_fixture();
+        db.insert_edges_batch(&[
+            (1, 5, "series".to_string(), 0.95),
+            (6, 1, "series".to_string(), 0.6),
+            (1, 6, "author".to_string(), 0.8),
+        ])
+        .unwrap();
+        let source = db.get_book(1).unwrap();
+
+        let series = edge_recommendations(&db, &source, 20, Some(&["series".to_string()]), None).unwrap();
+        let ids: Vec<i64> = series.iter().map(|r| r.book.id).collect();
+        assert_eq!(ids, vec![5, 6]);
+
+        let all = edge_recommendations(&db, &source, 20, None, None).unwrap();
+        assert_eq!(all.len(), 6);
+    }
+
+    #[test]
+    fn test_diversity_breaks_up_same_author_runs() {
+        use crate::vector::EMBEDDING_DIM;
+
+        let (db, vector_store) = graph_fixture();
+        db.with_conn(|conn| {
+            conn.execute_batch(
+                "UPDATE books SET author = 'Austen' WHERE id IN (2, 3, 4);
+                 UPDATE books SET author = 'Dickens', series = 'Boz' WHERE id = 5;",
+            )?;
+            Ok(())
+        })
+        .unwrap();
+        db.insert_edges_batch(&[
+            (1, 2, "author".to_string(), 0.95),
+            (1, 3, "author".to_string(), 0.94),
+            (1, 4, "author".to_string(), 0.93),
+            (1, 5, "series".to_string(), 0.8),
+        ])
+        .unwrap();
+
+        // The Austen books are near-duplicates in embedding space
+        let embedding = |x: f32, y: f32| {
+            let mut e = vec![0.0f32; EMBEDDING_DIM];
+            e[0] = x;
+            e[1] = y;
+            e
+        };
+        for (id, e) in [(2, embedding(1.0, 0.0)), (3, embedding(1.0, 0.05)), (4, embedding(1.0, 0.1)), (5, embedding(0.0, 1.0))] {
+            vector_store.store_embedding(id, &e, "test", None, 1).unwrap();
+        }
+
+        let source = db.get_book(1).unwrap();
+        let candidates = || edge_recommendations(&db, &source, 20, Some(&["author".to_string(), "series".to_string()]), None).unwrap();
+        let ids = |recs: Vec<Recommendation>| recs.iter().map(|r| r.book.id).collect::<Vec<_>>();
+
+        assert_eq!(ids(diversify(&vector_store, candidates(), 0.0, 4)), vec![2, 3, 4, 5]);
+
+        let diverse = ids(diversify(&vector_store, candidates(), 0.7, 4));
+        assert_eq!(diverse[..2], [2, 5]);
+        assert_eq!(diverse.len(), 4);
+    }
+
+    #[test]
+    fn test_series_position() {
+        assert_eq!(series_position(Some(1.0), Some(2.0)), "next");
+        assert_eq!(series_position(Some(3.0), Some(2.5)), "previous");
+        assert_eq!(series_position(Some(2.0), Some(2.0)), "in series");
+        assert_eq!(series_position(Some(2.0), None), "in series");
+        assert_eq!(series_position(None, Some(2.0)), "in series");
+        assert_eq!(series_position(None, None), "in series");
+    }
+
+    #[test]
+    fn test_book_graph_is_stable_and_weight_ordered() {
+        let (db, vector_store) = graph_fixture();
+
+        let first = build_book_graph(&db, &vector_store, 1, 2, 3, 0, false).unwrap();
+        let second = build_book_graph(&db, &vector_store, 1, 2, 3, 0, false).unwrap();
+        assert_eq!(
+            serde_json::to_string(&first).unwrap(),
+            serde_json::to_string(&second).unwrap()
+        );
+
+        // Strongest edges first: 1-2 (0.9), then 2-5 (0.8) beats 1-4 (0.7)
+        let ids: Vec<i64> = first.nodes.iter().map(|n| n.id).collect();
+        assert_eq!(ids, vec![1, 2, 5]);
+        assert!(first.has_more);
+        let edge_pairs: Vec<(i64, i64)> = first.edges.iter().map(|e| (e.source, e.target)).collect();
+        assert_eq!(edge_pairs, vec![(1, 2), (2, 5)]);
+
+        // Next page continues in weight order, always including the center
+        let page = build_book_graph(&db, &vector_store, 1, 2, 3, 2, false).unwrap();
+        let ids: Vec<i64> = page.nodes.iter().map(|n| n.id).collect();
+        assert_eq!(ids, vec![1, 4, 3]);
+        assert!(page.has_more);
+
+        let last = build_book_graph(&db, &vector_store, 1, 2, 3, 4, false).unwrap();
+        let ids: Vec<i64> = last.nodes.iter().map(|n| n.id).collect();
+        assert_eq!(ids, vec![1, 6]);
+        assert!(!last.has_more);
+    }
+
+    #[test]
+    fn test_book_graph_excludes_read_books() {
+        let (db, vector_store) = graph_fixture();
+        db.set_read_status(2, "finished").unwrap();
+        db.set_read_status(3, "abandoned").unwrap();
+        db.set_read_status(1, "finished").unwrap();
+
+        let graph = build_book_graph(&db, &vector_store, 1, 2, 10, 0, true).unwrap();
+        let ids: Vec<i64> = graph.nodes.iter().map(|n| n.id).collect();
+        assert_eq!(ids, vec![1, 5, 4, 6]);
+
+        // 5 and 6 hang off hidden books, so they connect to the center directly
+        let edges: Vec<(i64, i64, &str)> =
+            graph.edges.iter().map(|e| (e.source, e.target, e.edge_type.as_str())).collect();
+        assert_eq!(edges, vec![(1, 4, "content"), (1, 5, "bridged"), (1, 6, "bridged")]);
+        assert_eq!(graph.edges[1].weight, 0.8);
+        assert_eq!(graph.edges[2].weight, 0.5);
+
+        // Off by default
+        let all = build_book_graph(&db, &vector_store, 1, 2, 10, 0, false).unwrap();
+        assert_eq!(all.nodes.len(), 6);
+    }
+}
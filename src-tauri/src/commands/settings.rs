@@ -1,7 +1,12 @@
 //! Settings commands
 
-use crate::db::Settings;
+use crate::db::{PoolStats, Settings, SCHEMA_VERSION};
+use crate::graph::{rebuild_graph, EdgeBuildConfig, EdgeDistribution, MAX_SIMILAR_CACHE_SIZE};
+use crate::ollama::{EmbeddingTextConfig, EMBEDDING_TEXT_VERSION};
 use crate::state::AppState;
+use crate::vector::{EmbeddingRepairReport, SimilarityMetric, EMBEDDING_DIM};
+use crate::AppError;
+use super::CommandError;
 use std::sync::Arc;
 use tauri::State;
 
@@ -13,21 +18,66 @@ pub struct DatabaseStats {
     pub books_count: i64,
     pub embeddings_count: i64,
     pub embeddings_size_bytes: u64,
+    pub embedding_text_version: i64,
+    pub stale_embeddings_count: i64,
+    /// Stored embeddings per model, largest first
+    pub embeddings_by_model: Vec<ModelEmbeddingCount>,
+}
+
+/// Number of embeddings stored for one model
+#[derive(Debug, Clone, serde::Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ModelEmbeddingCount {
+    pub model: String,
+    pub count: i64,
+}
+
+/// App, schema and embedding versions, for support and compatibility checks
+#[derive(Debug, Clone, serde::Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct VersionInfo {
+    pub app_version: String,
+    /// Schema version recorded in the open database
+    pub schema_version: i32,
+    /// Schema version this build migrates to
+    pub latest_schema_version: i32,
+    pub migration_pending: bool,
+    pub embedding_text_version: i64,
+    pub embedding_model: String,
+    pub embedding_dimension: usize,
+}
+
+/// Get version information
+#[tauri::command]
+pub async fn get_version_info(
+    state: State<'_, Arc<AppState>>,
+) -> Result<VersionInfo, CommandError> {
+    let schema_version = state.db.schema_version()?;
+
+    Ok(VersionInfo {
+        app_version: env!("CARGO_PKG_VERSION").to_string(),
+        schema_version,
+        latest_schema_version: SCHEMA_VERSION,
+        migration_pending: schema_version < SCHEMA_VERSION,
+        embedding_text_version: EMBEDDING_TEXT_VERSION,
+        embedding_model: state.ollama.read().model().to_string(),
+        embedding_dimension: EMBEDDING_DIM,
+    })
 }
 
 /// Get all settings
 #[tauri::command]
 pub async fn get_settings(
     state: State<'_, Arc<AppState>>,
-) -> Result<Settings, String> {
-    state.db.get_settings().map_err(|e| e.to_string())
+) -> Result<Settings, CommandError> {
+    state.db.get_settings().map_err(CommandError::from)
 }
 
 /// Get the current database path
 #[tauri::command]
 pub async fn get_database_path(
     state: State<'_, Arc<AppState>>,
-) -> Result<String, String> {
+) -> Result<String, CommandError> {
     Ok(state.db.path().to_string())
 }
 
@@ -35,7 +85,7 @@ pub async fn get_database_path(
 #[tauri::command]
 pub async fn get_database_stats(
     state: State<'_, Arc<AppState>>,
-) -> Result<DatabaseStats, String> {
+) -> Result<DatabaseStats, CommandError> {
     // Get database file size
     let db_path = state.db.path();
     let database_size_bytes = std::fs::metadata(db_path)
@@ -52,21 +102,44 @@ pub async fn get_database_stats(
     let embeddings_count = state.vector_store.count().unwrap_or(0);
     // Each embedding is 768 floats * 4 bytes = 3072 bytes
     let embeddings_size_bytes = (embeddings_count as u64) * 768 * 4;
+    let stale_embeddings_count = state
+        .vector_store
+        .stale_embedding_ids(EMBEDDING_TEXT_VERSION)
+        .map(|ids| ids.len() as i64)
+        .unwrap_or(0);
+    let embeddings_by_model = state
+        .vector_store
+        .count_by_model()
+        .unwrap_or_default()
+        .into_iter()
+        .map(|(model, count)| ModelEmbeddingCount { model, count })
+        .collect();
 
     Ok(DatabaseStats {
         database_size_bytes,
         books_count,
         embeddings_count,
         embeddings_size_bytes,
+        embedding_text_version: EMBEDDING_TEXT_VERSION,
+        stale_embeddings_count,
+        embeddings_by_model,
     })
 }
 
+/// Get database connection pool usage
+#[tauri::command]
+pub async fn get_pool_stats(
+    state: State<'_, Arc<AppState>>,
+) -> Result<PoolStats, CommandError> {
+    Ok(state.db.pool_stats())
+}
+
 /// Reset/clear the database (deletes all books, libraries, and settings)
 #[tauri::command]
 pub async fn reset_database(
     state: State<'_, Arc<AppState>>,
-) -> Result<(), String> {
-    state.db.reset().map_err(|e| e.to_string())
+) -> Result<(), CommandError> {
+    state.db.reset().map_err(CommandError::from)
 }
 
 /// Clear embeddings result
@@ -82,12 +155,12 @@ pub struct ClearEmbeddingsResult {
 #[tauri::command]
 pub async fn clear_embeddings(
     state: State<'_, Arc<AppState>>,
-) -> Result<ClearEmbeddingsResult, String> {
+) -> Result<ClearEmbeddingsResult, CommandError> {
     // Clear embeddings from vector store
-    let embeddings_cleared = state.vector_store.clear_all().map_err(|e| e.to_string())?;
+    let embeddings_cleared = state.vector_store.clear_all()?;
 
     // Reset all book embedding statuses to pending
-    let books_reset = state.db.reset_all_embedding_statuses().map_err(|e| e.to_string())?;
+    let books_reset = state.db.reset_all_embedding_statuses()?;
 
     tracing::info!(
         "Cleared {} embeddings and reset {} book statuses",
@@ -101,59 +174,122 @@ pub async fn clear_embeddings(
     })
 }
 
+/// Clear embeddings and reset statuses for one library's books only
+#[tauri::command]
+pub async fn clear_embeddings_for_library(
+    state: State<'_, Arc<AppState>>,
+    library_id: i64,
+) -> Result<ClearEmbeddingsResult, CommandError> {
+    let library = state
+        .db
+        .get_libraries()?
+        .into_iter()
+        .find(|l| l.id == library_id)
+        .ok_or_else(|| AppError::NotFound(format!("Library {}", library_id)))?;
+
+    let book_ids = state.db.get_library_book_ids(&library.path)?;
+    let embeddings_cleared = state.vector_store.delete_embeddings(&book_ids)?;
+    let books_reset = state.db.reset_embedding_statuses(&book_ids)?;
+
+    tracing::info!(
+        "Cleared {} embeddings and reset {} book statuses in library {}",
+        embeddings_cleared,
+        books_reset,
+        library.name
+    );
+
+    Ok(ClearEmbeddingsResult {
+        embeddings_cleared,
+        books_reset,
+    })
+}
+
+/// Requeue books whose stored embedding is corrupt, optionally deleting the bad rows
+#[tauri::command]
+pub async fn repair_embeddings(
+    state: State<'_, Arc<AppState>>,
+    delete_corrupt: Option<bool>,
+) -> Result<EmbeddingRepairReport, CommandError> {
+    state
+        .vector_store
+        .repair_embeddings(delete_corrupt.unwrap_or(true))
+        .map_err(CommandError::from)
+}
+
+/// Fill in missing sort title and author sort fields for all books
+#[tauri::command]
+pub async fn regenerate_sort_fields(
+    state: State<'_, Arc<AppState>>,
+) -> Result<usize, CommandError> {
+    state.db.regenerate_sort_fields().map_err(CommandError::from)
+}
+
 /// Get the configured database path from preferences (may differ from current)
 #[tauri::command]
-pub async fn get_database_path_preference() -> Result<Option<String>, String> {
-    // Read from a simple config file
-    let config_path = get_config_path()?;
-    if config_path.exists() {
-        let content = std::fs::read_to_string(&config_path).map_err(|e| e.to_string())?;
-        let config: serde_json::Value = serde_json::from_str(&content).unwrap_or_default();
-        Ok(config.get("database_path").and_then(|v| v.as_str()).map(String::from))
-    } else {
-        Ok(None)
-    }
+pub async fn get_database_path_preference() -> Result<Option<String>, CommandError> {
+    Ok(crate::state::database_path_preference().map(|p| p.to_string_lossy().to_string()))
 }
 
 /// Set the database path preference (requires app restart to take effect)
+///
+/// Only takes effect once a database exists at the path; use
+/// `migrate_database_to` to move the current data there.
 #[tauri::command]
-pub async fn set_database_path_preference(path: String) -> Result<(), String> {
-    // Validate path
-    let path_buf = std::path::PathBuf::from(&path);
-
-    // If it's a directory, append the database filename
-    let db_path = if path_buf.is_dir() {
-        path_buf.join("library.db")
-    } else {
-        path_buf.clone()
-    };
+pub async fn set_database_path_preference(path: String) -> Result<(), CommandError> {
+    let db_path = resolve_database_path(&path);
 
     // Ensure parent directory exists
     if let Some(parent) = db_path.parent() {
         std::fs::create_dir_all(parent).map_err(|e| format!("Failed to create directory: {}", e))?;
     }
 
-    // Save to config file
-    let config_path = get_config_path()?;
-    let config = serde_json::json!({
-        "database_path": db_path.to_string_lossy()
-    });
-
-    if let Some(parent) = config_path.parent() {
-        std::fs::create_dir_all(parent).map_err(|e| e.to_string())?;
-    }
+    Ok(crate::state::save_database_path_preference(&db_path)?)
+}
 
-    std::fs::write(&config_path, serde_json::to_string_pretty(&config).unwrap())
-        .map_err(|e| format!("Failed to save config: {}", e))?;
+/// Result of moving the database
+#[derive(Debug, Clone, serde::Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DatabaseMigrationResult {
+    pub path: String,
+    pub bytes_copied: u64,
+    /// The app keeps using the old file until it restarts
+    pub restart_required: bool,
+}
 
-    Ok(())
+/// Copy the database to a new location and make it the preferred one
+///
+/// The copy is consistent and written atomically (see `Database::copy_to`);
+/// the old file is left in place as a backup. The open pool keeps using the
+/// old file, so the new location is picked up on restart.
+#[tauri::command]
+pub async fn migrate_database_to(
+    state: State<'_, Arc<AppState>>,
+    new_path: String,
+) -> Result<DatabaseMigrationResult, CommandError> {
+    let db_path = resolve_database_path(&new_path);
+    let state = state.inner().clone();
+    let target = db_path.clone();
+    let bytes_copied = tokio::task::spawn_blocking(move || state.db.copy_to(&target))
+        .await??;
+
+    crate::state::save_database_path_preference(&db_path)?;
+    tracing::info!("Database copied to {:?} ({} bytes), active after restart", db_path, bytes_copied);
+
+    Ok(DatabaseMigrationResult {
+        path: db_path.to_string_lossy().to_string(),
+        bytes_copied,
+        restart_required: true,
+    })
 }
 
-/// Get the path to the config file
-fn get_config_path() -> Result<std::path::PathBuf, String> {
-    let config_dir = dirs::config_dir()
-        .ok_or_else(|| "Could not determine config directory".to_string())?;
-    Ok(config_dir.join("epub-graph").join("config.json"))
+/// Database file for a user-chosen path (directories get `library.db`)
+fn resolve_database_path(path: &str) -> std::path::PathBuf {
+    let path_buf = std::path::PathBuf::from(path);
+    if path_buf.is_dir() {
+        path_buf.join("library.db")
+    } else {
+        path_buf
+    }
 }
 
 /// Update settings
@@ -161,35 +297,153 @@ fn get_config_path() -> Result<std::path::PathBuf, String> {
 pub async fn update_settings(
     state: State<'_, Arc<AppState>>,
     settings: PartialSettings,
-) -> Result<(), String> {
+) -> Result<(), CommandError> {
     if let Some(ref endpoint) = settings.ollama_endpoint {
-        state.db.update_setting("ollama_endpoint", endpoint).map_err(|e| e.to_string())?;
+        state.db.update_setting("ollama_endpoint", endpoint)?;
         let mut ollama = state.ollama.write();
         let current_model = ollama.model().to_string();
         ollama.configure(endpoint.clone(), current_model);
     }
 
     if let Some(ref model) = settings.ollama_model {
-        state.db.update_setting("ollama_model", model).map_err(|e| e.to_string())?;
+        state.db.update_setting("ollama_model", model)?;
         let mut ollama = state.ollama.write();
         let current_endpoint = ollama.endpoint().to_string();
         ollama.configure(current_endpoint, model.clone());
+        state.vector_store.set_active_model(model);
     }
     
     if let Some(batch_size) = settings.embedding_batch_size {
-        state.db.update_setting("embedding_batch_size", &batch_size.to_string()).map_err(|e| e.to_string())?;
+        state.db.update_setting("embedding_batch_size", &batch_size.to_string())?;
     }
     
     if let Some(max_recs) = settings.max_recommendations {
-        state.db.update_setting("max_recommendations", &max_recs.to_string()).map_err(|e| e.to_string())?;
+        state.db.update_setting("max_recommendations", &max_recs.to_string())?;
     }
     
     if let Some(auto_scan) = settings.auto_scan_enabled {
-        state.db.update_setting("auto_scan_enabled", if auto_scan { "1" } else { "0" }).map_err(|e| e.to_string())?;
+        state.db.update_setting("auto_scan_enabled", if auto_scan { "1" } else { "0" })?;
     }
     
     if let Some(interval) = settings.scan_interval_minutes {
-        state.db.update_setting("scan_interval_minutes", &interval.to_string()).map_err(|e| e.to_string())?;
+        state.db.update_setting("scan_interval_minutes", &interval.to_string())?;
+    }
+
+    if let Some(scan_archives) = settings.scan_archives {
+        state.db.update_setting("scan_archives", if scan_archives { "1" } else { "0" })?;
+    }
+
+    if let Some(pool_size) = settings.db_pool_size {
+        if pool_size == 0 {
+            return Err(AppError::InvalidInput("Pool size must be at least 1".to_string()).into());
+        }
+        state.db.update_setting("db_pool_size", &pool_size.to_string())?;
+    }
+
+    if let Some(rate) = settings.ollama_requests_per_second {
+        if !rate.is_finite() || rate < 0.0 {
+            return Err(AppError::InvalidInput("Requests per second must be zero (unlimited) or positive".to_string()).into());
+        }
+        state.db.update_setting("ollama_requests_per_second", &rate.to_string())?;
+        state.rate_limiter.set_rate(rate);
+    }
+
+    if let Some(fields) = settings.embedding_fields {
+        if fields.fields.is_empty() {
+            return Err(AppError::InvalidInput("At least one embedding field is required".to_string()).into());
+        }
+        let current = state.db.get_settings()?.embedding_fields;
+        if fields != current {
+            let json = serde_json::to_string(&fields)?;
+            state.db.update_setting("embedding_fields", &json)?;
+
+            // Embeddings built from the old field set no longer match
+            let stale = state.vector_store.mark_all_stale()?;
+            state.db.reset_all_embedding_statuses()?;
+            tracing::info!("Embedding fields changed, {} embeddings marked stale", stale);
+        }
+    }
+
+    if let Some(days) = settings.recent_reads_days {
+        state.db.update_setting("recent_reads_days", &days.to_string())?;
+    }
+
+    if let Some(cover_dir) = settings.cover_dir {
+        state.db.update_setting("cover_dir", cover_dir.trim())?;
+    }
+
+    // Floors take effect the next time edges are built
+    for (key, floor) in [
+        ("edge_candidate_floor", settings.edge_candidate_floor),
+        ("content_edge_floor", settings.content_edge_floor),
+    ] {
+        if let Some(floor) = floor {
+            if !(0.0..=1.0).contains(&floor) {
+                return Err(AppError::InvalidInput("Similarity floors must be between 0 and 1".to_string()).into());
+            }
+            state.db.update_setting(key, &floor.to_string())?;
+        }
+    }
+
+    if let Some(enabled) = settings.enrichment_enabled {
+        state.db.update_setting("enrichment_enabled", if enabled { "1" } else { "0" })?;
+    }
+
+    if let Some(endpoint) = settings.enrichment_endpoint {
+        state.db.update_setting("enrichment_endpoint", endpoint.trim())?;
+    }
+
+    if let Some(threshold) = settings.liked_rating_threshold {
+        if !crate::db::is_valid_rating(threshold) {
+            return Err(AppError::InvalidInput(
+                "Liked rating threshold must be between 0.5 and 5 in steps of 0.5".to_string(),
+            )
+            .into());
+        }
+        state.db.update_setting("liked_rating_threshold", &threshold.to_string())?;
+    }
+
+    if let Some(enabled) = settings.edge_prune_enabled {
+        state.db.update_setting("edge_prune_enabled", if enabled { "1" } else { "0" })?;
+    }
+
+    if let Some(max_per_book) = settings.edge_prune_max_per_book {
+        if max_per_book == 0 {
+            return Err(AppError::InvalidInput("Books must keep at least one edge when pruning".to_string()).into());
+        }
+        state.db.update_setting("edge_prune_max_per_book", &max_per_book.to_string())?;
+    }
+
+    if let Some(min_weight) = settings.edge_prune_min_weight {
+        if !(0.0..=1.0).contains(&min_weight) {
+            return Err(AppError::InvalidInput("Prune weight floor must be between 0 and 1".to_string()).into());
+        }
+        state.db.update_setting("edge_prune_min_weight", &min_weight.to_string())?;
+    }
+
+    if let Some(timeout_ms) = settings.recommendation_timeout_ms {
+        state.db.update_setting("recommendation_timeout_ms", &timeout_ms.to_string())?;
+    }
+
+    if let Some(size) = settings.similar_cache_size {
+        if size > MAX_SIMILAR_CACHE_SIZE {
+            return Err(AppError::InvalidInput(format!(
+                "At most {} similar books can be precomputed per book",
+                MAX_SIMILAR_CACHE_SIZE
+            ))
+            .into());
+        }
+        state.db.update_setting("similar_cache_size", &size.to_string())?;
+    }
+
+    if let Some(metric) = settings.similarity_metric {
+        state.db.update_setting("similarity_metric", metric.as_str())?;
+        state.vector_store.set_metric(metric);
+    }
+
+    if let Some(threshold) = settings.ann_threshold {
+        state.db.update_setting("ann_threshold", &threshold.to_string())?;
+        state.vector_store.set_ann_threshold(threshold);
     }
     
     Ok(())
@@ -205,6 +459,24 @@ pub struct PartialSettings {
     pub max_recommendations: Option<i32>,
     pub auto_scan_enabled: Option<bool>,
     pub scan_interval_minutes: Option<i32>,
+    pub similarity_metric: Option<SimilarityMetric>,
+    pub scan_archives: Option<bool>,
+    pub db_pool_size: Option<u32>,
+    pub ollama_requests_per_second: Option<f64>,
+    pub embedding_fields: Option<EmbeddingTextConfig>,
+    pub recent_reads_days: Option<u32>,
+    pub cover_dir: Option<String>,
+    pub edge_candidate_floor: Option<f64>,
+    pub content_edge_floor: Option<f64>,
+    pub enrichment_enabled: Option<bool>,
+    pub enrichment_endpoint: Option<String>,
+    pub liked_rating_threshold: Option<f64>,
+    pub edge_prune_enabled: Option<bool>,
+    pub edge_prune_max_per_book: Option<u32>,
+    pub edge_prune_min_weight: Option<f64>,
+    pub recommendation_timeout_ms: Option<u64>,
+    pub similar_cache_size: Option<u32>,
+    pub ann_threshold: Option<usize>,
 }
 
 /// Result of rebuilding graph edges
@@ -212,8 +484,13 @@ pub struct PartialSettings {
 #[serde(rename_all = "camelCase")]
 pub struct RebuildGraphResult {
     pub books_processed: i64,
+    /// Edges written (or that would be written, for a dry run)
     pub edges_created: i64,
     pub duration_ms: u64,
+    pub dry_run: bool,
+    pub distribution: EdgeDistribution,
+    /// Edges removed by the post-rebuild prunes
+    pub edges_pruned: i64,
 }
 
 /// Progress event for graph rebuild
@@ -226,124 +503,126 @@ pub struct GraphRebuildProgress {
 }
 
 /// Rebuild graph edges from existing embeddings
-/// This computes similarity between all books with embeddings and creates edges
+/// This computes similarity between all books with embeddings and creates edges.
+/// With `dry_run` the edges are only counted; otherwise the new graph replaces
+/// the old one atomically once complete, and is then pruned to each book's
+/// strongest edges if `edge_prune_enabled` is set. Each book keeps at most
+/// `max_degree` outgoing edges, and afterwards over-connected books are cut
+/// back to that many incoming edges too.
 #[tauri::command]
 pub async fn rebuild_graph_edges(
     state: State<'_, Arc<AppState>>,
     app: tauri::AppHandle,
-) -> Result<RebuildGraphResult, String> {
+    dry_run: Option<bool>,
+    max_degree: Option<usize>,
+) -> Result<RebuildGraphResult, CommandError> {
     use std::time::Instant;
     use tauri::Emitter;
 
     let start = Instant::now();
+    let dry_run = dry_run.unwrap_or(false);
 
-    tracing::info!("Starting graph edge rebuild...");
-
-    // Clear existing edges
-    state.db.with_conn(|conn| {
-        conn.execute("DELETE FROM book_edges", [])
-            .map_err(crate::AppError::Database)
-    }).map_err(|e| e.to_string())?;
-
-    // Get all book IDs with embeddings
-    let book_ids: Vec<i64> = state.db.with_conn(|conn| {
-        let mut stmt = conn.prepare(
-            "SELECT DISTINCT b.id FROM books b
-             INNER JOIN embeddings e ON b.id = e.book_id
-             WHERE b.embedding_status = 'complete'"
-        ).map_err(crate::AppError::Database)?;
-
-        let ids = stmt.query_map([], |row| row.get(0))
-            .map_err(crate::AppError::Database)?
-            .collect::<Result<Vec<i64>, _>>()
-            .map_err(crate::AppError::Database)?;
-
-        Ok(ids)
-    }).map_err(|e: crate::AppError| e.to_string())?;
-
-    let total_books = book_ids.len() as i64;
-    tracing::info!("Found {} books with embeddings", total_books);
+    tracing::info!("Starting graph edge rebuild{}...", if dry_run { " (dry run)" } else { "" });
 
     // Emit initial progress
     let _ = app.emit("graph-rebuild-progress", GraphRebuildProgress {
         current: 0,
-        total: total_books,
+        total: 0,
         edges_so_far: 0,
     });
 
-    let mut total_edges = 0i64;
-
-    // Process books in batches to avoid memory issues
-    for (idx, &book_id) in book_ids.iter().enumerate() {
-        // Find similar books
-        let similar = state.vector_store.find_similar_to_book(book_id, 30);
-
-        if similar.is_empty() {
-            continue;
-        }
-
-        // Get source book
-        let source_book = match state.db.get_book(book_id) {
-            Ok(b) => b,
-            Err(_) => continue,
-        };
-
-        let mut edges_to_insert = Vec::new();
-
-        for (target_id, similarity) in similar {
-            if similarity < 0.3 {
-                continue;
-            }
-
-            let target_book = match state.db.get_book(target_id) {
-                Ok(b) => b,
-                Err(_) => continue,
-            };
-
-            let (weight, edge_type) = crate::graph::compute_edge_weight(
-                &source_book,
-                &target_book,
-                Some(similarity),
-            );
-
-            if weight >= 0.3 {
-                edges_to_insert.push((book_id, target_id, edge_type, weight));
-            }
-        }
-
-        if !edges_to_insert.is_empty() {
-            if let Err(e) = state.db.insert_edges_batch(&edges_to_insert) {
-                tracing::warn!("Failed to insert edges for book {}: {}", book_id, e);
-            } else {
-                total_edges += edges_to_insert.len() as i64;
-            }
-        }
+    let mut config = EdgeBuildConfig::load(&state.db);
+    if let Some(max_degree) = max_degree {
+        config.max_degree = max_degree;
+    }
 
+    let summary = rebuild_graph(&state.db, &state.vector_store, &config, dry_run, |current, total, edges| {
         // Emit progress every 100 books
-        if (idx + 1) % 100 == 0 || idx + 1 == book_ids.len() {
+        if current % 100 == 0 || current == total {
             let _ = app.emit("graph-rebuild-progress", GraphRebuildProgress {
-                current: (idx + 1) as i64,
-                total: total_books,
-                edges_so_far: total_edges,
+                current: current as i64,
+                total: total as i64,
+                edges_so_far: edges as i64,
             });
         }
 
         // Log progress every 1000 books
-        if (idx + 1) % 1000 == 0 {
-            tracing::info!("Processed {}/{} books, {} edges so far", idx + 1, book_ids.len(), total_edges);
+        if current % 1000 == 0 {
+            tracing::info!("Processed {}/{} books, {} edges so far", current, total, edges);
         }
+    })?;
+
+    let settings = state.db.get_settings()?;
+    let mut edges_pruned = if !dry_run && settings.edge_prune_enabled {
+        let pruned = state
+            .db
+            .prune_edges(settings.edge_prune_max_per_book as usize, settings.edge_prune_min_weight)?;
+        tracing::info!("Pruned {} weak edges", pruned);
+        pruned as i64
+    } else {
+        0
+    };
+    if !dry_run && config.max_degree > 0 {
+        let pruned = state.db.prune_edges_over_degree(config.max_degree)?;
+        tracing::info!("Pruned {} edges from over-connected books", pruned);
+        edges_pruned += pruned as i64;
     }
 
     let duration_ms = start.elapsed().as_millis() as u64;
 
     tracing::info!(
         "Graph rebuild complete: {} books, {} edges in {}ms",
-        total_books, total_edges, duration_ms
+        summary.books_processed, summary.edges, duration_ms
     );
 
-    Ok(RebuildGraphResult {
-        books_processed: total_books,
-        edges_created: total_edges,
+    let result = RebuildGraphResult {
+        books_processed: summary.books_processed as i64,
+        edges_created: summary.edges as i64,
         duration_ms,
-    })
+        dry_run,
+        distribution: summary.distribution,
+        edges_pruned,
+    };
+
+    // Tell the frontend every cached recommendation may be out of date
+    if !dry_run {
+        let _ = app.emit("graph:updated", result.clone());
+    }
+
+    Ok(result)
+}
+
+/// Link two books by hand with a `manual` edge that rebuilds keep
+#[tauri::command]
+pub async fn add_manual_edge(
+    state: State<'_, Arc<AppState>>,
+    source_id: i64,
+    target_id: i64,
+    weight: f64,
+) -> Result<(), CommandError> {
+    state.db.add_manual_edge(source_id, target_id, weight).map_err(CommandError::from)
+}
+
+/// Remove a manual link between two books; returns whether one existed
+#[tauri::command]
+pub async fn remove_manual_edge(
+    state: State<'_, Arc<AppState>>,
+    source_id: i64,
+    target_id: i64,
+) -> Result<bool, CommandError> {
+    state.db.remove_manual_edge(source_id, target_id).map_err(CommandError::from)
+}
+
+/// Recompute graph edges for a single book (e.g. after a metadata edit)
+/// Returns the number of edges now stored for the book
+#[tauri::command]
+pub async fn recompute_book_edges(
+    state: State<'_, Arc<AppState>>,
+    book_id: i64,
+) -> Result<i64, CommandError> {
+    let edges = crate::graph::recompute_book_edges(&state.db, &state.vector_store, book_id)?;
+
+    tracing::info!("Recomputed {} edges for book {}", edges, book_id);
+
+    Ok(edges as i64)
 }
@@ -1,20 +1,22 @@
 //! Up Next queue commands
 
-use crate::db::Book;
+use crate::db::{Book, CurrentlyReading};
 use crate::state::AppState;
+use crate::AppError;
+use super::CommandError;
 use std::sync::Arc;
 use tauri::State;
 
 /// Get all books in the Up Next queue
 #[tauri::command]
-pub async fn get_up_next_books(state: State<'_, Arc<AppState>>) -> Result<Vec<Book>, String> {
-    state.db.get_up_next_books().map_err(|e| e.to_string())
+pub async fn get_up_next_books(state: State<'_, Arc<AppState>>) -> Result<Vec<Book>, CommandError> {
+    state.db.get_up_next_books().map_err(CommandError::from)
 }
 
 /// Add a book to the Up Next queue
 #[tauri::command]
-pub async fn add_to_up_next(book_id: i64, state: State<'_, Arc<AppState>>) -> Result<(), String> {
-    state.db.add_to_up_next(book_id).map_err(|e| e.to_string())
+pub async fn add_to_up_next(book_id: i64, state: State<'_, Arc<AppState>>) -> Result<(), CommandError> {
+    state.db.add_to_up_next(book_id).map_err(CommandError::from)
 }
 
 /// Remove a book from the Up Next queue
@@ -22,24 +24,72 @@ pub async fn add_to_up_next(book_id: i64, state: State<'_, Arc<AppState>>) -> Re
 pub async fn remove_from_up_next(
     book_id: i64,
     state: State<'_, Arc<AppState>>,
-) -> Result<(), String> {
-    state.db.remove_from_up_next(book_id).map_err(|e| e.to_string())
+) -> Result<(), CommandError> {
+    state.db.remove_from_up_next(book_id).map_err(CommandError::from)
+}
+
+/// Move a book to a position in the Up Next queue (0 = first)
+#[tauri::command]
+pub async fn move_up_next(
+    book_id: i64,
+    position: i64,
+    state: State<'_, Arc<AppState>>,
+) -> Result<(), CommandError> {
+    state.db.reorder_up_next(book_id, position).map_err(CommandError::from)
+}
+
+/// Empty the Up Next queue; returns how many books were removed
+#[tauri::command]
+pub async fn clear_up_next(state: State<'_, Arc<AppState>>) -> Result<usize, CommandError> {
+    state.db.clear_up_next().map_err(CommandError::from)
 }
 
 /// Check if a book is in the Up Next queue
 #[tauri::command]
-pub async fn is_in_up_next(book_id: i64, state: State<'_, Arc<AppState>>) -> Result<bool, String> {
-    state.db.is_in_up_next(book_id).map_err(|e| e.to_string())
+pub async fn is_in_up_next(book_id: i64, state: State<'_, Arc<AppState>>) -> Result<bool, CommandError> {
+    state.db.is_in_up_next(book_id).map_err(CommandError::from)
 }
 
 /// Get the count of books in the Up Next queue
 #[tauri::command]
-pub async fn get_up_next_count(state: State<'_, Arc<AppState>>) -> Result<i64, String> {
-    state.db.get_up_next_count().map_err(|e| e.to_string())
+pub async fn get_up_next_count(state: State<'_, Arc<AppState>>) -> Result<i64, CommandError> {
+    state.db.get_up_next_count().map_err(CommandError::from)
 }
 
 /// Get books with "want" read status (automatically included in Up Next view)
 #[tauri::command]
-pub async fn get_want_to_read_books(state: State<'_, Arc<AppState>>) -> Result<Vec<Book>, String> {
-    state.db.get_want_to_read_books().map_err(|e| e.to_string())
+pub async fn get_want_to_read_books(state: State<'_, Arc<AppState>>) -> Result<Vec<Book>, CommandError> {
+    state.db.get_want_to_read_books().map_err(CommandError::from)
+}
+
+/// Get books in progress for the "continue reading" shelf, most recently
+/// read first
+#[tauri::command]
+pub async fn get_currently_reading(
+    limit: Option<i64>,
+    state: State<'_, Arc<AppState>>,
+) -> Result<Vec<CurrentlyReading>, CommandError> {
+    let limit = limit.unwrap_or(20).min(100);
+    state.db.get_currently_reading(limit).map_err(CommandError::from)
+}
+
+/// Record how far into a book the user is (percent, 0-100) and, optionally,
+/// where (an EPUB CFI)
+///
+/// Returns the book's new read status if progress changed it.
+#[tauri::command]
+pub async fn set_progress(
+    book_id: i64,
+    percent: f64,
+    location: Option<String>,
+    state: State<'_, Arc<AppState>>,
+) -> Result<Option<String>, CommandError> {
+    if !percent.is_finite() {
+        return Err(AppError::InvalidInput("Progress must be a number between 0 and 100".to_string()).into());
+    }
+    let status = state.db.set_reading_progress(book_id, percent, location.as_deref())?;
+    if status == Some("finished") {
+        state.db.update_user_wpm()?;
+    }
+    Ok(status.map(String::from))
 }
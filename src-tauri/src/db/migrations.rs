@@ -1,10 +1,39 @@
 //! Database migrations
 
-use crate::AppResult;
+use super::normalize_path;
+use crate::{AppError, AppResult};
 use rusqlite::Connection;
 
 /// Current schema version
-const SCHEMA_VERSION: i32 = 2;
+pub const SCHEMA_VERSION: i32 = 19;
+
+/// Read the schema version recorded in a database (0 if never migrated)
+pub fn schema_version(conn: &Connection) -> AppResult<i32> {
+    let has_table: bool = conn.query_row(
+        "SELECT COUNT(*) > 0 FROM sqlite_master WHERE type = 'table' AND name = 'schema_version'",
+        [],
+        |row| row.get(0),
+    )?;
+    if !has_table {
+        return Ok(0);
+    }
+
+    Ok(conn.query_row("SELECT COALESCE(MAX(version), 0) FROM schema_version", [], |row| row.get(0))?)
+}
+
+/// Check that data written with schema `version` can be loaded
+///
+/// Older versions are migrated forward; newer ones come from a newer build
+/// of the app and are refused.
+pub fn check_schema_compatibility(version: i32) -> AppResult<()> {
+    if version > SCHEMA_VERSION {
+        return Err(AppError::InvalidInput(format!(
+            "Data uses schema version {}, but this version of the app supports up to {}; please update the app",
+            version, SCHEMA_VERSION
+        )));
+    }
+    Ok(())
+}
 
 /// Run all pending migrations
 pub fn run_migrations(conn: &Connection) -> AppResult<()> {
@@ -27,6 +56,13 @@ pub fn run_migrations(conn: &Connection) -> AppResult<()> {
         .unwrap_or(0);
     
     tracing::info!("Current schema version: {}, target: {}", current_version, SCHEMA_VERSION);
+    if current_version > SCHEMA_VERSION {
+        tracing::warn!(
+            "Database schema version {} is newer than this app supports ({})",
+            current_version,
+            SCHEMA_VERSION
+        );
+    }
     
     // Apply migrations
     if current_version < 1 {
@@ -35,6 +71,57 @@ pub fn run_migrations(conn: &Connection) -> AppResult<()> {
     if current_version < 2 {
         migrate_v2(conn)?;
     }
+    if current_version < 3 {
+        migrate_v3(conn)?;
+    }
+    if current_version < 4 {
+        migrate_v4(conn)?;
+    }
+    if current_version < 5 {
+        migrate_v5(conn)?;
+    }
+    if current_version < 6 {
+        migrate_v6(conn)?;
+    }
+    if current_version < 7 {
+        migrate_v7(conn)?;
+    }
+    if current_version < 8 {
+        migrate_v8(conn)?;
+    }
+    if current_version < 9 {
+        migrate_v9(conn)?;
+    }
+    if current_version < 10 {
+        migrate_v10(conn)?;
+    }
+    if current_version < 11 {
+        migrate_v11(conn)?;
+    }
+    if current_version < 12 {
+        migrate_v12(conn)?;
+    }
+    if current_version < 13 {
+        migrate_v13(conn)?;
+    }
+    if current_version < 14 {
+        migrate_v14(conn)?;
+    }
+    if current_version < 15 {
+        migrate_v15(conn)?;
+    }
+    if current_version < 16 {
+        migrate_v16(conn)?;
+    }
+    if current_version < 17 {
+        migrate_v17(conn)?;
+    }
+    if current_version < 18 {
+        migrate_v18(conn)?;
+    }
+    if current_version < 19 {
+        migrate_v19(conn)?;
+    }
 
     Ok(())
 }
@@ -296,3 +383,571 @@ fn migrate_v2(conn: &Connection) -> AppResult<()> {
     tracing::info!("Migration v2 applied successfully");
     Ok(())
 }
+
+/// Migration v3: Archive container paths
+fn migrate_v3(conn: &Connection) -> AppResult<()> {
+    tracing::info!("Applying migration v3: Book container paths");
+
+    conn.execute_batch(r#"
+        -- Archive (e.g. .zip) the EPUB was extracted from, if any
+        ALTER TABLE books ADD COLUMN container_path TEXT;
+    "#)?;
+
+    // Record migration
+    conn.execute(
+        "INSERT INTO schema_version (version) VALUES (?)",
+        [3],
+    )?;
+
+    tracing::info!("Migration v3 applied successfully");
+    Ok(())
+}
+
+/// Migration v4: Book length
+fn migrate_v4(conn: &Connection) -> AppResult<()> {
+    tracing::info!("Applying migration v4: Book word counts");
+
+    conn.execute_batch(r#"
+        -- Words in the book's text, counted during metadata parsing
+        ALTER TABLE books ADD COLUMN word_count INTEGER;
+    "#)?;
+
+    // Record migration
+    conn.execute(
+        "INSERT INTO schema_version (version) VALUES (?)",
+        [4],
+    )?;
+
+    tracing::info!("Migration v4 applied successfully");
+    Ok(())
+}
+
+/// Migration v5: Searchable book notes
+fn migrate_v5(conn: &Connection) -> AppResult<()> {
+    tracing::info!("Applying migration v5: Notes full-text index");
+
+    conn.execute_batch(r#"
+        -- Full-text index over user notes (ratings.book_id is the rowid)
+        CREATE VIRTUAL TABLE IF NOT EXISTS notes_fts USING fts5(
+            notes,
+            content='ratings',
+            content_rowid='book_id',
+            tokenize='porter unicode61 remove_diacritics 2'
+        );
+
+        CREATE TRIGGER IF NOT EXISTS ratings_notes_ai AFTER INSERT ON ratings BEGIN
+            INSERT INTO notes_fts(rowid, notes) VALUES (new.book_id, new.notes);
+        END;
+
+        CREATE TRIGGER IF NOT EXISTS ratings_notes_ad AFTER DELETE ON ratings BEGIN
+            INSERT INTO notes_fts(notes_fts, rowid, notes) VALUES ('delete', old.book_id, old.notes);
+        END;
+
+        CREATE TRIGGER IF NOT EXISTS ratings_notes_au AFTER UPDATE OF notes ON ratings BEGIN
+            INSERT INTO notes_fts(notes_fts, rowid, notes) VALUES ('delete', old.book_id, old.notes);
+            INSERT INTO notes_fts(rowid, notes) VALUES (new.book_id, new.notes);
+        END;
+
+        -- Index any notes written before the table existed
+        INSERT INTO notes_fts(notes_fts) VALUES ('rebuild');
+    "#)?;
+
+    // Record migration
+    conn.execute(
+        "INSERT INTO schema_version (version) VALUES (?)",
+        [5],
+    )?;
+
+    tracing::info!("Migration v5 applied successfully");
+    Ok(())
+}
+
+/// Migration v6: Change tracking for sync clients
+fn migrate_v6(conn: &Connection) -> AppResult<()> {
+    tracing::info!("Applying migration v6: Sync change tracking");
+
+    conn.execute_batch(r#"
+        CREATE INDEX IF NOT EXISTS idx_books_date_modified ON books(date_modified);
+
+        -- Tombstones for deleted books so clients can sync removals
+        CREATE TABLE IF NOT EXISTS deleted_books (
+            book_id INTEGER PRIMARY KEY,
+            path TEXT NOT NULL,
+            deleted_at INTEGER NOT NULL DEFAULT (strftime('%s', 'now'))
+        );
+
+        CREATE INDEX IF NOT EXISTS idx_deleted_books_deleted_at ON deleted_books(deleted_at);
+
+        CREATE TRIGGER IF NOT EXISTS books_tombstone AFTER DELETE ON books BEGIN
+            INSERT OR REPLACE INTO deleted_books (book_id, path) VALUES (old.id, old.path);
+        END;
+    "#)?;
+
+    // Record migration
+    conn.execute(
+        "INSERT INTO schema_version (version) VALUES (?)",
+        [6],
+    )?;
+
+    tracing::info!("Migration v6 applied successfully");
+    Ok(())
+}
+
+/// Migration v7: Normalize stored book paths
+fn migrate_v7(conn: &Connection) -> AppResult<()> {
+    tracing::info!("Applying migration v7: Normalize book paths");
+
+    let books: Vec<(i64, String, Option<String>)> = {
+        let mut stmt = conn.prepare("SELECT id, path, container_path FROM books")?;
+        let rows = stmt.query_map([], |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)))?;
+        rows.collect::<Result<_, _>>()?
+    };
+
+    let mut skipped = 0;
+    for (id, path, container_path) in books {
+        let normalized = normalize_path(&path);
+        let normalized_container = container_path.as_deref().map(normalize_path);
+        if normalized == path && normalized_container == container_path {
+            continue;
+        }
+
+        // Another row already holds the normalized path; leave this one for
+        // duplicate cleanup rather than losing its ratings here
+        let updated = conn.execute(
+            "UPDATE OR IGNORE books SET path = ?, container_path = ? WHERE id = ?",
+            rusqlite::params![normalized, normalized_container, id],
+        )?;
+        if updated == 0 {
+            skipped += 1;
+        }
+    }
+
+    if skipped > 0 {
+        tracing::warn!("{} book paths collide after normalization and were left unchanged", skipped);
+    }
+
+    // Record migration
+    conn.execute(
+        "INSERT INTO schema_version (version) VALUES (?)",
+        [7],
+    )?;
+
+    tracing::info!("Migration v7 applied successfully");
+    Ok(())
+}
+
+/// Migration v8: Reading progress
+fn migrate_v8(conn: &Connection) -> AppResult<()> {
+    tracing::info!("Applying migration v8: Reading progress");
+
+    conn.execute_batch(r#"
+        ALTER TABLE ratings ADD COLUMN progress_percent REAL;
+        ALTER TABLE ratings ADD COLUMN last_read_at INTEGER;
+
+        CREATE INDEX IF NOT EXISTS idx_ratings_last_read_at ON ratings(last_read_at);
+    "#)?;
+
+    // Record migration
+    conn.execute(
+        "INSERT INTO schema_version (version) VALUES (?)",
+        [8],
+    )?;
+
+    tracing::info!("Migration v8 applied successfully");
+    Ok(())
+}
+
+/// Migration v9: Metadata parse retries
+fn migrate_v9(conn: &Connection) -> AppResult<()> {
+    tracing::info!("Applying migration v9: Metadata parse retries");
+
+    conn.execute_batch(r#"
+        -- Transient parse failures (locked or unreadable files) per book
+        CREATE TABLE IF NOT EXISTS metadata_retries (
+            book_id INTEGER PRIMARY KEY REFERENCES books(id) ON DELETE CASCADE,
+            attempts INTEGER NOT NULL DEFAULT 0,
+            last_error TEXT,
+            last_attempt_at INTEGER NOT NULL DEFAULT (strftime('%s', 'now'))
+        );
+    "#)?;
+
+    // Record migration
+    conn.execute(
+        "INSERT INTO schema_version (version) VALUES (?)",
+        [9],
+    )?;
+
+    tracing::info!("Migration v9 applied successfully");
+    Ok(())
+}
+
+/// Migration v10: Metadata enrichment sources
+fn migrate_v10(conn: &Connection) -> AppResult<()> {
+    tracing::info!("Applying migration v10: Metadata enrichment sources");
+
+    conn.execute_batch(r#"
+        -- Last online lookup per book, so each provider is asked only once
+        CREATE TABLE IF NOT EXISTS book_enrichment (
+            book_id INTEGER PRIMARY KEY REFERENCES books(id) ON DELETE CASCADE,
+            source TEXT NOT NULL,
+            found INTEGER NOT NULL DEFAULT 0,
+            enriched_at INTEGER NOT NULL DEFAULT (strftime('%s', 'now'))
+        );
+    "#)?;
+
+    // Record migration
+    conn.execute(
+        "INSERT INTO schema_version (version) VALUES (?)",
+        [10],
+    )?;
+
+    tracing::info!("Migration v10 applied successfully");
+    Ok(())
+}
+
+/// Migration v11: Half-star ratings
+fn migrate_v11(conn: &Connection) -> AppResult<()> {
+    tracing::info!("Applying migration v11: Half-star ratings");
+
+    // SQLite can't change a CHECK constraint in place, so the table is rebuilt
+    conn.execute_batch(r#"
+        CREATE TABLE ratings_new (
+            book_id INTEGER PRIMARY KEY REFERENCES books(id) ON DELETE CASCADE,
+            -- 0.5 to 5 stars in half-star steps
+            rating REAL CHECK (rating >= 0.5 AND rating <= 5 AND rating * 2 = CAST(rating * 2 AS INTEGER)),
+            read_status TEXT DEFAULT 'unread',
+            date_started INTEGER,
+            date_finished INTEGER,
+            notes TEXT,
+            date_rated INTEGER NOT NULL DEFAULT (strftime('%s', 'now')),
+            progress_percent REAL,
+            last_read_at INTEGER
+        );
+
+        INSERT INTO ratings_new (book_id, rating, read_status, date_started, date_finished,
+                                 notes, date_rated, progress_percent, last_read_at)
+        SELECT book_id, CAST(rating AS REAL), read_status, date_started, date_finished,
+               notes, date_rated, progress_percent, last_read_at
+        FROM ratings;
+
+        DROP TABLE ratings;
+        ALTER TABLE ratings_new RENAME TO ratings;
+
+        CREATE INDEX IF NOT EXISTS idx_ratings_status ON ratings(read_status);
+        CREATE INDEX IF NOT EXISTS idx_ratings_last_read_at ON ratings(last_read_at);
+
+        -- The notes index triggers went with the old table
+        CREATE TRIGGER IF NOT EXISTS ratings_notes_ai AFTER INSERT ON ratings BEGIN
+            INSERT INTO notes_fts(rowid, notes) VALUES (new.book_id, new.notes);
+        END;
+
+        CREATE TRIGGER IF NOT EXISTS ratings_notes_ad AFTER DELETE ON ratings BEGIN
+            INSERT INTO notes_fts(notes_fts, rowid, notes) VALUES ('delete', old.book_id, old.notes);
+        END;
+
+        CREATE TRIGGER IF NOT EXISTS ratings_notes_au AFTER UPDATE OF notes ON ratings BEGIN
+            INSERT INTO notes_fts(notes_fts, rowid, notes) VALUES ('delete', old.book_id, old.notes);
+            INSERT INTO notes_fts(rowid, notes) VALUES (new.book_id, new.notes);
+        END;
+    "#)?;
+
+    // Record migration
+    conn.execute(
+        "INSERT INTO schema_version (version) VALUES (?)",
+        [11],
+    )?;
+
+    tracing::info!("Migration v11 applied successfully");
+    Ok(())
+}
+
+/// Migration v12: Precomputed similar books
+fn migrate_v12(conn: &Connection) -> AppResult<()> {
+    tracing::info!("Applying migration v12: Precomputed similar books");
+
+    conn.execute_batch(r#"
+        -- Each book's nearest embedding neighbours, best first, whatever
+        -- their similarity (unlike book_edges)
+        CREATE TABLE IF NOT EXISTS similar_cache (
+            book_id INTEGER NOT NULL REFERENCES books(id) ON DELETE CASCADE,
+            rank INTEGER NOT NULL,
+            target_id INTEGER NOT NULL REFERENCES books(id) ON DELETE CASCADE,
+            score REAL NOT NULL,
+            PRIMARY KEY (book_id, rank)
+        );
+
+        CREATE INDEX IF NOT EXISTS idx_similar_cache_target ON similar_cache(target_id);
+    "#)?;
+
+    // Record migration
+    conn.execute(
+        "INSERT INTO schema_version (version) VALUES (?)",
+        [12],
+    )?;
+
+    tracing::info!("Migration v12 applied successfully");
+    Ok(())
+}
+
+/// Migration v13: Book file formats
+fn migrate_v13(conn: &Connection) -> AppResult<()> {
+    tracing::info!("Applying migration v13: Book file formats");
+
+    conn.execute_batch(r#"
+        -- File format (epub, mobi, azw3); only EPUBs were scanned before,
+        -- but imported libraries may point at Kindle files
+        ALTER TABLE books ADD COLUMN format TEXT NOT NULL DEFAULT 'epub';
+        UPDATE books SET format = 'mobi' WHERE lower(path) LIKE '%.mobi' OR lower(path) LIKE '%.mobi.gz';
+        UPDATE books SET format = 'azw3' WHERE lower(path) LIKE '%.azw3' OR lower(path) LIKE '%.azw3.gz';
+
+        CREATE INDEX IF NOT EXISTS idx_books_format ON books(format);
+    "#)?;
+
+    // Record migration
+    conn.execute(
+        "INSERT INTO schema_version (version) VALUES (?)",
+        [13],
+    )?;
+
+    tracing::info!("Migration v13 applied successfully");
+    Ok(())
+}
+
+/// Migration v14: Searchable tags
+fn migrate_v14(conn: &Connection) -> AppResult<()> {
+    tracing::info!("Applying migration v14: Tags full-text index");
+
+    conn.execute_batch(r#"
+        -- One row per tagged book (rowid = book id) holding all its tag names
+        CREATE VIRTUAL TABLE IF NOT EXISTS tags_fts USING fts5(
+            tags,
+            tokenize='porter unicode61 remove_diacritics 2'
+        );
+
+        CREATE TRIGGER IF NOT EXISTS book_tags_fts_ai AFTER INSERT ON book_tags BEGIN
+            DELETE FROM tags_fts WHERE rowid = new.book_id;
+            INSERT INTO tags_fts(rowid, tags)
+            SELECT bt.book_id, group_concat(t.name, ' ') FROM book_tags bt
+            JOIN tags t ON t.id = bt.tag_id
+            WHERE bt.book_id = new.book_id GROUP BY bt.book_id;
+        END;
+
+        -- Also fires for the cascade when a book is deleted
+        CREATE TRIGGER IF NOT EXISTS book_tags_fts_ad AFTER DELETE ON book_tags BEGIN
+            DELETE FROM tags_fts WHERE rowid = old.book_id;
+            INSERT INTO tags_fts(rowid, tags)
+            SELECT bt.book_id, group_concat(t.name, ' ') FROM book_tags bt
+            JOIN tags t ON t.id = bt.tag_id
+            WHERE bt.book_id = old.book_id GROUP BY bt.book_id;
+        END;
+
+        -- Index tags added before the table existed
+        INSERT INTO tags_fts(rowid, tags)
+        SELECT bt.book_id, group_concat(t.name, ' ') FROM book_tags bt
+        JOIN tags t ON t.id = bt.tag_id
+        GROUP BY bt.book_id;
+    "#)?;
+
+    // Record migration
+    conn.execute(
+        "INSERT INTO schema_version (version) VALUES (?)",
+        [14],
+    )?;
+
+    tracing::info!("Migration v14 applied successfully");
+    Ok(())
+}
+
+/// Migration v15: Edge generation counter
+fn migrate_v15(conn: &Connection) -> AppResult<()> {
+    tracing::info!("Applying migration v15: Edge generation counter");
+
+    conn.execute_batch(r#"
+        -- Bumped on every edge change, so cached graphs know when to reload
+        CREATE TABLE IF NOT EXISTS edge_generation (
+            id INTEGER PRIMARY KEY CHECK (id = 1),
+            generation INTEGER NOT NULL
+        );
+        INSERT OR IGNORE INTO edge_generation (id, generation) VALUES (1, 0);
+
+        CREATE TRIGGER IF NOT EXISTS book_edges_generation_ai AFTER INSERT ON book_edges BEGIN
+            UPDATE edge_generation SET generation = generation + 1;
+        END;
+
+        CREATE TRIGGER IF NOT EXISTS book_edges_generation_au AFTER UPDATE ON book_edges BEGIN
+            UPDATE edge_generation SET generation = generation + 1;
+        END;
+
+        -- Also fires for the cascade when a book is deleted
+        CREATE TRIGGER IF NOT EXISTS book_edges_generation_ad AFTER DELETE ON book_edges BEGIN
+            UPDATE edge_generation SET generation = generation + 1;
+        END;
+    "#)?;
+
+    // Record migration
+    conn.execute(
+        "INSERT INTO schema_version (version) VALUES (?)",
+        [15],
+    )?;
+
+    tracing::info!("Migration v15 applied successfully");
+    Ok(())
+}
+
+/// Migration v16: Reading position
+fn migrate_v16(conn: &Connection) -> AppResult<()> {
+    tracing::info!("Applying migration v16: Reading position");
+
+    conn.execute_batch(r#"
+        -- Where the reader left off (an EPUB CFI), alongside progress_percent
+        ALTER TABLE ratings ADD COLUMN progress_location TEXT;
+    "#)?;
+
+    // Record migration
+    conn.execute(
+        "INSERT INTO schema_version (version) VALUES (?)",
+        [16],
+    )?;
+
+    tracing::info!("Migration v16 applied successfully");
+    Ok(())
+}
+
+/// Migration v17: Collections
+fn migrate_v17(conn: &Connection) -> AppResult<()> {
+    tracing::info!("Applying migration v17: Collections");
+
+    conn.execute_batch(r#"
+        -- User-defined shelves; a book can be on any number of them
+        CREATE TABLE IF NOT EXISTS collections (
+            id INTEGER PRIMARY KEY,
+            name TEXT NOT NULL UNIQUE COLLATE NOCASE,
+            created_at INTEGER NOT NULL DEFAULT (strftime('%s', 'now'))
+        );
+
+        CREATE TABLE IF NOT EXISTS collection_books (
+            collection_id INTEGER NOT NULL REFERENCES collections(id) ON DELETE CASCADE,
+            book_id INTEGER NOT NULL REFERENCES books(id) ON DELETE CASCADE,
+            added_at INTEGER NOT NULL DEFAULT (strftime('%s', 'now')),
+            PRIMARY KEY (collection_id, book_id)
+        );
+
+        CREATE INDEX IF NOT EXISTS idx_collection_books_book ON collection_books(book_id);
+    "#)?;
+
+    // Record migration
+    conn.execute(
+        "INSERT INTO schema_version (version) VALUES (?)",
+        [17],
+    )?;
+
+    tracing::info!("Migration v17 applied successfully");
+    Ok(())
+}
+
+/// Migration v18: Reindex tags on rename
+fn migrate_v18(conn: &Connection) -> AppResult<()> {
+    tracing::info!("Applying migration v18: Tag rename reindexing");
+
+    conn.execute_batch(r#"
+        -- Renaming a tag changes the indexed tags of every book that has it
+        CREATE TRIGGER IF NOT EXISTS tags_fts_au AFTER UPDATE OF name ON tags BEGIN
+            DELETE FROM tags_fts WHERE rowid IN (SELECT book_id FROM book_tags WHERE tag_id = new.id);
+            INSERT INTO tags_fts(rowid, tags)
+            SELECT bt.book_id, group_concat(t.name, ' ') FROM book_tags bt
+            JOIN tags t ON t.id = bt.tag_id
+            WHERE bt.book_id IN (SELECT book_id FROM book_tags WHERE tag_id = new.id)
+            GROUP BY bt.book_id;
+        END;
+    "#)?;
+
+    // Record migration
+    conn.execute(
+        "INSERT INTO schema_version (version) VALUES (?)",
+        [18],
+    )?;
+
+    tracing::info!("Migration v18 applied successfully");
+    Ok(())
+}
+
+/// Migration v19: Case-insensitive path lookups and normalized library roots
+fn migrate_v19(conn: &Connection) -> AppResult<()> {
+    tracing::info!("Applying migration v19: Path lookups");
+
+    conn.execute_batch(r#"
+        -- Paths keep their case; lookups ignore it on macOS and Windows
+        CREATE INDEX IF NOT EXISTS idx_books_path_nocase ON books(path COLLATE NOCASE);
+    "#)?;
+
+    // Library roots are compared with normalized book paths
+    let libraries: Vec<(i64, String)> = {
+        let mut stmt = conn.prepare("SELECT id, path FROM libraries")?;
+        let rows = stmt.query_map([], |row| Ok((row.get(0)?, row.get(1)?)))?;
+        rows.collect::<Result<_, _>>()?
+    };
+    for (id, path) in libraries {
+        let normalized = normalize_path(&path);
+        if normalized != path {
+            // A root already registered in its normalized form stays as is
+            conn.execute("UPDATE OR IGNORE libraries SET path = ? WHERE id = ?", rusqlite::params![normalized, id])?;
+        }
+    }
+
+    // Record migration
+    conn.execute(
+        "INSERT INTO schema_version (version) VALUES (?)",
+        [19],
+    )?;
+
+    tracing::info!("Migration v19 applied successfully");
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_integer_ratings_migrate_to_half_stars() {
+        let conn = Connection::open_in_memory().unwrap();
+        conn.execute_batch(
+            "CREATE TABLE schema_version (
+                version INTEGER PRIMARY KEY,
+                applied_at INTEGER DEFAULT (strftime('%s', 'now'))
+            )",
+        )
+        .unwrap();
+        let before_half_stars = [
+            migrate_v1, migrate_v2, migrate_v3, migrate_v4, migrate_v5,
+            migrate_v6, migrate_v7, migrate_v8, migrate_v9, migrate_v10,
+        ];
+        for migrate in before_half_stars {
+            migrate(&conn).unwrap();
+        }
+        conn.execute_batch(
+            "INSERT INTO books (id, path, title) VALUES (1, '/b/1.epub', 'One'), (2, '/b/2.epub', 'Two');
+             INSERT INTO ratings (book_id, rating, notes, progress_percent) VALUES (1, 4, 'Lighthouse', 40.0);",
+        )
+        .unwrap();
+
+        run_migrations(&conn).unwrap();
+        assert_eq!(schema_version(&conn).unwrap(), SCHEMA_VERSION);
+
+        let (rating, progress): (f64, f64) = conn
+            .query_row("SELECT rating, progress_percent FROM ratings WHERE book_id = 1", [], |row| {
+                Ok((row.get(0)?, row.get(1)?))
+            })
+            .unwrap();
+        assert_eq!((rating, progress), (4.0, 40.0));
+        assert!(conn.execute("INSERT INTO ratings (book_id, rating) VALUES (2, 2.5)", []).is_ok());
+        assert!(conn.execute("UPDATE ratings SET rating = 2.25 WHERE book_id = 2", []).is_err());
+
+        // The notes index still follows the rebuilt table
+        conn.execute("UPDATE ratings SET notes = 'Harbour' WHERE book_id = 1", []).unwrap();
+        let matches: i64 = conn
+            .query_row("SELECT COUNT(*) FROM notes_fts WHERE notes_fts MATCH 'harbour'", [], |row| row.get(0))
+            .unwrap();
+        assert_eq!(matches, 1);
+    }
+}
@@ -5,15 +5,243 @@
 mod migrations;
 mod queries;
 
+pub use migrations::{check_schema_compatibility, schema_version, SCHEMA_VERSION};
 pub use queries::*;
 
+use crate::ollama::EmbeddingTextConfig;
+use crate::vector::SimilarityMetric;
 use crate::{AppError, AppResult};
 use r2d2::{Pool, PooledConnection};
 use r2d2_sqlite::SqliteConnectionManager;
 use rusqlite::Connection;
 use std::path::Path;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::Duration;
+
+/// Whether paths on this platform compare case-insensitively (macOS, Windows)
+const CASE_INSENSITIVE_PATHS: bool = cfg!(any(target_os = "macos", target_os = "windows"));
+
+/// SQLite collation for comparing stored paths, so differently-cased
+/// spellings of the same file match where the filesystem ignores case
+pub const PATH_COLLATION: &str = if CASE_INSENSITIVE_PATHS { "NOCASE" } else { "BINARY" };
+
+/// Normalize a book or library path for storage and lookup
+///
+/// Resolves `.` and `..` lexically, drops duplicate and trailing separators,
+/// and uses the platform separator. Case is kept as given; lookups compare
+/// with [`PATH_COLLATION`].
+pub fn normalize_path(path: &str) -> String {
+    use std::path::{Component, PathBuf};
+
+    let mut normalized = PathBuf::new();
+    for component in Path::new(path).components() {
+        match component {
+            Component::CurDir => {}
+            Component::ParentDir => match normalized.components().next_back() {
+                Some(Component::Normal(_)) => {
+                    normalized.pop();
+                }
+                // `..` above the root stays at the root
+                Some(Component::RootDir) | Some(Component::Prefix(_)) => {}
+                _ => normalized.push(".."),
+            },
+            other => normalized.push(other.as_os_str()),
+        }
+    }
+
+    normalized.to_string_lossy().to_string()
+}
+
+/// `path` relative to `root`, if it lies under it
+///
+/// Compares whole components, ignoring case where the filesystem does.
+pub fn strip_root<'a>(path: &'a Path, root: &Path) -> Option<&'a Path> {
+    let same = |a: &std::ffi::OsStr, b: &std::ffi::OsStr| {
+        if CASE_INSENSITIVE_PATHS {
+            a.to_string_lossy().to_lowercase() == b.to_string_lossy().to_lowercase()
+        } else {
+            a == b
+        }
+    };
+
+    let mut rest = path.components();
+    for part in root.components() {
+        if !same(rest.next()?.as_os_str(), part.as_os_str()) {
+            return None;
+        }
+    }
+    Some(rest.as_path())
+}
+
+/// Default maximum number of pooled connections
+pub const DEFAULT_POOL_SIZE: u32 = 16;
+
+/// Default rating at or above which a book counts as liked
+pub const DEFAULT_LIKED_RATING: f64 = 4.0;
+
+/// Rating at or below which a book counts as disliked
+pub const DISLIKED_RATING: f64 = 2.0;
+
+/// Default time budget for interactive recommendation commands
+pub const DEFAULT_RECOMMENDATION_TIMEOUT_MS: u64 = 5_000;
+
+/// Default number of computed edges each book keeps when pruning
+pub const DEFAULT_EDGE_PRUNE_MAX_PER_BOOK: u32 = 30;
+
+/// Columns of `books_fts`
+pub const BOOK_FTS_COLUMNS: &[&str] = &["title", "author", "series", "description"];
+
+/// Columns of `notes_fts`
+pub const NOTES_FTS_COLUMNS: &[&str] = &["notes"];
+
+/// Columns of `tags_fts`
+pub const TAGS_FTS_COLUMNS: &[&str] = &["tags"];
+
+/// A search term or operator parsed from user input
+enum SearchToken {
+    Term { field: Option<String>, text: String, prefix: bool },
+    Operator(&'static str),
+}
+
+/// Turn user search input into an FTS5 query for an index with `columns`
+///
+/// Every word and `"quoted phrase"` becomes an FTS5 string, so stray quotes,
+/// parentheses or `*` can't cause syntax errors; a trailing `*` keeps a word
+/// a prefix search. `AND`, `OR` and `NOT` between two terms stay operators.
+/// `field:word` and `field:"phrase"` scope a term to one column.
+///
+/// Returns `None` if no searchable term is left, or if a term is scoped to a
+/// field this index doesn't have (so the query can't match here).
+pub fn sanitize_fts_query(input: &str, columns: &[&str]) -> Option<String> {
+    let all_fields = BOOK_FTS_COLUMNS.iter().chain(NOTES_FTS_COLUMNS).chain(TAGS_FTS_COLUMNS);
+    let known_field = |name: &str| all_fields.clone().any(|f| f.eq_ignore_ascii_case(name));
+
+    // Split into words and quoted phrases; an unclosed quote runs to the end
+    let mut tokens = Vec::new();
+    let mut chars = input.chars().peekable();
+    while let Some(&c) = chars.peek() {
+        if c.is_whitespace() {
+            chars.next();
+            continue;
+        }
+
+        let mut word = String::new();
+        while let Some(&c) = chars.peek() {
+            if c.is_whitespace() || c == '"' {
+                break;
+            }
+            word.push(c);
+            chars.next();
+        }
+
+        let field = word.strip_suffix(':').filter(|f| known_field(f)).map(str::to_ascii_lowercase);
+        if word.is_empty() || (field.is_some() && chars.peek() == Some(&'"')) {
+            chars.next();
+            let phrase: String = chars.by_ref().take_while(|&c| c != '"').collect();
+            tokens.push(SearchToken::Term { field, text: phrase, prefix: false });
+            continue;
+        }
+
+        match word.as_str() {
+            "AND" => tokens.push(SearchToken::Operator("AND")),
+            "OR" => tokens.push(SearchToken::Operator("OR")),
+            "NOT" => tokens.push(SearchToken::Operator("NOT")),
+            _ => {
+                let (field, text) = match word.split_once(':') {
+                    Some((field, text)) if known_field(field) => (Some(field.to_ascii_lowercase()), text),
+                    _ => (None, word.as_str()),
+                };
+                let stripped = text.trim_end_matches('*');
+                tokens.push(SearchToken::Term {
+                    field,
+                    text: stripped.to_string(),
+                    prefix: stripped.len() < text.len(),
+                });
+            }
+        }
+    }
+
+    // Operators only survive between two terms
+    let mut query = String::new();
+    let mut operator = None;
+    for token in tokens {
+        match token {
+            SearchToken::Operator(op) => {
+                if !query.is_empty() {
+                    operator = Some(op);
+                }
+            }
+            SearchToken::Term { field, text, prefix } => {
+                if !text.chars().any(char::is_alphanumeric) {
+                    continue;
+                }
+                if !query.is_empty() {
+                    query.push(' ');
+                    if let Some(op) = operator.take() {
+                        query.push_str(op);
+                        query.push(' ');
+                    }
+                }
+                if let Some(field) = field {
+                    if !columns.contains(&field.as_str()) {
+                        return None;
+                    }
+                    query.push_str(&field);
+                    query.push_str(" : ");
+                }
+                query.push('"');
+                query.push_str(&text.replace('"', "\"\""));
+                query.push('"');
+                if prefix {
+                    query.push('*');
+                }
+            }
+        }
+    }
+
+    (!query.is_empty()).then_some(query)
+}
+
+/// Valid values of a book's read status
+pub const READ_STATUSES: [&str; 5] = ["unread", "want", "reading", "finished", "abandoned"];
+
+/// Whether a rating is 0.5 to 5 stars in half-star steps
+pub fn is_valid_rating(rating: f64) -> bool {
+    (0.5..=5.0).contains(&rating) && (rating * 2.0).fract() == 0.0
+}
+
+/// Connection pool configuration
+#[derive(Debug, Clone)]
+pub struct PoolConfig {
+    /// Maximum number of open connections
+    pub max_size: u32,
+    /// How long to wait for a free connection before giving up
+    pub connection_timeout: Duration,
+}
+
+impl Default for PoolConfig {
+    fn default() -> Self {
+        Self {
+            max_size: DEFAULT_POOL_SIZE,
+            connection_timeout: Duration::from_secs(10),
+        }
+    }
+}
+
+/// Connection pool usage snapshot
+#[derive(Debug, Clone, serde::Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PoolStats {
+    pub max_size: u32,
+    pub connections: u32,
+    pub idle: u32,
+    pub in_use: u32,
+}
 
 /// Database wrapper with connection pooling
+///
+/// Clones share the same pool.
+#[derive(Clone)]
 pub struct Database {
     pool: Pool<SqliteConnectionManager>,
     db_path: String,
@@ -22,6 +250,11 @@ pub struct Database {
 impl Database {
     /// Create a new database connection pool
     pub fn new(path: &Path) -> AppResult<Self> {
+        Self::with_pool_config(path, PoolConfig::default())
+    }
+
+    /// Create a database connection pool with a custom pool configuration
+    pub fn with_pool_config(path: &Path, config: PoolConfig) -> AppResult<Self> {
         let db_path = path.to_string_lossy().to_string();
 
         let manager = SqliteConnectionManager::file(path)
@@ -37,8 +270,55 @@ impl Database {
                 Ok(())
             });
 
-        let pool = Pool::builder()
-            .max_size(16)
+        let builder = Pool::builder()
+            .max_size(config.max_size.max(1))
+            .connection_timeout(config.connection_timeout);
+
+        Self::from_manager(builder, manager, db_path)
+    }
+
+    /// Create an isolated in-memory database, mainly for tests
+    ///
+    /// Pooled connections share one database through a shared-cache URI,
+    /// which is also returned by `path()` so a `VectorStore` can open it.
+    /// The database lives as long as the pool.
+    pub fn new_in_memory() -> AppResult<Self> {
+        static NEXT_ID: AtomicUsize = AtomicUsize::new(0);
+        let db_path = format!(
+            "file:epubgraph-{}-{}?mode=memory&cache=shared",
+            std::process::id(),
+            NEXT_ID.fetch_add(1, Ordering::Relaxed)
+        );
+
+        // WAL and synchronous don't apply to memory databases
+        let manager = SqliteConnectionManager::file(&db_path)
+            .with_init(|conn| {
+                conn.execute_batch(
+                    "PRAGMA foreign_keys = ON;
+                     PRAGMA temp_store = MEMORY;"
+                )?;
+                Ok(())
+            });
+
+        // Never let the pool close every connection, or the data is lost
+        let config = PoolConfig::default();
+        let builder = Pool::builder()
+            .max_size(config.max_size)
+            .min_idle(Some(1))
+            .max_lifetime(None)
+            .idle_timeout(None)
+            .connection_timeout(config.connection_timeout);
+
+        Self::from_manager(builder, manager, db_path)
+    }
+
+    /// Build the pool and run migrations
+    fn from_manager(
+        builder: r2d2::Builder<SqliteConnectionManager>,
+        manager: SqliteConnectionManager,
+        db_path: String,
+    ) -> AppResult<Self> {
+        let pool = builder
             .build(manager)
             .map_err(|e| AppError::Database(rusqlite::Error::InvalidParameterName(e.to_string())))?;
 
@@ -52,11 +332,83 @@ impl Database {
         Ok(Self { pool, db_path })
     }
 
+    /// Schema version recorded in this database
+    pub fn schema_version(&self) -> AppResult<i32> {
+        self.with_conn(migrations::schema_version)
+    }
+
     /// Get the database file path
     pub fn path(&self) -> &str {
         &self.db_path
     }
 
+    /// Write a consistent copy of the database to a new file
+    ///
+    /// The WAL is checkpointed and the copy is built with `VACUUM INTO` in a
+    /// temporary file next to `dest`, then renamed into place, so `dest` is
+    /// either a complete database or absent. Fails before copying if the
+    /// target is read-only or lacks room for the database's live pages.
+    /// Refuses to overwrite an existing file. Returns the size of the copy in
+    /// bytes.
+    pub fn copy_to(&self, dest: &Path) -> AppResult<u64> {
+        if dest.exists() {
+            return Err(AppError::InvalidInput(format!("{} already exists", dest.display())));
+        }
+        let file_name = dest
+            .file_name()
+            .map(|name| name.to_string_lossy().to_string())
+            .ok_or_else(|| AppError::InvalidInput(format!("{} is not a file path", dest.display())))?;
+        let parent = dest.parent().filter(|p| !p.as_os_str().is_empty()).unwrap_or(Path::new("."));
+        std::fs::create_dir_all(parent)?;
+
+        // Fail before copying anything if the target is read-only
+        let probe = parent.join(format!(".{}.write-test", file_name));
+        std::fs::write(&probe, b"")?;
+        std::fs::remove_file(&probe)?;
+
+        // `VACUUM INTO` writes at most the pages in use
+        let needed: i64 = self.with_conn(|conn| {
+            conn.query_row(
+                "SELECT (p.page_count - f.freelist_count) * s.page_size
+                 FROM pragma_page_count() p, pragma_freelist_count() f, pragma_page_size() s",
+                [],
+                |row| row.get(0),
+            )
+            .map_err(AppError::Database)
+        })?;
+        let available = fs4::available_space(parent)?;
+        if available < needed.max(0) as u64 {
+            return Err(AppError::InvalidInput(format!(
+                "Not enough space in {}: {} bytes needed, {} available",
+                parent.display(),
+                needed,
+                available
+            )));
+        }
+
+        // Leftover sidecars would be replayed into the new file when opened
+        for suffix in ["-wal", "-shm", ".tmp"] {
+            let stale = parent.join(format!("{}{}", file_name, suffix));
+            if stale.exists() {
+                std::fs::remove_file(stale)?;
+            }
+        }
+
+        let tmp = parent.join(format!("{}.tmp", file_name));
+        let copied = self.with_conn(|conn| {
+            conn.query_row("PRAGMA wal_checkpoint(TRUNCATE)", [], |_| Ok(()))?;
+            conn.execute("VACUUM INTO ?", [tmp.to_string_lossy()])?;
+            Ok(())
+        });
+        if let Err(e) = copied {
+            let _ = std::fs::remove_file(&tmp);
+            return Err(e);
+        }
+
+        std::fs::rename(&tmp, dest)?;
+        Ok(std::fs::metadata(dest)?.len())
+    }
+
     /// Reset the database by deleting all data
     pub fn reset(&self) -> AppResult<()> {
         let conn = self.conn()?;
@@ -79,8 +431,27 @@ impl Database {
     
     /// Get a connection from the pool
     pub fn conn(&self) -> AppResult<PooledConnection<SqliteConnectionManager>> {
-        self.pool.get()
-            .map_err(|e| AppError::Database(rusqlite::Error::InvalidParameterName(e.to_string())))
+        self.pool.get().map_err(|e| {
+            let state = self.pool.state();
+            AppError::PoolTimeout(format!(
+                "no connection available after {:?} ({} of {} in use): {}",
+                self.pool.connection_timeout(),
+                state.connections - state.idle_connections,
+                self.pool.max_size(),
+                e
+            ))
+        })
+    }
+
+    /// Get current connection pool usage
+    pub fn pool_stats(&self) -> PoolStats {
+        let state = self.pool.state();
+        PoolStats {
+            max_size: self.pool.max_size(),
+            connections: state.connections,
+            idle: state.idle_connections,
+            in_use: state.connections - state.idle_connections,
+        }
     }
     
     /// Execute a function with a connection
@@ -129,9 +500,18 @@ pub struct Book {
     pub date_indexed: Option<i64>,
     pub embedding_status: String,
     pub embedding_model: Option<String>,
+    pub container_path: Option<String>,
+    pub word_count: Option<i64>,
+    /// File format (`epub`, `mobi`, `azw3`)
+    pub format: String,
     // User data (from join)
-    pub rating: Option<i32>,
+    pub rating: Option<f64>,
     pub read_status: Option<String>,
+    pub notes: Option<String>,
+    /// Percent read (0-100), if progress has been recorded
+    pub progress_percent: Option<f64>,
+    /// Reading position within the book (an EPUB CFI)
+    pub progress_location: Option<String>,
 }
 
 /// Library record
@@ -155,6 +535,36 @@ fn default_accessible() -> bool {
     true
 }
 
+/// Scan history record
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ScanRecord {
+    pub id: i64,
+    pub library_id: i64,
+    pub started_at: i64,
+    /// `None` while the scan is still running (or if it was interrupted)
+    pub completed_at: Option<i64>,
+    pub books_found: Option<i64>,
+    pub books_added: Option<i64>,
+    pub books_updated: Option<i64>,
+    pub errors: Vec<String>,
+}
+
+/// Books that look like different editions or copies of the same work
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DuplicateGroup {
+    /// `"isbn"` or `"fileHash"` if any two books share one, otherwise
+    /// `"titleAuthor"`
+    pub reason: String,
+    pub books: Vec<Book>,
+    /// Book suggested to keep (rated, embedded, then oldest)
+    pub suggested_keep_id: i64,
+}
+
+/// Edge type of user-created links, which graph rebuilds keep
+pub const MANUAL_EDGE_TYPE: &str = "manual";
+
 /// Graph edge record
 #[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
 #[serde(rename_all = "camelCase")]
@@ -176,6 +586,60 @@ pub struct PagedResult<T> {
     pub has_more: bool,
 }
 
+/// Changes since a sync cursor
+///
+/// `server_time` is taken before the changes are read; pass it back as the
+/// next cursor. Items changed in that same second may be returned twice.
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SyncDelta<T> {
+    pub items: Vec<T>,
+    pub server_time: i64,
+}
+
+/// A book removed from the library
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DeletedBook {
+    pub book_id: i64,
+    pub path: String,
+    pub deleted_at: i64,
+}
+
+/// A book's entry in the durable embedding job queue
+#[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct EmbeddingJob {
+    pub book_id: i64,
+    /// `pending`, `running`, `complete` or `failed`
+    pub status: String,
+    /// Higher runs first
+    pub priority: i32,
+    pub attempts: u32,
+    pub last_error: Option<String>,
+}
+
+/// A user-defined collection of books
+#[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Collection {
+    pub id: i64,
+    pub name: String,
+    pub created_at: i64,
+    pub book_count: i64,
+}
+
+/// A book in progress, for the "continue reading" shelf
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CurrentlyReading {
+    pub book: Book,
+    /// Percent read (0-100), if progress has been recorded
+    pub progress_percent: Option<f64>,
+    /// When progress was last recorded (unix seconds)
+    pub last_read_at: Option<i64>,
+}
+
 /// Book query parameters
 #[derive(Debug, Clone, Default, serde::Deserialize)]
 #[serde(rename_all = "camelCase")]
@@ -185,8 +649,17 @@ pub struct BookQuery {
     pub series: Option<String>,
     pub tags: Option<Vec<String>>,
     pub read_status: Option<String>,
-    pub min_rating: Option<i32>,
+    pub min_rating: Option<f64>,
     pub embedding_status: Option<String>,
+    /// Import source (`scan`, `calibre`, `import`)
+    pub source: Option<String>,
+    pub language: Option<String>,
+    /// File format (`epub`, `mobi`, `azw3`)
+    pub format: Option<String>,
+    /// Leave out books in this series
+    pub exclude_series: Option<String>,
+    /// Only books in this collection
+    pub collection_id: Option<i64>,
     pub sort_by: Option<String>,
     pub sort_order: Option<String>,
     pub limit: Option<i64>,
@@ -203,6 +676,43 @@ pub struct Settings {
     pub max_recommendations: i32,
     pub auto_scan_enabled: bool,
     pub scan_interval_minutes: i32,
+    pub similarity_metric: SimilarityMetric,
+    pub scan_archives: bool,
+    /// Maximum pooled database connections (applied on restart)
+    pub db_pool_size: u32,
+    /// Shared Ollama request budget (0 disables limiting)
+    pub ollama_requests_per_second: f64,
+    /// Metadata fields used to build embedding text
+    pub embedding_fields: EmbeddingTextConfig,
+    /// Only books rated/finished within this many days seed personalized
+    /// recommendations (0 = all time)
+    pub recent_reads_days: u32,
+    /// Where extracted covers are stored (empty = `covers` in the data dir)
+    pub cover_dir: String,
+    /// Personal reading speed from finished books (0 = not enough data yet)
+    pub user_wpm: f64,
+    /// Embedding similarity a neighbor needs to be considered for any edge
+    pub edge_candidate_floor: f64,
+    /// Embedding similarity a neighbor needs for a content edge
+    pub content_edge_floor: f64,
+    /// Look up books without a description online by ISBN
+    pub enrichment_enabled: bool,
+    /// Enrichment provider (empty = OpenLibrary, see `enrich::provider_for`)
+    pub enrichment_endpoint: String,
+    /// Rating at or above which a book seeds personalized recommendations
+    pub liked_rating_threshold: f64,
+    /// Prune weak edges after each full graph rebuild
+    pub edge_prune_enabled: bool,
+    /// Strongest computed edges each book keeps when pruning
+    pub edge_prune_max_per_book: u32,
+    /// Edges below this weight are dropped when pruning
+    pub edge_prune_min_weight: f64,
+    /// Time budget for interactive recommendations in ms (0 = unbounded)
+    pub recommendation_timeout_ms: u64,
+    /// Similar books precomputed per book when it's embedded (0 = off)
+    pub similar_cache_size: u32,
+    /// Candidate count from which similarity search is approximate (0 = always exact)
+    pub ann_threshold: usize,
 }
 
 impl Default for Settings {
@@ -214,6 +724,235 @@ impl Default for Settings {
             max_recommendations: 20,
             auto_scan_enabled: true,
             scan_interval_minutes: 60,
+            similarity_metric: SimilarityMetric::default(),
+            scan_archives: false,
+            db_pool_size: DEFAULT_POOL_SIZE,
+            ollama_requests_per_second: crate::ollama::DEFAULT_REQUESTS_PER_SECOND,
+            embedding_fields: EmbeddingTextConfig::default(),
+            recent_reads_days: 0,
+            cover_dir: String::new(),
+            user_wpm: 0.0,
+            edge_candidate_floor: 0.3,
+            content_edge_floor: crate::graph::DEFAULT_EDGE_FLOOR,
+            enrichment_enabled: false,
+            enrichment_endpoint: String::new(),
+            liked_rating_threshold: DEFAULT_LIKED_RATING,
+            edge_prune_enabled: false,
+            edge_prune_max_per_book: DEFAULT_EDGE_PRUNE_MAX_PER_BOOK,
+            edge_prune_min_weight: 0.0,
+            recommendation_timeout_ms: DEFAULT_RECOMMENDATION_TIMEOUT_MS,
+            similar_cache_size: 0,
+            ann_threshold: crate::vector::DEFAULT_ANN_THRESHOLD,
         }
     }
 }
+
+impl Settings {
+    /// Reading speed for time estimates, personal when known
+    pub fn words_per_minute(&self) -> f64 {
+        if self.user_wpm > 0.0 {
+            self.user_wpm
+        } else {
+            crate::epub::DEFAULT_WORDS_PER_MINUTE
+        }
+    }
+}
+
+/// A scanned book at `/library/{title}.epub` with only a title and author
+#[cfg(test)]
+pub(crate) fn test_book(title: &str, author: Option<&str>) -> NewBook {
+    NewBook {
+        path: format!("/library/{}.epub", title),
+        cover_path: None,
+        file_size: 0,
+        file_hash: None,
+        title: title.to_string(),
+        sort_title: None,
+        author: author.map(str::to_string),
+        author_sort: None,
+        series: None,
+        series_index: None,
+        description: None,
+        language: None,
+        publisher: None,
+        publish_date: None,
+        isbn: None,
+        source: "scan".to_string(),
+        container_path: None,
+        word_count: None,
+        format: crate::epub::Format::Epub,
+        tags: Vec::new(),
+        authors: Vec::new(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sanitize_fts_query() {
+        let books = |input: &str| sanitize_fts_query(input, BOOK_FTS_COLUMNS);
+
+        assert_eq!(books("tolkien").as_deref(), Some(r#""tolkien""#));
+        assert_eq!(books(r#"the "long journey""#).as_deref(), Some(r#""the" "long journey""#));
+        assert_eq!(books("tolk*").as_deref(), Some(r#""tolk"*"#));
+
+        // Quotes, parentheses and stray operators are defused
+        assert_eq!(books(r#"it's (a) "open quote"#).as_deref(), Some(r#""it's" "(a)" "open quote""#));
+        assert_eq!(books("dune AND (messiah OR children)").as_deref(), Some(r#""dune" AND "(messiah" OR "children)""#));
+        assert_eq!(books("OR dune NOT").as_deref(), Some(r#""dune""#));
+        assert_eq!(books(r#"say "hi"there"#).as_deref(), Some(r#""say" "hi" "there""#));
+        assert_eq!(books(r#"( ) * "" AND"#), None);
+        assert_eq!(books(""), None);
+
+        // Field prefixes scope a term to a column of this index
+        assert_eq!(
+            books(r#"Author:tolkien title:"the hobbit" re:zero"#).as_deref(),
+            Some(r#"author : "tolkien" title : "the hobbit" "re:zero""#)
+        );
+        assert_eq!(sanitize_fts_query("author:tolkien", NOTES_FTS_COLUMNS), None);
+        assert_eq!(sanitize_fts_query("notes:reread", NOTES_FTS_COLUMNS).as_deref(), Some(r#"notes : "reread""#));
+    }
+
+    #[test]
+    fn test_normalize_path() {
+        let expected = if cfg!(windows) { r"\books\a.epub" } else { "/books/a.epub" };
+        assert_eq!(normalize_path("/books/a.epub"), expected);
+        assert_eq!(normalize_path("/books//./sub/../a.epub"), expected);
+        assert_eq!(normalize_path("/books/a.epub/"), expected);
+        assert_eq!(normalize_path("/../books/a.epub"), expected);
+
+        // Case is kept, even where lookups ignore it
+        assert_eq!(normalize_path("/Books/A.epub"), normalize_path("/Books/./A.epub"));
+        assert_ne!(normalize_path("/Books/A.epub"), expected);
+    }
+
+    #[test]
+    fn test_strip_root() {
+        let case_folds = cfg!(any(target_os = "macos", target_os = "windows"));
+        let strip = |path: &str, root: &str| strip_root(Path::new(path), Path::new(root)).map(Path::to_path_buf);
+        assert_eq!(strip("/Books/Author/a.epub", "/Books"), Some(Path::new("Author/a.epub").to_path_buf()));
+        assert_eq!(strip("/Books/a.epub", "/Books/"), Some(Path::new("a.epub").to_path_buf()));
+        assert_eq!(strip("/Books-extra/a.epub", "/Books"), None);
+        assert_eq!(strip("/books/a.epub", "/Books").is_some(), case_folds);
+    }
+
+    #[test]
+    fn test_path_variants_resolve_to_same_book() {
+        let db = Database::new_in_memory().unwrap();
+        let id = db
+            .insert_book(&NewBook { path: "/Books/Dune.epub".to_string(), ..test_book("Dune", None) })
+            .unwrap();
+
+        let find = |path: &str| db.get_book_by_path(path).unwrap().map(|b| b.id);
+        assert_eq!(find("/Books/Dune.epub/"), Some(id));
+        assert_eq!(find("/Books/extra/../Dune.epub"), Some(id));
+
+        // Case only folds where the filesystem does, and is kept either way
+        let case_folds = cfg!(any(target_os = "macos", target_os = "windows"));
+        assert_eq!(find("/books/dune.epub"), if case_folds { Some(id) } else { None });
+        assert_eq!(db.get_book(id).unwrap().path, normalize_path("/Books/Dune.epub"));
+
+        // A moved book is stored normalized too
+        db.update_book_path(id, "/Books/Sorted/../Arrakis//Dune.epub").unwrap();
+        assert_eq!(find("/Books/Arrakis/Dune.epub"), Some(id));
+        assert_eq!(db.get_book(id).unwrap().path, normalize_path("/Books/Arrakis/Dune.epub"));
+        assert_eq!(find("/Books/Dune.epub"), None);
+    }
+
+    #[test]
+    fn test_schema_compatibility() {
+        let db = Database::new_in_memory().unwrap();
+        let version = db.schema_version().unwrap();
+        assert_eq!(version, SCHEMA_VERSION);
+
+        assert!(check_schema_compatibility(version).is_ok());
+        assert!(check_schema_compatibility(version - 1).is_ok());
+        assert!(check_schema_compatibility(version + 1).is_err());
+
+        let fresh = Connection::open_in_memory().unwrap();
+        assert_eq!(schema_version(&fresh).unwrap(), 0);
+    }
+
+    #[test]
+    fn test_in_memory_database() {
+        let db = Database::new_in_memory().unwrap();
+        db.with_conn(|conn| {
+            conn.execute("INSERT INTO books (id, path, title) VALUES (1, '/books/a.epub', 'Dune')", [])?;
+            Ok(())
+        })
+        .unwrap();
+
+        // Hold one connection so the query runs on another pooled connection
+        let _held = db.conn().unwrap();
+        let result = db.query_books(&BookQuery::default()).unwrap();
+        assert_eq!(result.total, 1);
+        assert_eq!(db.get_book(1).unwrap().title, "Dune");
+
+        // Each in-memory database is isolated
+        let other = Database::new_in_memory().unwrap();
+        assert!(other.get_book(1).is_err());
+
+        // Foreign keys are enforced
+        let orphan = db.with_conn(|conn| {
+            conn.execute("INSERT INTO ratings (book_id, rating) VALUES (99, 3)", [])?;
+            Ok(())
+        });
+        assert!(orphan.is_err());
+    }
+
+    #[test]
+    fn test_copy_to_new_location() {
+        let db = Database::new_in_memory().unwrap();
+        db.with_conn(|conn| {
+            conn.execute_batch(
+                "INSERT INTO books (id, path, title) VALUES (1, '/books/a.epub', 'Dune');
+                 INSERT INTO ratings (book_id, rating, notes) VALUES (1, 5, 'Spice');
+                 INSERT INTO settings (key, value) VALUES ('user_wpm', '210');"
+            )?;
+            Ok(())
+        })
+        .unwrap();
+
+        let dir = tempfile::TempDir::new().unwrap();
+        let dest = dir.path().join("moved").join("library.db");
+        assert!(db.copy_to(&dest).unwrap() > 0);
+        assert!(!dir.path().join("moved").join("library.db.tmp").exists());
+
+        let reopened = Database::new(&dest).unwrap();
+        assert_eq!(reopened.schema_version().unwrap(), SCHEMA_VERSION);
+        let book = reopened.get_book(1).unwrap();
+        assert_eq!((book.title.as_str(), book.rating, book.notes.as_deref()), ("Dune", Some(5.0), Some("Spice")));
+        assert_eq!(reopened.get_settings().unwrap().user_wpm, 210.0);
+
+        // Never overwrite an existing database
+        assert!(db.copy_to(&dest).is_err());
+    }
+
+    #[test]
+    fn test_exhausted_pool_times_out() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let db = Database::with_pool_config(
+            &dir.path().join("test.db"),
+            PoolConfig {
+                max_size: 1,
+                connection_timeout: Duration::from_millis(100),
+            },
+        )
+        .unwrap();
+
+        let held = db.conn().unwrap();
+        let stats = db.pool_stats();
+        assert_eq!(stats.max_size, 1);
+        assert_eq!(stats.in_use, 1);
+
+        let start = std::time::Instant::now();
+        let result = db.conn();
+        assert!(matches!(result, Err(AppError::PoolTimeout(_))));
+        assert!(start.elapsed() < Duration::from_secs(5));
+
+        drop(held);
+        assert!(db.conn().is_ok());
+    }
+}
@@ -1,8 +1,16 @@
 //! Database query functions
 
-use super::{Book, BookEdge, BookQuery, Database, Library, PagedResult, Settings};
+use super::{
+    normalize_path, sanitize_fts_query, Book, BookEdge, BookQuery, Collection, CurrentlyReading, Database,
+    DeletedBook, DuplicateGroup, EmbeddingJob, Library, PagedResult, ScanRecord, Settings, SyncDelta,
+    BOOK_FTS_COLUMNS, MANUAL_EDGE_TYPE, NOTES_FTS_COLUMNS, PATH_COLLATION, READ_STATUSES, TAGS_FTS_COLUMNS,
+};
+use crate::epub::{generate_author_sort, generate_sort_title, Format};
 use crate::{AppError, AppResult};
+use r2d2::PooledConnection;
+use r2d2_sqlite::SqliteConnectionManager;
 use rusqlite::{params, Row};
+use std::collections::{HashMap, HashSet};
 
 impl Database {
     // ============================================
@@ -38,8 +46,9 @@ impl Database {
         })
     }
     
-    /// Add a new library
+    /// Add a new library, normalizing its root like book paths
     pub fn add_library(&self, name: &str, path: &str, is_calibre: bool, calibre_db_path: Option<&str>) -> AppResult<Library> {
+        let path = normalize_path(path);
         self.with_conn(|conn| {
             conn.execute(
                 "INSERT INTO libraries (name, path, is_calibre, calibre_db_path) VALUES (?, ?, ?, ?)",
@@ -51,7 +60,7 @@ impl Database {
             Ok(Library {
                 id,
                 name: name.to_string(),
-                path: path.to_string(),
+                path: path.clone(),
                 is_calibre,
                 calibre_db_path: calibre_db_path.map(String::from),
                 last_scan: None,
@@ -62,12 +71,16 @@ impl Database {
         })
     }
     
-    /// Remove a library (books are NOT deleted)
+    /// Remove a library and its scan history (books are NOT deleted)
     pub fn remove_library(&self, id: i64) -> AppResult<()> {
-        self.with_conn(|conn| {
-            conn.execute("DELETE FROM libraries WHERE id = ?", [id])?;
-            Ok(())
-        })
+        let mut conn = self.conn()?;
+        let tx = conn.transaction()?;
+
+        tx.execute("DELETE FROM scan_history WHERE library_id = ?", [id])?;
+        tx.execute("DELETE FROM libraries WHERE id = ?", [id])?;
+
+        tx.commit()?;
+        Ok(())
     }
     
     /// Update library last scan time
@@ -81,83 +94,149 @@ impl Database {
         })
     }
     
+    /// Record the start of a library scan, returning the history row ID
+    pub fn start_scan_record(&self, library_id: i64) -> AppResult<i64> {
+        self.with_conn(|conn| {
+            conn.execute(
+                "INSERT INTO scan_history (library_id, started_at) VALUES (?, strftime('%s', 'now'))",
+                [library_id],
+            )?;
+            Ok(conn.last_insert_rowid())
+        })
+    }
+
+    /// Mark a scan as complete with its results
+    pub fn complete_scan_record(
+        &self,
+        id: i64,
+        books_found: i64,
+        books_added: i64,
+        books_updated: i64,
+        errors: &[String],
+    ) -> AppResult<()> {
+        let errors = serde_json::to_string(errors)?;
+        self.with_conn(|conn| {
+            conn.execute(
+                "UPDATE scan_history
+                 SET completed_at = strftime('%s', 'now'), books_found = ?, books_added = ?,
+                     books_updated = ?, errors = ?
+                 WHERE id = ?",
+                params![books_found, books_added, books_updated, errors, id],
+            )?;
+            Ok(())
+        })
+    }
+
+    /// Get past scans of a library, most recent first
+    pub fn get_scan_history(&self, library_id: i64, limit: i64) -> AppResult<Vec<ScanRecord>> {
+        self.with_conn(|conn| {
+            let mut stmt = conn.prepare(
+                "SELECT id, library_id, started_at, completed_at, books_found, books_added,
+                        books_updated, errors
+                 FROM scan_history
+                 WHERE library_id = ?
+                 ORDER BY started_at DESC, id DESC
+                 LIMIT ?"
+            )?;
+
+            let records = stmt.query_map(params![library_id, limit], |row| {
+                let errors: Option<String> = row.get(7)?;
+                Ok(ScanRecord {
+                    id: row.get(0)?,
+                    library_id: row.get(1)?,
+                    started_at: row.get(2)?,
+                    completed_at: row.get(3)?,
+                    books_found: row.get(4)?,
+                    books_added: row.get(5)?,
+                    books_updated: row.get(6)?,
+                    errors: errors
+                        .and_then(|e| serde_json::from_str(&e).ok())
+                        .unwrap_or_default(),
+                })
+            })?.collect::<Result<Vec<_>, _>>()?;
+
+            Ok(records)
+        })
+    }
+
     // ============================================
     // BOOK OPERATIONS
     // ============================================
     
+    /// Get the IDs of every book matching a query's filters
+    ///
+    /// Sorting and pagination are ignored. Cheap enough to narrow the
+    /// candidate set before a similarity search.
+    pub fn query_book_ids(&self, query: &BookQuery) -> AppResult<std::collections::HashSet<i64>> {
+        self.with_conn(|conn| {
+            let mut sql = String::from("SELECT b.id FROM books b LEFT JOIN ratings r ON b.id = r.book_id");
+            let (conditions, params_vec) = book_query_conditions(query);
+            if !conditions.is_empty() {
+                sql.push_str(" WHERE ");
+                sql.push_str(&conditions.join(" AND "));
+            }
+
+            let mut stmt = conn.prepare(&sql)?;
+            let params_refs: Vec<&dyn rusqlite::ToSql> = params_vec.iter().map(|p| p.as_ref()).collect();
+            let ids = stmt.query_map(params_refs.as_slice(), |row| row.get(0))?
+                .collect::<Result<_, _>>()?;
+            Ok(ids)
+        })
+    }
+
     /// Query books with filtering and pagination
     pub fn query_books(&self, query: &BookQuery) -> AppResult<PagedResult<Book>> {
         self.with_conn(|conn| {
             let mut sql = String::from(
-                "SELECT b.*, r.rating, r.read_status 
+                "SELECT b.*, r.rating, r.read_status, r.notes, r.progress_percent, r.progress_location
                  FROM books b 
                  LEFT JOIN ratings r ON b.id = r.book_id"
             );
             
-            let mut conditions = Vec::new();
-            let mut params_vec: Vec<Box<dyn rusqlite::ToSql>> = Vec::new();
-            
-            // FTS search
-            if let Some(ref search) = query.search {
-                if !search.is_empty() {
-                    conditions.push("b.id IN (SELECT rowid FROM books_fts WHERE books_fts MATCH ?)");
-                    params_vec.push(Box::new(search.clone()));
-                }
-            }
-            
-            // Author filter
-            if let Some(ref author) = query.author {
-                conditions.push("b.author = ?");
-                params_vec.push(Box::new(author.clone()));
-            }
-            
-            // Series filter
-            if let Some(ref series) = query.series {
-                conditions.push("b.series = ?");
-                params_vec.push(Box::new(series.clone()));
-            }
-            
-            // Read status filter
-            if let Some(ref status) = query.read_status {
-                conditions.push("r.read_status = ?");
-                params_vec.push(Box::new(status.clone()));
-            }
-            
-            // Min rating filter
-            if let Some(min_rating) = query.min_rating {
-                conditions.push("r.rating >= ?");
-                params_vec.push(Box::new(min_rating));
-            }
-            
-            // Embedding status filter
-            if let Some(ref status) = query.embedding_status {
-                conditions.push("b.embedding_status = ?");
-                params_vec.push(Box::new(status.clone()));
-            }
-            
-            // Build WHERE clause
+            let (conditions, mut params_vec) = book_query_conditions(query);
+            let mut where_clause = String::new();
             if !conditions.is_empty() {
-                sql.push_str(" WHERE ");
-                sql.push_str(&conditions.join(" AND "));
+                where_clause = format!(" WHERE {}", conditions.join(" AND "));
             }
             
             // Count total
-            let count_sql = format!("SELECT COUNT(*) FROM ({}) AS subq", sql);
+            let count_sql = format!("SELECT COUNT(*) FROM ({}{}) AS subq", sql, where_clause);
             let params_refs: Vec<&dyn rusqlite::ToSql> = params_vec.iter().map(|p| p.as_ref()).collect();
             let total: i64 = conn.query_row(&count_sql, params_refs.as_slice(), |row| row.get(0))?;
             
-            // Sorting
+            // Sorting; relevance needs a search term, without one it falls
+            // back to date added
             let sort_by = query.sort_by.as_deref().unwrap_or("date_added");
             let sort_order = query.sort_order.as_deref().unwrap_or("desc");
-            let sort_column = match sort_by {
-                "title" => "b.sort_title",
-                "author" => "b.author_sort",
-                "dateAdded" | "date_added" => "b.date_added",
-                "rating" => "r.rating",
-                "series" => "b.series, b.series_index",
-                _ => "b.date_added",
+            let search = query.search.as_deref().and_then(|s| sanitize_fts_query(s, BOOK_FTS_COLUMNS));
+            let order_by = match (sort_by, search) {
+                ("relevance", Some(search)) => {
+                    // Best BM25 match first; books matching only on notes or
+                    // tags follow, newest first. Title hits outweigh author,
+                    // series and description hits.
+                    sql.push_str(
+                        " LEFT JOIN (
+                             SELECT rowid, bm25(books_fts, 10.0, 5.0, 3.0, 1.0) AS rank
+                             FROM books_fts WHERE books_fts MATCH ?
+                         ) fts ON fts.rowid = b.id"
+                    );
+                    params_vec.insert(0, Box::new(search));
+                    "fts.rank IS NULL, fts.rank, b.date_added DESC".to_string()
+                }
+                _ => {
+                    let sort_column = match sort_by {
+                        "title" => "COALESCE(b.sort_title, b.title) COLLATE NOCASE",
+                        "author" => "COALESCE(b.author_sort, b.author) COLLATE NOCASE",
+                        "dateAdded" | "date_added" => "b.date_added",
+                        "rating" => "r.rating",
+                        "series" => "b.series, b.series_index",
+                        _ => "b.date_added",
+                    };
+                    format!("{} {}", sort_column, sort_order.to_uppercase())
+                }
             };
-            sql.push_str(&format!(" ORDER BY {} {}", sort_column, sort_order.to_uppercase()));
+            sql.push_str(&where_clause);
+            sql.push_str(&format!(" ORDER BY {}", order_by));
             
             // Pagination
             let limit = query.limit.unwrap_or(50).min(1000);
@@ -181,7 +260,7 @@ impl Database {
     pub fn get_book(&self, id: i64) -> AppResult<Book> {
         self.with_conn(|conn| {
             conn.query_row(
-                "SELECT b.*, r.rating, r.read_status 
+                "SELECT b.*, r.rating, r.read_status, r.notes, r.progress_percent, r.progress_location
                  FROM books b 
                  LEFT JOIN ratings r ON b.id = r.book_id
                  WHERE b.id = ?",
@@ -194,15 +273,19 @@ impl Database {
         })
     }
     
-    /// Get a book by path
+    /// Get a book by path (normalized the same way as stored paths, and
+    /// ignoring case where the filesystem does)
     pub fn get_book_by_path(&self, path: &str) -> AppResult<Option<Book>> {
         self.with_conn(|conn| {
             conn.query_row(
-                "SELECT b.*, r.rating, r.read_status 
-                 FROM books b 
-                 LEFT JOIN ratings r ON b.id = r.book_id
-                 WHERE b.path = ?",
-                [path],
+                &format!(
+                    "SELECT b.*, r.rating, r.read_status, r.notes, r.progress_percent, r.progress_location
+                     FROM books b
+                     LEFT JOIN ratings r ON b.id = r.book_id
+                     WHERE b.path = ? COLLATE {}",
+                    PATH_COLLATION
+                ),
+                [normalize_path(path)],
                 row_to_book,
             ).optional().map_err(AppError::Database)
         })
@@ -214,10 +297,10 @@ impl Database {
             conn.execute(
                 "INSERT INTO books (path, cover_path, file_size, file_hash, title, sort_title, 
                                    author, author_sort, series, series_index, description, 
-                                   language, publisher, publish_date, isbn, source)
-                 VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)",
+                                   language, publisher, publish_date, isbn, source, container_path, word_count, format)
+                 VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)",
                 params![
-                    book.path,
+                    normalize_path(&book.path),
                     book.cover_path,
                     book.file_size,
                     book.file_hash,
@@ -233,14 +316,24 @@ impl Database {
                     book.publish_date,
                     book.isbn,
                     book.source,
+                    book.container_path.as_deref().map(normalize_path),
+                    book.word_count,
+                    book.format.as_str(),
                 ],
             )?;
             
-            Ok(conn.last_insert_rowid())
+            let id = conn.last_insert_rowid();
+            insert_book_tags(conn, id, &book.tags)?;
+            insert_book_authors(conn, id, &book.authors)?;
+            Ok(id)
         })
     }
     
     /// Insert multiple books in a batch (for scanning)
+    ///
+    /// Books whose path is already in the database are skipped; the returned
+    /// ids are those of the newly inserted books only. A known book found
+    /// under a differently-cased path takes the new spelling.
     pub fn insert_books_batch(&self, books: &[NewBook]) -> AppResult<Vec<i64>> {
         let mut conn = self.conn()?;
         let tx = conn.transaction()?;
@@ -248,16 +341,31 @@ impl Database {
         let mut ids = Vec::with_capacity(books.len());
         
         {
+            let mut existing =
+                tx.prepare(&format!("SELECT id, path FROM books WHERE path = ? COLLATE {}", PATH_COLLATION))?;
+            let mut respell = tx.prepare("UPDATE books SET path = ? WHERE id = ?")?;
             let mut stmt = tx.prepare(
                 "INSERT OR IGNORE INTO books (path, cover_path, file_size, file_hash, title, sort_title, 
                                               author, author_sort, series, series_index, description, 
-                                              language, publisher, publish_date, isbn, source)
-                 VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)"
+                                              language, publisher, publish_date, isbn, source, container_path, word_count, format)
+                 VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)"
             )?;
             
             for book in books {
-                stmt.execute(params![
-                    book.path,
+                let path = normalize_path(&book.path);
+                let known = existing
+                    .query_row([&path], |row| Ok((row.get::<_, i64>(0)?, row.get::<_, String>(1)?)))
+                    .optional()?;
+                if let Some((id, stored)) = known {
+                    // Older versions stored lowercased paths on macOS and Windows
+                    if stored != path {
+                        respell.execute(params![path, id])?;
+                    }
+                    continue;
+                }
+
+                let inserted = stmt.execute(params![
+                    path,
                     book.cover_path,
                     book.file_size,
                     book.file_hash,
@@ -273,8 +381,17 @@ impl Database {
                     book.publish_date,
                     book.isbn,
                     book.source,
+                    book.container_path.as_deref().map(normalize_path),
+                    book.word_count,
+                    book.format.as_str(),
                 ])?;
-                ids.push(tx.last_insert_rowid());
+                // `INSERT OR IGNORE` changes nothing for a duplicate path
+                if inserted > 0 {
+                    let id = tx.last_insert_rowid();
+                    insert_book_tags(&tx, id, &book.tags)?;
+                    insert_book_authors(&tx, id, &book.authors)?;
+                    ids.push(id);
+                }
             }
         }
         
@@ -282,6 +399,237 @@ impl Database {
         Ok(ids)
     }
     
+    /// Fill in missing `sort_title`/`author_sort` values from title and author
+    ///
+    /// Existing sort fields (e.g. curated in Calibre) are left untouched.
+    /// Returns the number of books updated.
+    pub fn regenerate_sort_fields(&self) -> AppResult<usize> {
+        let mut conn = self.conn()?;
+        let tx = conn.transaction()?;
+
+        let rows: Vec<(i64, String, Option<String>)> = tx
+            .prepare(
+                "SELECT id, title, author, sort_title, author_sort FROM books
+                 WHERE sort_title IS NULL OR (author_sort IS NULL AND author IS NOT NULL)"
+            )?
+            .query_map([], |row| {
+                let title: String = row.get(1)?;
+                let author: Option<String> = row.get(2)?;
+                let sort_title: Option<String> = row.get(3)?;
+                let author_sort: Option<String> = row.get(4)?;
+                Ok((
+                    row.get(0)?,
+                    sort_title.unwrap_or_else(|| generate_sort_title(&title)),
+                    author_sort.or_else(|| author.as_deref().map(generate_author_sort)),
+                ))
+            })?
+            .collect::<Result<_, _>>()?;
+
+        for (id, sort_title, author_sort) in &rows {
+            tx.execute(
+                "UPDATE books SET sort_title = ?, author_sort = ? WHERE id = ?",
+                params![sort_title, author_sort, id],
+            )?;
+        }
+
+        tx.commit()?;
+        Ok(rows.len())
+    }
+
+    /// Get `(id, path)` of books that have no file hash, under a library
+    /// root or anywhere
+    pub fn get_unhashed_books(&self, library_path: Option<&str>) -> AppResult<Vec<(i64, String)>> {
+        self.with_conn(|conn| {
+            let mut stmt = conn.prepare(&format!(
+                "SELECT id, path FROM books
+                 WHERE file_hash IS NULL AND (?1 IS NULL OR {})
+                 ORDER BY id",
+                path_under("path", "?1")
+            ))?;
+            let books = stmt.query_map([library_path.map(normalize_path)], |row| Ok((row.get(0)?, row.get(1)?)))?
+                .collect::<Result<Vec<_>, _>>()?;
+            Ok(books)
+        })
+    }
+
+    /// Get the IDs of all books under a library path
+    pub fn get_library_book_ids(&self, library_path: &str) -> AppResult<Vec<i64>> {
+        self.with_conn(|conn| {
+            let mut stmt =
+                conn.prepare(&format!("SELECT id FROM books WHERE {} ORDER BY id", path_under("path", "?1")))?;
+            let ids = stmt.query_map([normalize_path(library_path)], |row| row.get(0))?
+                .collect::<Result<Vec<i64>, _>>()?;
+            Ok(ids)
+        })
+    }
+
+    /// Store file hashes in a single transaction
+    pub fn set_file_hashes_batch(&self, hashes: &[(i64, String)]) -> AppResult<()> {
+        let mut conn = self.conn()?;
+        let tx = conn.transaction()?;
+
+        {
+            let mut stmt = tx.prepare("UPDATE books SET file_hash = ? WHERE id = ?")?;
+            for (book_id, hash) in hashes {
+                stmt.execute(params![hash, book_id])?;
+            }
+        }
+
+        tx.commit()?;
+        Ok(())
+    }
+
+    /// Get tag names for a book
+    pub fn get_book_tags(&self, book_id: i64) -> AppResult<Vec<String>> {
+        self.with_conn(|conn| {
+            let mut stmt = conn.prepare(
+                "SELECT t.name FROM book_tags bt
+                 JOIN tags t ON t.id = bt.tag_id
+                 WHERE bt.book_id = ?
+                 ORDER BY t.name"
+            )?;
+            let tags = stmt.query_map([book_id], |row| row.get(0))?
+                .collect::<Result<Vec<String>, _>>()?;
+            Ok(tags)
+        })
+    }
+
+    /// Get tag sets keyed by book, for `book_ids` or every tagged book
+    ///
+    /// Untagged books are left out.
+    pub fn get_tag_sets(&self, book_ids: Option<&[i64]>) -> AppResult<HashMap<i64, HashSet<String>>> {
+        self.with_conn(|conn| {
+            let mut sql = String::from(
+                "SELECT bt.book_id, t.name FROM book_tags bt
+                 JOIN tags t ON t.id = bt.tag_id"
+            );
+            let ids = book_ids.unwrap_or(&[]);
+            if book_ids.is_some() {
+                let placeholders = vec!["?"; ids.len()].join(", ");
+                sql.push_str(&format!(" WHERE bt.book_id IN ({})", placeholders));
+            }
+
+            let mut stmt = conn.prepare(&sql)?;
+            let mut rows = stmt.query(rusqlite::params_from_iter(ids))?;
+            let mut tag_sets: HashMap<i64, HashSet<String>> = HashMap::new();
+            while let Some(row) = rows.next()? {
+                tag_sets.entry(row.get(0)?).or_default().insert(row.get(1)?);
+            }
+            Ok(tag_sets)
+        })
+    }
+
+    /// Get the tags two books share, by name
+    pub fn get_shared_tags(&self, book_a: i64, book_b: i64) -> AppResult<Vec<String>> {
+        self.with_conn(|conn| {
+            let mut stmt = conn.prepare(
+                "SELECT t.name FROM book_tags a
+                 JOIN book_tags b ON b.tag_id = a.tag_id
+                 JOIN tags t ON t.id = a.tag_id
+                 WHERE a.book_id = ? AND b.book_id = ?
+                 ORDER BY t.name"
+            )?;
+            let tags = stmt.query_map([book_a, book_b], |row| row.get(0))?
+                .collect::<Result<Vec<String>, _>>()?;
+            Ok(tags)
+        })
+    }
+
+    /// Replace a book's tags, creating tags as needed
+    pub fn set_book_tags(&self, book_id: i64, tags: &[String]) -> AppResult<()> {
+        let mut conn = self.conn()?;
+        let tx = conn.transaction()?;
+
+        tx.execute("DELETE FROM book_tags WHERE book_id = ?", [book_id])?;
+        insert_book_tags(&tx, book_id, tags)?;
+
+        tx.commit()?;
+        Ok(())
+    }
+
+    /// `calibre_id -> (book id, date_modified)` for linked books under a
+    /// library root
+    pub fn get_calibre_links(&self, library_path: &str) -> AppResult<HashMap<i64, (i64, i64)>> {
+        self.with_conn(|conn| {
+            let mut stmt = conn.prepare(&format!(
+                "SELECT calibre_id, id, date_modified FROM books
+                 WHERE calibre_id IS NOT NULL AND {}",
+                path_under("path", "?1")
+            ))?;
+            let links = stmt
+                .query_map([normalize_path(library_path)], |row| Ok((row.get(0)?, (row.get(1)?, row.get(2)?))))?
+                .collect::<Result<_, _>>()?;
+            Ok(links)
+        })
+    }
+
+    /// Link books to their Calibre ids in a single transaction
+    pub fn link_calibre_books(&self, links: &[(i64, i64)]) -> AppResult<()> {
+        let mut conn = self.conn()?;
+        let tx = conn.transaction()?;
+
+        {
+            let mut stmt = tx.prepare("UPDATE books SET calibre_id = ?, source = 'calibre' WHERE id = ?")?;
+            for (book_id, calibre_id) in links {
+                stmt.execute(params![calibre_id, book_id])?;
+            }
+        }
+
+        tx.commit()?;
+        Ok(())
+    }
+
+    /// Overwrite a book's metadata, tags and authors with a Calibre book's,
+    /// linking the two
+    ///
+    /// Ratings, read status and notes live elsewhere and are kept. A changed
+    /// description queues the book for embedding again.
+    pub fn update_from_calibre(&self, book_id: i64, calibre_id: i64, book: &NewBook) -> AppResult<()> {
+        let mut conn = self.conn()?;
+        let tx = conn.transaction()?;
+
+        let description: Option<String> = tx
+            .query_row("SELECT description FROM books WHERE id = ?", [book_id], |row| row.get(0))
+            .map_err(|e| match e {
+                rusqlite::Error::QueryReturnedNoRows => AppError::NotFound(format!("Book {} not found", book_id)),
+                e => AppError::Database(e),
+            })?;
+        tx.execute(
+            "UPDATE books SET
+                title = ?, sort_title = ?, author = ?, author_sort = ?, series = ?, series_index = ?,
+                description = ?, language = ?, publisher = ?, publish_date = ?, isbn = ?,
+                calibre_id = ?, source = 'calibre', date_modified = strftime('%s', 'now')
+             WHERE id = ?",
+            params![book.title, book.sort_title, book.author, book.author_sort, book.series, book.series_index,
+                    book.description, book.language, book.publisher, book.publish_date, book.isbn,
+                    calibre_id, book_id],
+        )?;
+        if description != book.description {
+            let status = if book.description.is_some() { "pending" } else { "no_description" };
+            tx.execute("UPDATE books SET embedding_status = ? WHERE id = ?", params![status, book_id])?;
+        }
+
+        tx.execute("DELETE FROM book_tags WHERE book_id = ?", [book_id])?;
+        insert_book_tags(&tx, book_id, &book.tags)?;
+        tx.execute("DELETE FROM book_authors WHERE book_id = ?", [book_id])?;
+        insert_book_authors(&tx, book_id, &book.authors)?;
+
+        tx.commit()?;
+        Ok(())
+    }
+
+    /// Replace the people credited on a book, creating authors as needed
+    pub fn set_book_authors(&self, book_id: i64, authors: &[NewAuthor]) -> AppResult<()> {
+        let mut conn = self.conn()?;
+        let tx = conn.transaction()?;
+
+        tx.execute("DELETE FROM book_authors WHERE book_id = ?", [book_id])?;
+        insert_book_authors(&tx, book_id, authors)?;
+
+        tx.commit()?;
+        Ok(())
+    }
+
     /// Update a book
     pub fn update_book(&self, id: i64, updates: &BookUpdate) -> AppResult<()> {
         self.with_conn(|conn| {
@@ -324,6 +672,22 @@ impl Database {
         })
     }
     
+    /// Point a book at its file's new location, keeping everything else
+    ///
+    /// The path is normalized like inserted ones, so lookups find it.
+    pub fn update_book_path(&self, id: i64, path: &str) -> AppResult<()> {
+        self.with_conn(|conn| {
+            let updated = conn.execute(
+                "UPDATE books SET path = ?, date_modified = strftime('%s', 'now') WHERE id = ?",
+                params![normalize_path(path), id],
+            )?;
+            if updated == 0 {
+                return Err(AppError::NotFound(format!("Book {} not found", id)));
+            }
+            Ok(())
+        })
+    }
+
     /// Delete a book
     pub fn delete_book(&self, id: i64) -> AppResult<()> {
         self.with_conn(|conn| {
@@ -332,71 +696,478 @@ impl Database {
         })
     }
     
-    // ============================================
-    // RATINGS OPERATIONS
-    // ============================================
-    
-    /// Set book rating
-    pub fn set_rating(&self, book_id: i64, rating: i32) -> AppResult<()> {
+    /// Books added or changed at or after `since` (unix seconds)
+    ///
+    /// Rating, status and note changes count as modifications.
+    pub fn get_books_modified_since(&self, since: i64) -> AppResult<SyncDelta<Book>> {
         self.with_conn(|conn| {
-            conn.execute(
-                "INSERT INTO ratings (book_id, rating, date_rated) 
-                 VALUES (?, ?, strftime('%s', 'now'))
-                 ON CONFLICT(book_id) DO UPDATE SET rating = ?, date_rated = strftime('%s', 'now')",
-                params![book_id, rating, rating],
+            let server_time: i64 =
+                conn.query_row("SELECT CAST(strftime('%s', 'now') AS INTEGER)", [], |row| row.get(0))?;
+
+            let mut stmt = conn.prepare(
+                "SELECT b.*, r.rating, r.read_status, r.notes, r.progress_percent, r.progress_location
+                 FROM books b
+                 LEFT JOIN ratings r ON b.id = r.book_id
+                 WHERE b.date_modified >= ?1 OR r.date_rated >= ?1
+                 ORDER BY b.date_modified, b.id"
             )?;
-            Ok(())
+            let items = stmt.query_map([since], row_to_book)?
+                .collect::<Result<Vec<_>, _>>()?;
+
+            Ok(SyncDelta { items, server_time })
         })
     }
-    
-    /// Set read status
-    pub fn set_read_status(&self, book_id: i64, status: &str) -> AppResult<()> {
+
+    /// Books deleted at or after `since` (unix seconds)
+    pub fn get_deleted_since(&self, since: i64) -> AppResult<SyncDelta<DeletedBook>> {
         self.with_conn(|conn| {
-            conn.execute(
-                "INSERT INTO ratings (book_id, read_status, date_rated) 
-                 VALUES (?, ?, strftime('%s', 'now'))
-                 ON CONFLICT(book_id) DO UPDATE SET read_status = ?, date_rated = strftime('%s', 'now')",
-                params![book_id, status, status],
+            let server_time: i64 =
+                conn.query_row("SELECT CAST(strftime('%s', 'now') AS INTEGER)", [], |row| row.get(0))?;
+
+            let mut stmt = conn.prepare(
+                "SELECT book_id, path, deleted_at FROM deleted_books
+                 WHERE deleted_at >= ?
+                 ORDER BY deleted_at, book_id"
             )?;
-            Ok(())
+            let items = stmt.query_map([since], |row| {
+                Ok(DeletedBook {
+                    book_id: row.get(0)?,
+                    path: row.get(1)?,
+                    deleted_at: row.get(2)?,
+                })
+            })?.collect::<Result<Vec<_>, _>>()?;
+
+            Ok(SyncDelta { items, server_time })
         })
     }
-    
+
     // ============================================
-    // GRAPH OPERATIONS
+    // DUPLICATE EDITIONS
     // ============================================
-    
-    /// Insert or update a graph edge
-    pub fn upsert_edge(&self, edge: &BookEdge) -> AppResult<()> {
+
+    /// Group books that are logically the same work stored as different files
+    ///
+    /// Books are linked by normalized ISBN first, then by normalized
+    /// title + author; groups are the connected components of those links.
+    pub fn find_duplicate_editions(&self) -> AppResult<Vec<DuplicateGroup>> {
+        let books = self.all_books_with_ratings()?;
+
+        let mut by_isbn: HashMap<String, usize> = HashMap::new();
+        let mut isbn_links = Vec::new();
+        for (i, book) in books.iter().enumerate() {
+            if let Some(isbn) = book.isbn.as_deref().and_then(normalize_isbn) {
+                match by_isbn.get(&isbn) {
+                    Some(&first) => isbn_links.push((first, i)),
+                    None => {
+                        by_isbn.insert(isbn, i);
+                    }
+                }
+            }
+        }
+
+        Ok(cluster_duplicates(books, "isbn", &isbn_links))
+    }
+
+    /// Group likely duplicate copies of the same book
+    ///
+    /// Books are linked by identical file hash first, then by normalized
+    /// title + author. Books that haven't been hashed yet can only match on
+    /// title + author.
+    pub fn find_duplicates(&self) -> AppResult<Vec<DuplicateGroup>> {
+        let books = self.all_books_with_ratings()?;
+        let position: HashMap<i64, usize> = books.iter().enumerate().map(|(i, b)| (b.id, i)).collect();
+
+        let mut hash_links = Vec::new();
+        for group in self.books_grouped_by_hash()? {
+            let members: Vec<usize> = group.iter().filter_map(|id| position.get(id).copied()).collect();
+            if let Some((&first, rest)) = members.split_first() {
+                hash_links.extend(rest.iter().map(|&i| (first, i)));
+            }
+        }
+
+        Ok(cluster_duplicates(books, "fileHash", &hash_links))
+    }
+
+    /// IDs of books sharing a file hash, one group per hash held by several books
+    pub fn books_grouped_by_hash(&self) -> AppResult<Vec<Vec<i64>>> {
         self.with_conn(|conn| {
-            conn.execute(
-                "INSERT INTO book_edges (source_id, target_id, edge_type, weight, model_version)
-                 VALUES (?, ?, ?, ?, ?)
-                 ON CONFLICT(source_id, target_id, edge_type) DO UPDATE SET 
-                    weight = ?, computed_at = strftime('%s', 'now'), model_version = ?",
-                params![
-                    edge.source_id, edge.target_id, edge.edge_type, edge.weight, edge.model_version,
-                    edge.weight, edge.model_version
-                ],
+            let mut stmt = conn.prepare(
+                "SELECT file_hash, id FROM books
+                 WHERE file_hash IN (
+                     SELECT file_hash FROM books
+                     WHERE file_hash IS NOT NULL
+                     GROUP BY file_hash HAVING COUNT(*) > 1
+                 )
+                 ORDER BY file_hash, id"
             )?;
-            Ok(())
+            let rows = stmt.query_map([], |row| Ok((row.get::<_, String>(0)?, row.get::<_, i64>(1)?)))?
+                .collect::<Result<Vec<_>, _>>()?;
+
+            let mut groups: Vec<Vec<i64>> = Vec::new();
+            let mut last_hash: Option<String> = None;
+            for (hash, id) in rows {
+                if last_hash.as_ref() != Some(&hash) {
+                    groups.push(Vec::new());
+                    last_hash = Some(hash);
+                }
+                groups.last_mut().expect("group pushed above").push(id);
+            }
+            groups.sort_by_key(|group| group[0]);
+            Ok(groups)
         })
     }
-    
-    /// Get edges for a book
-    pub fn get_edges(&self, book_id: i64, min_weight: f64) -> AppResult<Vec<BookEdge>> {
+
+    /// Every book with its rating data, in ID order
+    fn all_books_with_ratings(&self) -> AppResult<Vec<Book>> {
         self.with_conn(|conn| {
             let mut stmt = conn.prepare(
-                "SELECT source_id, target_id, edge_type, weight, computed_at, model_version
-                 FROM book_edges
-                 WHERE (source_id = ? OR target_id = ?) AND weight >= ?
-                 ORDER BY weight DESC"
+                "SELECT b.*, r.rating, r.read_status, r.notes, r.progress_percent, r.progress_location
+                 FROM books b
+                 LEFT JOIN ratings r ON b.id = r.book_id
+                 ORDER BY b.id"
             )?;
-            
-            let edges = stmt.query_map(params![book_id, book_id, min_weight], |row| {
-                Ok(BookEdge {
-                    source_id: row.get(0)?,
-                    target_id: row.get(1)?,
+            let books = stmt.query_map([], row_to_book)?
+                .collect::<Result<Vec<_>, _>>()?;
+            Ok(books)
+        })
+    }
+
+    /// Merge duplicate editions into the kept book
+    ///
+    /// Rating, read status and notes missing on the kept book are taken from
+    /// the duplicates, which are then removed from the database (files are
+    /// not deleted). Returns the number of books removed.
+    pub fn merge_duplicate_editions(&self, keep_id: i64, duplicate_ids: &[i64]) -> AppResult<usize> {
+        let mut conn = self.conn()?;
+        let tx = conn.transaction()?;
+
+        tx.execute("INSERT OR IGNORE INTO ratings (book_id) VALUES (?)", [keep_id])?;
+        let mut removed = 0;
+        for &id in duplicate_ids.iter().filter(|&&id| id != keep_id) {
+            tx.execute(
+                "UPDATE ratings SET
+                    rating = COALESCE(rating, (SELECT rating FROM ratings WHERE book_id = ?1)),
+                    read_status = CASE
+                        WHEN read_status IS NULL OR read_status = 'unread'
+                        THEN COALESCE((SELECT read_status FROM ratings WHERE book_id = ?1), read_status)
+                        ELSE read_status END,
+                    notes = COALESCE(notes, (SELECT notes FROM ratings WHERE book_id = ?1))
+                 WHERE book_id = ?2",
+                params![id, keep_id],
+            )?;
+            removed += tx.execute("DELETE FROM books WHERE id = ?", [id])?;
+        }
+
+        tx.commit()?;
+        Ok(removed)
+    }
+
+    // ============================================
+    // RATINGS OPERATIONS
+    // ============================================
+    
+    /// Set book rating (0.5 to 5 stars in half-star steps)
+    pub fn set_rating(&self, book_id: i64, rating: f64) -> AppResult<()> {
+        if !super::is_valid_rating(rating) {
+            return Err(AppError::InvalidInput(
+                "Rating must be between 0.5 and 5 in steps of 0.5".to_string(),
+            ));
+        }
+        self.with_conn(|conn| {
+            conn.execute(UPSERT_RATING_SQL, params![book_id, rating])?;
+            Ok(())
+        })
+    }
+
+    /// Give several books the same rating in a single transaction
+    ///
+    /// The rating is validated once; IDs of books that don't exist are
+    /// skipped. Returns the number of books rated.
+    pub fn set_ratings_batch(&self, book_ids: &[i64], rating: f64) -> AppResult<usize> {
+        if !super::is_valid_rating(rating) {
+            return Err(AppError::InvalidInput(
+                "Rating must be between 0.5 and 5 in steps of 0.5".to_string(),
+            ));
+        }
+        self.upsert_ratings_batch(book_ids, UPSERT_RATING_SQL, &rating)
+    }
+    
+    /// Set read status
+    ///
+    /// Starting a book stamps `date_started` and finishing it stamps
+    /// `date_finished`; re-reading a finished book starts both afresh.
+    pub fn set_read_status(&self, book_id: i64, status: &str) -> AppResult<()> {
+        self.with_conn(|conn| {
+            conn.execute(UPSERT_READ_STATUS_SQL, params![book_id, status])?;
+            Ok(())
+        })
+    }
+
+    /// Give several books the same read status in a single transaction
+    ///
+    /// Dates are stamped as by [`Database::set_read_status`]; IDs of books
+    /// that don't exist are skipped. Returns the number of books updated.
+    pub fn set_read_status_batch(&self, book_ids: &[i64], status: &str) -> AppResult<usize> {
+        if !READ_STATUSES.contains(&status) {
+            return Err(AppError::InvalidInput(format!(
+                "Invalid status. Must be one of: {:?}",
+                READ_STATUSES
+            )));
+        }
+        self.upsert_ratings_batch(book_ids, UPSERT_READ_STATUS_SQL, &status)
+    }
+
+    /// Run a `ratings` upsert taking `(book_id, value)` for each existing book
+    fn upsert_ratings_batch(&self, book_ids: &[i64], sql: &str, value: &dyn rusqlite::ToSql) -> AppResult<usize> {
+        let mut conn = self.conn()?;
+        let tx = conn.transaction()?;
+        let mut updated = 0;
+
+        {
+            let mut exists = tx.prepare("SELECT 1 FROM books WHERE id = ?")?;
+            let mut upsert = tx.prepare(sql)?;
+            let unique: HashSet<i64> = book_ids.iter().copied().collect();
+            for book_id in unique {
+                if exists.exists([book_id])? {
+                    updated += upsert.execute(params![book_id, value])?;
+                }
+            }
+        }
+
+        tx.commit()?;
+        Ok(updated)
+    }
+    
+    /// Set free-form notes for a book (empty or None clears them)
+    pub fn set_book_notes(&self, book_id: i64, notes: Option<&str>) -> AppResult<()> {
+        let notes = notes.map(str::trim).filter(|n| !n.is_empty());
+        self.with_conn(|conn| {
+            conn.execute(
+                "INSERT INTO ratings (book_id, notes, date_rated)
+                 VALUES (?, ?, strftime('%s', 'now'))
+                 ON CONFLICT(book_id) DO UPDATE SET notes = ?",
+                params![book_id, notes, notes],
+            )?;
+            Ok(())
+        })
+    }
+
+    /// Get `(word_count, seconds)` for finished books with a known length
+    /// and reading period
+    pub fn get_reading_speed_samples(&self) -> AppResult<Vec<(i64, i64)>> {
+        self.with_conn(|conn| {
+            let mut stmt = conn.prepare(
+                "SELECT b.word_count, r.date_finished - r.date_started
+                 FROM ratings r
+                 JOIN books b ON b.id = r.book_id
+                 WHERE r.read_status = 'finished'
+                   AND b.word_count > 0
+                   AND r.date_finished > r.date_started
+                 ORDER BY r.date_finished DESC"
+            )?;
+
+            let samples = stmt.query_map([], |row| Ok((row.get(0)?, row.get(1)?)))?
+                .collect::<Result<Vec<_>, _>>()?;
+
+            Ok(samples)
+        })
+    }
+
+    /// Recompute the user's reading speed from finished books and store it
+    /// as the `user_wpm` setting
+    ///
+    /// Leaves the setting alone until there are enough finished books.
+    pub fn update_user_wpm(&self) -> AppResult<Option<f64>> {
+        let wpm = crate::epub::personal_words_per_minute(&self.get_reading_speed_samples()?);
+        if let Some(wpm) = wpm {
+            self.update_setting("user_wpm", &wpm.to_string())?;
+        }
+        Ok(wpm)
+    }
+
+    /// Record reading progress (clamped to 0-100) and position, and stamp
+    /// the read time
+    ///
+    /// Any progress marks the book as reading, and 100% as finished. Returns
+    /// the read status if it changed.
+    pub fn set_reading_progress(
+        &self,
+        book_id: i64,
+        percent: f64,
+        location: Option<&str>,
+    ) -> AppResult<Option<&'static str>> {
+        let percent = percent.clamp(0.0, 100.0);
+        let mut conn = self.conn()?;
+        let tx = conn.transaction()?;
+
+        tx.execute(
+            "INSERT INTO ratings (book_id, progress_percent, progress_location, last_read_at)
+             VALUES (?1, ?2, ?3, strftime('%s', 'now'))
+             ON CONFLICT(book_id) DO UPDATE SET
+                progress_percent = ?2, progress_location = ?3, last_read_at = strftime('%s', 'now')",
+            params![book_id, percent, location],
+        )?;
+
+        let status = if percent >= 100.0 {
+            Some("finished")
+        } else if percent > 0.0 {
+            Some("reading")
+        } else {
+            None
+        };
+        let current: Option<String> = tx.query_row(
+            "SELECT read_status FROM ratings WHERE book_id = ?",
+            [book_id],
+            |row| row.get(0),
+        )?;
+        let changed = status.filter(|status| current.as_deref() != Some(*status));
+        if let Some(status) = changed {
+            tx.execute(UPSERT_READ_STATUS_SQL, params![book_id, status])?;
+        }
+
+        tx.commit()?;
+        Ok(changed)
+    }
+
+    /// Get books marked as reading, most recently read first
+    ///
+    /// Books without recorded progress fall back to when reading started or
+    /// the status was set.
+    pub fn get_currently_reading(&self, limit: i64) -> AppResult<Vec<CurrentlyReading>> {
+        self.with_conn(|conn| {
+            let mut stmt = conn.prepare(
+                "SELECT b.*, r.rating, r.read_status, r.notes, r.progress_percent, r.progress_location, r.last_read_at
+                 FROM books b
+                 JOIN ratings r ON b.id = r.book_id
+                 WHERE r.read_status = 'reading'
+                 ORDER BY COALESCE(r.last_read_at, r.date_started, r.date_rated) DESC, b.id
+                 LIMIT ?"
+            )?;
+
+            let books = stmt.query_map([limit], |row| {
+                Ok(CurrentlyReading {
+                    book: row_to_book(row)?,
+                    progress_percent: row.get(29)?,
+                    last_read_at: row.get(31)?,
+                })
+            })?.collect::<Result<Vec<_>, _>>()?;
+
+            Ok(books)
+        })
+    }
+
+    /// Search book notes, best matches first
+    pub fn search_book_notes(&self, query: &str, limit: i64) -> AppResult<Vec<Book>> {
+        let Some(query) = sanitize_fts_query(query, NOTES_FTS_COLUMNS) else {
+            return Ok(Vec::new());
+        };
+        self.with_conn(|conn| {
+            let mut stmt = conn.prepare(
+                "SELECT b.*, r.rating, r.read_status, r.notes, r.progress_percent, r.progress_location
+                 FROM notes_fts
+                 JOIN books b ON b.id = notes_fts.rowid
+                 JOIN ratings r ON r.book_id = b.id
+                 WHERE notes_fts MATCH ?
+                 ORDER BY notes_fts.rank
+                 LIMIT ?"
+            )?;
+
+            let books = stmt.query_map(params![query, limit], row_to_book)?
+                .collect::<Result<Vec<_>, _>>()?;
+
+            Ok(books)
+        })
+    }
+
+    /// Get rating/read-status signals used to personalize recommendations
+    pub fn get_preference_signals(&self) -> AppResult<Vec<PreferenceSignal>> {
+        self.get_preference_signals_since(i64::MIN)
+    }
+
+    /// Get IDs of books rated at or below `max_rating`
+    pub fn get_disliked_book_ids(&self, max_rating: f64) -> AppResult<Vec<i64>> {
+        self.with_conn(|conn| {
+            let mut stmt = conn.prepare(
+                "SELECT book_id FROM ratings
+                 WHERE rating IS NOT NULL AND rating <= ?
+                 ORDER BY book_id"
+            )?;
+            let ids = stmt.query_map([max_rating], |row| row.get(0))?
+                .collect::<Result<Vec<i64>, _>>()?;
+            Ok(ids)
+        })
+    }
+
+    /// Get preference signals rated or changed at or after `since` (unix seconds)
+    pub fn get_preference_signals_since(&self, since: i64) -> AppResult<Vec<PreferenceSignal>> {
+        self.with_conn(|conn| {
+            let mut stmt = conn.prepare(
+                "SELECT book_id, rating, read_status, date_rated
+                 FROM ratings
+                 WHERE (rating IS NOT NULL
+                        OR read_status IN ('finished', 'reading', 'abandoned'))
+                   AND date_rated >= ?
+                 ORDER BY date_rated DESC"
+            )?;
+
+            let signals = stmt.query_map([since], |row| {
+                Ok(PreferenceSignal {
+                    book_id: row.get(0)?,
+                    rating: row.get(1)?,
+                    read_status: row.get(2)?,
+                    updated_at: row.get(3)?,
+                })
+            })?.collect::<Result<Vec<_>, _>>()?;
+
+            Ok(signals)
+        })
+    }
+    
+    // ============================================
+    // GRAPH OPERATIONS
+    // ============================================
+    
+    /// Insert or update a graph edge
+    pub fn upsert_edge(&self, edge: &BookEdge) -> AppResult<()> {
+        self.with_conn(|conn| {
+            conn.execute(
+                "INSERT INTO book_edges (source_id, target_id, edge_type, weight, model_version)
+                 VALUES (?, ?, ?, ?, ?)
+                 ON CONFLICT(source_id, target_id, edge_type) DO UPDATE SET 
+                    weight = ?, computed_at = strftime('%s', 'now'), model_version = ?",
+                params![
+                    edge.source_id, edge.target_id, edge.edge_type, edge.weight, edge.model_version,
+                    edge.weight, edge.model_version
+                ],
+            )?;
+            Ok(())
+        })
+    }
+    
+    /// Get edges for a book
+    ///
+    /// `edge_types` restricts the result to those types; `None` returns all.
+    pub fn get_edges(&self, book_id: i64, min_weight: f64, edge_types: Option<&[String]>) -> AppResult<Vec<BookEdge>> {
+        self.with_conn(|conn| {
+            let mut sql = String::from(
+                "SELECT source_id, target_id, edge_type, weight, computed_at, model_version
+                 FROM book_edges
+                 WHERE (source_id = ? OR target_id = ?) AND weight >= ?"
+            );
+            let mut params_vec: Vec<&dyn rusqlite::ToSql> = vec![&book_id, &book_id, &min_weight];
+            if let Some(types) = edge_types {
+                let placeholders = vec!["?"; types.len()].join(", ");
+                sql.push_str(&format!(" AND edge_type IN ({})", placeholders));
+                params_vec.extend(types.iter().map(|t| t as &dyn rusqlite::ToSql));
+            }
+            // source_id + target_id orders by the other end, as this book is one of them
+            sql.push_str(" ORDER BY weight DESC, source_id + target_id, edge_type");
+
+            let mut stmt = conn.prepare(&sql)?;
+            let edges = stmt.query_map(params_vec.as_slice(), |row| {
+                Ok(BookEdge {
+                    source_id: row.get(0)?,
+                    target_id: row.get(1)?,
                     edge_type: row.get(2)?,
                     weight: row.get(3)?,
                     computed_at: row.get(4)?,
@@ -407,6 +1178,71 @@ impl Database {
             Ok(edges)
         })
     }
+
+    /// Get books with no edge of at least `min_weight` in either direction
+    ///
+    /// These are dead ends for recommendations, typically books that were
+    /// never embedded or have sparse metadata.
+    pub fn get_isolated_books(&self, min_weight: f64, limit: i64) -> AppResult<Vec<Book>> {
+        self.with_conn(|conn| {
+            let mut stmt = conn.prepare(
+                "SELECT b.*, r.rating, r.read_status, r.notes, r.progress_percent, r.progress_location
+                 FROM books b
+                 LEFT JOIN ratings r ON b.id = r.book_id
+                 WHERE NOT EXISTS (
+                     SELECT 1 FROM book_edges e
+                     WHERE (e.source_id = b.id OR e.target_id = b.id) AND e.weight >= ?1
+                 )
+                 ORDER BY b.id
+                 LIMIT ?2"
+            )?;
+
+            let books = stmt.query_map(params![min_weight, limit], row_to_book)?
+                .collect::<Result<Vec<_>, _>>()?;
+
+            Ok(books)
+        })
+    }
+
+    /// Get content edges between books by different authors
+    ///
+    /// Authors come from `book_authors`, falling back to `books.author` for
+    /// books without linked authors. A book with several authors yields one
+    /// link per author pair.
+    pub fn get_author_content_links(&self, min_weight: f64) -> AppResult<Vec<AuthorLink>> {
+        self.with_conn(|conn| {
+            let mut stmt = conn.prepare(
+                "WITH book_author(book_id, name) AS (
+                     SELECT ba.book_id, a.name
+                     FROM book_authors ba
+                     JOIN authors a ON a.id = ba.author_id
+                     UNION
+                     SELECT b.id, b.author
+                     FROM books b
+                     WHERE b.author IS NOT NULL AND b.author != ''
+                       AND NOT EXISTS (SELECT 1 FROM book_authors ba WHERE ba.book_id = b.id)
+                 )
+                 SELECT e.source_id, sa.name, e.target_id, ta.name, e.weight
+                 FROM book_edges e
+                 JOIN book_author sa ON sa.book_id = e.source_id
+                 JOIN book_author ta ON ta.book_id = e.target_id
+                 WHERE e.edge_type = 'content' AND e.weight >= ? AND sa.name != ta.name
+                 ORDER BY e.source_id, e.target_id"
+            )?;
+
+            let links = stmt.query_map([min_weight], |row| {
+                Ok(AuthorLink {
+                    source_book: row.get(0)?,
+                    source_author: row.get(1)?,
+                    target_book: row.get(2)?,
+                    target_author: row.get(3)?,
+                    weight: row.get(4)?,
+                })
+            })?.collect::<Result<Vec<_>, _>>()?;
+
+            Ok(links)
+        })
+    }
     
     // ============================================
     // SETTINGS OPERATIONS
@@ -431,6 +1267,43 @@ impl Database {
                     "max_recommendations" => settings.max_recommendations = value.parse().unwrap_or(20),
                     "auto_scan_enabled" => settings.auto_scan_enabled = value == "1",
                     "scan_interval_minutes" => settings.scan_interval_minutes = value.parse().unwrap_or(60),
+                    "similarity_metric" => settings.similarity_metric = value.parse().unwrap_or_default(),
+                    "scan_archives" => settings.scan_archives = value == "1",
+                    "ollama_requests_per_second" => {
+                        settings.ollama_requests_per_second = value
+                            .parse()
+                            .unwrap_or(crate::ollama::DEFAULT_REQUESTS_PER_SECOND)
+                    }
+                    "embedding_fields" => {
+                        settings.embedding_fields = serde_json::from_str(&value).unwrap_or_default()
+                    }
+                    "recent_reads_days" => settings.recent_reads_days = value.parse().unwrap_or(0),
+                    "cover_dir" => settings.cover_dir = value,
+                    "user_wpm" => settings.user_wpm = value.parse().unwrap_or(0.0),
+                    "edge_candidate_floor" => settings.edge_candidate_floor = value.parse().unwrap_or(0.3),
+                    "content_edge_floor" => {
+                        settings.content_edge_floor = value.parse().unwrap_or(crate::graph::DEFAULT_EDGE_FLOOR)
+                    }
+                    "enrichment_enabled" => settings.enrichment_enabled = value == "1",
+                    "enrichment_endpoint" => settings.enrichment_endpoint = value,
+                    "liked_rating_threshold" => {
+                        settings.liked_rating_threshold = value.parse().unwrap_or(super::DEFAULT_LIKED_RATING)
+                    }
+                    "edge_prune_enabled" => settings.edge_prune_enabled = value == "1",
+                    "edge_prune_max_per_book" => {
+                        settings.edge_prune_max_per_book =
+                            value.parse().unwrap_or(super::DEFAULT_EDGE_PRUNE_MAX_PER_BOOK)
+                    }
+                    "edge_prune_min_weight" => settings.edge_prune_min_weight = value.parse().unwrap_or(0.0),
+                    "similar_cache_size" => settings.similar_cache_size = value.parse().unwrap_or(0),
+                    "ann_threshold" => {
+                        settings.ann_threshold = value.parse().unwrap_or(crate::vector::DEFAULT_ANN_THRESHOLD)
+                    }
+                    "recommendation_timeout_ms" => {
+                        settings.recommendation_timeout_ms =
+                            value.parse().unwrap_or(super::DEFAULT_RECOMMENDATION_TIMEOUT_MS)
+                    }
+                    "db_pool_size" => settings.db_pool_size = value.parse().unwrap_or(super::DEFAULT_POOL_SIZE),
                     _ => {}
                 }
             }
@@ -473,10 +1346,32 @@ impl Database {
                 "UPDATE books SET embedding_status = 'pending', embedding_model = NULL, date_indexed = NULL",
                 [],
             )?;
+            // Every cached similarity was computed from the old embeddings
+            conn.execute("DELETE FROM similar_cache", [])?;
             Ok(updated as i64)
         })
     }
 
+    /// Reset embedding statuses to pending for the given books
+    pub fn reset_embedding_statuses(&self, book_ids: &[i64]) -> AppResult<i64> {
+        let mut conn = self.conn()?;
+        let tx = conn.transaction()?;
+
+        let mut updated = 0;
+        {
+            let mut stmt = tx.prepare(
+                "UPDATE books SET embedding_status = 'pending', embedding_model = NULL, date_indexed = NULL
+                 WHERE id = ?"
+            )?;
+            for book_id in book_ids {
+                updated += stmt.execute([book_id])? as i64;
+            }
+        }
+
+        tx.commit()?;
+        Ok(updated)
+    }
+
     /// Get books pending embedding generation
     pub fn get_pending_embedding_books(&self, limit: i64) -> AppResult<Vec<i64>> {
         self.with_conn(|conn| {
@@ -489,14 +1384,183 @@ impl Database {
         })
     }
 
+    /// Queue books in the durable embedding job queue
+    ///
+    /// A book already waiting keeps the higher of its two priorities; a
+    /// finished or failed job is queued again from scratch, and a running one
+    /// is left alone. Unknown book IDs are skipped. Returns the number of
+    /// books queued.
+    pub fn enqueue_embedding_jobs(&self, book_ids: &[i64], priority: i32) -> AppResult<usize> {
+        let mut conn = self.conn()?;
+        let tx = conn.transaction()?;
+        let mut queued = 0;
+
+        {
+            let mut stmt = tx.prepare(
+                "INSERT INTO embedding_jobs (book_id, priority)
+                 SELECT id, ?2 FROM books WHERE id = ?1
+                 ON CONFLICT(book_id) DO UPDATE SET
+                    priority = CASE WHEN status = 'pending' THEN MAX(priority, excluded.priority)
+                                    ELSE excluded.priority END,
+                    attempts = CASE WHEN status = 'pending' THEN attempts ELSE 0 END,
+                    created_at = CASE WHEN status = 'pending' THEN created_at ELSE strftime('%s', 'now') END,
+                    last_error = CASE WHEN status = 'pending' THEN last_error END,
+                    completed_at = NULL,
+                    status = 'pending'
+                 WHERE status != 'running'"
+            )?;
+            for book_id in book_ids {
+                queued += stmt.execute(params![book_id, priority])?;
+            }
+        }
+
+        tx.commit()?;
+        Ok(queued)
+    }
+
+    /// Take the next embedding job, highest priority then oldest first
+    ///
+    /// The job is marked running and its attempt counted; finish it with
+    /// [`Database::complete_embedding_job`], [`Database::fail_embedding_job`]
+    /// or [`Database::release_embedding_job`].
+    pub fn claim_embedding_job(&self) -> AppResult<Option<EmbeddingJob>> {
+        let mut conn = self.conn()?;
+        let tx = conn.transaction()?;
+
+        let book_id: Option<i64> = tx
+            .query_row(
+                "SELECT book_id FROM embedding_jobs WHERE status = 'pending'
+                 ORDER BY priority DESC, created_at, id LIMIT 1",
+                [],
+                |row| row.get(0),
+            )
+            .optional()?;
+        let Some(book_id) = book_id else {
+            return Ok(None);
+        };
+
+        tx.execute(
+            "UPDATE embedding_jobs SET status = 'running', attempts = attempts + 1,
+                started_at = strftime('%s', 'now')
+             WHERE book_id = ?",
+            [book_id],
+        )?;
+        let job = tx.query_row(
+            &format!("{} WHERE book_id = ?", EMBEDDING_JOB_SELECT),
+            [book_id],
+            row_to_embedding_job,
+        )?;
+
+        tx.commit()?;
+        Ok(Some(job))
+    }
+
+    /// Mark a book's embedding job done
+    pub fn complete_embedding_job(&self, book_id: i64) -> AppResult<()> {
+        self.with_conn(|conn| {
+            conn.execute(
+                "UPDATE embedding_jobs SET status = 'complete', last_error = NULL,
+                    completed_at = strftime('%s', 'now')
+                 WHERE book_id = ?",
+                [book_id],
+            )?;
+            Ok(())
+        })
+    }
+
+    /// Record a failed attempt at a book's embedding job
+    ///
+    /// The job goes back in the queue until it has been tried `max_attempts`
+    /// times, then stays failed until [`Database::retry_failed_embedding_jobs`].
+    /// Returns whether it will be retried.
+    pub fn fail_embedding_job(&self, book_id: i64, error: &str, max_attempts: u32) -> AppResult<bool> {
+        self.with_conn(|conn| {
+            conn.execute(
+                "UPDATE embedding_jobs SET last_error = ?2,
+                    status = CASE WHEN attempts >= ?3 THEN 'failed' ELSE 'pending' END,
+                    completed_at = CASE WHEN attempts >= ?3 THEN strftime('%s', 'now') END
+                 WHERE book_id = ?1",
+                params![book_id, error, max_attempts],
+            )?;
+            let retry = conn
+                .query_row("SELECT status = 'pending' FROM embedding_jobs WHERE book_id = ?", [book_id], |row| {
+                    row.get(0)
+                })
+                .optional()?;
+            Ok(retry.unwrap_or(false))
+        })
+    }
+
+    /// Count embedding jobs waiting to run
+    pub fn count_pending_embedding_jobs(&self) -> AppResult<usize> {
+        self.with_conn(|conn| {
+            let count: i64 =
+                conn.query_row("SELECT COUNT(*) FROM embedding_jobs WHERE status = 'pending'", [], |row| row.get(0))?;
+            Ok(count as usize)
+        })
+    }
+
+    /// Put a running job back in the queue without counting the attempt
+    pub fn release_embedding_job(&self, book_id: i64) -> AppResult<()> {
+        self.with_conn(|conn| {
+            conn.execute(
+                "UPDATE embedding_jobs SET status = 'pending', attempts = MAX(attempts - 1, 0)
+                 WHERE book_id = ? AND status = 'running'",
+                [book_id],
+            )?;
+            Ok(())
+        })
+    }
+
+    /// Return jobs left running by a worker that stopped mid-job to the
+    /// queue, returning how many there were
+    pub fn requeue_running_embedding_jobs(&self) -> AppResult<usize> {
+        self.with_conn(|conn| {
+            Ok(conn.execute("UPDATE embedding_jobs SET status = 'pending' WHERE status = 'running'", [])?)
+        })
+    }
+
+    /// Queue every failed embedding job again with a fresh set of attempts,
+    /// returning how many were queued
+    pub fn retry_failed_embedding_jobs(&self) -> AppResult<usize> {
+        let mut conn = self.conn()?;
+        let tx = conn.transaction()?;
+
+        tx.execute(
+            "UPDATE books SET embedding_status = 'pending'
+             WHERE id IN (SELECT book_id FROM embedding_jobs WHERE status = 'failed')",
+            [],
+        )?;
+        let retried = tx.execute(
+            "UPDATE embedding_jobs SET status = 'pending', attempts = 0, completed_at = NULL
+             WHERE status = 'failed'",
+            [],
+        )?;
+
+        tx.commit()?;
+        Ok(retried)
+    }
+
+    /// Get a book's embedding job, if it has ever been queued
+    pub fn get_embedding_job(&self, book_id: i64) -> AppResult<Option<EmbeddingJob>> {
+        self.with_conn(|conn| {
+            Ok(conn
+                .query_row(&format!("{} WHERE book_id = ?", EMBEDDING_JOB_SELECT), [book_id], row_to_embedding_job)
+                .optional()?)
+        })
+    }
+
     /// Get books needing metadata parsing (no description, not failed/skipped)
+    ///
+    /// Books that have already hit transient failures come last.
     pub fn get_books_needing_metadata(&self, limit: i64) -> AppResult<Vec<(i64, String)>> {
         self.with_conn(|conn| {
             let mut stmt = conn.prepare(
-                "SELECT id, path FROM books
-                 WHERE (description IS NULL OR description = '')
-                 AND (embedding_status IS NULL OR embedding_status = '')
-                 ORDER BY date_added DESC
+                "SELECT b.id, b.path FROM books b
+                 LEFT JOIN metadata_retries m ON m.book_id = b.id
+                 WHERE (b.description IS NULL OR b.description = '')
+                 AND COALESCE(b.embedding_status, '') IN ('', 'pending', 'needs_metadata')
+                 ORDER BY COALESCE(m.attempts, 0), b.date_added DESC
                  LIMIT ?"
             )?;
             let results = stmt.query_map([limit], |row| {
@@ -506,6 +1570,81 @@ impl Database {
         })
     }
 
+    /// Count a transient metadata parse failure; returns attempts so far
+    pub fn record_metadata_retry(&self, book_id: i64, error: &str) -> AppResult<i64> {
+        self.with_conn(|conn| {
+            let attempts = conn.query_row(
+                "INSERT INTO metadata_retries (book_id, attempts, last_error)
+                 VALUES (?1, 1, ?2)
+                 ON CONFLICT(book_id) DO UPDATE SET
+                    attempts = attempts + 1, last_error = ?2, last_attempt_at = strftime('%s', 'now')
+                 RETURNING attempts",
+                params![book_id, error],
+                |row| row.get(0),
+            )?;
+            Ok(attempts)
+        })
+    }
+
+    /// Get books with an ISBN but no description that `source` hasn't looked up yet
+    pub fn get_books_needing_enrichment(&self, source: &str, limit: i64) -> AppResult<Vec<(i64, String)>> {
+        self.with_conn(|conn| {
+            let mut stmt = conn.prepare(
+                "SELECT b.id, b.isbn FROM books b
+                 WHERE b.isbn IS NOT NULL AND TRIM(b.isbn) != ''
+                 AND (b.description IS NULL OR b.description = '')
+                 AND NOT EXISTS (
+                    SELECT 1 FROM book_enrichment e WHERE e.book_id = b.id AND e.source = ?1
+                 )
+                 ORDER BY b.date_added DESC
+                 LIMIT ?2"
+            )?;
+            let results = stmt.query_map(params![source, limit], |row| {
+                Ok((row.get::<_, i64>(0)?, row.get::<_, String>(1)?.trim().to_string()))
+            })?.collect::<Result<Vec<_>, _>>()?;
+            Ok(results)
+        })
+    }
+
+    /// Record an enrichment lookup, replacing any earlier one for the book
+    pub fn record_enrichment(&self, book_id: i64, source: &str, found: bool) -> AppResult<()> {
+        self.with_conn(|conn| {
+            conn.execute(
+                "INSERT OR REPLACE INTO book_enrichment (book_id, source, found) VALUES (?, ?, ?)",
+                params![book_id, source, found],
+            )?;
+            Ok(())
+        })
+    }
+
+    /// Source that filled in a book's metadata, if any
+    pub fn get_enrichment_source(&self, book_id: i64) -> AppResult<Option<String>> {
+        self.with_conn(|conn| {
+            let source = conn.query_row(
+                "SELECT source FROM book_enrichment WHERE book_id = ? AND found = 1",
+                [book_id],
+                |row| row.get(0),
+            ).optional()?;
+            Ok(source)
+        })
+    }
+
+    /// Requeue books skipped during metadata parsing and forget their retry counts
+    pub fn retry_skipped_metadata(&self) -> AppResult<i64> {
+        let mut conn = self.conn()?;
+        let tx = conn.transaction()?;
+
+        let requeued = tx.execute(
+            "UPDATE books SET embedding_status = NULL
+             WHERE embedding_status = 'skipped' AND (description IS NULL OR description = '')",
+            [],
+        )?;
+        tx.execute("DELETE FROM metadata_retries", [])?;
+
+        tx.commit()?;
+        Ok(requeued as i64)
+    }
+
     /// Get all book IDs and paths for cleanup checking
     pub fn get_all_book_paths(&self) -> AppResult<Vec<(i64, String)>> {
         self.with_conn(|conn| {
@@ -517,7 +1656,86 @@ impl Database {
         })
     }
 
+    /// Get books with no known cover image, oldest first
+    pub fn get_books_without_cover(&self, limit: i64) -> AppResult<Vec<Book>> {
+        self.with_conn(|conn| {
+            let mut stmt = conn.prepare(
+                "SELECT b.*, r.rating, r.read_status, r.notes, r.progress_percent, r.progress_location
+                 FROM books b
+                 LEFT JOIN ratings r ON b.id = r.book_id
+                 WHERE b.cover_path IS NULL OR b.cover_path = ''
+                 ORDER BY b.id
+                 LIMIT ?"
+            )?;
+
+            let books = stmt.query_map([limit], row_to_book)?
+                .collect::<Result<Vec<_>, _>>()?;
+
+            Ok(books)
+        })
+    }
+
+    /// Ids of books with an ISBN, keyed by the normalized 13-digit ISBN
+    pub fn get_book_ids_by_isbn(&self) -> AppResult<HashMap<String, Vec<i64>>> {
+        self.with_conn(|conn| {
+            let mut stmt = conn.prepare(
+                "SELECT id, isbn FROM books WHERE isbn IS NOT NULL AND TRIM(isbn) != '' ORDER BY id"
+            )?;
+            let rows = stmt.query_map([], |row| Ok((row.get::<_, i64>(0)?, row.get::<_, String>(1)?)))?;
+
+            let mut by_isbn: HashMap<String, Vec<i64>> = HashMap::new();
+            for row in rows {
+                let (id, isbn) = row?;
+                if let Some(isbn) = normalize_isbn(&isbn) {
+                    by_isbn.entry(isbn).or_default().push(id);
+                }
+            }
+            Ok(by_isbn)
+        })
+    }
+
+    /// Set the cover image path for a book
+    pub fn set_cover_path(&self, id: i64, cover_path: Option<&str>) -> AppResult<()> {
+        self.with_conn(|conn| {
+            conn.execute(
+                "UPDATE books SET cover_path = ?, date_modified = strftime('%s', 'now') WHERE id = ?",
+                params![cover_path, id],
+            )?;
+            Ok(())
+        })
+    }
+
+    /// Store the word count computed while parsing a book
+    pub fn set_word_count(&self, id: i64, word_count: Option<i64>) -> AppResult<()> {
+        self.with_conn(|conn| {
+            conn.execute(
+                "UPDATE books SET word_count = ? WHERE id = ?",
+                params![word_count, id],
+            )?;
+            Ok(())
+        })
+    }
+
+    /// Get unread or want-to-read books with a known length of at most `max_words`
+    pub fn get_unread_books_under_words(&self, max_words: i64) -> AppResult<Vec<Book>> {
+        self.with_conn(|conn| {
+            let mut stmt = conn.prepare(
+                "SELECT b.*, r.rating, r.read_status, r.notes, r.progress_percent, r.progress_location
+                 FROM books b
+                 LEFT JOIN ratings r ON b.id = r.book_id
+                 WHERE b.word_count IS NOT NULL AND b.word_count <= ?
+                   AND COALESCE(r.read_status, 'unread') IN ('unread', 'want')"
+            )?;
+
+            let books = stmt.query_map([max_words], row_to_book)?
+                .collect::<Result<Vec<_>, _>>()?;
+
+            Ok(books)
+        })
+    }
+
     /// Update book metadata from EPUB parsing
+    #[allow(clippy::too_many_arguments)]
     pub fn update_book_metadata(
         &self,
         id: i64,
@@ -554,6 +1772,64 @@ impl Database {
         })
     }
 
+    /// Store parsed metadata for a batch of books in one transaction
+    ///
+    /// Fields the parse found nothing for keep their current values. Subjects
+    /// only tag books that have no tags yet, and creators are only linked to
+    /// books with no linked authors, so Calibre or hand-made ones are kept.
+    /// Books with a description are queued for embedding; the rest are
+    /// marked `no_description`.
+    pub fn apply_parsed_metadata(&self, books: &[(i64, NewBook)]) -> AppResult<()> {
+        let mut conn = self.conn()?;
+        let tx = conn.transaction()?;
+
+        for (id, book) in books {
+            let status = if book.description.is_some() { "pending" } else { "no_description" };
+            tx.execute(
+                "UPDATE books SET
+                    title = ?,
+                    author = COALESCE(?, author),
+                    author_sort = COALESCE(?, author_sort),
+                    description = COALESCE(?, description),
+                    series = COALESCE(?, series),
+                    series_index = COALESCE(?, series_index),
+                    language = COALESCE(?, language),
+                    publisher = COALESCE(?, publisher),
+                    publish_date = COALESCE(?, publish_date),
+                    isbn = COALESCE(?, isbn),
+                    word_count = ?,
+                    embedding_status = ?,
+                    date_indexed = strftime('%s', 'now'),
+                    date_modified = strftime('%s', 'now')
+                 WHERE id = ?",
+                params![book.title, book.author, book.author_sort, book.description, book.series,
+                        book.series_index, book.language, book.publisher, book.publish_date, book.isbn,
+                        book.word_count, status, id],
+            )?;
+
+            let tagged: bool = tx.query_row(
+                "SELECT EXISTS(SELECT 1 FROM book_tags WHERE book_id = ?)",
+                [id],
+                |row| row.get(0),
+            )?;
+            if !tagged {
+                insert_book_tags(&tx, *id, &book.tags)?;
+            }
+
+            let authored: bool = tx.query_row(
+                "SELECT EXISTS(SELECT 1 FROM book_authors WHERE book_id = ?)",
+                [id],
+                |row| row.get(0),
+            )?;
+            if !authored {
+                insert_book_authors(&tx, *id, &book.authors)?;
+            }
+        }
+
+        tx.commit()?;
+        Ok(())
+    }
+
     /// Insert multiple edges in a batch
     pub fn insert_edges_batch(&self, edges: &[(i64, i64, String, f64)]) -> AppResult<()> {
         let mut conn = self.conn()?;
@@ -574,139 +1850,600 @@ impl Database {
         Ok(())
     }
 
-    // ============================================
-    // STATISTICS
-    // ============================================
-
-    // ============================================
-    // UP NEXT OPERATIONS
-    // ============================================
-
-    /// Get all books in the Up Next queue
-    pub fn get_up_next_books(&self) -> AppResult<Vec<Book>> {
+    /// Get ids of books whose embedding is complete and at `text_version`
+    pub fn get_current_embedding_book_ids(&self, text_version: i64) -> AppResult<Vec<i64>> {
         self.with_conn(|conn| {
             let mut stmt = conn.prepare(
-                "SELECT b.*, r.rating, r.read_status
-                 FROM books b
-                 LEFT JOIN ratings r ON b.id = r.book_id
-                 INNER JOIN up_next un ON b.id = un.book_id
-                 ORDER BY un.position ASC, un.added_at ASC"
+                "SELECT DISTINCT b.id FROM books b
+                 INNER JOIN embeddings e ON b.id = e.book_id
+                 WHERE b.embedding_status = 'complete' AND e.text_version = ?"
             )?;
-
-            let books = stmt.query_map([], row_to_book)?
-                .collect::<Result<Vec<_>, _>>()?;
-
-            Ok(books)
+            let ids = stmt.query_map([text_version], |row| row.get(0))?
+                .collect::<Result<Vec<i64>, _>>()?;
+            Ok(ids)
         })
     }
 
-    /// Add a book to the Up Next queue
-    pub fn add_to_up_next(&self, book_id: i64) -> AppResult<()> {
+    /// Get ids of books with a current embedding but no edges in either direction
+    pub fn get_unlinked_embedded_book_ids(&self, text_version: i64) -> AppResult<Vec<i64>> {
         self.with_conn(|conn| {
-            // Get the next position (max + 1)
-            let next_position: i64 = conn
-                .query_row(
-                    "SELECT COALESCE(MAX(position), -1) + 1 FROM up_next",
-                    [],
-                    |row| row.get(0),
-                )
-                .unwrap_or(0);
-
-            conn.execute(
-                "INSERT OR IGNORE INTO up_next (book_id, position) VALUES (?, ?)",
-                params![book_id, next_position],
+            let mut stmt = conn.prepare(
+                "SELECT DISTINCT b.id FROM books b
+                 INNER JOIN embeddings e ON b.id = e.book_id
+                 WHERE b.embedding_status = 'complete' AND e.text_version = ?
+                 AND NOT EXISTS (
+                     SELECT 1 FROM book_edges be WHERE be.source_id = b.id OR be.target_id = b.id
+                 )
+                 ORDER BY b.id"
             )?;
-            Ok(())
+            let ids = stmt.query_map([text_version], |row| row.get(0))?
+                .collect::<Result<Vec<i64>, _>>()?;
+            Ok(ids)
         })
     }
 
-    /// Remove a book from the Up Next queue
-    pub fn remove_from_up_next(&self, book_id: i64) -> AppResult<()> {
+    /// Counter that changes whenever any edge is added, changed or removed
+    pub fn edge_generation(&self) -> AppResult<i64> {
         self.with_conn(|conn| {
-            conn.execute("DELETE FROM up_next WHERE book_id = ?", [book_id])?;
-            Ok(())
+            Ok(conn.query_row("SELECT generation FROM edge_generation", [], |row| row.get(0))?)
         })
     }
 
-    /// Check if a book is in the Up Next queue
-    pub fn is_in_up_next(&self, book_id: i64) -> AppResult<bool> {
+    /// Count edges at or above `min_weight`
+    pub fn count_edges(&self, min_weight: f64) -> AppResult<usize> {
         self.with_conn(|conn| {
-            let count: i64 = conn.query_row(
-                "SELECT COUNT(*) FROM up_next WHERE book_id = ?",
-                [book_id],
-                |row| row.get(0),
-            )?;
-            Ok(count > 0)
+            let count: i64 =
+                conn.query_row("SELECT COUNT(*) FROM book_edges WHERE weight >= ?", [min_weight], |row| row.get(0))?;
+            Ok(count as usize)
         })
     }
 
-    /// Get the count of books in the Up Next queue
-    pub fn get_up_next_count(&self) -> AppResult<i64> {
+    /// Start replacing the whole graph
+    ///
+    /// Edges are staged in a temporary table on a dedicated connection and
+    /// only swapped into `book_edges` by [`EdgeRebuild::commit`]; dropping the
+    /// rebuild instead leaves the existing graph untouched.
+    pub fn begin_edge_rebuild(&self) -> AppResult<EdgeRebuild> {
+        let conn = self.conn()?;
+        conn.execute_batch(
+            "DROP TABLE IF EXISTS temp.staged_edges;
+             CREATE TEMP TABLE staged_edges (
+                source_id INTEGER NOT NULL,
+                target_id INTEGER NOT NULL,
+                edge_type TEXT NOT NULL,
+                weight REAL NOT NULL
+             );"
+        )?;
+        Ok(EdgeRebuild { conn })
+    }
+
+    /// Link two books by hand, in both directions
+    ///
+    /// Manual edges survive graph rebuilds and per-book recomputes; adding the
+    /// same pair again updates the weight.
+    pub fn add_manual_edge(&self, source_id: i64, target_id: i64, weight: f64) -> AppResult<()> {
+        if source_id == target_id {
+            return Err(AppError::InvalidInput("A book can't be linked to itself".to_string()));
+        }
+        if !(0.0..=1.0).contains(&weight) {
+            return Err(AppError::InvalidInput("Edge weight must be between 0 and 1".to_string()));
+        }
+        self.get_book(source_id)?;
+        self.get_book(target_id)?;
+
+        self.insert_edges_batch(&[
+            (source_id, target_id, MANUAL_EDGE_TYPE.to_string(), weight),
+            (target_id, source_id, MANUAL_EDGE_TYPE.to_string(), weight),
+        ])
+    }
+
+    /// Remove a manual link between two books; returns whether one existed
+    pub fn remove_manual_edge(&self, source_id: i64, target_id: i64) -> AppResult<bool> {
         self.with_conn(|conn| {
-            let count: i64 = conn.query_row(
-                "SELECT COUNT(*) FROM up_next",
-                [],
-                |row| row.get(0),
+            let removed = conn.execute(
+                "DELETE FROM book_edges
+                 WHERE edge_type = ?3
+                 AND ((source_id = ?1 AND target_id = ?2) OR (source_id = ?2 AND target_id = ?1))",
+                params![source_id, target_id, MANUAL_EDGE_TYPE],
             )?;
-            Ok(count)
+            Ok(removed > 0)
         })
     }
 
-    /// Get books with "want" read status (for automatic Up Next inclusion)
-    pub fn get_want_to_read_books(&self) -> AppResult<Vec<Book>> {
+    /// Replace every computed edge touching a book (as source or target) in one transaction
+    ///
+    /// Manual edges are kept.
+    pub fn replace_book_edges(&self, book_id: i64, edges: &[(i64, i64, String, f64)]) -> AppResult<()> {
+        let mut conn = self.conn()?;
+        let tx = conn.transaction()?;
+
+        tx.execute(
+            "DELETE FROM book_edges WHERE (source_id = ?1 OR target_id = ?1) AND edge_type != ?2",
+            params![book_id, MANUAL_EDGE_TYPE],
+        )?;
+
+        {
+            let mut stmt = tx.prepare(
+                "INSERT OR REPLACE INTO book_edges (source_id, target_id, edge_type, weight)
+                 VALUES (?, ?, ?, ?)"
+            )?;
+
+            for (source, target, edge_type, weight) in edges {
+                stmt.execute(params![source, target, edge_type, weight])?;
+            }
+        }
+
+        tx.commit()?;
+        Ok(())
+    }
+
+    /// Replace a book's precomputed similar books with `similar`, best first
+    pub fn replace_similar_cache(&self, book_id: i64, similar: &[(i64, f64)]) -> AppResult<()> {
+        let mut conn = self.conn()?;
+        let tx = conn.transaction()?;
+        tx.execute("DELETE FROM similar_cache WHERE book_id = ?", [book_id])?;
+        {
+            let mut stmt = tx.prepare(
+                "INSERT INTO similar_cache (book_id, rank, target_id, score) VALUES (?, ?, ?, ?)"
+            )?;
+            for (rank, (target_id, score)) in similar.iter().enumerate() {
+                stmt.execute(params![book_id, rank as i64, target_id, score])?;
+            }
+        }
+        tx.commit()?;
+        Ok(())
+    }
+
+    /// Get up to `limit` precomputed similar books as `(target_id, score)`, best first
+    pub fn get_similar_cache(&self, book_id: i64, limit: i64) -> AppResult<Vec<(i64, f64)>> {
         self.with_conn(|conn| {
             let mut stmt = conn.prepare(
-                "SELECT b.*, r.rating, r.read_status
-                 FROM books b
-                 LEFT JOIN ratings r ON b.id = r.book_id
-                 WHERE r.read_status = 'want'
-                 ORDER BY r.date_rated DESC"
+                "SELECT target_id, score FROM similar_cache WHERE book_id = ? ORDER BY rank LIMIT ?"
             )?;
-
-            let books = stmt.query_map([], row_to_book)?
+            let similar = stmt.query_map(params![book_id, limit], |row| Ok((row.get(0)?, row.get(1)?)))?
                 .collect::<Result<Vec<_>, _>>()?;
-
-            Ok(books)
+            Ok(similar)
         })
     }
 
-    // ============================================
-    // STATISTICS
-    // ============================================
+    /// Drop cached similar books computed from a book's embedding
+    ///
+    /// Clears the book's own list and its entries in other books' lists,
+    /// since both were scored against the old embedding.
+    pub fn invalidate_similar_cache(&self, book_id: i64) -> AppResult<()> {
+        self.with_conn(|conn| {
+            conn.execute("DELETE FROM similar_cache WHERE book_id = ?1 OR target_id = ?1", [book_id])?;
+            Ok(())
+        })
+    }
 
-    /// Get library statistics
-    pub fn get_stats(&self) -> AppResult<LibraryStats> {
+    /// Cap graph density: keep each book's `max_per_node` strongest outgoing edges
+    ///
+    /// Edges weaker than `min_weight` are dropped first. Manual edges are
+    /// never pruned and don't count towards the cap. Returns the number of
+    /// edges removed.
+    pub fn prune_edges(&self, max_per_node: usize, min_weight: f64) -> AppResult<usize> {
         self.with_conn(|conn| {
-            let total_books: i64 = conn.query_row("SELECT COUNT(*) FROM books", [], |r| r.get(0))?;
-            let total_authors: i64 = conn.query_row("SELECT COUNT(DISTINCT author) FROM books WHERE author IS NOT NULL", [], |r| r.get(0))?;
-            let total_series: i64 = conn.query_row("SELECT COUNT(DISTINCT series) FROM books WHERE series IS NOT NULL", [], |r| r.get(0))?;
-            let books_with_embeddings: i64 = conn.query_row("SELECT COUNT(*) FROM books WHERE embedding_status = 'complete'", [], |r| r.get(0))?;
-            let pending_embeddings: i64 = conn.query_row("SELECT COUNT(*) FROM books WHERE embedding_status = 'pending'", [], |r| r.get(0))?;
-            let books_needing_metadata: i64 = conn.query_row(
-                "SELECT COUNT(*) FROM books
-                 WHERE (description IS NULL OR description = '')
-                 AND (embedding_status IS NULL OR embedding_status = '')",
-                [],
-                |r| r.get(0)
+            let removed = conn.execute(
+                "DELETE FROM book_edges
+                 WHERE edge_type != ?3
+                 AND (weight < ?2 OR rowid IN (
+                     SELECT rowid FROM (
+                         SELECT rowid, ROW_NUMBER() OVER (
+                             PARTITION BY source_id ORDER BY weight DESC, target_id, edge_type
+                         ) AS rank
+                         FROM book_edges
+                         WHERE edge_type != ?3 AND weight >= ?2
+                     )
+                     WHERE rank > ?1
+                 ))",
+                params![max_per_node as i64, min_weight, MANUAL_EDGE_TYPE],
             )?;
+            Ok(removed)
+        })
+    }
 
-            Ok(LibraryStats {
-                total_books,
-                total_authors,
-                total_series,
-                books_with_embeddings,
-                pending_embeddings,
-                books_needing_metadata,
-            })
+    /// Cap every book at `max_degree` outgoing and `max_degree` incoming edges
+    ///
+    /// Keeps the strongest edges on each side, so books that ended up as hubs
+    /// with many weak incoming links are cut back. Manual edges are never
+    /// pruned and don't count towards the cap. Returns the number of edges
+    /// removed.
+    pub fn prune_edges_over_degree(&self, max_degree: usize) -> AppResult<usize> {
+        self.with_conn(|conn| {
+            let removed = conn.execute(
+                "DELETE FROM book_edges
+                 WHERE rowid IN (
+                     SELECT rowid FROM (
+                         SELECT rowid,
+                             ROW_NUMBER() OVER (
+                                 PARTITION BY source_id ORDER BY weight DESC, target_id, edge_type
+                             ) AS out_rank,
+                             ROW_NUMBER() OVER (
+                                 PARTITION BY target_id ORDER BY weight DESC, source_id, edge_type
+                             ) AS in_rank
+                         FROM book_edges
+                         WHERE edge_type != ?2
+                     )
+                     WHERE out_rank > ?1 OR in_rank > ?1
+                 )",
+                params![max_degree as i64, MANUAL_EDGE_TYPE],
+            )?;
+            Ok(removed)
         })
     }
-}
 
-// ============================================
-// HELPER TYPES AND FUNCTIONS
-// ============================================
+    // ============================================
+    // COLLECTION OPERATIONS
+    // ============================================
 
-/// New book data for insertion
+    /// Get all collections by name, with how many books each holds
+    pub fn get_collections(&self) -> AppResult<Vec<Collection>> {
+        self.with_conn(|conn| {
+            let mut stmt = conn.prepare(&format!("{} ORDER BY c.name", COLLECTION_SELECT))?;
+            let collections = stmt.query_map([], row_to_collection)?
+                .collect::<Result<Vec<_>, _>>()?;
+            Ok(collections)
+        })
+    }
+
+    /// Get the collections a book belongs to, by name
+    pub fn get_book_collections(&self, book_id: i64) -> AppResult<Vec<Collection>> {
+        self.with_conn(|conn| {
+            let mut stmt = conn.prepare(&format!(
+                "{} WHERE c.id IN (SELECT collection_id FROM collection_books WHERE book_id = ?) ORDER BY c.name",
+                COLLECTION_SELECT
+            ))?;
+            let collections = stmt.query_map([book_id], row_to_collection)?
+                .collect::<Result<Vec<_>, _>>()?;
+            Ok(collections)
+        })
+    }
+
+    /// Get a collection by ID
+    pub fn get_collection(&self, id: i64) -> AppResult<Collection> {
+        self.with_conn(|conn| {
+            conn.query_row(&format!("{} WHERE c.id = ?", COLLECTION_SELECT), [id], row_to_collection)
+                .map_err(|e| match e {
+                    rusqlite::Error::QueryReturnedNoRows => AppError::NotFound(format!("Collection {} not found", id)),
+                    _ => AppError::Database(e),
+                })
+        })
+    }
+
+    /// Create an empty collection
+    ///
+    /// Names are trimmed and must be unique, ignoring case.
+    pub fn create_collection(&self, name: &str) -> AppResult<Collection> {
+        let name = self.check_collection_name(name, None)?;
+        let id = self.with_conn(|conn| {
+            conn.execute("INSERT INTO collections (name) VALUES (?)", [&name])?;
+            Ok(conn.last_insert_rowid())
+        })?;
+        self.get_collection(id)
+    }
+
+    /// Rename a collection
+    pub fn rename_collection(&self, id: i64, name: &str) -> AppResult<Collection> {
+        let name = self.check_collection_name(name, Some(id))?;
+        let updated = self.with_conn(|conn| {
+            Ok(conn.execute("UPDATE collections SET name = ? WHERE id = ?", params![name, id])?)
+        })?;
+        if updated == 0 {
+            return Err(AppError::NotFound(format!("Collection {} not found", id)));
+        }
+        self.get_collection(id)
+    }
+
+    /// Delete a collection (its books are NOT deleted)
+    pub fn delete_collection(&self, id: i64) -> AppResult<()> {
+        let deleted = self.with_conn(|conn| Ok(conn.execute("DELETE FROM collections WHERE id = ?", [id])?))?;
+        if deleted == 0 {
+            return Err(AppError::NotFound(format!("Collection {} not found", id)));
+        }
+        Ok(())
+    }
+
+    /// Add books to a collection in a single transaction
+    ///
+    /// Books already in the collection and IDs of books that don't exist are
+    /// skipped. Returns the number of books added.
+    pub fn add_books_to_collection(&self, collection_id: i64, book_ids: &[i64]) -> AppResult<usize> {
+        self.get_collection(collection_id)?;
+
+        let mut conn = self.conn()?;
+        let tx = conn.transaction()?;
+        let mut added = 0;
+
+        {
+            let mut stmt = tx.prepare(
+                "INSERT OR IGNORE INTO collection_books (collection_id, book_id)
+                 SELECT ?1, id FROM books WHERE id = ?2"
+            )?;
+            for book_id in book_ids {
+                added += stmt.execute(params![collection_id, book_id])?;
+            }
+        }
+
+        tx.commit()?;
+        Ok(added)
+    }
+
+    /// Remove books from a collection, returning how many were removed
+    pub fn remove_books_from_collection(&self, collection_id: i64, book_ids: &[i64]) -> AppResult<usize> {
+        let mut conn = self.conn()?;
+        let tx = conn.transaction()?;
+        let mut removed = 0;
+
+        {
+            let mut stmt = tx.prepare("DELETE FROM collection_books WHERE collection_id = ? AND book_id = ?")?;
+            for book_id in book_ids {
+                removed += stmt.execute(params![collection_id, book_id])?;
+            }
+        }
+
+        tx.commit()?;
+        Ok(removed)
+    }
+
+    /// Trim a collection name and check it's non-empty and not taken by
+    /// another collection
+    fn check_collection_name(&self, name: &str, id: Option<i64>) -> AppResult<String> {
+        let name = name.trim();
+        if name.is_empty() {
+            return Err(AppError::InvalidInput("Collection name cannot be empty".to_string()));
+        }
+
+        let taken: bool = self.with_conn(|conn| {
+            Ok(conn.query_row(
+                "SELECT EXISTS(SELECT 1 FROM collections WHERE name = ?1 AND id IS NOT ?2)",
+                params![name, id],
+                |row| row.get(0),
+            )?)
+        })?;
+        if taken {
+            return Err(AppError::InvalidInput(format!("A collection named \"{}\" already exists", name)));
+        }
+        Ok(name.to_string())
+    }
+
+    // ============================================
+    // UP NEXT OPERATIONS
+    // ============================================
+
+    /// Get all books in the Up Next queue
+    pub fn get_up_next_books(&self) -> AppResult<Vec<Book>> {
+        self.with_conn(|conn| {
+            let mut stmt = conn.prepare(
+                "SELECT b.*, r.rating, r.read_status, r.notes, r.progress_percent, r.progress_location
+                 FROM books b
+                 LEFT JOIN ratings r ON b.id = r.book_id
+                 INNER JOIN up_next un ON b.id = un.book_id
+                 ORDER BY un.position ASC, un.added_at ASC"
+            )?;
+
+            let books = stmt.query_map([], row_to_book)?
+                .collect::<Result<Vec<_>, _>>()?;
+
+            Ok(books)
+        })
+    }
+
+    /// Add a book to the Up Next queue
+    pub fn add_to_up_next(&self, book_id: i64) -> AppResult<()> {
+        self.with_conn(|conn| {
+            // Get the next position (max + 1)
+            let next_position: i64 = conn
+                .query_row(
+                    "SELECT COALESCE(MAX(position), -1) + 1 FROM up_next",
+                    [],
+                    |row| row.get(0),
+                )
+                .unwrap_or(0);
+
+            conn.execute(
+                "INSERT OR IGNORE INTO up_next (book_id, position) VALUES (?, ?)",
+                params![book_id, next_position],
+            )?;
+            Ok(())
+        })
+    }
+
+    /// Remove a book from the Up Next queue, closing the gap it leaves
+    pub fn remove_from_up_next(&self, book_id: i64) -> AppResult<()> {
+        let mut conn = self.conn()?;
+        let tx = conn.transaction()?;
+
+        let mut queue = up_next_order(&tx)?;
+        queue.retain(|&id| id != book_id);
+        tx.execute("DELETE FROM up_next WHERE book_id = ?", [book_id])?;
+        write_up_next_order(&tx, &queue)?;
+
+        tx.commit()?;
+        Ok(())
+    }
+
+    /// Move a book to a new position in the Up Next queue, shifting the books
+    /// in between
+    ///
+    /// Positions start at 0; anything past the end moves the book to the end.
+    pub fn reorder_up_next(&self, book_id: i64, new_position: i64) -> AppResult<()> {
+        let mut conn = self.conn()?;
+        let tx = conn.transaction()?;
+
+        let mut queue = up_next_order(&tx)?;
+        let current = queue
+            .iter()
+            .position(|&id| id == book_id)
+            .ok_or_else(|| AppError::NotFound(format!("Book {} is not in Up Next", book_id)))?;
+        queue.remove(current);
+        let new_position = (new_position.max(0) as usize).min(queue.len());
+        queue.insert(new_position, book_id);
+        write_up_next_order(&tx, &queue)?;
+
+        tx.commit()?;
+        Ok(())
+    }
+
+    /// Empty the Up Next queue, returning how many books were in it
+    pub fn clear_up_next(&self) -> AppResult<usize> {
+        self.with_conn(|conn| Ok(conn.execute("DELETE FROM up_next", [])?))
+    }
+
+    /// Check if a book is in the Up Next queue
+    pub fn is_in_up_next(&self, book_id: i64) -> AppResult<bool> {
+        self.with_conn(|conn| {
+            let count: i64 = conn.query_row(
+                "SELECT COUNT(*) FROM up_next WHERE book_id = ?",
+                [book_id],
+                |row| row.get(0),
+            )?;
+            Ok(count > 0)
+        })
+    }
+
+    /// Get the count of books in the Up Next queue
+    pub fn get_up_next_count(&self) -> AppResult<i64> {
+        self.with_conn(|conn| {
+            let count: i64 = conn.query_row(
+                "SELECT COUNT(*) FROM up_next",
+                [],
+                |row| row.get(0),
+            )?;
+            Ok(count)
+        })
+    }
+
+    /// Get books with "want" read status (for automatic Up Next inclusion)
+    pub fn get_want_to_read_books(&self) -> AppResult<Vec<Book>> {
+        self.with_conn(|conn| {
+            let mut stmt = conn.prepare(
+                "SELECT b.*, r.rating, r.read_status, r.notes, r.progress_percent, r.progress_location
+                 FROM books b
+                 LEFT JOIN ratings r ON b.id = r.book_id
+                 WHERE r.read_status = 'want'
+                 ORDER BY r.date_rated DESC"
+            )?;
+
+            let books = stmt.query_map([], row_to_book)?
+                .collect::<Result<Vec<_>, _>>()?;
+
+            Ok(books)
+        })
+    }
+
+    // ============================================
+    // STATISTICS
+    // ============================================
+
+    /// Get library statistics
+    pub fn get_stats(&self) -> AppResult<LibraryStats> {
+        self.with_conn(|conn| {
+            let total_books: i64 = conn.query_row("SELECT COUNT(*) FROM books", [], |r| r.get(0))?;
+            let total_authors: i64 = conn.query_row("SELECT COUNT(DISTINCT author) FROM books WHERE author IS NOT NULL", [], |r| r.get(0))?;
+            let total_series: i64 = conn.query_row("SELECT COUNT(DISTINCT series) FROM books WHERE series IS NOT NULL", [], |r| r.get(0))?;
+            let books_with_embeddings: i64 = conn.query_row("SELECT COUNT(*) FROM books WHERE embedding_status = 'complete'", [], |r| r.get(0))?;
+            let pending_embeddings: i64 = conn.query_row("SELECT COUNT(*) FROM books WHERE embedding_status = 'pending'", [], |r| r.get(0))?;
+            let books_needing_metadata: i64 = conn.query_row(
+                "SELECT COUNT(*) FROM books
+                 WHERE (description IS NULL OR description = '')
+                 AND COALESCE(embedding_status, '') IN ('', 'pending', 'needs_metadata')",
+                [],
+                |r| r.get(0)
+            )?;
+
+            Ok(LibraryStats {
+                total_books,
+                total_authors,
+                total_series,
+                books_with_embeddings,
+                pending_embeddings,
+                books_needing_metadata,
+            })
+        })
+    }
+
+    /// Get embedding progress for each library
+    ///
+    /// Books belong to a library when their path is under its root. Books
+    /// not yet parsed count as pending.
+    pub fn get_embedding_coverage(&self) -> AppResult<Vec<LibraryCoverage>> {
+        self.with_conn(|conn| {
+            let mut stmt = conn.prepare(&format!(
+                "SELECT l.id, l.name,
+                        COUNT(b.id),
+                        COALESCE(SUM(b.embedding_status = 'complete'), 0),
+                        COALESCE(SUM(b.id IS NOT NULL AND COALESCE(b.embedding_status, '') IN ('', 'pending')), 0),
+                        COALESCE(SUM(b.embedding_status = 'failed'), 0),
+                        COALESCE(SUM(b.embedding_status = 'skipped'), 0)
+                 FROM libraries l
+                 LEFT JOIN books b ON {}
+                 GROUP BY l.id
+                 ORDER BY l.name",
+                path_under("b.path", "l.path")
+            ))?;
+
+            let coverage = stmt.query_map([], |row| {
+                Ok(LibraryCoverage {
+                    library_id: row.get(0)?,
+                    name: row.get(1)?,
+                    total: row.get(2)?,
+                    embedded: row.get(3)?,
+                    pending: row.get(4)?,
+                    failed: row.get(5)?,
+                    skipped: row.get(6)?,
+                })
+            })?.collect::<Result<Vec<_>, _>>()?;
+
+            Ok(coverage)
+        })
+    }
+
+    /// Get the most common tags and the tag hierarchy with rolled-up counts
+    ///
+    /// A parent's `total` counts each book once even if it carries several
+    /// of the parent's descendants. `top_n` limits both the flat list and the
+    /// number of root nodes.
+    pub fn get_tag_distribution(&self, top_n: usize) -> AppResult<TagDistribution> {
+        self.with_conn(|conn| {
+            let total_books: i64 = conn.query_row("SELECT COUNT(*) FROM books", [], |r| r.get(0))?;
+
+            // Direct counts and subtree counts (distinct books under each tag)
+            let mut stmt = conn.prepare(
+                "WITH RECURSIVE ancestry(tag_id, ancestor_id) AS (
+                     SELECT id, id FROM tags
+                     UNION
+                     SELECT a.tag_id, t.parent_id
+                     FROM ancestry a JOIN tags t ON t.id = a.ancestor_id
+                     WHERE t.parent_id IS NOT NULL
+                 )
+                 SELECT t.id, t.name, t.parent_id,
+                        (SELECT COUNT(*) FROM book_tags bt WHERE bt.tag_id = t.id),
+                        (SELECT COUNT(DISTINCT bt.book_id)
+                         FROM ancestry a JOIN book_tags bt ON bt.tag_id = a.tag_id
+                         WHERE a.ancestor_id = t.id)
+                 FROM tags t"
+            )?;
+            let rows = stmt.query_map([], |row| {
+                Ok((
+                    row.get::<_, i64>(0)?,
+                    row.get::<_, String>(1)?,
+                    row.get::<_, Option<i64>>(2)?,
+                    row.get::<_, i64>(3)?,
+                    row.get::<_, i64>(4)?,
+                ))
+            })?.collect::<Result<Vec<_>, _>>()?;
+
+            Ok(build_tag_distribution(total_books, rows, top_n))
+        })
+    }
+}
+
+// ============================================
+// HELPER TYPES AND FUNCTIONS
+// ============================================
+
+/// New book data for insertion
 #[derive(Debug, Clone)]
 pub struct NewBook {
     pub path: String,
@@ -725,6 +2462,34 @@ pub struct NewBook {
     pub publish_date: Option<String>,
     pub isbn: Option<String>,
     pub source: String,
+    /// Archive the EPUB lives inside, when it isn't a plain file
+    pub container_path: Option<String>,
+    pub word_count: Option<i64>,
+    pub format: Format,
+    /// Subjects from the book's metadata, stored as tags on insert
+    pub tags: Vec<String>,
+    /// Everyone credited, in order, stored in `book_authors` on insert
+    pub authors: Vec<NewAuthor>,
+}
+
+/// Someone credited on a new book
+#[derive(Debug, Clone, PartialEq)]
+pub struct NewAuthor {
+    pub name: String,
+    pub sort_name: Option<String>,
+    /// What they did, e.g. `author`, `editor` or `translator`
+    pub role: String,
+}
+
+impl NewAuthor {
+    /// An author credited by name alone
+    pub fn author(name: &str) -> Self {
+        Self {
+            name: name.to_string(),
+            sort_name: Some(generate_author_sort(name)),
+            role: "author".to_string(),
+        }
+    }
 }
 
 /// Book update data
@@ -738,6 +2503,26 @@ pub struct BookUpdate {
     pub description: Option<String>,
 }
 
+/// A user's rating/read-status signal for a single book
+#[derive(Debug, Clone)]
+pub struct PreferenceSignal {
+    pub book_id: i64,
+    pub rating: Option<f64>,
+    pub read_status: Option<String>,
+    /// When the rating or status was last changed (unix seconds)
+    pub updated_at: i64,
+}
+
+/// A content edge between books by two different authors
+#[derive(Debug, Clone)]
+pub struct AuthorLink {
+    pub source_book: i64,
+    pub source_author: String,
+    pub target_book: i64,
+    pub target_author: String,
+    pub weight: f64,
+}
+
 /// Library statistics
 #[derive(Debug, Clone, serde::Serialize)]
 #[serde(rename_all = "camelCase")]
@@ -750,43 +2535,534 @@ pub struct LibraryStats {
     pub books_needing_metadata: i64,
 }
 
-/// Convert a database row to a Book struct
-fn row_to_book(row: &Row<'_>) -> rusqlite::Result<Book> {
-    Ok(Book {
-        id: row.get(0)?,
-        path: row.get(1)?,
-        cover_path: row.get(2)?,
-        file_size: row.get(3)?,
-        file_hash: row.get(4)?,
-        title: row.get(5)?,
-        sort_title: row.get(6)?,
-        author: row.get(7)?,
-        author_sort: row.get(8)?,
-        series: row.get(9)?,
-        series_index: row.get(10)?,
-        description: row.get(11)?,
-        language: row.get(12)?,
-        publisher: row.get(13)?,
-        publish_date: row.get(14)?,
-        isbn: row.get(15)?,
-        calibre_id: row.get(16)?,
-        source: row.get(17)?,
-        date_added: row.get(18)?,
-        date_modified: row.get(19)?,
-        date_indexed: row.get(20)?,
-        embedding_status: row.get(21)?,
-        embedding_model: row.get(22)?,
-        rating: row.get(23)?,
-        read_status: row.get(24)?,
-    })
+/// A whole-graph replacement in progress (see [`Database::begin_edge_rebuild`])
+pub struct EdgeRebuild {
+    conn: PooledConnection<SqliteConnectionManager>,
 }
 
-// Extension trait for optional query results
-trait OptionalExt<T> {
-    fn optional(self) -> Result<Option<T>, rusqlite::Error>;
-}
+impl EdgeRebuild {
+    /// Add edges to the staged graph
+    pub fn stage(&mut self, edges: &[(i64, i64, String, f64)]) -> AppResult<()> {
+        let tx = self.conn.transaction()?;
 
-impl<T> OptionalExt<T> for Result<T, rusqlite::Error> {
+        {
+            let mut stmt = tx.prepare(
+                "INSERT INTO temp.staged_edges (source_id, target_id, edge_type, weight)
+                 VALUES (?, ?, ?, ?)"
+            )?;
+
+            for (source, target, edge_type, weight) in edges {
+                stmt.execute(params![source, target, edge_type, weight])?;
+            }
+        }
+
+        tx.commit()?;
+        Ok(())
+    }
+
+    /// Atomically replace the computed edges in `book_edges` with the staged edges
+    ///
+    /// Manual edges are kept. On error the transaction rolls back and the old
+    /// graph is kept. Returns the number of edges stored.
+    pub fn commit(mut self) -> AppResult<usize> {
+        let tx = self.conn.transaction()?;
+        tx.execute("DELETE FROM book_edges WHERE edge_type != ?", [MANUAL_EDGE_TYPE])?;
+        let stored = tx.execute(
+            "INSERT OR REPLACE INTO book_edges (source_id, target_id, edge_type, weight)
+             SELECT source_id, target_id, edge_type, weight FROM temp.staged_edges",
+            [],
+        )?;
+        tx.commit()?;
+        Ok(stored)
+    }
+}
+
+impl Drop for EdgeRebuild {
+    fn drop(&mut self) {
+        // The connection goes back to the pool; don't leave staged rows on it
+        let _ = self.conn.execute("DROP TABLE IF EXISTS temp.staged_edges", []);
+    }
+}
+
+/// Embedding progress for one library
+#[derive(Debug, Clone, serde::Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct LibraryCoverage {
+    pub library_id: i64,
+    pub name: String,
+    pub total: i64,
+    pub embedded: i64,
+    pub pending: i64,
+    pub failed: i64,
+    pub skipped: i64,
+}
+
+/// Tag usage across the library
+#[derive(Debug, Clone, serde::Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TagDistribution {
+    pub total_books: i64,
+    /// Most common tags by direct count
+    pub top: Vec<TagCount>,
+    /// Root tags with their descendants nested, largest subtree first
+    pub tree: Vec<TagNode>,
+}
+
+/// How many books carry a tag
+#[derive(Debug, Clone, serde::Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TagCount {
+    pub name: String,
+    pub count: i64,
+    /// Share of all books (0-100)
+    pub percent: f64,
+}
+
+/// A tag in the hierarchy
+#[derive(Debug, Clone, serde::Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TagNode {
+    pub name: String,
+    /// Books tagged with this tag itself
+    pub count: i64,
+    /// Books tagged with this tag or any descendant
+    pub total: i64,
+    /// `total` as a share of all books (0-100)
+    pub percent: f64,
+    pub children: Vec<TagNode>,
+}
+
+/// A tag row: `(id, name, parent_id, direct count, subtree count)`
+type TagRow = (i64, String, Option<i64>, i64, i64);
+
+/// Assemble a [`TagDistribution`] from tag rows
+fn build_tag_distribution(total_books: i64, rows: Vec<TagRow>, top_n: usize) -> TagDistribution {
+    let percent = |count: i64| {
+        if total_books > 0 {
+            count as f64 / total_books as f64 * 100.0
+        } else {
+            0.0
+        }
+    };
+
+    let mut top: Vec<TagCount> = rows
+        .iter()
+        .filter(|(_, _, _, count, _)| *count > 0)
+        .map(|(_, name, _, count, _)| TagCount { name: name.clone(), count: *count, percent: percent(*count) })
+        .collect();
+    top.sort_by(|a, b| b.count.cmp(&a.count).then_with(|| a.name.cmp(&b.name)));
+    top.truncate(top_n);
+
+    // Group used tags by parent; tags whose parent is missing become roots
+    let ids: std::collections::HashSet<i64> = rows.iter().map(|r| r.0).collect();
+    let mut children: HashMap<Option<i64>, Vec<&TagRow>> = HashMap::new();
+    for row in rows.iter().filter(|r| r.4 > 0) {
+        let parent = row.2.filter(|p| ids.contains(p) && *p != row.0);
+        children.entry(parent).or_default().push(row);
+    }
+
+    // Depth-first walk; `visited` guards against parent cycles
+    fn walk(
+        parent: Option<i64>,
+        children: &HashMap<Option<i64>, Vec<&TagRow>>,
+        visited: &mut std::collections::HashSet<i64>,
+        percent: &dyn Fn(i64) -> f64,
+    ) -> Vec<TagNode> {
+        let mut nodes = Vec::new();
+        for (id, name, _, count, total) in children.get(&parent).into_iter().flatten() {
+            if !visited.insert(*id) {
+                continue;
+            }
+            nodes.push(TagNode {
+                name: name.clone(),
+                count: *count,
+                total: *total,
+                percent: percent(*total),
+                children: walk(Some(*id), children, visited, percent),
+            });
+        }
+        nodes.sort_by(|a, b| b.total.cmp(&a.total).then_with(|| a.name.cmp(&b.name)));
+        nodes
+    }
+
+    let mut tree = walk(None, &children, &mut std::collections::HashSet::new(), &percent);
+    tree.truncate(top_n);
+
+    TagDistribution { total_books, top, tree }
+}
+
+/// Embedding job columns for [`row_to_embedding_job`]; callers append WHERE
+const EMBEDDING_JOB_SELECT: &str =
+    "SELECT book_id, status, priority, attempts, last_error FROM embedding_jobs";
+
+/// Collections with their book counts; callers append WHERE/ORDER BY
+const COLLECTION_SELECT: &str = "SELECT c.id, c.name, c.created_at,
+            (SELECT COUNT(*) FROM collection_books cb WHERE cb.collection_id = c.id)
+     FROM collections c";
+
+/// Set a book's rating, taking `(book_id, rating)`
+const UPSERT_RATING_SQL: &str = "INSERT INTO ratings (book_id, rating, date_rated)
+     VALUES (?1, ?2, strftime('%s', 'now'))
+     ON CONFLICT(book_id) DO UPDATE SET rating = ?2, date_rated = strftime('%s', 'now')";
+
+/// Set a book's read status and stamp its reading dates, taking `(book_id, status)`
+const UPSERT_READ_STATUS_SQL: &str = "INSERT INTO ratings (book_id, read_status, date_rated, date_started, date_finished)
+     VALUES (?1, ?2, strftime('%s', 'now'),
+             CASE WHEN ?2 = 'reading' THEN strftime('%s', 'now') END,
+             CASE WHEN ?2 = 'finished' THEN strftime('%s', 'now') END)
+     ON CONFLICT(book_id) DO UPDATE SET
+        read_status = ?2,
+        date_rated = strftime('%s', 'now'),
+        date_started = CASE
+            WHEN ?2 = 'reading' AND read_status IS NOT 'reading' THEN strftime('%s', 'now')
+            ELSE date_started
+        END,
+        date_finished = CASE
+            WHEN ?2 = 'finished' AND read_status IS NOT 'finished' THEN strftime('%s', 'now')
+            WHEN ?2 = 'reading' THEN NULL
+            ELSE date_finished
+        END";
+
+/// WHERE conditions and parameters for a book query's filters
+///
+/// Conditions refer to `books b` and `ratings r`.
+fn book_query_conditions(query: &BookQuery) -> (Vec<String>, Vec<Box<dyn rusqlite::ToSql>>) {
+    let mut conditions = Vec::new();
+    let mut params_vec: Vec<Box<dyn rusqlite::ToSql>> = Vec::new();
+    
+    // FTS search over metadata, notes and tags; an index is skipped when the
+    // query scopes a term to a field it doesn't have
+    if let Some(ref search) = query.search {
+        let indexes = [
+            ("books_fts", BOOK_FTS_COLUMNS),
+            ("notes_fts", NOTES_FTS_COLUMNS),
+            ("tags_fts", TAGS_FTS_COLUMNS),
+        ];
+        let mut matches = Vec::new();
+        for (table, columns) in indexes {
+            if let Some(fts_query) = sanitize_fts_query(search, columns) {
+                matches.push(format!("b.id IN (SELECT rowid FROM {0} WHERE {0} MATCH ?)", table));
+                params_vec.push(Box::new(fts_query));
+            }
+        }
+        if !matches.is_empty() {
+            conditions.push(format!("({})", matches.join(" OR ")));
+        }
+    }
+    
+    // Author filter, matching co-authors as well as the displayed author
+    if let Some(ref author) = query.author {
+        conditions.push(
+            "(b.author = ? OR b.id IN (SELECT ba.book_id FROM book_authors ba
+                                      JOIN authors a ON a.id = ba.author_id WHERE a.name = ?))"
+                .to_string(),
+        );
+        params_vec.push(Box::new(author.clone()));
+        params_vec.push(Box::new(author.clone()));
+    }
+    
+    // Series filter
+    if let Some(ref series) = query.series {
+        conditions.push("b.series = ?".to_string());
+        params_vec.push(Box::new(series.clone()));
+    }
+    
+    // Read status filter
+    if let Some(ref status) = query.read_status {
+        conditions.push("r.read_status = ?".to_string());
+        params_vec.push(Box::new(status.clone()));
+    }
+    
+    // Min rating filter
+    if let Some(min_rating) = query.min_rating {
+        conditions.push("r.rating >= ?".to_string());
+        params_vec.push(Box::new(min_rating));
+    }
+    
+    // Embedding status filter
+    if let Some(ref status) = query.embedding_status {
+        conditions.push("b.embedding_status = ?".to_string());
+        params_vec.push(Box::new(status.clone()));
+    }
+    
+    // Source filter
+    if let Some(ref source) = query.source {
+        conditions.push("b.source = ?".to_string());
+        params_vec.push(Box::new(source.clone()));
+    }
+
+    // Language filter
+    if let Some(ref language) = query.language {
+        conditions.push("b.language = ?".to_string());
+        params_vec.push(Box::new(language.clone()));
+    }
+
+    // File format filter
+    if let Some(ref format) = query.format {
+        conditions.push("b.format = ?".to_string());
+        params_vec.push(Box::new(format.clone()));
+    }
+
+    // Series exclusion
+    if let Some(ref series) = query.exclude_series {
+        conditions.push("(b.series IS NULL OR b.series != ?)".to_string());
+        params_vec.push(Box::new(series.clone()));
+    }
+
+    // Collection filter
+    if let Some(collection_id) = query.collection_id {
+        conditions.push("b.id IN (SELECT book_id FROM collection_books WHERE collection_id = ?)".to_string());
+        params_vec.push(Box::new(collection_id));
+    }
+
+    (conditions, params_vec)
+}
+
+/// SQL condition matching `column` at or below the library root `root`
+///
+/// Both are SQL expressions. Whole path components are compared, so
+/// `/lib/books` doesn't contain `/lib/books-extra`; case is ignored where
+/// the filesystem does. Plain comparisons rather than `LIKE`, so `%` and `_`
+/// in a path are literal.
+fn path_under(column: &str, root: &str) -> String {
+    format!(
+        "({column} = {root} COLLATE {collation}
+          OR substr({column}, 1, length({root}) + 1) = ({root} || '{separator}') COLLATE {collation})",
+        column = column,
+        root = root,
+        collation = PATH_COLLATION,
+        separator = std::path::MAIN_SEPARATOR,
+    )
+}
+
+/// Tag a book, creating tags as needed; blank tags are ignored
+fn insert_book_tags(conn: &rusqlite::Connection, book_id: i64, tags: &[String]) -> rusqlite::Result<()> {
+    for tag in tags.iter().map(|t| t.trim()).filter(|t| !t.is_empty()) {
+        conn.execute("INSERT OR IGNORE INTO tags (name) VALUES (?)", [tag])?;
+        conn.execute(
+            "INSERT OR IGNORE INTO book_tags (book_id, tag_id)
+             SELECT ?, id FROM tags WHERE name = ?",
+            params![book_id, tag],
+        )?;
+    }
+    Ok(())
+}
+
+/// Link authors to a book, creating them as needed
+///
+/// Someone credited twice keeps their first role.
+fn insert_book_authors(conn: &rusqlite::Connection, book_id: i64, authors: &[NewAuthor]) -> rusqlite::Result<()> {
+    for author in authors {
+        let name = author.name.trim();
+        if name.is_empty() {
+            continue;
+        }
+        conn.execute(
+            "INSERT OR IGNORE INTO authors (name, sort_name) VALUES (?, ?)",
+            params![name, author.sort_name],
+        )?;
+        conn.execute(
+            "INSERT OR IGNORE INTO book_authors (book_id, author_id, role)
+             SELECT ?, id, ? FROM authors WHERE name = ?",
+            params![book_id, author.role, name],
+        )?;
+    }
+    Ok(())
+}
+
+/// Book IDs in the Up Next queue, in order
+fn up_next_order(conn: &rusqlite::Connection) -> rusqlite::Result<Vec<i64>> {
+    let mut stmt = conn.prepare("SELECT book_id FROM up_next ORDER BY position ASC, added_at ASC")?;
+    let ids = stmt.query_map([], |row| row.get(0))?.collect();
+    ids
+}
+
+/// Number the Up Next queue 0, 1, 2... in the given order
+fn write_up_next_order(conn: &rusqlite::Connection, order: &[i64]) -> rusqlite::Result<()> {
+    let mut stmt = conn.prepare("UPDATE up_next SET position = ? WHERE book_id = ? AND position IS NOT ?1")?;
+    for (position, book_id) in order.iter().enumerate() {
+        stmt.execute(params![position as i64, book_id])?;
+    }
+    Ok(())
+}
+
+fn row_to_embedding_job(row: &Row<'_>) -> rusqlite::Result<EmbeddingJob> {
+    Ok(EmbeddingJob {
+        book_id: row.get(0)?,
+        status: row.get(1)?,
+        priority: row.get(2)?,
+        attempts: row.get(3)?,
+        last_error: row.get(4)?,
+    })
+}
+
+fn row_to_collection(row: &Row<'_>) -> rusqlite::Result<Collection> {
+    Ok(Collection {
+        id: row.get(0)?,
+        name: row.get(1)?,
+        created_at: row.get(2)?,
+        book_count: row.get(3)?,
+    })
+}
+
+/// Convert a database row to a Book struct
+fn row_to_book(row: &Row<'_>) -> rusqlite::Result<Book> {
+    Ok(Book {
+        id: row.get(0)?,
+        path: row.get(1)?,
+        cover_path: row.get(2)?,
+        file_size: row.get(3)?,
+        file_hash: row.get(4)?,
+        title: row.get(5)?,
+        sort_title: row.get(6)?,
+        author: row.get(7)?,
+        author_sort: row.get(8)?,
+        series: row.get(9)?,
+        series_index: row.get(10)?,
+        description: row.get(11)?,
+        language: row.get(12)?,
+        publisher: row.get(13)?,
+        publish_date: row.get(14)?,
+        isbn: row.get(15)?,
+        calibre_id: row.get(16)?,
+        source: row.get(17)?,
+        date_added: row.get(18)?,
+        date_modified: row.get(19)?,
+        date_indexed: row.get(20)?,
+        embedding_status: row.get(21)?,
+        embedding_model: row.get(22)?,
+        container_path: row.get(23)?,
+        word_count: row.get(24)?,
+        format: row.get(25)?,
+        rating: row.get(26)?,
+        read_status: row.get(27)?,
+        notes: row.get(28)?,
+        progress_percent: row.get(29)?,
+        progress_location: row.get(30)?,
+    })
+}
+
+/// Normalize an ISBN to its 13-digit form
+pub fn normalize_isbn(isbn: &str) -> Option<String> {
+    let chars: String = isbn
+        .chars()
+        .filter(|c| c.is_ascii_digit() || *c == 'X' || *c == 'x')
+        .collect::<String>()
+        .to_uppercase();
+
+    match chars.len() {
+        13 if chars.chars().all(|c| c.is_ascii_digit()) => Some(chars),
+        10 => {
+            let core = format!("978{}", &chars[..9]);
+            let sum: u32 = core
+                .chars()
+                .filter_map(|c| c.to_digit(10))
+                .enumerate()
+                .map(|(i, d)| if i % 2 == 0 { d } else { d * 3 })
+                .sum();
+            Some(format!("{}{}", core, (10 - sum % 10) % 10))
+        }
+        _ => None,
+    }
+}
+
+/// Group books joined by `links` or by matching title + author
+///
+/// Groups are the connected components of both kinds of link; a group's
+/// reason is `reason` if any of its books were joined by `links`.
+fn cluster_duplicates(books: Vec<Book>, reason: &str, links: &[(usize, usize)]) -> Vec<DuplicateGroup> {
+    let mut parent: Vec<usize> = (0..books.len()).collect();
+    fn find(parent: &mut [usize], i: usize) -> usize {
+        let mut root = i;
+        while parent[root] != root {
+            root = parent[root];
+        }
+        parent[i] = root;
+        root
+    }
+
+    let mut strongly_linked = vec![false; books.len()];
+    for &(a, b) in links {
+        let (root_a, root_b) = (find(&mut parent, a), find(&mut parent, b));
+        parent[root_b] = root_a;
+        strongly_linked[a] = true;
+        strongly_linked[b] = true;
+    }
+
+    let mut by_title: HashMap<String, usize> = HashMap::new();
+    for (i, book) in books.iter().enumerate() {
+        if let Some(key) = title_author_key(&book.title, book.author.as_deref()) {
+            if let Some(&first) = by_title.get(&key) {
+                let (a, b) = (find(&mut parent, first), find(&mut parent, i));
+                parent[b] = a;
+            } else {
+                by_title.insert(key, i);
+            }
+        }
+    }
+
+    let mut components: HashMap<usize, Vec<usize>> = HashMap::new();
+    for i in 0..books.len() {
+        let root = find(&mut parent, i);
+        components.entry(root).or_default().push(i);
+    }
+
+    let mut groups: Vec<DuplicateGroup> = components
+        .into_values()
+        .filter(|members| members.len() > 1)
+        .map(|members| {
+            let reason = if members.iter().any(|&i| strongly_linked[i]) { reason } else { "titleAuthor" };
+            let group_books: Vec<Book> = members.iter().map(|&i| books[i].clone()).collect();
+            let suggested_keep_id = group_books
+                .iter()
+                .max_by_key(|b| {
+                    (
+                        b.rating.is_some(),
+                        b.embedding_status == "complete",
+                        b.read_status.is_some() || b.notes.is_some(),
+                        std::cmp::Reverse(b.id),
+                    )
+                })
+                .map(|b| b.id)
+                .unwrap_or_default();
+            DuplicateGroup {
+                reason: reason.to_string(),
+                books: group_books,
+                suggested_keep_id,
+            }
+        })
+        .collect();
+
+    groups.sort_by_key(|g| g.books[0].id);
+    groups
+}
+
+/// Key identifying a work by title and author, ignoring articles, subtitles,
+/// punctuation and author name order
+fn title_author_key(title: &str, author: Option<&str>) -> Option<String> {
+    fn words(s: &str) -> Vec<String> {
+        s.split(|c: char| !c.is_alphanumeric())
+            .filter(|w| !w.is_empty())
+            .map(|w| w.to_lowercase())
+            .collect()
+    }
+
+    let title = title.split([':', '(', '[']).next().unwrap_or(title);
+    let title = words(&generate_sort_title(title.trim())).join(" ");
+    let mut author = words(author?);
+    if title.is_empty() || author.is_empty() {
+        return None;
+    }
+    author.sort();
+    Some(format!("{}|{}", title, author.join(" ")))
+}
+
+// Extension trait for optional query results
+trait OptionalExt<T> {
+    fn optional(self) -> Result<Option<T>, rusqlite::Error>;
+}
+
+impl<T> OptionalExt<T> for Result<T, rusqlite::Error> {
     fn optional(self) -> Result<Option<T>, rusqlite::Error> {
         match self {
             Ok(v) => Ok(Some(v)),
@@ -795,3 +3071,1102 @@ impl<T> OptionalExt<T> for Result<T, rusqlite::Error> {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn test_db() -> (tempfile::TempDir, Database) {
+        let dir = tempfile::TempDir::new().unwrap();
+        let db = Database::new(&dir.path().join("test.db")).unwrap();
+        (dir, db)
+    }
+
+    #[test]
+    fn test_scan_produces_completed_history_row() {
+        let (dir, db) = test_db();
+        let library_dir = dir.path().join("books");
+        std::fs::create_dir(&library_dir).unwrap();
+        std::fs::write(library_dir.join("a.epub"), b"epub").unwrap();
+        std::fs::write(library_dir.join("b.epub"), b"epub").unwrap();
+
+        let library = db
+            .add_library("Books", library_dir.to_str().unwrap(), false, None)
+            .unwrap();
+
+        let record_id = db.start_scan_record(library.id).unwrap();
+        let running = db.get_scan_history(library.id, 10).unwrap();
+        assert_eq!(running.len(), 1);
+        assert_eq!(running[0].completed_at, None);
+
+        let books = crate::scanner::Scanner::new().fast_scan(&library_dir).unwrap();
+        let added = db.insert_books_batch(&books).unwrap();
+        db.complete_scan_record(record_id, books.len() as i64, added.len() as i64, 0, &[])
+            .unwrap();
+
+        let history = db.get_scan_history(library.id, 10).unwrap();
+        assert_eq!(history.len(), 1);
+        let record = &history[0];
+        assert!(record.completed_at.unwrap() >= record.started_at);
+        assert_eq!(record.books_found, Some(2));
+        assert_eq!(record.books_added, Some(2));
+        assert!(record.errors.is_empty());
+
+        // A scanned library can still be removed
+        db.remove_library(library.id).unwrap();
+        assert!(db.get_libraries().unwrap().is_empty());
+        assert!(db.get_scan_history(library.id, 10).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_batch_insert_returns_only_new_ids() {
+        let (dir, db) = test_db();
+        std::fs::write(dir.path().join("a.epub"), b"epub").unwrap();
+        let existing = crate::scanner::Scanner::new().fast_scan(dir.path()).unwrap();
+        let first = db.insert_books_batch(&existing).unwrap();
+        assert_eq!(first.len(), 1);
+
+        std::fs::write(dir.path().join("b.epub"), b"epub").unwrap();
+        let books = crate::scanner::Scanner::new().fast_scan(dir.path()).unwrap();
+        assert_eq!(books.len(), 2);
+
+        let added = db.insert_books_batch(&books).unwrap();
+        assert_eq!(added.len(), 1);
+        assert_ne!(added[0], first[0]);
+        assert!(db.get_book(added[0]).unwrap().path.ends_with("b.epub"));
+    }
+
+    #[test]
+    fn test_book_notes_set_get_and_search() {
+        let (dir, db) = test_db();
+        std::fs::write(dir.path().join("a.epub"), b"epub").unwrap();
+        std::fs::write(dir.path().join("b.epub"), b"epub").unwrap();
+        let books = crate::scanner::Scanner::new().fast_scan(dir.path()).unwrap();
+        let ids = db.insert_books_batch(&books).unwrap();
+
+        db.set_rating(ids[0], 4.0).unwrap();
+        db.set_book_notes(ids[0], Some("Loved the lighthouse chapters")).unwrap();
+        db.set_book_notes(ids[1], Some("Slow start, great ending")).unwrap();
+
+        let book = db.get_book(ids[0]).unwrap();
+        assert_eq!(book.notes.as_deref(), Some("Loved the lighthouse chapters"));
+        assert_eq!(book.rating, Some(4.0));
+
+        let found = db.search_book_notes("lighthouse", 10).unwrap();
+        assert_eq!(found.len(), 1);
+        assert_eq!(found[0].id, ids[0]);
+
+        let query = crate::db::BookQuery {
+            search: Some("ending".to_string()),
+            ..Default::default()
+        };
+        let result = db.query_books(&query).unwrap();
+        assert_eq!(result.items.len(), 1);
+        assert_eq!(result.items[0].id, ids[1]);
+
+        // Updating replaces the indexed text; clearing removes it
+        db.set_book_notes(ids[0], Some("Reread in winter")).unwrap();
+        assert!(db.search_book_notes("lighthouse", 10).unwrap().is_empty());
+        db.set_book_notes(ids[1], Some("  ")).unwrap();
+        assert_eq!(db.get_book(ids[1]).unwrap().notes, None);
+        assert!(db.search_book_notes("ending", 10).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_search_matches_tags() {
+        let (dir, db) = test_db();
+        std::fs::write(dir.path().join("a.epub"), b"epub").unwrap();
+        std::fs::write(dir.path().join("b.epub"), b"epub").unwrap();
+        let mut books = crate::scanner::Scanner::new().fast_scan(dir.path()).unwrap();
+        books.sort_by(|a, b| a.path.cmp(&b.path));
+        books[0].tags = vec!["Epic Fantasy".to_string(), " ".to_string()];
+        let ids = db.insert_books_batch(&books).unwrap();
+        assert_eq!(db.get_book_tags(ids[0]).unwrap(), vec!["Epic Fantasy"]);
+
+        let search = |text: &str| {
+            let query = crate::db::BookQuery { search: Some(text.to_string()), ..Default::default() };
+            db.query_books(&query).unwrap().items.iter().map(|b| b.id).collect::<Vec<_>>()
+        };
+        assert_eq!(search("fantasy"), vec![ids[0]]);
+
+        // Retagging updates the index
+        db.set_book_tags(ids[0], &[]).unwrap();
+        db.set_book_tags(ids[1], &["Fantasy".to_string(), "Fairy tales".to_string()]).unwrap();
+        assert_eq!(search("fantasy"), vec![ids[1]]);
+        assert_eq!(search("fairy"), vec![ids[1]]);
+
+        // Renaming a tag reindexes the books that have it
+        db.with_conn(|conn| Ok(conn.execute("UPDATE tags SET name = 'Folklore' WHERE name = 'Fairy tales'", [])?))
+            .unwrap();
+        assert!(search("fairy").is_empty());
+        assert_eq!(search("folklore"), vec![ids[1]]);
+        assert_eq!(search("fantasy"), vec![ids[1]]);
+
+        // Deleting a book drops its tags from the index
+        db.with_conn(|conn| Ok(conn.execute("DELETE FROM books WHERE id = ?", [ids[1]])?)).unwrap();
+        assert!(search("fantasy").is_empty());
+    }
+
+    #[test]
+    fn test_search_input_is_sanitized() {
+        let db = Database::new_in_memory().unwrap();
+        db.with_conn(|conn| {
+            conn.execute_batch(
+                "INSERT INTO books (id, path, title, author) VALUES
+                    (1, '/b/1.epub', 'The Hobbit', 'J. R. R. Tolkien'),
+                    (2, '/b/2.epub', 'Tolkien: A Biography', 'Humphrey Carpenter'),
+                    (3, '/b/3.epub', 'The Lord of the Rings', 'J. R. R. Tolkien');"
+            )?;
+            Ok(())
+        })
+        .unwrap();
+        db.set_book_notes(2, Some("Lent to a friend")).unwrap();
+
+        let search = |text: &str| {
+            let query = crate::db::BookQuery {
+                search: Some(text.to_string()),
+                sort_by: Some("title".to_string()),
+                sort_order: Some("asc".to_string()),
+                ..Default::default()
+            };
+            db.query_books(&query).unwrap().items.iter().map(|b| b.id).collect::<Vec<_>>()
+        };
+
+        // Input that is invalid FTS5 syntax no longer errors
+        assert_eq!(search(r#"hobbit" ("#), vec![1]);
+        assert_eq!(search("tolkien AND"), vec![1, 3, 2]);
+        assert_eq!(search(r#""lord of the""#), vec![3]);
+        assert_eq!(search("hob*"), vec![1]);
+
+        // Field prefixes narrow the match, also to notes
+        assert_eq!(search("author:tolkien"), vec![1, 3]);
+        assert_eq!(search("title:tolkien"), vec![2]);
+        assert_eq!(search("notes:friend"), vec![2]);
+        assert_eq!(db.search_book_notes("friend)", 10).unwrap().len(), 1);
+    }
+
+    #[test]
+    fn test_relevance_sort_ranks_best_matches_first() {
+        let db = Database::new_in_memory().unwrap();
+        db.with_conn(|conn| {
+            conn.execute_batch(
+                "INSERT INTO books (id, path, title, author, description, date_added) VALUES
+                    (1, '/b/1.epub', 'Travels', 'Someone', 'A long voyage, one dragon in passing, then home', 300),
+                    (2, '/b/2.epub', 'The Dragon Republic', 'R. F. Kuang', 'War and gods', 100),
+                    (3, '/b/3.epub', 'Dragon Dragon', 'Someone Else', 'Dragons everywhere, a dragon hoard', 200),
+                    (4, '/b/4.epub', 'Unrelated', 'Nobody', 'Nothing to see', 400);"
+            )?;
+            Ok(())
+        })
+        .unwrap();
+        db.set_book_tags(4, &["Dragon".to_string()]).unwrap();
+
+        let ids = |sort_by: &str, search: Option<&str>| {
+            let query = crate::db::BookQuery {
+                search: search.map(String::from),
+                sort_by: Some(sort_by.to_string()),
+                ..Default::default()
+            };
+            let result = db.query_books(&query).unwrap();
+            (result.items.iter().map(|b| b.id).collect::<Vec<_>>(), result.total)
+        };
+
+        // Title matches beat a passing mention; a tag-only match comes last
+        assert_eq!(ids("relevance", Some("dragon")), (vec![3, 2, 1, 4], 4));
+        assert_eq!(ids("dateAdded", Some("dragon")).0, vec![4, 1, 3, 2]);
+
+        // Without a search term, relevance means newest first
+        assert_eq!(ids("relevance", None).0, vec![4, 1, 3, 2]);
+    }
+
+    #[test]
+    fn test_half_star_ratings() {
+        let db = Database::new_in_memory().unwrap();
+        db.with_conn(|conn| {
+            conn.execute_batch(
+                "INSERT INTO books (id, path, title) VALUES (1, '/b/1.epub', 'One'), (2, '/b/2.epub', 'Two')"
+            )?;
+            Ok(())
+        })
+        .unwrap();
+
+        db.set_rating(1, 3.5).unwrap();
+        db.set_rating(2, 0.5).unwrap();
+        assert_eq!(db.get_book(1).unwrap().rating, Some(3.5));
+        assert_eq!(db.get_book(2).unwrap().rating, Some(0.5));
+
+        for invalid in [0.0, 3.25, 5.5] {
+            assert!(matches!(db.set_rating(1, invalid), Err(AppError::InvalidInput(_))));
+        }
+        assert_eq!(db.get_book(1).unwrap().rating, Some(3.5));
+
+        let query = crate::db::BookQuery {
+            min_rating: Some(3.5),
+            ..Default::default()
+        };
+        let ids: Vec<i64> = db.query_books(&query).unwrap().items.iter().map(|b| b.id).collect();
+        assert_eq!(ids, vec![1]);
+    }
+
+    #[test]
+    fn test_batch_rating_and_read_status() {
+        let db = Database::new_in_memory().unwrap();
+        db.with_conn(|conn| {
+            conn.execute_batch(
+                "INSERT INTO books (id, path, title) VALUES
+                    (1, '/b/1.epub', 'One'), (2, '/b/2.epub', 'Two'), (3, '/b/3.epub', 'Three');
+                 INSERT INTO ratings (book_id, rating, read_status, date_rated) VALUES (2, 1.0, 'reading', 100);"
+            )?;
+            Ok(())
+        })
+        .unwrap();
+
+        // Duplicates count once and unknown books are skipped
+        assert_eq!(db.set_ratings_batch(&[1, 2, 2, 99], 4.5).unwrap(), 2);
+        assert_eq!(db.get_book(1).unwrap().rating, Some(4.5));
+        assert_eq!(db.get_book(2).unwrap().rating, Some(4.5));
+        assert_eq!(db.get_book(3).unwrap().rating, None);
+        assert_eq!(db.get_book(2).unwrap().read_status.as_deref(), Some("reading"));
+
+        assert_eq!(db.set_read_status_batch(&[2, 3], "finished").unwrap(), 2);
+        for id in [2, 3] {
+            let book = db.get_book(id).unwrap();
+            assert_eq!(book.read_status.as_deref(), Some("finished"));
+        }
+        assert_eq!(db.get_book(2).unwrap().rating, Some(4.5));
+
+        assert!(matches!(db.set_ratings_batch(&[1, 3], 4.25), Err(AppError::InvalidInput(_))));
+        assert!(matches!(db.set_read_status_batch(&[1, 3], "skimmed"), Err(AppError::InvalidInput(_))));
+        assert_eq!(db.get_book(3).unwrap().rating, None);
+        assert_eq!(db.set_ratings_batch(&[], 3.0).unwrap(), 0);
+    }
+
+    #[test]
+    fn test_collections() {
+        let db = Database::new_in_memory().unwrap();
+        db.with_conn(|conn| {
+            conn.execute_batch(
+                "INSERT INTO books (id, path, title) VALUES
+                    (1, '/b/1.epub', 'One'), (2, '/b/2.epub', 'Two'), (3, '/b/3.epub', 'Three');"
+            )?;
+            Ok(())
+        })
+        .unwrap();
+
+        let beach = db.create_collection("  Beach Reads ").unwrap();
+        assert_eq!(beach.name, "Beach Reads");
+        let work = db.create_collection("Work").unwrap();
+        assert!(matches!(db.create_collection("beach reads"), Err(AppError::InvalidInput(_))));
+        assert!(matches!(db.create_collection(" "), Err(AppError::InvalidInput(_))));
+
+        assert_eq!(db.add_books_to_collection(beach.id, &[1, 2, 2, 99]).unwrap(), 2);
+        assert_eq!(db.add_books_to_collection(work.id, &[2, 3]).unwrap(), 2);
+        assert!(matches!(db.add_books_to_collection(999, &[1]), Err(AppError::NotFound(_))));
+
+        let query = crate::db::BookQuery {
+            collection_id: Some(beach.id),
+            ..Default::default()
+        };
+        let mut ids: Vec<i64> = db.query_books(&query).unwrap().items.iter().map(|b| b.id).collect();
+        ids.sort();
+        assert_eq!(ids, vec![1, 2]);
+
+        let names: Vec<String> = db.get_book_collections(2).unwrap().into_iter().map(|c| c.name).collect();
+        assert_eq!(names, vec!["Beach Reads", "Work"]);
+
+        // Renaming to its own name in another case is fine; taking another's isn't
+        assert_eq!(db.rename_collection(work.id, "WORK").unwrap().name, "WORK");
+        assert!(matches!(db.rename_collection(work.id, "Beach Reads"), Err(AppError::InvalidInput(_))));
+
+        assert_eq!(db.remove_books_from_collection(beach.id, &[2, 3]).unwrap(), 1);
+        let counts: Vec<(String, i64)> =
+            db.get_collections().unwrap().into_iter().map(|c| (c.name, c.book_count)).collect();
+        assert_eq!(counts, vec![("Beach Reads".to_string(), 1), ("WORK".to_string(), 2)]);
+
+        // Deleting a collection or a book only removes memberships
+        db.delete_book(3).unwrap();
+        db.delete_collection(beach.id).unwrap();
+        assert!(matches!(db.delete_collection(beach.id), Err(AppError::NotFound(_))));
+        assert_eq!(db.get_collection(work.id).unwrap().book_count, 1);
+        assert!(db.get_book(1).is_ok());
+    }
+
+    #[test]
+    fn test_reorder_up_next() {
+        let db = Database::new_in_memory().unwrap();
+        db.with_conn(|conn| {
+            conn.execute_batch(
+                "INSERT INTO books (id, path, title) VALUES
+                    (1, '/b/1.epub', 'One'), (2, '/b/2.epub', 'Two'),
+                    (3, '/b/3.epub', 'Three'), (4, '/b/4.epub', 'Four');"
+            )?;
+            Ok(())
+        })
+        .unwrap();
+        for id in 1..=4 {
+            db.add_to_up_next(id).unwrap();
+        }
+        let queue = |db: &Database| -> Vec<(i64, i64)> {
+            db.with_conn(|conn| {
+                let mut stmt = conn.prepare("SELECT book_id, position FROM up_next ORDER BY position")?;
+                let rows = stmt.query_map([], |row| Ok((row.get(0)?, row.get(1)?)))?.collect::<Result<_, _>>()?;
+                Ok(rows)
+            })
+            .unwrap()
+        };
+
+        db.reorder_up_next(3, 0).unwrap();
+        assert_eq!(queue(&db), vec![(3, 0), (1, 1), (2, 2), (4, 3)]);
+
+        db.reorder_up_next(3, 100).unwrap();
+        assert_eq!(queue(&db), vec![(1, 0), (2, 1), (4, 2), (3, 3)]);
+
+        db.reorder_up_next(1, 2).unwrap();
+        let ids: Vec<i64> = db.get_up_next_books().unwrap().iter().map(|b| b.id).collect();
+        assert_eq!(ids, vec![2, 4, 1, 3]);
+
+        db.remove_from_up_next(4).unwrap();
+        assert_eq!(queue(&db), vec![(2, 0), (1, 1), (3, 2)]);
+        db.add_to_up_next(4).unwrap();
+        assert_eq!(queue(&db).last(), Some(&(4, 3)));
+
+        assert!(matches!(db.reorder_up_next(99, 0), Err(AppError::NotFound(_))));
+        assert_eq!(db.clear_up_next().unwrap(), 4);
+        assert_eq!(db.get_up_next_count().unwrap(), 0);
+    }
+
+    #[test]
+    fn test_embedding_job_queue() {
+        let db = Database::new_in_memory().unwrap();
+        db.with_conn(|conn| {
+            conn.execute_batch(
+                "INSERT INTO books (id, path, title) VALUES
+                    (1, '/b/1.epub', 'One'), (2, '/b/2.epub', 'Two'), (3, '/b/3.epub', 'Three');"
+            )?;
+            Ok(())
+        })
+        .unwrap();
+
+        assert_eq!(db.enqueue_embedding_jobs(&[1, 2, 99], 0).unwrap(), 2);
+        assert_eq!(db.enqueue_embedding_jobs(&[3], 100).unwrap(), 1);
+        // Re-queueing a waiting book never lowers its priority
+        db.enqueue_embedding_jobs(&[2], 50).unwrap();
+        db.enqueue_embedding_jobs(&[2], 10).unwrap();
+
+        let claimed: Vec<i64> = std::iter::from_fn(|| db.claim_embedding_job().unwrap())
+            .map(|job| {
+                assert_eq!((job.status.as_str(), job.attempts), ("running", 1));
+                job.book_id
+            })
+            .collect();
+        assert_eq!(claimed, vec![3, 2, 1]);
+
+        // Running jobs aren't queued twice
+        assert_eq!(db.enqueue_embedding_jobs(&[3], 0).unwrap(), 0);
+
+        db.complete_embedding_job(3).unwrap();
+        assert!(db.fail_embedding_job(2, "timed out", 2).unwrap());
+        db.release_embedding_job(1).unwrap();
+        assert_eq!(db.get_embedding_job(1).unwrap().unwrap().attempts, 0);
+
+        // A second failure uses up the attempts
+        assert_eq!(db.claim_embedding_job().unwrap().unwrap().book_id, 2);
+        assert!(!db.fail_embedding_job(2, "timed out again", 2).unwrap());
+        let job = db.get_embedding_job(2).unwrap().unwrap();
+        assert_eq!((job.status.as_str(), job.attempts), ("failed", 2));
+        assert_eq!(job.last_error.as_deref(), Some("timed out again"));
+
+        // A crash mid-job leaves it running until the next start
+        assert_eq!(db.claim_embedding_job().unwrap().unwrap().book_id, 1);
+        assert_eq!(db.requeue_running_embedding_jobs().unwrap(), 1);
+        assert_eq!(db.get_embedding_job(1).unwrap().unwrap().status, "pending");
+
+        db.update_embedding_status(2, "failed").unwrap();
+        assert_eq!(db.retry_failed_embedding_jobs().unwrap(), 1);
+        let job = db.get_embedding_job(2).unwrap().unwrap();
+        assert_eq!((job.status.as_str(), job.attempts), ("pending", 0));
+        assert_eq!(db.get_book(2).unwrap().embedding_status, "pending");
+
+        // A finished book queued again starts over
+        db.enqueue_embedding_jobs(&[3], 5).unwrap();
+        let job = db.get_embedding_job(3).unwrap().unwrap();
+        assert_eq!((job.status.as_str(), job.priority, job.attempts), ("pending", 5, 0));
+        assert_eq!(db.get_embedding_job(99).unwrap(), None);
+    }
+
+    #[test]
+    fn test_tag_distribution_rolls_up_parents() {
+        let db = Database::new_in_memory().unwrap();
+        db.with_conn(|conn| {
+            conn.execute_batch(
+                "INSERT INTO books (id, path, title) VALUES
+                    (1, '/b/1.epub', 'One'), (2, '/b/2.epub', 'Two'),
+                    (3, '/b/3.epub', 'Three'), (4, '/b/4.epub', 'Four');
+                 INSERT INTO tags (id, name, parent_id) VALUES
+                    (1, 'Fiction', NULL),
+                    (2, 'Fantasy', 1),
+                    (3, 'Science Fiction', 1),
+                    (4, 'Poetry', NULL),
+                    (5, 'Unused', NULL);
+                 INSERT INTO book_tags (book_id, tag_id) VALUES
+                    (1, 2), (2, 2), (2, 3), (3, 1), (3, 3), (4, 4);"
+            )?;
+            Ok(())
+        })
+        .unwrap();
+
+        let distribution = db.get_tag_distribution(10).unwrap();
+        assert_eq!(distribution.total_books, 4);
+
+        let top: Vec<(&str, i64)> = distribution.top.iter().map(|t| (t.name.as_str(), t.count)).collect();
+        assert_eq!(top, vec![("Fantasy", 2), ("Science Fiction", 2), ("Fiction", 1), ("Poetry", 1)]);
+        assert_eq!(distribution.top[0].percent, 50.0);
+
+        // Fiction covers books 1-3 once each, though book 2 has two subgenres
+        let fiction = &distribution.tree[0];
+        assert_eq!((fiction.name.as_str(), fiction.count, fiction.total), ("Fiction", 1, 3));
+        assert_eq!(fiction.percent, 75.0);
+        let children: Vec<&str> = fiction.children.iter().map(|c| c.name.as_str()).collect();
+        assert_eq!(children, vec!["Fantasy", "Science Fiction"]);
+        assert_eq!(distribution.tree.len(), 2);
+        assert_eq!(distribution.tree[1].name, "Poetry");
+
+        assert_eq!(db.get_tag_distribution(1).unwrap().tree.len(), 1);
+    }
+
+    #[test]
+    fn test_currently_reading_by_recency() {
+        let db = Database::new_in_memory().unwrap();
+        db.with_conn(|conn| {
+            conn.execute_batch(
+                "INSERT INTO books (id, path, title) VALUES
+                    (1, '/b/1.epub', 'Older'),
+                    (2, '/b/2.epub', 'Newer'),
+                    (3, '/b/3.epub', 'Finished'),
+                    (4, '/b/4.epub', 'No Progress');
+                 INSERT INTO ratings (book_id, read_status, date_rated, progress_percent, last_read_at) VALUES
+                    (1, 'reading', 100, 12.5, 1000),
+                    (2, 'reading', 100, 80.0, 2000),
+                    (3, 'finished', 100, 100.0, 3000),
+                    (4, 'reading', 500, NULL, NULL);"
+            )?;
+            Ok(())
+        })
+        .unwrap();
+
+        let reading = db.get_currently_reading(10).unwrap();
+        let ids: Vec<i64> = reading.iter().map(|r| r.book.id).collect();
+        assert_eq!(ids, vec![2, 1, 4]);
+        assert_eq!(reading[0].progress_percent, Some(80.0));
+        assert_eq!(reading[0].book.read_status.as_deref(), Some("reading"));
+        assert_eq!(reading[2].progress_percent, None);
+
+        // Recording progress moves a book to the front
+        assert_eq!(db.set_reading_progress(1, 60.0, None).unwrap(), None);
+        let reading = db.get_currently_reading(10).unwrap();
+        assert_eq!(reading[0].book.id, 1);
+        assert_eq!(reading[0].progress_percent, Some(60.0));
+    }
+
+    #[test]
+    fn test_reading_progress_sets_read_status() {
+        let db = Database::new_in_memory().unwrap();
+        db.with_conn(|conn| {
+            conn.execute_batch("INSERT INTO books (id, path, title) VALUES (1, '/b/1.epub', 'One')")?;
+            Ok(())
+        })
+        .unwrap();
+
+        assert_eq!(db.set_reading_progress(1, 0.0, None).unwrap(), None);
+        assert_eq!(db.get_book(1).unwrap().read_status.as_deref(), Some("unread"));
+
+        let cfi = "epubcfi(/6/4!/4/2/1:0)";
+        assert_eq!(db.set_reading_progress(1, 12.5, Some(cfi)).unwrap(), Some("reading"));
+        assert_eq!(db.set_reading_progress(1, 40.0, Some(cfi)).unwrap(), None);
+        let book = db.get_book(1).unwrap();
+        assert_eq!(book.read_status.as_deref(), Some("reading"));
+        assert_eq!(book.progress_percent, Some(40.0));
+        assert_eq!(book.progress_location.as_deref(), Some(cfi));
+
+        // Progress past the end is clamped and finishes the book
+        assert_eq!(db.set_reading_progress(1, 140.0, None).unwrap(), Some("finished"));
+        let book = db.get_book(1).unwrap();
+        assert_eq!(book.read_status.as_deref(), Some("finished"));
+        assert_eq!(book.progress_percent, Some(100.0));
+        assert_eq!(book.progress_location, None);
+        let finished: Option<i64> = db
+            .with_conn(|conn| {
+                Ok(conn.query_row("SELECT date_finished FROM ratings WHERE book_id = 1", [], |row| row.get(0))?)
+            })
+            .unwrap();
+        assert!(finished.is_some());
+    }
+
+    #[test]
+    fn test_personal_reading_speed() {
+        let db = Database::new_in_memory().unwrap();
+        db.with_conn(|conn| {
+            conn.execute_batch(
+                "INSERT INTO books (id, path, title, word_count) VALUES
+                    (1, '/b/1.epub', 'Novella', 30000),
+                    (2, '/b/2.epub', 'Novel', 90000),
+                    (3, '/b/3.epub', 'Unknown Length', NULL),
+                    (4, '/b/4.epub', 'In Progress', 50000);
+                 INSERT INTO ratings (book_id, read_status, date_started, date_finished) VALUES
+                    (1, 'finished', 1000, 1000 + 100 * 60),
+                    (2, 'finished', 5000, 5000 + 500 * 60),
+                    (3, 'finished', 1000, 9000),
+                    (4, 'reading', 1000, NULL);"
+            )?;
+            Ok(())
+        })
+        .unwrap();
+
+        // Default speed until the first book is finished
+        assert_eq!(db.get_settings().unwrap().words_per_minute(), crate::epub::DEFAULT_WORDS_PER_MINUTE);
+
+        // 120k words over 600 minutes
+        assert_eq!(db.update_user_wpm().unwrap(), Some(200.0));
+        let wpm = db.get_settings().unwrap().words_per_minute();
+        assert_eq!(wpm, 200.0);
+        assert_eq!(crate::epub::estimate_reading_time(50_000, wpm), 250.0);
+
+        // Starting and finishing a book stamps the reading period
+        db.set_read_status(4, "finished").unwrap();
+        let samples = db.get_reading_speed_samples().unwrap();
+        assert_eq!(samples.len(), 3);
+        db.set_read_status(4, "reading").unwrap();
+        assert_eq!(db.get_reading_speed_samples().unwrap().len(), 2);
+    }
+
+    #[test]
+    fn test_isolated_books() {
+        let db = Database::new_in_memory().unwrap();
+        db.with_conn(|conn| {
+            conn.execute_batch(
+                "INSERT INTO books (id, path, title, embedding_status) VALUES
+                    (1, '/b/1.epub', 'Connected', 'complete'),
+                    (2, '/b/2.epub', 'Also Connected', 'complete'),
+                    (3, '/b/3.epub', 'Poems', 'pending'),
+                    (4, '/b/4.epub', 'Weakly Linked', 'complete');
+                 INSERT INTO book_edges (source_id, target_id, edge_type, weight) VALUES
+                    (1, 2, 'content', 0.8),
+                    (4, 1, 'content', 0.1);"
+            )?;
+            Ok(())
+        })
+        .unwrap();
+
+        let isolated = db.get_isolated_books(0.3, 10).unwrap();
+        let ids: Vec<i64> = isolated.iter().map(|b| b.id).collect();
+        assert_eq!(ids, vec![3, 4]);
+        assert_eq!(isolated[0].embedding_status, "pending");
+
+        // Any edge at all counts without a threshold
+        let ids: Vec<i64> = db.get_isolated_books(0.0, 10).unwrap().iter().map(|b| b.id).collect();
+        assert_eq!(ids, vec![3]);
+    }
+
+    #[test]
+    fn test_author_content_links() {
+        let db = Database::new_in_memory().unwrap();
+        db.with_conn(|conn| {
+            conn.execute_batch(
+                "INSERT INTO books (id, path, title, author) VALUES
+                    (1, '/b/1.epub', 'One', 'Ann Leckie'),
+                    (2, '/b/2.epub', 'Two', 'Ann Leckie'),
+                    (3, '/b/3.epub', 'Three', 'Unlinked Name'),
+                    (4, '/b/4.epub', 'Four', NULL);
+                 INSERT INTO authors (id, name) VALUES (1, 'Martha Wells');
+                 INSERT INTO book_authors (book_id, author_id) VALUES (3, 1);
+                 INSERT INTO book_edges (source_id, target_id, edge_type, weight) VALUES
+                    (1, 2, 'content', 0.9),
+                    (1, 3, 'content', 0.8),
+                    (1, 3, 'author', 0.8),
+                    (2, 3, 'content', 0.2),
+                    (2, 4, 'content', 0.9);"
+            )?;
+            Ok(())
+        })
+        .unwrap();
+
+        // Same-author, non-content, weak and author-less edges drop out, and
+        // linked authors win over the free-text column
+        let links = db.get_author_content_links(0.3).unwrap();
+        assert_eq!(links.len(), 1);
+        assert_eq!((links[0].source_book, links[0].target_book), (1, 3));
+        assert_eq!(links[0].source_author, "Ann Leckie");
+        assert_eq!(links[0].target_author, "Martha Wells");
+    }
+
+    #[test]
+    fn test_query_books_by_source() {
+        let db = Database::new_in_memory().unwrap();
+        db.with_conn(|conn| {
+            conn.execute_batch(
+                "INSERT INTO books (id, path, title) VALUES (1, '/b/1.epub', 'Scanned');
+                 INSERT INTO books (id, path, title, source) VALUES
+                    (2, '/b/2.epub', 'Curated', 'calibre'),
+                    (3, '/b/3.epub', 'Imported', 'import');"
+            )?;
+            Ok(())
+        })
+        .unwrap();
+
+        let query = crate::db::BookQuery {
+            source: Some("calibre".to_string()),
+            ..Default::default()
+        };
+        let result = db.query_books(&query).unwrap();
+        assert_eq!(result.total, 1);
+        assert_eq!(result.items[0].id, 2);
+        assert_eq!(result.items[0].source, "calibre");
+
+        // Books without an explicit source were scanned
+        let query = crate::db::BookQuery {
+            source: Some("scan".to_string()),
+            ..Default::default()
+        };
+        let ids: Vec<i64> = db.query_books(&query).unwrap().items.iter().map(|b| b.id).collect();
+        assert_eq!(ids, vec![1]);
+    }
+
+    #[test]
+    fn test_null_sort_fields_order_and_backfill() {
+        let db = Database::new_in_memory().unwrap();
+        db.with_conn(|conn| {
+            conn.execute_batch(
+                "INSERT INTO books (id, path, title, author) VALUES
+                    (1, '/b/1.epub', 'The Zebra', 'Anne Young'),
+                    (2, '/b/2.epub', 'Middlemarch', 'George Eliot');
+                 INSERT INTO books (id, path, title, sort_title, author, author_sort) VALUES
+                    (3, '/b/3.epub', 'Atlas', 'Atlas', 'Zed', 'Baker, Zed');"
+            )?;
+            Ok(())
+        })
+        .unwrap();
+
+        let sorted_ids = |sort_by: &str| -> Vec<i64> {
+            let query = crate::db::BookQuery {
+                sort_by: Some(sort_by.to_string()),
+                sort_order: Some("asc".to_string()),
+                ..Default::default()
+            };
+            db.query_books(&query).unwrap().items.iter().map(|b| b.id).collect()
+        };
+
+        // Null sort fields fall back to the display value instead of scattering
+        assert_eq!(sorted_ids("title"), vec![3, 2, 1]);
+        assert_eq!(sorted_ids("author"), vec![1, 3, 2]);
+
+        assert_eq!(db.regenerate_sort_fields().unwrap(), 2);
+        let zebra = db.get_book(1).unwrap();
+        assert_eq!(zebra.sort_title.as_deref(), Some("Zebra"));
+        assert_eq!(zebra.author_sort.as_deref(), Some("Young, Anne"));
+        assert_eq!(db.get_book(3).unwrap().author_sort.as_deref(), Some("Baker, Zed"));
+
+        // Generated sort fields drive ordering; a second pass has nothing to do
+        assert_eq!(sorted_ids("author"), vec![3, 2, 1]);
+        assert_eq!(db.regenerate_sort_fields().unwrap(), 0);
+    }
+
+    #[test]
+    fn test_duplicate_editions_by_isbn() {
+        let db = Database::new_in_memory().unwrap();
+        db.with_conn(|conn| {
+            conn.execute_batch(
+                "INSERT INTO books (id, path, title, author, isbn) VALUES
+                    (1, '/b/dune.epub', 'Dune', 'Frank Herbert', '0-441-17271-7'),
+                    (2, '/calibre/dune (1).epub', 'Dune (Deluxe)', 'Herbert, F.', '9780441172719'),
+                    (3, '/b/other.epub', 'Emma', 'Jane Austen', '9780141439587');"
+            )?;
+            Ok(())
+        })
+        .unwrap();
+        db.set_rating(2, 5.0).unwrap();
+
+        let groups = db.find_duplicate_editions().unwrap();
+        assert_eq!(groups.len(), 1);
+        assert_eq!(groups[0].reason, "isbn");
+        let ids: Vec<i64> = groups[0].books.iter().map(|b| b.id).collect();
+        assert_eq!(ids, vec![1, 2]);
+        assert_eq!(groups[0].suggested_keep_id, 2);
+
+        assert_eq!(db.merge_duplicate_editions(2, &[1]).unwrap(), 1);
+        assert!(db.find_duplicate_editions().unwrap().is_empty());
+        assert_eq!(db.get_book(2).unwrap().rating, Some(5.0));
+    }
+
+    #[test]
+    fn test_duplicate_editions_by_title_and_author() {
+        let db = Database::new_in_memory().unwrap();
+        db.with_conn(|conn| {
+            conn.execute_batch(
+                "INSERT INTO books (id, path, title, author) VALUES
+                    (1, '/b/a.epub', 'The Left Hand of Darkness', 'Ursula K. Le Guin'),
+                    (2, '/b/b.epub', 'Left Hand of Darkness: A Novel', 'Le Guin, Ursula K.'),
+                    (3, '/b/c.epub', 'The Left Hand of Darkness', 'Someone Else'),
+                    (4, '/b/d.epub', 'Untitled', NULL),
+                    (5, '/b/e.epub', 'Untitled', NULL);"
+            )?;
+            Ok(())
+        })
+        .unwrap();
+        db.set_read_status(1, "finished").unwrap();
+        db.set_book_notes(2, Some("Reread the ice crossing")).unwrap();
+
+        let groups = db.find_duplicate_editions().unwrap();
+        assert_eq!(groups.len(), 1);
+        assert_eq!(groups[0].reason, "titleAuthor");
+        let ids: Vec<i64> = groups[0].books.iter().map(|b| b.id).collect();
+        assert_eq!(ids, vec![1, 2]);
+        assert_eq!(groups[0].suggested_keep_id, 1);
+
+        // Merging carries over notes the kept book lacks
+        db.merge_duplicate_editions(1, &[2]).unwrap();
+        let kept = db.get_book(1).unwrap();
+        assert_eq!(kept.read_status.as_deref(), Some("finished"));
+        assert_eq!(kept.notes.as_deref(), Some("Reread the ice crossing"));
+        assert!(db.get_book(2).is_err());
+    }
+
+    #[test]
+    fn test_find_duplicates_by_hash_then_title() {
+        let db = Database::new_in_memory().unwrap();
+        db.with_conn(|conn| {
+            conn.execute_batch(
+                "INSERT INTO books (id, path, title, author, file_hash) VALUES
+                    (1, '/b/dune.epub', 'Dune', 'Frank Herbert', 'aaa'),
+                    (2, '/backup/renamed.epub', 'Unknown', NULL, 'aaa'),
+                    (3, '/b/dune-2.epub', 'Dune', 'Herbert, Frank', NULL),
+                    (4, '/b/emma.epub', 'Emma', 'Jane Austen', 'bbb'),
+                    (5, '/c/emma.epub', 'Emma', 'Jane Austen', NULL),
+                    (6, '/c/persuasion.epub', 'Persuasion', 'Jane Austen', 'ccc'),
+                    (7, '/d/persuasion.epub', 'Persuasion', 'Jane Austen', 'ccc');"
+            )?;
+            Ok(())
+        })
+        .unwrap();
+        db.set_rating(3, 4.0).unwrap();
+
+        assert_eq!(db.books_grouped_by_hash().unwrap(), vec![vec![1, 2], vec![6, 7]]);
+
+        // The hash joins the renamed copy, the title its re-download
+        let groups = db.find_duplicates().unwrap();
+        let summary: Vec<(Vec<i64>, &str, i64)> = groups
+            .iter()
+            .map(|g| (g.books.iter().map(|b| b.id).collect(), g.reason.as_str(), g.suggested_keep_id))
+            .collect();
+        assert_eq!(
+            summary,
+            vec![(vec![1, 2, 3], "fileHash", 3), (vec![4, 5], "titleAuthor", 4), (vec![6, 7], "fileHash", 6)]
+        );
+    }
+
+    #[test]
+    fn test_sync_delta_since_cursor() {
+        let db = Database::new_in_memory().unwrap();
+        db.with_conn(|conn| {
+            conn.execute_batch(
+                "INSERT INTO books (id, path, title, date_modified) VALUES
+                    (1, '/b/old.epub', 'Old', 1000),
+                    (2, '/b/edited.epub', 'Edited', 1000),
+                    (3, '/b/gone.epub', 'Gone', 1000)",
+            )?;
+            Ok(())
+        })
+        .unwrap();
+
+        let cursor = db.get_books_modified_since(0).unwrap().server_time;
+        assert!(cursor > 1000);
+
+        db.update_book(2, &BookUpdate { title: Some("Edited again".into()), ..Default::default() })
+            .unwrap();
+        db.delete_book(3).unwrap();
+
+        let modified = db.get_books_modified_since(cursor).unwrap();
+        let ids: Vec<i64> = modified.items.iter().map(|b| b.id).collect();
+        assert_eq!(ids, vec![2]);
+        assert!(modified.server_time >= cursor);
+
+        let deleted = db.get_deleted_since(cursor).unwrap();
+        assert_eq!(deleted.items.len(), 1);
+        assert_eq!(deleted.items[0].book_id, 3);
+        assert_eq!(deleted.items[0].path, "/b/gone.epub");
+        assert!(db.get_deleted_since(deleted.server_time + 1).unwrap().items.is_empty());
+    }
+
+    #[test]
+    fn test_unhashed_books_stay_within_their_library() {
+        let db = Database::new_in_memory().unwrap();
+        db.with_conn(|conn| {
+            conn.execute_batch(
+                "INSERT INTO books (id, path, title) VALUES
+                    (1, '/lib/books/a.epub', 'A'),
+                    (2, '/lib/books-extra/b.epub', 'B'),
+                    (3, '/lib/bookshelf/c.epub', 'C')",
+            )?;
+            Ok(())
+        })
+        .unwrap();
+
+        let ids = |root: Option<&str>| {
+            db.get_unhashed_books(root).unwrap().into_iter().map(|(id, _)| id).collect::<Vec<_>>()
+        };
+        assert_eq!(ids(Some("/lib/books")), vec![1]);
+        assert_eq!(ids(Some("/lib/books/")), vec![1]);
+        assert_eq!(ids(Some("/lib/books_")), Vec::<i64>::new());
+        assert_eq!(ids(None), vec![1, 2, 3]);
+    }
+
+    #[test]
+    fn test_calibre_links_stay_within_their_library() {
+        let db = Database::new_in_memory().unwrap();
+        db.with_conn(|conn| {
+            conn.execute_batch(
+                "INSERT INTO books (id, path, title, calibre_id, date_modified) VALUES
+                    (1, '/lib/books/a.epub', 'A', 1, 10),
+                    (2, '/lib/books-extra/b.epub', 'B', 1, 20),
+                    (3, '/lib/books-extra/c.epub', 'C', 2, 30)",
+            )?;
+            Ok(())
+        })
+        .unwrap();
+
+        // Calibre ids repeat across libraries, so each only sees its own books
+        assert_eq!(db.get_calibre_links("/lib/books").unwrap(), HashMap::from([(1, (1, 10))]));
+        assert_eq!(
+            db.get_calibre_links("/lib/books-extra").unwrap(),
+            HashMap::from([(1, (2, 20)), (2, (3, 30))])
+        );
+    }
+
+    #[test]
+    fn test_embedding_coverage_per_library() {
+        let db = Database::new_in_memory().unwrap();
+        db.add_library("Main", "/main", false, None).unwrap();
+        db.add_library("Archive", "/archive", false, None).unwrap();
+        db.add_library("Empty", "/empty", false, None).unwrap();
+        db.add_library("Main extras", "/main-extras", false, None).unwrap();
+        db.with_conn(|conn| {
+            conn.execute_batch(
+                "INSERT INTO books (path, title, embedding_status) VALUES
+                    ('/main/a.epub', 'A', 'complete'),
+                    ('/main/b.epub', 'B', 'complete'),
+                    ('/main/c.epub', 'C', 'failed'),
+                    ('/archive/d.epub', 'D', 'complete'),
+                    ('/archive/e.epub', 'E', 'pending'),
+                    ('/archive/f.epub', 'F', ''),
+                    ('/archive/g.epub', 'G', 'skipped'),
+                    ('/elsewhere/h.epub', 'H', 'complete'),
+                    ('/main-extras/i.epub', 'I', 'complete')",
+            )?;
+            Ok(())
+        })
+        .unwrap();
+
+        let coverage = db.get_embedding_coverage().unwrap();
+        let counts: Vec<(&str, [i64; 5])> = coverage
+            .iter()
+            .map(|c| (c.name.as_str(), [c.total, c.embedded, c.pending, c.failed, c.skipped]))
+            .collect();
+        assert_eq!(
+            counts,
+            vec![
+                ("Archive", [4, 1, 2, 0, 1]),
+                ("Empty", [0, 0, 0, 0, 0]),
+                ("Main", [3, 2, 0, 1, 0]),
+                ("Main extras", [1, 1, 0, 0, 0]),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_failed_edge_rebuild_keeps_existing_edges() {
+        let db = Database::new_in_memory().unwrap();
+        db.with_conn(|conn| {
+            conn.execute_batch(
+                "INSERT INTO books (id, path, title) VALUES (1, '/b/1.epub', 'A'), (2, '/b/2.epub', 'B'), (3, '/b/3.epub', 'C')",
+            )?;
+            Ok(())
+        })
+        .unwrap();
+        db.insert_edges_batch(&[(1, 2, "content".to_string(), 0.9)]).unwrap();
+        let edge_count = || db.with_conn(|conn| {
+            Ok(conn.query_row("SELECT COUNT(*) FROM book_edges", [], |r| r.get::<_, i64>(0))?)
+        }).unwrap();
+
+        // Abandoned rebuild
+        let mut rebuild = db.begin_edge_rebuild().unwrap();
+        rebuild.stage(&[(2, 3, "content".to_string(), 0.5)]).unwrap();
+        drop(rebuild);
+        assert_eq!(edge_count(), 1);
+
+        // Rebuild that fails while swapping (edge to a missing book)
+        let mut rebuild = db.begin_edge_rebuild().unwrap();
+        rebuild
+            .stage(&[(2, 3, "content".to_string(), 0.5), (1, 99, "content".to_string(), 0.5)])
+            .unwrap();
+        assert!(rebuild.commit().is_err());
+        assert_eq!(db.get_edges(1, 0.0, None).unwrap().len(), 1);
+        assert_eq!(edge_count(), 1);
+
+        // Successful rebuild replaces the graph
+        let mut rebuild = db.begin_edge_rebuild().unwrap();
+        rebuild.stage(&[(2, 3, "content".to_string(), 0.5), (3, 2, "content".to_string(), 0.5)]).unwrap();
+        assert_eq!(rebuild.commit().unwrap(), 2);
+        assert!(db.get_edges(1, 0.0, None).unwrap().is_empty());
+        assert_eq!(edge_count(), 2);
+    }
+
+    #[test]
+    fn test_rebuild_keeps_manual_edges() {
+        let db = Database::new_in_memory().unwrap();
+        db.with_conn(|conn| {
+            conn.execute_batch(
+                "INSERT INTO books (id, path, title) VALUES (1, '/b/1.epub', 'A'), (2, '/b/2.epub', 'B'), (3, '/b/3.epub', 'C')",
+            )?;
+            Ok(())
+        })
+        .unwrap();
+        db.insert_edges_batch(&[(1, 2, "content".to_string(), 0.9)]).unwrap();
+        db.add_manual_edge(1, 3, 0.8).unwrap();
+        assert!(db.add_manual_edge(1, 1, 0.8).is_err());
+        assert!(db.add_manual_edge(1, 99, 0.8).is_err());
+
+        let mut rebuild = db.begin_edge_rebuild().unwrap();
+        rebuild.stage(&[(2, 3, "content".to_string(), 0.5)]).unwrap();
+        rebuild.commit().unwrap();
+
+        let types = |book_id: i64| -> Vec<(i64, i64, String)> {
+            db.get_edges(book_id, 0.0, None)
+                .unwrap()
+                .into_iter()
+                .map(|e| (e.source_id, e.target_id, e.edge_type))
+                .collect()
+        };
+        // The computed 1-2 edge is gone, the manual 1-3 link is not
+        let mut edges = types(1);
+        edges.sort();
+        assert_eq!(edges, vec![(1, 3, "manual".to_string()), (3, 1, "manual".to_string())]);
+
+        // Recomputing a book's edges keeps its manual links too
+        db.replace_book_edges(3, &[]).unwrap();
+        assert_eq!(types(3).len(), 2);
+
+        assert!(db.remove_manual_edge(3, 1).unwrap());
+        assert!(types(1).is_empty());
+        assert!(!db.remove_manual_edge(3, 1).unwrap());
+    }
+
+    #[test]
+    fn test_edge_generation_tracks_every_change() {
+        let db = Database::new_in_memory().unwrap();
+        db.with_conn(|conn| {
+            conn.execute_batch(
+                "INSERT INTO books (id, path, title) VALUES
+                    (1, '/b/1.epub', 'One'), (2, '/b/2.epub', 'Two'), (3, '/b/3.epub', 'Three')"
+            )?;
+            Ok(())
+        })
+        .unwrap();
+
+        let mut last = db.edge_generation().unwrap();
+        let mut changed = |db: &Database| {
+            let generation = db.edge_generation().unwrap();
+            let changed = generation != last;
+            last = generation;
+            changed
+        };
+
+        db.insert_edges_batch(&[(1, 2, "content".to_string(), 0.9), (2, 3, "content".to_string(), 0.8)])
+            .unwrap();
+        assert!(changed(&db));
+        assert_eq!(db.count_edges(0.85).unwrap(), 1);
+
+        db.get_edges(1, 0.0, None).unwrap();
+        assert!(!changed(&db));
+
+        // Deleting a book cascades to its edges
+        db.with_conn(|conn| Ok(conn.execute("DELETE FROM books WHERE id = 3", [])?)).unwrap();
+        assert!(changed(&db));
+        assert_eq!(db.count_edges(0.0).unwrap(), 1);
+    }
+
+    #[test]
+    fn test_prune_edges_keeps_strongest() {
+        let db = Database::new_in_memory().unwrap();
+        db.with_conn(|conn| {
+            for id in 1..=51 {
+                conn.execute(
+                    "INSERT INTO books (id, path, title) VALUES (?1, '/b/' || ?1 || '.epub', 'Book')",
+                    [id],
+                )?;
+            }
+            Ok(())
+        })
+        .unwrap();
+        // Book 1 links to 50 others with weights 0.02 ..= 1.0
+        let edges: Vec<(i64, i64, String, f64)> =
+            (2..=51).map(|target| (1, target, "content".to_string(), (target - 1) as f64 / 50.0)).collect();
+        db.insert_edges_batch(&edges).unwrap();
+        db.insert_edges_batch(&[(2, 1, "content".to_string(), 0.1)]).unwrap();
+        db.add_manual_edge(1, 2, 0.05).unwrap();
+
+        let removed = db.prune_edges(10, 0.0).unwrap();
+        assert_eq!(removed, 40);
+
+        let computed: Vec<(i64, f64)> = db
+            .get_edges(1, 0.0, None)
+            .unwrap()
+            .into_iter()
+            .filter(|e| e.source_id == 1 && e.edge_type == "content")
+            .map(|e| (e.target_id, e.weight))
+            .collect();
+        assert_eq!(computed.len(), 10);
+        assert_eq!(computed.iter().map(|&(target, _)| target).min(), Some(42));
+        assert!(computed.iter().all(|&(_, weight)| weight >= 0.82 - 1e-9));
+
+        // Manual links are never pruned and don't count towards the cap
+        let manual = db.get_edges(1, 0.0, Some(&["manual".to_string()])).unwrap();
+        assert_eq!(manual.len(), 2);
+
+        // The weight floor drops weak edges regardless of rank, manual ones excepted
+        assert_eq!(db.prune_edges(10, 0.5).unwrap(), 1);
+        assert!(db.get_edges(2, 0.0, Some(&["content".to_string()])).unwrap().is_empty());
+        assert_eq!(db.get_edges(1, 0.0, Some(&["manual".to_string()])).unwrap().len(), 2);
+    }
+
+    #[test]
+    fn test_prune_edges_over_degree_trims_hubs() {
+        let db = Database::new_in_memory().unwrap();
+        db.with_conn(|conn| {
+            for id in 1..=21 {
+                conn.execute(
+                    "INSERT INTO books (id, path, title) VALUES (?1, '/b/' || ?1 || '.epub', 'Book')",
+                    [id],
+                )?;
+            }
+            Ok(())
+        })
+        .unwrap();
+        // Book 1 is a hub: 20 books link to it, each with a single edge
+        let edges: Vec<(i64, i64, String, f64)> =
+            (2..=21).map(|source| (source, 1, "content".to_string(), source as f64 / 25.0)).collect();
+        db.insert_edges_batch(&edges).unwrap();
+        db.add_manual_edge(2, 1, 0.05).unwrap();
+
+        assert_eq!(db.prune_edges_over_degree(5).unwrap(), 15);
+
+        let mut sources: Vec<i64> = db
+            .get_edges(1, 0.0, Some(&["content".to_string()]))
+            .unwrap()
+            .into_iter()
+            .map(|e| e.source_id)
+            .collect();
+        sources.sort();
+        assert_eq!(sources, vec![17, 18, 19, 20, 21]);
+        assert_eq!(db.get_edges(1, 0.0, Some(&["manual".to_string()])).unwrap().len(), 2);
+        assert_eq!(db.prune_edges_over_degree(5).unwrap(), 0);
+    }
+}
@@ -0,0 +1,346 @@
+//! Metadata enrichment from online sources
+//!
+//! Fills gaps (description, series, tags) in sparse book metadata by looking
+//! books up by ISBN. Only missing fields are written; nothing the EPUB or the
+//! user provided is overwritten.
+
+use crate::db::Database;
+use crate::ollama::RateLimiter;
+use crate::{AppError, AppResult};
+use futures::future::BoxFuture;
+use serde::{Deserialize, Serialize};
+
+/// Default OpenLibrary endpoint
+pub const OPEN_LIBRARY_ENDPOINT: &str = "https://openlibrary.org";
+
+/// Lookups per second; public services ask clients to keep this low
+pub const ENRICHMENT_REQUESTS_PER_SECOND: f64 = 1.0;
+
+/// Metadata returned by a provider
+///
+/// This is also the JSON shape expected from a custom HTTP provider.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct EnrichedMetadata {
+    pub description: Option<String>,
+    pub series: Option<String>,
+    pub series_index: Option<f64>,
+    #[serde(default)]
+    pub tags: Vec<String>,
+}
+
+/// Source of book metadata keyed by ISBN
+///
+/// Implemented by [`OpenLibraryProvider`] and [`HttpProvider`]; tests can
+/// supply a stand-in.
+pub trait EnrichmentProvider: Send + Sync {
+    /// Name stored as the enrichment source of each book
+    fn name(&self) -> &str;
+
+    /// Look up a book; `None` when the provider doesn't know the ISBN
+    fn lookup<'a>(&'a self, isbn: &'a str) -> BoxFuture<'a, AppResult<Option<EnrichedMetadata>>>;
+}
+
+fn http_client() -> reqwest::Client {
+    reqwest::Client::builder()
+        .timeout(std::time::Duration::from_secs(30))
+        .build()
+        .expect("Failed to create HTTP client")
+}
+
+/// OpenLibrary's `/api/books` endpoint
+pub struct OpenLibraryProvider {
+    endpoint: String,
+    client: reqwest::Client,
+}
+
+impl OpenLibraryProvider {
+    pub fn new(endpoint: &str) -> Self {
+        Self {
+            endpoint: endpoint.trim_end_matches('/').to_string(),
+            client: http_client(),
+        }
+    }
+
+    async fn fetch(&self, isbn: &str) -> AppResult<Option<EnrichedMetadata>> {
+        let key = format!("ISBN:{}", isbn);
+        let response = self
+            .client
+            .get(format!("{}/api/books", self.endpoint))
+            .query(&[("bibkeys", key.as_str()), ("format", "json"), ("jscmd", "details")])
+            .send()
+            .await
+            .map_err(|e| AppError::Enrichment(format!("OpenLibrary request failed: {}", e)))?;
+        if !response.status().is_success() {
+            return Err(AppError::Enrichment(format!("OpenLibrary returned {}", response.status())));
+        }
+
+        let body: serde_json::Value = response
+            .json()
+            .await
+            .map_err(|e| AppError::Enrichment(format!("Failed to parse OpenLibrary response: {}", e)))?;
+        Ok(parse_open_library(&body, &key))
+    }
+}
+
+impl Default for OpenLibraryProvider {
+    fn default() -> Self {
+        Self::new(OPEN_LIBRARY_ENDPOINT)
+    }
+}
+
+impl EnrichmentProvider for OpenLibraryProvider {
+    fn name(&self) -> &str {
+        "openlibrary"
+    }
+
+    fn lookup<'a>(&'a self, isbn: &'a str) -> BoxFuture<'a, AppResult<Option<EnrichedMetadata>>> {
+        Box::pin(self.fetch(isbn))
+    }
+}
+
+/// Extract metadata from an OpenLibrary `jscmd=details` response
+fn parse_open_library(body: &serde_json::Value, key: &str) -> Option<EnrichedMetadata> {
+    let details = body.get(key)?.get("details")?;
+    let text = |value: &serde_json::Value| -> Option<String> {
+        // Descriptions are either a plain string or `{ "type", "value" }`
+        let text = value.as_str().or_else(|| value.get("value")?.as_str())?.trim();
+        (!text.is_empty()).then(|| text.to_string())
+    };
+
+    Some(EnrichedMetadata {
+        description: details.get("description").and_then(text),
+        series: details
+            .get("series")
+            .and_then(|s| s.as_array())
+            .and_then(|s| s.first())
+            .and_then(text),
+        series_index: None,
+        tags: details
+            .get("subjects")
+            .and_then(|s| s.as_array())
+            .map(|subjects| subjects.iter().filter_map(text).collect())
+            .unwrap_or_default(),
+    })
+}
+
+/// A self-hosted service answering `GET` on a URL template
+///
+/// `{isbn}` in the template is replaced by the ISBN; the response must be
+/// [`EnrichedMetadata`] JSON, and a 404 means the book is unknown.
+pub struct HttpProvider {
+    url_template: String,
+    client: reqwest::Client,
+}
+
+impl HttpProvider {
+    pub fn new(url_template: &str) -> Self {
+        Self {
+            url_template: url_template.to_string(),
+            client: http_client(),
+        }
+    }
+
+    async fn fetch(&self, isbn: &str) -> AppResult<Option<EnrichedMetadata>> {
+        let url = self.url_template.replace("{isbn}", isbn);
+        let response = self
+            .client
+            .get(&url)
+            .send()
+            .await
+            .map_err(|e| AppError::Enrichment(format!("Request to {} failed: {}", url, e)))?;
+        if response.status() == reqwest::StatusCode::NOT_FOUND {
+            return Ok(None);
+        }
+        if !response.status().is_success() {
+            return Err(AppError::Enrichment(format!("{} returned {}", url, response.status())));
+        }
+
+        let metadata = response
+            .json()
+            .await
+            .map_err(|e| AppError::Enrichment(format!("Failed to parse response from {}: {}", url, e)))?;
+        Ok(Some(metadata))
+    }
+}
+
+impl EnrichmentProvider for HttpProvider {
+    fn name(&self) -> &str {
+        &self.url_template
+    }
+
+    fn lookup<'a>(&'a self, isbn: &'a str) -> BoxFuture<'a, AppResult<Option<EnrichedMetadata>>> {
+        Box::pin(self.fetch(isbn))
+    }
+}
+
+/// Provider for the `enrichment_endpoint` setting
+///
+/// Empty uses OpenLibrary; a URL containing `{isbn}` is a custom
+/// [`HttpProvider`]; any other URL is an OpenLibrary-compatible mirror.
+pub fn provider_for(endpoint: &str) -> Box<dyn EnrichmentProvider> {
+    let endpoint = endpoint.trim();
+    if endpoint.is_empty() {
+        Box::new(OpenLibraryProvider::default())
+    } else if endpoint.contains("{isbn}") {
+        Box::new(HttpProvider::new(endpoint))
+    } else {
+        Box::new(OpenLibraryProvider::new(endpoint))
+    }
+}
+
+/// Outcome of [`enrich_books`]
+#[derive(Debug, Clone, Default, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct EnrichmentResult {
+    pub checked: usize,
+    /// Books that gained at least one field
+    pub enriched: usize,
+    /// Books the provider didn't know or had nothing new for
+    pub not_found: usize,
+    /// Lookups that errored; these books are tried again next time
+    pub failed: usize,
+    pub duration_ms: u64,
+}
+
+/// Look up to `limit` books with an ISBN but no description and fill their gaps
+///
+/// Each book is looked up once per provider: the attempt is recorded as its
+/// enrichment source whether or not anything was found, except on errors.
+/// Books that gain a description are queued for embedding.
+pub async fn enrich_books(
+    db: &Database,
+    provider: &dyn EnrichmentProvider,
+    rate_limiter: &RateLimiter,
+    limit: i64,
+) -> AppResult<EnrichmentResult> {
+    let start = std::time::Instant::now();
+    let mut result = EnrichmentResult::default();
+
+    for (book_id, isbn) in db.get_books_needing_enrichment(provider.name(), limit)? {
+        result.checked += 1;
+        rate_limiter.acquire().await;
+
+        let metadata = match provider.lookup(&isbn).await {
+            Ok(metadata) => metadata,
+            Err(e) => {
+                tracing::warn!("Enrichment lookup for book {} ({}) failed: {}", book_id, isbn, e);
+                result.failed += 1;
+                continue;
+            }
+        };
+
+        let filled = match metadata {
+            Some(metadata) => fill_gaps(db, book_id, &metadata)?,
+            None => false,
+        };
+        db.record_enrichment(book_id, provider.name(), filled)?;
+        if filled {
+            result.enriched += 1;
+        } else {
+            result.not_found += 1;
+        }
+    }
+
+    result.duration_ms = start.elapsed().as_millis() as u64;
+    Ok(result)
+}
+
+/// Write the fields the book is missing; returns whether anything changed
+fn fill_gaps(db: &Database, book_id: i64, metadata: &EnrichedMetadata) -> AppResult<bool> {
+    let book = db.get_book(book_id)?;
+    let missing = |value: &Option<String>| !value.as_deref().is_some_and(|v| !v.trim().is_empty());
+
+    let description = metadata.description.as_deref().filter(|_| missing(&book.description));
+    let series = metadata.series.as_deref().filter(|_| missing(&book.series));
+    let series_index = series.and(metadata.series_index);
+    let mut changed = description.is_some() || series.is_some();
+    if changed {
+        db.update_book_metadata(book_id, None, None, None, description, series, series_index, None, None, None, None)?;
+    }
+
+    if !metadata.tags.is_empty() && db.get_book_tags(book_id)?.is_empty() {
+        db.set_book_tags(book_id, &metadata.tags)?;
+        changed = true;
+    }
+
+    if description.is_some() {
+        db.update_embedding_status(book_id, "pending")?;
+    }
+    Ok(changed)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Provider that knows one ISBN
+    struct MockProvider;
+
+    impl EnrichmentProvider for MockProvider {
+        fn name(&self) -> &str {
+            "mock"
+        }
+
+        fn lookup<'a>(&'a self, isbn: &'a str) -> BoxFuture<'a, AppResult<Option<EnrichedMetadata>>> {
+            Box::pin(async move {
+                Ok((isbn == "9780141439518").then(|| EnrichedMetadata {
+                    description: Some("A novel of manners.".to_string()),
+                    series: None,
+                    series_index: None,
+                    tags: vec!["Fiction".to_string()],
+                }))
+            })
+        }
+    }
+
+    #[tokio::test]
+    async fn test_mock_provider_fills_missing_description() {
+        let db = Database::new_in_memory().unwrap();
+        db.with_conn(|conn| {
+            conn.execute_batch(
+                "INSERT INTO books (id, path, title, isbn, embedding_status) VALUES
+                    (1, '/b/1.epub', 'Pride and Prejudice', '9780141439518', 'no_description'),
+                    (2, '/b/2.epub', 'Unknown', '9780000000000', 'no_description');
+                 INSERT INTO books (id, path, title, isbn, description) VALUES
+                    (3, '/b/3.epub', 'Described', '9780141439518', 'Already here');
+                 INSERT INTO books (id, path, title) VALUES (4, '/b/4.epub', 'No ISBN');",
+            )?;
+            Ok(())
+        })
+        .unwrap();
+        let limiter = RateLimiter::new(0.0);
+
+        let result = enrich_books(&db, &MockProvider, &limiter, 10).await.unwrap();
+        assert_eq!((result.checked, result.enriched, result.not_found), (2, 1, 1));
+
+        let book = db.get_book(1).unwrap();
+        assert_eq!(book.description.as_deref(), Some("A novel of manners."));
+        assert_eq!(book.embedding_status, "pending");
+        assert_eq!(db.get_book_tags(1).unwrap(), vec!["Fiction"]);
+        assert_eq!(db.get_enrichment_source(1).unwrap().as_deref(), Some("mock"));
+        assert_eq!(db.get_book(3).unwrap().description.as_deref(), Some("Already here"));
+
+        // Both lookups were recorded, so nothing is checked again
+        let again = enrich_books(&db, &MockProvider, &limiter, 10).await.unwrap();
+        assert_eq!(again.checked, 0);
+    }
+
+    #[test]
+    fn test_parse_open_library_details() {
+        let body = serde_json::json!({
+            "ISBN:9780141439518": {
+                "details": {
+                    "description": { "type": "/type/text", "value": "A novel of manners." },
+                    "series": ["Penguin Classics"],
+                    "subjects": ["Fiction", "England"]
+                }
+            }
+        });
+        let metadata = parse_open_library(&body, "ISBN:9780141439518").unwrap();
+        assert_eq!(metadata.description.as_deref(), Some("A novel of manners."));
+        assert_eq!(metadata.series.as_deref(), Some("Penguin Classics"));
+        assert_eq!(metadata.tags, vec!["Fiction", "England"]);
+        assert!(parse_open_library(&body, "ISBN:123").is_none());
+    }
+}
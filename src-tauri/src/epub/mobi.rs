@@ -0,0 +1,331 @@
+//! MOBI and AZW3 (Kindle) metadata
+//!
+//! Both are Palm database files: a header, a table of record offsets, then
+//! the records. Record 0 holds the MOBI header and, in almost every book, an
+//! EXTH block with the metadata. Only the headers and the cover record are
+//! read; the text is often HUFF/CDIC compressed, so no word count is taken.
+
+use super::{
+    detect_image_mime, distinct_authors, distinct_subjects, generate_author_sort, generate_sort_title,
+    series_from_title, split_archive_path, Format,
+};
+use crate::db::NewBook;
+use crate::{AppError, AppResult};
+use std::path::Path;
+
+/// Length of the Palm database header, before the record table
+const PDB_HEADER_LEN: usize = 78;
+/// Offset of the MOBI header in record 0, after the PalmDOC header
+const MOBI_HEADER_OFFSET: usize = 16;
+/// MOBI header flag for a following EXTH block
+const EXTH_FLAG: u32 = 0x40;
+/// MOBI text encoding code for UTF-8 (the other is CP1252)
+const UTF8_ENCODING: u32 = 65001;
+/// Record index meaning "none"
+const NO_RECORD: u32 = u32::MAX;
+
+// EXTH record types
+const EXTH_AUTHOR: u32 = 100;
+const EXTH_PUBLISHER: u32 = 101;
+const EXTH_DESCRIPTION: u32 = 103;
+const EXTH_ISBN: u32 = 104;
+const EXTH_SUBJECT: u32 = 105;
+const EXTH_PUBLISH_DATE: u32 = 106;
+const EXTH_COVER_OFFSET: u32 = 201;
+const EXTH_THUMBNAIL_OFFSET: u32 = 202;
+const EXTH_UPDATED_TITLE: u32 = 503;
+const EXTH_LANGUAGE: u32 = 524;
+
+/// Headers of a MOBI/AZW3 file
+#[derive(Debug)]
+pub(super) struct MobiBook {
+    /// Start of each record in the file
+    records: Vec<usize>,
+    full_name: Option<String>,
+    first_image: Option<u32>,
+    /// EXTH records as (type, data), in file order
+    exth: Vec<(u32, Vec<u8>)>,
+    utf8: bool,
+}
+
+impl MobiBook {
+    /// Read the headers of a MOBI/AZW3 file
+    ///
+    /// Fails for anything that isn't a MOBI book, and for DRM-protected books.
+    pub(super) fn read(data: &[u8]) -> AppResult<Self> {
+        let invalid = |what: &str| AppError::EpubParse(format!("Failed to parse MOBI: {}", what));
+
+        if data.len() < PDB_HEADER_LEN || &data[60..68] != b"BOOKMOBI" {
+            return Err(invalid("not a MOBI file"));
+        }
+        let record_count = be_u16(data, 76).ok_or_else(|| invalid("truncated header"))? as usize;
+        let records = (0..record_count)
+            .map(|i| be_u32(data, PDB_HEADER_LEN + i * 8).map(|offset| offset as usize))
+            .collect::<Option<Vec<_>>>()
+            .filter(|records| !records.is_empty() && records.iter().all(|&offset| offset <= data.len()))
+            .ok_or_else(|| invalid("bad record table"))?;
+
+        let record0 = record(data, &records, 0).ok_or_else(|| invalid("bad record table"))?;
+        if be_u16(record0, 12).unwrap_or(0) != 0 {
+            return Err(AppError::EpubParse("MOBI is DRM-protected".to_string()));
+        }
+        if record0.get(MOBI_HEADER_OFFSET..MOBI_HEADER_OFFSET + 4) != Some(b"MOBI".as_slice()) {
+            return Err(invalid("missing MOBI header"));
+        }
+        let header_len = be_u32(record0, 20).ok_or_else(|| invalid("truncated MOBI header"))? as usize;
+        let utf8 = be_u32(record0, 28) == Some(UTF8_ENCODING);
+
+        let full_name = be_u32(record0, 84)
+            .zip(be_u32(record0, 88))
+            .and_then(|(offset, len)| record0.get(offset as usize..(offset as usize).checked_add(len as usize)?))
+            .map(|name| decode(name, utf8))
+            .filter(|name| !name.is_empty());
+        let first_image = be_u32(record0, 108).filter(|&index| index != NO_RECORD);
+
+        let exth = match be_u32(record0, 128) {
+            Some(flags) if flags & EXTH_FLAG != 0 => read_exth(record0, MOBI_HEADER_OFFSET + header_len),
+            _ => Vec::new(),
+        };
+
+        Ok(Self { records, full_name, first_image, exth, utf8 })
+    }
+
+    /// First EXTH record of a type, decoded as text
+    fn text(&self, kind: u32) -> Option<String> {
+        self.texts(kind).into_iter().find(|value| !value.is_empty())
+    }
+
+    /// Every EXTH record of a type, decoded as text
+    fn texts(&self, kind: u32) -> Vec<String> {
+        self.exth
+            .iter()
+            .filter(|(k, _)| *k == kind)
+            .map(|(_, value)| decode(value, self.utf8))
+            .collect()
+    }
+
+    /// First EXTH record of a type, read as a number
+    fn number(&self, kind: u32) -> Option<u32> {
+        self.exth.iter().find(|(k, _)| *k == kind).and_then(|(_, value)| be_u32(value, 0))
+    }
+
+    /// Cover image bytes and MIME type, falling back to the thumbnail
+    pub(super) fn cover(&self, data: &[u8]) -> Option<(Vec<u8>, String)> {
+        let first_image = self.first_image?;
+        [EXTH_COVER_OFFSET, EXTH_THUMBNAIL_OFFSET]
+            .into_iter()
+            .filter_map(|kind| self.number(kind))
+            .filter(|&offset| offset != NO_RECORD)
+            .find_map(|offset| {
+                let index = first_image.checked_add(offset)? as usize;
+                let image = record(data, &self.records, index)?;
+                let mime = detect_image_mime(image)?;
+                Some((image.to_vec(), mime.to_string()))
+            })
+    }
+}
+
+/// Parse a MOBI/AZW3 file's metadata
+pub(super) fn parse(path: &Path, format: Format, data: Vec<u8>, file_size: i64) -> AppResult<NewBook> {
+    let book = MobiBook::read(&data)?;
+
+    let title = book
+        .text(EXTH_UPDATED_TITLE)
+        .or_else(|| book.full_name.clone())
+        .unwrap_or_else(|| super::book_stem(path).unwrap_or_else(|| "Unknown".to_string()));
+    let author = book.text(EXTH_AUTHOR);
+    let (series, series_index) = series_from_title(&title);
+
+    Ok(NewBook {
+        path: path.to_string_lossy().to_string(),
+        cover_path: None, // Set by scanner
+        file_size,
+        file_hash: None,
+        sort_title: Some(generate_sort_title(&title)),
+        author_sort: author.as_deref().map(generate_author_sort),
+        title,
+        author,
+        series,
+        series_index,
+        description: book.text(EXTH_DESCRIPTION),
+        language: book.text(EXTH_LANGUAGE),
+        publisher: book.text(EXTH_PUBLISHER),
+        publish_date: book.text(EXTH_PUBLISH_DATE),
+        isbn: book.text(EXTH_ISBN),
+        source: "scan".to_string(),
+        container_path: split_archive_path(path).map(|(archive, _)| archive.to_string_lossy().to_string()),
+        word_count: None,
+        format,
+        tags: distinct_subjects(book.texts(EXTH_SUBJECT).iter().map(String::as_str)),
+        authors: distinct_authors(book.texts(EXTH_AUTHOR).iter().map(|name| (name.as_str(), None, None))),
+    })
+}
+
+/// Bytes of record `index`, which runs to the next record or the end of file
+fn record<'a>(data: &'a [u8], records: &[usize], index: usize) -> Option<&'a [u8]> {
+    let start = *records.get(index)?;
+    let end = records.get(index + 1).copied().unwrap_or(data.len());
+    data.get(start..end)
+}
+
+/// Read the EXTH block starting at `start` in record 0
+///
+/// A truncated block yields the records read so far.
+fn read_exth(record0: &[u8], start: usize) -> Vec<(u32, Vec<u8>)> {
+    let mut exth = Vec::new();
+    if record0.get(start..start + 4) != Some(b"EXTH".as_slice()) {
+        return exth;
+    }
+    let count = be_u32(record0, start + 8).unwrap_or(0);
+    let mut pos = start + 12;
+    for _ in 0..count {
+        let (Some(kind), Some(len)) = (be_u32(record0, pos), be_u32(record0, pos + 4)) else {
+            break;
+        };
+        let Some(value) = record0.get(pos + 8..pos + (len as usize).max(8)) else {
+            break;
+        };
+        exth.push((kind, value.to_vec()));
+        pos += (len as usize).max(8);
+    }
+    exth
+}
+
+/// Decode header text; non-UTF-8 books use CP1252, read here as Latin-1
+fn decode(bytes: &[u8], utf8: bool) -> String {
+    let text = if utf8 {
+        String::from_utf8_lossy(bytes).into_owned()
+    } else {
+        bytes.iter().map(|&b| b as char).collect()
+    };
+    text.trim_matches(|c: char| c == '\0' || c.is_whitespace()).to_string()
+}
+
+fn be_u16(data: &[u8], at: usize) -> Option<u16> {
+    data.get(at..at + 2).map(|b| u16::from_be_bytes([b[0], b[1]]))
+}
+
+fn be_u32(data: &[u8], at: usize) -> Option<u32> {
+    data.get(at..at + 4).map(|b| u32::from_be_bytes([b[0], b[1], b[2], b[3]]))
+}
+
+/// Build a minimal MOBI file in memory (test fixture)
+///
+/// The title goes in the full name and the author in EXTH, along with any
+/// extra `(type, value)` records; a cover becomes the first image record.
+#[cfg(test)]
+pub(crate) fn test_mobi_bytes(title: &str, author: &str, extra: &[(u32, &str)], cover: Option<&[u8]>) -> Vec<u8> {
+    const HEADER_LEN: usize = 232;
+
+    let mut exth_records: Vec<(u32, Vec<u8>)> = vec![(EXTH_AUTHOR, author.as_bytes().to_vec())];
+    exth_records.extend(extra.iter().map(|(kind, value)| (*kind, value.as_bytes().to_vec())));
+    if cover.is_some() {
+        exth_records.push((EXTH_COVER_OFFSET, 0u32.to_be_bytes().to_vec()));
+    }
+    let mut exth = b"EXTH".to_vec();
+    let body_len: usize = exth_records.iter().map(|(_, value)| value.len() + 8).sum();
+    exth.extend(((body_len + 12) as u32).to_be_bytes());
+    exth.extend((exth_records.len() as u32).to_be_bytes());
+    for (kind, value) in &exth_records {
+        exth.extend(kind.to_be_bytes());
+        exth.extend(((value.len() + 8) as u32).to_be_bytes());
+        exth.extend(value);
+    }
+
+    let mut record0 = vec![0u8; MOBI_HEADER_OFFSET + HEADER_LEN];
+    let put = |buf: &mut Vec<u8>, at: usize, value: u32| buf[at..at + 4].copy_from_slice(&value.to_be_bytes());
+    record0[MOBI_HEADER_OFFSET..MOBI_HEADER_OFFSET + 4].copy_from_slice(b"MOBI");
+    put(&mut record0, 20, HEADER_LEN as u32);
+    put(&mut record0, 28, UTF8_ENCODING);
+    let name_offset = record0.len() + exth.len();
+    put(&mut record0, 84, name_offset as u32);
+    put(&mut record0, 88, title.len() as u32);
+    put(&mut record0, 108, if cover.is_some() { 1 } else { NO_RECORD });
+    put(&mut record0, 128, EXTH_FLAG);
+    record0.extend(exth);
+    record0.extend(title.as_bytes());
+
+    let records: Vec<&[u8]> = std::iter::once(record0.as_slice()).chain(cover).collect();
+    let mut data = vec![0u8; PDB_HEADER_LEN];
+    data[..title.len().min(31)].copy_from_slice(&title.as_bytes()[..title.len().min(31)]);
+    data[60..68].copy_from_slice(b"BOOKMOBI");
+    data[76..78].copy_from_slice(&(records.len() as u16).to_be_bytes());
+    let mut offset = PDB_HEADER_LEN + records.len() * 8;
+    for (i, record) in records.iter().enumerate() {
+        data.extend((offset as u32).to_be_bytes());
+        data.extend([0, 0, 0, i as u8]);
+        offset += record.len();
+    }
+    for record in records {
+        data.extend(record);
+    }
+    data
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::epub::EpubParser;
+
+    const PNG: &[u8] = &[0x89, 0x50, 0x4E, 0x47, 0x0D, 0x0A, 0x1A, 0x0A, 0, 0, 0, 0];
+
+    #[test]
+    fn test_parse_mobi_metadata_and_cover() {
+        let temp = tempfile::TempDir::new().unwrap();
+        let path = temp.path().join("foundation.azw3");
+        let extra = [
+            (EXTH_UPDATED_TITLE, "Foundation (Foundation, #1)"),
+            (EXTH_ISBN, "9780553293357"),
+            (EXTH_LANGUAGE, "en"),
+            (EXTH_DESCRIPTION, "The Galactic Empire is falling."),
+            (EXTH_SUBJECT, "Science Fiction"),
+            (EXTH_SUBJECT, "Classics"),
+            (EXTH_AUTHOR, "Second Author"),
+        ];
+        std::fs::write(&path, test_mobi_bytes("Foundation", "Isaac Asimov", &extra, Some(PNG))).unwrap();
+
+        let parser = EpubParser::new();
+        let book = parser.parse(&path).unwrap();
+        assert_eq!(book.format, Format::Azw3);
+        assert_eq!(book.title, "Foundation (Foundation, #1)");
+        assert_eq!(book.author.as_deref(), Some("Isaac Asimov"));
+        assert_eq!(book.author_sort.as_deref(), Some("Asimov, Isaac"));
+        assert_eq!(book.series.as_deref(), Some("Foundation"));
+        assert_eq!(book.series_index, Some(1.0));
+        assert_eq!(book.isbn.as_deref(), Some("9780553293357"));
+        assert_eq!(book.language.as_deref(), Some("en"));
+        assert_eq!(book.description.as_deref(), Some("The Galactic Empire is falling."));
+        assert_eq!(book.word_count, None);
+        assert_eq!(book.tags, vec!["Science Fiction", "Classics"]);
+        let authors: Vec<&str> = book.authors.iter().map(|a| a.name.as_str()).collect();
+        assert_eq!(authors, vec!["Isaac Asimov", "Second Author"]);
+
+        let (cover, mime) = parser.extract_cover(&path).unwrap().unwrap();
+        assert_eq!(cover, PNG);
+        assert_eq!(mime, "image/png");
+    }
+
+    #[test]
+    fn test_mobi_title_falls_back_to_full_name() {
+        let temp = tempfile::TempDir::new().unwrap();
+        let path = temp.path().join("dune.mobi");
+        std::fs::write(&path, test_mobi_bytes("Dune", "Frank Herbert", &[], None)).unwrap();
+
+        let parser = EpubParser::new();
+        let book = parser.parse(&path).unwrap();
+        assert_eq!(book.format, Format::Mobi);
+        assert_eq!(book.title, "Dune");
+        assert!(parser.extract_cover(&path).unwrap().is_none());
+    }
+
+    #[test]
+    fn test_rejects_drm_and_non_mobi_files() {
+        let mut data = test_mobi_bytes("Locked", "Author", &[], None);
+        let record0 = be_u32(&data, PDB_HEADER_LEN).unwrap() as usize;
+        data[record0 + 13] = 2;
+        assert!(MobiBook::read(&data).unwrap_err().to_string().contains("DRM"));
+
+        assert!(MobiBook::read(b"not a mobi file").is_err());
+        assert!(MobiBook::read(&crate::epub::test_epub_bytes("Epub", "Author")).is_err());
+    }
+}
@@ -1,14 +1,66 @@
 //! EPUB parsing module
 //!
-//! Extracts metadata from EPUB files
+//! Extracts metadata from EPUB files, and from MOBI/AZW3 Kindle files via
+//! [`mobi`]
 
-use crate::db::NewBook;
+mod mobi;
+
+use crate::db::{NewAuthor, NewBook};
 use crate::{AppError, AppResult};
+use once_cell::sync::Lazy;
+use regex::Regex;
 use std::fs::File;
-use std::io::BufReader;
-use std::path::Path;
+use std::io::{BufReader, Cursor, Read, Seek};
+use std::path::{Path, PathBuf};
+
+/// Maximum EPUB size to parse (larger files are likely audiobooks or corrupted)
+const MAX_EPUB_SIZE: u64 = 100 * 1024 * 1024;
+
+/// Seekable byte source an EPUB can be read from
+trait ReadSeek: Read + Seek {}
+
+impl<T: Read + Seek> ReadSeek for T {}
+
+type Doc = epub::doc::EpubDoc<Box<dyn ReadSeek>>;
+
+/// Ebook file formats the parser can read
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Format {
+    Epub,
+    /// Mobipocket, used by older Kindle books
+    Mobi,
+    /// Kindle Format 8
+    Azw3,
+}
+
+impl Format {
+    /// Every supported format, EPUB first
+    pub const ALL: [Format; 3] = [Format::Epub, Format::Mobi, Format::Azw3];
+
+    /// Lowercase name, which is also the file extension and the stored value
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Format::Epub => "epub",
+            Format::Mobi => "mobi",
+            Format::Azw3 => "azw3",
+        }
+    }
+
+    /// Format for a file extension (case-insensitive, without the dot)
+    pub fn from_extension(ext: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|format| ext.eq_ignore_ascii_case(format.as_str()))
+    }
+
+    /// Format of a book path, looking through a trailing `.gz`
+    pub fn from_path(path: &Path) -> Option<Self> {
+        let name = path.file_name()?.to_string_lossy().to_lowercase();
+        let name = name.strip_suffix(".gz").unwrap_or(&name);
+        Self::from_extension(Path::new(name).extension()?.to_str()?)
+    }
+}
 
-/// EPUB parser for metadata extraction
+/// Parser for book metadata; despite the name, reads every [`Format`]
 pub struct EpubParser;
 
 impl EpubParser {
@@ -17,27 +69,24 @@ impl EpubParser {
         Self
     }
     
-    /// Parse an EPUB file and extract metadata
+    /// Parse a book file and extract metadata
+    ///
+    /// The format is taken from the extension; anything unrecognised is read
+    /// as an EPUB. Besides plain files this reads gzipped files (`.epub.gz`)
+    /// and books inside `.zip` archives (addressed as `archive.zip/inner.epub`).
     pub fn parse(&self, path: &Path) -> AppResult<NewBook> {
-        // Skip very large files (>100MB) - likely audiobooks or corrupted
-        let file_size = std::fs::metadata(path)
-            .map(|m| m.len() as i64)
-            .unwrap_or(0);
-
-        if file_size > 100 * 1024 * 1024 {
-            return Err(AppError::EpubParse(format!(
-                "File too large ({}MB), skipping",
-                file_size / 1024 / 1024
-            )));
+        match Format::from_path(path) {
+            Some(format @ (Format::Mobi | Format::Azw3)) => {
+                let (data, file_size) = read_source(path)?;
+                mobi::parse(path, format, data, file_size)
+            }
+            Some(Format::Epub) | None => self.parse_epub(path),
         }
+    }
 
-        let file = File::open(path)
-            .map_err(|e| AppError::EpubParse(format!("Failed to open file: {}", e)))?;
-
-        let reader = BufReader::new(file);
-
-        let doc = epub::doc::EpubDoc::from_reader(reader)
-            .map_err(|e| AppError::EpubParse(format!("Failed to parse EPUB: {}", e)))?;
+    /// Parse an EPUB file and extract metadata
+    fn parse_epub(&self, path: &Path) -> AppResult<NewBook> {
+        let (doc, file_size) = open_doc(path)?;
 
         // Extract metadata - epub crate returns Option<&MetadataItem> from mdata
         // We need to access the .value field for the actual string content
@@ -46,12 +95,16 @@ impl EpubParser {
             .map(|m| m.value.clone())
             .unwrap_or_else(|| {
                 // Fallback to filename
-                path.file_stem()
-                    .map(|s| s.to_string_lossy().to_string())
-                    .unwrap_or_else(|| "Unknown".to_string())
+                book_stem(path).unwrap_or_else(|| "Unknown".to_string())
             });
 
-        let author = doc.mdata("creator").map(|m| m.value.clone());
+        let authors = creators(&doc.metadata);
+        // The first author proper is shown, even if an editor is listed first
+        let author = authors
+            .iter()
+            .find(|a| a.role == "author")
+            .or(authors.first())
+            .map(|a| a.name.clone());
         let description = doc.mdata("description").map(|m| m.value.clone());
         let language = doc.mdata("language").map(|m| m.value.clone());
         let publisher = doc.mdata("publisher").map(|m| m.value.clone());
@@ -59,6 +112,7 @@ impl EpubParser {
         let isbn = doc.mdata("identifier")
             .map(|m| m.value.clone())
             .filter(|id| id.starts_with("978") || id.starts_with("979") || id.contains("isbn"));
+        let tags = distinct_subjects(doc.metadata.iter().filter(|m| m.property == "subject").map(|m| m.value.as_str()));
 
         // Extract series info from calibre metadata or title parsing
         let (series, series_index) = extract_series_info(&title, &doc);
@@ -69,6 +123,9 @@ impl EpubParser {
         // Generate author sort name
         let author_sort = author.as_ref().map(|a| generate_author_sort(a));
 
+        // Count words for reading-time estimates
+        let word_count = Some(count_words(SpineText::new(doc))).filter(|&count| count > 0);
+
         Ok(NewBook {
             path: path.to_string_lossy().to_string(),
             cover_path: None, // Set by scanner
@@ -86,26 +143,131 @@ impl EpubParser {
             publish_date,
             isbn,
             source: "scan".to_string(),
+            container_path: split_archive_path(path)
+                .map(|(archive, _)| archive.to_string_lossy().to_string()),
+            word_count,
+            format: Format::Epub,
+            tags,
+            authors,
         })
     }
     
-    /// Extract cover image data from EPUB (returns raw bytes)
-    pub fn extract_cover(&self, path: &Path) -> AppResult<Option<Vec<u8>>> {
-        let file = File::open(path)
-            .map_err(|e| AppError::EpubParse(format!("Failed to open file: {}", e)))?;
-
-        let reader = BufReader::new(file);
+    /// Extract cover image data from a book (returns raw bytes and MIME type)
+    pub fn extract_cover(&self, path: &Path) -> AppResult<Option<(Vec<u8>, String)>> {
+        if matches!(Format::from_path(path), Some(Format::Mobi | Format::Azw3)) {
+            let (data, _) = read_source(path)?;
+            return Ok(mobi::MobiBook::read(&data)?.cover(&data));
+        }
 
-        let mut doc = epub::doc::EpubDoc::from_reader(reader)
-            .map_err(|e| AppError::EpubParse(format!("Failed to parse EPUB: {}", e)))?;
+        let (mut doc, _) = open_doc(path)?;
 
         // Try to get cover image - get_cover returns (Vec<u8>, String)
-        if let Some((cover_data, _mime_type)) = doc.get_cover() {
-            return Ok(Some(cover_data));
+        if let Some((cover_data, mime_type)) = doc.get_cover() {
+            let mime_type = if mime_type.starts_with("image/") {
+                mime_type
+            } else {
+                detect_image_mime(&cover_data).unwrap_or("image/jpeg").to_string()
+            };
+            return Ok(Some((cover_data, mime_type)));
         }
 
         Ok(None)
     }
+
+    /// Text of each content document, in the book's reading order
+    ///
+    /// Walks the spine rather than the manifest, so stylesheets, images and
+    /// the navigation document are never read, and skips cover pages and
+    /// non-linear items. Markup is stripped and entities decoded; this is the
+    /// text word counts are taken from.
+    pub fn spine_text_iter(&self, path: &Path) -> AppResult<SpineText> {
+        let (doc, _) = open_doc(path)?;
+        Ok(SpineText::new(doc))
+    }
+
+    /// The first `max_chars` characters of a book's running text
+    ///
+    /// Whitespace is collapsed and the cut falls on a word boundary where
+    /// possible. Empty if the book has no text.
+    pub fn extract_text_excerpt(&self, path: &Path, max_chars: usize) -> AppResult<String> {
+        let mut excerpt = String::new();
+        for text in self.spine_text_iter(path)? {
+            for word in text.split_whitespace() {
+                if !excerpt.is_empty() {
+                    excerpt.push(' ');
+                }
+                excerpt.push_str(word);
+            }
+            if excerpt.chars().count() >= max_chars {
+                break;
+            }
+        }
+
+        if let Some((cut, next)) = excerpt.char_indices().nth(max_chars) {
+            excerpt.truncate(cut);
+            if next != ' ' {
+                if let Some(space) = excerpt.rfind(' ') {
+                    excerpt.truncate(space);
+                }
+            }
+        }
+        Ok(excerpt)
+    }
+}
+
+/// Iterator over the plain text of an EPUB's spine documents
+///
+/// Returned by [`EpubParser::spine_text_iter`]; yields one string per content
+/// document.
+pub struct SpineText {
+    doc: Doc,
+    idrefs: std::vec::IntoIter<String>,
+}
+
+impl SpineText {
+    fn new(doc: Doc) -> Self {
+        let idrefs: Vec<String> = doc
+            .spine
+            .iter()
+            .filter(|item| item.linear)
+            .map(|item| item.idref.clone())
+            .collect();
+        Self { doc, idrefs: idrefs.into_iter() }
+    }
+
+    /// Whether a manifest item is part of the book's running text
+    fn is_content(&self, id: &str) -> bool {
+        let Some(resource) = self.doc.resources.get(id) else {
+            return false;
+        };
+        let mime = resource.mime.to_ascii_lowercase();
+        if mime != "application/xhtml+xml" && mime != "text/html" {
+            return false;
+        }
+        let properties = resource.properties.as_deref().unwrap_or_default();
+        if properties.split_whitespace().any(|p| p == "nav" || p == "cover-image") {
+            return false;
+        }
+        let is_cover = |name: &str| name.eq_ignore_ascii_case("cover");
+        let stem = resource.path.file_stem().and_then(|s| s.to_str()).unwrap_or_default();
+        !is_cover(id) && !is_cover(stem)
+    }
+}
+
+impl Iterator for SpineText {
+    type Item = String;
+
+    fn next(&mut self) -> Option<String> {
+        loop {
+            let id = self.idrefs.next()?;
+            if !self.is_content(&id) {
+                continue;
+            }
+            if let Some((content, _)) = self.doc.get_resource_str(&id) {
+                return Some(html_to_text(&content));
+            }
+        }
+    }
 }
 
 impl Default for EpubParser {
@@ -114,8 +276,445 @@ impl Default for EpubParser {
     }
 }
 
+/// Average adult reading speed used for reading-time estimates
+pub const DEFAULT_WORDS_PER_MINUTE: f64 = 250.0;
+
+/// Estimate reading time in minutes for a word count
+pub fn estimate_reading_time(word_count: i64, words_per_minute: f64) -> f64 {
+    if words_per_minute <= 0.0 {
+        return 0.0;
+    }
+    word_count.max(0) as f64 / words_per_minute
+}
+
+/// Finished books needed before a personal reading speed is trusted
+pub const MIN_WPM_SAMPLES: usize = 2;
+
+/// Personal reading speed from `(word_count, seconds)` samples
+///
+/// Speed is total words over total time, so long books count for more.
+/// Returns `None` until there are [`MIN_WPM_SAMPLES`] usable samples.
+pub fn personal_words_per_minute(samples: &[(i64, i64)]) -> Option<f64> {
+    let usable: Vec<&(i64, i64)> = samples.iter().filter(|&&(words, secs)| words > 0 && secs > 0).collect();
+    if usable.len() < MIN_WPM_SAMPLES {
+        return None;
+    }
+    let words: i64 = usable.iter().map(|&&(words, _)| words).sum();
+    let seconds: i64 = usable.iter().map(|&&(_, secs)| secs).sum();
+    Some(words as f64 / (seconds as f64 / 60.0))
+}
+
+/// Count the words in the text of every spine document
+fn count_words(spine: SpineText) -> i64 {
+    spine.map(|text| text.split_whitespace().count() as i64).sum()
+}
+
+/// Text of an (X)HTML document: markup stripped, entities decoded
+fn html_to_text(html: &str) -> String {
+    decode_entities(&strip_tags(html))
+}
+
+/// Remove markup from an (X)HTML document, leaving its text
+///
+/// The contents of `<head>`, `<script>` and `<style>` aren't text and are
+/// dropped along with the tags.
+fn strip_tags(html: &str) -> String {
+    let mut text = String::with_capacity(html.len());
+    let mut in_tag = false;
+    let mut tag = String::new();
+    let mut skipping: Option<&str> = None;
+
+    for c in html.chars() {
+        match c {
+            '<' => {
+                in_tag = true;
+                tag.clear();
+            }
+            '>' if in_tag => {
+                in_tag = false;
+                let name: String = tag
+                    .trim_start_matches('/')
+                    .chars()
+                    .take_while(|c| c.is_ascii_alphanumeric())
+                    .collect::<String>()
+                    .to_ascii_lowercase();
+                match skipping {
+                    Some(skipped) if tag.starts_with('/') && name == skipped => skipping = None,
+                    None if !tag.starts_with('/') && !tag.ends_with('/') => {
+                        skipping = ["head", "script", "style"].into_iter().find(|&s| s == name);
+                    }
+                    _ => {}
+                }
+                text.push(' ');
+            }
+            _ if in_tag => tag.push(c),
+            _ if skipping.is_none() => text.push(c),
+            _ => {}
+        }
+    }
+
+    text
+}
+
+/// Decode character references (`&amp;`, `&#8217;`, `&#x2014;`, ...)
+///
+/// Unknown named entities are left as they are.
+fn decode_entities(text: &str) -> String {
+    let mut decoded = String::with_capacity(text.len());
+    let mut rest = text;
+
+    while let Some(start) = rest.find('&') {
+        decoded.push_str(&rest[..start]);
+        rest = &rest[start..];
+        let entity = rest[1..].find(';').filter(|&end| end <= 10).map(|end| &rest[1..end + 1]);
+        let value = entity.and_then(|entity| match entity {
+            "amp" => Some('&'),
+            "lt" => Some('<'),
+            "gt" => Some('>'),
+            "quot" => Some('"'),
+            "apos" => Some('\''),
+            "nbsp" => Some('\u{a0}'),
+            "lsquo" => Some('\u{2018}'),
+            "rsquo" => Some('\u{2019}'),
+            "ldquo" => Some('\u{201c}'),
+            "rdquo" => Some('\u{201d}'),
+            "ndash" => Some('\u{2013}'),
+            "mdash" => Some('\u{2014}'),
+            "hellip" => Some('\u{2026}'),
+            _ => {
+                let code = entity.strip_prefix('#')?;
+                let code = match code.strip_prefix(['x', 'X']) {
+                    Some(hex) => u32::from_str_radix(hex, 16).ok()?,
+                    None => code.parse().ok()?,
+                };
+                char::from_u32(code)
+            }
+        });
+        match (entity, value) {
+            (Some(entity), Some(c)) => {
+                decoded.push(c);
+                rest = &rest[entity.len() + 2..];
+            }
+            _ => {
+                decoded.push('&');
+                rest = &rest[1..];
+            }
+        }
+    }
+
+    decoded.push_str(rest);
+    decoded
+}
+
+/// Open an EPUB from a plain file, a gzipped file, or a `.zip` archive entry
+///
+/// Returns the parsed document and the size of the book file in bytes.
+fn open_doc(path: &Path) -> AppResult<(Doc, i64)> {
+    let (source, file_size) = open_source(path)?;
+
+    let doc = epub::doc::EpubDoc::from_reader(source)
+        .map_err(|e| AppError::EpubParse(format!("Failed to parse EPUB: {}", e)))?;
+
+    Ok((doc, file_size))
+}
+
+/// Read a whole book file into memory, for formats read from a byte slice
+fn read_source(path: &Path) -> AppResult<(Vec<u8>, i64)> {
+    let (source, file_size) = open_source(path)?;
+    let mut data = Vec::new();
+    source
+        .take(MAX_EPUB_SIZE + 1)
+        .read_to_end(&mut data)?;
+    check_size(data.len() as u64)?;
+    Ok((data, file_size))
+}
+
+/// Open the raw bytes for a book path
+fn open_source(path: &Path) -> AppResult<(Box<dyn ReadSeek>, i64)> {
+    if let Some((archive_path, entry_name)) = split_archive_path(path) {
+        let data = read_archive_entry(&archive_path, &entry_name)?;
+        let file_size = data.len() as i64;
+        return Ok((Box::new(Cursor::new(data)), file_size));
+    }
+
+    // Skip very large files (>100MB) - likely audiobooks or corrupted
+    let file_size = std::fs::metadata(path)
+        .map(|m| m.len())
+        .unwrap_or(0);
+    check_size(file_size)?;
+
+    // Open failures stay I/O errors so callers can retry locked files
+    let file = File::open(path)?;
+
+    if is_compressed_book(path) {
+        // The EPUB reader needs to seek, so decompress into memory
+        let mut data = Vec::new();
+        flate2::read::GzDecoder::new(BufReader::new(file))
+            .take(MAX_EPUB_SIZE + 1)
+            .read_to_end(&mut data)
+            .map_err(|e| AppError::EpubParse(format!("Failed to decompress book: {}", e)))?;
+        check_size(data.len() as u64)?;
+        return Ok((Box::new(Cursor::new(data)), file_size as i64));
+    }
+
+    Ok((Box::new(BufReader::new(file)), file_size as i64))
+}
+
+/// Reject EPUBs over the size limit
+fn check_size(size: u64) -> AppResult<()> {
+    if size > MAX_EPUB_SIZE {
+        return Err(AppError::EpubParse(format!(
+            "File too large ({}MB), skipping",
+            size / 1024 / 1024
+        )));
+    }
+    Ok(())
+}
+
+/// Read a single EPUB entry out of a `.zip` archive
+fn read_archive_entry(archive_path: &Path, entry_name: &str) -> AppResult<Vec<u8>> {
+    let file = File::open(archive_path)
+        .map_err(|e| AppError::EpubParse(format!("Failed to open archive: {}", e)))?;
+    let mut archive = zip::ZipArchive::new(BufReader::new(file))
+        .map_err(|e| AppError::EpubParse(format!("Failed to read archive: {}", e)))?;
+    let entry = archive
+        .by_name(entry_name)
+        .map_err(|e| AppError::EpubParse(format!("Missing archive entry {}: {}", entry_name, e)))?;
+    check_size(entry.size())?;
+
+    let mut data = Vec::with_capacity(entry.size() as usize);
+    entry
+        .take(MAX_EPUB_SIZE + 1)
+        .read_to_end(&mut data)
+        .map_err(|e| AppError::EpubParse(format!("Failed to extract {}: {}", entry_name, e)))?;
+    Ok(data)
+}
+
+/// List EPUB entries inside a `.zip` archive as (entry name, uncompressed size)
+///
+/// Nested archives are not descended into.
+pub fn list_archive_epubs(archive_path: &Path) -> AppResult<Vec<(String, u64)>> {
+    let file = File::open(archive_path)?;
+    let mut archive = zip::ZipArchive::new(BufReader::new(file))
+        .map_err(|e| AppError::EpubParse(format!("Failed to read archive: {}", e)))?;
+
+    let mut epubs = Vec::new();
+    for i in 0..archive.len() {
+        let entry = archive
+            .by_index(i)
+            .map_err(|e| AppError::EpubParse(format!("Failed to read archive: {}", e)))?;
+        if entry.is_file() && entry.name().to_lowercase().ends_with(".epub") {
+            epubs.push((entry.name().to_string(), entry.size()));
+        }
+    }
+    Ok(epubs)
+}
+
+/// Split a book path that points inside a `.zip` archive into (archive, entry name)
+///
+/// Returns `None` for paths that exist on disk as regular files.
+pub fn split_archive_path(path: &Path) -> Option<(PathBuf, String)> {
+    if path.exists() {
+        return None;
+    }
+
+    let archive = path.ancestors().skip(1).find(|p| {
+        p.extension().is_some_and(|ext| ext.eq_ignore_ascii_case("zip")) && p.is_file()
+    })?;
+
+    // Zip entry names always use forward slashes
+    let entry_name = path
+        .strip_prefix(archive)
+        .ok()?
+        .components()
+        .map(|c| c.as_os_str().to_string_lossy())
+        .collect::<Vec<_>>()
+        .join("/");
+
+    Some((archive.to_path_buf(), entry_name))
+}
+
+/// Check whether the file (or archive) backing a book path still exists
+pub fn source_exists(path: &Path) -> bool {
+    path.exists() || split_archive_path(path).is_some()
+}
+
+/// Check whether a path is a gzipped book (`.epub.gz`, `.mobi.gz`, ...)
+pub fn is_compressed_book(path: &Path) -> bool {
+    path.extension().is_some_and(|ext| ext.eq_ignore_ascii_case("gz")) && Format::from_path(path).is_some()
+}
+
+/// File name of a book without its extension (`.epub`, `.epub.gz`, ...)
+pub fn book_stem(path: &Path) -> Option<String> {
+    let stem = path.file_stem()?;
+    if is_compressed_book(path) {
+        return Path::new(stem).file_stem().map(|s| s.to_string_lossy().to_string());
+    }
+    Some(stem.to_string_lossy().to_string())
+}
+
+#[cfg(test)]
+pub(crate) use mobi::test_mobi_bytes;
+
+/// Build a minimal valid EPUB in memory (test fixture)
+#[cfg(test)]
+pub(crate) fn test_epub_bytes(title: &str, author: &str) -> Vec<u8> {
+    test_epub_bytes_with_cover(title, author, None)
+}
+
+/// Build a minimal EPUB, optionally with a PNG cover image
+#[cfg(test)]
+pub(crate) fn test_epub_bytes_with_cover(title: &str, author: &str, cover: Option<&[u8]>) -> Vec<u8> {
+    build_test_epub(title, &[(author, None)], None, &[], cover)
+}
+
+/// Build a minimal EPUB carrying a description
+#[cfg(test)]
+pub(crate) fn test_epub_bytes_with_description(title: &str, author: &str, description: &str) -> Vec<u8> {
+    build_test_epub(title, &[(author, None)], Some(description), &[], None)
+}
+
+/// Build a minimal EPUB carrying `<dc:subject>` entries
+#[cfg(test)]
+pub(crate) fn test_epub_bytes_with_subjects(title: &str, author: &str, subjects: &[&str]) -> Vec<u8> {
+    build_test_epub(title, &[(author, None)], None, subjects, None)
+}
+
+/// Build a minimal EPUB crediting several `(name, relator code)` creators
+#[cfg(test)]
+pub(crate) fn test_epub_bytes_with_creators(title: &str, creators: &[(&str, Option<&str>)]) -> Vec<u8> {
+    build_test_epub(title, creators, None, &[], None)
+}
+
+#[cfg(test)]
+fn build_test_epub(
+    title: &str,
+    creators: &[(&str, Option<&str>)],
+    description: Option<&str>,
+    subjects: &[&str],
+    cover: Option<&[u8]>,
+) -> Vec<u8> {
+    use std::io::Write;
+    use zip::write::FileOptions;
+
+    let mut writer = zip::ZipWriter::new(Cursor::new(Vec::new()));
+    let stored = FileOptions::default().compression_method(zip::CompressionMethod::Stored);
+
+    writer.start_file("mimetype", stored).unwrap();
+    writer.write_all(b"application/epub+zip").unwrap();
+
+    writer.start_file("META-INF/container.xml", FileOptions::default()).unwrap();
+    writer
+        .write_all(
+            br#"<?xml version="1.0"?>
+<container version="1.0" xmlns="urn:oasis:names:tc:opendocument:xmlns:container">
+  <rootfiles>
+    <rootfile full-path="OEBPS/content.opf" media-type="application/oebps-package+xml"/>
+  </rootfiles>
+</container>"#,
+        )
+        .unwrap();
+
+    writer.start_file("OEBPS/content.opf", FileOptions::default()).unwrap();
+    write!(
+        writer,
+        r#"<?xml version="1.0" encoding="UTF-8"?>
+<package xmlns="http://www.idpf.org/2007/opf" version="2.0" unique-identifier="id">
+  <metadata xmlns:dc="http://purl.org/dc/elements/1.1/">
+    <dc:title>{}</dc:title>
+    {}
+    <dc:language>en</dc:language>
+    <dc:identifier id="id">test-{}</dc:identifier>
+    {}
+    {}
+    {}
+  </metadata>
+  <manifest>
+    <item id="ch1" href="ch1.xhtml" media-type="application/xhtml+xml"/>
+    {}
+  </manifest>
+  <spine>
+    <itemref idref="ch1"/>
+  </spine>
+</package>"#,
+        title,
+        creators
+            .iter()
+            .enumerate()
+            .map(|(i, (name, role))| {
+                let role = role
+                    .map(|r| format!(r##"<meta refines="#creator{}" property="role">{}</meta>"##, i, r))
+                    .unwrap_or_default();
+                format!(r#"<dc:creator id="creator{}">{}</dc:creator>{}"#, i, name, role)
+            })
+            .collect::<String>(),
+        title.len(),
+        description.map(|d| format!("<dc:description>{}</dc:description>", d)).unwrap_or_default(),
+        subjects.iter().map(|s| format!("<dc:subject>{}</dc:subject>", s)).collect::<String>(),
+        if cover.is_some() { r#"<meta name="cover" content="cover-img"/>"# } else { "" },
+        if cover.is_some() { r#"<item id="cover-img" href="cover.png" media-type="image/png"/>"# } else { "" },
+    )
+    .unwrap();
+
+    if let Some(cover) = cover {
+        writer.start_file("OEBPS/cover.png", FileOptions::default()).unwrap();
+        writer.write_all(cover).unwrap();
+    }
+
+    writer.start_file("OEBPS/ch1.xhtml", FileOptions::default()).unwrap();
+    writer
+        .write_all(b"<html><body><p>Once upon a time.</p></body></html>")
+        .unwrap();
+
+    writer.finish().unwrap().into_inner()
+}
+
+/// Detect an image MIME type from its magic bytes
+pub fn detect_image_mime(data: &[u8]) -> Option<&'static str> {
+    if data.starts_with(&[0x89, 0x50, 0x4E, 0x47]) {
+        Some("image/png")
+    } else if data.starts_with(&[0xFF, 0xD8, 0xFF]) {
+        Some("image/jpeg")
+    } else if data.starts_with(b"GIF87a") || data.starts_with(b"GIF89a") {
+        Some("image/gif")
+    } else if data.len() >= 12 && data.starts_with(b"RIFF") && &data[8..12] == b"WEBP" {
+        Some("image/webp")
+    } else if is_svg(data) {
+        Some("image/svg+xml")
+    } else {
+        None
+    }
+}
+
+/// Check whether data looks like an SVG document
+fn is_svg(data: &[u8]) -> bool {
+    let head = String::from_utf8_lossy(&data[..data.len().min(512)]);
+    let head = head.trim_start_matches('\u{feff}').trim_start();
+    (head.starts_with("<?xml") || head.starts_with("<svg") || head.starts_with("<!DOCTYPE svg"))
+        && head.contains("<svg")
+}
+
+/// Guess an image MIME type from a file extension
+pub fn mime_from_extension(ext: &str) -> Option<&'static str> {
+    match ext.to_lowercase().as_str() {
+        "jpg" | "jpeg" => Some("image/jpeg"),
+        "png" => Some("image/png"),
+        "gif" => Some("image/gif"),
+        "webp" => Some("image/webp"),
+        "svg" => Some("image/svg+xml"),
+        _ => None,
+    }
+}
+
+/// Encode image bytes as a base64 data URL
+pub fn cover_data_url(data: &[u8], mime_type: &str) -> String {
+    use ::base64::Engine;
+    let base64_str = ::base64::engine::general_purpose::STANDARD.encode(data);
+    format!("data:{};base64,{}", mime_type, base64_str)
+}
+
 /// Extract series information from title or calibre metadata
-fn extract_series_info(title: &str, doc: &epub::doc::EpubDoc<BufReader<File>>) -> (Option<String>, Option<f64>) {
+fn extract_series_info(title: &str, doc: &Doc) -> (Option<String>, Option<f64>) {
     // Try calibre:series metadata first
     if let Some(series) = doc.mdata("calibre:series").map(|m| m.value.clone()) {
         let index = doc
@@ -123,53 +722,100 @@ fn extract_series_info(title: &str, doc: &epub::doc::EpubDoc<BufReader<File>>) -
             .and_then(|m| m.value.parse::<f64>().ok());
         return (Some(series), index);
     }
-    
-    // Try to parse from title patterns like:
-    // "Series Name #1 - Book Title"
-    // "Book Title (Series Name, #1)"
-    // "Book Title (Series Name Book 1)"
-    
-    // Pattern: (Series Name, #N)
-    if let Some(captures) = regex_lite::Regex::new(r"\(([^,]+),\s*#?(\d+(?:\.\d+)?)\)")
-        .ok()
-        .and_then(|re| re.captures(title))
-    {
-        let series = captures.get(1).map(|m| m.as_str().trim().to_string());
-        let index = captures.get(2).and_then(|m| m.as_str().parse::<f64>().ok());
-        if series.is_some() {
-            return (series, index);
+
+    series_from_title(title)
+}
+
+/// `Book Title (Series Name, #N)`
+static SERIES_IN_PARENS: Lazy<Regex> =
+    Lazy::new(|| Regex::new(r"\(([^,()]+),\s*#?(\d+(?:\.\d+)?)\)").unwrap());
+/// `Series Name #N - Book Title`
+static SERIES_PREFIX: Lazy<Regex> =
+    Lazy::new(|| Regex::new(r"^(.+?)\s*#(\d+(?:\.\d+)?)\s*[-–:]").unwrap());
+/// `Book Title (Series Name Book N)`
+static SERIES_BOOK_N: Lazy<Regex> =
+    Lazy::new(|| Regex::new(r"\((.+?)\s+Book\s+(\d+(?:\.\d+)?)\)").unwrap());
+
+/// Parse series information out of a title
+fn series_from_title(title: &str) -> (Option<String>, Option<f64>) {
+    [&SERIES_IN_PARENS, &SERIES_PREFIX, &SERIES_BOOK_N]
+        .into_iter()
+        .find_map(|pattern| {
+            let captures = pattern.captures(title)?;
+            let series = captures[1].trim();
+            (!series.is_empty()).then(|| (Some(series.to_string()), captures[2].parse::<f64>().ok()))
+        })
+        .unwrap_or((None, None))
+}
+
+/// Everyone in the `creator` entries, in order
+///
+/// EPUB 3 gives a creator's role (a MARC relator code) and sort name as
+/// refinements; creators without a role are authors.
+fn creators(metadata: &[epub::doc::MetadataItem]) -> Vec<NewAuthor> {
+    distinct_authors(metadata.iter().filter(|m| m.property == "creator").map(|creator| {
+        let refinement = |property: &str| {
+            creator
+                .refined
+                .iter()
+                .find(|r| r.property == property)
+                .map(|r| r.value.as_str())
+        };
+        (creator.value.as_str(), refinement("role"), refinement("file-as"))
+    }))
+}
+
+/// Trimmed, non-empty `(name, relator code, sort name)` credits as authors,
+/// with duplicate names removed
+fn distinct_authors<'a>(
+    credits: impl Iterator<Item = (&'a str, Option<&'a str>, Option<&'a str>)>,
+) -> Vec<NewAuthor> {
+    let mut authors: Vec<NewAuthor> = Vec::new();
+    for (name, role, sort_name) in credits {
+        let name = name.trim();
+        if name.is_empty() || authors.iter().any(|a| a.name == name) {
+            continue;
         }
-    }
-    
-    // Pattern: Series Name #N -
-    if let Some(captures) = regex_lite::Regex::new(r"^(.+?)\s*#(\d+(?:\.\d+)?)\s*[-–:]")
-        .ok()
-        .and_then(|re| re.captures(title))
-    {
-        let series = captures.get(1).map(|m| m.as_str().trim().to_string());
-        let index = captures.get(2).and_then(|m| m.as_str().parse::<f64>().ok());
-        if series.is_some() {
-            return (series, index);
+        let mut author = NewAuthor::author(name);
+        if let Some(role) = role {
+            author.role = relator_role(role);
         }
+        if let Some(sort_name) = sort_name.map(str::trim).filter(|s| !s.is_empty()) {
+            author.sort_name = Some(sort_name.to_string());
+        }
+        authors.push(author);
     }
-    
-    // Pattern: (Series Name Book N)
-    if let Some(captures) = regex_lite::Regex::new(r"\((.+?)\s+Book\s+(\d+(?:\.\d+)?)\)")
-        .ok()
-        .and_then(|re| re.captures(title))
-    {
-        let series = captures.get(1).map(|m| m.as_str().trim().to_string());
-        let index = captures.get(2).and_then(|m| m.as_str().parse::<f64>().ok());
-        if series.is_some() {
-            return (series, index);
+    authors
+}
+
+/// Readable role for a MARC relator code; unknown codes are kept as given
+fn relator_role(code: &str) -> String {
+    let code = code.trim().to_lowercase();
+    match code.as_str() {
+        "aut" => "author",
+        "edt" => "editor",
+        "trl" => "translator",
+        "ill" => "illustrator",
+        "nrt" => "narrator",
+        "aui" => "introduction",
+        _ => return code,
+    }
+    .to_string()
+}
+
+/// Trimmed, non-empty subjects with case-insensitive duplicates removed
+fn distinct_subjects<'a>(subjects: impl Iterator<Item = &'a str>) -> Vec<String> {
+    let mut tags: Vec<String> = Vec::new();
+    for subject in subjects.map(str::trim).filter(|s| !s.is_empty()) {
+        if !tags.iter().any(|tag| tag.eq_ignore_ascii_case(subject)) {
+            tags.push(subject.to_string());
         }
     }
-    
-    (None, None)
+    tags
 }
 
 /// Generate a sort-friendly title (strip leading articles)
-fn generate_sort_title(title: &str) -> String {
+pub fn generate_sort_title(title: &str) -> String {
     let lower = title.to_lowercase();
     
     let articles = ["the ", "a ", "an ", "le ", "la ", "les ", "un ", "une ", "el ", "los ", "las "];
@@ -184,7 +830,7 @@ fn generate_sort_title(title: &str) -> String {
 }
 
 /// Generate author sort name (Last, First)
-fn generate_author_sort(author: &str) -> String {
+pub fn generate_author_sort(author: &str) -> String {
     // Handle multiple authors (take first)
     let author = author.split(&[',', ';', '&'][..]).next().unwrap_or(author).trim();
     
@@ -198,87 +844,238 @@ fn generate_author_sort(author: &str) -> String {
 }
 
 /// Calculate SHA-256 hash of file for deduplication
-#[allow(dead_code)]
-fn calculate_file_hash(path: &Path) -> AppResult<String> {
+///
+/// Files are streamed through a fixed-size buffer so memory stays bounded
+/// for large books. EPUBs inside archives hash the stored entry's bytes.
+pub fn calculate_file_hash(path: &Path) -> AppResult<String> {
     use sha2::{Sha256, Digest};
-    
-    let mut file = File::open(path)?;
+
     let mut hasher = Sha256::new();
-    
-    std::io::copy(&mut file, &mut hasher)?;
-    
+
+    if let Some((archive, entry)) = split_archive_path(path) {
+        hasher.update(read_archive_entry(&archive, &entry)?);
+    } else {
+        let mut reader = BufReader::with_capacity(256 * 1024, File::open(path)?);
+        std::io::copy(&mut reader, &mut hasher)?;
+    }
+
     let hash = hasher.finalize();
     Ok(format!("{:x}", hash))
 }
 
-// Minimal regex support for series parsing
-mod regex_lite {
-    use std::collections::HashMap;
-    
-    pub struct Regex {
-        pattern: String,
-    }
-    
-    pub struct Captures<'a> {
-        text: &'a str,
-        groups: HashMap<usize, (usize, usize)>,
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+
+    #[test]
+    fn test_word_count_and_reading_time() {
+        assert_eq!(
+            strip_tags("<p>Once <em>upon</em> a time.</p>").split_whitespace().count(),
+            4
+        );
+        assert_eq!(estimate_reading_time(25_000, DEFAULT_WORDS_PER_MINUTE), 100.0);
+
+        let temp = tempfile::TempDir::new().unwrap();
+        let path = temp.path().join("short.epub");
+        std::fs::write(&path, test_epub_bytes("Short", "Author")).unwrap();
+
+        let book = EpubParser::new().parse(&path).unwrap();
+        assert_eq!(book.word_count, Some(4));
     }
-    
-    impl Regex {
-        pub fn new(pattern: &str) -> Result<Self, ()> {
-            Ok(Self { pattern: pattern.to_string() })
-        }
-        
-        pub fn captures<'a>(&self, text: &'a str) -> Option<Captures<'a>> {
-            // Simple pattern matching for our specific use cases
-            // This is a simplified implementation - in production, use the regex crate
-            
-            if self.pattern.contains(r"\(([^,]+),\s*#?(\d+(?:\.\d+)?)\)") {
-                // Match (Series Name, #N) pattern
-                if let Some(start) = text.find('(') {
-                    if let Some(end) = text[start..].find(')') {
-                        let inner = &text[start + 1..start + end];
-                        if let Some(comma) = inner.find(',') {
-                            let _series = &inner[..comma];
-                            let rest = inner[comma + 1..].trim();
-                            let rest = rest.trim_start_matches('#');
-                            if let Ok(_num) = rest.parse::<f64>() {
-                                let mut groups = HashMap::new();
-                                groups.insert(1, (start + 1, start + 1 + comma));
-                                groups.insert(2, (start + comma + 2, start + end));
-                                return Some(Captures { text, groups });
-                            }
-                        }
-                    }
-                }
-            }
-            
-            None
+
+    /// EPUB 3 with a cover page, a nav document and a stylesheet around
+    /// three chapters, the last one non-linear
+    fn multi_chapter_epub() -> Vec<u8> {
+        use zip::write::FileOptions;
+
+        let mut writer = zip::ZipWriter::new(Cursor::new(Vec::new()));
+        let files: [(&str, &str); 8] = [
+            ("mimetype", "application/epub+zip"),
+            (
+                "META-INF/container.xml",
+                r#"<?xml version="1.0"?>
+<container version="1.0" xmlns="urn:oasis:names:tc:opendocument:xmlns:container">
+  <rootfiles>
+    <rootfile full-path="OEBPS/content.opf" media-type="application/oebps-package+xml"/>
+  </rootfiles>
+</container>"#,
+            ),
+            (
+                "OEBPS/content.opf",
+                r#"<?xml version="1.0" encoding="UTF-8"?>
+<package xmlns="http://www.idpf.org/2007/opf" version="3.0" unique-identifier="id">
+  <metadata xmlns:dc="http://purl.org/dc/elements/1.1/">
+    <dc:title>Chapters</dc:title>
+    <dc:identifier id="id">test-chapters</dc:identifier>
+  </metadata>
+  <manifest>
+    <item id="cover" href="cover.xhtml" media-type="application/xhtml+xml"/>
+    <item id="nav" href="nav.xhtml" media-type="application/xhtml+xml" properties="nav"/>
+    <item id="css" href="style.css" media-type="text/css"/>
+    <item id="ch2" href="ch2.xhtml" media-type="application/xhtml+xml"/>
+    <item id="ch1" href="ch1.xhtml" media-type="application/xhtml+xml"/>
+    <item id="notes" href="notes.xhtml" media-type="application/xhtml+xml"/>
+  </manifest>
+  <spine>
+    <itemref idref="cover"/>
+    <itemref idref="nav"/>
+    <itemref idref="ch1"/>
+    <itemref idref="ch2"/>
+    <itemref idref="notes" linear="no"/>
+  </spine>
+</package>"#,
+            ),
+            ("OEBPS/cover.xhtml", "<html><body><p>Cover words here</p></body></html>"),
+            ("OEBPS/nav.xhtml", "<html><body><nav><ol><li>Chapter One</li><li>Chapter Two</li></ol></nav></body></html>"),
+            ("OEBPS/style.css", "body { font-family: serif; margin: 0 auto; }"),
+            (
+                "OEBPS/ch1.xhtml",
+                "<html><head><title>Chapter One</title><style>p { x: y }</style></head>\
+                 <body><h1>One</h1><p>Tom&nbsp;&amp;&nbsp;Jerry ran&#8212;fast.</p></body></html>",
+            ),
+            ("OEBPS/ch2.xhtml", "<html><body><h1>Two</h1><p>It was &ldquo;late&rdquo; and dark.</p></body></html>"),
+        ];
+        for (name, content) in files {
+            writer.start_file(name, FileOptions::default()).unwrap();
+            writer.write_all(content.as_bytes()).unwrap();
         }
+        writer.start_file("OEBPS/notes.xhtml", FileOptions::default()).unwrap();
+        writer.write_all(b"<html><body><p>Endnotes are not running text.</p></body></html>").unwrap();
+        writer.finish().unwrap().into_inner()
     }
-    
-    impl<'a> Captures<'a> {
-        pub fn get(&self, index: usize) -> Option<Match<'a>> {
-            self.groups.get(&index).map(|(start, end)| Match {
-                text: &self.text[*start..*end],
-            })
-        }
+
+    #[test]
+    fn test_spine_text_in_reading_order() {
+        let temp = tempfile::TempDir::new().unwrap();
+        let path = temp.path().join("chapters.epub");
+        std::fs::write(&path, multi_chapter_epub()).unwrap();
+
+        let parser = EpubParser::new();
+        let texts: Vec<String> = parser
+            .spine_text_iter(&path)
+            .unwrap()
+            .map(|text| text.split_whitespace().collect::<Vec<_>>().join(" "))
+            .collect();
+        assert_eq!(texts, vec!["One Tom & Jerry ran\u{2014}fast.", "Two It was \u{201c}late\u{201d} and dark."]);
+
+        // Cover, nav, stylesheet, head and non-linear notes don't count
+        assert_eq!(parser.parse(&path).unwrap().word_count, Some(11));
     }
-    
-    pub struct Match<'a> {
-        text: &'a str,
+
+    #[test]
+    fn test_text_excerpt_spans_chapters_and_cuts_at_words() {
+        let temp = tempfile::TempDir::new().unwrap();
+        let path = temp.path().join("chapters.epub");
+        std::fs::write(&path, multi_chapter_epub()).unwrap();
+
+        let parser = EpubParser::new();
+        assert_eq!(parser.extract_text_excerpt(&path, 32).unwrap(), "One Tom & Jerry ran\u{2014}fast. Two It");
+        assert_eq!(parser.extract_text_excerpt(&path, 17).unwrap(), "One Tom & Jerry");
+        assert_eq!(
+            parser.extract_text_excerpt(&path, 1000).unwrap(),
+            "One Tom & Jerry ran\u{2014}fast. Two It was \u{201c}late\u{201d} and dark."
+        );
     }
-    
-    impl<'a> Match<'a> {
-        pub fn as_str(&self) -> &'a str {
-            self.text.trim()
-        }
+
+    #[test]
+    fn test_parse_collects_subjects() {
+        let temp = tempfile::TempDir::new().unwrap();
+        let path = temp.path().join("hobbit.epub");
+        let subjects = ["Fantasy", "Adventure", " fantasy ", ""];
+        std::fs::write(&path, test_epub_bytes_with_subjects("The Hobbit", "J.R.R. Tolkien", &subjects)).unwrap();
+
+        let book = EpubParser::new().parse(&path).unwrap();
+        assert_eq!(book.tags, vec!["Fantasy", "Adventure"]);
     }
-}
 
-#[cfg(test)]
-mod tests {
-    use super::*;
+    #[test]
+    fn test_parse_collects_all_creators() {
+        let temp = tempfile::TempDir::new().unwrap();
+        let path = temp.path().join("good-omens.epub");
+        let creators = [
+            ("Jane Editor", Some("edt")),
+            ("Terry Pratchett", Some("aut")),
+            ("Neil Gaiman", None),
+            ("Terry Pratchett", None),
+            ("Jean Dupont", Some("trl")),
+        ];
+        std::fs::write(&path, test_epub_bytes_with_creators("Good Omens", &creators)).unwrap();
+
+        let book = EpubParser::new().parse(&path).unwrap();
+        assert_eq!(book.author.as_deref(), Some("Terry Pratchett"));
+        assert_eq!(book.author_sort.as_deref(), Some("Pratchett, Terry"));
+        let credits: Vec<(&str, &str)> = book.authors.iter().map(|a| (a.name.as_str(), a.role.as_str())).collect();
+        assert_eq!(
+            credits,
+            vec![
+                ("Jane Editor", "editor"),
+                ("Terry Pratchett", "author"),
+                ("Neil Gaiman", "author"),
+                ("Jean Dupont", "translator"),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_parse_gzipped_epub() {
+        let temp = tempfile::TempDir::new().unwrap();
+        let path = temp.path().join("dune.epub.gz");
+
+        let mut encoder = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::default());
+        encoder.write_all(&test_epub_bytes("Dune", "Frank Herbert")).unwrap();
+        std::fs::write(&path, encoder.finish().unwrap()).unwrap();
+
+        let book = EpubParser::new().parse(&path).unwrap();
+        assert_eq!(book.title, "Dune");
+        assert_eq!(book.author.as_deref(), Some("Frank Herbert"));
+        assert_eq!(book.container_path, None);
+        assert_eq!(book_stem(&path).as_deref(), Some("dune"));
+    }
+
+    #[test]
+    fn test_parse_epub_inside_zip() {
+        let temp = tempfile::TempDir::new().unwrap();
+        let archive_path = temp.path().join("Emma.zip");
+
+        let mut writer = zip::ZipWriter::new(File::create(&archive_path).unwrap());
+        writer
+            .start_file("Emma/emma.epub", zip::write::FileOptions::default())
+            .unwrap();
+        writer.write_all(&test_epub_bytes("Emma", "Jane Austen")).unwrap();
+        writer.finish().unwrap();
+
+        let entries = list_archive_epubs(&archive_path).unwrap();
+        assert_eq!(entries.len(), 1);
+        assert_eq!(entries[0].0, "Emma/emma.epub");
+
+        let book_path = archive_path.join("Emma").join("emma.epub");
+        assert!(source_exists(&book_path));
+
+        let book = EpubParser::new().parse(&book_path).unwrap();
+        assert_eq!(book.title, "Emma");
+        assert_eq!(book.author.as_deref(), Some("Jane Austen"));
+        assert_eq!(book.container_path, Some(archive_path.to_string_lossy().to_string()));
+    }
+
+    #[test]
+    fn test_cover_data_url_prefix() {
+        let cases: [(&[u8], &str); 5] = [
+            (&[0x89, 0x50, 0x4E, 0x47, 0x0D, 0x0A, 0x1A, 0x0A], "data:image/png;base64,"),
+            (&[0xFF, 0xD8, 0xFF, 0xE0, 0x00, 0x10], "data:image/jpeg;base64,"),
+            (b"GIF89a\x01\x00\x01\x00", "data:image/gif;base64,"),
+            (b"RIFF\x24\x00\x00\x00WEBPVP8 ", "data:image/webp;base64,"),
+            (b"<?xml version=\"1.0\"?>\n<svg xmlns=\"http://www.w3.org/2000/svg\"/>", "data:image/svg+xml;base64,"),
+        ];
+
+        for (data, prefix) in cases {
+            let mime = detect_image_mime(data).unwrap();
+            assert!(cover_data_url(data, mime).starts_with(prefix), "{}", prefix);
+        }
+
+        assert_eq!(detect_image_mime(b"not an image"), None);
+        assert_eq!(mime_from_extension("WEBP"), Some("image/webp"));
+    }
     
     #[test]
     fn test_sort_title() {
@@ -287,6 +1084,23 @@ mod tests {
         assert_eq!(generate_sort_title("1984"), "1984");
     }
     
+    #[test]
+    fn test_series_from_title() {
+        let cases = [
+            ("The Well of Ascension (Mistborn, #2)", Some("Mistborn"), Some(2.0)),
+            ("Edgedancer (The Stormlight Archive, 2.5)", Some("The Stormlight Archive"), Some(2.5)),
+            ("Mistborn #2 - The Well of Ascension", Some("Mistborn"), Some(2.0)),
+            ("Stormlight Archive #2.5: Edgedancer", Some("Stormlight Archive"), Some(2.5)),
+            ("The Hobbit (Middle-earth Book 1)", Some("Middle-earth"), Some(1.0)),
+            ("Dust (Silo Book 3.5)", Some("Silo"), Some(3.5)),
+            ("Catch-22", None, None),
+            ("Notes (Revised, Expanded)", None, None),
+        ];
+        for (title, series, index) in cases {
+            assert_eq!(series_from_title(title), (series.map(String::from), index), "{}", title);
+        }
+    }
+
     #[test]
     fn test_author_sort() {
         assert_eq!(generate_author_sort("John Smith"), "Smith, John");
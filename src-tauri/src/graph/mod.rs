@@ -7,11 +7,17 @@
 //! 4. Personalized PageRank for relevance scoring
 //! 5. Maximal Marginal Relevance for diversity
 
-use crate::db::{Book, Database};
-use crate::AppResult;
+use crate::db::{Book, Database, PreferenceSignal};
+use crate::ollama::EMBEDDING_TEXT_VERSION;
+use crate::vector::VectorStore;
+use crate::{AppError, AppResult};
 use petgraph::graph::{DiGraph, NodeIndex};
 use petgraph::visit::EdgeRef;
-use std::collections::{HashMap, HashSet, VecDeque};
+use std::collections::{BTreeMap, BinaryHeap, HashMap, HashSet, VecDeque};
+use std::time::Instant;
+
+/// Weakest edge loaded into a `BookGraph` for recommendations
+pub const GRAPH_MIN_WEIGHT: f64 = 0.3;
 
 /// In-memory graph representation for fast traversal
 pub struct BookGraph {
@@ -73,6 +79,36 @@ impl BookGraph {
         Ok(graph)
     }
 
+    /// Serialize the edge list in insertion order
+    ///
+    /// [`from_bytes`](Self::from_bytes) replays it, so neighbor order is kept.
+    pub fn to_bytes(&self) -> AppResult<Vec<u8>> {
+        let edges: Vec<Edge> = self
+            .graph
+            .edge_references()
+            .map(|edge| {
+                (
+                    self.node_to_id[&edge.source()],
+                    self.node_to_id[&edge.target()],
+                    edge.weight().edge_type.clone(),
+                    edge.weight().weight,
+                )
+            })
+            .collect();
+        bincode::serialize(&edges).map_err(|e| AppError::InvalidInput(format!("Can't serialize graph: {}", e)))
+    }
+
+    /// Rebuild a graph written by [`to_bytes`](Self::to_bytes)
+    pub fn from_bytes(bytes: &[u8]) -> AppResult<Self> {
+        let edges: Vec<Edge> =
+            bincode::deserialize(bytes).map_err(|e| AppError::InvalidInput(format!("Corrupt graph data: {}", e)))?;
+        let mut graph = Self::new();
+        for (source, target, edge_type, weight) in edges {
+            graph.add_edge(source, target, weight, edge_type);
+        }
+        Ok(graph)
+    }
+
     /// Add or get a node for a book ID
     fn get_or_create_node(&mut self, book_id: i64) -> NodeIndex {
         if let Some(&idx) = self.id_to_node.get(&book_id) {
@@ -112,10 +148,93 @@ impl BookGraph {
         }
     }
 
+    /// Strongest chain of edges leading from `source` to `target`
+    ///
+    /// Dijkstra with edge cost `-ln(weight)`, so the path with the largest
+    /// product of weights wins and every extra hop has to earn its place.
+    /// Returns each book on the path with the type of the edge leading to it
+    /// (empty for `source`), or `None` when `target` can't be reached.
+    pub fn shortest_path(&self, source: i64, target: i64) -> Option<Vec<(i64, String)>> {
+        let start = *self.id_to_node.get(&source)?;
+        let goal = *self.id_to_node.get(&target)?;
+
+        let mut cost: HashMap<NodeIndex, f64> = HashMap::from([(start, 0.0)]);
+        let mut previous: HashMap<NodeIndex, (NodeIndex, String)> = HashMap::new();
+        let mut queue = BinaryHeap::from([PathStep { cost: 0.0, node: start }]);
+
+        while let Some(PathStep { cost: node_cost, node }) = queue.pop() {
+            if node == goal {
+                break;
+            }
+            if node_cost > cost[&node] {
+                continue; // Already reached more cheaply
+            }
+            for edge in self.graph.edges(node) {
+                let weight = edge.weight().weight.min(1.0);
+                if weight <= 0.0 {
+                    continue;
+                }
+                let next_cost = node_cost - weight.ln();
+                if cost.get(&edge.target()).map_or(true, |&known| next_cost < known) {
+                    cost.insert(edge.target(), next_cost);
+                    previous.insert(edge.target(), (node, edge.weight().edge_type.clone()));
+                    queue.push(PathStep { cost: next_cost, node: edge.target() });
+                }
+            }
+        }
+
+        if !cost.contains_key(&goal) {
+            return None;
+        }
+        let mut path = Vec::new();
+        let mut node = goal;
+        while let Some((from, edge_type)) = previous.get(&node) {
+            path.push((self.node_to_id[&node], edge_type.clone()));
+            node = *from;
+        }
+        path.push((source, String::new()));
+        path.reverse();
+        Some(path)
+    }
+
     /// Get node count
     pub fn node_count(&self) -> usize {
         self.graph.node_count()
     }
+
+    /// Get edge count
+    pub fn edge_count(&self) -> usize {
+        self.graph.edge_count()
+    }
+}
+
+/// Node waiting in `BookGraph::shortest_path`, cheapest popped first
+struct PathStep {
+    cost: f64,
+    node: NodeIndex,
+}
+
+impl PartialEq for PathStep {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == std::cmp::Ordering::Equal
+    }
+}
+
+impl Eq for PathStep {}
+
+impl PartialOrd for PathStep {
+    fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for PathStep {
+    fn cmp(&self, other: &Self) -> std::cmp::Ordering {
+        other
+            .cost
+            .total_cmp(&self.cost)
+            .then_with(|| other.node.cmp(&self.node))
+    }
 }
 
 impl Default for BookGraph {
@@ -135,6 +254,9 @@ pub struct TraversalConfig {
     pub decay_factor: f64,
     /// Maximum candidates to expand
     pub max_candidates: usize,
+    /// Stop expanding past the seeds' neighbours at this time and return the
+    /// candidates found so far
+    pub deadline: Option<Instant>,
 }
 
 impl Default for TraversalConfig {
@@ -144,6 +266,7 @@ impl Default for TraversalConfig {
             min_weights: vec![0.5, 0.4, 0.3],      // Decreasing thresholds
             decay_factor: 0.75,                    // 25% decay per hop
             max_candidates: 500,
+            deadline: None,
         }
     }
 }
@@ -157,6 +280,9 @@ pub struct TraversalCandidate {
     pub edge_types: Vec<String>,
 }
 
+/// Frontier entry: (node, accumulated score, path, edge types, hop)
+type FrontierEntry = (i64, f64, Vec<i64>, Vec<String>, usize);
+
 /// Multi-hop graph traversal for candidate expansion
 ///
 /// This is a key component of the state-of-the-art recommendation algorithm.
@@ -171,7 +297,7 @@ pub fn multi_hop_traversal(
 ) -> Vec<TraversalCandidate> {
     let mut candidates: HashMap<i64, TraversalCandidate> = HashMap::new();
     let mut visited: HashSet<i64> = HashSet::new();
-    let mut frontier: VecDeque<(i64, f64, Vec<i64>, Vec<String>, usize)> = VecDeque::new();
+    let mut frontier: VecDeque<FrontierEntry> = VecDeque::new();
 
     // Initialize frontier with seeds
     for &seed in seeds {
@@ -180,6 +306,10 @@ pub fn multi_hop_traversal(
     }
 
     while let Some((node, accumulated_score, path, edge_types, hop)) = frontier.pop_front() {
+        // The seeds' direct neighbours are always expanded
+        if hop > 0 && past(config.deadline) {
+            break;
+        }
         if hop >= config.max_hops || candidates.len() >= config.max_candidates {
             continue;
         }
@@ -233,7 +363,124 @@ pub fn multi_hop_traversal(
     }
 
     let mut result: Vec<_> = candidates.into_values().collect();
-    result.sort_by(|a, b| b.score.partial_cmp(&a.score).unwrap_or(std::cmp::Ordering::Equal));
+    result.sort_by(|a, b| {
+        b.score
+            .partial_cmp(&a.score)
+            .unwrap_or(std::cmp::Ordering::Equal)
+            .then(a.book_id.cmp(&b.book_id))
+    });
+    result
+}
+
+/// Whether an optional deadline has passed
+fn past(deadline: Option<Instant>) -> bool {
+    deadline.is_some_and(|deadline| Instant::now() >= deadline)
+}
+
+/// Small deterministic PRNG (SplitMix64) so walks are reproducible from a seed
+struct SplitMix64(u64);
+
+impl SplitMix64 {
+    fn next_u64(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        z ^ (z >> 31)
+    }
+
+    /// Uniform float in `[0, 1)`
+    fn next_f64(&mut self) -> f64 {
+        (self.next_u64() >> 11) as f64 / (1u64 << 53) as f64
+    }
+}
+
+/// Candidate generation strategy for the hybrid pipeline
+#[derive(Debug, Clone, PartialEq, Default)]
+pub enum CandidateStrategy {
+    /// Exhaustive breadth-first expansion (see [`multi_hop_traversal`])
+    #[default]
+    Traversal,
+    /// Weighted random walks (see [`random_walk_recommendations`])
+    RandomWalk {
+        walks: usize,
+        walk_length: usize,
+        seed: u64,
+    },
+}
+
+/// Weighted random-walk candidate sampling
+///
+/// Runs `walks` walks of up to `walk_length` steps, each starting from a
+/// seed (round-robin) and following edges with probability proportional to
+/// their weight. Candidates are ranked by visit frequency, so results don't
+/// depend on expansion order and cost scales with the walk budget rather than
+/// the graph size. Each candidate keeps the path of its first visit.
+pub fn random_walk_recommendations(
+    graph: &BookGraph,
+    seeds: &[i64],
+    walks: usize,
+    walk_length: usize,
+    rng_seed: u64,
+) -> Vec<TraversalCandidate> {
+    if seeds.is_empty() || walks == 0 {
+        return vec![];
+    }
+
+    let mut rng = SplitMix64(rng_seed);
+    let mut visits: HashMap<i64, (usize, Vec<i64>, Vec<String>)> = HashMap::new();
+    let mut total_visits = 0usize;
+
+    for walk in 0..walks {
+        let mut node = seeds[walk % seeds.len()];
+        let mut path = vec![node];
+        let mut edge_types: Vec<String> = Vec::new();
+
+        for _ in 0..walk_length {
+            let neighbors = graph.neighbors(node);
+            let total_weight: f64 = neighbors.iter().map(|(_, w, _)| w.max(0.0)).sum();
+            if total_weight <= 0.0 {
+                break;
+            }
+
+            let mut pick = rng.next_f64() * total_weight;
+            let (next, _, edge_type) = neighbors
+                .iter()
+                .find(|(_, weight, _)| {
+                    pick -= weight.max(0.0);
+                    pick < 0.0
+                })
+                .unwrap_or(&neighbors[neighbors.len() - 1]);
+
+            node = *next;
+            path.push(node);
+            edge_types.push(edge_type.clone());
+
+            if !seeds.contains(&node) {
+                total_visits += 1;
+                visits
+                    .entry(node)
+                    .or_insert_with(|| (0, path.clone(), edge_types.clone()))
+                    .0 += 1;
+            }
+        }
+    }
+
+    let mut result: Vec<TraversalCandidate> = visits
+        .into_iter()
+        .map(|(book_id, (count, path, edge_types))| TraversalCandidate {
+            book_id,
+            score: count as f64 / total_visits as f64,
+            path,
+            edge_types,
+        })
+        .collect();
+    result.sort_by(|a, b| {
+        b.score
+            .partial_cmp(&a.score)
+            .unwrap_or(std::cmp::Ordering::Equal)
+            .then(a.book_id.cmp(&b.book_id))
+    });
     result
 }
 
@@ -248,6 +495,8 @@ pub struct PageRankConfig {
     pub iterations: usize,
     /// Convergence threshold
     pub epsilon: f64,
+    /// Share of a disliked book's score passed on to its neighbors
+    pub dislike_damping: f64,
 }
 
 impl Default for PageRankConfig {
@@ -257,20 +506,96 @@ impl Default for PageRankConfig {
             preference_weight: 0.3,
             iterations: 20,
             epsilon: 1e-6,
+            dislike_damping: 0.2,
         }
     }
 }
 
+/// Half-life of a preference signal's influence, in days
+pub const PREFERENCE_HALF_LIFE_DAYS: f64 = 180.0;
+
+/// Lower bound for the recency decay so old favorites still count a little
+const MIN_RECENCY_FACTOR: f64 = 0.25;
+
+/// Earliest signal time that may seed personalization
+///
+/// A window of 0 days means all time.
+pub fn recent_reads_cutoff(window_days: u32, now: i64) -> i64 {
+    if window_days == 0 {
+        i64::MIN
+    } else {
+        now - window_days as i64 * 86_400
+    }
+}
+
+/// Compute the personalization weight of a single rating/read-status signal
+///
+/// Ratings of at least `liked_rating` weigh most, finished and
+/// currently-reading books less, and abandoned books act as a mild negative.
+/// Lower explicit ratings carry no positive signal. Weights decay with the
+/// age of the signal.
+pub fn preference_weight(signal: &PreferenceSignal, now: i64, liked_rating: f64) -> f64 {
+    let base = match (signal.rating, signal.read_status.as_deref()) {
+        (_, Some("abandoned")) => -0.3,
+        (Some(rating), _) if rating >= liked_rating => rating / 5.0,
+        (Some(_), _) => 0.0,
+        (None, Some("finished")) => 0.5,
+        (None, Some("reading")) => 0.4,
+        _ => 0.0,
+    };
+
+    let age_days = (now - signal.updated_at).max(0) as f64 / 86_400.0;
+    let recency = 0.5f64
+        .powf(age_days / PREFERENCE_HALF_LIFE_DAYS)
+        .max(MIN_RECENCY_FACTOR);
+
+    base * recency
+}
+
+/// Build a weighted preference vector from rating/read-status signals
+///
+/// Books without any signal are dropped; negative weights are kept so the
+/// PageRank personalization can push away from them.
+pub fn build_preference_vector(signals: &[PreferenceSignal], now: i64, liked_rating: f64) -> Vec<(i64, f64)> {
+    signals
+        .iter()
+        .map(|s| (s.book_id, preference_weight(s, now, liked_rating)))
+        .filter(|&(_, weight)| weight != 0.0)
+        .collect()
+}
+
 /// Personalized PageRank for relevance scoring
 ///
 /// Combines:
 /// - Graph structure (random walk probability)
 /// - User preferences (teleport to highly-rated books)
 /// - Seed relevance (teleport to query-similar books)
+/// - Dislikes (never teleported to, and walks through them are dampened)
 pub fn personalized_pagerank(
     graph: &BookGraph,
     seeds: &[i64],
     preferences: &[i64],
+    dislikes: &[i64],
+    config: &PageRankConfig,
+) -> HashMap<i64, f64> {
+    let weighted: Vec<(i64, f64)> = preferences.iter().map(|&id| (id, 1.0)).collect();
+    weighted_personalized_pagerank(graph, seeds, &weighted, dislikes, config)
+}
+
+/// Personalized PageRank with per-book preference weights
+///
+/// Positive weights split the preference teleport mass proportionally.
+/// Negative weights subtract from a node's teleport probability (clamped
+/// at zero). Without seeds, preferences receive the whole teleport mass.
+///
+/// Disliked books get no teleport probability at all, and only pass
+/// `config.dislike_damping` of their score on, so books reached mainly
+/// through them rank lower too.
+pub fn weighted_personalized_pagerank(
+    graph: &BookGraph,
+    seeds: &[i64],
+    preferences: &[(i64, f64)],
+    dislikes: &[i64],
     config: &PageRankConfig,
 ) -> HashMap<i64, f64> {
     let n = graph.node_count();
@@ -278,28 +603,28 @@ pub fn personalized_pagerank(
         return HashMap::new();
     }
 
-    let mut scores: HashMap<i64, f64> = HashMap::new();
     let all_nodes: Vec<i64> = graph.id_to_node.keys().copied().collect();
-
-    // Initialize uniform distribution
     let initial_score = 1.0 / n as f64;
-    for &node in &all_nodes {
-        scores.insert(node, initial_score);
-    }
 
     // Build personalization vector
     let mut personalization: HashMap<i64, f64> = HashMap::new();
-    let total_personalization = seeds.len() + preferences.len();
+    let positive_total: f64 = preferences.iter().map(|&(_, w)| w.max(0.0)).sum();
 
-    if total_personalization > 0 {
-        let seed_weight = (1.0 - config.preference_weight) / seeds.len().max(1) as f64;
-        let pref_weight = config.preference_weight / preferences.len().max(1) as f64;
+    if !seeds.is_empty() || positive_total > 0.0 {
+        let preference_share = if seeds.is_empty() { 1.0 } else { config.preference_weight };
+        let seed_weight = (1.0 - preference_share) / seeds.len().max(1) as f64;
 
         for &seed in seeds {
             *personalization.entry(seed).or_default() += seed_weight;
         }
-        for &pref in preferences {
-            *personalization.entry(pref).or_default() += pref_weight;
+        if positive_total > 0.0 {
+            for &(pref, weight) in preferences {
+                *personalization.entry(pref).or_default() +=
+                    preference_share * weight / positive_total;
+            }
+        }
+        for value in personalization.values_mut() {
+            *value = value.max(0.0);
         }
     } else {
         // Uniform personalization if no seeds/preferences
@@ -307,44 +632,441 @@ pub fn personalized_pagerank(
             personalization.insert(node, initial_score);
         }
     }
+    for &dislike in dislikes {
+        personalization.insert(dislike, 0.0);
+    }
+
+    // Incoming edges of each node as (source, weight / source out-degree),
+    // built once so each iteration is linear in the number of edges
+    let position: HashMap<i64, usize> = all_nodes.iter().enumerate().map(|(i, &id)| (id, i)).collect();
+    let mut incoming: Vec<Vec<(usize, f64)>> = vec![Vec::new(); n];
+    let dislikes: HashSet<i64> = dislikes.iter().copied().collect();
+    for (source, &node) in all_nodes.iter().enumerate() {
+        let neighbors = graph.neighbors(node);
+        let out_degree = neighbors.len().max(1) as f64;
+        let damping = if dislikes.contains(&node) { config.dislike_damping } else { 1.0 };
+        for (neighbor, weight, _) in neighbors {
+            incoming[position[&neighbor]].push((source, damping * weight / out_degree));
+        }
+    }
+    let teleport: Vec<f64> = all_nodes
+        .iter()
+        .map(|node| personalization.get(node).copied().unwrap_or(initial_score))
+        .collect();
 
-    // Power iteration
+    // Power iteration from a uniform distribution
+    let mut scores = vec![initial_score; n];
     for _iter in 0..config.iterations {
-        let mut new_scores: HashMap<i64, f64> = HashMap::new();
         let mut max_diff: f64 = 0.0;
+        let new_scores: Vec<f64> = (0..n)
+            .map(|node| {
+                let score: f64 = incoming[node].iter().map(|&(source, share)| scores[source] * share).sum();
 
-        for &node in &all_nodes {
-            let mut score = 0.0;
-
-            // Sum contributions from incoming edges
-            for (&other_node, &other_score) in &scores {
-                for (neighbor, weight, _) in graph.neighbors(other_node) {
-                    if neighbor == node {
-                        let out_degree = graph.neighbors(other_node).len().max(1) as f64;
-                        score += (other_score * weight) / out_degree;
-                    }
+                // Apply damping and personalization
+                let score = config.damping * score + (1.0 - config.damping) * teleport[node];
+                max_diff = max_diff.max((score - scores[node]).abs());
+                score
+            })
+            .collect();
+
+        scores = new_scores;
+
+        // Check convergence
+        if max_diff < config.epsilon {
+            break;
+        }
+    }
+
+    all_nodes.into_iter().zip(scores).collect()
+}
+
+/// Rank unread books that fit in a reading-time budget by predicted interest
+///
+/// Interest is the user's personalized PageRank score, normalized to `[0, 1]`.
+/// Books without a word count are excluded. Ties go to the shorter book.
+pub fn books_under_time(
+    db: &Database,
+    graph: &BookGraph,
+    max_minutes: f64,
+    words_per_minute: f64,
+    limit: usize,
+) -> AppResult<Vec<(Book, f64)>> {
+    let max_words = (max_minutes * words_per_minute).floor() as i64;
+    let candidates = db.get_unread_books_under_words(max_words)?;
+
+    if candidates.is_empty() {
+        return Ok(Vec::new());
+    }
+
+    let now = chrono::Utc::now().timestamp();
+    let liked_rating = db.get_settings()?.liked_rating_threshold;
+    let preferences = build_preference_vector(&db.get_preference_signals()?, now, liked_rating);
+    let scores = if preferences.is_empty() {
+        HashMap::new()
+    } else {
+        weighted_personalized_pagerank(graph, &[], &preferences, &[], &PageRankConfig::default())
+    };
+    let max_score = scores.values().copied().fold(0.0, f64::max);
+
+    let mut ranked: Vec<(Book, f64)> = candidates
+        .into_iter()
+        .map(|book| {
+            let score = if max_score > 0.0 {
+                scores.get(&book.id).copied().unwrap_or(0.0) / max_score
+            } else {
+                0.0
+            };
+            (book, score)
+        })
+        .collect();
+
+    ranked.sort_by(|a, b| {
+        b.1.partial_cmp(&a.1)
+            .unwrap_or(std::cmp::Ordering::Equal)
+            .then(a.0.word_count.cmp(&b.0.word_count))
+            .then(a.0.id.cmp(&b.0.id))
+    });
+    ranked.truncate(limit);
+
+    Ok(ranked)
+}
+
+/// Configuration for "surprise me" recommendations
+#[derive(Debug, Clone)]
+pub struct SerendipityConfig {
+    /// Lowest similarity to the taste profile worth suggesting
+    pub min_similarity: f64,
+    /// Highest similarity; anything closer is an obvious pick
+    pub max_similarity: f64,
+    /// MMR lambda (low favours variety over band fit)
+    pub lambda: f64,
+}
+
+impl Default for SerendipityConfig {
+    fn default() -> Self {
+        Self {
+            min_similarity: 0.4,
+            max_similarity: 0.7,
+            lambda: 0.3,
+        }
+    }
+}
+
+/// Recommend books that are related to the user's taste but not obvious
+///
+/// The taste profile is the average embedding of liked
+/// (see `liked_rating_threshold`) or finished books. Candidates must fall
+/// within the configured similarity band; those nearest the middle of the
+/// band score highest, and MMR spreads the picks out. Books already read,
+/// being read or abandoned are excluded. Returns each book with its
+/// similarity to the profile.
+pub fn serendipitous_recommendations(
+    db: &Database,
+    vector_store: &VectorStore,
+    config: &SerendipityConfig,
+    limit: usize,
+) -> AppResult<Vec<(Book, f64)>> {
+    let liked_rating = db.get_settings()?.liked_rating_threshold;
+    let signals = db.get_preference_signals()?;
+    let profile_ids: Vec<i64> = signals
+        .iter()
+        .filter(|s| s.rating.is_some_and(|r| r >= liked_rating) || s.read_status.as_deref() == Some("finished"))
+        .map(|s| s.book_id)
+        .collect();
+    let Some(profile) = vector_store.compute_average_embedding(&profile_ids) else {
+        return Ok(Vec::new());
+    };
+
+    let exclude: Vec<i64> = signals
+        .iter()
+        .filter(|s| matches!(s.read_status.as_deref(), Some("finished" | "reading" | "abandoned")))
+        .map(|s| s.book_id)
+        .chain(profile_ids.iter().copied())
+        .collect();
+
+    let center = (config.min_similarity + config.max_similarity) / 2.0;
+    let half_width = ((config.max_similarity - config.min_similarity) / 2.0).max(f64::EPSILON);
+    let in_band: Vec<(i64, f64)> = vector_store
+        .find_similar(&profile, usize::MAX, &exclude)
+        .into_iter()
+        .filter(|(_, similarity)| (config.min_similarity..=config.max_similarity).contains(similarity))
+        .collect();
+
+    let candidates: Vec<TraversalCandidate> = in_band
+        .iter()
+        .map(|&(book_id, similarity)| TraversalCandidate {
+            book_id,
+            score: 1.0 - (similarity - center).abs() / half_width,
+            path: vec![book_id],
+            edge_types: vec![],
+        })
+        .collect();
+
+    let metric = vector_store.metric();
+    let diverse = maximal_marginal_relevance(
+        &candidates,
+        |a, b| match (vector_store.get_embedding(a), vector_store.get_embedding(b)) {
+            (Some(a), Some(b)) => metric.normalize(metric.similarity(&a, &b)),
+            _ => 0.0,
+        },
+        config.lambda,
+        limit,
+    );
+
+    let similarities: HashMap<i64, f64> = in_band.into_iter().collect();
+    Ok(diverse
+        .into_iter()
+        .filter_map(|c| {
+            let book = db.get_book(c.book_id).ok()?;
+            Some((book, similarities[&c.book_id]))
+        })
+        .collect())
+}
+
+/// Configuration for author-network recommendations
+#[derive(Debug, Clone)]
+pub struct AuthorNetworkConfig {
+    /// Minimum content-edge weight linking two authors' books
+    pub min_weight: f64,
+    /// Author hops to walk out from the favorite authors
+    pub max_hops: usize,
+    /// Score multiplier applied per hop after the first
+    pub hop_decay: f64,
+    /// Books suggested per discovered author
+    pub books_per_author: usize,
+}
+
+impl Default for AuthorNetworkConfig {
+    fn default() -> Self {
+        Self {
+            min_weight: 0.5,
+            max_hops: 2,
+            hop_decay: 0.5,
+            books_per_author: 2,
+        }
+    }
+}
+
+/// A book by an author discovered through the author network
+#[derive(Debug, Clone)]
+pub struct AuthorNetworkCandidate {
+    pub book: Book,
+    pub score: f64,
+    pub author: String,
+    /// Favorite author the discovery started from
+    pub via_author: String,
+}
+
+/// Recommend books by authors similar to the user's favorite authors
+///
+/// Content edges are aggregated into an author graph, where two authors are
+/// connected by the summed weight of the edges between their books. Favorite
+/// authors are weighted by the preference signals on their books, and a
+/// short traversal from them scores the other authors. Authors with any
+/// rated or read book are left out, and each discovered author contributes
+/// their most strongly connected books.
+pub fn author_network_recommendations(
+    db: &Database,
+    config: &AuthorNetworkConfig,
+    limit: usize,
+) -> AppResult<Vec<AuthorNetworkCandidate>> {
+    let links = db.get_author_content_links(config.min_weight)?;
+
+    // Edges may be stored in both directions; count each book pair once
+    let mut pairs: HashMap<(i64, &str, i64, &str), f64> = HashMap::new();
+    for link in &links {
+        let forward = (link.source_book, link.source_author.as_str());
+        let backward = (link.target_book, link.target_author.as_str());
+        let (a, b) = if forward <= backward { (forward, backward) } else { (backward, forward) };
+        let weight = pairs.entry((a.0, a.1, b.0, b.1)).or_insert(0.0);
+        *weight = weight.max(link.weight);
+    }
+
+    let mut author_edges: HashMap<&str, HashMap<&str, f64>> = HashMap::new();
+    let mut book_strength: HashMap<(&str, i64), f64> = HashMap::new();
+    let mut book_authors: HashMap<i64, HashSet<&str>> = HashMap::new();
+    for (&(book_a, author_a, book_b, author_b), &weight) in &pairs {
+        *author_edges.entry(author_a).or_default().entry(author_b).or_insert(0.0) += weight;
+        *author_edges.entry(author_b).or_default().entry(author_a).or_insert(0.0) += weight;
+        *book_strength.entry((author_a, book_a)).or_insert(0.0) += weight;
+        *book_strength.entry((author_b, book_b)).or_insert(0.0) += weight;
+        book_authors.entry(book_a).or_default().insert(author_a);
+        book_authors.entry(book_b).or_default().insert(author_b);
+    }
+    let max_edge = author_edges
+        .values()
+        .flat_map(|neighbors| neighbors.values().copied())
+        .fold(0.0, f64::max);
+    if max_edge <= 0.0 {
+        return Ok(Vec::new());
+    }
+
+    let now = chrono::Utc::now().timestamp();
+    let liked_rating = db.get_settings()?.liked_rating_threshold;
+    let signals = db.get_preference_signals()?;
+    let known_books: HashSet<i64> = signals.iter().map(|s| s.book_id).collect();
+    let mut favorites: HashMap<&str, f64> = HashMap::new();
+    let mut known_authors: HashSet<&str> = HashSet::new();
+    for signal in &signals {
+        let Some(authors) = book_authors.get(&signal.book_id) else {
+            continue;
+        };
+        known_authors.extend(authors.iter().copied());
+        let weight = preference_weight(signal, now, liked_rating);
+        if weight > 0.0 {
+            for &author in authors {
+                *favorites.entry(author).or_insert(0.0) += weight;
+            }
+        }
+    }
+
+    // Best score per author, with the favorite it was reached from
+    let mut reached: HashMap<&str, (f64, &str)> = HashMap::new();
+    let mut frontier: Vec<(&str, f64, &str)> = favorites
+        .iter()
+        .map(|(&author, &weight)| (author, weight, author))
+        .collect();
+    let mut decay = 1.0;
+    for _ in 0..config.max_hops {
+        let mut next = Vec::new();
+        for &(author, score, origin) in &frontier {
+            for (&neighbor, &weight) in &author_edges[author] {
+                if favorites.contains_key(neighbor) {
+                    continue;
+                }
+                let candidate = score * (weight / max_edge) * decay;
+                if reached.get(neighbor).map_or(true, |&(best, _)| candidate > best) {
+                    reached.insert(neighbor, (candidate, origin));
+                    next.push((neighbor, candidate, origin));
                 }
             }
+        }
+        frontier = next;
+        decay *= config.hop_decay;
+    }
 
-            // Apply damping and personalization
-            let personalization_score = personalization.get(&node).copied().unwrap_or(initial_score);
-            score = config.damping * score + (1.0 - config.damping) * personalization_score;
+    let mut discovered: Vec<(&str, f64, &str)> = reached
+        .into_iter()
+        .filter(|(author, _)| !known_authors.contains(author))
+        .map(|(author, (score, origin))| (author, score, origin))
+        .collect();
+    discovered.sort_by(|a, b| b.1.partial_cmp(&a.1).unwrap_or(std::cmp::Ordering::Equal).then(a.0.cmp(b.0)));
+
+    let mut results = Vec::new();
+    let mut seen = HashSet::new();
+    for (author, score, origin) in discovered {
+        let mut books: Vec<(i64, f64)> = book_strength
+            .iter()
+            .filter(|&(&(book_author, book_id), _)| book_author == author && !known_books.contains(&book_id))
+            .map(|(&(_, book_id), &strength)| (book_id, strength))
+            .collect();
+        books.sort_by(|a, b| b.1.partial_cmp(&a.1).unwrap_or(std::cmp::Ordering::Equal).then(a.0.cmp(&b.0)));
+
+        for (book_id, _) in books.into_iter().take(config.books_per_author) {
+            if results.len() >= limit {
+                return Ok(results);
+            }
+            if !seen.insert(book_id) {
+                continue;
+            }
+            if let Ok(book) = db.get_book(book_id) {
+                results.push(AuthorNetworkCandidate {
+                    book,
+                    score,
+                    author: author.to_string(),
+                    via_author: origin.to_string(),
+                });
+            }
+        }
+    }
 
-            let old_score = scores.get(&node).copied().unwrap_or(0.0);
-            max_diff = max_diff.max((score - old_score).abs());
+    Ok(results)
+}
 
-            new_scores.insert(node, score);
-        }
+/// A candidate recommended for a set of seed books
+#[derive(Debug, Clone)]
+pub struct SetCandidate {
+    pub book_id: i64,
+    /// Blend of traversal score and embedding similarity
+    pub score: f64,
+    /// Similarity to the seeds' average embedding, if both exist
+    pub similarity: Option<f64>,
+    /// Traversal path from the nearest seed (empty if found only by embedding)
+    pub path: Vec<i64>,
+    pub edge_types: Vec<String>,
+}
 
-        scores = new_scores;
+/// Recommend books that fit a set of seed books taken together
+///
+/// Multi-hop traversal is seeded from every book at once, and the seeds'
+/// average embedding pulls in content matches the graph misses. Traversal
+/// scores are normalized to `[0, 1]` and blended evenly with similarity.
+/// Seed books are never returned.
+pub fn recommendations_for_set(
+    vector_store: &VectorStore,
+    graph: &BookGraph,
+    seeds: &[i64],
+    limit: usize,
+) -> AppResult<Vec<SetCandidate>> {
+    if seeds.is_empty() {
+        return Ok(Vec::new());
+    }
 
-        // Check convergence
-        if max_diff < config.epsilon {
-            break;
+    let traversal = multi_hop_traversal(graph, seeds, &TraversalConfig::default());
+    let max_traversal = traversal.iter().map(|c| c.score).fold(0.0, f64::max);
+
+    let average = vector_store.compute_average_embedding(seeds);
+    let metric = vector_store.metric();
+    let similarity_to_seeds = |book_id: i64| {
+        let embedding = vector_store.get_embedding(book_id)?;
+        Some(metric.normalize(metric.similarity(average.as_ref()?, &embedding)))
+    };
+
+    let mut merged: HashMap<i64, SetCandidate> = HashMap::new();
+    for c in traversal {
+        let traversal_score = if max_traversal > 0.0 { c.score / max_traversal } else { 0.0 };
+        merged.insert(
+            c.book_id,
+            SetCandidate {
+                book_id: c.book_id,
+                score: traversal_score,
+                similarity: None,
+                path: c.path,
+                edge_types: c.edge_types,
+            },
+        );
+    }
+    if let Some(ref average) = average {
+        for (book_id, _) in vector_store.find_similar(average, limit * 3, seeds) {
+            merged.entry(book_id).or_insert_with(|| SetCandidate {
+                book_id,
+                score: 0.0,
+                similarity: None,
+                path: vec![],
+                edge_types: vec![],
+            });
         }
     }
 
-    scores
+    let mut results: Vec<SetCandidate> = merged
+        .into_values()
+        .filter(|c| !seeds.contains(&c.book_id))
+        .map(|mut c| {
+            c.similarity = similarity_to_seeds(c.book_id);
+            c.score = 0.5 * c.score + 0.5 * c.similarity.unwrap_or(0.0).max(0.0);
+            c
+        })
+        .collect();
+
+    results.sort_by(|a, b| {
+        b.score
+            .partial_cmp(&a.score)
+            .unwrap_or(std::cmp::Ordering::Equal)
+            .then(a.book_id.cmp(&b.book_id))
+    });
+    results.truncate(limit);
+
+    Ok(results)
 }
 
 /// Maximal Marginal Relevance for diversity
@@ -356,6 +1078,20 @@ pub fn maximal_marginal_relevance(
     similarity_fn: impl Fn(i64, i64) -> f64,
     lambda: f64,
     top_k: usize,
+) -> Vec<TraversalCandidate> {
+    maximal_marginal_relevance_until(candidates, similarity_fn, lambda, top_k, None)
+}
+
+/// [`maximal_marginal_relevance`] that gives up on diversity at `deadline`
+///
+/// Picks made before the deadline are kept; the remaining slots are filled
+/// by relevance alone, so a slow `similarity_fn` can't hold up the result.
+pub fn maximal_marginal_relevance_until(
+    candidates: &[TraversalCandidate],
+    similarity_fn: impl Fn(i64, i64) -> f64,
+    lambda: f64,
+    top_k: usize,
+    deadline: Option<Instant>,
 ) -> Vec<TraversalCandidate> {
     if candidates.is_empty() {
         return vec![];
@@ -364,11 +1100,15 @@ pub fn maximal_marginal_relevance(
     let mut selected: Vec<TraversalCandidate> = Vec::new();
     let mut remaining: Vec<_> = candidates.to_vec();
 
-    while selected.len() < top_k && !remaining.is_empty() {
+    'select: while selected.len() < top_k && !remaining.is_empty() {
         let mut best_idx = 0;
         let mut best_mmr = f64::NEG_INFINITY;
 
         for (idx, candidate) in remaining.iter().enumerate() {
+            if past(deadline) {
+                break 'select;
+            }
+
             // Relevance score (from traversal)
             let relevance = candidate.score;
 
@@ -385,7 +1125,8 @@ pub fn maximal_marginal_relevance(
             // MMR score
             let mmr = lambda * relevance - (1.0 - lambda) * max_sim;
 
-            if mmr > best_mmr {
+            // Equal scores go to the lower book id
+            if mmr > best_mmr || (mmr == best_mmr && candidate.book_id < remaining[best_idx].book_id) {
                 best_mmr = mmr;
                 best_idx = idx;
             }
@@ -394,6 +1135,18 @@ pub fn maximal_marginal_relevance(
         selected.push(remaining.remove(best_idx));
     }
 
+    // Out of time: fill the rest by relevance alone
+    if selected.len() < top_k {
+        remaining.sort_by(|a, b| {
+            b.score
+                .partial_cmp(&a.score)
+                .unwrap_or(std::cmp::Ordering::Equal)
+                .then(a.book_id.cmp(&b.book_id))
+        });
+        remaining.truncate(top_k - selected.len());
+        selected.extend(remaining);
+    }
+
     selected
 }
 
@@ -409,15 +1162,33 @@ pub struct RecommendationScore {
 }
 
 /// Generate recommendations using the full hybrid pipeline
+///
+/// With a `deadline`, traversal stops expanding and MMR stops diversifying
+/// once it passes, and the best result computed so far is returned.
 pub fn generate_recommendations(
     graph: &BookGraph,
     source_book_id: i64,
     user_highly_rated: &[i64],
+    strategy: &CandidateStrategy,
     limit: usize,
+    deadline: Option<Instant>,
 ) -> Vec<RecommendationScore> {
-    // Stage 1: Multi-hop traversal from source
-    let traversal_config = TraversalConfig::default();
-    let candidates = multi_hop_traversal(graph, &[source_book_id], &traversal_config);
+    // Stage 1: Candidate expansion from source
+    let candidates = match *strategy {
+        CandidateStrategy::Traversal => {
+            let config = TraversalConfig { deadline, ..TraversalConfig::default() };
+            multi_hop_traversal(graph, &[source_book_id], &config)
+        }
+        CandidateStrategy::RandomWalk { walks, walk_length, seed } => {
+            // Visit frequencies are small; rescale so the best candidate is 1
+            let mut walked = random_walk_recommendations(graph, &[source_book_id], walks, walk_length, seed);
+            let max = walked.first().map(|c| c.score).unwrap_or(0.0);
+            if max > 0.0 {
+                walked.iter_mut().for_each(|c| c.score /= max);
+            }
+            walked
+        }
+    };
 
     if candidates.is_empty() {
         return vec![];
@@ -429,6 +1200,7 @@ pub fn generate_recommendations(
         graph,
         &[source_book_id],
         user_highly_rated,
+        &[],
         &pagerank_config,
     );
 
@@ -461,7 +1233,7 @@ pub fn generate_recommendations(
         })
         .collect();
 
-    let diverse = maximal_marginal_relevance(
+    let diverse = maximal_marginal_relevance_until(
         &scored_candidates,
         |a, b| {
             // Simple similarity: inverse of score difference
@@ -471,6 +1243,7 @@ pub fn generate_recommendations(
         },
         0.7, // Lambda: 70% relevance, 30% diversity
         limit,
+        deadline,
     );
 
     // Return final recommendations
@@ -485,14 +1258,284 @@ pub fn generate_recommendations(
         .collect()
 }
 
+/// Graph edge ready for insertion: (source_id, target_id, edge_type, weight)
+pub type Edge = (i64, i64, String, f64);
+
+/// Tag names per book, see `Database::get_tag_sets`
+pub type TagSets = HashMap<i64, HashSet<String>>;
+
+/// Default embedding similarity a content edge must exceed
+pub const DEFAULT_EDGE_FLOOR: f64 = 0.3;
+
+/// Configuration for building a book's edges
+///
+/// `candidate_floor` decides which neighbors are examined at all, while
+/// `edge_floor` decides which of them get a content edge. Lowering only the
+/// candidate floor finds more author/series links among weak content
+/// neighbors without adding weak content edges.
+#[derive(Debug, Clone)]
+pub struct EdgeBuildConfig {
+    /// Number of nearest embedding neighbors to consider
+    pub neighbors: usize,
+    /// Minimum embedding similarity for a neighbor to be considered
+    pub candidate_floor: f64,
+    /// Minimum embedding similarity for a content edge
+    pub edge_floor: f64,
+    /// Minimum weight for an edge to be stored
+    pub min_weight: f64,
+    /// Most edges kept per book, strongest first; 0 keeps them all
+    pub max_degree: usize,
+}
+
+/// Default cap on the edges built for one book, so generic books don't
+/// become hubs that dominate traversal
+pub const DEFAULT_MAX_DEGREE: usize = 30;
+
+impl Default for EdgeBuildConfig {
+    fn default() -> Self {
+        Self {
+            neighbors: 50,
+            candidate_floor: 0.3,
+            edge_floor: DEFAULT_EDGE_FLOOR,
+            min_weight: 0.3,
+            max_degree: DEFAULT_MAX_DEGREE,
+        }
+    }
+}
+
+impl EdgeBuildConfig {
+    /// Defaults with the floors from `settings`
+    pub fn from_settings(settings: &crate::db::Settings) -> Self {
+        Self {
+            candidate_floor: settings.edge_candidate_floor,
+            edge_floor: settings.content_edge_floor,
+            ..Self::default()
+        }
+    }
+
+    /// Config from the stored settings, falling back to the defaults
+    pub fn load(db: &Database) -> Self {
+        db.get_settings()
+            .map(|settings| Self::from_settings(&settings))
+            .unwrap_or_default()
+    }
+}
+
+/// Build a book's outgoing edges from its nearest embedding neighbors
+///
+/// Every qualifying relationship (content, author, series, ...) becomes its own
+/// edge. This is the single place edges are computed, shared by the background
+/// worker, batch embedding and full graph rebuilds.
+///
+/// `tags` holds the tag sets of every book, for callers building many books'
+/// edges; with `None` just this book's and its neighbors' tags are loaded.
+pub fn build_edges_for_book(
+    db: &Database,
+    vector_store: &VectorStore,
+    book_id: i64,
+    config: &EdgeBuildConfig,
+    tags: Option<&TagSets>,
+) -> AppResult<Vec<Edge>> {
+    let similar = vector_store.find_similar_to_book(book_id, config.neighbors);
+
+    if similar.is_empty() {
+        return Ok(Vec::new());
+    }
+
+    // Get book metadata for edge weight computation
+    let source_book = db.get_book(book_id)?;
+    let loaded;
+    let tags = match tags {
+        Some(tags) => tags,
+        None => {
+            let mut ids: Vec<i64> = similar.iter().map(|&(id, _)| id).collect();
+            ids.push(book_id);
+            loaded = db.get_tag_sets(Some(&ids))?;
+            &loaded
+        }
+    };
+
+    let mut edges = Vec::new();
+
+    for (target_id, embedding_sim) in similar {
+        if embedding_sim < config.candidate_floor {
+            continue; // Skip low similarity
+        }
+
+        let Ok(target_book) = db.get_book(target_id) else {
+            continue;
+        };
+
+        for (weight, edge_type) in
+            compute_all_edge_weights(&source_book, &target_book, Some(embedding_sim), config.edge_floor, tags)
+        {
+            if weight >= config.min_weight {
+                edges.push((book_id, target_id, edge_type, weight));
+            }
+        }
+    }
+
+    if config.max_degree > 0 && edges.len() > config.max_degree {
+        edges.sort_by(|a, b| b.3.total_cmp(&a.3));
+        edges.truncate(config.max_degree);
+    }
+
+    Ok(edges)
+}
+
+/// Replace all edges touching a book with freshly computed ones
+///
+/// Edge weights are symmetric, so incoming edges are rebuilt by mirroring the
+/// outgoing ones. Returns the number of edges now stored for the book.
+pub fn recompute_book_edges(db: &Database, vector_store: &VectorStore, book_id: i64) -> AppResult<usize> {
+    let outgoing = build_edges_for_book(db, vector_store, book_id, &EdgeBuildConfig::load(db), None)?;
+
+    let mut edges = outgoing.clone();
+    edges.extend(
+        outgoing
+            .into_iter()
+            .map(|(source, target, edge_type, weight)| (target, source, edge_type, weight)),
+    );
+
+    db.replace_book_edges(book_id, &edges)?;
+    Ok(edges.len())
+}
+
+/// Largest `similar_cache_size` accepted
+pub const MAX_SIMILAR_CACHE_SIZE: u32 = 200;
+
+/// Refresh the precomputed similar books after a book is (re-)embedded
+///
+/// Entries scored against the book's old embedding are always dropped; when
+/// `similar_cache_size` is set, the book's nearest neighbours are then stored
+/// regardless of similarity. Returns the number cached.
+pub fn precompute_similar_books(db: &Database, vector_store: &VectorStore, book_id: i64) -> AppResult<usize> {
+    db.invalidate_similar_cache(book_id)?;
+
+    let size = db.get_settings()?.similar_cache_size.min(MAX_SIMILAR_CACHE_SIZE) as usize;
+    if size == 0 {
+        return Ok(0);
+    }
+    let similar = vector_store.find_similar_to_book(book_id, size);
+    db.replace_similar_cache(book_id, &similar)?;
+    Ok(similar.len())
+}
+
+/// Edge counts per type and weight for a set of edges
+#[derive(Debug, Clone, serde::Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct EdgeDistribution {
+    pub by_type: BTreeMap<String, i64>,
+    /// Counts in ten 0.1-wide weight buckets, lowest first
+    pub weight_histogram: Vec<i64>,
+}
+
+impl Default for EdgeDistribution {
+    fn default() -> Self {
+        Self {
+            by_type: BTreeMap::new(),
+            weight_histogram: vec![0; 10],
+        }
+    }
+}
+
+impl EdgeDistribution {
+    fn add(&mut self, edges: &[Edge]) {
+        for (_, _, edge_type, weight) in edges {
+            *self.by_type.entry(edge_type.clone()).or_default() += 1;
+            let bucket = ((weight * 10.0).floor().max(0.0) as usize).min(9);
+            self.weight_histogram[bucket] += 1;
+        }
+    }
+}
+
+/// Outcome of a full graph rebuild
+#[derive(Debug, Clone)]
+pub struct GraphRebuildSummary {
+    pub books_processed: usize,
+    pub edges: usize,
+    pub distribution: EdgeDistribution,
+}
+
+/// Recompute every edge from the current embeddings using `config`
+///
+/// Books with stale embeddings are skipped. With `dry_run` nothing is
+/// written; otherwise edges are staged and swapped in at the end, so a
+/// failure leaves the previous graph intact. `on_progress(done, total,
+/// edges)` is called after each book.
+pub fn rebuild_graph(
+    db: &Database,
+    vector_store: &VectorStore,
+    config: &EdgeBuildConfig,
+    dry_run: bool,
+    mut on_progress: impl FnMut(usize, usize, usize),
+) -> AppResult<GraphRebuildSummary> {
+    const STAGE_BATCH: usize = 5_000;
+
+    let book_ids = db.get_current_embedding_book_ids(EMBEDDING_TEXT_VERSION)?;
+    let stale_ids: HashSet<i64> = vector_store
+        .stale_embedding_ids(EMBEDDING_TEXT_VERSION)?
+        .into_iter()
+        .collect();
+    tracing::info!(
+        "Found {} books with embeddings ({} stale skipped)",
+        book_ids.len(),
+        stale_ids.len()
+    );
+
+    let mut rebuild = if dry_run { None } else { Some(db.begin_edge_rebuild()?) };
+    let mut distribution = EdgeDistribution::default();
+    let mut pending: Vec<Edge> = Vec::new();
+    let mut total_edges = 0;
+    let tags = db.get_tag_sets(None)?;
+
+    for (idx, &book_id) in book_ids.iter().enumerate() {
+        let mut edges = match build_edges_for_book(db, vector_store, book_id, config, Some(&tags)) {
+            Ok(edges) => edges,
+            Err(e) => {
+                tracing::warn!("Skipping edges for book {}: {}", book_id, e);
+                Vec::new()
+            }
+        };
+        edges.retain(|(_, target_id, _, _)| !stale_ids.contains(target_id));
+
+        distribution.add(&edges);
+        total_edges += edges.len();
+        pending.extend(edges);
+
+        if let Some(ref mut rebuild) = rebuild {
+            if pending.len() >= STAGE_BATCH {
+                rebuild.stage(&pending)?;
+                pending.clear();
+            }
+        } else {
+            pending.clear();
+        }
+
+        on_progress(idx + 1, book_ids.len(), total_edges);
+    }
+
+    if let Some(mut rebuild) = rebuild {
+        rebuild.stage(&pending)?;
+        rebuild.commit()?;
+    }
+
+    Ok(GraphRebuildSummary {
+        books_processed: book_ids.len(),
+        edges: total_edges,
+        distribution,
+    })
+}
+
 /// Compute edge weight between two books based on multiple signals
 /// Returns the primary edge (combined score, primary type)
 pub fn compute_edge_weight(
     book_a: &Book,
     book_b: &Book,
     embedding_similarity: Option<f64>,
+    tags: &TagSets,
 ) -> (f64, String) {
-    let edges = compute_all_edge_weights(book_a, book_b, embedding_similarity);
+    let edges = compute_all_edge_weights(book_a, book_b, embedding_similarity, DEFAULT_EDGE_FLOOR, tags);
 
     if edges.is_empty() {
         return (0.0, "none".to_string());
@@ -505,18 +1548,22 @@ pub fn compute_edge_weight(
 }
 
 /// Compute ALL qualifying edge weights between two books
-/// Returns a vector of (weight, edge_type) for each qualifying relationship
+/// Returns a vector of (weight, edge_type) for each qualifying relationship;
+/// a content edge needs an embedding similarity above `edge_floor`, and a
+/// tag edge is weighted by the Jaccard similarity of the books' `tags`
 pub fn compute_all_edge_weights(
     book_a: &Book,
     book_b: &Book,
     embedding_similarity: Option<f64>,
+    edge_floor: f64,
+    tags: &TagSets,
 ) -> Vec<(f64, String)> {
     let mut edges: Vec<(f64, String)> = Vec::new();
 
     // Content similarity from embeddings
     if let Some(sim) = embedding_similarity {
-        if sim > 0.3 {
-            edges.push((sim, "content".to_string()));
+        if sim > edge_floor {
+            edges.push((sim.min(1.0), "content".to_string()));
         }
     }
 
@@ -535,12 +1582,23 @@ pub fn compute_all_edge_weights(
         edges.push((series_sim, "series".to_string()));
     }
 
+    // Shared tags
+    if let (Some(tags_a), Some(tags_b)) = (tags.get(&book_a.id), tags.get(&book_b.id)) {
+        let shared = tags_a.intersection(tags_b).count();
+        if shared > 0 {
+            let jaccard = shared as f64 / (tags_a.len() + tags_b.len() - shared) as f64;
+            edges.push((jaccard, "tag".to_string()));
+        }
+    }
+
     edges
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::db::{test_book, NewBook, DEFAULT_LIKED_RATING};
+
 
     #[test]
     fn test_graph_operations() {
@@ -555,6 +1613,109 @@ mod tests {
         assert_eq!(neighbors.len(), 2);
     }
 
+    #[test]
+    fn test_graph_bytes_round_trip() {
+        let mut graph = BookGraph::new();
+        graph.add_edge(1, 2, 0.8, "content".to_string());
+        graph.add_edge(1, 3, 0.6, "author".to_string());
+        graph.add_edge(2, 3, 0.7, "series".to_string());
+
+        let restored = BookGraph::from_bytes(&graph.to_bytes().unwrap()).unwrap();
+        assert_eq!(restored.node_count(), 3);
+        assert_eq!(restored.edge_count(), 3);
+        for id in 1..=3 {
+            assert_eq!(restored.neighbors(id), graph.neighbors(id));
+        }
+
+        assert!(BookGraph::from_bytes(&[1, 2, 3]).is_err());
+    }
+
+    #[test]
+    fn test_shortest_path_prefers_strong_links() {
+        let mut graph = BookGraph::new();
+        let mut connect = |a: i64, b: i64, weight: f64, edge_type: &str| {
+            graph.add_edge(a, b, weight, edge_type.to_string());
+            graph.add_edge(b, a, weight, edge_type.to_string());
+        };
+        // A weak direct link loses to two strong hops
+        connect(1, 4, 0.3, "content");
+        connect(1, 2, 0.9, "author");
+        connect(2, 4, 0.9, "content");
+        connect(1, 3, 0.5, "series");
+        connect(3, 4, 0.5, "tag");
+        graph.add_edge(5, 1, 0.9, "content".to_string());
+
+        let path = graph.shortest_path(1, 4).unwrap();
+        assert_eq!(
+            path,
+            vec![(1, String::new()), (2, "author".to_string()), (4, "content".to_string())]
+        );
+        assert_eq!(graph.shortest_path(1, 1), Some(vec![(1, String::new())]));
+
+        // Edges are directed, and unknown books have no path
+        assert!(graph.shortest_path(1, 5).is_none());
+        assert!(graph.shortest_path(1, 99).is_none());
+    }
+
+    #[test]
+    fn test_random_walk_favors_strong_connections() {
+        let mut graph = BookGraph::new();
+        let mut connect = |a: i64, b: i64, weight: f64| {
+            graph.add_edge(a, b, weight, "content".to_string());
+            graph.add_edge(b, a, weight, "content".to_string());
+        };
+        connect(1, 2, 0.95);
+        connect(1, 3, 0.1);
+        connect(2, 4, 0.9);
+        connect(3, 5, 0.9);
+
+        let first = random_walk_recommendations(&graph, &[1], 500, 3, 42);
+        let second = random_walk_recommendations(&graph, &[1], 500, 3, 42);
+        let ids = |c: &[TraversalCandidate]| c.iter().map(|c| c.book_id).collect::<Vec<_>>();
+        assert_eq!(ids(&first), ids(&second));
+
+        let score = |id: i64| first.iter().find(|c| c.book_id == id).map(|c| c.score).unwrap_or(0.0);
+        assert!(score(2) > score(3) * 3.0);
+        assert!(score(4) > score(5));
+        assert!(first.iter().all(|c| c.book_id != 1));
+        assert!((first.iter().map(|c| c.score).sum::<f64>() - 1.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_pagerank_converges_quickly_on_large_graph() {
+        let mut rng = SplitMix64(7);
+        let mut graph = BookGraph::new();
+        for source in 0..1000i64 {
+            for _ in 0..10 {
+                let target = (rng.next_u64() % 1000) as i64;
+                graph.add_edge(source, target, 0.1 + 0.9 * rng.next_f64(), "content".to_string());
+            }
+        }
+        let config = PageRankConfig {
+            iterations: 100,
+            ..Default::default()
+        };
+
+        let started = Instant::now();
+        let scores = personalized_pagerank(&graph, &[1, 2], &[3], &[], &config);
+        assert!(started.elapsed() < std::time::Duration::from_millis(500), "took {:?}", started.elapsed());
+        assert_eq!(scores.len(), 1000);
+
+        // Converged: further iterations barely move any score
+        let longer = personalized_pagerank(
+            &graph,
+            &[1, 2],
+            &[3],
+            &[],
+            &PageRankConfig {
+                iterations: 200,
+                epsilon: 0.0,
+                ..config
+            },
+        );
+        assert!(scores.iter().all(|(id, score)| (score - longer[id]).abs() < 1e-5));
+    }
+
     #[test]
     fn test_multi_hop_traversal() {
         let mut graph = BookGraph::new();
@@ -570,6 +1731,442 @@ mod tests {
         assert!(candidates.iter().any(|c| c.book_id == 3));
     }
 
+    fn signal(book_id: i64, rating: Option<f64>, status: Option<&str>, updated_at: i64) -> PreferenceSignal {
+        PreferenceSignal {
+            book_id,
+            rating,
+            read_status: status.map(String::from),
+            updated_at,
+        }
+    }
+
+    #[test]
+    fn test_preference_weights() {
+        let now = 1_700_000_000;
+        let five_star = preference_weight(&signal(1, Some(5.0), None, now), now, DEFAULT_LIKED_RATING);
+        let finished = preference_weight(&signal(2, None, Some("finished"), now), now, DEFAULT_LIKED_RATING);
+        let reading = preference_weight(&signal(3, None, Some("reading"), now), now, DEFAULT_LIKED_RATING);
+        let abandoned = preference_weight(&signal(4, Some(5.0), Some("abandoned"), now), now, DEFAULT_LIKED_RATING);
+        let disliked = preference_weight(&signal(5, Some(2.0), Some("finished"), now), now, DEFAULT_LIKED_RATING);
+
+        assert!(five_star > finished && finished > reading && reading > 0.0);
+        assert!(abandoned < 0.0);
+        assert_eq!(disliked, 0.0);
+
+        // A lower threshold lets half-star ratings below 4 count as liked
+        let three_and_a_half = signal(6, Some(3.5), None, now);
+        assert_eq!(preference_weight(&three_and_a_half, now, DEFAULT_LIKED_RATING), 0.0);
+        assert!(preference_weight(&three_and_a_half, now, 3.5) > 0.0);
+
+        // Older signals count less than recent ones
+        let old = preference_weight(&signal(1, Some(5.0), None, now - 365 * 86_400), now, DEFAULT_LIKED_RATING);
+        assert!(old < five_star && old > 0.0);
+    }
+
+    #[test]
+    fn test_finished_books_drive_personalization() {
+
+        let dir = tempfile::TempDir::new().unwrap();
+        let db = Database::new(&dir.path().join("test.db")).unwrap();
+
+        let ids: Vec<i64> = ["a", "b", "c", "d"]
+            .iter()
+            .map(|t| db.insert_book(&test_book(t, None)).unwrap())
+            .collect();
+
+        db.insert_edges_batch(&[
+            (ids[0], ids[1], "content".to_string(), 0.9),
+            (ids[2], ids[3], "content".to_string(), 0.9),
+        ])
+        .unwrap();
+
+        // Only an unrated, finished book - no explicit ratings at all
+        db.set_read_status(ids[0], "finished").unwrap();
+
+        let now = chrono::Utc::now().timestamp();
+        let preferences = build_preference_vector(&db.get_preference_signals().unwrap(), now, DEFAULT_LIKED_RATING);
+        assert_eq!(preferences.len(), 1);
+        assert!(preferences[0].1 > 0.0);
+
+        let graph = BookGraph::from_database(&db, GRAPH_MIN_WEIGHT).unwrap();
+        let scores =
+            weighted_personalized_pagerank(&graph, &[], &preferences, &[], &PageRankConfig::default());
+        assert!(scores[&ids[1]] > scores[&ids[3]]);
+
+        // A disliked book drags down what it leads to
+        let disliked = weighted_personalized_pagerank(&graph, &[], &preferences, &[ids[2]], &PageRankConfig::default());
+        assert_eq!(disliked[&ids[0]], scores[&ids[0]]);
+        assert!(disliked[&ids[2]] < scores[&ids[2]]);
+        assert!(disliked[&ids[3]] < scores[&ids[3]]);
+    }
+
+    #[test]
+    fn test_author_network_surfaces_unread_neighbor_author() {
+        let db = Database::new_in_memory().unwrap();
+        db.with_conn(|conn| {
+            conn.execute_batch(
+                "INSERT INTO books (id, path, title, author) VALUES
+                    (1, '/b/1.epub', 'Favorite', 'Loved Author'),
+                    (2, '/b/2.epub', 'Kindred', 'New Author'),
+                    (3, '/b/3.epub', 'Kindred Too', 'New Author'),
+                    (4, '/b/4.epub', 'Two Steps Out', 'Distant Author'),
+                    (5, '/b/5.epub', 'Already Read', 'Read Author'),
+                    (6, '/b/6.epub', 'Unrelated', 'Stranger');
+                 INSERT INTO book_edges (source_id, target_id, edge_type, weight) VALUES
+                    (1, 2, 'content', 0.9),
+                    (2, 1, 'content', 0.9),
+                    (1, 5, 'content', 0.9),
+                    (2, 3, 'content', 0.9),
+                    (3, 4, 'content', 0.6),
+                    (1, 6, 'author', 0.9);
+                 INSERT INTO ratings (book_id, rating) VALUES (1, 5);
+                 INSERT INTO ratings (book_id, read_status) VALUES (5, 'finished');"
+            )?;
+            Ok(())
+        })
+        .unwrap();
+
+        let results =
+            author_network_recommendations(&db, &AuthorNetworkConfig::default(), 10).unwrap();
+        let ids: Vec<i64> = results.iter().map(|c| c.book.id).collect();
+        assert_eq!(ids, vec![2, 3, 4]);
+        assert_eq!(results[0].author, "New Author");
+        assert_eq!(results[0].via_author, "Loved Author");
+        assert_eq!(results[2].author, "Distant Author");
+        assert!(results[0].score > results[2].score);
+    }
+
+    #[test]
+    fn test_recent_reads_window_limits_seeds() {
+        let db = Database::new_in_memory().unwrap();
+        let now = chrono::Utc::now().timestamp();
+        db.with_conn(|conn| {
+            conn.execute_batch(&format!(
+                "INSERT INTO books (id, path, title) VALUES (1, '/b/old.epub', 'Old'), (2, '/b/new.epub', 'New');
+                 INSERT INTO ratings (book_id, rating, date_rated) VALUES (1, 5, {}), (2, 4, {});",
+                now - 400 * 86_400,
+                now - 86_400,
+            ))?;
+            Ok(())
+        })
+        .unwrap();
+
+        let seed_ids = |days: u32| -> Vec<i64> {
+            db.get_preference_signals_since(recent_reads_cutoff(days, now))
+                .unwrap()
+                .iter()
+                .map(|s| s.book_id)
+                .collect()
+        };
+
+        assert_eq!(seed_ids(30), vec![2]);
+        assert_eq!(seed_ids(0), vec![2, 1]);
+    }
+
+    #[test]
+    fn test_build_edges_for_book() {
+        use crate::vector::EMBEDDING_DIM;
+
+        let dir = tempfile::TempDir::new().unwrap();
+        let db_path = dir.path().join("test.db");
+        let db = Database::new(&db_path).unwrap();
+        let vector_store = VectorStore::new(db_path.to_str().unwrap()).unwrap();
+
+        let source = db.insert_book(&test_book("source", Some("Austen"))).unwrap();
+        let near = db.insert_book(&test_book("near", Some("Austen"))).unwrap();
+        let far = db.insert_book(&test_book("far", Some("Bronte"))).unwrap();
+        let unrelated = db.insert_book(&test_book("unrelated", Some("Dickens"))).unwrap();
+
+        let embedding = |x: f32, y: f32| {
+            let mut e = vec![0.0f32; EMBEDDING_DIM];
+            e[0] = x;
+            e[1] = y;
+            e
+        };
+        vector_store.store_embedding(source, &embedding(1.0, 0.0), "test", None, 1).unwrap();
+        vector_store.store_embedding(near, &embedding(1.0, 0.1), "test", None, 1).unwrap();
+        vector_store.store_embedding(far, &embedding(1.0, 1.0), "test", None, 1).unwrap();
+        vector_store.store_embedding(unrelated, &embedding(0.0, 1.0), "test", None, 1).unwrap();
+        let tags = |names: &[&str]| names.iter().map(|t| t.to_string()).collect::<Vec<_>>();
+        db.set_book_tags(source, &tags(&["Regency", "Romance"])).unwrap();
+        db.set_book_tags(near, &tags(&["Satire"])).unwrap();
+        db.set_book_tags(far, &tags(&["Gothic", "Romance"])).unwrap();
+        db.set_book_tags(unrelated, &tags(&["Romance"])).unwrap();
+
+        let mut edges = build_edges_for_book(&db, &vector_store, source, &EdgeBuildConfig::default(), None).unwrap();
+        edges.sort_by(|a, b| (a.1, &a.2).cmp(&(b.1, &b.2)));
+        let summary: Vec<(i64, &str)> = edges.iter().map(|e| (e.1, e.2.as_str())).collect();
+
+        // One edge per qualifying relationship; the orthogonal book is dropped
+        // even though it shares a tag
+        assert_eq!(summary, vec![(near, "author"), (near, "content"), (far, "content"), (far, "tag")]);
+        assert!(edges.iter().all(|e| e.0 == source && e.3 >= 0.3 && e.3 <= 1.0));
+        assert!((edges[3].3 - 1.0 / 3.0).abs() < 1e-9);
+        assert_eq!(db.get_shared_tags(source, far).unwrap(), vec!["Romance"]);
+
+        // Preloaded tag sets give the same edges
+        let all_tags = db.get_tag_sets(None).unwrap();
+        assert_eq!(all_tags.len(), 4);
+        let mut preloaded =
+            build_edges_for_book(&db, &vector_store, source, &EdgeBuildConfig::default(), Some(&all_tags)).unwrap();
+        preloaded.sort_by(|a, b| (a.1, &a.2).cmp(&(b.1, &b.2)));
+        assert_eq!(preloaded, edges);
+
+        // Neighbor count and weight threshold come from the config
+        let config = EdgeBuildConfig { neighbors: 1, min_weight: 0.9, ..EdgeBuildConfig::default() };
+        let edges = build_edges_for_book(&db, &vector_store, source, &config, None).unwrap();
+        assert_eq!(edges.len(), 1);
+        assert_eq!(edges[0].1, near);
+        // The degree cap keeps only the strongest edges
+        let config = EdgeBuildConfig { max_degree: 2, ..EdgeBuildConfig::default() };
+        let edges = build_edges_for_book(&db, &vector_store, source, &config, None).unwrap();
+        let summary: Vec<(i64, &str)> = edges.iter().map(|e| (e.1, e.2.as_str())).collect();
+        assert_eq!(summary, vec![(near, "content"), (near, "author")]);
+    }
+
+    #[test]
+    fn test_candidate_floor_finds_weak_author_neighbors() {
+        use crate::vector::EMBEDDING_DIM;
+
+        let dir = tempfile::TempDir::new().unwrap();
+        let db_path = dir.path().join("test.db");
+        let db = Database::new(&db_path).unwrap();
+        let vector_store = VectorStore::new(db_path.to_str().unwrap()).unwrap();
+
+        let source = db.insert_book(&test_book("source", Some("Austen"))).unwrap();
+        let weak = db.insert_book(&test_book("weak", Some("Austen"))).unwrap();
+
+        // Cosine similarity 0.2: below the default floors
+        let mut a = vec![0.0f32; EMBEDDING_DIM];
+        a[0] = 1.0;
+        let mut b = vec![0.0f32; EMBEDDING_DIM];
+        b[0] = 0.2;
+        b[1] = (1.0f32 - 0.04).sqrt();
+        vector_store.store_embedding(source, &a, "test", None, 1).unwrap();
+        vector_store.store_embedding(weak, &b, "test", None, 1).unwrap();
+
+        let edges = build_edges_for_book(&db, &vector_store, source, &EdgeBuildConfig::default(), None).unwrap();
+        assert!(edges.is_empty());
+
+        // A lower candidate floor finds the author link but adds no content edge
+        let config = EdgeBuildConfig { candidate_floor: 0.1, ..EdgeBuildConfig::default() };
+        let edges = build_edges_for_book(&db, &vector_store, source, &config, None).unwrap();
+        let summary: Vec<(i64, &str)> = edges.iter().map(|e| (e.1, e.2.as_str())).collect();
+        assert_eq!(summary, vec![(weak, "author")]);
+
+        // The floors are read from settings
+        db.update_setting("edge_candidate_floor", "0.1").unwrap();
+        assert_eq!(EdgeBuildConfig::load(&db).candidate_floor, 0.1);
+        assert_eq!(EdgeBuildConfig::load(&db).edge_floor, DEFAULT_EDGE_FLOOR);
+    }
+
+    #[test]
+    fn test_recompute_edges_after_metadata_edit() {
+        use crate::db::BookUpdate;
+        use crate::vector::EMBEDDING_DIM;
+
+        let dir = tempfile::TempDir::new().unwrap();
+        let db_path = dir.path().join("test.db");
+        let db = Database::new(&db_path).unwrap();
+        let vector_store = VectorStore::new(db_path.to_str().unwrap()).unwrap();
+
+        let a = db.insert_book(&test_book("a", Some("Le Guin"))).unwrap();
+        let b = db.insert_book(&test_book("b", Some("Herbert"))).unwrap();
+
+        let mut embedding = vec![0.0f32; EMBEDDING_DIM];
+        embedding[0] = 1.0;
+        vector_store.store_embedding(a, &embedding, "test", None, 1).unwrap();
+        embedding[1] = 0.5;
+        vector_store.store_embedding(b, &embedding, "test", None, 1).unwrap();
+
+        let edge_types = |db: &Database| {
+            let mut types: Vec<String> = db
+                .get_edges(a, 0.0, None)
+                .unwrap()
+                .into_iter()
+                .filter(|e| e.source_id == a)
+                .map(|e| e.edge_type)
+                .collect();
+            types.sort();
+            types
+        };
+
+        assert_eq!(recompute_book_edges(&db, &vector_store, a).unwrap(), 2);
+        assert_eq!(edge_types(&db), vec!["content"]);
+
+        // Same author after the edit adds an author edge (in both directions)
+        db.update_book(a, &BookUpdate { author: Some("Herbert".to_string()), ..Default::default() })
+            .unwrap();
+        assert_eq!(recompute_book_edges(&db, &vector_store, a).unwrap(), 4);
+        assert_eq!(edge_types(&db), vec!["author", "content"]);
+        assert!(db.get_edges(b, 0.0, None).unwrap().iter().any(|e| e.source_id == b && e.edge_type == "author"));
+    }
+
+    #[test]
+    fn test_books_under_time() {
+
+        let dir = tempfile::TempDir::new().unwrap();
+        let db = Database::new(&dir.path().join("test.db")).unwrap();
+
+        // At 250 wpm: 20 min, 80 min, 240 min, unknown, 4 min
+        let novella = db.insert_book(&NewBook { word_count: Some(5_000), ..test_book("novella", None) }).unwrap();
+        let short_novel = db.insert_book(&NewBook { word_count: Some(20_000), ..test_book("short", None) }).unwrap();
+        let _epic = db.insert_book(&NewBook { word_count: Some(60_000), ..test_book("epic", None) }).unwrap();
+        let _unknown = db.insert_book(&test_book("unknown", None)).unwrap();
+        let finished = db.insert_book(&NewBook { word_count: Some(1_000), ..test_book("finished", None) }).unwrap();
+
+        // The finished book points at the short novel, so it is the better pick
+        db.set_read_status(finished, "finished").unwrap();
+        db.insert_edges_batch(&[(finished, short_novel, "content".to_string(), 0.9)])
+            .unwrap();
+        let graph = BookGraph::from_database(&db, GRAPH_MIN_WEIGHT).unwrap();
+
+        let results = books_under_time(&db, &graph, 90.0, 250.0, 10).unwrap();
+        let ids: Vec<i64> = results.iter().map(|(book, _)| book.id).collect();
+        assert_eq!(ids, vec![short_novel, novella]);
+        assert!(results[0].1 > results[1].1);
+
+        let results = books_under_time(&db, &graph, 30.0, 250.0, 10).unwrap();
+        assert_eq!(results.len(), 1);
+        assert_eq!(results[0].0.id, novella);
+    }
+
+    #[test]
+    fn test_serendipity_stays_in_band() {
+        use crate::vector::EMBEDDING_DIM;
+
+        let db = Database::new_in_memory().unwrap();
+        db.with_conn(|conn| {
+            for id in 1..=6 {
+                conn.execute(
+                    "INSERT INTO books (id, path, title) VALUES (?, ?, ?)",
+                    rusqlite::params![id, format!("/b/{}.epub", id), format!("Book {}", id)],
+                )?;
+            }
+            Ok(())
+        })
+        .unwrap();
+        let vector_store = VectorStore::new(db.path()).unwrap();
+
+        // Unit vectors at a chosen cosine similarity to the profile axis
+        let at_similarity = |cos: f32| {
+            let mut v = vec![0.0f32; EMBEDDING_DIM];
+            v[0] = cos;
+            v[1] = (1.0 - cos * cos).sqrt();
+            v
+        };
+        vector_store.store_embedding(1, &at_similarity(1.0), "test", None, 1).unwrap();
+        vector_store.store_embedding(2, &at_similarity(0.95), "test", None, 1).unwrap();
+        vector_store.store_embedding(3, &at_similarity(0.6), "test", None, 1).unwrap();
+        vector_store.store_embedding(4, &at_similarity(0.5), "test", None, 1).unwrap();
+        vector_store.store_embedding(5, &at_similarity(0.1), "test", None, 1).unwrap();
+        vector_store.store_embedding(6, &at_similarity(0.55), "test", None, 1).unwrap();
+        db.set_rating(1, 5.0).unwrap();
+        db.set_read_status(6, "abandoned").unwrap();
+
+        let results = serendipitous_recommendations(&db, &vector_store, &SerendipityConfig::default(), 10).unwrap();
+        let mut ids: Vec<i64> = results.iter().map(|(book, _)| book.id).collect();
+        ids.sort();
+
+        // The closest match (2) and the unrelated book (5) are left out, as is
+        // the abandoned book even though it is in the band
+        assert_eq!(ids, vec![3, 4]);
+        assert!(results.iter().all(|(_, sim)| (0.4..=0.7).contains(sim)));
+    }
+
+    #[test]
+    fn test_recommendations_for_set_merges_seed_neighborhoods() {
+        use crate::vector::EMBEDDING_DIM;
+
+        let db = Database::new_in_memory().unwrap();
+        db.with_conn(|conn| {
+            for id in 1..=7 {
+                conn.execute(
+                    "INSERT INTO books (id, path, title) VALUES (?, ?, ?)",
+                    rusqlite::params![id, format!("/b/{}.epub", id), format!("Book {}", id)],
+                )?;
+            }
+            Ok(())
+        })
+        .unwrap();
+        db.insert_edges_batch(&[
+            (1, 3, "content".to_string(), 0.9),
+            (2, 4, "content".to_string(), 0.9),
+            (1, 5, "content".to_string(), 0.8),
+            (2, 5, "content".to_string(), 0.8),
+            (1, 2, "author".to_string(), 0.9),
+        ])
+        .unwrap();
+
+        let vector_store = VectorStore::new(db.path()).unwrap();
+        let axis = |i: usize| {
+            let mut v = vec![0.0f32; EMBEDDING_DIM];
+            v[i] = 1.0;
+            v
+        };
+        vector_store.store_embedding(1, &axis(0), "test", None, 1).unwrap();
+        vector_store.store_embedding(2, &axis(1), "test", None, 1).unwrap();
+        // Between both seeds, but not connected in the graph
+        let mut between = axis(0);
+        between[1] = 1.0;
+        vector_store.store_embedding(6, &between, "test", None, 1).unwrap();
+        // Unrelated to either seed
+        vector_store.store_embedding(7, &axis(2), "test", None, 1).unwrap();
+
+        let graph = BookGraph::from_database(&db, GRAPH_MIN_WEIGHT).unwrap();
+        let results = recommendations_for_set(&vector_store, &graph, &[1, 2], 10).unwrap();
+        let ids: HashSet<i64> = results.iter().map(|c| c.book_id).collect();
+
+        // Neighbors of each seed plus the embedding match, never the seeds
+        for id in [3, 4, 5, 6] {
+            assert!(ids.contains(&id), "missing {}", id);
+        }
+        assert!(!ids.contains(&1) && !ids.contains(&2));
+
+        let find = |id: i64| results.iter().find(|c| c.book_id == id);
+        let between = find(6).unwrap();
+        assert!(between.path.is_empty());
+        if let Some(unrelated) = find(7) {
+            assert!(between.score > unrelated.score);
+        }
+        assert!(results.windows(2).all(|w| w[0].score >= w[1].score));
+    }
+
+    #[test]
+    fn test_rebuild_graph_dry_run_writes_nothing() {
+        use crate::vector::EMBEDDING_DIM;
+
+        let db = Database::new_in_memory().unwrap();
+        db.with_conn(|conn| {
+            conn.execute_batch(
+                "INSERT INTO books (id, path, title, author, embedding_status) VALUES
+                    (1, '/b/1.epub', 'One', 'Le Guin', 'complete'),
+                    (2, '/b/2.epub', 'Two', 'Le Guin', 'complete')",
+            )?;
+            Ok(())
+        })
+        .unwrap();
+        let vector_store = VectorStore::new(db.path()).unwrap();
+        let mut embedding = vec![0.0f32; EMBEDDING_DIM];
+        embedding[0] = 1.0;
+        for id in [1, 2] {
+            vector_store
+                .store_embedding(id, &embedding, "test", None, EMBEDDING_TEXT_VERSION)
+                .unwrap();
+        }
+
+        let preview = rebuild_graph(&db, &vector_store, &EdgeBuildConfig::default(), true, |_, _, _| {}).unwrap();
+        assert_eq!(preview.books_processed, 2);
+        assert!(preview.edges > 0);
+        assert_eq!(preview.distribution.by_type.values().sum::<i64>(), preview.edges as i64);
+        assert_eq!(preview.distribution.weight_histogram.iter().sum::<i64>(), preview.edges as i64);
+        assert!(db.get_edges(1, 0.0, None).unwrap().is_empty());
+
+        let built = rebuild_graph(&db, &vector_store, &EdgeBuildConfig::default(), false, |_, _, _| {}).unwrap();
+        assert_eq!(built.edges, preview.edges);
+        assert!(!db.get_edges(1, 0.0, None).unwrap().is_empty());
+    }
+
     #[test]
     fn test_mmr_diversity() {
         let candidates = vec![
@@ -581,4 +2178,76 @@ mod tests {
         let result = maximal_marginal_relevance(&candidates, |_, _| 0.5, 0.7, 2);
         assert_eq!(result.len(), 2);
     }
+
+    #[test]
+    fn test_equal_scores_sort_by_book_id() {
+        let mut graph = BookGraph::new();
+        for target in [5, 3, 4, 2] {
+            graph.add_edge(1, target, 0.8, "content".to_string());
+        }
+
+        let ids: Vec<i64> = multi_hop_traversal(&graph, &[1], &TraversalConfig::default())
+            .iter()
+            .map(|c| c.book_id)
+            .collect();
+        assert_eq!(ids, vec![2, 3, 4, 5]);
+
+        let ids: Vec<i64> = generate_recommendations(&graph, 1, &[], &CandidateStrategy::Traversal, 3, None)
+            .iter()
+            .map(|s| s.book_id)
+            .collect();
+        assert_eq!(ids, vec![2, 3, 4]);
+
+        // MMR breaks ties the same way, whatever the input order
+        let tied: Vec<TraversalCandidate> = [4, 2, 3]
+            .into_iter()
+            .map(|book_id| TraversalCandidate { book_id, score: 0.5, path: vec![], edge_types: vec![] })
+            .collect();
+        let ids: Vec<i64> = maximal_marginal_relevance(&tied, |_, _| 0.0, 0.7, 3)
+            .iter()
+            .map(|c| c.book_id)
+            .collect();
+        assert_eq!(ids, vec![2, 3, 4]);
+    }
+
+    #[test]
+    fn test_slow_similarity_returns_by_deadline() {
+        use std::time::Duration;
+
+        let candidates: Vec<TraversalCandidate> = (1..=50)
+            .map(|book_id| TraversalCandidate {
+                book_id,
+                score: 1.0 - book_id as f64 / 100.0,
+                path: vec![],
+                edge_types: vec![],
+            })
+            .collect();
+        let slow = |_: i64, _: i64| {
+            std::thread::sleep(Duration::from_millis(5));
+            0.5
+        };
+
+        let start = Instant::now();
+        let deadline = start + Duration::from_millis(100);
+        let result = maximal_marginal_relevance_until(&candidates, slow, 0.7, 20, Some(deadline));
+        // Unbounded this takes seconds; one in-flight similarity call may overrun
+        assert!(start.elapsed() < Duration::from_millis(500), "took {:?}", start.elapsed());
+        assert_eq!(result.len(), 20);
+        let mut ids: Vec<i64> = result.iter().map(|c| c.book_id).collect();
+        ids.sort();
+        ids.dedup();
+        assert_eq!(ids.len(), 20);
+        assert_eq!(result[0].book_id, 1);
+
+        // A deadline that has already passed still yields the direct neighbours
+        let mut graph = BookGraph::new();
+        for target in 2..=6 {
+            graph.add_edge(1, target, 0.9 - target as f64 / 100.0, "content".to_string());
+        }
+        let ids: Vec<i64> = generate_recommendations(&graph, 1, &[], &CandidateStrategy::Traversal, 3, Some(start))
+            .iter()
+            .map(|s| s.book_id)
+            .collect();
+        assert_eq!(ids, vec![2, 3, 4]);
+    }
 }
@@ -10,6 +10,7 @@
 pub mod calibre;
 pub mod commands;
 pub mod db;
+pub mod enrich;
 pub mod epub;
 pub mod graph;
 pub mod ollama;
@@ -36,6 +37,9 @@ pub enum AppError {
     
     #[error("Ollama error: {0}")]
     Ollama(String),
+
+    #[error("Ollama unavailable: {0}")]
+    OllamaUnavailable(String),
     
     #[error("Configuration error: {0}")]
     Config(String),
@@ -48,6 +52,15 @@ pub enum AppError {
     
     #[error("Serialization error: {0}")]
     Serialization(#[from] serde_json::Error),
+
+    #[error("Database busy: {0}")]
+    PoolTimeout(String),
+
+    #[error("Enrichment error: {0}")]
+    Enrichment(String),
+
+    #[error("Timed out: {0}")]
+    Timeout(String),
 }
 
 impl serde::Serialize for AppError {
@@ -59,5 +72,31 @@ impl serde::Serialize for AppError {
     }
 }
 
+impl AppError {
+    /// Whether the same operation might succeed later (I/O trouble such as a
+    /// file locked by a sync client, a busy database, or Ollama being down)
+    pub fn is_transient(&self) -> bool {
+        matches!(self, AppError::Io(_) | AppError::PoolTimeout(_) | AppError::OllamaUnavailable(_))
+    }
+
+    /// Stable camelCase name of the variant, for the frontend to switch on
+    pub fn kind(&self) -> &'static str {
+        match self {
+            AppError::Database(_) => "database",
+            AppError::Io(_) => "io",
+            AppError::EpubParse(_) => "epubParse",
+            AppError::Ollama(_) => "ollama",
+            AppError::OllamaUnavailable(_) => "ollamaUnavailable",
+            AppError::Config(_) => "config",
+            AppError::NotFound(_) => "notFound",
+            AppError::InvalidInput(_) => "invalidInput",
+            AppError::Serialization(_) => "serialization",
+            AppError::PoolTimeout(_) => "poolTimeout",
+            AppError::Enrichment(_) => "enrichment",
+            AppError::Timeout(_) => "timeout",
+        }
+    }
+}
+
 /// Result type alias for application operations
 pub type AppResult<T> = Result<T, AppError>;
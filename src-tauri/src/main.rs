@@ -2,7 +2,7 @@
 #![cfg_attr(not(debug_assertions), windows_subsystem = "windows")]
 
 use epub_graph_lib::commands;
-use epub_graph_lib::state::AppState;
+use epub_graph_lib::state::{AppState, SHUTDOWN_TIMEOUT};
 use std::sync::Arc;
 use tauri::Manager;
 use tracing_subscriber::{layer::SubscriberExt, util::SubscriberInitExt};
@@ -34,58 +34,119 @@ fn main() {
             commands::library::add_library,
             commands::library::remove_library,
             commands::library::scan_library,
+            commands::library::sync_calibre_library,
+            commands::library::scan_path,
+            commands::library::add_single_book,
+            commands::library::hash_library,
+            commands::library::compute_missing_hashes,
+            commands::library::get_scan_history,
+            commands::library::get_embedding_coverage,
+            commands::library::get_tag_distribution,
             commands::library::parse_metadata_batch,
+            commands::library::retry_skipped_metadata,
+            commands::library::enrich_metadata,
             commands::library::cleanup_orphaned_books,
             // Book commands
             commands::books::query_books,
             commands::books::get_book,
             commands::books::update_book,
             commands::books::delete_book,
+            commands::books::get_books_without_cover,
+            commands::books::refetch_covers,
+            commands::books::import_covers_by_isbn,
+            commands::books::get_books_modified_since,
+            commands::books::get_deleted_since,
+            commands::books::find_duplicate_editions,
+            commands::books::find_duplicates,
+            commands::books::merge_duplicate_editions,
             commands::books::set_rating,
             commands::books::set_read_status,
+            commands::books::set_ratings_batch,
+            commands::books::set_read_status_batch,
+            commands::books::set_book_notes,
+            commands::books::search_book_notes,
             commands::books::get_cover_image,
+            commands::books::extract_all_covers,
             // Recommendation commands
             commands::recommendations::get_recommendations,
+            commands::recommendations::get_recommendations_for_set,
+            commands::recommendations::get_advanced_recommendations,
+            commands::recommendations::get_similar_books,
             commands::recommendations::get_personalized_recommendations,
             commands::recommendations::get_book_graph,
+            commands::recommendations::get_connection_path,
+            commands::recommendations::get_serendipitous_recommendations,
+            commands::recommendations::get_books_under_time,
+            commands::recommendations::get_isolated_books,
+            commands::recommendations::get_author_network_recommendations,
             // Ollama commands
             commands::ollama::get_ollama_status,
             commands::ollama::configure_ollama,
             commands::ollama::get_processing_status,
+            commands::ollama::restart_worker,
             commands::ollama::pause_processing,
             commands::ollama::resume_processing,
             commands::ollama::prioritize_book,
+            commands::ollama::retry_failed_jobs,
             commands::ollama::process_embeddings_batch,
+            commands::ollama::process_all_pending,
+            commands::ollama::cancel_processing,
+            commands::ollama::get_embedding,
+            // Pipeline commands
+            commands::pipeline::rebuild_all,
             // Settings commands
             commands::settings::get_settings,
+            commands::settings::get_version_info,
             commands::settings::update_settings,
             commands::settings::get_database_path,
             commands::settings::get_database_stats,
+            commands::settings::get_pool_stats,
             commands::settings::reset_database,
             commands::settings::clear_embeddings,
+            commands::settings::clear_embeddings_for_library,
+            commands::settings::repair_embeddings,
+            commands::settings::regenerate_sort_fields,
             commands::settings::get_database_path_preference,
             commands::settings::set_database_path_preference,
+            commands::settings::migrate_database_to,
             commands::settings::rebuild_graph_edges,
+            commands::settings::recompute_book_edges,
+            commands::settings::add_manual_edge,
+            commands::settings::remove_manual_edge,
             // Export commands
             commands::export::export_library,
             commands::export::import_library,
+            commands::export::export_embeddings,
             commands::export::create_backup,
             commands::export::restore_backup,
             // Up Next commands
             commands::upnext::get_up_next_books,
             commands::upnext::add_to_up_next,
             commands::upnext::remove_from_up_next,
+            commands::upnext::move_up_next,
+            commands::upnext::clear_up_next,
             commands::upnext::is_in_up_next,
             commands::upnext::get_up_next_count,
+            commands::upnext::get_currently_reading,
+            commands::upnext::set_progress,
             commands::upnext::get_want_to_read_books,
+            // Collection commands
+            commands::collections::get_collections,
+            commands::collections::get_book_collections,
+            commands::collections::create_collection,
+            commands::collections::rename_collection,
+            commands::collections::delete_collection,
+            commands::collections::add_books_to_collection,
+            commands::collections::remove_books_from_collection,
         ])
         .setup(|app| {
             let state = app.state::<Arc<AppState>>();
             
             // Start background services
             let state_clone = state.inner().clone();
+            let events = Arc::new(app.handle().clone());
             tauri::async_runtime::spawn(async move {
-                if let Err(e) = state_clone.start_background_services().await {
+                if let Err(e) = state_clone.start_background_services(events).await {
                     tracing::error!("Failed to start background services: {}", e);
                 }
             });
@@ -93,6 +154,12 @@ fn main() {
             tracing::info!("EpubGraph initialized successfully");
             Ok(())
         })
-        .run(tauri::generate_context!())
-        .expect("error while running tauri application");
+        .build(tauri::generate_context!())
+        .expect("error while building tauri application")
+        .run(|app, event| {
+            if let tauri::RunEvent::Exit = event {
+                let state = app.state::<Arc<AppState>>().inner().clone();
+                tauri::async_runtime::block_on(state.shutdown(SHUTDOWN_TIMEOUT));
+            }
+        });
 }
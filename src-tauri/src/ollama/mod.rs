@@ -2,9 +2,25 @@
 //!
 //! Integration with local Ollama for embedding generation
 
+mod rate_limit;
+
+pub use rate_limit::{RateLimiter, DEFAULT_REQUESTS_PER_SECOND};
+
 use crate::{AppError, AppResult};
+use futures::future::BoxFuture;
 use serde::{Deserialize, Serialize};
 
+/// Source of embeddings for long-running jobs
+///
+/// Implemented by [`OllamaClient`]; tests can supply a stand-in.
+pub trait EmbeddingBackend: Send + Sync {
+    /// Model name stored alongside each embedding
+    fn model(&self) -> &str;
+
+    /// Embed a single text
+    fn embed<'a>(&'a self, text: &'a str) -> BoxFuture<'a, AppResult<Vec<f32>>>;
+}
+
 /// Ollama API client
 pub struct OllamaClient {
     endpoint: String,
@@ -105,11 +121,21 @@ impl OllamaClient {
             .json(&request)
             .send()
             .await
-            .map_err(|e| AppError::Ollama(format!("Request failed: {}", e)))?;
+            .map_err(|e| {
+                // Server down or not answering, as opposed to rejecting this request
+                if e.is_connect() || e.is_timeout() {
+                    AppError::OllamaUnavailable(format!("Request failed: {}", e))
+                } else {
+                    AppError::Ollama(format!("Request failed: {}", e))
+                }
+            })?;
         
         if !response.status().is_success() {
             let status = response.status();
             let body = response.text().await.unwrap_or_default();
+            if status == reqwest::StatusCode::SERVICE_UNAVAILABLE {
+                return Err(AppError::OllamaUnavailable(format!("Embedding failed ({}): {}", status, body)));
+            }
             return Err(AppError::Ollama(format!("Embedding failed ({}): {}", status, body)));
         }
         
@@ -132,6 +158,16 @@ impl OllamaClient {
     }
 }
 
+impl EmbeddingBackend for OllamaClient {
+    fn model(&self) -> &str {
+        OllamaClient::model(self)
+    }
+
+    fn embed<'a>(&'a self, text: &'a str) -> BoxFuture<'a, AppResult<Vec<f32>>> {
+        Box::pin(OllamaClient::embed(self, text))
+    }
+}
+
 /// Ollama server status
 #[derive(Debug, Clone, Serialize)]
 #[serde(rename_all = "camelCase")]
@@ -154,6 +190,10 @@ pub struct ProcessingStatus {
     pub is_paused: bool,
     pub estimated_time_remaining: Option<i64>,
     pub books_needing_metadata: i64,
+    /// Unix seconds the background worker last checked in
+    pub last_heartbeat: Option<i64>,
+    /// Work remains but the worker has been silent too long (see `restart_worker`)
+    pub stalled: bool,
 }
 
 // API request/response types
@@ -182,58 +222,319 @@ struct ModelInfo {
     size: i64,
 }
 
-/// Generate embedding text from book metadata
-pub fn book_to_embedding_text(
-    title: &str,
-    author: Option<&str>,
-    description: Option<&str>,
-    series: Option<&str>,
-) -> String {
-    let mut parts = vec![format!("Title: {}", title)];
-    
-    if let Some(author) = author {
-        parts.push(format!("Author: {}", author));
+/// Version of the embedding text format produced by `book_to_embedding_text`
+///
+/// Bump this whenever the text layout changes so that embeddings generated from
+/// the old format are treated as stale and regenerated.
+pub const EMBEDDING_TEXT_VERSION: i64 = 2;
+
+/// Book metadata field that can be included in embedding text
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum EmbeddingField {
+    Title,
+    Author,
+    Series,
+    Description,
+    Publisher,
+    Tags,
+    Language,
+}
+
+impl EmbeddingField {
+    fn label(&self) -> &'static str {
+        match self {
+            EmbeddingField::Title => "Title",
+            EmbeddingField::Author => "Author",
+            EmbeddingField::Series => "Series",
+            EmbeddingField::Description => "Description",
+            EmbeddingField::Publisher => "Publisher",
+            EmbeddingField::Tags => "Tags",
+            EmbeddingField::Language => "Language",
+        }
     }
-    
-    if let Some(series) = series {
-        parts.push(format!("Series: {}", series));
+}
+
+/// A field included in embedding text
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct EmbeddingFieldSpec {
+    pub field: EmbeddingField,
+    /// Times the line is repeated, to give the field more influence
+    #[serde(default = "default_field_weight")]
+    pub weight: u8,
+}
+
+fn default_field_weight() -> u8 {
+    1
+}
+
+/// Which metadata fields make up the embedding text, in order
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct EmbeddingTextConfig {
+    pub fields: Vec<EmbeddingFieldSpec>,
+    /// Characters of chapter text used in place of a missing description;
+    /// 0 leaves books without a description unembedded
+    #[serde(default)]
+    pub excerpt_chars: usize,
+}
+
+impl Default for EmbeddingTextConfig {
+    fn default() -> Self {
+        let fields = [
+            EmbeddingField::Title,
+            EmbeddingField::Author,
+            EmbeddingField::Series,
+            EmbeddingField::Tags,
+            EmbeddingField::Description,
+        ];
+        Self {
+            fields: fields
+                .into_iter()
+                .map(|field| EmbeddingFieldSpec { field, weight: 1 })
+                .collect(),
+            excerpt_chars: 0,
+        }
     }
-    
-    if let Some(description) = description {
-        // Truncate description to avoid token limits
-        // Use char_indices to find a valid UTF-8 boundary
-        let desc = if description.len() > 1000 {
-            let truncate_at = description
-                .char_indices()
-                .take_while(|(i, _)| *i < 1000)
-                .last()
-                .map(|(i, c)| i + c.len_utf8())
-                .unwrap_or(0);
-            format!("{}...", &description[..truncate_at])
-        } else {
-            description.to_string()
+}
+
+impl EmbeddingTextConfig {
+    /// Stable string identifying the config, mixed into the text hash
+    pub fn signature(&self) -> String {
+        let mut signature = self
+            .fields
+            .iter()
+            .map(|spec| format!("{}x{}", spec.field.label(), spec.weight))
+            .collect::<Vec<_>>()
+            .join(",");
+        if self.excerpt_chars > 0 {
+            signature.push_str(&format!(",Excerpt{}", self.excerpt_chars));
+        }
+        signature
+    }
+}
+
+/// Book metadata used to build embedding text
+#[derive(Debug, Clone, Default)]
+pub struct EmbeddingInput<'a> {
+    pub title: &'a str,
+    pub author: Option<&'a str>,
+    pub series: Option<&'a str>,
+    pub description: Option<&'a str>,
+    pub publisher: Option<&'a str>,
+    pub language: Option<&'a str>,
+    pub tags: &'a [String],
+    /// Chapter text, used in the description's place when there is none
+    pub excerpt: Option<&'a str>,
+}
+
+impl<'a> EmbeddingInput<'a> {
+    pub fn from_book(book: &'a crate::db::Book, tags: &'a [String]) -> Self {
+        Self {
+            title: &book.title,
+            author: book.author.as_deref(),
+            series: book.series.as_deref(),
+            description: book.description.as_deref(),
+            publisher: book.publisher.as_deref(),
+            language: book.language.as_deref(),
+            tags,
+            excerpt: None,
+        }
+    }
+
+    /// Use chapter text in place of the description, if there is any
+    pub fn with_excerpt(self, excerpt: Option<&'a str>) -> Self {
+        match excerpt {
+            Some(excerpt) => Self { description: None, excerpt: Some(excerpt), ..self },
+            None => self,
+        }
+    }
+}
+
+/// Generate embedding text from book metadata
+pub fn book_to_embedding_text(input: &EmbeddingInput<'_>, config: &EmbeddingTextConfig) -> String {
+    let mut parts = Vec::new();
+
+    for spec in &config.fields {
+        let value = match spec.field {
+            EmbeddingField::Title => Some(input.title.to_string()),
+            EmbeddingField::Author => input.author.map(String::from),
+            EmbeddingField::Series => input.series.map(String::from),
+            EmbeddingField::Publisher => input.publisher.map(String::from),
+            EmbeddingField::Tags => (!input.tags.is_empty()).then(|| input.tags.join(", ")),
+            EmbeddingField::Language => input.language.map(String::from),
+            EmbeddingField::Description => input.description.map(truncate_description),
         };
-        parts.push(format!("Description: {}", desc));
+        let (label, value) = match value {
+            None if spec.field == EmbeddingField::Description => ("Excerpt", input.excerpt.map(String::from)),
+            value => (spec.field.label(), value),
+        };
+
+        if let Some(value) = value {
+            for _ in 0..spec.weight {
+                parts.push(format!("{}: {}", label, value));
+            }
+        }
     }
-    
+
     parts.join("\n")
 }
 
+/// Truncate description to avoid token limits
+fn truncate_description(description: &str) -> String {
+    // Use char_indices to find a valid UTF-8 boundary
+    if description.len() > 1000 {
+        let truncate_at = description
+            .char_indices()
+            .take_while(|(i, _)| *i < 1000)
+            .last()
+            .map(|(i, c)| i + c.len_utf8())
+            .unwrap_or(0);
+        format!("{}...", &description[..truncate_at])
+    } else {
+        description.to_string()
+    }
+}
+
+/// Hash embedding text for change detection (includes the text format
+/// version and field configuration)
+pub fn embedding_text_hash(text: &str, config: &EmbeddingTextConfig) -> String {
+    format!(
+        "{:x}",
+        md5_hash(&format!("v{}\n{}\n{}", EMBEDDING_TEXT_VERSION, config.signature(), text))
+    )
+}
+
+/// Simple MD5 hash for text (used for change detection)
+fn md5_hash(text: &str) -> u128 {
+    use std::collections::hash_map::DefaultHasher;
+    use std::hash::{Hash, Hasher};
+
+    let mut hasher = DefaultHasher::new();
+    text.hash(&mut hasher);
+    hasher.finish() as u128
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
     
     #[test]
     fn test_embedding_text_generation() {
-        let text = book_to_embedding_text(
-            "The Great Gatsby",
-            Some("F. Scott Fitzgerald"),
-            Some("A story about the American Dream"),
-            None,
-        );
+        let input = EmbeddingInput {
+            title: "The Great Gatsby",
+            author: Some("F. Scott Fitzgerald"),
+            description: Some("A story about the American Dream"),
+            ..Default::default()
+        };
+        let text = book_to_embedding_text(&input, &EmbeddingTextConfig::default());
         
         assert!(text.contains("The Great Gatsby"));
         assert!(text.contains("F. Scott Fitzgerald"));
         assert!(text.contains("American Dream"));
     }
+
+    #[test]
+    fn test_tags_in_default_embedding_text() {
+        let tags = vec!["history".to_string(), "mathematics".to_string()];
+        let tagged = EmbeddingInput {
+            title: "The Code Book",
+            description: Some("Codes and ciphers"),
+            tags: &tags,
+            ..Default::default()
+        };
+        let config = EmbeddingTextConfig::default();
+        let text = book_to_embedding_text(&tagged, &config);
+        assert_eq!(text, "Title: The Code Book\nTags: history, mathematics\nDescription: Codes and ciphers");
+
+        // No tags, no line
+        let untagged = EmbeddingInput { tags: &[], ..tagged.clone() };
+        let untagged_text = book_to_embedding_text(&untagged, &config);
+        assert!(!untagged_text.contains("Tags:"));
+        assert_ne!(embedding_text_hash(&text, &config), embedding_text_hash(&untagged_text, &config));
+    }
+
+    #[test]
+    fn test_md5_hash() {
+        let hash1 = md5_hash("hello world");
+        let hash2 = md5_hash("hello world");
+        let hash3 = md5_hash("different text");
+
+        assert_eq!(hash1, hash2);
+        assert_ne!(hash1, hash3);
+    }
+
+    #[test]
+    fn test_embedding_text_hash_includes_version() {
+        let text = "Title: Dune";
+        let config = EmbeddingTextConfig::default();
+        assert_eq!(embedding_text_hash(text, &config), embedding_text_hash(text, &config));
+        assert_ne!(embedding_text_hash(text, &config), format!("{:x}", md5_hash(text)));
+    }
+
+    #[test]
+    fn test_embedding_field_config_changes_text_and_hash() {
+        let tags = vec!["space opera".to_string(), "politics".to_string()];
+        let input = EmbeddingInput {
+            title: "Dune",
+            author: Some("Frank Herbert"),
+            publisher: Some("Chilton"),
+            tags: &tags,
+            ..Default::default()
+        };
+        let default_config = EmbeddingTextConfig::default();
+        let default_text = book_to_embedding_text(&input, &default_config);
+        assert_eq!(default_text, "Title: Dune\nAuthor: Frank Herbert\nTags: space opera, politics");
+
+        // Exclude the author, add tags, and double the title's weight
+        let config = EmbeddingTextConfig {
+            fields: vec![
+                EmbeddingFieldSpec { field: EmbeddingField::Title, weight: 2 },
+                EmbeddingFieldSpec { field: EmbeddingField::Tags, weight: 1 },
+            ],
+            ..Default::default()
+        };
+        let text = book_to_embedding_text(&input, &config);
+        assert_eq!(text, "Title: Dune\nTitle: Dune\nTags: space opera, politics");
+        assert_ne!(
+            embedding_text_hash(&text, &config),
+            embedding_text_hash(&default_text, &default_config)
+        );
+
+        // The config alone changes the hash, even for identical text
+        assert_ne!(
+            embedding_text_hash(&default_text, &config),
+            embedding_text_hash(&default_text, &default_config)
+        );
+    }
+
+    #[test]
+    fn test_excerpt_stands_in_for_missing_description() {
+        let input = EmbeddingInput {
+            title: "Emma",
+            language: Some("en"),
+            excerpt: Some("Emma Woodhouse, handsome, clever, and rich"),
+            ..Default::default()
+        };
+        let config = EmbeddingTextConfig::default();
+        assert_eq!(
+            book_to_embedding_text(&input, &config),
+            "Title: Emma\nExcerpt: Emma Woodhouse, handsome, clever, and rich"
+        );
+
+        // A description wins over the excerpt; language is opt-in
+        let described = EmbeddingInput { description: Some("A comedy of manners"), ..input.clone() };
+        let mut with_language = config.clone();
+        with_language.fields.push(EmbeddingFieldSpec { field: EmbeddingField::Language, weight: 1 });
+        assert_eq!(
+            book_to_embedding_text(&described, &with_language),
+            "Title: Emma\nDescription: A comedy of manners\nLanguage: en"
+        );
+
+        // Enabling excerpts changes the signature, and so the hash
+        let excerpts = EmbeddingTextConfig { excerpt_chars: 2000, ..config.clone() };
+        assert_eq!(config.signature(), "Titlex1,Authorx1,Seriesx1,Tagsx1,Descriptionx1");
+        assert_ne!(excerpts.signature(), config.signature());
+    }
 }
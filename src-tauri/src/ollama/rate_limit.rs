@@ -0,0 +1,119 @@
+//! Token-bucket rate limiting for Ollama requests
+//!
+//! One limiter is shared by every code path that calls Ollama, so bulk
+//! embedding and interactive requests draw from the same budget.
+
+use parking_lot::Mutex;
+use std::time::{Duration, Instant};
+
+/// Default Ollama request budget
+pub const DEFAULT_REQUESTS_PER_SECOND: f64 = 10.0;
+
+/// Shared token-bucket rate limiter
+///
+/// The bucket holds up to one second's worth of tokens (at least one), so
+/// short bursts are allowed after idle periods.
+pub struct RateLimiter {
+    bucket: Mutex<Bucket>,
+}
+
+struct Bucket {
+    /// Tokens added per second; zero or less disables limiting
+    rate: f64,
+    tokens: f64,
+    last_refill: Instant,
+}
+
+impl Bucket {
+    fn capacity(&self) -> f64 {
+        self.rate.max(1.0)
+    }
+
+    fn refill(&mut self, now: Instant) {
+        let elapsed = now.duration_since(self.last_refill).as_secs_f64();
+        self.tokens = (self.tokens + elapsed * self.rate).min(self.capacity());
+        self.last_refill = now;
+    }
+}
+
+impl RateLimiter {
+    /// Create a limiter allowing `requests_per_second` on average
+    pub fn new(requests_per_second: f64) -> Self {
+        let rate = requests_per_second.max(0.0);
+        Self {
+            bucket: Mutex::new(Bucket {
+                rate,
+                tokens: rate.max(1.0),
+                last_refill: Instant::now(),
+            }),
+        }
+    }
+
+    /// Current requests-per-second budget
+    pub fn rate(&self) -> f64 {
+        self.bucket.lock().rate
+    }
+
+    /// Change the budget; takes effect for the next acquire
+    pub fn set_rate(&self, requests_per_second: f64) {
+        let mut bucket = self.bucket.lock();
+        bucket.refill(Instant::now());
+        bucket.rate = requests_per_second.max(0.0);
+        bucket.tokens = bucket.tokens.min(bucket.capacity());
+    }
+
+    /// Wait until a request may be sent
+    pub async fn acquire(&self) {
+        loop {
+            let wait = {
+                let mut bucket = self.bucket.lock();
+                if bucket.rate <= 0.0 {
+                    return;
+                }
+                bucket.refill(Instant::now());
+                if bucket.tokens >= 1.0 {
+                    bucket.tokens -= 1.0;
+                    return;
+                }
+                Duration::from_secs_f64((1.0 - bucket.tokens) / bucket.rate)
+            };
+            tokio::time::sleep(wait).await;
+        }
+    }
+}
+
+impl Default for RateLimiter {
+    fn default() -> Self {
+        Self::new(DEFAULT_REQUESTS_PER_SECOND)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_burst_is_throttled_to_rate() {
+        let limiter = RateLimiter::new(50.0);
+        let start = Instant::now();
+
+        // 50 tokens are available up front, the next 25 arrive at 50/s
+        for _ in 0..75 {
+            limiter.acquire().await;
+        }
+
+        let elapsed = start.elapsed();
+        assert!(elapsed >= Duration::from_millis(450), "{:?}", elapsed);
+        assert!(elapsed < Duration::from_secs(3), "{:?}", elapsed);
+    }
+
+    #[tokio::test]
+    async fn test_zero_rate_is_unlimited() {
+        let limiter = RateLimiter::new(0.0);
+        let start = Instant::now();
+        for _ in 0..1000 {
+            limiter.acquire().await;
+        }
+        assert!(start.elapsed() < Duration::from_millis(200));
+    }
+}
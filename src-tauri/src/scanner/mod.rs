@@ -2,9 +2,11 @@
 //!
 //! High-performance parallel scanning for EPUB files
 
-use crate::db::NewBook;
-use crate::AppResult;
+use crate::db::{Database, NewBook};
+use crate::epub::{EpubParser, Format};
+use crate::{AppError, AppResult};
 use std::path::{Path, PathBuf};
+use std::time::Duration;
 use walkdir::{DirEntry, WalkDir};
 
 /// Scan result
@@ -30,16 +32,248 @@ pub struct ScanProgress {
     pub eta_seconds: Option<u64>,
 }
 
+/// File hashing progress update
+#[derive(Debug, Clone, PartialEq, serde::Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct HashProgress {
+    pub processed: usize,
+    pub total: usize,
+}
+
+/// Result of hashing a library's files
+#[derive(Debug, Clone, serde::Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct HashResult {
+    pub total: usize,
+    pub hashed: usize,
+    pub failed: usize,
+    pub duration_ms: u64,
+}
+
+/// Result of re-checking coverless books for covers
+#[derive(Debug, Clone, Default, serde::Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CoverRefetchResult {
+    pub checked: usize,
+    /// Covers found as image files next to the book
+    pub found_files: usize,
+    /// Covers extracted from the EPUB into the cover cache
+    pub extracted: usize,
+    pub still_missing: usize,
+}
+
+/// Result of importing a folder of covers named by ISBN
+#[derive(Debug, Clone, Default, serde::Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CoverImportResult {
+    /// Image files whose name is an ISBN of a book in the library
+    pub matched: usize,
+    /// Image files with no matching book (or a name that isn't an ISBN)
+    pub unmatched: usize,
+    /// Books whose cover was set (editions sharing an ISBN each count)
+    pub books_updated: usize,
+}
+
+/// Cover extraction progress update
+#[derive(Debug, Clone, serde::Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CoverExtractionProgress {
+    pub processed: usize,
+    pub total: usize,
+}
+
+/// Result of extracting embedded covers into the cover cache
+#[derive(Debug, Clone, Default, serde::Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CoverExtractionResult {
+    pub checked: usize,
+    pub extracted: usize,
+    /// Books with no embedded cover (or one that couldn't be read)
+    pub no_cover: usize,
+    pub duration_ms: u64,
+}
+
+/// Marker file recording that a book has no extractable cover
+fn no_cover_marker(cache_dir: &Path, book_id: i64) -> PathBuf {
+    cache_dir.join(format!("{}.none", book_id))
+}
+
+/// Write extracted cover data to the cache and record it as the book's cover
+fn store_cached_cover(
+    db: &Database,
+    cache_dir: &Path,
+    book_id: i64,
+    data: &[u8],
+    mime_type: &str,
+) -> AppResult<PathBuf> {
+    let ext = if mime_type == "image/png" { "png" } else { "jpg" };
+    std::fs::create_dir_all(cache_dir)?;
+    let cover = cache_dir.join(format!("{}.{}", book_id, ext));
+    std::fs::write(&cover, data)?;
+    db.set_cover_path(book_id, Some(cover.to_string_lossy().as_ref()))?;
+
+    let marker = no_cover_marker(cache_dir, book_id);
+    if marker.exists() {
+        std::fs::remove_file(marker)?;
+    }
+    Ok(cover)
+}
+
+/// Make sure a book's cover is on disk, extracting it from the EPUB once
+///
+/// Returns the cover file, or `None` if the book has no cover. A book whose
+/// extraction fails gets a "no cover" marker in `cache_dir` so the EPUB
+/// isn't re-opened on every request; `refetch_covers` still retries it.
+/// Transient failures (e.g. an unmounted drive) are not marked.
+pub fn ensure_cover_extracted(db: &Database, cache_dir: &Path, book_id: i64) -> AppResult<Option<PathBuf>> {
+    let book = db.get_book(book_id)?;
+    if let Some(cover) = book.cover_path.as_deref().map(PathBuf::from) {
+        if cover.exists() {
+            return Ok(Some(cover));
+        }
+    }
+
+    let marker = no_cover_marker(cache_dir, book_id);
+    if marker.exists() {
+        return Ok(None);
+    }
+
+    match EpubParser::new().extract_cover(Path::new(&book.path)) {
+        Ok(Some((data, mime_type))) => store_cached_cover(db, cache_dir, book_id, &data, &mime_type).map(Some),
+        Ok(None) => {
+            std::fs::create_dir_all(cache_dir)?;
+            std::fs::write(&marker, b"")?;
+            Ok(None)
+        }
+        Err(e) if e.is_transient() => Err(e),
+        Err(e) => {
+            tracing::debug!("Cover extraction failed for {}: {}", book.path, e);
+            std::fs::create_dir_all(cache_dir)?;
+            std::fs::write(&marker, b"")?;
+            Ok(None)
+        }
+    }
+}
+
+/// Extract the embedded cover of every coverless book into `cache_dir`
+///
+/// Books already marked as having no cover are skipped without opening the
+/// EPUB. `on_progress(processed, total)` is called after each book.
+pub fn extract_all_covers<F>(db: &Database, cache_dir: &Path, mut on_progress: F) -> AppResult<CoverExtractionResult>
+where
+    F: FnMut(usize, usize),
+{
+    let start = std::time::Instant::now();
+    let books = db.get_books_without_cover(i64::MAX)?;
+    let total = books.len();
+    let mut result = CoverExtractionResult::default();
+
+    for (i, book) in books.iter().enumerate() {
+        result.checked += 1;
+        match ensure_cover_extracted(db, cache_dir, book.id) {
+            Ok(Some(_)) => result.extracted += 1,
+            Ok(None) => result.no_cover += 1,
+            Err(e) => {
+                tracing::debug!("Cover extraction failed for {}: {}", book.path, e);
+                result.no_cover += 1;
+            }
+        }
+        on_progress(i + 1, total);
+    }
+
+    result.duration_ms = start.elapsed().as_millis() as u64;
+    Ok(result)
+}
+
+/// Maximum threads used for file hashing (hashing is mostly I/O bound)
+pub const MAX_HASH_THREADS: usize = 8;
+
+/// Hash book files in parallel on a bounded thread pool
+///
+/// Takes `(book_id, path)` pairs and returns `(book_id, hash)` for every file
+/// that could be read, in input order. `on_progress(processed, total)` is
+/// called from worker threads as files complete.
+pub fn hash_files<F>(files: &[(i64, String)], threads: usize, on_progress: F) -> AppResult<Vec<(i64, String)>>
+where
+    F: Fn(usize, usize) + Sync,
+{
+    use rayon::prelude::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    let pool = rayon::ThreadPoolBuilder::new()
+        .num_threads(threads.clamp(1, MAX_HASH_THREADS))
+        .build()
+        .map_err(|e| crate::AppError::Config(format!("Failed to start hashing threads: {}", e)))?;
+
+    let total = files.len();
+    let processed = AtomicUsize::new(0);
+
+    let hashes = pool.install(|| {
+        files
+            .par_iter()
+            .filter_map(|(book_id, path)| {
+                let hash = crate::epub::calculate_file_hash(Path::new(path));
+                on_progress(processed.fetch_add(1, Ordering::Relaxed) + 1, total);
+                match hash {
+                    Ok(hash) => Some((*book_id, hash)),
+                    Err(e) => {
+                        tracing::warn!("Failed to hash {}: {}", path, e);
+                        None
+                    }
+                }
+            })
+            .collect()
+    });
+
+    Ok(hashes)
+}
+
+/// Maximum files parsed at once by [`parse_files`]
+pub const MAX_PARSE_CONCURRENCY: usize = 8;
+
+/// Parse book files concurrently, at most `concurrency` at a time
+///
+/// Each parse runs on the blocking pool and is given `timeout`; one that
+/// times out or panics becomes an error for that file alone. Results are
+/// in input order.
+pub async fn parse_files(paths: &[String], concurrency: usize, timeout: Duration) -> Vec<AppResult<NewBook>> {
+    use futures::stream::{self, StreamExt};
+
+    stream::iter(paths.iter().cloned())
+        .map(|path| parse_with_timeout(path, timeout))
+        .buffered(concurrency.clamp(1, MAX_PARSE_CONCURRENCY))
+        .collect()
+        .await
+}
+
+/// Parse one book file on the blocking pool, giving up after `timeout`
+async fn parse_with_timeout(path: String, timeout: Duration) -> AppResult<NewBook> {
+    let parse = tokio::task::spawn_blocking(move || EpubParser::new().parse(Path::new(&path)));
+    match tokio::time::timeout(timeout, parse).await {
+        Ok(Ok(result)) => result,
+        // Task panic - treat as a permanent parse failure
+        Ok(Err(e)) => Err(AppError::EpubParse(format!("Parser panicked: {}", e))),
+        // Timeout - the file may be on a slow or busy share
+        Err(_) => Err(AppError::Io(std::io::Error::new(
+            std::io::ErrorKind::TimedOut,
+            format!("Parsing timed out after {:?}", timeout),
+        ))),
+    }
+}
+
 /// Scanner configuration
 pub struct ScannerConfig {
     /// Maximum directory depth to scan
     pub max_depth: usize,
     /// Whether to follow symbolic links
     pub follow_links: bool,
-    /// File extensions to scan (lowercase)
+    /// File extensions to scan (lowercase); files without a known
+    /// [`Format`] are read as EPUBs
     pub extensions: Vec<String>,
     /// Cover image extensions (lowercase)
     pub cover_extensions: Vec<String>,
+    /// Whether to look for EPUBs inside `.zip` archives (one level deep)
+    pub descend_archives: bool,
 }
 
 impl Default for ScannerConfig {
@@ -47,8 +281,9 @@ impl Default for ScannerConfig {
         Self {
             max_depth: 20,
             follow_links: false,
-            extensions: vec!["epub".to_string()],
+            extensions: Format::ALL.iter().map(|format| format.as_str().to_string()).collect(),
             cover_extensions: vec!["jpg".to_string(), "jpeg".to_string(), "png".to_string()],
+            descend_archives: false,
         }
     }
 }
@@ -71,7 +306,7 @@ impl Scanner {
         Self { config }
     }
 
-    /// Fast scan - only discover EPUB files without parsing metadata
+    /// Fast scan - only discover book files without parsing metadata
     /// Returns minimal book records that can be quickly inserted into DB
     pub fn fast_scan(&self, root: &Path) -> AppResult<Vec<NewBook>> {
         tracing::info!("Fast scanning directory: {:?}", root);
@@ -83,44 +318,35 @@ impl Scanner {
             .max_depth(self.config.max_depth)
             .follow_links(self.config.follow_links)
             .into_iter()
-            .filter_entry(|e| !is_hidden(e))
+            .filter_entry(|e| e.depth() == 0 || !is_hidden(e))
             .filter_map(|e| e.ok())
-            .filter(|e| self.is_epub(e))
         {
             let path = entry.path();
-            let file_size = entry.metadata().map(|m| m.len() as i64).unwrap_or(0);
 
-            // Extract title from filename (fast, no file parsing)
-            let title = path
-                .file_stem()
-                .map(|s| s.to_string_lossy().to_string())
-                .unwrap_or_else(|| "Unknown".to_string());
-
-            // Try to find cover in same directory
-            let cover_path = self.find_cover(path);
-
-            books.push(NewBook {
-                path: path.to_string_lossy().to_string(),
-                cover_path: cover_path.map(|p| p.to_string_lossy().to_string()),
-                file_size,
-                file_hash: None,
-                title,
-                sort_title: None,
-                author: None,
-                author_sort: None,
-                series: None,
-                series_index: None,
-                description: None,
-                language: None,
-                publisher: None,
-                publish_date: None,
-                isbn: None,
-                source: "scan".to_string(),
-            });
+            if self.is_book(&entry) {
+                let file_size = entry.metadata().map(|m| m.len() as i64).unwrap_or(0);
+                books.push(self.discovered_book(path, path, file_size, None));
+            } else if self.config.descend_archives && is_archive(&entry) {
+                let inner = match crate::epub::list_archive_epubs(path) {
+                    Ok(inner) => inner,
+                    Err(e) => {
+                        tracing::debug!("Skipping unreadable archive {:?}: {}", path, e);
+                        continue;
+                    }
+                };
+
+                // Inner books are addressed as "archive.zip/entry.epub"
+                for (entry_name, size) in inner {
+                    let book_path = entry_name
+                        .split('/')
+                        .fold(path.to_path_buf(), |p, part| p.join(part));
+                    books.push(self.discovered_book(&book_path, path, size as i64, Some(path)));
+                }
+            }
         }
 
         tracing::info!(
-            "Fast scan found {} EPUB files in {:?}",
+            "Fast scan found {} book files in {:?}",
             books.len(),
             start.elapsed()
         );
@@ -128,24 +354,135 @@ impl Scanner {
         Ok(books)
     }
 
-    /// Check if a directory entry is an EPUB file
-    fn is_epub(&self, entry: &DirEntry) -> bool {
+    /// Build a minimal book record for a discovered file (no parsing)
+    fn discovered_book(
+        &self,
+        path: &Path,
+        file_path: &Path,
+        file_size: i64,
+        container: Option<&Path>,
+    ) -> NewBook {
+        // Extract title from filename (fast, no file parsing)
+        let title = crate::epub::book_stem(path).unwrap_or_else(|| "Unknown".to_string());
+
+        // Try to find cover next to the file on disk
+        let cover_path = self.find_cover(file_path);
+
+        NewBook {
+            path: path.to_string_lossy().to_string(),
+            cover_path: cover_path.map(|p| p.to_string_lossy().to_string()),
+            file_size,
+            file_hash: None,
+            title,
+            sort_title: None,
+            author: None,
+            author_sort: None,
+            series: None,
+            series_index: None,
+            description: None,
+            language: None,
+            publisher: None,
+            publish_date: None,
+            isbn: None,
+            source: "scan".to_string(),
+            container_path: container.map(|p| p.to_string_lossy().to_string()),
+            word_count: None,
+            format: Format::from_path(path).unwrap_or(Format::Epub),
+            tags: Vec::new(),
+            authors: Vec::new(),
+        }
+    }
+
+    /// Check if a directory entry is a book file (plain or gzipped)
+    fn is_book(&self, entry: &DirEntry) -> bool {
         if !entry.file_type().is_file() {
             return false;
         }
 
-        entry
-            .path()
-            .extension()
-            .and_then(|ext| ext.to_str())
-            .map(|ext| self.config.extensions.contains(&ext.to_lowercase()))
-            .unwrap_or(false)
+        let name = entry.file_name().to_string_lossy().to_lowercase();
+        self.config.extensions.iter().any(|ext| {
+            name.ends_with(&format!(".{}", ext)) || name.ends_with(&format!(".{}.gz", ext))
+        })
+    }
+
+    /// Look for covers for books that have none
+    ///
+    /// Checks the filesystem for cover images added since the scan, then
+    /// falls back to the EPUB's embedded cover, which is written to
+    /// `cache_dir` (library folders are never written to).
+    pub fn refetch_covers(&self, db: &Database, cache_dir: &Path, limit: i64) -> AppResult<CoverRefetchResult> {
+        let mut result = CoverRefetchResult::default();
+        let parser = EpubParser::new();
+
+        for book in db.get_books_without_cover(limit)? {
+            result.checked += 1;
+            let path = Path::new(&book.path);
+
+            if let Some(cover) = self.find_cover(path) {
+                db.set_cover_path(book.id, Some(cover.to_string_lossy().as_ref()))?;
+                result.found_files += 1;
+                continue;
+            }
+
+            match parser.extract_cover(path) {
+                Ok(Some((data, mime_type))) => {
+                    store_cached_cover(db, cache_dir, book.id, &data, &mime_type)?;
+                    result.extracted += 1;
+                }
+                Ok(None) => result.still_missing += 1,
+                Err(e) => {
+                    tracing::debug!("Cover extraction failed for {}: {}", book.path, e);
+                    result.still_missing += 1;
+                }
+            }
+        }
+
+        Ok(result)
+    }
+
+    /// Use the images in `dir` named by ISBN (`9781234567890.jpg`,
+    /// `0-306-40615-2.png`) as covers of the books with that ISBN
+    ///
+    /// ISBN-10 and ISBN-13 names both match either form stored on the book.
+    /// The files are used where they are, replacing any existing cover.
+    pub fn import_covers_by_isbn(&self, db: &Database, dir: &Path) -> AppResult<CoverImportResult> {
+        let by_isbn = db.get_book_ids_by_isbn()?;
+        let mut result = CoverImportResult::default();
+
+        for entry in std::fs::read_dir(dir)? {
+            let path = entry?.path();
+            let is_image = path
+                .extension()
+                .and_then(|e| e.to_str())
+                .is_some_and(|ext| self.config.cover_extensions.contains(&ext.to_lowercase()));
+            if !is_image || !path.is_file() {
+                continue;
+            }
+
+            let book_ids = path
+                .file_stem()
+                .and_then(|stem| stem.to_str())
+                .and_then(crate::db::normalize_isbn)
+                .and_then(|isbn| by_isbn.get(&isbn));
+            let Some(book_ids) = book_ids else {
+                result.unmatched += 1;
+                continue;
+            };
+
+            for &book_id in book_ids {
+                db.set_cover_path(book_id, Some(path.to_string_lossy().as_ref()))?;
+                result.books_updated += 1;
+            }
+            result.matched += 1;
+        }
+
+        Ok(result)
     }
 
     /// Find a cover image in the same directory or parent directory
-    fn find_cover(&self, epub_path: &Path) -> Option<PathBuf> {
+    pub fn find_cover(&self, epub_path: &Path) -> Option<PathBuf> {
         let parent = epub_path.parent()?;
-        let stem = epub_path.file_stem()?.to_string_lossy().to_lowercase();
+        let stem = crate::epub::book_stem(epub_path)?.to_lowercase();
 
         // Look for cover in same directory
         for ext in &self.config.cover_extensions {
@@ -182,6 +519,15 @@ impl Default for Scanner {
     }
 }
 
+/// Check if a directory entry is a `.zip` archive
+fn is_archive(entry: &DirEntry) -> bool {
+    entry.file_type().is_file()
+        && entry
+            .path()
+            .extension()
+            .is_some_and(|ext| ext.eq_ignore_ascii_case("zip"))
+}
+
 /// Check if a directory entry is hidden
 fn is_hidden(entry: &DirEntry) -> bool {
     entry
@@ -197,6 +543,98 @@ mod tests {
     use std::fs;
     use tempfile::TempDir;
 
+    #[test]
+    fn test_refetch_covers_finds_new_cover_file() {
+        let dir = TempDir::new().unwrap();
+        let book_dir = dir.path().join("library").join("Author");
+        fs::create_dir_all(&book_dir).unwrap();
+        let book_path = book_dir.join("book.epub");
+        fs::write(&book_path, b"not really an epub").unwrap();
+
+        let db = Database::new_in_memory().unwrap();
+        db.with_conn(|conn| {
+            conn.execute(
+                "INSERT INTO books (id, path, title) VALUES (1, ?, 'Book')",
+                [book_path.to_string_lossy().as_ref()],
+            )?;
+            Ok(())
+        })
+        .unwrap();
+
+        let scanner = Scanner::new();
+        let cache_dir = dir.path().join("covers");
+        let before = scanner.refetch_covers(&db, &cache_dir, 10).unwrap();
+        assert_eq!((before.checked, before.still_missing), (1, 1));
+        assert_eq!(db.get_books_without_cover(10).unwrap().len(), 1);
+
+        let cover = book_dir.join("cover.jpg");
+        fs::write(&cover, b"jpeg").unwrap();
+
+        let after = scanner.refetch_covers(&db, &cache_dir, 10).unwrap();
+        assert_eq!((after.checked, after.found_files), (1, 1));
+        assert!(db.get_books_without_cover(10).unwrap().is_empty());
+        assert_eq!(db.get_book(1).unwrap().cover_path, Some(cover.to_string_lossy().to_string()));
+    }
+
+    #[test]
+    fn test_import_covers_by_isbn() {
+        let dir = TempDir::new().unwrap();
+        let db = Database::new_in_memory().unwrap();
+        db.with_conn(|conn| {
+            conn.execute_batch(
+                "INSERT INTO books (id, path, title, isbn) VALUES
+                    (1, '/b/1.epub', 'Thirteen', '978-0-306-40615-7'),
+                    (2, '/b/2.epub', 'Ten', '0141439513'),
+                    (3, '/b/3.epub', 'Unmatched', '9780000000002');
+                 INSERT INTO books (id, path, title, cover_path) VALUES (4, '/b/4.epub', 'No ISBN', '/old.jpg');",
+            )?;
+            Ok(())
+        })
+        .unwrap();
+
+        let covers = dir.path().join("covers");
+        fs::create_dir(&covers).unwrap();
+        // An ISBN-10 file for book 1, an ISBN-13 file for book 2
+        let first = covers.join("0306406152.jpg");
+        let second = covers.join("9780141439518.PNG");
+        for path in [&first, &second, &covers.join("9781111111113.jpg"), &covers.join("notes.txt")] {
+            fs::write(path, b"image").unwrap();
+        }
+
+        let result = Scanner::new().import_covers_by_isbn(&db, &covers).unwrap();
+        assert_eq!((result.matched, result.unmatched, result.books_updated), (2, 1, 2));
+        assert_eq!(db.get_book(1).unwrap().cover_path, Some(first.to_string_lossy().to_string()));
+        assert_eq!(db.get_book(2).unwrap().cover_path, Some(second.to_string_lossy().to_string()));
+        assert_eq!(db.get_book(3).unwrap().cover_path, None);
+        assert_eq!(db.get_book(4).unwrap().cover_path.as_deref(), Some("/old.jpg"));
+    }
+
+    #[test]
+    fn test_parallel_hashing_matches_identical_files() {
+        let dir = TempDir::new().unwrap();
+        let mut files = Vec::new();
+        for (id, contents) in [(1, "same bytes"), (2, "other bytes"), (3, "same bytes"), (4, "more")] {
+            let path = dir.path().join(format!("{}.epub", id));
+            fs::write(&path, contents).unwrap();
+            files.push((id, path.to_string_lossy().to_string()));
+        }
+        files.push((5, dir.path().join("missing.epub").to_string_lossy().to_string()));
+
+        let max_seen = std::sync::atomic::AtomicUsize::new(0);
+        let hashes = hash_files(&files, 4, |processed, total| {
+            assert_eq!(total, 5);
+            max_seen.fetch_max(processed, std::sync::atomic::Ordering::Relaxed);
+        })
+        .unwrap();
+
+        assert_eq!(max_seen.into_inner(), 5);
+        let ids: Vec<i64> = hashes.iter().map(|(id, _)| *id).collect();
+        assert_eq!(ids, vec![1, 2, 3, 4]);
+        assert_eq!(hashes[0].1, hashes[2].1);
+        assert_ne!(hashes[0].1, hashes[1].1);
+        assert_eq!(hashes[0].1.len(), 64);
+    }
+
     #[test]
     fn test_scanner_finds_epub() {
         let temp = TempDir::new().unwrap();
@@ -243,4 +681,98 @@ mod tests {
         assert_eq!(results.len(), 1);
         assert!(results[0].path.contains("visible"));
     }
+
+    #[test]
+    fn test_scanner_finds_gzipped_epub() {
+        let temp = TempDir::new().unwrap();
+        fs::write(temp.path().join("book.epub.gz"), b"gzipped epub").unwrap();
+
+        let scanner = Scanner::new();
+        let results = scanner.fast_scan(temp.path()).unwrap();
+
+        assert_eq!(results.len(), 1);
+        assert_eq!(results[0].title, "book");
+        assert_eq!(results[0].container_path, None);
+    }
+
+    #[tokio::test]
+    async fn test_parse_files_keeps_input_order() {
+        let temp = TempDir::new().unwrap();
+        let mut paths = Vec::new();
+        for (i, title) in ["One", "Two", "Three", "Four"].into_iter().enumerate() {
+            let path = temp.path().join(format!("{}.epub", i));
+            let bytes = if i == 1 { b"not an epub".to_vec() } else { crate::epub::test_epub_bytes(title, "Author") };
+            fs::write(&path, bytes).unwrap();
+            paths.push(path.to_string_lossy().to_string());
+        }
+
+        let results = parse_files(&paths, 3, Duration::from_secs(10)).await;
+        let titles: Vec<Option<String>> = results.iter().map(|r| r.as_ref().ok().map(|b| b.title.clone())).collect();
+        assert_eq!(titles, vec![Some("One".into()), None, Some("Three".into()), Some("Four".into())]);
+        assert!(!results[1].as_ref().unwrap_err().is_transient());
+    }
+
+    #[test]
+    fn test_scanner_finds_kindle_formats() {
+        let temp = TempDir::new().unwrap();
+        for name in ["a.epub", "b.mobi", "c.AZW3", "d.mobi.gz", "e.pdf"] {
+            fs::write(temp.path().join(name), b"book").unwrap();
+        }
+
+        let mut results: Vec<(String, Format)> = Scanner::new()
+            .fast_scan(temp.path())
+            .unwrap()
+            .into_iter()
+            .map(|book| (book.title, book.format))
+            .collect();
+        results.sort_by(|a, b| a.0.cmp(&b.0));
+
+        assert_eq!(
+            results,
+            vec![
+                ("a".to_string(), Format::Epub),
+                ("b".to_string(), Format::Mobi),
+                ("c".to_string(), Format::Azw3),
+                ("d".to_string(), Format::Mobi),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_scanner_descends_into_zip() {
+        use std::io::Write;
+
+        let temp = TempDir::new().unwrap();
+        let archive_path = temp.path().join("Persuasion.zip");
+        let mut writer = zip::ZipWriter::new(fs::File::create(&archive_path).unwrap());
+        writer
+            .start_file("persuasion.epub", zip::write::FileOptions::default())
+            .unwrap();
+        writer
+            .write_all(&crate::epub::test_epub_bytes("Persuasion", "Jane Austen"))
+            .unwrap();
+        writer.start_file("readme.txt", zip::write::FileOptions::default()).unwrap();
+        writer.write_all(b"not a book").unwrap();
+        writer.finish().unwrap();
+
+        // Archives are ignored unless enabled
+        assert!(Scanner::new().fast_scan(temp.path()).unwrap().is_empty());
+
+        let scanner = Scanner::with_config(ScannerConfig {
+            descend_archives: true,
+            ..Default::default()
+        });
+        let results = scanner.fast_scan(temp.path()).unwrap();
+
+        assert_eq!(results.len(), 1);
+        let book_path = archive_path.join("persuasion.epub");
+        assert_eq!(results[0].path, book_path.to_string_lossy());
+        assert_eq!(
+            results[0].container_path,
+            Some(archive_path.to_string_lossy().to_string())
+        );
+
+        let parsed = crate::epub::EpubParser::new().parse(&book_path).unwrap();
+        assert_eq!(parsed.title, "Persuasion");
+    }
 }
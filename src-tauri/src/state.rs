@@ -6,15 +6,21 @@
 //! - Ollama client state
 //! - Vector store for embeddings
 
-use crate::db::Database;
-use crate::ollama::OllamaClient;
+use crate::db::{Database, PoolConfig, DEFAULT_POOL_SIZE};
+use crate::graph::{BookGraph, GRAPH_MIN_WEIGHT};
+use crate::ollama::{OllamaClient, RateLimiter};
 use crate::vector::VectorStore;
+use crate::watcher::{run_watcher, LibraryWatcher};
+use crate::worker::{BackgroundWorker, EmbeddingProgress, EventSink, HashBackfill, Heartbeat};
 use crate::AppResult;
-use parking_lot::RwLock;
-use std::path::PathBuf;
+use parking_lot::{Mutex, RwLock};
+use std::path::{Path, PathBuf};
 use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::Arc;
 
+/// How long quitting waits for the background worker to finish its job
+pub const SHUTDOWN_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(10);
+
 /// Global application state shared across all Tauri commands
 pub struct AppState {
     /// SQLite database connection pool
@@ -24,10 +30,23 @@ pub struct AppState {
     pub vector_store: Arc<VectorStore>,
 
     /// Ollama client for embedding generation
-    pub ollama: RwLock<OllamaClient>,
+    pub ollama: Arc<RwLock<OllamaClient>>,
+
+    /// Request budget shared by everything that calls Ollama
+    pub rate_limiter: Arc<RateLimiter>,
 
     /// Flag to pause/resume background processing
-    pub processing_paused: AtomicBool,
+    pub processing_paused: Arc<AtomicBool>,
+
+    /// Flag to stop a running "process all pending" job
+    pub processing_cancelled: AtomicBool,
+
+    /// Set once the app is quitting, so the worker stops taking new jobs
+    pub shutting_down: Arc<AtomicBool>,
+
+    /// Latest progress of a running embedding job (a batch, "process all
+    /// pending", or the worker's queue)
+    pub processing_progress: Arc<RwLock<Option<EmbeddingProgress>>>,
 
     /// Application data directory
     pub data_dir: PathBuf,
@@ -35,6 +54,21 @@ pub struct AppState {
     /// Channel for background job coordination
     pub job_sender: async_channel::Sender<BackgroundJob>,
     pub job_receiver: async_channel::Receiver<BackgroundJob>,
+
+    /// Liveness of the background worker, for stall detection
+    pub heartbeat: Arc<Heartbeat>,
+
+    /// Books queued for background hashing
+    pub hash_backfill: Arc<HashBackfill>,
+
+    /// Watches library folders for added, changed and removed books
+    pub watcher: Arc<LibraryWatcher>,
+
+    /// Running background worker task
+    worker_task: Mutex<Option<tokio::task::JoinHandle<()>>>,
+
+    /// Recommendation graph with the edge generation it was built at
+    book_graph: RwLock<Option<(i64, Arc<BookGraph>)>>,
 }
 
 /// Background job types
@@ -42,10 +76,16 @@ pub struct AppState {
 pub enum BackgroundJob {
     /// Scan a library for new books
     ScanLibrary { library_id: i64 },
-    /// Generate embedding for a book
+    /// Queue a book for embedding (higher priority runs first)
     GenerateEmbedding { book_id: i64, priority: i32 },
+    /// Queue several books for embedding, e.g. a batch added by the watcher
+    GenerateEmbeddings { book_ids: Vec<i64> },
+    /// Work through the embedding job queue in the database
+    ProcessEmbeddingQueue,
     /// Recompute graph edges for a book
     UpdateGraphEdges { book_id: i64 },
+    /// Hash a book's file for duplicate detection
+    ComputeHash { book_id: i64 },
     /// Stop all background processing
     Shutdown,
 }
@@ -60,53 +100,172 @@ impl AppState {
 
         std::fs::create_dir_all(&data_dir)?;
 
-        let db_path = data_dir.join("library.db");
+        // A moved database is only used once it exists, so a preference set
+        // without migrating the data can't leave the user with an empty library
+        let db_path = match database_path_preference() {
+            Some(path) if path.exists() => path,
+            Some(path) => {
+                tracing::warn!("Preferred database {:?} does not exist, using the default", path);
+                data_dir.join("library.db")
+            }
+            None => data_dir.join("library.db"),
+        };
         tracing::info!("Database path: {:?}", db_path);
 
-        // Initialize database
-        let db = Database::new(&db_path)?;
+        // Initialize database, reopening with the configured pool size if it differs
+        let mut db = Database::new(&db_path)?;
+        let pool_size = db.get_settings().map(|s| s.db_pool_size).unwrap_or(DEFAULT_POOL_SIZE);
+        if pool_size != DEFAULT_POOL_SIZE {
+            db = Database::with_pool_config(
+                &db_path,
+                PoolConfig {
+                    max_size: pool_size,
+                    ..Default::default()
+                },
+            )?;
+        }
 
         // Initialize vector store (uses same database)
         let vector_store = Arc::new(VectorStore::new(db_path.to_str().unwrap_or("library.db"))?);
+        if let Ok(settings) = db.get_settings() {
+            vector_store.set_metric(settings.similarity_metric);
+            vector_store.set_ann_threshold(settings.ann_threshold);
+            vector_store.set_active_model(&settings.ollama_model);
+        }
 
-        // Load embeddings cache in background
+        // Load embeddings cache in background, from the last snapshot if it's current
         let vs_clone = vector_store.clone();
+        let snapshot_path = embedding_snapshot_path(&db_path);
         std::thread::spawn(move || {
+            match vs_clone.try_load_snapshot(&snapshot_path) {
+                Ok(true) => return,
+                Ok(false) => {}
+                Err(e) => tracing::warn!("Failed to read embedding snapshot: {}", e),
+            }
             if let Err(e) = vs_clone.load_cache() {
                 tracing::warn!("Failed to load embedding cache: {}", e);
             }
         });
 
         // Initialize Ollama client with default settings
-        let ollama = RwLock::new(OllamaClient::new(
+        let ollama = Arc::new(RwLock::new(OllamaClient::new(
             "http://localhost:11434".to_string(),
             "nomic-embed-text".to_string(),
-        ));
+        )));
+
+        let rate_limiter = Arc::new(RateLimiter::default());
+        if let Ok(settings) = db.get_settings() {
+            rate_limiter.set_rate(settings.ollama_requests_per_second);
+        }
 
         // Create job channel (unbounded for simplicity)
         let (job_sender, job_receiver) = async_channel::unbounded();
+        let watcher = LibraryWatcher::default().with_job_sender(job_sender.clone());
 
         Ok(Self {
             db,
             vector_store,
             ollama,
-            processing_paused: AtomicBool::new(false),
+            rate_limiter,
+            processing_paused: Arc::new(AtomicBool::new(false)),
+            processing_cancelled: AtomicBool::new(false),
+            shutting_down: Arc::new(AtomicBool::new(false)),
+            processing_progress: Arc::new(RwLock::new(None)),
             data_dir,
             job_sender,
             job_receiver,
+            heartbeat: Arc::new(Heartbeat::default()),
+            hash_backfill: Arc::new(HashBackfill::default()),
+            watcher: Arc::new(watcher),
+            worker_task: Mutex::new(None),
+            book_graph: RwLock::new(None),
         })
     }
     
     /// Start background services
-    pub async fn start_background_services(&self) -> AppResult<()> {
+    pub async fn start_background_services(&self, events: Arc<dyn EventSink>) -> AppResult<()> {
         tracing::info!("Starting background services...");
 
-        // The embedding processor runs in a loop, checking for pending books
-        // and generating embeddings when Ollama is available
+        // The worker processes queued embedding and graph jobs
+        self.spawn_worker(events.clone());
+
+        // The watcher keeps books in step with their library folders
+        self.start_watcher(events)?;
 
         Ok(())
     }
+
+    /// Watch every library with watching enabled and apply changes as they
+    /// settle
+    ///
+    /// Calibre libraries are left out; their books come from Calibre's
+    /// database rather than the folder.
+    fn start_watcher(&self, events: Arc<dyn EventSink>) -> AppResult<()> {
+        self.watcher.start()?;
+        for library in self.db.get_libraries()? {
+            if !library.watch_enabled || library.is_calibre {
+                continue;
+            }
+            if let Err(e) = self.watcher.watch_path(Path::new(&library.path)) {
+                tracing::warn!("Failed to watch library {}: {}", library.name, e);
+            }
+        }
+        tokio::spawn(run_watcher(self.watcher.clone(), self.db.clone(), events));
+        Ok(())
+    }
+
+    /// Start the background worker, aborting any previous one
+    ///
+    /// Queued jobs stay in the channel, so a stalled worker can be replaced
+    /// without losing work other than the job it was stuck on.
+    pub fn spawn_worker(&self, events: Arc<dyn EventSink>) {
+        let worker = BackgroundWorker::new(
+            self.db.clone(),
+            self.vector_store.clone(),
+            self.ollama.clone(),
+            self.rate_limiter.clone(),
+            self.job_receiver.clone(),
+            self.processing_paused.clone(),
+        )
+        .with_events(events)
+        .with_heartbeat(self.heartbeat.clone())
+        .with_hash_backfill(self.hash_backfill.clone())
+        .with_progress(self.processing_progress.clone())
+        .with_shutdown(self.shutting_down.clone());
+
+        let mut task = self.worker_task.lock();
+        if let Some(previous) = task.take() {
+            previous.abort();
+            tracing::info!("Restarting background worker");
+        }
+        self.heartbeat.beat();
+        *task = Some(tokio::spawn(async move { worker.run().await }));
+    }
     
+    /// Stop background work and save state before the app exits
+    ///
+    /// Waits up to `timeout` for the worker to finish the job in hand, so an
+    /// embedding being stored isn't cut off halfway; queued embedding jobs
+    /// stay in the database for the next start. Then snapshots the embedding
+    /// cache.
+    pub async fn shutdown(&self, timeout: std::time::Duration) {
+        tracing::info!("Shutting down background services...");
+        self.shutting_down.store(true, Ordering::Relaxed);
+        self.processing_cancelled.store(true, Ordering::Relaxed);
+        self.queue_job(BackgroundJob::Shutdown);
+        self.watcher.stop();
+
+        let task = self.worker_task.lock().take();
+        if let Some(task) = task {
+            match tokio::time::timeout(timeout, task).await {
+                Ok(_) => tracing::info!("Background worker stopped"),
+                Err(_) => tracing::warn!("Background worker did not stop within {:?}; exiting anyway", timeout),
+            }
+        }
+
+        self.save_embedding_snapshot();
+    }
+
     /// Check if processing is paused
     pub fn is_processing_paused(&self) -> bool {
         self.processing_paused.load(Ordering::Relaxed)
@@ -124,12 +283,142 @@ impl AppState {
         tracing::info!("Background processing resumed");
     }
     
+    /// Ask a running "process all pending" job to stop after its current books
+    pub fn cancel_processing(&self) {
+        self.processing_cancelled.store(true, Ordering::Relaxed);
+        tracing::info!("Background processing cancelled");
+    }
+    
+    /// Directory extracted covers are written to
+    pub fn cover_dir(&self) -> PathBuf {
+        match self.db.get_settings() {
+            Ok(settings) if !settings.cover_dir.is_empty() => PathBuf::from(settings.cover_dir),
+            _ => self.data_dir.join("covers"),
+        }
+    }
+    
     /// Queue a background job
     pub fn queue_job(&self, job: BackgroundJob) {
         if let Err(e) = self.job_sender.try_send(job) {
             tracing::error!("Failed to queue job: {}", e);
         }
     }
+
+    /// Queue a `ComputeHash` job for every book without a file hash
+    ///
+    /// Books whose job is still pending aren't queued again. Returns the
+    /// number of books without a hash.
+    pub fn queue_missing_hashes(&self) -> AppResult<usize> {
+        let unhashed = self.db.get_unhashed_books(None)?;
+        for book_id in self.hash_backfill.start(unhashed.iter().map(|&(id, _)| id)) {
+            self.queue_job(BackgroundJob::ComputeHash { book_id });
+        }
+        Ok(unhashed.len())
+    }
+
+    /// The recommendation graph, rebuilt only when edges have changed
+    ///
+    /// A graph built from the database is also written next to it, so the
+    /// next start can read it back instead of querying every edge.
+    pub fn book_graph(&self) -> AppResult<Arc<BookGraph>> {
+        let generation = self.db.edge_generation()?;
+        if let Some((built_at, graph)) = &*self.book_graph.read() {
+            if *built_at == generation {
+                return Ok(graph.clone());
+            }
+        }
+
+        let path = graph_snapshot_path(Path::new(self.db.path()));
+        let graph = match self.read_graph_snapshot(&path, generation) {
+            Some(graph) => graph,
+            None => {
+                let graph = BookGraph::from_database(&self.db, GRAPH_MIN_WEIGHT)?;
+                if let Err(e) = write_graph_snapshot(&path, generation, &graph) {
+                    tracing::warn!("Failed to save graph snapshot: {}", e);
+                }
+                graph
+            }
+        };
+
+        let graph = Arc::new(graph);
+        *self.book_graph.write() = Some((generation, graph.clone()));
+        Ok(graph)
+    }
+
+    /// Read the graph snapshot if it was taken at `generation`
+    fn read_graph_snapshot(&self, path: &Path, generation: i64) -> Option<BookGraph> {
+        let bytes = std::fs::read(path).ok()?;
+        if bytes.len() < 8 {
+            return None;
+        }
+        let (header, body) = bytes.split_at(8);
+        if i64::from_le_bytes(header.try_into().ok()?) != generation {
+            return None;
+        }
+        let graph = BookGraph::from_bytes(body).ok()?;
+        // A restored backup can reach the same generation with other edges
+        (self.db.count_edges(GRAPH_MIN_WEIGHT).ok()? == graph.edge_count()).then_some(graph)
+    }
+
+    /// Snapshot the embedding cache so the next start can skip reloading it
+    pub fn save_embedding_snapshot(&self) {
+        let path = embedding_snapshot_path(Path::new(self.db.path()));
+        if let Err(e) = self.vector_store.save_cache_snapshot(&path) {
+            tracing::warn!("Failed to save embedding snapshot: {}", e);
+        }
+    }
+}
+
+/// Write `graph` with the edge generation it was built at
+fn write_graph_snapshot(path: &Path, generation: i64, graph: &BookGraph) -> AppResult<()> {
+    let mut bytes = generation.to_le_bytes().to_vec();
+    bytes.extend(graph.to_bytes()?);
+    let mut partial = path.as_os_str().to_owned();
+    partial.push(".partial");
+    std::fs::write(&partial, bytes)?;
+    std::fs::rename(&partial, path)?;
+    Ok(())
+}
+
+/// Where the recommendation graph of the database at `db_path` is snapshotted
+fn graph_snapshot_path(db_path: &Path) -> PathBuf {
+    let mut path = db_path.as_os_str().to_owned();
+    path.push(".graph");
+    PathBuf::from(path)
+}
+
+/// Where the embedding cache of the database at `db_path` is snapshotted
+fn embedding_snapshot_path(db_path: &Path) -> PathBuf {
+    let mut path = db_path.as_os_str().to_owned();
+    path.push(".vectors");
+    PathBuf::from(path)
+}
+
+/// Path of the app config file (holds settings needed before the database opens)
+pub fn config_path() -> Option<PathBuf> {
+    ::dirs::config_dir().map(|dir| dir.join("epub-graph").join("config.json"))
+}
+
+/// Database location chosen by the user, if any
+pub fn database_path_preference() -> Option<PathBuf> {
+    let content = std::fs::read_to_string(config_path()?).ok()?;
+    let config: serde_json::Value = serde_json::from_str(&content).unwrap_or_default();
+    config.get("database_path").and_then(|v| v.as_str()).map(PathBuf::from)
+}
+
+/// Save the database location used from the next start
+pub fn save_database_path_preference(path: &std::path::Path) -> AppResult<()> {
+    let config_path = config_path()
+        .ok_or_else(|| crate::AppError::Config("Could not determine config directory".to_string()))?;
+    if let Some(parent) = config_path.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+
+    let config = serde_json::json!({
+        "database_path": path.to_string_lossy()
+    });
+    std::fs::write(&config_path, serde_json::to_string_pretty(&config)?)?;
+    Ok(())
 }
 
 // Platform-specific data directory helper
@@ -1,25 +1,226 @@
 //! Vector store module for embedding storage and similarity search
 //!
 //! Uses SQLite for storage with in-memory caching for fast similarity search.
-//! Embeddings are stored as JSON-encoded float arrays.
+//! Embeddings are stored as little-endian float arrays alongside their L2
+//! norm; the cache holds unit vectors so a comparison is one dot product.
 
 use crate::{AppError, AppResult};
 use dashmap::DashMap;
+use hnsw_rs::prelude::{DistCosine, Hnsw};
 use parking_lot::RwLock;
 use rusqlite::{params, Connection};
+use std::collections::HashSet;
+use std::fs::File;
+use std::io::{BufReader, BufWriter, Write};
+use std::path::Path;
 use std::sync::Arc;
 
 /// Dimension of nomic-embed-text embeddings
 pub const EMBEDDING_DIM: usize = 768;
 
+/// Candidate count from which `find_similar` searches the ANN index
+pub const DEFAULT_ANN_THRESHOLD: usize = 20_000;
+
+/// HNSW links per node
+const ANN_MAX_CONNECTIONS: usize = 16;
+/// HNSW layers
+const ANN_MAX_LAYERS: usize = 16;
+/// Candidate list size while inserting
+const ANN_EF_CONSTRUCTION: usize = 200;
+/// Bumped whenever the snapshot layout changes
+const SNAPSHOT_VERSION: u32 = 1;
+
+/// Smallest candidate list size while searching
+const ANN_EF_SEARCH: usize = 64;
+/// Neighbours fetched per requested result, to absorb filtered-out hits
+const ANN_OVERFETCH: usize = 2;
+
+/// Similarity metric used to compare embeddings
+///
+/// Different embedding models are trained for different distance metrics.
+/// Raw scores are used for ranking and then mapped into `[0, 1]` so they can
+/// be stored as graph edge weights.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum SimilarityMetric {
+    /// Cosine of the angle between vectors
+    #[default]
+    Cosine,
+    /// Raw dot product (assumes unit-length embeddings)
+    DotProduct,
+    /// Negated euclidean distance
+    NegativeEuclidean,
+}
+
+impl SimilarityMetric {
+    /// Raw similarity score (higher is more similar)
+    pub fn similarity(&self, a: &[f32], b: &[f32]) -> f64 {
+        match self {
+            SimilarityMetric::Cosine => cosine_similarity(a, b),
+            SimilarityMetric::DotProduct => dot_product(a, b),
+            SimilarityMetric::NegativeEuclidean => -euclidean_distance(a, b),
+        }
+    }
+
+    /// Raw similarity of two embeddings given as unit vectors and lengths
+    ///
+    /// Same as [`similarity`](Self::similarity) on the original vectors
+    /// (up to rounding), but costs a single dot product.
+    pub fn similarity_unit(&self, a: &[f32], a_norm: f64, b: &[f32], b_norm: f64) -> f64 {
+        if a.len() != b.len() {
+            return self.similarity(a, b);
+        }
+        let cosine = dot_product(a, b);
+        match self {
+            SimilarityMetric::Cosine => cosine,
+            SimilarityMetric::DotProduct => cosine * a_norm * b_norm,
+            SimilarityMetric::NegativeEuclidean => {
+                -(a_norm * a_norm + b_norm * b_norm - 2.0 * a_norm * b_norm * cosine).max(0.0).sqrt()
+            }
+        }
+    }
+
+    /// Map a raw score into the `[0, 1]` range expected by edge weights
+    pub fn normalize(&self, raw: f64) -> f64 {
+        match self {
+            SimilarityMetric::Cosine | SimilarityMetric::DotProduct => raw.clamp(0.0, 1.0),
+            SimilarityMetric::NegativeEuclidean => 1.0 / (1.0 - raw.min(0.0)),
+        }
+    }
+
+    /// Settings key for this metric
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            SimilarityMetric::Cosine => "cosine",
+            SimilarityMetric::DotProduct => "dotProduct",
+            SimilarityMetric::NegativeEuclidean => "negativeEuclidean",
+        }
+    }
+}
+
+impl std::str::FromStr for SimilarityMetric {
+    type Err = AppError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "cosine" => Ok(SimilarityMetric::Cosine),
+            "dotProduct" => Ok(SimilarityMetric::DotProduct),
+            "negativeEuclidean" => Ok(SimilarityMetric::NegativeEuclidean),
+            _ => Err(AppError::InvalidInput(format!("Unknown similarity metric: {}", s))),
+        }
+    }
+}
+
+/// Outcome of checking stored embeddings for corrupt rows
+#[derive(Debug, Clone, serde::Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct EmbeddingRepairReport {
+    /// Valid embeddings loaded into the cache
+    pub loaded: usize,
+    /// Books whose stored embedding could not be decoded
+    pub corrupt_book_ids: Vec<i64>,
+    /// Corrupt rows removed from the database
+    pub deleted: usize,
+}
+
+/// A cached embedding: its direction as a unit vector, and its length
+#[derive(Debug, Clone)]
+struct CachedEmbedding {
+    unit: Vec<f32>,
+    norm: f64,
+}
+
+impl CachedEmbedding {
+    fn new(embedding: Vec<f32>) -> Self {
+        let norm = l2_norm(&embedding);
+        Self::with_norm(embedding, norm)
+    }
+
+    /// Cache an embedding whose norm is already known
+    fn with_norm(mut embedding: Vec<f32>, norm: f64) -> Self {
+        if norm > 0.0 {
+            for val in &mut embedding {
+                *val = (*val as f64 / norm) as f32;
+            }
+        }
+        Self { unit: embedding, norm }
+    }
+
+    /// The embedding as stored (up to rounding)
+    fn original(&self) -> Vec<f32> {
+        self.unit.iter().map(|val| (*val as f64 * self.norm) as f32).collect()
+    }
+}
+
+/// On-disk copy of the cache, see `VectorStore::save_cache_snapshot`
+#[derive(serde::Serialize, serde::Deserialize)]
+struct CacheSnapshot {
+    version: u32,
+    model: Option<String>,
+    fingerprint: TableFingerprint,
+    /// Book ID, unit vector and norm of each cached embedding
+    entries: Vec<(i64, Vec<f32>, f64)>,
+}
+
+/// Cheap summary of the embeddings table that changes whenever rows are
+/// added, replaced or removed
+#[derive(Debug, PartialEq, serde::Serialize, serde::Deserialize)]
+struct TableFingerprint {
+    max_created_at: Option<i64>,
+    count: i64,
+}
+
+/// Approximate nearest neighbor index over the cached unit vectors
+///
+/// HNSW can't remove points, so replaced and deleted embeddings stay in the
+/// graph. Searches rescore hits from the cache and skip books it no longer
+/// holds; once too many points are stale the index is dropped and rebuilt
+/// on next use.
+struct AnnIndex {
+    hnsw: Hnsw<'static, f32, DistCosine>,
+    /// Points whose book was since re-embedded or deleted
+    stale: usize,
+}
+
+impl AnnIndex {
+    fn build(cache: &DashMap<i64, CachedEmbedding>) -> Self {
+        let points: Vec<(Vec<f32>, usize)> = cache
+            .iter()
+            .map(|entry| (entry.value().unit.clone(), *entry.key() as usize))
+            .collect();
+        let hnsw = Hnsw::new(
+            ANN_MAX_CONNECTIONS,
+            points.len().max(1),
+            ANN_MAX_LAYERS,
+            ANN_EF_CONSTRUCTION,
+            DistCosine {},
+        );
+        let data: Vec<(&Vec<f32>, usize)> = points.iter().map(|(unit, id)| (unit, *id)).collect();
+        hnsw.parallel_insert(&data);
+        Self { hnsw, stale: 0 }
+    }
+
+    fn is_worn_out(&self) -> bool {
+        self.stale * 5 > self.hnsw.get_nb_point()
+    }
+}
+
 /// Vector store for book embeddings
 pub struct VectorStore {
-    /// In-memory cache of embeddings for fast similarity search
-    cache: DashMap<i64, Vec<f32>>,
+    /// In-memory cache of normalized embeddings for fast similarity search
+    cache: DashMap<i64, CachedEmbedding>,
     /// Database path for persistence
     db_path: String,
     /// Whether cache is fully loaded
     cache_loaded: RwLock<bool>,
+    /// Metric used for similarity search
+    metric: RwLock<SimilarityMetric>,
+    /// Model whose embeddings are served; `None` serves the newest of any model
+    active_model: RwLock<Option<String>>,
+    /// ANN index, built lazily once the library is large enough
+    ann_index: RwLock<Option<AnnIndex>>,
+    /// Candidate count from which the ANN index is used (0 = never)
+    ann_threshold: RwLock<usize>,
 }
 
 impl VectorStore {
@@ -29,6 +230,10 @@ impl VectorStore {
             cache: DashMap::new(),
             db_path: db_path.to_string(),
             cache_loaded: RwLock::new(false),
+            metric: RwLock::new(SimilarityMetric::default()),
+            active_model: RwLock::new(None),
+            ann_index: RwLock::new(None),
+            ann_threshold: RwLock::new(DEFAULT_ANN_THRESHOLD),
         };
 
         // Ensure the embeddings table exists
@@ -41,17 +246,50 @@ impl VectorStore {
     fn init_schema(&self) -> AppResult<()> {
         let conn = Connection::open(&self.db_path)?;
 
-        conn.execute(
-            "CREATE TABLE IF NOT EXISTS embeddings (
-                book_id INTEGER PRIMARY KEY,
-                embedding BLOB NOT NULL,
-                model TEXT NOT NULL,
-                text_hash TEXT,
-                created_at INTEGER NOT NULL DEFAULT (strftime('%s', 'now')),
-                FOREIGN KEY (book_id) REFERENCES books(id) ON DELETE CASCADE
-            )",
+        conn.execute(&embeddings_table_sql("embeddings"), [])?;
+
+        // Older databases predate text versioning; their rows were built with
+        // the first text format
+        if conn.prepare("SELECT text_version FROM embeddings LIMIT 0").is_err() {
+            conn.execute(
+                "ALTER TABLE embeddings ADD COLUMN text_version INTEGER NOT NULL DEFAULT 1",
+                [],
+            )?;
+        }
+
+        // Norms were added later; rows without one get it computed on load
+        if conn.prepare("SELECT norm FROM embeddings LIMIT 0").is_err() {
+            conn.execute("ALTER TABLE embeddings ADD COLUMN norm REAL", [])?;
+        }
+
+        // Older databases keyed embeddings by book alone, so a second model
+        // overwrote the first; rebuild the table keyed by (book_id, model)
+        let key_columns: i64 = conn.query_row(
+            "SELECT COUNT(*) FROM pragma_table_info('embeddings') WHERE pk > 0",
             [],
+            |row| row.get(0),
         )?;
+        if key_columns == 1 {
+            let mut conn = conn;
+            let tx = conn.transaction()?;
+            tx.execute(&embeddings_table_sql("embeddings_by_model"), [])?;
+            tx.execute(
+                "INSERT INTO embeddings_by_model
+                     (book_id, embedding, model, text_hash, text_version, created_at)
+                 SELECT book_id, embedding, model, text_hash, text_version, created_at
+                 FROM embeddings",
+                [],
+            )?;
+            tx.execute("DROP TABLE embeddings", [])?;
+            tx.execute("ALTER TABLE embeddings_by_model RENAME TO embeddings", [])?;
+            tx.execute(
+                "CREATE INDEX IF NOT EXISTS idx_embeddings_model ON embeddings(model)",
+                [],
+            )?;
+            tx.commit()?;
+            tracing::info!("Migrated embeddings to per-model storage");
+            return Ok(());
+        }
 
         conn.execute(
             "CREATE INDEX IF NOT EXISTS idx_embeddings_model ON embeddings(model)",
@@ -61,39 +299,229 @@ impl VectorStore {
         Ok(())
     }
 
+    /// Get the active similarity metric
+    pub fn metric(&self) -> SimilarityMetric {
+        *self.metric.read()
+    }
+
+    /// Change the similarity metric used by `find_similar`
+    pub fn set_metric(&self, metric: SimilarityMetric) {
+        *self.metric.write() = metric;
+    }
+
+    /// Get the candidate count from which similarity search is approximate
+    pub fn ann_threshold(&self) -> usize {
+        *self.ann_threshold.read()
+    }
+
+    /// Search the ANN index once at least `threshold` books are candidates
+    ///
+    /// Smaller searches stay exact; 0 always searches exactly.
+    pub fn set_ann_threshold(&self, threshold: usize) {
+        *self.ann_threshold.write() = threshold;
+    }
+
+    /// Get the model whose embeddings are served
+    pub fn active_model(&self) -> Option<String> {
+        self.active_model.read().clone()
+    }
+
+    /// Serve embeddings from `model` only
+    ///
+    /// Embeddings from other models stay stored, so switching back doesn't
+    /// require re-embedding. The cache is dropped and reloaded lazily.
+    pub fn set_active_model(&self, model: &str) {
+        let mut active = self.active_model.write();
+        if active.as_deref() == Some(model) {
+            return;
+        }
+        *active = Some(model.to_string());
+        self.cache.clear();
+        *self.ann_index.write() = None;
+        *self.cache_loaded.write() = false;
+    }
+
+    /// Whether embeddings from `model` belong in the cache
+    fn serves(&self, model: &str) -> bool {
+        self.active_model.read().as_deref().map_or(true, |active| active == model)
+    }
+
     /// Load all embeddings into cache
+    ///
+    /// Corrupt rows are requeued for regeneration, see `repair_embeddings`.
     pub fn load_cache(&self) -> AppResult<usize> {
-        let conn = Connection::open(&self.db_path)?;
+        Ok(self.repair_embeddings(false)?.loaded)
+    }
 
-        let mut stmt = conn.prepare("SELECT book_id, embedding FROM embeddings")?;
-        let rows = stmt.query_map([], |row| {
-            let book_id: i64 = row.get(0)?;
-            let embedding_blob: Vec<u8> = row.get(1)?;
-            Ok((book_id, embedding_blob))
-        })?;
+    /// Load valid embeddings into cache and requeue books whose stored blob is corrupt
+    ///
+    /// Corrupt rows are either deleted or marked stale (text version 0) so
+    /// they are never served, and the owning book is reset to `pending`.
+    pub fn repair_embeddings(&self, delete_corrupt: bool) -> AppResult<EmbeddingRepairReport> {
+        let mut conn = Connection::open(&self.db_path)?;
+
+        let model = self.active_model();
+        let rows: Vec<(i64, Vec<u8>, Option<f64>)> = conn
+            .prepare(
+                "SELECT book_id, embedding, norm FROM embeddings
+                 WHERE ?1 IS NULL OR model = ?1
+                 ORDER BY created_at",
+            )?
+            .query_map([&model], |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)))?
+            .collect::<Result<_, _>>()?;
+
+        let mut loaded = 0;
+        let mut corrupt_book_ids = Vec::new();
+        for (book_id, blob, norm) in rows {
+            match deserialize_embedding(&blob) {
+                Ok(embedding) if embedding.len() == EMBEDDING_DIM => {
+                    let cached = match norm {
+                        Some(norm) => CachedEmbedding::with_norm(embedding, norm),
+                        None => CachedEmbedding::new(embedding),
+                    };
+                    self.cache.insert(book_id, cached);
+                    loaded += 1;
+                }
+                _ => {
+                    self.cache.remove(&book_id);
+                    corrupt_book_ids.push(book_id);
+                }
+            }
+        }
+
+        if !corrupt_book_ids.is_empty() {
+            tracing::warn!(
+                "Found {} corrupt embeddings, requeueing books {:?}",
+                corrupt_book_ids.len(),
+                corrupt_book_ids
+            );
 
-        let mut count = 0;
-        for row in rows {
-            let (book_id, blob) = row?;
-            if let Ok(embedding) = deserialize_embedding(&blob) {
-                self.cache.insert(book_id, embedding);
-                count += 1;
+            let tx = conn.transaction()?;
+            for book_id in &corrupt_book_ids {
+                if delete_corrupt {
+                    tx.execute(
+                        "DELETE FROM embeddings WHERE book_id = ?1 AND (?2 IS NULL OR model = ?2)",
+                        params![book_id, model],
+                    )?;
+                } else {
+                    tx.execute(
+                        "UPDATE embeddings SET text_version = 0
+                         WHERE book_id = ?1 AND (?2 IS NULL OR model = ?2)",
+                        params![book_id, model],
+                    )?;
+                }
+                tx.execute(
+                    "UPDATE books SET embedding_status = 'pending' WHERE id = ?",
+                    [book_id],
+                )?;
             }
+            tx.commit()?;
         }
 
         *self.cache_loaded.write() = true;
-        tracing::info!("Loaded {} embeddings into cache", count);
+        *self.ann_index.write() = None;
+        tracing::info!("Loaded {} embeddings into cache", loaded);
 
-        Ok(count)
+        Ok(EmbeddingRepairReport {
+            loaded,
+            deleted: if delete_corrupt { corrupt_book_ids.len() } else { 0 },
+            corrupt_book_ids,
+        })
+    }
+
+    /// Fingerprint of the embeddings served under the active model
+    fn table_fingerprint(&self, conn: &Connection) -> AppResult<TableFingerprint> {
+        Ok(conn.query_row(
+            "SELECT MAX(created_at), COUNT(*) FROM embeddings WHERE ?1 IS NULL OR model = ?1",
+            [self.active_model()],
+            |row| {
+                Ok(TableFingerprint {
+                    max_created_at: row.get(0)?,
+                    count: row.get(1)?,
+                })
+            },
+        )?)
+    }
+
+    /// Write the loaded cache to `path` so the next start can skip the database
+    ///
+    /// Nothing is written until the cache is fully loaded. Returns the number
+    /// of embeddings saved.
+    pub fn save_cache_snapshot(&self, path: &Path) -> AppResult<usize> {
+        if !*self.cache_loaded.read() {
+            return Ok(0);
+        }
+
+        let conn = Connection::open(&self.db_path)?;
+        let snapshot = CacheSnapshot {
+            version: SNAPSHOT_VERSION,
+            model: self.active_model(),
+            fingerprint: self.table_fingerprint(&conn)?,
+            entries: self
+                .cache
+                .iter()
+                .map(|entry| (*entry.key(), entry.value().unit.clone(), entry.value().norm))
+                .collect(),
+        };
+
+        // Write aside and rename so a crash never leaves a torn snapshot
+        let mut partial = path.as_os_str().to_owned();
+        partial.push(".partial");
+        let mut writer = BufWriter::new(File::create(&partial)?);
+        bincode::serialize_into(&mut writer, &snapshot).map_err(snapshot_error)?;
+        writer.flush()?;
+        drop(writer);
+        std::fs::rename(&partial, path)?;
+
+        tracing::info!("Saved {} cached embeddings to {:?}", snapshot.entries.len(), path);
+        Ok(snapshot.entries.len())
+    }
+
+    /// Fill the cache from a snapshot at `path` if it still matches the database
+    ///
+    /// Returns `false`, leaving the cache alone, when there is no snapshot or
+    /// it was taken for another model or before the embeddings changed; the
+    /// caller then falls back to `load_cache`.
+    pub fn try_load_snapshot(&self, path: &Path) -> AppResult<bool> {
+        let file = match File::open(path) {
+            Ok(file) => file,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(false),
+            Err(e) => return Err(e.into()),
+        };
+        let snapshot: CacheSnapshot =
+            bincode::deserialize_from(BufReader::new(file)).map_err(snapshot_error)?;
+
+        let conn = Connection::open(&self.db_path)?;
+        if snapshot.version != SNAPSHOT_VERSION
+            || snapshot.model != self.active_model()
+            || snapshot.fingerprint != self.table_fingerprint(&conn)?
+        {
+            tracing::info!("Embedding snapshot {:?} is out of date", path);
+            return Ok(false);
+        }
+
+        self.cache.clear();
+        for (book_id, unit, norm) in snapshot.entries {
+            self.cache.insert(book_id, CachedEmbedding { unit, norm });
+        }
+        *self.cache_loaded.write() = true;
+        *self.ann_index.write() = None;
+        tracing::info!("Loaded {} embeddings from snapshot", self.cache.len());
+        Ok(true)
     }
 
     /// Store an embedding for a book
+    ///
+    /// Replaces any earlier embedding of the book by the same model; other
+    /// models' embeddings are kept. The vector is stored as given, with its
+    /// norm; the cache keeps it normalized.
     pub fn store_embedding(
         &self,
         book_id: i64,
         embedding: &[f32],
         model: &str,
         text_hash: Option<&str>,
+        text_version: i64,
     ) -> AppResult<()> {
         if embedding.len() != EMBEDDING_DIM {
             return Err(AppError::InvalidInput(format!(
@@ -105,92 +533,262 @@ impl VectorStore {
 
         let conn = Connection::open(&self.db_path)?;
         let blob = serialize_embedding(embedding);
+        let norm = l2_norm(embedding);
 
         conn.execute(
-            "INSERT OR REPLACE INTO embeddings (book_id, embedding, model, text_hash)
-             VALUES (?, ?, ?, ?)",
-            params![book_id, blob, model, text_hash],
+            "INSERT OR REPLACE INTO embeddings (book_id, embedding, model, text_hash, text_version, norm)
+             VALUES (?, ?, ?, ?, ?, ?)",
+            params![book_id, blob, model, text_hash, text_version, norm],
         )?;
 
         // Update cache
-        self.cache.insert(book_id, embedding.to_vec());
+        if self.serves(model) {
+            let cached = CachedEmbedding::with_norm(embedding.to_vec(), norm);
+            let mut ann_index = self.ann_index.write();
+            if let Some(index) = ann_index.as_mut() {
+                index.hnsw.insert((&cached.unit, book_id as usize));
+            }
+            if self.cache.insert(book_id, cached).is_some() {
+                self.mark_ann_stale(&mut ann_index, 1);
+            }
+        }
 
         Ok(())
     }
 
-    /// Get embedding for a book
+    /// Get embedding for a book from the active model
+    ///
+    /// Returns the vector as it was stored, not normalized.
     pub fn get_embedding(&self, book_id: i64) -> Option<Vec<f32>> {
         // Check cache first
-        if let Some(embedding) = self.cache.get(&book_id) {
-            return Some(embedding.clone());
+        if let Some(cached) = self.cache.get(&book_id) {
+            return Some(cached.original());
         }
 
-        // Load from database
+        let model = self.active_model();
+        let embedding = self.load_embedding(book_id, model.as_deref())?;
+        self.cache.insert(book_id, CachedEmbedding::new(embedding.clone()));
+
+        Some(embedding)
+    }
+
+    /// Get embedding for a book from a specific model, bypassing the cache
+    pub fn get_embedding_for_model(&self, book_id: i64, model: &str) -> Option<Vec<f32>> {
+        self.load_embedding(book_id, Some(model))
+    }
+
+    /// Read the newest embedding of a book, optionally restricted to `model`
+    fn load_embedding(&self, book_id: i64, model: Option<&str>) -> Option<Vec<f32>> {
         let conn = Connection::open(&self.db_path).ok()?;
         let blob: Vec<u8> = conn
             .query_row(
-                "SELECT embedding FROM embeddings WHERE book_id = ?",
-                [book_id],
+                "SELECT embedding FROM embeddings
+                 WHERE book_id = ?1 AND (?2 IS NULL OR model = ?2)
+                 ORDER BY created_at DESC LIMIT 1",
+                params![book_id, model],
                 |row| row.get(0),
             )
             .ok()?;
 
-        let embedding = deserialize_embedding(&blob).ok()?;
-        self.cache.insert(book_id, embedding.clone());
-
-        Some(embedding)
+        deserialize_embedding(&blob).ok()
     }
 
     /// Delete embedding for a book
     pub fn delete_embedding(&self, book_id: i64) -> AppResult<()> {
         let conn = Connection::open(&self.db_path)?;
         conn.execute("DELETE FROM embeddings WHERE book_id = ?", [book_id])?;
-        self.cache.remove(&book_id);
+        if self.cache.remove(&book_id).is_some() {
+            self.mark_ann_stale(&mut self.ann_index.write(), 1);
+        }
         Ok(())
     }
 
-    /// Find k nearest neighbors by cosine similarity
+    /// Delete embeddings for several books in one transaction
+    pub fn delete_embeddings(&self, book_ids: &[i64]) -> AppResult<i64> {
+        let mut conn = Connection::open(&self.db_path)?;
+        let tx = conn.transaction()?;
+
+        let mut deleted = 0;
+        {
+            let mut stmt = tx.prepare("DELETE FROM embeddings WHERE book_id = ?")?;
+            for book_id in book_ids {
+                deleted += stmt.execute([book_id])? as i64;
+            }
+        }
+        tx.commit()?;
+
+        let removed = book_ids.iter().filter(|id| self.cache.remove(id).is_some()).count();
+        self.mark_ann_stale(&mut self.ann_index.write(), removed);
+        Ok(deleted)
+    }
+
+    /// Count points of the ANN index as stale, dropping it when worn out
+    fn mark_ann_stale(&self, ann_index: &mut Option<AnnIndex>, count: usize) {
+        if let Some(index) = ann_index.as_mut() {
+            index.stale += count;
+            if index.is_worn_out() {
+                *ann_index = None;
+            }
+        }
+    }
+
+    /// Build the approximate nearest neighbor index from the cache
+    ///
+    /// `find_similar` builds it on first use once the library passes the ANN
+    /// threshold; call this to pay that cost up front. Returns the number of
+    /// indexed embeddings.
+    pub fn build_ann_index(&self) -> usize {
+        if !*self.cache_loaded.read() {
+            let _ = self.load_cache();
+        }
+
+        let mut ann_index = self.ann_index.write();
+        let index = AnnIndex::build(&self.cache);
+        let points = index.hnsw.get_nb_point();
+        *ann_index = Some(index);
+        tracing::info!("Built ANN index over {} embeddings", points);
+        points
+    }
+
+    /// Find k nearest neighbors using the active similarity metric
+    ///
+    /// Returned scores are normalized into `[0, 1]`. With the cosine metric
+    /// and at least `ann_threshold` candidates the search goes through the
+    /// ANN index and may miss some true neighbours.
     pub fn find_similar(&self, query_embedding: &[f32], k: usize, exclude_ids: &[i64]) -> Vec<(i64, f64)> {
+        self.find_similar_in(query_embedding, k, exclude_ids, None)
+    }
+
+    /// Find k nearest neighbors among `allowed` books only
+    ///
+    /// Restricting the candidates up front (e.g. to IDs from
+    /// `Database::query_book_ids`) both skips scoring the rest and still
+    /// returns a full k when enough allowed books exist. `None` searches
+    /// every embedding.
+    pub fn find_similar_in(
+        &self,
+        query_embedding: &[f32],
+        k: usize,
+        exclude_ids: &[i64],
+        allowed: Option<&HashSet<i64>>,
+    ) -> Vec<(i64, f64)> {
         // Ensure cache is loaded
         if !*self.cache_loaded.read() {
             let _ = self.load_cache();
         }
 
-        let mut similarities: Vec<(i64, f64)> = self
-            .cache
-            .iter()
-            .filter(|entry| !exclude_ids.contains(entry.key()))
-            .map(|entry| {
-                let book_id = *entry.key();
-                let similarity = cosine_similarity(query_embedding, entry.value());
-                (book_id, similarity)
-            })
-            .collect();
+        let metric = self.metric();
+        let query = CachedEmbedding::new(query_embedding.to_vec());
+        let score = |book_id: i64, cached: &CachedEmbedding| {
+            (book_id, metric.similarity_unit(&query.unit, query.norm, &cached.unit, cached.norm))
+        };
+
+        let candidates = allowed.map_or(self.cache.len(), |allowed| allowed.len());
+        let approximate = (metric == SimilarityMetric::Cosine && self.uses_ann(candidates))
+            .then(|| self.find_similar_approx(&query, k, exclude_ids, allowed, score))
+            .flatten();
 
-        // Sort by similarity descending
-        similarities.sort_by(|a, b| b.1.partial_cmp(&a.1).unwrap_or(std::cmp::Ordering::Equal));
+        let mut similarities: Vec<(i64, f64)> = match (approximate, allowed) {
+            (Some(similarities), _) => similarities,
+            (None, Some(allowed)) => allowed
+                .iter()
+                .filter(|id| !exclude_ids.contains(id))
+                .filter_map(|&id| self.cache.get(&id).map(|entry| score(id, entry.value())))
+                .collect(),
+            (None, None) => self
+                .cache
+                .iter()
+                .filter(|entry| !exclude_ids.contains(entry.key()))
+                .map(|entry| score(*entry.key(), entry.value()))
+                .collect(),
+        };
+
+        // Sort by raw similarity descending, ties by book id
+        similarities.sort_by(|a, b| b.1.partial_cmp(&a.1).unwrap_or(std::cmp::Ordering::Equal).then(a.0.cmp(&b.0)));
 
         // Take top k
         similarities.truncate(k);
+        for (_, similarity) in &mut similarities {
+            *similarity = metric.normalize(*similarity);
+        }
         similarities
     }
 
+    /// Whether a search over `candidates` books should use the ANN index
+    fn uses_ann(&self, candidates: usize) -> bool {
+        let threshold = self.ann_threshold();
+        threshold > 0 && candidates >= threshold
+    }
+
+    /// Score the ANN index's nearest hits, building the index if needed
+    ///
+    /// Returns `None` when filtering left fewer than k hits, so the caller
+    /// falls back to an exact scan.
+    fn find_similar_approx(
+        &self,
+        query: &CachedEmbedding,
+        k: usize,
+        exclude_ids: &[i64],
+        allowed: Option<&HashSet<i64>>,
+        score: impl Fn(i64, &CachedEmbedding) -> (i64, f64),
+    ) -> Option<Vec<(i64, f64)>> {
+        if self.ann_index.read().is_none() {
+            self.build_ann_index();
+        }
+        let ann_index = self.ann_index.read();
+        let index = ann_index.as_ref()?;
+
+        // Fetch more when the allowed set only covers part of the library
+        let total = self.cache.len().max(1);
+        let candidates = allowed.map_or(total, |allowed| allowed.len()).max(1);
+        let fetch = ((k + exclude_ids.len()) * ANN_OVERFETCH * total / candidates).min(total);
+        let neighbours = index.hnsw.search(&query.unit, fetch, fetch.max(ANN_EF_SEARCH));
+
+        let mut seen = HashSet::new();
+        let similarities: Vec<(i64, f64)> = neighbours
+            .into_iter()
+            .map(|neighbour| neighbour.d_id as i64)
+            .filter(|id| seen.insert(*id))
+            .filter(|id| !exclude_ids.contains(id) && allowed.map_or(true, |allowed| allowed.contains(id)))
+            .filter_map(|id| self.cache.get(&id).map(|entry| score(id, entry.value())))
+            .collect();
+
+        (similarities.len() >= k).then_some(similarities)
+    }
+
     /// Find books similar to a given book
     pub fn find_similar_to_book(&self, book_id: i64, k: usize) -> Vec<(i64, f64)> {
+        self.find_similar_to_book_in(book_id, k, None)
+    }
+
+    /// Find books similar to a given book among `allowed` books only
+    pub fn find_similar_to_book_in(&self, book_id: i64, k: usize, allowed: Option<&HashSet<i64>>) -> Vec<(i64, f64)> {
         if let Some(embedding) = self.get_embedding(book_id) {
-            self.find_similar(&embedding, k, &[book_id])
+            self.find_similar_in(&embedding, k, &[book_id], allowed)
         } else {
             vec![]
         }
     }
 
-    /// Get count of stored embeddings
+    /// Get count of stored embeddings across all models
     pub fn count(&self) -> AppResult<i64> {
         let conn = Connection::open(&self.db_path)?;
         let count: i64 = conn.query_row("SELECT COUNT(*) FROM embeddings", [], |row| row.get(0))?;
         Ok(count)
     }
 
+    /// Get count of stored embeddings per model, largest first
+    pub fn count_by_model(&self) -> AppResult<Vec<(String, i64)>> {
+        let conn = Connection::open(&self.db_path)?;
+        let mut stmt = conn.prepare(
+            "SELECT model, COUNT(*) FROM embeddings GROUP BY model ORDER BY COUNT(*) DESC, model",
+        )?;
+        let counts = stmt
+            .query_map([], |row| Ok((row.get(0)?, row.get(1)?)))?
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(counts)
+    }
+
     /// Check if book has embedding
     pub fn has_embedding(&self, book_id: i64) -> bool {
         if self.cache.contains_key(&book_id) {
@@ -199,8 +797,8 @@ impl VectorStore {
 
         if let Ok(conn) = Connection::open(&self.db_path) {
             let result: Result<i64, _> = conn.query_row(
-                "SELECT 1 FROM embeddings WHERE book_id = ?",
-                [book_id],
+                "SELECT 1 FROM embeddings WHERE book_id = ?1 AND (?2 IS NULL OR model = ?2)",
+                params![book_id, self.active_model()],
                 |row| row.get(0),
             );
             result.is_ok()
@@ -209,16 +807,104 @@ impl VectorStore {
         }
     }
 
+    /// Check if a book's embedding was built from an older text format
+    pub fn is_stale(&self, book_id: i64, current_version: i64) -> bool {
+        Connection::open(&self.db_path)
+            .and_then(|conn| {
+                conn.query_row(
+                    "SELECT text_version FROM embeddings
+                     WHERE book_id = ?1 AND (?2 IS NULL OR model = ?2)
+                     ORDER BY created_at DESC LIMIT 1",
+                    params![book_id, self.active_model()],
+                    |row| row.get::<_, i64>(0),
+                )
+            })
+            .map(|version| version != current_version)
+            .unwrap_or(false)
+    }
+
+    /// Get IDs of books whose embeddings were built from an older text format
+    pub fn stale_embedding_ids(&self, current_version: i64) -> AppResult<Vec<i64>> {
+        let conn = Connection::open(&self.db_path)?;
+        let mut stmt = conn.prepare(
+            "SELECT DISTINCT book_id FROM embeddings
+             WHERE text_version != ?1 AND (?2 IS NULL OR model = ?2)",
+        )?;
+        let ids = stmt
+            .query_map(params![current_version, self.active_model()], |row| row.get(0))?
+            .collect::<Result<Vec<i64>, _>>()?;
+        Ok(ids)
+    }
+
+    /// Mark every stored embedding stale so it is regenerated
+    pub fn mark_all_stale(&self) -> AppResult<usize> {
+        let conn = Connection::open(&self.db_path)?;
+        Ok(conn.execute("UPDATE embeddings SET text_version = 0", [])?)
+    }
+
     /// Clear all embeddings from the database and cache
     pub fn clear_all(&self) -> AppResult<i64> {
         let conn = Connection::open(&self.db_path)?;
         let count: i64 = conn.query_row("SELECT COUNT(*) FROM embeddings", [], |row| row.get(0))?;
         conn.execute("DELETE FROM embeddings", [])?;
         self.cache.clear();
+        *self.ann_index.write() = None;
         tracing::info!("Cleared {} embeddings", count);
         Ok(count)
     }
 
+    /// Write all valid embeddings of the active model to `path` in a compact binary format
+    ///
+    /// Byte layout (all little-endian):
+    ///
+    /// | offset | size      | field                          |
+    /// |--------|-----------|--------------------------------|
+    /// | 0      | 4         | record count `n` (u32)         |
+    /// | 4      | 4         | dimensions `d` (u32)           |
+    /// | 8      | n * (8+4d)| records: book ID (i64), then `d` f32 values |
+    ///
+    /// Records are ordered by book ID. A JSON sidecar at `<path>.json` lists
+    /// `{ "bookId", "model" }` for each record in the same order. Returns the
+    /// number of records written.
+    pub fn export_embeddings(&self, path: &Path) -> AppResult<usize> {
+        let conn = Connection::open(&self.db_path)?;
+        let rows: Vec<(i64, Vec<u8>, String)> = conn
+            .prepare(
+                "SELECT book_id, embedding, model FROM embeddings
+                 WHERE ?1 IS NULL OR model = ?1
+                 ORDER BY book_id",
+            )?
+            .query_map([self.active_model()], |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)))?
+            .collect::<Result<_, _>>()?;
+
+        let records: Vec<(i64, Vec<f32>, String)> = rows
+            .into_iter()
+            .filter_map(|(book_id, blob, model)| {
+                let embedding = deserialize_embedding(&blob).ok()?;
+                (embedding.len() == EMBEDDING_DIM).then_some((book_id, embedding, model))
+            })
+            .collect();
+
+        let mut writer = BufWriter::new(File::create(path)?);
+        writer.write_all(&(records.len() as u32).to_le_bytes())?;
+        writer.write_all(&(EMBEDDING_DIM as u32).to_le_bytes())?;
+        for (book_id, embedding, _) in &records {
+            writer.write_all(&book_id.to_le_bytes())?;
+            writer.write_all(&serialize_embedding(embedding))?;
+        }
+        writer.flush()?;
+
+        let sidecar: Vec<serde_json::Value> = records
+            .iter()
+            .map(|(book_id, _, model)| serde_json::json!({ "bookId": book_id, "model": model }))
+            .collect();
+        let mut sidecar_path = path.as_os_str().to_owned();
+        sidecar_path.push(".json");
+        std::fs::write(sidecar_path, serde_json::to_vec_pretty(&sidecar)?)?;
+
+        Ok(records.len())
+    }
+
     /// Compute average embedding for multiple books (for user profile)
     pub fn compute_average_embedding(&self, book_ids: &[i64]) -> Option<Vec<f32>> {
         let embeddings: Vec<Vec<f32>> = book_ids
@@ -254,6 +940,29 @@ impl VectorStore {
     }
 }
 
+/// DDL for the embeddings table under `name`, keyed by book and model
+fn embeddings_table_sql(name: &str) -> String {
+    format!(
+        "CREATE TABLE IF NOT EXISTS {} (
+            book_id INTEGER NOT NULL,
+            embedding BLOB NOT NULL,
+            model TEXT NOT NULL,
+            text_hash TEXT,
+            text_version INTEGER NOT NULL DEFAULT 1,
+            created_at INTEGER NOT NULL DEFAULT (strftime('%s', 'now')),
+            norm REAL,
+            PRIMARY KEY (book_id, model),
+            FOREIGN KEY (book_id) REFERENCES books(id) ON DELETE CASCADE
+        )",
+        name
+    )
+}
+
+/// Wrap a snapshot (de)serialization failure as an IO error
+fn snapshot_error(e: bincode::Error) -> AppError {
+    AppError::Io(std::io::Error::new(std::io::ErrorKind::InvalidData, e))
+}
+
 /// Compute cosine similarity between two vectors
 pub fn cosine_similarity(a: &[f32], b: &[f32]) -> f64 {
     if a.len() != b.len() {
@@ -280,6 +989,63 @@ pub fn cosine_similarity(a: &[f32], b: &[f32]) -> f64 {
     }
 }
 
+/// Euclidean length of a vector
+fn l2_norm(v: &[f32]) -> f64 {
+    v.iter().map(|x| *x as f64 * *x as f64).sum::<f64>().sqrt()
+}
+
+/// Compute the dot product of two vectors
+pub fn dot_product(a: &[f32], b: &[f32]) -> f64 {
+    if a.len() != b.len() {
+        return 0.0;
+    }
+
+    a.iter().zip(b.iter()).map(|(x, y)| *x as f64 * *y as f64).sum()
+}
+
+/// Compute the euclidean distance between two vectors
+pub fn euclidean_distance(a: &[f32], b: &[f32]) -> f64 {
+    if a.len() != b.len() {
+        return f64::INFINITY;
+    }
+
+    a.iter()
+        .zip(b.iter())
+        .map(|(x, y)| {
+            let d = *x as f64 - *y as f64;
+            d * d
+        })
+        .sum::<f64>()
+        .sqrt()
+}
+
+/// Read a file written by `VectorStore::export_embeddings`
+pub fn read_embeddings_export(path: &Path) -> AppResult<Vec<(i64, Vec<f32>)>> {
+    let bytes = std::fs::read(path)?;
+    let invalid = || AppError::InvalidInput("Truncated embeddings export".to_string());
+
+    let header = |at: usize| -> AppResult<usize> {
+        let field: [u8; 4] = bytes.get(at..at + 4).and_then(|b| b.try_into().ok()).ok_or_else(invalid)?;
+        Ok(u32::from_le_bytes(field) as usize)
+    };
+    let count = header(0)?;
+    let dim = header(4)?;
+
+    // The header is untrusted; check it against the file before sizing anything
+    let record_len = dim.checked_mul(4).and_then(|n| n.checked_add(8)).ok_or_else(invalid)?;
+    let body = &bytes[8..];
+    if count.checked_mul(record_len) != Some(body.len()) {
+        return Err(invalid());
+    }
+
+    body.chunks(record_len)
+        .map(|record| {
+            let id: [u8; 8] = record[..8].try_into().map_err(|_| invalid())?;
+            Ok((i64::from_le_bytes(id), deserialize_embedding(&record[8..])?))
+        })
+        .collect()
+}
+
 /// Serialize embedding to bytes (little-endian f32 array)
 fn serialize_embedding(embedding: &[f32]) -> Vec<u8> {
     let mut bytes = Vec::with_capacity(embedding.len() * 4);
@@ -326,6 +1092,480 @@ mod tests {
         assert!((cosine_similarity(&a, &d) + 1.0).abs() < 1e-6);
     }
 
+    #[test]
+    fn test_metrics_rank_nearest_first() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let store = VectorStore::new(dir.path().join("test.db").to_str().unwrap()).unwrap();
+        *store.cache_loaded.write() = true;
+
+        // Unit vectors: book 2 is closest to the query, book 4 is opposite
+        let query = vec![1.0f32, 0.0, 0.0];
+        store.cache.insert(2, CachedEmbedding::new(vec![0.96, 0.28, 0.0]));
+        store.cache.insert(3, CachedEmbedding::new(vec![0.6, 0.8, 0.0]));
+        store.cache.insert(4, CachedEmbedding::new(vec![-1.0, 0.0, 0.0]));
+
+        for metric in [
+            SimilarityMetric::Cosine,
+            SimilarityMetric::DotProduct,
+            SimilarityMetric::NegativeEuclidean,
+        ] {
+            store.set_metric(metric);
+            let results = store.find_similar(&query, 3, &[]);
+            let ids: Vec<i64> = results.iter().map(|r| r.0).collect();
+            assert_eq!(ids, vec![2, 3, 4], "{:?}", metric);
+            assert!(results.iter().all(|r| (0.0..=1.0).contains(&r.1)), "{:?}", metric);
+        }
+    }
+
+    #[test]
+    fn test_cache_is_normalized_but_originals_are_returned() {
+        let db = crate::db::Database::new_in_memory().unwrap();
+        db.with_conn(|conn| {
+            conn.execute_batch(
+                "INSERT INTO books (id, path, title) VALUES (1, '/b/a.epub', 'A'), (2, '/b/b.epub', 'B')"
+            )?;
+            Ok(())
+        })
+        .unwrap();
+        let store = VectorStore::new(db.path()).unwrap();
+        let mut long = vec![0.0f32; EMBEDDING_DIM];
+        long[0] = 3.0;
+        long[1] = 4.0;
+        let mut short = vec![0.0f32; EMBEDDING_DIM];
+        short[0] = 0.5;
+        store.store_embedding(1, &long, "test", None, 1).unwrap();
+        store.store_embedding(2, &short, "test", None, 1).unwrap();
+
+        assert!((store.cache.get(&1).unwrap().unit[0] - 0.6).abs() < 1e-6);
+        let norm: f64 = Connection::open(db.path())
+            .unwrap()
+            .query_row("SELECT norm FROM embeddings WHERE book_id = 1", [], |row| row.get(0))
+            .unwrap();
+        assert_eq!(norm, 5.0);
+
+        // Reloading uses the stored norm and still hands back the original
+        let reloaded = VectorStore::new(db.path()).unwrap();
+        assert_eq!(reloaded.load_cache().unwrap(), 2);
+        for store in [&store, &reloaded] {
+            let restored = store.get_embedding(1).unwrap();
+            assert!(restored.iter().zip(&long).all(|(a, b)| (a - b).abs() < 1e-6));
+        }
+
+        // Each metric scores against the original lengths
+        let mut query = vec![0.0f32; EMBEDDING_DIM];
+        query[0] = 2.0;
+        for metric in [
+            SimilarityMetric::Cosine,
+            SimilarityMetric::DotProduct,
+            SimilarityMetric::NegativeEuclidean,
+        ] {
+            reloaded.set_metric(metric);
+            for (id, score) in reloaded.find_similar(&query, 2, &[]) {
+                let original = if id == 1 { &long } else { &short };
+                let expected = metric.normalize(metric.similarity(&query, original));
+                assert!((score - expected).abs() < 1e-6, "{:?} {}: {} vs {}", metric, id, score, expected);
+            }
+        }
+    }
+
+    #[test]
+    fn test_euclidean_normalization() {
+        let metric = SimilarityMetric::NegativeEuclidean;
+        assert_eq!(metric.normalize(0.0), 1.0);
+        assert!((metric.normalize(-1.0) - 0.5).abs() < 1e-9);
+        assert_eq!("negativeEuclidean".parse::<SimilarityMetric>().unwrap(), metric);
+    }
+
+    #[test]
+    fn test_version_bump_marks_embeddings_stale() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let db_path = dir.path().join("test.db");
+        let db = crate::db::Database::new(&db_path).unwrap();
+        db.with_conn(|conn| {
+            conn.execute("INSERT INTO books (id, path, title) VALUES (1, '/books/a.epub', 'A')", [])?;
+            Ok(())
+        })
+        .unwrap();
+        let store = VectorStore::new(db_path.to_str().unwrap()).unwrap();
+        let embedding = vec![0.1f32; EMBEDDING_DIM];
+
+        store.store_embedding(1, &embedding, "test-model", Some("abc"), 1).unwrap();
+        assert!(!store.is_stale(1, 1));
+        assert!(store.stale_embedding_ids(1).unwrap().is_empty());
+
+        // Bumping the text version invalidates existing embeddings
+        assert!(store.is_stale(1, 2));
+        assert_eq!(store.stale_embedding_ids(2).unwrap(), vec![1]);
+
+        // Re-embedding with the new version clears the stale flag
+        store.store_embedding(1, &embedding, "test-model", Some("def"), 2).unwrap();
+        assert!(store.stale_embedding_ids(2).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_corrupt_embedding_requeues_book() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let db_path = dir.path().join("test.db");
+        let db = crate::db::Database::new(&db_path).unwrap();
+        db.with_conn(|conn| {
+            conn.execute(
+                "INSERT INTO books (id, path, title, embedding_status)
+                 VALUES (1, '/books/a.epub', 'A', 'complete'), (2, '/books/b.epub', 'B', 'complete')",
+                [],
+            )?;
+            Ok(())
+        })
+        .unwrap();
+        let store = VectorStore::new(db_path.to_str().unwrap()).unwrap();
+        store.store_embedding(1, &vec![0.1f32; EMBEDDING_DIM], "test-model", None, 1).unwrap();
+
+        // Truncated blob, as left behind by a crash mid-write
+        let conn = Connection::open(&db_path).unwrap();
+        conn.execute(
+            "INSERT INTO embeddings (book_id, embedding, model) VALUES (2, ?, 'test-model')",
+            [vec![0u8; 7]],
+        )
+        .unwrap();
+
+        let store = VectorStore::new(db_path.to_str().unwrap()).unwrap();
+        assert_eq!(store.load_cache().unwrap(), 1);
+        assert_eq!(db.get_book(2).unwrap().embedding_status, "pending");
+        assert_eq!(db.get_book(1).unwrap().embedding_status, "complete");
+        assert!(store.is_stale(2, 1));
+        assert_eq!(store.stale_embedding_ids(1).unwrap(), vec![2]);
+
+        let report = store.repair_embeddings(true).unwrap();
+        assert_eq!(report.corrupt_book_ids, vec![2]);
+        assert_eq!(report.deleted, 1);
+        assert!(!store.has_embedding(2));
+        assert_eq!(store.count().unwrap(), 1);
+    }
+
+    #[test]
+    fn test_export_embeddings_round_trip() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let db = crate::db::Database::new_in_memory().unwrap();
+        db.with_conn(|conn| {
+            conn.execute_batch(
+                "INSERT INTO books (id, path, title) VALUES (1, '/b/a.epub', 'A'), (2, '/b/b.epub', 'B')"
+            )?;
+            Ok(())
+        })
+        .unwrap();
+        let store = VectorStore::new(db.path()).unwrap();
+
+        let first: Vec<f32> = (0..EMBEDDING_DIM).map(|i| i as f32 / 10.0).collect();
+        let second: Vec<f32> = (0..EMBEDDING_DIM).map(|i| -(i as f32)).collect();
+        store.store_embedding(2, &second, "model-b", None, 1).unwrap();
+        store.store_embedding(1, &first, "model-a", None, 1).unwrap();
+
+        let path = dir.path().join("embeddings.bin");
+        assert_eq!(store.export_embeddings(&path).unwrap(), 2);
+        assert_eq!(
+            std::fs::metadata(&path).unwrap().len() as usize,
+            8 + 2 * (8 + EMBEDDING_DIM * 4)
+        );
+
+        let records = read_embeddings_export(&path).unwrap();
+        assert_eq!(records, vec![(1, first), (2, second)]);
+
+        let sidecar: serde_json::Value =
+            serde_json::from_slice(&std::fs::read(dir.path().join("embeddings.bin.json")).unwrap()).unwrap();
+        assert_eq!(
+            sidecar,
+            serde_json::json!([
+                { "bookId": 1, "model": "model-a" },
+                { "bookId": 2, "model": "model-b" }
+            ])
+        );
+    }
+
+    #[test]
+    fn test_read_embeddings_export_rejects_bad_headers() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let path = dir.path().join("embeddings.bin");
+        let read = |count: u32, dim: u32, body: &[u8]| {
+            let mut bytes = [count.to_le_bytes(), dim.to_le_bytes()].concat();
+            bytes.extend_from_slice(body);
+            std::fs::write(&path, bytes).unwrap();
+            read_embeddings_export(&path)
+        };
+
+        // A header whose sizes overflow, or promise more than the file holds
+        assert!(read(u32::MAX, u32::MAX, &[0; 16]).is_err());
+        assert!(read(1_000_000, 2, &[0; 16]).is_err());
+        assert!(read(1, 2, &[0; 12]).is_err());
+
+        let mut record = 7i64.to_le_bytes().to_vec();
+        record.extend_from_slice(&serialize_embedding(&[1.0, 2.0]));
+        assert_eq!(read(1, 2, &record).unwrap(), vec![(7, vec![1.0, 2.0])]);
+        assert!(read(0, u32::MAX, &[]).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_cache_snapshot_reloads_until_embeddings_change() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let db = crate::db::Database::new_in_memory().unwrap();
+        db.with_conn(|conn| {
+            conn.execute_batch(
+                "INSERT INTO books (id, path, title) VALUES (1, '/b/a.epub', 'A'), (2, '/b/b.epub', 'B')"
+            )?;
+            Ok(())
+        })
+        .unwrap();
+        let store = VectorStore::new(db.path()).unwrap();
+        store.set_active_model("test");
+        let first: Vec<f32> = (0..EMBEDDING_DIM).map(|i| i as f32 / 10.0).collect();
+        store.store_embedding(1, &first, "test", None, 1).unwrap();
+
+        // A cache that was never loaded isn't complete, so it isn't saved
+        let path = dir.path().join("library.db.vectors");
+        assert_eq!(store.save_cache_snapshot(&path).unwrap(), 0);
+        assert!(!store.try_load_snapshot(&path).unwrap());
+
+        store.load_cache().unwrap();
+        assert_eq!(store.save_cache_snapshot(&path).unwrap(), 1);
+
+        let reopened = VectorStore::new(db.path()).unwrap();
+        reopened.set_active_model("test");
+        assert!(reopened.try_load_snapshot(&path).unwrap());
+        assert_eq!(reopened.cache.len(), 1);
+        let restored = reopened.get_embedding(1).unwrap();
+        assert!(restored.iter().zip(&first).all(|(a, b)| (a - b).abs() < 1e-4));
+
+        // Another model's snapshot, or one taken before a change, is ignored
+        let other = VectorStore::new(db.path()).unwrap();
+        other.set_active_model("other");
+        assert!(!other.try_load_snapshot(&path).unwrap());
+
+        store.store_embedding(2, &first, "test", None, 1).unwrap();
+        let reopened = VectorStore::new(db.path()).unwrap();
+        reopened.set_active_model("test");
+        assert!(!reopened.try_load_snapshot(&path).unwrap());
+        assert!(reopened.cache.is_empty());
+    }
+
+    #[test]
+    fn test_allowed_ids_restrict_candidates() {
+        let db = crate::db::Database::new_in_memory().unwrap();
+        db.with_conn(|conn| {
+            conn.execute_batch(
+                "INSERT INTO books (id, path, title, language) VALUES
+                    (1, '/b/1.epub', 'Source', 'fr'),
+                    (2, '/b/2.epub', 'Close EN', 'en'),
+                    (3, '/b/3.epub', 'Closer EN', 'en'),
+                    (4, '/b/4.epub', 'Near FR', 'fr'),
+                    (5, '/b/5.epub', 'Far FR', 'fr')"
+            )?;
+            Ok(())
+        })
+        .unwrap();
+        let store = VectorStore::new(db.path()).unwrap();
+        let at_similarity = |cos: f32| {
+            let mut v = vec![0.0f32; EMBEDDING_DIM];
+            v[0] = cos;
+            v[1] = (1.0 - cos * cos).sqrt();
+            v
+        };
+        for (id, cos) in [(1, 1.0), (2, 0.95), (3, 0.99), (4, 0.8), (5, 0.3)] {
+            store.store_embedding(id, &at_similarity(cos), "test", None, 1).unwrap();
+        }
+
+        // Post-filtering the global top 2 leaves nothing in French
+        let unfiltered: Vec<i64> = store.find_similar_to_book(1, 2).iter().map(|r| r.0).collect();
+        assert_eq!(unfiltered, vec![3, 2]);
+
+        let french = db
+            .query_book_ids(&crate::db::BookQuery {
+                language: Some("fr".to_string()),
+                ..Default::default()
+            })
+            .unwrap();
+        let filtered = store.find_similar_to_book_in(1, 2, Some(&french));
+        let ids: Vec<i64> = filtered.iter().map(|r| r.0).collect();
+        assert_eq!(ids, vec![4, 5]);
+        assert!(filtered[0].1 > filtered[1].1);
+
+        // Excluding a series narrows the set the same way
+        let ids = db
+            .query_book_ids(&crate::db::BookQuery {
+                exclude_series: Some("Anything".to_string()),
+                ..Default::default()
+            })
+            .unwrap();
+        assert_eq!(ids.len(), 5);
+    }
+
+    #[test]
+    fn test_ann_index_follows_stores_and_deletes() {
+        let db = crate::db::Database::new_in_memory().unwrap();
+        db.with_conn(|conn| {
+            for id in 1..=6 {
+                conn.execute(
+                    "INSERT INTO books (id, path, title) VALUES (?, ?, ?)",
+                    rusqlite::params![id, format!("/b/{}.epub", id), format!("Book {}", id)],
+                )?;
+            }
+            Ok(())
+        })
+        .unwrap();
+        let store = VectorStore::new(db.path()).unwrap();
+        let at_similarity = |cos: f32| {
+            let mut v = vec![0.0f32; EMBEDDING_DIM];
+            v[0] = cos;
+            v[1] = (1.0 - cos * cos).sqrt();
+            v
+        };
+        for (id, cos) in [(1, 1.0), (2, 0.95), (3, 0.99), (4, 0.8), (5, 0.3)] {
+            store.store_embedding(id, &at_similarity(cos), "test", None, 1).unwrap();
+        }
+
+        store.set_ann_threshold(0);
+        let exact = store.find_similar_to_book(1, 3);
+        store.set_ann_threshold(1);
+        let approximate = store.find_similar_to_book(1, 3);
+        assert!(store.ann_index.read().is_some());
+        assert_eq!(approximate, exact);
+
+        // New embeddings join the index; replaced and deleted ones drop out
+        store.store_embedding(6, &at_similarity(0.999), "test", None, 1).unwrap();
+        store.store_embedding(3, &at_similarity(0.5), "test", None, 1).unwrap();
+        let ids: Vec<i64> = store.find_similar_to_book(1, 3).iter().map(|r| r.0).collect();
+        assert_eq!(ids, vec![6, 2, 4]);
+
+        store.delete_embedding(6).unwrap();
+        let ids: Vec<i64> = store.find_similar_to_book(1, 3).iter().map(|r| r.0).collect();
+        assert_eq!(ids, vec![2, 4, 3]);
+
+        // Exact search is unaffected by the index
+        store.set_ann_threshold(0);
+        let ids: Vec<i64> = store.find_similar_to_book(1, 3).iter().map(|r| r.0).collect();
+        assert_eq!(ids, vec![2, 4, 3]);
+    }
+
+    #[test]
+    fn test_equal_similarity_sorts_by_book_id() {
+        let db = crate::db::Database::new_in_memory().unwrap();
+        db.with_conn(|conn| {
+            for id in [7, 3, 9, 5] {
+                conn.execute(
+                    "INSERT INTO books (id, path, title) VALUES (?, ?, ?)",
+                    rusqlite::params![id, format!("/b/{}.epub", id), format!("Book {}", id)],
+                )?;
+            }
+            Ok(())
+        })
+        .unwrap();
+        let store = VectorStore::new(db.path()).unwrap();
+        for id in [7, 3, 9, 5] {
+            store.store_embedding(id, &vec![0.5f32; EMBEDDING_DIM], "test", None, 1).unwrap();
+        }
+
+        let ids: Vec<i64> = store.find_similar(&vec![0.5f32; EMBEDDING_DIM], 3, &[]).iter().map(|r| r.0).collect();
+        assert_eq!(ids, vec![3, 5, 7]);
+    }
+
+    #[test]
+    fn test_clear_one_library_keeps_others() {
+        let db = crate::db::Database::new_in_memory().unwrap();
+        db.with_conn(|conn| {
+            conn.execute_batch(
+                "INSERT INTO books (id, path, title, embedding_status) VALUES
+                    (1, '/lib/fiction/a.epub', 'A', 'complete'),
+                    (2, '/lib/fiction/b.epub', 'B', 'complete'),
+                    (3, '/lib/poetry/c.epub', 'C', 'complete'),
+                    (4, '/lib/poetry-drafts/d.epub', 'D', 'complete')"
+            )?;
+            Ok(())
+        })
+        .unwrap();
+        let store = VectorStore::new(db.path()).unwrap();
+        let embedding = vec![0.5f32; EMBEDDING_DIM];
+        for id in 1..=4 {
+            store.store_embedding(id, &embedding, "test", None, 1).unwrap();
+        }
+
+        // A sibling folder sharing the name as a prefix is another library
+        let ids = db.get_library_book_ids("/lib/poetry/").unwrap();
+        assert_eq!(ids, vec![3]);
+        assert_eq!(store.delete_embeddings(&ids).unwrap(), 1);
+        assert_eq!(db.reset_embedding_statuses(&ids).unwrap(), 1);
+
+        assert!(!store.has_embedding(3));
+        assert!(store.has_embedding(1) && store.has_embedding(2) && store.has_embedding(4));
+        assert_eq!(db.get_book(3).unwrap().embedding_status, "pending");
+        assert_eq!(db.get_book(1).unwrap().embedding_status, "complete");
+    }
+
+    #[test]
+    fn test_models_stored_side_by_side() {
+        let db = crate::db::Database::new_in_memory().unwrap();
+        db.with_conn(|conn| {
+            conn.execute_batch(
+                "INSERT INTO books (id, path, title) VALUES (1, '/b/a.epub', 'A'), (2, '/b/b.epub', 'B')"
+            )?;
+            Ok(())
+        })
+        .unwrap();
+        let store = VectorStore::new(db.path()).unwrap();
+
+        let mut a = vec![0.0f32; EMBEDDING_DIM];
+        a[0] = 1.0;
+        let mut b = vec![0.0f32; EMBEDDING_DIM];
+        b[1] = 1.0;
+        store.set_active_model("model-a");
+        store.store_embedding(1, &a, "model-a", None, 1).unwrap();
+        store.store_embedding(1, &b, "model-b", None, 1).unwrap();
+        store.store_embedding(2, &b, "model-b", None, 1).unwrap();
+
+        assert_eq!(store.get_embedding_for_model(1, "model-a"), Some(a.clone()));
+        assert_eq!(store.get_embedding_for_model(1, "model-b"), Some(b.clone()));
+        assert_eq!(
+            store.count_by_model().unwrap(),
+            vec![("model-b".to_string(), 2), ("model-a".to_string(), 1)]
+        );
+
+        // Only the active model's embeddings are served
+        assert_eq!(store.get_embedding(1), Some(a));
+        assert!(!store.has_embedding(2));
+        assert!(store.find_similar(&b, 5, &[]).iter().all(|(id, _)| *id == 1));
+
+        store.set_active_model("model-b");
+        assert_eq!(store.get_embedding(1), Some(b.clone()));
+        let ids: Vec<i64> = store.find_similar(&b, 5, &[]).iter().map(|r| r.0).collect();
+        assert_eq!(ids.len(), 2);
+        assert!(ids.contains(&1) && ids.contains(&2));
+    }
+
+    #[test]
+    fn test_legacy_embeddings_table_migrates() {
+        let db = crate::db::Database::new_in_memory().unwrap();
+        db.with_conn(|conn| {
+            conn.execute_batch(
+                "INSERT INTO books (id, path, title) VALUES (1, '/b/a.epub', 'A');
+                 CREATE TABLE embeddings (
+                     book_id INTEGER PRIMARY KEY,
+                     embedding BLOB NOT NULL,
+                     model TEXT NOT NULL,
+                     text_hash TEXT,
+                     created_at INTEGER NOT NULL DEFAULT (strftime('%s', 'now'))
+                 );"
+            )?;
+            conn.execute(
+                "INSERT INTO embeddings (book_id, embedding, model) VALUES (1, ?, 'old-model')",
+                [serialize_embedding(&vec![0.5f32; EMBEDDING_DIM])],
+            )?;
+            Ok(())
+        })
+        .unwrap();
+
+        let store = VectorStore::new(db.path()).unwrap();
+        store.store_embedding(1, &vec![0.25f32; EMBEDDING_DIM], "new-model", None, 1).unwrap();
+
+        assert_eq!(store.get_embedding_for_model(1, "old-model"), Some(vec![0.5f32; EMBEDDING_DIM]));
+        assert_eq!(store.count().unwrap(), 2);
+    }
+
     #[test]
     fn test_serialization() {
         let original = vec![1.0f32, 2.0, 3.0, -4.5];
@@ -2,36 +2,134 @@
 //!
 //! Monitors library directories for changes and triggers incremental updates.
 
-use crate::db::Database;
-use crate::epub::EpubParser;
+use crate::db::{Book, Database};
+use crate::epub::{calculate_file_hash, EpubParser};
+use crate::state::BackgroundJob;
+use crate::worker::{emit_to, EventSink};
 use crate::AppResult;
+use futures::{Stream, StreamExt};
+use notify::event::{ModifyKind, RenameMode};
 use notify::{Config, Event, EventKind, RecommendedWatcher, RecursiveMode, Watcher};
-use std::collections::HashSet;
+use std::collections::{HashMap, HashSet};
 use std::path::{Path, PathBuf};
 use std::sync::mpsc::{channel, Receiver};
 use std::sync::Arc;
-use std::time::Duration;
-use parking_lot::RwLock;
+use std::time::{Duration, Instant};
+use parking_lot::{Mutex, RwLock};
+
+/// How long the watcher must see no new books before queueing embeddings
+const EMBED_QUIET_PERIOD: Duration = Duration::from_secs(3);
+
+/// Most books queued in one embedding job
+const EMBED_BATCH_SIZE: usize = 100;
+
+/// How long a path must go without new events before they're acted on
+pub const DEFAULT_DEBOUNCE: Duration = Duration::from_secs(2);
+
+/// How long a settled delete or create waits for the other end of a move
+/// before it's acted on alone
+pub const DEFAULT_MOVE_WINDOW: Duration = Duration::from_secs(3);
+
+/// How often `run_watcher` checks for changes that have gone quiet
+const SETTLE_INTERVAL: Duration = Duration::from_millis(500);
+
+/// What happened to a path, with the events seen so far coalesced
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Change {
+    Created,
+    Modified,
+    Deleted,
+}
+
+impl Change {
+    /// The net change after `next` follows `self`
+    ///
+    /// A delete always wins (removing an unknown book is harmless). Anything
+    /// after a create is still a new book, and a create after a delete is an
+    /// editor saving atomically, so it's a modification.
+    fn then(self, next: Change) -> Change {
+        match (self, next) {
+            (_, Change::Deleted) => Change::Deleted,
+            (Change::Created, _) => Change::Created,
+            _ => Change::Modified,
+        }
+    }
+}
 
 /// File system watcher for library directories
+///
+/// Every part sits behind its own lock, so the watcher can be shared as is:
+/// applying changes never blocks watching a new path or stopping.
 pub struct LibraryWatcher {
-    watcher: Option<RecommendedWatcher>,
+    watcher: Mutex<Option<RecommendedWatcher>>,
     watched_paths: Arc<RwLock<HashSet<PathBuf>>>,
-    event_receiver: Option<Receiver<Result<Event, notify::Error>>>,
+    event_receiver: Mutex<Option<Receiver<Result<Event, notify::Error>>>>,
+    job_sender: Option<async_channel::Sender<BackgroundJob>>,
+    /// Books added since the last flush, and when the last one arrived
+    pending_embeds: Mutex<(Vec<i64>, Option<Instant>)>,
+    /// Changed paths waiting to go quiet, with when they last changed
+    pending_changes: Mutex<HashMap<PathBuf, (Change, Instant)>>,
+    debounce: Duration,
+    /// Settled changes that may be one end of a move, with when they settled
+    unpaired: Mutex<Unpaired>,
+    move_window: Duration,
+}
+
+/// Deletes of known books and creates of new files not yet paired into moves
+#[derive(Default)]
+struct Unpaired {
+    deleted: Vec<(PathBuf, Instant)>,
+    created: Vec<(PathBuf, Instant)>,
 }
 
 impl LibraryWatcher {
     /// Create a new library watcher
     pub fn new() -> AppResult<Self> {
         Ok(Self {
-            watcher: None,
+            watcher: Mutex::new(None),
             watched_paths: Arc::new(RwLock::new(HashSet::new())),
-            event_receiver: None,
+            event_receiver: Mutex::new(None),
+            job_sender: None,
+            pending_embeds: Mutex::new((Vec::new(), None)),
+            pending_changes: Mutex::new(HashMap::new()),
+            debounce: DEFAULT_DEBOUNCE,
+            unpaired: Mutex::new(Unpaired::default()),
+            move_window: DEFAULT_MOVE_WINDOW,
         })
     }
 
+    /// Queue embedding jobs for added books on the given channel
+    ///
+    /// Books are coalesced: nothing is sent until no new book has arrived for
+    /// [`EMBED_QUIET_PERIOD`], then they go out in jobs of up to
+    /// [`EMBED_BATCH_SIZE`], so a large copy-in doesn't flood the queue.
+    pub fn with_job_sender(mut self, sender: async_channel::Sender<BackgroundJob>) -> Self {
+        self.job_sender = Some(sender);
+        self
+    }
+
+    /// Wait until a path has had no events for `debounce` before acting on
+    /// it (default [`DEFAULT_DEBOUNCE`])
+    ///
+    /// A save that fires several modify events then re-parses the book once.
+    pub fn with_debounce(mut self, debounce: Duration) -> Self {
+        self.debounce = debounce;
+        self
+    }
+
+    /// Hold a settled delete of a book, or create of a new file, for up to
+    /// `window` in case the other end of a move settles later (default
+    /// [`DEFAULT_MOVE_WINDOW`])
+    ///
+    /// A copy-then-delete move, or a large file still being written, can
+    /// settle one end well before the other.
+    pub fn with_move_window(mut self, window: Duration) -> Self {
+        self.move_window = window;
+        self
+    }
+
     /// Start watching with event channel
-    pub fn start(&mut self) -> AppResult<()> {
+    pub fn start(&self) -> AppResult<()> {
         let (tx, rx) = channel();
 
         let watcher = RecommendedWatcher::new(
@@ -42,16 +140,16 @@ impl LibraryWatcher {
         )
         .map_err(|e| crate::AppError::Config(format!("Failed to create watcher: {}", e)))?;
 
-        self.watcher = Some(watcher);
-        self.event_receiver = Some(rx);
+        *self.watcher.lock() = Some(watcher);
+        *self.event_receiver.lock() = Some(rx);
 
         tracing::info!("File watcher started");
         Ok(())
     }
 
     /// Add a library path to watch
-    pub fn watch_path(&mut self, path: &Path) -> AppResult<()> {
-        if let Some(ref mut watcher) = self.watcher {
+    pub fn watch_path(&self, path: &Path) -> AppResult<()> {
+        if let Some(ref mut watcher) = *self.watcher.lock() {
             watcher
                 .watch(path, RecursiveMode::Recursive)
                 .map_err(|e| crate::AppError::Config(format!("Failed to watch path: {}", e)))?;
@@ -63,8 +161,8 @@ impl LibraryWatcher {
     }
 
     /// Remove a library path from watching
-    pub fn unwatch_path(&mut self, path: &Path) -> AppResult<()> {
-        if let Some(ref mut watcher) = self.watcher {
+    pub fn unwatch_path(&self, path: &Path) -> AppResult<()> {
+        if let Some(ref mut watcher) = *self.watcher.lock() {
             let _ = watcher.unwatch(path);
             self.watched_paths.write().remove(path);
             tracing::info!("Stopped watching: {:?}", path);
@@ -73,16 +171,20 @@ impl LibraryWatcher {
     }
 
     /// Process pending events (non-blocking)
+    ///
+    /// New events are held per path until the path has been quiet for the
+    /// debounce window, and a book deleted and created elsewhere in the same
+    /// window is moved. Returns the coalesced events acted on this time.
     pub fn process_events(&self, db: &Database) -> Vec<WatcherEvent> {
-        let mut events = Vec::new();
+        let now = Instant::now();
 
-        if let Some(ref rx) = self.event_receiver {
+        if let Some(ref rx) = *self.event_receiver.lock() {
             // Drain all available events
             while let Ok(result) = rx.try_recv() {
                 match result {
                     Ok(event) => {
-                        if let Some(watch_event) = self.process_notify_event(event) {
-                            events.push(watch_event);
+                        if let Some(watch_event) = Self::process_notify_event(event) {
+                            self.record_event(&watch_event, now);
                         }
                     }
                     Err(e) => {
@@ -92,6 +194,50 @@ impl LibraryWatcher {
             }
         }
 
+        self.apply_settled(db, now)
+    }
+
+    /// Stream of events as they arrive, before debouncing
+    ///
+    /// Takes the event channel over from `process_events`. A thread bridges
+    /// it to the stream until the watcher stops or the stream is dropped;
+    /// before `start` the stream is empty.
+    pub fn event_stream(&self) -> impl Stream<Item = WatcherEvent> {
+        let (tx, rx) = async_channel::unbounded();
+        if let Some(results) = self.event_receiver.lock().take() {
+            std::thread::spawn(move || {
+                for result in results {
+                    match result {
+                        Ok(event) => {
+                            let Some(watch_event) = Self::process_notify_event(event) else {
+                                continue;
+                            };
+                            if tx.try_send(watch_event).is_err() {
+                                break;
+                            }
+                        }
+                        Err(e) => tracing::warn!("Watch error: {:?}", e),
+                    }
+                }
+            });
+        }
+        rx
+    }
+
+    /// Whether any changes or new books are waiting to be acted on
+    fn has_pending(&self) -> bool {
+        let unpaired = self.unpaired.lock();
+        !self.pending_changes.lock().is_empty()
+            || !unpaired.deleted.is_empty()
+            || !unpaired.created.is_empty()
+            || self.pending_embeds.lock().1.is_some()
+    }
+
+    /// Apply the changes quiet since `now - debounce` to the database, and
+    /// queue embeddings once new books stop arriving
+    fn apply_settled(&self, db: &Database, now: Instant) -> Vec<WatcherEvent> {
+        let events = self.detect_moves(self.take_settled(now), db, now);
+
         // Process events and update database
         for event in &events {
             if let Err(e) = self.handle_event(event, db) {
@@ -99,15 +245,187 @@ impl LibraryWatcher {
             }
         }
 
+        let quiet = self
+            .pending_embeds
+            .lock()
+            .1
+            .is_some_and(|last| last.elapsed() >= EMBED_QUIET_PERIOD);
+        if quiet {
+            self.flush_embeds();
+        }
+
         events
     }
 
+    /// Queue embedding jobs for all books added since the last flush
+    ///
+    /// Returns the number of jobs sent.
+    pub fn flush_embeds(&self) -> usize {
+        let book_ids = {
+            let mut pending = self.pending_embeds.lock();
+            pending.1 = None;
+            std::mem::take(&mut pending.0)
+        };
+        let Some(ref sender) = self.job_sender else {
+            return 0;
+        };
+
+        let mut sent = 0;
+        for chunk in book_ids.chunks(EMBED_BATCH_SIZE) {
+            let job = BackgroundJob::GenerateEmbeddings { book_ids: chunk.to_vec() };
+            match sender.try_send(job) {
+                Ok(()) => sent += 1,
+                Err(e) => tracing::error!("Failed to queue embeddings for watched books: {}", e),
+            }
+        }
+        if sent > 0 {
+            tracing::info!("Queued embeddings for {} books added by the watcher", book_ids.len());
+        }
+        sent
+    }
+
+    /// Note an event's paths as changed at `now`, merging with changes not
+    /// yet acted on
+    fn record_event(&self, event: &WatcherEvent, now: Instant) {
+        let changes: Vec<(&PathBuf, Change)> = match event {
+            WatcherEvent::FileCreated(paths) => paths.iter().map(|p| (p, Change::Created)).collect(),
+            WatcherEvent::FileModified(paths) => paths.iter().map(|p| (p, Change::Modified)).collect(),
+            WatcherEvent::FileDeleted(paths) => paths.iter().map(|p| (p, Change::Deleted)).collect(),
+            // Paired up again by `detect_moves` once both ends settle
+            WatcherEvent::FileMoved { from, to } => vec![(from, Change::Deleted), (to, Change::Created)],
+        };
+
+        let mut pending = self.pending_changes.lock();
+        for (path, change) in changes {
+            pending
+                .entry(path.clone())
+                .and_modify(|(previous, seen)| {
+                    *previous = previous.then(change);
+                    *seen = now;
+                })
+                .or_insert((change, now));
+        }
+    }
+
+    /// Remove and return the changes to paths quiet since `now - debounce`,
+    /// deletions first
+    fn take_settled(&self, now: Instant) -> Vec<WatcherEvent> {
+        let mut created = Vec::new();
+        let mut modified = Vec::new();
+        let mut deleted = Vec::new();
+
+        self.pending_changes.lock().retain(|path, (change, seen)| {
+            if now.saturating_duration_since(*seen) < self.debounce {
+                return true;
+            }
+            match change {
+                Change::Created => created.push(path.clone()),
+                Change::Modified => modified.push(path.clone()),
+                Change::Deleted => deleted.push(path.clone()),
+            }
+            false
+        });
+
+        [
+            (deleted, WatcherEvent::FileDeleted as fn(Vec<PathBuf>) -> WatcherEvent),
+            (created, WatcherEvent::FileCreated),
+            (modified, WatcherEvent::FileModified),
+        ]
+        .into_iter()
+        .filter(|(paths, _)| !paths.is_empty())
+        .map(|(mut paths, event)| {
+            paths.sort();
+            event(paths)
+        })
+        .collect()
+    }
+
+    /// Turn deleted books whose file was created at another path into moves
+    ///
+    /// A created file is the same book when its size matches, and its hash
+    /// too if the book has one. Deletes of known books and creates of new
+    /// files wait up to the move window for their other end; those still
+    /// unmatched after it are acted on alone. Other paths pass straight
+    /// through.
+    fn detect_moves(&self, events: Vec<WatcherEvent>, db: &Database, now: Instant) -> Vec<WatcherEvent> {
+        let is_book = |path: &Path| matches!(db.get_book_by_path(&path.to_string_lossy()), Ok(Some(_)));
+        let mut deleted = Vec::new();
+        let mut created = Vec::new();
+        let mut rest = Vec::new();
+
+        let mut unpaired = self.unpaired.lock();
+        let Unpaired { deleted: held_deleted, created: held_created } = &mut *unpaired;
+        for event in events {
+            match event {
+                // Only a known book can have moved, and only to a new file
+                WatcherEvent::FileDeleted(paths) => {
+                    for path in paths {
+                        if is_book(&path) {
+                            held_deleted.push((path, now));
+                        } else {
+                            deleted.push(path);
+                        }
+                    }
+                }
+                WatcherEvent::FileCreated(paths) => {
+                    for path in paths {
+                        if is_book(&path) {
+                            created.push(path);
+                        } else {
+                            held_created.push((path, now));
+                        }
+                    }
+                }
+                other => rest.push(other),
+            }
+        }
+
+        let mut moves = Vec::new();
+        held_deleted.retain(|(from, _)| {
+            let Ok(Some(book)) = db.get_book_by_path(&from.to_string_lossy()) else {
+                return true;
+            };
+            let Some(i) = held_created.iter().position(|(to, _)| is_same_file(&book, to)) else {
+                return true;
+            };
+            moves.push(WatcherEvent::FileMoved { from: from.clone(), to: held_created.remove(i).0 });
+            false
+        });
+
+        // Whatever has waited out the window is a plain delete or create
+        let release = |held: &mut Vec<(PathBuf, Instant)>, out: &mut Vec<PathBuf>| {
+            held.retain(|(path, settled)| {
+                if now.saturating_duration_since(*settled) < self.move_window {
+                    return true;
+                }
+                out.push(path.clone());
+                false
+            });
+        };
+        release(held_deleted, &mut deleted);
+        release(held_created, &mut created);
+
+        restore_events(deleted, created, moves, rest)
+    }
+
     /// Convert notify event to our event type
-    fn process_notify_event(&self, event: Event) -> Option<WatcherEvent> {
+    fn process_notify_event(event: Event) -> Option<WatcherEvent> {
+        // A rename within the watched folders names both ends
+        if let EventKind::Modify(ModifyKind::Name(RenameMode::Both)) = event.kind {
+            if let [from, to] = &event.paths[..] {
+                return match (is_book_file(from), is_book_file(to)) {
+                    (true, true) => Some(WatcherEvent::FileMoved { from: from.clone(), to: to.clone() }),
+                    (true, false) => Some(WatcherEvent::FileDeleted(vec![from.clone()])),
+                    (false, true) => Some(WatcherEvent::FileCreated(vec![to.clone()])),
+                    (false, false) => None,
+                };
+            }
+        }
+
         let paths: Vec<_> = event
             .paths
             .into_iter()
-            .filter(|p| is_epub_file(p))
+            .filter(|p| is_book_file(p))
             .collect();
 
         if paths.is_empty() {
@@ -116,6 +434,9 @@ impl LibraryWatcher {
 
         match event.kind {
             EventKind::Create(_) => Some(WatcherEvent::FileCreated(paths)),
+            // One end of a rename whose other end is reported separately
+            EventKind::Modify(ModifyKind::Name(RenameMode::From)) => Some(WatcherEvent::FileDeleted(paths)),
+            EventKind::Modify(ModifyKind::Name(RenameMode::To)) => Some(WatcherEvent::FileCreated(paths)),
             EventKind::Modify(_) => Some(WatcherEvent::FileModified(paths)),
             EventKind::Remove(_) => Some(WatcherEvent::FileDeleted(paths)),
             _ => None,
@@ -132,18 +453,7 @@ impl LibraryWatcher {
                     if db.get_book_by_path(path.to_string_lossy().as_ref())?.is_some() {
                         continue;
                     }
-
-                    // Parse and insert new book
-                    match parser.parse(path) {
-                        Ok(new_book) => {
-                            if let Ok(id) = db.insert_book(&new_book) {
-                                tracing::info!("Added new book from watcher: {} (id: {})", new_book.title, id);
-                            }
-                        }
-                        Err(e) => {
-                            tracing::warn!("Failed to parse new EPUB {:?}: {}", path, e);
-                        }
-                    }
+                    self.add_book(&parser, path, db);
                 }
             }
             WatcherEvent::FileModified(paths) => {
@@ -151,22 +461,26 @@ impl LibraryWatcher {
                 for path in paths {
                     let path_str = path.to_string_lossy();
 
-                    // Check if in database
-                    if let Some(existing) = db.get_book_by_path(&path_str)? {
-                        // Re-parse and update metadata
-                        if let Ok(new_book) = parser.parse(path) {
-                            let update = crate::db::BookUpdate {
-                                title: Some(new_book.title),
-                                author: new_book.author,
-                                series: new_book.series,
-                                series_index: new_book.series_index,
-                                description: new_book.description,
-                            };
-                            if let Err(e) = db.update_book(existing.id, &update) {
-                                tracing::warn!("Failed to update book {}: {}", existing.id, e);
-                            } else {
-                                tracing::info!("Updated book from watcher: {}", existing.title);
-                            }
+                    // Check if in database; a file saved atomically over one
+                    // the watcher never saw is new
+                    let Some(existing) = db.get_book_by_path(&path_str)? else {
+                        self.add_book(&parser, path, db);
+                        continue;
+                    };
+
+                    // Re-parse and update metadata
+                    if let Ok(new_book) = parser.parse(path) {
+                        let update = crate::db::BookUpdate {
+                            title: Some(new_book.title),
+                            author: new_book.author,
+                            series: new_book.series,
+                            series_index: new_book.series_index,
+                            description: new_book.description,
+                        };
+                        if let Err(e) = db.update_book(existing.id, &update) {
+                            tracing::warn!("Failed to update book {}: {}", existing.id, e);
+                        } else {
+                            tracing::info!("Updated book from watcher: {}", existing.title);
                         }
                     }
                 }
@@ -185,20 +499,49 @@ impl LibraryWatcher {
                     }
                 }
             }
+            WatcherEvent::FileMoved { from, to } => {
+                // Keep the row, and with it the rating, status and embedding
+                match db.get_book_by_path(&from.to_string_lossy())? {
+                    Some(existing) => {
+                        if let Err(e) = db.update_book_path(existing.id, &to.to_string_lossy()) {
+                            tracing::warn!("Failed to move book {}: {}", existing.id, e);
+                        } else {
+                            tracing::info!("Moved book from watcher: {} to {:?}", existing.title, to);
+                        }
+                    }
+                    None => self.add_book(&EpubParser::new(), to, db),
+                }
+            }
         }
         Ok(())
     }
 
+    /// Parse and insert a new book, queueing it for embedding
+    fn add_book(&self, parser: &EpubParser, path: &Path, db: &Database) {
+        match parser.parse(path) {
+            Ok(new_book) => {
+                if let Ok(id) = db.insert_book(&new_book) {
+                    tracing::info!("Added new book from watcher: {} (id: {})", new_book.title, id);
+                    let mut pending = self.pending_embeds.lock();
+                    pending.0.push(id);
+                    pending.1 = Some(Instant::now());
+                }
+            }
+            Err(e) => {
+                tracing::warn!("Failed to parse new EPUB {:?}: {}", path, e);
+            }
+        }
+    }
+
     /// Stop watching all paths
-    pub fn stop(&mut self) {
-        if let Some(ref mut watcher) = self.watcher {
+    pub fn stop(&self) {
+        if let Some(mut watcher) = self.watcher.lock().take() {
             for path in self.watched_paths.read().iter() {
                 let _ = watcher.unwatch(path);
             }
         }
         self.watched_paths.write().clear();
-        self.watcher = None;
-        self.event_receiver = None;
+        *self.event_receiver.lock() = None;
         tracing::info!("File watcher stopped");
     }
 }
@@ -206,9 +549,15 @@ impl LibraryWatcher {
 impl Default for LibraryWatcher {
     fn default() -> Self {
         Self::new().unwrap_or_else(|_| Self {
-            watcher: None,
+            watcher: Mutex::new(None),
             watched_paths: Arc::new(RwLock::new(HashSet::new())),
-            event_receiver: None,
+            event_receiver: Mutex::new(None),
+            job_sender: None,
+            pending_embeds: Mutex::new((Vec::new(), None)),
+            pending_changes: Mutex::new(HashMap::new()),
+            debounce: DEFAULT_DEBOUNCE,
+            unpaired: Mutex::new(Unpaired::default()),
+            move_window: DEFAULT_MOVE_WINDOW,
         })
     }
 }
@@ -225,13 +574,107 @@ pub enum WatcherEvent {
     FileCreated(Vec<PathBuf>),
     FileModified(Vec<PathBuf>),
     FileDeleted(Vec<PathBuf>),
+    /// A book's file moved or was renamed
+    FileMoved { from: PathBuf, to: PathBuf },
+}
+
+/// Payload for `library:changed`, sent after the watcher updates books
+#[derive(Debug, Clone, Default, PartialEq, Eq, serde::Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct LibraryChanged {
+    pub created: usize,
+    pub modified: usize,
+    pub deleted: usize,
+    pub moved: usize,
+}
+
+impl LibraryChanged {
+    /// Count the paths changed by `events`
+    fn from_events(events: &[WatcherEvent]) -> Self {
+        let mut changed = Self::default();
+        for event in events {
+            match event {
+                WatcherEvent::FileCreated(paths) => changed.created += paths.len(),
+                WatcherEvent::FileModified(paths) => changed.modified += paths.len(),
+                WatcherEvent::FileDeleted(paths) => changed.deleted += paths.len(),
+                WatcherEvent::FileMoved { .. } => changed.moved += 1,
+            }
+        }
+        changed
+    }
 }
 
-/// Check if a path is an EPUB file
-fn is_epub_file(path: &Path) -> bool {
+/// Keep the database in step with watched folders until the watcher stops
+///
+/// Consumes the watcher's `event_stream`, debouncing like `process_events`,
+/// and emits `library:changed` after each set of changes is applied.
+pub async fn run_watcher(watcher: Arc<LibraryWatcher>, db: Database, events: Arc<dyn EventSink>) {
+    let mut stream = std::pin::pin!(watcher.event_stream());
+    let mut settle = tokio::time::interval(SETTLE_INTERVAL);
+    settle.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+
+    loop {
+        tokio::select! {
+            event = stream.next() => match event {
+                Some(event) => watcher.record_event(&event, Instant::now()),
+                None => break,
+            },
+            _ = settle.tick() => {
+                if !watcher.has_pending() {
+                    continue;
+                }
+                // Parsing books blocks, so it runs off the async threads
+                let (watcher, db) = (watcher.clone(), db.clone());
+                let applied = tokio::task::spawn_blocking(move || watcher.apply_settled(&db, Instant::now()));
+                match applied.await {
+                    Ok(applied) if !applied.is_empty() => {
+                        emit_to(events.as_ref(), "library:changed", LibraryChanged::from_events(&applied));
+                    }
+                    Ok(_) => {}
+                    Err(e) => tracing::error!("Applying watcher changes panicked: {}", e),
+                }
+            }
+        }
+    }
+    tracing::info!("Library watcher task stopped");
+}
+
+/// Rebuild events from their paths, deletions first
+fn restore_events(
+    mut deleted: Vec<PathBuf>,
+    mut created: Vec<PathBuf>,
+    moves: Vec<WatcherEvent>,
+    rest: Vec<WatcherEvent>,
+) -> Vec<WatcherEvent> {
+    deleted.sort();
+    created.sort();
+    let mut events = Vec::new();
+    if !deleted.is_empty() {
+        events.push(WatcherEvent::FileDeleted(deleted));
+    }
+    events.extend(moves);
+    if !created.is_empty() {
+        events.push(WatcherEvent::FileCreated(created));
+    }
+    events.extend(rest);
+    events
+}
+
+/// Whether the file at `path` looks like `book`'s file
+fn is_same_file(book: &Book, path: &Path) -> bool {
+    let size_matches = std::fs::metadata(path).map(|m| m.len() as i64 == book.file_size).unwrap_or(false);
+    match &book.file_hash {
+        Some(hash) => size_matches && calculate_file_hash(path).ok().as_ref() == Some(hash),
+        None => size_matches,
+    }
+}
+
+/// Check if a path is a book file in a supported format
+fn is_book_file(path: &Path) -> bool {
     path.extension()
-        .map(|ext| ext.eq_ignore_ascii_case("epub"))
-        .unwrap_or(false)
+        .and_then(|ext| ext.to_str())
+        .and_then(crate::epub::Format::from_extension)
+        .is_some()
 }
 
 #[cfg(test)]
@@ -239,10 +682,222 @@ mod tests {
     use super::*;
 
     #[test]
-    fn test_is_epub_file() {
-        assert!(is_epub_file(Path::new("book.epub")));
-        assert!(is_epub_file(Path::new("Book.EPUB")));
-        assert!(!is_epub_file(Path::new("book.pdf")));
-        assert!(!is_epub_file(Path::new("book")));
+    fn test_is_book_file() {
+        assert!(is_book_file(Path::new("book.epub")));
+        assert!(is_book_file(Path::new("Book.EPUB")));
+        assert!(is_book_file(Path::new("book.azw3")));
+        assert!(!is_book_file(Path::new("book.pdf")));
+        assert!(!is_book_file(Path::new("book")));
+    }
+
+    #[test]
+    fn test_created_files_queue_batched_embeddings() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let db = Database::new_in_memory().unwrap();
+        let (sender, receiver) = async_channel::unbounded();
+        let watcher = LibraryWatcher::new().unwrap().with_job_sender(sender);
+
+        let paths: Vec<PathBuf> = (0..EMBED_BATCH_SIZE + 5)
+            .map(|i| {
+                let path = dir.path().join(format!("book-{}.epub", i));
+                std::fs::write(&path, crate::epub::test_epub_bytes(&format!("Book {}", i), "Author")).unwrap();
+                path
+            })
+            .collect();
+        watcher.handle_event(&WatcherEvent::FileCreated(paths), &db).unwrap();
+
+        // Nothing is sent until the batch is flushed
+        assert!(receiver.is_empty());
+        assert_eq!(watcher.flush_embeds(), 2);
+
+        let mut queued = Vec::new();
+        while let Ok(job) = receiver.try_recv() {
+            match job {
+                BackgroundJob::GenerateEmbeddings { book_ids } => queued.extend(book_ids),
+                other => panic!("unexpected job {:?}", other),
+            }
+        }
+        assert_eq!(queued.len(), EMBED_BATCH_SIZE + 5);
+        assert_eq!(watcher.flush_embeds(), 0);
+    }
+
+    #[test]
+    fn test_events_are_debounced_per_path() {
+        let watcher = LibraryWatcher::new().unwrap().with_debounce(Duration::from_secs(2));
+        let path = |name: &str| PathBuf::from(format!("/library/{}.epub", name));
+        let start = Instant::now();
+        let at = |secs: u64| start + Duration::from_secs(secs);
+
+        // Repeated saves
+        watcher.record_event(&WatcherEvent::FileModified(vec![path("saved")]), at(0));
+        watcher.record_event(&WatcherEvent::FileModified(vec![path("saved")]), at(1));
+        // An atomic save: the old file is removed, then the new one created
+        watcher.record_event(&WatcherEvent::FileDeleted(vec![path("atomic")]), at(0));
+        watcher.record_event(&WatcherEvent::FileCreated(vec![path("atomic")]), at(0));
+        // A copy that's still being written
+        watcher.record_event(&WatcherEvent::FileCreated(vec![path("copied")]), at(0));
+        watcher.record_event(&WatcherEvent::FileModified(vec![path("copied")]), at(1));
+        // Added, then removed again
+        watcher.record_event(&WatcherEvent::FileCreated(vec![path("gone")]), at(0));
+        watcher.record_event(&WatcherEvent::FileDeleted(vec![path("gone")]), at(0));
+
+        // Nothing has been quiet long enough, until only the last changes are pending
+        assert!(watcher.take_settled(at(1)).is_empty());
+        let settled = watcher.take_settled(at(2));
+        assert!(matches!(&settled[..], [WatcherEvent::FileDeleted(d), WatcherEvent::FileModified(m)]
+            if *d == vec![path("gone")] && *m == vec![path("atomic")]));
+
+        let settled = watcher.take_settled(at(3));
+        assert!(matches!(&settled[..], [WatcherEvent::FileCreated(c), WatcherEvent::FileModified(m)]
+            if *c == vec![path("copied")] && *m == vec![path("saved")]));
+        assert!(watcher.take_settled(at(10)).is_empty());
+    }
+
+    #[test]
+    fn test_atomic_save_of_unknown_file_adds_book() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let db = Database::new_in_memory().unwrap();
+        let watcher = LibraryWatcher::new().unwrap();
+
+        let path = dir.path().join("new.epub");
+        std::fs::write(&path, crate::epub::test_epub_bytes("New", "Author")).unwrap();
+        watcher.handle_event(&WatcherEvent::FileModified(vec![path.clone()]), &db).unwrap();
+        assert_eq!(db.get_book_by_path(path.to_str().unwrap()).unwrap().unwrap().title, "New");
+    }
+
+    #[test]
+    fn test_moved_book_keeps_its_row() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let db = Database::new_in_memory().unwrap();
+        let watcher = LibraryWatcher::new().unwrap();
+
+        let old = dir.path().join("old.epub");
+        std::fs::write(&old, crate::epub::test_epub_bytes("Moved", "Author")).unwrap();
+        watcher.handle_event(&WatcherEvent::FileCreated(vec![old.clone()]), &db).unwrap();
+        let book = db.get_book_by_path(old.to_str().unwrap()).unwrap().unwrap();
+        db.set_rating(book.id, 4.5).unwrap();
+
+        std::fs::create_dir(dir.path().join("sorted")).unwrap();
+        let new = dir.path().join("sorted").join("new.epub");
+        std::fs::rename(&old, &new).unwrap();
+        let other = dir.path().join("other.epub");
+        std::fs::write(&other, crate::epub::test_epub_bytes("A different book", "Someone else")).unwrap();
+
+        let settled = vec![
+            WatcherEvent::FileDeleted(vec![old.clone()]),
+            WatcherEvent::FileCreated(vec![other.clone(), new.clone()]),
+        ];
+        let now = Instant::now();
+        let mut events = watcher.detect_moves(settled, &db, now);
+        assert!(matches!(&events[..], [WatcherEvent::FileMoved { from, to }] if *from == old && *to == new));
+        assert_eq!(watcher.unpaired.lock().created.len(), 1);
+
+        // The unmatched new file is only added once the move window is over
+        events.extend(watcher.detect_moves(Vec::new(), &db, now + DEFAULT_MOVE_WINDOW));
+        assert!(matches!(&events[1..], [WatcherEvent::FileCreated(created)] if *created == vec![other.clone()]));
+        assert!(watcher.unpaired.lock().created.is_empty());
+
+        for event in &events {
+            watcher.handle_event(event, &db).unwrap();
+        }
+        assert!(db.get_book_by_path(old.to_str().unwrap()).unwrap().is_none());
+        let moved = db.get_book_by_path(new.to_str().unwrap()).unwrap().unwrap();
+        assert_eq!(moved.id, book.id);
+        assert_eq!(moved.rating, Some(4.5));
+        assert!(db.get_book_by_path(other.to_str().unwrap()).unwrap().is_some());
+    }
+
+    #[test]
+    fn test_move_pairs_across_settle_rounds() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let db = Database::new_in_memory().unwrap();
+        let watcher = LibraryWatcher::new().unwrap();
+
+        let old = dir.path().join("old.epub");
+        let gone = dir.path().join("gone.epub");
+        for (path, title) in [(&old, "Moved"), (&gone, "Deleted")] {
+            std::fs::write(path, crate::epub::test_epub_bytes(title, "Author")).unwrap();
+            watcher.handle_event(&WatcherEvent::FileCreated(vec![path.clone()]), &db).unwrap();
+        }
+        let book = db.get_book_by_path(old.to_str().unwrap()).unwrap().unwrap();
+
+        let new = dir.path().join("new.epub");
+        std::fs::rename(&old, &new).unwrap();
+        std::fs::remove_file(&gone).unwrap();
+
+        // The delete settles a round before the create that matches it
+        let now = Instant::now();
+        let deleted = vec![WatcherEvent::FileDeleted(vec![old.clone(), gone.clone()])];
+        assert!(watcher.detect_moves(deleted, &db, now).is_empty());
+
+        let later = now + Duration::from_secs(1);
+        let created = vec![WatcherEvent::FileCreated(vec![new.clone()])];
+        let events = watcher.detect_moves(created, &db, later);
+        assert!(matches!(&events[..], [WatcherEvent::FileMoved { from, to }] if *from == old && *to == new));
+
+        // A delete nothing claims is released once its window is over
+        let events = watcher.detect_moves(Vec::new(), &db, now + DEFAULT_MOVE_WINDOW);
+        assert!(matches!(&events[..], [WatcherEvent::FileDeleted(paths)] if *paths == vec![gone.clone()]));
+        assert!(watcher.unpaired.lock().deleted.is_empty());
+
+        watcher.handle_event(&WatcherEvent::FileMoved { from: old, to: new.clone() }, &db).unwrap();
+        assert_eq!(db.get_book_by_path(new.to_str().unwrap()).unwrap().unwrap().id, book.id);
+    }
+
+    #[test]
+    fn test_rename_events_become_moves() {
+        let rename = |from: &str, to: &str| {
+            Event::new(EventKind::Modify(ModifyKind::Name(RenameMode::Both)))
+                .add_path(PathBuf::from(from))
+                .add_path(PathBuf::from(to))
+        };
+
+        let moved = LibraryWatcher::process_notify_event(rename("/a/book.epub", "/b/book.epub"));
+        assert!(matches!(moved, Some(WatcherEvent::FileMoved { from, to })
+            if from == Path::new("/a/book.epub") && to == Path::new("/b/book.epub")));
+
+        // Finishing a download renames a partial file to a book
+        let finished = LibraryWatcher::process_notify_event(rename("/a/book.epub.part", "/a/book.epub"));
+        assert!(matches!(finished, Some(WatcherEvent::FileCreated(paths))
+            if paths == vec![PathBuf::from("/a/book.epub")]));
+    }
+
+    struct CapturedEvents(Mutex<Vec<(String, serde_json::Value)>>);
+
+    impl EventSink for CapturedEvents {
+        fn emit_event(&self, event: &str, payload: serde_json::Value) {
+            self.0.lock().push((event.to_string(), payload));
+        }
+    }
+
+    #[tokio::test]
+    async fn test_run_watcher_applies_changes_from_stream() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let db = Database::new_in_memory().unwrap();
+        let watcher = LibraryWatcher::new()
+            .unwrap()
+            .with_debounce(Duration::from_millis(100))
+            .with_move_window(Duration::from_millis(100));
+        watcher.start().unwrap();
+        watcher.watch_path(dir.path()).unwrap();
+        let watcher = Arc::new(watcher);
+        let events = Arc::new(CapturedEvents(Mutex::new(Vec::new())));
+        let task = tokio::spawn(run_watcher(watcher.clone(), db.clone(), events.clone()));
+
+        let path = dir.path().join("dropped-in.epub");
+        std::fs::write(&path, crate::epub::test_epub_bytes("Dropped In", "Author")).unwrap();
+
+        let deadline = Instant::now() + Duration::from_secs(10);
+        while events.0.lock().is_empty() && Instant::now() < deadline {
+            tokio::time::sleep(Duration::from_millis(50)).await;
+        }
+        let captured = events.0.lock().clone();
+        assert_eq!(captured[0].0, "library:changed");
+        assert_eq!(captured[0].1["created"], 1);
+        assert!(db.get_book_by_path(path.to_str().unwrap()).unwrap().is_some());
+
+        // Stopping the watcher ends the stream and the task
+        watcher.stop();
+        tokio::time::timeout(Duration::from_secs(5), task).await.unwrap().unwrap();
     }
 }
@@ -1,49 +1,504 @@
 //! Background worker for embedding generation and graph updates
 //!
 //! Processes jobs from the queue to:
-//! - Generate embeddings via Ollama
+//! - Generate embeddings via Ollama, working through the durable
+//!   `embedding_jobs` queue so queued books survive a restart
 //! - Update graph edges based on similarity
 //! - Handle library scanning
 
-use crate::db::Database;
-use crate::graph::compute_all_edge_weights;
-use crate::ollama::{book_to_embedding_text, OllamaClient};
+use crate::db::{Book, Database};
+use crate::epub::{calculate_file_hash, EpubParser};
+use crate::graph::{build_edges_for_book, precompute_similar_books, EdgeBuildConfig, DEFAULT_MAX_DEGREE};
+use crate::ollama::{
+    book_to_embedding_text, embedding_text_hash, EmbeddingBackend, EmbeddingInput, EmbeddingTextConfig,
+    OllamaClient, RateLimiter, EMBEDDING_TEXT_VERSION,
+};
+use crate::scanner::HashProgress;
 use crate::state::BackgroundJob;
 use crate::vector::VectorStore;
 use crate::AppResult;
-use std::sync::atomic::{AtomicBool, Ordering};
+use std::collections::HashSet;
+use std::sync::atomic::{AtomicBool, AtomicI64, AtomicUsize, Ordering};
 use std::sync::Arc;
-use std::time::Duration;
+use std::time::{Duration, Instant};
 use parking_lot::RwLock;
 
 /// Background worker configuration
 pub struct WorkerConfig {
-    /// Minimum delay between jobs (rate limiting)
-    pub job_delay_ms: u64,
-    /// Maximum retries for failed jobs
+    /// Maximum retries for failed embedding jobs
     pub max_retries: u32,
     /// Batch size for edge computation
     pub edge_batch_size: usize,
+    /// Most edges built per book, strongest first; 0 keeps them all
+    pub max_degree: usize,
+    /// Book files hashed per second by `ComputeHash` jobs
+    pub hashes_per_second: f64,
 }
 
 impl Default for WorkerConfig {
     fn default() -> Self {
         Self {
-            job_delay_ms: 100,
             max_retries: 3,
             edge_batch_size: 100,
+            max_degree: DEFAULT_MAX_DEGREE,
+            hashes_per_second: 5.0,
         }
     }
 }
 
+/// Seconds without a heartbeat, while work remains, before the worker counts
+/// as stalled; longer than an Ollama request may take
+pub const STALL_THRESHOLD_SECS: i64 = 180;
+
+/// How often an idle worker checks in
+const IDLE_HEARTBEAT: Duration = Duration::from_secs(10);
+
+/// Last time the background worker showed signs of life
+#[derive(Debug, Default)]
+pub struct Heartbeat(AtomicI64);
+
+impl Heartbeat {
+    /// Record that the worker is alive now
+    pub fn beat(&self) {
+        self.beat_at(chrono::Utc::now().timestamp());
+    }
+
+    /// Record a beat at `timestamp` (unix seconds)
+    pub fn beat_at(&self, timestamp: i64) {
+        self.0.store(timestamp, Ordering::Relaxed);
+    }
+
+    /// Unix seconds of the last beat, `None` if the worker never ran
+    pub fn last(&self) -> Option<i64> {
+        Some(self.0.load(Ordering::Relaxed)).filter(|&t| t > 0)
+    }
+
+    /// Whether the worker has been silent for over `threshold_secs` while work remains
+    pub fn is_stalled(&self, now: i64, threshold_secs: i64, work_remains: bool) -> bool {
+        work_remains && self.last().is_some_and(|last| now - last > threshold_secs)
+    }
+}
+
+/// Books queued for background hashing, for `hash:progress` events
+///
+/// Shared between the code queueing `ComputeHash` jobs and the worker. A
+/// book already pending isn't queued twice; the totals start over once
+/// every queued book has been hashed.
+#[derive(Debug, Default)]
+pub struct HashBackfill {
+    pending: parking_lot::Mutex<HashSet<i64>>,
+    total: AtomicUsize,
+}
+
+impl HashBackfill {
+    /// Track books about to be queued, returning those not already pending
+    pub fn start(&self, book_ids: impl IntoIterator<Item = i64>) -> Vec<i64> {
+        let mut pending = self.pending.lock();
+        if pending.is_empty() {
+            self.total.store(0, Ordering::Relaxed);
+        }
+        let added: Vec<i64> = book_ids.into_iter().filter(|&id| pending.insert(id)).collect();
+        self.total.fetch_add(added.len(), Ordering::Relaxed);
+        added
+    }
+
+    /// Record that a book's hash job ran, returning the backfill's progress
+    pub fn finish(&self, book_id: i64) -> HashProgress {
+        let mut pending = self.pending.lock();
+        pending.remove(&book_id);
+        let total = self.total.load(Ordering::Relaxed).max(pending.len());
+        HashProgress { processed: total - pending.len(), total }
+    }
+}
+
+/// Destination for events emitted by the worker
+///
+/// Implemented for the Tauri `AppHandle` so events reach the frontend; tests can
+/// supply their own sink to capture them.
+pub trait EventSink: Send + Sync {
+    fn emit_event(&self, event: &str, payload: serde_json::Value);
+}
+
+impl<R: tauri::Runtime> EventSink for tauri::AppHandle<R> {
+    fn emit_event(&self, event: &str, payload: serde_json::Value) {
+        use tauri::Emitter;
+        if let Err(e) = self.emit(event, payload) {
+            tracing::warn!("Failed to emit {}: {}", event, e);
+        }
+    }
+}
+
+/// Payload for `recommendations:updated`, sent after a book's edges change
+#[derive(Debug, Clone, serde::Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RecommendationsUpdated {
+    pub book_id: i64,
+}
+
+/// Payload for `embeddings:model-changed`
+#[derive(Debug, Clone, serde::Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ModelChanged {
+    pub previous: String,
+    pub current: String,
+}
+
+/// Payload for `embeddings:progress`, sent after each book while draining
+/// the pending queue
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct EmbeddingProgress {
+    pub book_id: i64,
+    pub title: String,
+    pub processed: usize,
+    pub failed: usize,
+    pub remaining: usize,
+    /// Estimated seconds left at the recent rate
+    pub eta_seconds: Option<u64>,
+}
+
+/// Completions averaged over for [`EtaEstimator`]
+const ETA_WINDOW: usize = 20;
+
+/// Estimates the time left from the gaps between recent completions
+///
+/// Gaps rather than request durations are averaged so that concurrent
+/// requests aren't counted as if they ran one after another.
+struct EtaEstimator {
+    last: Instant,
+    recent: std::collections::VecDeque<f64>,
+}
+
+impl EtaEstimator {
+    fn new() -> Self {
+        Self { last: Instant::now(), recent: std::collections::VecDeque::with_capacity(ETA_WINDOW) }
+    }
+
+    /// Record that a book just finished
+    fn record(&mut self) {
+        if self.recent.len() == ETA_WINDOW {
+            self.recent.pop_front();
+        }
+        self.recent.push_back(self.last.elapsed().as_secs_f64());
+        self.last = Instant::now();
+    }
+
+    /// Estimated seconds to finish `remaining` books, once any have finished
+    fn eta_seconds(&self, remaining: usize) -> Option<u64> {
+        if self.recent.is_empty() {
+            return None;
+        }
+        let average = self.recent.iter().sum::<f64>() / self.recent.len() as f64;
+        Some((average * remaining as f64).round() as u64)
+    }
+}
+
+/// Outcome of [`process_all_pending`]
+#[derive(Debug, Clone, Default, serde::Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DrainResult {
+    pub processed: usize,
+    pub failed: usize,
+    /// Books left for metadata parsing because they have no description
+    pub skipped: usize,
+    /// Stopped early by pause, cancel or an unreachable backend; the rest
+    /// stay pending
+    pub interrupted: bool,
+    /// Ollama stopped answering (see [`MAX_CONSECUTIVE_UNAVAILABLE`])
+    pub backend_unavailable: bool,
+    pub duration_ms: u64,
+}
+
+/// Outcome of [`process_embedding_batch`]
+#[derive(Debug, Clone, Default)]
+pub struct BatchOutcome {
+    pub processed: usize,
+    pub failed: usize,
+    /// Ollama stopped answering; unprocessed books stay pending
+    pub backend_unavailable: bool,
+    /// Some books weren't sent because the batch was paused or stopped;
+    /// they stay pending
+    pub interrupted: bool,
+}
+
+/// Connection failures in a row after which a run stops instead of
+/// marking every remaining book failed
+pub const MAX_CONSECUTIVE_UNAVAILABLE: usize = 3;
+
+/// What happened to one book's embedding request
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum EmbedOutcome {
+    Stored,
+    Failed,
+    /// The backend was unreachable; the book is left pending
+    Unavailable,
+}
+
+/// Whether a book has a non-blank description
+fn has_description(book: &Book) -> bool {
+    book.description.as_deref().is_some_and(|d| !d.trim().is_empty())
+}
+
+/// Opening chapter text for a book without a description
+///
+/// `None` when the book has a description, excerpts are turned off
+/// (`excerpt_chars` is 0), or no text could be read.
+fn chapter_excerpt(book: &Book, config: &EmbeddingTextConfig) -> Option<String> {
+    if has_description(book) || config.excerpt_chars == 0 {
+        return None;
+    }
+    match EpubParser::new().extract_text_excerpt(std::path::Path::new(&book.path), config.excerpt_chars) {
+        Ok(excerpt) if !excerpt.is_empty() => Some(excerpt),
+        Ok(_) => None,
+        Err(e) => {
+            tracing::debug!("No text excerpt for book {}: {}", book.id, e);
+            None
+        }
+    }
+}
+
+/// Store an embedding response, update the book's status and refresh its edges
+fn store_embedding_result(
+    db: &Database,
+    vector_store: &VectorStore,
+    book_id: i64,
+    model: &str,
+    text_hash: &str,
+    embedding: AppResult<Vec<f32>>,
+    events: &dyn EventSink,
+) -> AppResult<EmbedOutcome> {
+    let stored = embedding.and_then(|embedding| {
+        vector_store.store_embedding(book_id, &embedding, model, Some(text_hash), EMBEDDING_TEXT_VERSION)
+    });
+    match stored {
+        Ok(()) => {
+            db.update_embedding_status(book_id, "complete")?;
+            if let Err(e) = precompute_similar_books(db, vector_store, book_id) {
+                tracing::warn!("Failed to cache similar books for book {}: {}", book_id, e);
+            }
+
+            match build_edges_for_book(db, vector_store, book_id, &EdgeBuildConfig::load(db), None) {
+                Ok(edges) if !edges.is_empty() => {
+                    db.insert_edges_batch(&edges)?;
+                    emit_to(events, "recommendations:updated", RecommendationsUpdated { book_id });
+                }
+                Ok(_) => {}
+                Err(e) => tracing::warn!("Failed to compute edges for book {}: {}", book_id, e),
+            }
+            Ok(EmbedOutcome::Stored)
+        }
+        Err(crate::AppError::OllamaUnavailable(e)) => {
+            tracing::debug!("Ollama unavailable while embedding book {}: {}", book_id, e);
+            Ok(EmbedOutcome::Unavailable)
+        }
+        Err(e) => {
+            tracing::warn!("Embedding failed for book {}: {}", book_id, e);
+            db.update_embedding_status(book_id, "failed")?;
+            Ok(EmbedOutcome::Failed)
+        }
+    }
+}
+
+/// Everything an embedding run reads from and reports to
+///
+/// `concurrency` caps the requests in flight; `paused` and `cancelled` are
+/// checked before new requests are sent.
+#[derive(Clone, Copy)]
+pub struct EmbeddingContext<'a> {
+    pub db: &'a Database,
+    pub vector_store: &'a VectorStore,
+    pub backend: &'a dyn EmbeddingBackend,
+    pub rate_limiter: &'a RateLimiter,
+    pub paused: &'a AtomicBool,
+    pub cancelled: &'a AtomicBool,
+    pub concurrency: usize,
+    pub events: &'a dyn EventSink,
+}
+
+/// Embed up to `batch_size` pending books, up to `concurrency` requests
+/// in flight at once
+///
+/// Books that already have an up-to-date embedding are marked complete and
+/// books without a description are left for metadata parsing. Responses are
+/// stored one at a time as they arrive. After [`MAX_CONSECUTIVE_UNAVAILABLE`]
+/// connection failures in a row, or once `paused` is set, no new requests
+/// are sent; requests in flight are still stored and the remaining books
+/// stay pending rather than failed. Emits `embeddings:progress` after each
+/// book.
+pub async fn process_embedding_batch(ctx: &EmbeddingContext<'_>, batch_size: usize) -> AppResult<BatchOutcome> {
+    let EmbeddingContext { db, vector_store, backend, rate_limiter, paused, concurrency, events, .. } = *ctx;
+    let pending_books = db.get_pending_embedding_books(batch_size as i64)?;
+    let config = db.get_settings()?.embedding_fields;
+    let model = backend.model().to_string();
+    let total = db.get_stats()?.pending_embeddings.max(0) as usize;
+
+    let mut outcome = BatchOutcome::default();
+    let mut skipped = 0;
+    let mut jobs = Vec::with_capacity(pending_books.len());
+
+    for book_id in pending_books {
+        // Check if already has an up-to-date embedding
+        if vector_store.has_embedding(book_id) && !vector_store.is_stale(book_id, EMBEDDING_TEXT_VERSION) {
+            db.update_embedding_status(book_id, "complete").ok();
+            outcome.processed += 1;
+            continue;
+        }
+
+        let Ok(book) = db.get_book(book_id) else {
+            continue;
+        };
+
+        // Embeddings from titles only are meaningless; wait for metadata
+        let excerpt = chapter_excerpt(&book, &config);
+        if !has_description(&book) && excerpt.is_none() {
+            db.update_embedding_status(book_id, "needs_metadata").ok();
+            tracing::debug!("Skipping book {} - no description available", book.title);
+            skipped += 1;
+            continue;
+        }
+
+        let tags = db.get_book_tags(book_id).unwrap_or_default();
+        let input = EmbeddingInput::from_book(&book, &tags).with_excerpt(excerpt.as_deref());
+        let text = book_to_embedding_text(&input, &config);
+        jobs.push((book, text));
+    }
+
+    // Every request waits for a permit, then checks whether the batch has
+    // been stopped before it's sent
+    let permits = tokio::sync::Semaphore::new(concurrency.max(1));
+    let stopped = AtomicBool::new(false);
+    let mut in_flight: futures::stream::FuturesUnordered<_> = jobs
+        .iter()
+        .map(|(book, text)| {
+            let (permits, stopped) = (&permits, &stopped);
+            async move {
+                let _permit = permits.acquire().await.ok()?;
+                if stopped.load(Ordering::Relaxed) || paused.load(Ordering::Relaxed) {
+                    return None;
+                }
+                rate_limiter.acquire().await;
+                Some((book, text, backend.embed(text).await))
+            }
+        })
+        .collect();
+
+    let mut eta = EtaEstimator::new();
+    let mut unavailable_streak = 0;
+    while let Some(response) = futures::StreamExt::next(&mut in_flight).await {
+        let Some((book, text, embedding)) = response else {
+            outcome.interrupted = true;
+            continue;
+        };
+        let text_hash = embedding_text_hash(text, &config);
+        match store_embedding_result(db, vector_store, book.id, &model, &text_hash, embedding, events)? {
+            EmbedOutcome::Stored => {
+                tracing::info!("Generated embedding for: {}", book.title);
+                outcome.processed += 1;
+                unavailable_streak = 0;
+            }
+            EmbedOutcome::Failed => {
+                outcome.failed += 1;
+                unavailable_streak = 0;
+            }
+            EmbedOutcome::Unavailable => {
+                unavailable_streak += 1;
+                if unavailable_streak >= MAX_CONSECUTIVE_UNAVAILABLE && !outcome.backend_unavailable {
+                    tracing::warn!("Ollama unreachable; stopping batch early");
+                    outcome.backend_unavailable = true;
+                    stopped.store(true, Ordering::Relaxed);
+                }
+                continue;
+            }
+        }
+
+        eta.record();
+        let remaining = total.saturating_sub(outcome.processed + outcome.failed + skipped);
+        emit_to(
+            events,
+            "embeddings:progress",
+            EmbeddingProgress {
+                book_id: book.id,
+                title: book.title.clone(),
+                processed: outcome.processed,
+                failed: outcome.failed,
+                remaining,
+                eta_seconds: eta.eta_seconds(remaining),
+            },
+        );
+    }
+
+    Ok(outcome)
+}
+
+/// Remembers which embedding model was last used
+///
+/// Embeddings from different models can't be compared, so once the
+/// configured model changes mid-run, embedding stops and a re-embed with the
+/// new model is flagged until [`ModelTracker::reset`] is called.
+#[derive(Debug, Default)]
+pub struct ModelTracker {
+    last_model: parking_lot::Mutex<Option<String>>,
+    reembed_needed: AtomicBool,
+}
+
+impl ModelTracker {
+    /// Record `model` as in use; returns false if embedding must stop
+    pub fn check(&self, model: &str) -> bool {
+        let mut last = self.last_model.lock();
+        match last.as_deref() {
+            Some(previous) if previous != model => {
+                self.reembed_needed.store(true, Ordering::Relaxed);
+                false
+            }
+            _ if self.reembed_needed() => false,
+            _ => {
+                *last = Some(model.to_string());
+                true
+            }
+        }
+    }
+
+    /// The model used for the most recent embeddings
+    pub fn last_model(&self) -> Option<String> {
+        self.last_model.lock().clone()
+    }
+
+    /// Whether the model changed and existing embeddings need regenerating
+    pub fn reembed_needed(&self) -> bool {
+        self.reembed_needed.load(Ordering::Relaxed)
+    }
+
+    /// Accept `model` once a re-embed with it has been scheduled
+    pub fn reset(&self, model: &str) {
+        *self.last_model.lock() = Some(model.to_string());
+        self.reembed_needed.store(false, Ordering::Relaxed);
+    }
+}
+
+/// Serialize `payload` and send it to `events`
+pub(crate) fn emit_to<S: serde::Serialize>(events: &dyn EventSink, event: &str, payload: S) {
+    match serde_json::to_value(payload) {
+        Ok(value) => events.emit_event(event, value),
+        Err(e) => tracing::warn!("Failed to serialize {} payload: {}", event, e),
+    }
+}
+
 /// Background worker that processes embedding and graph jobs
 pub struct BackgroundWorker {
     db: Database,
     vector_store: Arc<VectorStore>,
     ollama: Arc<RwLock<OllamaClient>>,
+    rate_limiter: Arc<RateLimiter>,
     job_receiver: async_channel::Receiver<BackgroundJob>,
     paused: Arc<AtomicBool>,
+    shutting_down: Arc<AtomicBool>,
     config: WorkerConfig,
+    events: Option<Arc<dyn EventSink>>,
+    models: Arc<ModelTracker>,
+    heartbeat: Arc<Heartbeat>,
+    hash_backfill: Arc<HashBackfill>,
+    hash_limiter: RateLimiter,
+    progress: Arc<RwLock<Option<EmbeddingProgress>>>,
 }
 
 impl BackgroundWorker {
@@ -51,16 +506,93 @@ impl BackgroundWorker {
         db: Database,
         vector_store: Arc<VectorStore>,
         ollama: Arc<RwLock<OllamaClient>>,
+        rate_limiter: Arc<RateLimiter>,
         job_receiver: async_channel::Receiver<BackgroundJob>,
         paused: Arc<AtomicBool>,
     ) -> Self {
+        let config = WorkerConfig::default();
+        let hash_limiter = RateLimiter::new(config.hashes_per_second);
         Self {
             db,
             vector_store,
             ollama,
+            rate_limiter,
             job_receiver,
             paused,
-            config: WorkerConfig::default(),
+            shutting_down: Arc::new(AtomicBool::new(false)),
+            config,
+            events: None,
+            models: Arc::new(ModelTracker::default()),
+            heartbeat: Arc::new(Heartbeat::default()),
+            hash_backfill: Arc::new(HashBackfill::default()),
+            hash_limiter,
+            progress: Arc::new(RwLock::new(None)),
+        }
+    }
+
+    /// Tracker for the embedding model in use
+    pub fn model_tracker(&self) -> Arc<ModelTracker> {
+        self.models.clone()
+    }
+
+    /// Check the model against the last one used, emitting
+    /// `embeddings:model-changed` the first time a change is seen
+    fn check_model(&self, model: &str) -> bool {
+        let already_flagged = self.models.reembed_needed();
+        if self.models.check(model) {
+            return true;
+        }
+        if !already_flagged {
+            let previous = self.models.last_model().unwrap_or_default();
+            tracing::warn!(
+                "Embedding model changed from {} to {}; stopping until re-embed",
+                previous,
+                model
+            );
+            self.emit(
+                "embeddings:model-changed",
+                ModelChanged { previous, current: model.to_string() },
+            );
+        }
+        false
+    }
+
+    /// Send worker events (e.g. `recommendations:updated`) to the given sink
+    pub fn with_events(mut self, events: Arc<dyn EventSink>) -> Self {
+        self.events = Some(events);
+        self
+    }
+
+    /// Report liveness to the given heartbeat (see `get_processing_status`)
+    pub fn with_heartbeat(mut self, heartbeat: Arc<Heartbeat>) -> Self {
+        self.heartbeat = heartbeat;
+        self
+    }
+
+    /// Record the latest `embeddings:progress` of queued embeddings in
+    /// `progress` (cleared when a run ends), for `get_processing_status`
+    pub fn with_progress(mut self, progress: Arc<RwLock<Option<EmbeddingProgress>>>) -> Self {
+        self.progress = progress;
+        self
+    }
+
+    /// Stop after the job in hand once `shutting_down` is set, skipping jobs
+    /// still in the channel (queued embeddings stay in the database)
+    pub fn with_shutdown(mut self, shutting_down: Arc<AtomicBool>) -> Self {
+        self.shutting_down = shutting_down;
+        self
+    }
+
+    /// Report hash job progress against the given backfill
+    pub fn with_hash_backfill(mut self, hash_backfill: Arc<HashBackfill>) -> Self {
+        self.hash_backfill = hash_backfill;
+        self
+    }
+
+    /// Emit an event if a sink is attached
+    fn emit<S: serde::Serialize>(&self, event: &str, payload: S) {
+        if let Some(ref events) = self.events {
+            emit_to(events.as_ref(), event, payload);
         }
     }
 
@@ -68,16 +600,35 @@ impl BackgroundWorker {
     pub async fn run(&self) {
         tracing::info!("Background worker started");
 
+        // Jobs a previous worker was in the middle of start over
+        match self.db.requeue_running_embedding_jobs() {
+            Ok(0) => {}
+            Ok(requeued) => tracing::info!("Requeued {} interrupted embedding jobs", requeued),
+            Err(e) => tracing::warn!("Failed to requeue interrupted embedding jobs: {}", e),
+        }
+
         loop {
+            self.heartbeat.beat();
+
             // Check for shutdown or pause
+            if self.shutting_down.load(Ordering::Relaxed) {
+                tracing::info!("Background worker shutting down");
+                break;
+            }
             if self.paused.load(Ordering::Relaxed) {
                 tokio::time::sleep(Duration::from_millis(500)).await;
                 continue;
             }
 
-            // Wait for next job
-            match self.job_receiver.recv().await {
-                Ok(job) => {
+            // Wait for next job, waking up now and then to keep the heartbeat
+            // fresh and pick up queued embeddings (e.g. left from the last run)
+            match tokio::time::timeout(IDLE_HEARTBEAT, self.job_receiver.recv()).await {
+                Err(_) => {
+                    if let Err(e) = self.drain_embedding_queue().await {
+                        tracing::error!("Embedding queue error: {}", e);
+                    }
+                }
+                Ok(Ok(job)) => {
                     if matches!(job, BackgroundJob::Shutdown) {
                         tracing::info!("Background worker shutting down");
                         break;
@@ -86,11 +637,8 @@ impl BackgroundWorker {
                     if let Err(e) = self.process_job(job).await {
                         tracing::error!("Job processing error: {}", e);
                     }
-
-                    // Rate limiting
-                    tokio::time::sleep(Duration::from_millis(self.config.job_delay_ms)).await;
                 }
-                Err(_) => {
+                Ok(Err(_)) => {
                     // Channel closed, exit
                     break;
                 }
@@ -101,12 +649,23 @@ impl BackgroundWorker {
     /// Process a single job
     async fn process_job(&self, job: BackgroundJob) -> AppResult<()> {
         match job {
-            BackgroundJob::GenerateEmbedding { book_id, priority: _ } => {
-                self.generate_embedding(book_id).await
+            BackgroundJob::GenerateEmbedding { book_id, priority } => {
+                self.db.enqueue_embedding_jobs(&[book_id], priority)?;
+                self.drain_embedding_queue().await.map(|_| ())
+            }
+            BackgroundJob::GenerateEmbeddings { book_ids } => {
+                self.db.enqueue_embedding_jobs(&book_ids, 0)?;
+                self.drain_embedding_queue().await.map(|_| ())
             }
+            BackgroundJob::ProcessEmbeddingQueue => self.drain_embedding_queue().await.map(|_| ()),
             BackgroundJob::UpdateGraphEdges { book_id } => {
                 self.update_graph_edges(book_id).await
             }
+            BackgroundJob::ComputeHash { book_id } => {
+                let result = self.compute_hash(book_id).await;
+                self.emit("hash:progress", self.hash_backfill.finish(book_id));
+                result
+            }
             BackgroundJob::ScanLibrary { library_id } => {
                 tracing::info!("Library scan requested for {}", library_id);
                 // Scanning is handled by the command directly
@@ -116,10 +675,104 @@ impl BackgroundWorker {
         }
     }
 
+    /// Hash a book's file, unless it already has a hash
+    ///
+    /// Hashing reads the whole file, so jobs are throttled to
+    /// `hashes_per_second` to leave disk bandwidth for everything else.
+    async fn compute_hash(&self, book_id: i64) -> AppResult<()> {
+        let book = self.db.get_book(book_id)?;
+        if book.file_hash.is_some() {
+            return Ok(());
+        }
+
+        self.hash_limiter.acquire().await;
+        let hash = tokio::task::spawn_blocking(move || calculate_file_hash(std::path::Path::new(&book.path)));
+        match hash.await {
+            Ok(hash) => self.db.set_file_hashes_batch(&[(book_id, hash?)]),
+            Err(e) => Err(crate::AppError::Io(std::io::Error::other(format!("Hashing panicked: {}", e)))),
+        }
+    }
+
+    /// Embed queued books until the queue is empty or processing is paused
+    /// or shutting down
+    ///
+    /// Failed jobs are retried up to `max_retries` times. A transient error
+    /// (e.g. Ollama being down) or a model change stops the run, leaving the
+    /// rest queued for the next one. Emits `embeddings:progress` after each
+    /// book. Returns the number of jobs completed.
+    async fn drain_embedding_queue(&self) -> AppResult<usize> {
+        let mut completed = 0;
+        let mut failed = 0;
+        let mut eta = EtaEstimator::new();
+
+        while !self.paused.load(Ordering::Relaxed)
+            && !self.shutting_down.load(Ordering::Relaxed)
+            && !self.models.reembed_needed()
+        {
+            self.heartbeat.beat();
+            let Some(job) = self.db.claim_embedding_job()? else {
+                break;
+            };
+
+            let result = self.generate_embedding(job.book_id).await;
+            let stop = match result {
+                // The model changed, so the book was left unembedded
+                Ok(()) if self.models.reembed_needed() => {
+                    self.db.release_embedding_job(job.book_id)?;
+                    continue;
+                }
+                Ok(()) => {
+                    self.db.complete_embedding_job(job.book_id)?;
+                    completed += 1;
+                    false
+                }
+                Err(e) => {
+                    let retry = self.db.fail_embedding_job(job.book_id, &e.to_string(), self.config.max_retries + 1)?;
+                    if retry {
+                        self.db.update_embedding_status(job.book_id, "pending")?;
+                    }
+                    tracing::warn!(
+                        "Failed to embed book {} (attempt {}, {}): {}",
+                        job.book_id,
+                        job.attempts,
+                        if retry { "will retry" } else { "giving up" },
+                        e
+                    );
+                    failed += 1;
+                    e.is_transient()
+                }
+            };
+
+            eta.record();
+            let remaining = self.db.count_pending_embedding_jobs()?;
+            let progress = EmbeddingProgress {
+                book_id: job.book_id,
+                title: self.db.get_book(job.book_id).map(|book| book.title).unwrap_or_default(),
+                processed: completed,
+                failed,
+                remaining,
+                eta_seconds: eta.eta_seconds(remaining),
+            };
+            *self.progress.write() = Some(progress.clone());
+            self.emit("embeddings:progress", progress);
+
+            if stop {
+                break;
+            }
+        }
+
+        if completed + failed > 0 {
+            *self.progress.write() = None;
+        }
+        Ok(completed)
+    }
+
     /// Generate embedding for a book
     async fn generate_embedding(&self, book_id: i64) -> AppResult<()> {
-        // Check if already has embedding
-        if self.vector_store.has_embedding(book_id) {
+        // Check if already has an up-to-date embedding
+        if self.vector_store.has_embedding(book_id)
+            && !self.vector_store.is_stale(book_id, EMBEDDING_TEXT_VERSION)
+        {
             tracing::debug!("Book {} already has embedding", book_id);
             return Ok(());
         }
@@ -128,21 +781,27 @@ impl BackgroundWorker {
         let book = self.db.get_book(book_id)?;
 
         // Build text for embedding
-        let text = book_to_embedding_text(
-            &book.title,
-            book.author.as_deref(),
-            book.description.as_deref(),
-            book.series.as_deref(),
-        );
+        let config = self.db.get_settings()?.embedding_fields;
+        let tags = self.db.get_book_tags(book_id)?;
+        let excerpt = chapter_excerpt(&book, &config);
+        let input = EmbeddingInput::from_book(&book, &tags).with_excerpt(excerpt.as_deref());
+        let text = book_to_embedding_text(&input, &config);
 
-        // Generate embedding
-        let embedding = {
+        // Release lock before async call
+        let (endpoint, model) = {
             let ollama = self.ollama.read();
-            let endpoint = ollama.endpoint().to_string();
-            let model = ollama.model().to_string();
-            drop(ollama); // Release lock before async call
+            (ollama.endpoint().to_string(), ollama.model().to_string())
+        };
 
+        // Leave the book pending if the model changed since the last embedding
+        if !self.check_model(&model) {
+            return Ok(());
+        }
+
+        // Generate embedding
+        let embedding = {
             let client = OllamaClient::new(endpoint, model.clone());
+            self.rate_limiter.acquire().await;
             match client.embed(&text).await {
                 Ok(emb) => emb,
                 Err(e) => {
@@ -154,13 +813,23 @@ impl BackgroundWorker {
             }
         };
 
+        // Settings may have changed while the request was in flight
+        let current_model = self.ollama.read().model().to_string();
+        if current_model != model {
+            self.check_model(&current_model);
+            self.db.update_embedding_status(book_id, "pending")?;
+            return Ok(());
+        }
+
         // Store embedding
-        let model = self.ollama.read().model().to_string();
-        let text_hash = format!("{:x}", md5_hash(&text));
-        self.vector_store.store_embedding(book_id, &embedding, &model, Some(&text_hash))?;
+        let text_hash = embedding_text_hash(&text, &config);
+        self.vector_store.store_embedding(book_id, &embedding, &model, Some(&text_hash), EMBEDDING_TEXT_VERSION)?;
 
         // Update book status
         self.db.update_embedding_status(book_id, "complete")?;
+        if let Err(e) = precompute_similar_books(&self.db, &self.vector_store, book_id) {
+            tracing::warn!("Failed to cache similar books for book {}: {}", book_id, e);
+        }
 
         tracing::info!("Generated embedding for book {}: {}", book_id, book.title);
 
@@ -172,44 +841,19 @@ impl BackgroundWorker {
 
     /// Update graph edges for a book based on embedding similarity
     async fn update_graph_edges(&self, book_id: i64) -> AppResult<()> {
-        // Find similar books by embedding
-        let similar = self.vector_store.find_similar_to_book(book_id, 50);
-
-        if similar.is_empty() {
-            return Ok(());
-        }
-
-        // Get book metadata for edge weight computation
-        let source_book = self.db.get_book(book_id)?;
-
-        let mut edges_to_insert = Vec::new();
-
-        for (target_id, embedding_sim) in similar {
-            if embedding_sim < 0.3 {
-                continue; // Skip low similarity
-            }
-
-            if let Ok(target_book) = self.db.get_book(target_id) {
-                // Get ALL qualifying edge types (content, author, series)
-                let all_edges = compute_all_edge_weights(
-                    &source_book,
-                    &target_book,
-                    Some(embedding_sim),
-                );
-
-                // Store each qualifying edge type separately
-                for (weight, edge_type) in all_edges {
-                    if weight >= 0.3 {
-                        edges_to_insert.push((book_id, target_id, edge_type, weight));
-                    }
-                }
-            }
-        }
+        let config = EdgeBuildConfig {
+            max_degree: self.config.max_degree,
+            ..EdgeBuildConfig::load(&self.db)
+        };
+        let edges_to_insert = build_edges_for_book(&self.db, &self.vector_store, book_id, &config, None)?;
 
         // Batch insert edges
         if !edges_to_insert.is_empty() {
             self.db.insert_edges_batch(&edges_to_insert)?;
             tracing::debug!("Inserted {} edges for book {}", edges_to_insert.len(), book_id);
+
+            // Let the frontend refresh this book's recommendations
+            self.emit("recommendations:updated", RecommendationsUpdated { book_id });
         }
 
         Ok(())
@@ -221,6 +865,8 @@ pub async fn process_pending_embeddings(
     db: &Database,
     vector_store: &Arc<VectorStore>,
     ollama: &Arc<RwLock<OllamaClient>>,
+    rate_limiter: &RateLimiter,
+    models: &ModelTracker,
     paused: &Arc<AtomicBool>,
     batch_size: usize,
 ) -> AppResult<usize> {
@@ -231,6 +877,7 @@ pub async fn process_pending_embeddings(
         return Ok(0);
     }
 
+    let config = db.get_settings()?.embedding_fields;
     let mut processed = 0;
 
     for book_id in pending_books {
@@ -238,8 +885,8 @@ pub async fn process_pending_embeddings(
             break;
         }
 
-        // Check if already has embedding
-        if vector_store.has_embedding(book_id) {
+        // Check if already has an up-to-date embedding
+        if vector_store.has_embedding(book_id) && !vector_store.is_stale(book_id, EMBEDDING_TEXT_VERSION) {
             db.update_embedding_status(book_id, "complete")?;
             processed += 1;
             continue;
@@ -247,25 +894,39 @@ pub async fn process_pending_embeddings(
 
         // Get book and generate embedding
         if let Ok(book) = db.get_book(book_id) {
-            let text = book_to_embedding_text(
-                &book.title,
-                book.author.as_deref(),
-                book.description.as_deref(),
-                book.series.as_deref(),
-            );
+            let tags = db.get_book_tags(book_id)?;
+            let excerpt = chapter_excerpt(&book, &config);
+            let input = EmbeddingInput::from_book(&book, &tags).with_excerpt(excerpt.as_deref());
+            let text = book_to_embedding_text(&input, &config);
 
             let (endpoint, model) = {
                 let o = ollama.read();
                 (o.endpoint().to_string(), o.model().to_string())
             };
 
+            // Stop the batch if the model changed; remaining books stay pending
+            if !models.check(&model) {
+                break;
+            }
+
             let client = OllamaClient::new(endpoint, model.clone());
 
+            rate_limiter.acquire().await;
             match client.embed(&text).await {
+                Ok(_) if ollama.read().model() != model => {
+                    models.check(ollama.read().model());
+                    break;
+                }
                 Ok(embedding) => {
-                    let text_hash = format!("{:x}", md5_hash(&text));
-                    if vector_store.store_embedding(book_id, &embedding, &model, Some(&text_hash)).is_ok() {
+                    let text_hash = embedding_text_hash(&text, &config);
+                    if vector_store
+                        .store_embedding(book_id, &embedding, &model, Some(&text_hash), EMBEDDING_TEXT_VERSION)
+                        .is_ok()
+                    {
                         db.update_embedding_status(book_id, "complete")?;
+                        if let Err(e) = precompute_similar_books(db, vector_store, book_id) {
+                            tracing::warn!("Failed to cache similar books for book {}: {}", book_id, e);
+                        }
                         processed += 1;
                     }
                 }
@@ -274,36 +935,787 @@ pub async fn process_pending_embeddings(
                     db.update_embedding_status(book_id, "failed")?;
                 }
             }
-
-            // Small delay between API calls
-            tokio::time::sleep(Duration::from_millis(50)).await;
         }
     }
 
     Ok(processed)
 }
 
-/// Simple MD5 hash for text (used for change detection)
-fn md5_hash(text: &str) -> u128 {
-    use std::collections::hash_map::DefaultHasher;
-    use std::hash::{Hash, Hasher};
+/// Embed every pending book, `concurrency` requests at a time
+///
+/// Loops until the queue is empty or `paused`/`cancelled` is set, emitting
+/// `embeddings:progress` after each book and `recommendations:updated` when
+/// its edges are stored. Each book is attempted at most once per run, so
+/// books that keep failing to store can't stall the loop.
+pub async fn process_all_pending(ctx: &EmbeddingContext<'_>) -> AppResult<DrainResult> {
+    let EmbeddingContext { db, vector_store, backend, rate_limiter, paused, cancelled, concurrency, events } = *ctx;
+    let start = Instant::now();
+    let concurrency = concurrency.max(1);
+    let config = db.get_settings()?.embedding_fields;
+    let model = backend.model().to_string();
+    let total = db.get_stats()?.pending_embeddings.max(0) as usize;
 
-    let mut hasher = DefaultHasher::new();
-    text.hash(&mut hasher);
-    hasher.finish() as u128
+    let mut result = DrainResult::default();
+    let mut attempted: HashSet<i64> = HashSet::new();
+    let mut eta = EtaEstimator::new();
+    let mut unavailable_streak = 0;
+
+    loop {
+        let batch: Vec<i64> = db
+            .get_pending_embedding_books((attempted.len() + concurrency) as i64)?
+            .into_iter()
+            .filter(|id| !attempted.contains(id))
+            .take(concurrency)
+            .collect();
+        if batch.is_empty() {
+            break;
+        }
+
+        // Build texts up front; books without a description wait for metadata
+        let mut jobs = Vec::with_capacity(batch.len());
+        for book_id in batch {
+            attempted.insert(book_id);
+            let Ok(book) = db.get_book(book_id) else {
+                continue;
+            };
+            let excerpt = chapter_excerpt(&book, &config);
+            if !has_description(&book) && excerpt.is_none() {
+                db.update_embedding_status(book_id, "needs_metadata")?;
+                result.skipped += 1;
+                continue;
+            }
+            let tags = db.get_book_tags(book_id)?;
+            let input = EmbeddingInput::from_book(&book, &tags).with_excerpt(excerpt.as_deref());
+            let text = book_to_embedding_text(&input, &config);
+            jobs.push((book, text));
+        }
+
+        let embeddings = futures::future::join_all(jobs.iter().map(|(_, text)| async move {
+            rate_limiter.acquire().await;
+            backend.embed(text).await
+        }))
+        .await;
+
+        for ((book, text), embedding) in jobs.iter().zip(embeddings) {
+            let text_hash = embedding_text_hash(text, &config);
+            match store_embedding_result(db, vector_store, book.id, &model, &text_hash, embedding, events)? {
+                EmbedOutcome::Stored => {
+                    result.processed += 1;
+                    unavailable_streak = 0;
+                }
+                EmbedOutcome::Failed => {
+                    result.failed += 1;
+                    unavailable_streak = 0;
+                }
+                EmbedOutcome::Unavailable => unavailable_streak += 1,
+            }
+
+            eta.record();
+            let remaining = total.saturating_sub(result.processed + result.failed + result.skipped);
+            emit_to(
+                events,
+                "embeddings:progress",
+                EmbeddingProgress {
+                    book_id: book.id,
+                    title: book.title.clone(),
+                    processed: result.processed,
+                    failed: result.failed,
+                    remaining,
+                    eta_seconds: eta.eta_seconds(remaining),
+                },
+            );
+        }
+
+        if unavailable_streak >= MAX_CONSECUTIVE_UNAVAILABLE {
+            tracing::warn!("Ollama unreachable; stopping with {} books left pending", total.saturating_sub(result.processed + result.failed + result.skipped));
+            result.backend_unavailable = true;
+            result.interrupted = true;
+            break;
+        }
+        if paused.load(Ordering::Relaxed) || cancelled.load(Ordering::Relaxed) {
+            result.interrupted = true;
+            break;
+        }
+    }
+
+    result.duration_ms = start.elapsed().as_millis() as u64;
+    Ok(result)
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::db::test_book;
+    use crate::vector::EMBEDDING_DIM;
+    use parking_lot::Mutex;
+
+    /// Event sink that records everything emitted
+    #[derive(Default)]
+    struct CapturedEvents(Mutex<Vec<(String, serde_json::Value)>>);
+
+    impl EventSink for CapturedEvents {
+        fn emit_event(&self, event: &str, payload: serde_json::Value) {
+            self.0.lock().push((event.to_string(), payload));
+        }
+    }
+
+    #[tokio::test]
+    async fn test_edge_insertion_emits_recommendations_updated() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let db_path = dir.path().join("test.db");
+        let db = Database::new(&db_path).unwrap();
+        let vector_store = Arc::new(VectorStore::new(db_path.to_str().unwrap()).unwrap());
+
+        let mut ids = Vec::new();
+        for title in ["a", "b"] {
+            let id = db.insert_book(&test_book(title, None)).unwrap();
+            let mut embedding = vec![0.0f32; EMBEDDING_DIM];
+            embedding[0] = 1.0;
+            vector_store.store_embedding(id, &embedding, "test", None, 1).unwrap();
+            ids.push(id);
+        }
+
+        let events = Arc::new(CapturedEvents::default());
+        let (_sender, receiver) = async_channel::unbounded();
+        let worker = BackgroundWorker::new(
+            db,
+            vector_store,
+            Arc::new(RwLock::new(OllamaClient::new(
+                "http://localhost:11434".to_string(),
+                "nomic-embed-text".to_string(),
+            ))),
+            Arc::new(RateLimiter::default()),
+            receiver,
+            Arc::new(AtomicBool::new(false)),
+        )
+        .with_events(events.clone());
+
+        worker.update_graph_edges(ids[0]).await.unwrap();
+
+        let captured = events.0.lock();
+        assert_eq!(captured.len(), 1);
+        assert_eq!(captured[0].0, "recommendations:updated");
+        assert_eq!(captured[0].1, serde_json::json!({ "bookId": ids[0] }));
+    }
+
+    #[tokio::test]
+    async fn test_hash_jobs_fill_missing_hashes_and_report_progress() {
+        let temp = tempfile::TempDir::new().unwrap();
+        let db = Database::new_in_memory().unwrap();
+        for (id, title) in [(1, "First"), (2, "Second")] {
+            let path = temp.path().join(format!("{}.epub", title));
+            std::fs::write(&path, crate::epub::test_epub_bytes(title, "Author")).unwrap();
+            db.with_conn(|conn| {
+                conn.execute(
+                    "INSERT INTO books (id, path, title) VALUES (?, ?, ?)",
+                    rusqlite::params![id, path.to_string_lossy(), title],
+                )?;
+                Ok(())
+            })
+            .unwrap();
+        }
+
+        let backfill = Arc::new(HashBackfill::default());
+        assert_eq!(backfill.start([1, 2]), vec![1, 2]);
+        // Already pending books aren't queued again
+        assert!(backfill.start([2]).is_empty());
+
+        let events = Arc::new(CapturedEvents::default());
+        let (_sender, receiver) = async_channel::unbounded();
+        let worker = BackgroundWorker::new(
+            db.clone(),
+            Arc::new(VectorStore::new(db.path()).unwrap()),
+            Arc::new(RwLock::new(OllamaClient::new(
+                "http://localhost:11434".to_string(),
+                "nomic-embed-text".to_string(),
+            ))),
+            Arc::new(RateLimiter::default()),
+            receiver,
+            Arc::new(AtomicBool::new(false)),
+        )
+        .with_events(events.clone())
+        .with_hash_backfill(backfill.clone());
+
+        for book_id in [1, 2] {
+            worker.process_job(BackgroundJob::ComputeHash { book_id }).await.unwrap();
+        }
+
+        let hash = db.get_book(1).unwrap().file_hash.unwrap();
+        assert_eq!(hash.len(), 64);
+        assert!(db.get_unhashed_books(None).unwrap().is_empty());
+
+        let captured = events.0.lock();
+        let progress: Vec<(u64, u64)> = captured
+            .iter()
+            .filter(|(event, _)| event == "hash:progress")
+            .map(|(_, p)| (p["processed"].as_u64().unwrap(), p["total"].as_u64().unwrap()))
+            .collect();
+        assert_eq!(progress, vec![(1, 2), (2, 2)]);
+
+        // A finished backfill starts counting afresh
+        assert_eq!(backfill.start([3]), vec![3]);
+        assert_eq!(backfill.finish(3), HashProgress { processed: 1, total: 1 });
+    }
+
+    #[test]
+    fn test_embedding_fills_similar_cache_in_rank_order() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let db_path = dir.path().join("test.db");
+        let db = Database::new(&db_path).unwrap();
+        let vector_store = VectorStore::new(db_path.to_str().unwrap()).unwrap();
+        db.with_conn(|conn| {
+            conn.execute_batch(
+                "INSERT INTO books (id, path, title) VALUES
+                    (1, '/b/1.epub', 'Source'), (2, '/b/2.epub', 'Far'),
+                    (3, '/b/3.epub', 'Near'), (4, '/b/4.epub', 'Middle')",
+            )?;
+            Ok(())
+        })
+        .unwrap();
+        db.update_setting("similar_cache_size", "2").unwrap();
+
+        let embedding = |x: f32, y: f32| {
+            let mut embedding = vec![0.0f32; EMBEDDING_DIM];
+            embedding[0] = x;
+            embedding[1] = y;
+            embedding
+        };
+        for (id, x, y) in [(2, 0.0, 1.0), (3, 1.0, 0.1), (4, 1.0, 1.0)] {
+            vector_store.store_embedding(id, &embedding(x, y), "test", None, 1).unwrap();
+        }
+
+        let events = CapturedEvents::default();
+        let store = |e: Vec<f32>| store_embedding_result(&db, &vector_store, 1, "test", "hash", Ok(e), &events);
+        assert_eq!(store(embedding(1.0, 0.0)).unwrap(), EmbedOutcome::Stored);
+
+        // Weak neighbours are kept too, best first, up to the configured size
+        let cached = db.get_similar_cache(1, 10).unwrap();
+        assert_eq!(cached.iter().map(|&(id, _)| id).collect::<Vec<_>>(), vec![3, 4]);
+        assert!(cached[0].1 > cached[1].1);
+
+        // Re-embedding replaces the book's list and drops stale entries elsewhere
+        db.replace_similar_cache(2, &[(1, 0.2), (4, 0.1)]).unwrap();
+        assert_eq!(store(embedding(0.0, 1.0)).unwrap(), EmbedOutcome::Stored);
+        assert_eq!(db.get_similar_cache(1, 10).unwrap().iter().map(|&(id, _)| id).collect::<Vec<_>>(), vec![2, 4]);
+        assert_eq!(db.get_similar_cache(2, 10).unwrap(), vec![(4, 0.1)]);
+    }
+
+    #[test]
+    fn test_eta_averages_recent_gaps() {
+        let mut eta = EtaEstimator::new();
+        assert_eq!(eta.eta_seconds(10), None);
+
+        // A slow start rolls out of the window as books speed up
+        eta.recent = (0..ETA_WINDOW).map(|_| 10.0).collect();
+        for _ in 0..ETA_WINDOW / 2 {
+            eta.record();
+        }
+        assert_eq!(eta.recent.len(), ETA_WINDOW);
+        assert_eq!(eta.eta_seconds(4), Some(20));
+        assert_eq!(eta.eta_seconds(0), Some(0));
+    }
 
     #[test]
-    fn test_md5_hash() {
-        let hash1 = md5_hash("hello world");
-        let hash2 = md5_hash("hello world");
-        let hash3 = md5_hash("different text");
+    fn test_old_heartbeat_reports_stalled() {
+        let now = 1_700_000_000;
+        let heartbeat = Heartbeat::default();
+
+        // A worker that never ran isn't stalled, just absent
+        assert!(!heartbeat.is_stalled(now, STALL_THRESHOLD_SECS, true));
+
+        heartbeat.beat_at(now - 10);
+        assert!(!heartbeat.is_stalled(now, STALL_THRESHOLD_SECS, true));
+
+        heartbeat.beat_at(now - STALL_THRESHOLD_SECS - 1);
+        assert!(heartbeat.is_stalled(now, STALL_THRESHOLD_SECS, true));
+        // Nothing left to do: a quiet worker is fine
+        assert!(!heartbeat.is_stalled(now, STALL_THRESHOLD_SECS, false));
+    }
+
+    #[tokio::test]
+    async fn test_worker_loop_beats() {
+        let db = Database::new_in_memory().unwrap();
+        let vector_store = Arc::new(VectorStore::new(db.path()).unwrap());
+        let (sender, receiver) = async_channel::unbounded();
+        let heartbeat = Arc::new(Heartbeat::default());
+        heartbeat.beat_at(1);
+        let worker = BackgroundWorker::new(
+            db,
+            vector_store,
+            Arc::new(RwLock::new(OllamaClient::new(
+                "http://localhost:11434".to_string(),
+                "nomic-embed-text".to_string(),
+            ))),
+            Arc::new(RateLimiter::default()),
+            receiver,
+            Arc::new(AtomicBool::new(false)),
+        )
+        .with_heartbeat(heartbeat.clone());
+
+        sender.send(BackgroundJob::Shutdown).await.unwrap();
+        worker.run().await;
+        assert!(heartbeat.last().unwrap() > 1);
+    }
+
+    #[tokio::test]
+    async fn test_worker_exits_promptly_on_shutdown() {
+        let db = Database::new_in_memory().unwrap();
+        db.with_conn(|conn| {
+            conn.execute_batch("INSERT INTO books (id, path, title) VALUES (1, '/b/a.epub', 'A')")?;
+            Ok(())
+        })
+        .unwrap();
+        let vector_store = Arc::new(VectorStore::new(db.path()).unwrap());
+        let (sender, receiver) = async_channel::unbounded();
+        let shutting_down = Arc::new(AtomicBool::new(false));
+        let worker = BackgroundWorker::new(
+            db,
+            vector_store,
+            Arc::new(RwLock::new(OllamaClient::new(
+                "http://127.0.0.1:9".to_string(),
+                "nomic-embed-text".to_string(),
+            ))),
+            Arc::new(RateLimiter::default()),
+            receiver,
+            Arc::new(AtomicBool::new(false)),
+        )
+        .with_shutdown(shutting_down.clone());
+        let worker = Arc::new(worker);
+
+        // An idle worker stops well before its next heartbeat
+        let task = tokio::spawn({
+            let worker = worker.clone();
+            async move { worker.run().await }
+        });
+        sender.send(BackgroundJob::Shutdown).await.unwrap();
+        tokio::time::timeout(Duration::from_secs(2), task).await.unwrap().unwrap();
+
+        // Once shutting down, work still in the channel is skipped and queued
+        // embeddings stay in the database
+        sender.send(BackgroundJob::GenerateEmbedding { book_id: 1, priority: 0 }).await.unwrap();
+        sender.send(BackgroundJob::Shutdown).await.unwrap();
+        shutting_down.store(true, Ordering::Relaxed);
+        tokio::time::timeout(Duration::from_secs(2), worker.run()).await.unwrap();
+        assert_eq!(worker.db.get_embedding_job(1).unwrap(), None);
+    }
+
+    #[tokio::test]
+    async fn test_model_switch_stops_embedding() {
+        let db = Database::new_in_memory().unwrap();
+        db.with_conn(|conn| {
+            conn.execute_batch(
+                "INSERT INTO books (id, path, title, embedding_status) VALUES
+                    (1, '/b/a.epub', 'A', 'pending'),
+                    (2, '/b/b.epub', 'B', 'pending')",
+            )?;
+            Ok(())
+        })
+        .unwrap();
+        let vector_store = Arc::new(VectorStore::new(db.path()).unwrap());
+        let ollama = Arc::new(RwLock::new(OllamaClient::new(
+            "http://localhost:11434".to_string(),
+            "model-a".to_string(),
+        )));
+
+        let events = Arc::new(CapturedEvents::default());
+        let (_sender, receiver) = async_channel::unbounded();
+        let worker = BackgroundWorker::new(
+            db,
+            vector_store.clone(),
+            ollama.clone(),
+            Arc::new(RateLimiter::new(0.0)),
+            receiver,
+            Arc::new(AtomicBool::new(false)),
+        )
+        .with_events(events.clone());
+
+        // Earlier books were embedded with model-a, then settings switch models
+        let models = worker.model_tracker();
+        assert!(models.check("model-a"));
+        ollama.write().configure("http://localhost:11434".to_string(), "model-b".to_string());
+
+        worker.generate_embedding(1).await.unwrap();
+        worker.generate_embedding(2).await.unwrap();
+
+        // Nothing was embedded with the new model and books remain queued
+        assert!(models.reembed_needed());
+        assert!(!vector_store.has_embedding(1));
+        assert_eq!(worker.db.get_book(1).unwrap().embedding_status, "pending");
+        {
+            let captured = events.0.lock();
+            assert_eq!(captured.len(), 1);
+            assert_eq!(captured[0].0, "embeddings:model-changed");
+            assert_eq!(captured[0].1, serde_json::json!({ "previous": "model-a", "current": "model-b" }));
+        }
+
+        // The batch path stops too
+        let paused = Arc::new(AtomicBool::new(false));
+        let processed = process_pending_embeddings(
+            &worker.db,
+            &vector_store,
+            &ollama,
+            &RateLimiter::new(0.0),
+            &models,
+            &paused,
+            10,
+        )
+        .await
+        .unwrap();
+        assert_eq!(processed, 0);
+
+        models.reset("model-b");
+        assert!(!models.reembed_needed());
+        assert!(models.check("model-b"));
+    }
+
+    #[tokio::test]
+    async fn test_embedding_queue_records_failures() {
+        let db = Database::new_in_memory().unwrap();
+        db.with_conn(|conn| {
+            conn.execute_batch(
+                "INSERT INTO books (id, path, title, description, embedding_status) VALUES
+                    (1, '/b/a.epub', 'A', 'About A', 'pending'),
+                    (2, '/b/b.epub', 'B', 'About B', 'pending')",
+            )?;
+            Ok(())
+        })
+        .unwrap();
+        let vector_store = Arc::new(VectorStore::new(db.path()).unwrap());
+        // Nothing listens on the discard port, so every request fails to connect
+        let ollama = Arc::new(RwLock::new(OllamaClient::new(
+            "http://127.0.0.1:9".to_string(),
+            "nomic-embed-text".to_string(),
+        )));
+        let (_sender, receiver) = async_channel::unbounded();
+        let events = Arc::new(CapturedEvents::default());
+        let progress = Arc::new(RwLock::new(None));
+        let worker = BackgroundWorker::new(
+            db,
+            vector_store,
+            ollama,
+            Arc::new(RateLimiter::new(0.0)),
+            receiver,
+            Arc::new(AtomicBool::new(false)),
+        )
+        .with_events(events.clone())
+        .with_progress(progress.clone());
+
+        worker.db.enqueue_embedding_jobs(&[1], 0).unwrap();
+        worker.process_job(BackgroundJob::GenerateEmbedding { book_id: 2, priority: 100 }).await.unwrap();
+
+        // Progress is reported per book and cleared once the run ends
+        {
+            let captured = events.0.lock();
+            assert_eq!(captured.len(), 1);
+            assert_eq!(captured[0].0, "embeddings:progress");
+            assert_eq!(captured[0].1["title"], "B");
+            assert_eq!((captured[0].1["failed"].as_u64(), captured[0].1["remaining"].as_u64()), (Some(1), Some(2)));
+        }
+        assert!(progress.read().is_none());
+
+        // The unreachable backend stops the run after the high-priority book
+        let job = worker.db.get_embedding_job(2).unwrap().unwrap();
+        assert_eq!((job.status.as_str(), job.attempts), ("pending", 1));
+        assert!(job.last_error.is_some());
+        assert_eq!(worker.db.get_book(2).unwrap().embedding_status, "pending");
+        assert_eq!(worker.db.get_embedding_job(1).unwrap().unwrap().attempts, 0);
+
+        // Out of retries, the job fails until retried
+        for _ in 0..worker.config.max_retries {
+            worker.drain_embedding_queue().await.unwrap();
+        }
+        assert_eq!(worker.db.get_embedding_job(2).unwrap().unwrap().status, "failed");
+        assert_eq!(worker.db.get_book(2).unwrap().embedding_status, "failed");
+        assert_eq!(worker.db.get_embedding_job(1).unwrap().unwrap().status, "pending");
+    }
+
+    /// Embedding backend that returns a fixed vector and can cancel the run
+    /// after a number of calls
+    struct MockBackend {
+        calls: std::sync::atomic::AtomicUsize,
+        cancel_after: usize,
+        cancelled: Arc<AtomicBool>,
+    }
+
+    impl EmbeddingBackend for MockBackend {
+        fn model(&self) -> &str {
+            "mock"
+        }
+
+        fn embed<'a>(&'a self, _text: &'a str) -> futures::future::BoxFuture<'a, AppResult<Vec<f32>>> {
+            let calls = self.calls.fetch_add(1, Ordering::SeqCst) + 1;
+            if calls >= self.cancel_after {
+                self.cancelled.store(true, Ordering::SeqCst);
+            }
+            Box::pin(async { Ok(vec![0.5f32; EMBEDDING_DIM]) })
+        }
+    }
+
+    #[tokio::test]
+    async fn test_process_all_pending_drains_and_stops_on_cancel() {
+        let db = Database::new_in_memory().unwrap();
+        db.with_conn(|conn| {
+            for id in 1..=10 {
+                conn.execute(
+                    "INSERT INTO books (id, path, title, description, embedding_status) VALUES (?, ?, ?, ?, 'pending')",
+                    rusqlite::params![id, format!("/b/{}.epub", id), format!("Book {}", id), "A story"],
+                )?;
+            }
+            conn.execute(
+                "INSERT INTO books (id, path, title, embedding_status, date_added)
+                 VALUES (11, '/b/11.epub', 'No Blurb', 'pending', strftime('%s', 'now') + 60)",
+                [],
+            )?;
+            Ok(())
+        })
+        .unwrap();
+        let vector_store = VectorStore::new(db.path()).unwrap();
+        let paused = AtomicBool::new(false);
+        let cancelled = Arc::new(AtomicBool::new(false));
+        let events = CapturedEvents::default();
+
+        // The newest book has no description and is skipped in the first
+        // batch; cancelling part-way through stops after the books in flight
+        let backend = MockBackend {
+            calls: Default::default(),
+            cancel_after: 3,
+            cancelled: cancelled.clone(),
+        };
+        let rate_limiter = RateLimiter::new(0.0);
+        let ctx = EmbeddingContext {
+            db: &db,
+            vector_store: &vector_store,
+            backend: &backend,
+            rate_limiter: &rate_limiter,
+            paused: &paused,
+            cancelled: &cancelled,
+            concurrency: 2,
+            events: &events,
+        };
+        let result = process_all_pending(&ctx).await.unwrap();
+        assert!(result.interrupted);
+        assert_eq!((result.processed, result.skipped), (3, 1));
+        assert_eq!(db.get_book(11).unwrap().embedding_status, "needs_metadata");
+        assert_eq!(db.get_pending_embedding_books(100).unwrap().len(), 7);
+
+        // A fresh run drains the rest
+        cancelled.store(false, Ordering::SeqCst);
+        let backend = MockBackend {
+            calls: Default::default(),
+            cancel_after: usize::MAX,
+            cancelled: cancelled.clone(),
+        };
+        let rate_limiter = RateLimiter::new(0.0);
+        let ctx = EmbeddingContext {
+            db: &db,
+            vector_store: &vector_store,
+            backend: &backend,
+            rate_limiter: &rate_limiter,
+            paused: &paused,
+            cancelled: &cancelled,
+            concurrency: 2,
+            events: &events,
+        };
+        let result = process_all_pending(&ctx).await.unwrap();
+        assert!(!result.interrupted);
+        assert_eq!(result.processed, 7);
+        assert!(db.get_pending_embedding_books(100).unwrap().is_empty());
+        assert_eq!(vector_store.count().unwrap(), 10);
+
+        // One progress event per embedded book, counting down to zero
+        let captured = events.0.lock();
+        let progress: Vec<&serde_json::Value> = captured
+            .iter()
+            .filter(|(event, _)| event == "embeddings:progress")
+            .map(|(_, payload)| payload)
+            .collect();
+        assert_eq!(progress.len(), 10);
+        assert_eq!(progress.last().unwrap()["remaining"], 0);
+        assert!(progress.last().unwrap()["etaSeconds"].is_u64());
+    }
+
+    #[test]
+    fn test_chapter_excerpt_only_without_description() {
+        let temp = tempfile::TempDir::new().unwrap();
+        let path = temp.path().join("tale.epub");
+        std::fs::write(&path, crate::epub::test_epub_bytes("Tale", "Teller")).unwrap();
+
+        let db = Database::new_in_memory().unwrap();
+        db.with_conn(|conn| {
+            conn.execute(
+                "INSERT INTO books (id, path, title) VALUES (1, ?, 'Tale')",
+                [path.to_string_lossy()],
+            )?;
+            Ok(())
+        })
+        .unwrap();
+        let mut book = db.get_book(1).unwrap();
+
+        let mut config = EmbeddingTextConfig::default();
+        assert_eq!(chapter_excerpt(&book, &config), None);
+
+        config.excerpt_chars = 500;
+        let excerpt = chapter_excerpt(&book, &config);
+        assert_eq!(excerpt.as_deref(), Some("Once upon a time."));
+        let text = book_to_embedding_text(&EmbeddingInput::from_book(&book, &[]).with_excerpt(excerpt.as_deref()), &config);
+        assert_eq!(text, "Title: Tale\nExcerpt: Once upon a time.");
+
+        book.description = Some("A short tale".to_string());
+        assert_eq!(chapter_excerpt(&book, &config), None);
+    }
+
+    /// Backend that tracks how many requests are in flight at once and can
+    /// pause the run after a number of calls
+    struct ConcurrentBackend {
+        calls: std::sync::atomic::AtomicUsize,
+        in_flight: std::sync::atomic::AtomicUsize,
+        max_in_flight: std::sync::atomic::AtomicUsize,
+        pause_after: usize,
+        paused: Arc<AtomicBool>,
+    }
+
+    impl EmbeddingBackend for ConcurrentBackend {
+        fn model(&self) -> &str {
+            "mock"
+        }
+
+        fn embed<'a>(&'a self, _text: &'a str) -> futures::future::BoxFuture<'a, AppResult<Vec<f32>>> {
+            Box::pin(async move {
+                let calls = self.calls.fetch_add(1, Ordering::SeqCst) + 1;
+                if calls >= self.pause_after {
+                    self.paused.store(true, Ordering::SeqCst);
+                }
+                let in_flight = self.in_flight.fetch_add(1, Ordering::SeqCst) + 1;
+                self.max_in_flight.fetch_max(in_flight, Ordering::SeqCst);
+                tokio::time::sleep(Duration::from_millis(20)).await;
+                self.in_flight.fetch_sub(1, Ordering::SeqCst);
+                Ok(vec![0.5f32; EMBEDDING_DIM])
+            })
+        }
+    }
+
+    #[tokio::test]
+    async fn test_batch_runs_requests_concurrently_until_paused() {
+        let db = Database::new_in_memory().unwrap();
+        db.with_conn(|conn| {
+            for id in 1..=12 {
+                conn.execute(
+                    "INSERT INTO books (id, path, title, description, embedding_status) VALUES (?, ?, ?, ?, 'pending')",
+                    rusqlite::params![id, format!("/b/{}.epub", id), format!("Book {}", id), "A story"],
+                )?;
+            }
+            conn.execute("UPDATE books SET description = NULL WHERE id = 12", [])?;
+            Ok(())
+        })
+        .unwrap();
+        let vector_store = VectorStore::new(db.path()).unwrap();
+        let paused = Arc::new(AtomicBool::new(false));
+        let backend = ConcurrentBackend {
+            calls: Default::default(),
+            in_flight: Default::default(),
+            max_in_flight: Default::default(),
+            pause_after: 6,
+            paused: paused.clone(),
+        };
+
+        let events = Arc::new(CapturedEvents::default());
+        let ctx = EmbeddingContext {
+            db: &db,
+            vector_store: &vector_store,
+            backend: &backend,
+            rate_limiter: &RateLimiter::new(0.0),
+            paused: &paused,
+            cancelled: &AtomicBool::new(false),
+            concurrency: 3,
+            events: events.as_ref(),
+        };
+        let outcome = process_embedding_batch(&ctx, 20).await.unwrap();
+
+        // Never more than three at once; requests in flight when the pause
+        // came are still stored, and nothing new is sent
+        assert_eq!(backend.max_in_flight.load(Ordering::SeqCst), 3);
+        let calls = backend.calls.load(Ordering::SeqCst);
+        assert!((6..=8).contains(&calls), "{} requests sent", calls);
+        assert_eq!((outcome.processed, outcome.failed), (calls, 0));
+        assert!(outcome.interrupted);
+        assert_eq!(db.get_pending_embedding_books(100).unwrap().len(), 11 - calls);
+        let captured = events.0.lock();
+        assert_eq!(captured.iter().filter(|(event, _)| event == "embeddings:progress").count(), calls);
+        let last = &captured.last().unwrap().1;
+        assert_eq!(last["processed"].as_u64(), Some(calls as u64));
+        assert_eq!(last["remaining"].as_u64(), Some(11 - calls as u64));
+        assert!(last["etaSeconds"].is_u64());
+        assert_eq!(db.get_book(12).unwrap().embedding_status, "needs_metadata");
+    }
+
+    /// Backend that answers a few requests, then behaves like a stopped server
+    struct FailingBackend {
+        calls: std::sync::atomic::AtomicUsize,
+        up_for: usize,
+    }
+
+    impl EmbeddingBackend for FailingBackend {
+        fn model(&self) -> &str {
+            "mock"
+        }
+
+        fn embed<'a>(&'a self, _text: &'a str) -> futures::future::BoxFuture<'a, AppResult<Vec<f32>>> {
+            let calls = self.calls.fetch_add(1, Ordering::SeqCst) + 1;
+            let up = calls <= self.up_for;
+            Box::pin(async move {
+                if up {
+                    Ok(vec![0.5f32; EMBEDDING_DIM])
+                } else {
+                    Err(crate::AppError::OllamaUnavailable("Connection refused".to_string()))
+                }
+            })
+        }
+    }
+
+    #[tokio::test]
+    async fn test_batch_stops_when_backend_goes_down() {
+        let db = Database::new_in_memory().unwrap();
+        db.with_conn(|conn| {
+            for id in 1..=10 {
+                conn.execute(
+                    "INSERT INTO books (id, path, title, description, embedding_status) VALUES (?, ?, ?, ?, 'pending')",
+                    rusqlite::params![id, format!("/b/{}.epub", id), format!("Book {}", id), "A story"],
+                )?;
+            }
+            Ok(())
+        })
+        .unwrap();
+        let vector_store = VectorStore::new(db.path()).unwrap();
+        let backend = FailingBackend { calls: Default::default(), up_for: 4 };
+        let (rate_limiter, stop, events) = (RateLimiter::new(0.0), AtomicBool::new(false), CapturedEvents::default());
+        let ctx = EmbeddingContext {
+            db: &db,
+            vector_store: &vector_store,
+            backend: &backend,
+            rate_limiter: &rate_limiter,
+            paused: &stop,
+            cancelled: &stop,
+            concurrency: 1,
+            events: &events,
+        };
+
+        let outcome = process_embedding_batch(&ctx, 10).await.unwrap();
+
+        // Four embedded, then three refused connections end the batch
+        assert!(outcome.backend_unavailable);
+        assert_eq!((outcome.processed, outcome.failed), (4, 0));
+        assert_eq!(backend.calls.load(Ordering::SeqCst), 4 + MAX_CONSECUTIVE_UNAVAILABLE);
+        assert_eq!(db.get_pending_embedding_books(100).unwrap().len(), 6);
+        let query = crate::db::BookQuery {
+            embedding_status: Some("failed".to_string()),
+            ..Default::default()
+        };
+        assert_eq!(db.query_books(&query).unwrap().total, 0);
 
-        assert_eq!(hash1, hash2);
-        assert_ne!(hash1, hash3);
+        // Once Ollama is back the books left pending drain normally
+        let backend = FailingBackend { calls: Default::default(), up_for: usize::MAX };
+        let outcome = process_embedding_batch(&EmbeddingContext { backend: &backend, ..ctx }, 10).await.unwrap();
+        assert!(!outcome.backend_unavailable);
+        assert_eq!(outcome.processed, 6);
     }
 }
@@ -16,6 +16,29 @@ async function getInvoke() {
 // Types
 // ============================================
 
+/** Error thrown by every command; switch on `kind` to pick the right UI */
+export interface CommandError {
+	kind:
+		| 'database'
+		| 'io'
+		| 'epubParse'
+		| 'ollama'
+		| 'ollamaUnavailable'
+		| 'config'
+		| 'notFound'
+		| 'invalidInput'
+		| 'serialization'
+		| 'poolTimeout'
+		| 'enrichment'
+		| 'timeout'
+		| 'internal';
+	message: string;
+}
+
+export function isCommandError(error: unknown): error is CommandError {
+	return typeof error === 'object' && error !== null && 'kind' in error && 'message' in error;
+}
+
 export interface Book {
 	id: number;
 	path: string;
@@ -40,10 +63,19 @@ export interface Book {
 	dateIndexed: number | null;
 	embeddingStatus: EmbeddingStatus;
 	embeddingModel: string | null;
+	containerPath: string | null;
+	wordCount: number | null;
+	format: BookFormat;
+	/** 0.5 to 5 stars in half-star steps */
 	rating: number | null;
 	readStatus: ReadStatus | null;
+	notes: string | null;
+	progressPercent: number | null;
+	/** Reading position (EPUB CFI) */
+	progressLocation: string | null;
 }
 
+export type BookFormat = 'epub' | 'mobi' | 'azw3';
 export type ReadStatus = 'unread' | 'want' | 'reading' | 'finished' | 'abandoned';
 export type EmbeddingStatus = 'pending' | 'processing' | 'complete' | 'failed';
 
@@ -61,6 +93,7 @@ export interface Library {
 }
 
 export interface BookQuery {
+	/** Words, `"exact phrases"`, `prefix*`, `AND`/`OR`/`NOT`, and `field:term` (title, author, series, description, notes, tags) */
 	search?: string;
 	author?: string;
 	series?: string;
@@ -68,7 +101,13 @@ export interface BookQuery {
 	readStatus?: ReadStatus;
 	minRating?: number;
 	embeddingStatus?: EmbeddingStatus;
-	sortBy?: 'title' | 'author' | 'dateAdded' | 'rating' | 'series';
+	source?: string;
+	language?: string;
+	format?: BookFormat;
+	excludeSeries?: string;
+	collectionId?: number;
+	/** `relevance` ranks search matches best first; without a search it sorts by date added */
+	sortBy?: 'title' | 'author' | 'dateAdded' | 'rating' | 'series' | 'relevance';
 	sortOrder?: 'asc' | 'desc';
 	limit?: number;
 	offset?: number;
@@ -88,23 +127,58 @@ export interface ScanResult {
 	durationMs: number;
 }
 
+export interface CalibreSyncResult {
+	booksFound: number;
+	booksAdded: number;
+	booksUpdated: number;
+	booksUnchanged: number;
+	errors: string[];
+}
+
+export interface HashResult {
+	total: number;
+	hashed: number;
+	failed: number;
+	durationMs: number;
+}
+
+export interface ScanRecord {
+	id: number;
+	libraryId: number;
+	startedAt: number;
+	completedAt: number | null;
+	booksFound: number | null;
+	booksAdded: number | null;
+	booksUpdated: number | null;
+	errors: string[];
+}
+
 export interface Recommendation {
 	book: Book;
 	score: number;
 	reasons: RecommendationReason[];
 }
 
+export interface TimedRecommendation {
+	book: Book;
+	readingMinutes: number;
+	score: number;
+}
+
 export type RecommendationReason =
 	| { type: 'similarContent'; similarity: number }
 	| { type: 'sameAuthor'; author: string }
 	| { type: 'sameSeries'; series: string; position: string }
 	| { type: 'tagOverlap'; tags: string[] }
 	| { type: 'readersAlsoLiked'; basedOn: string }
-	| { type: 'nextInSeries'; previous: string };
+	| { type: 'nextInSeries'; previous: string }
+	| { type: 'relatedAuthor'; author: string; relatedTo: string }
+	| { type: 'manuallyLinked' };
 
 export interface GraphData {
 	nodes: GraphNode[];
 	edges: GraphEdge[];
+	hasMore: boolean;
 }
 
 export interface GraphNode {
@@ -122,6 +196,15 @@ export interface GraphEdge {
 	edgeType: string;
 }
 
+export interface ConnectionStep {
+	bookId: number;
+	title: string;
+	/** Type of the edge leading to this book (null for the starting book) */
+	edgeType: string | null;
+	/** Edge type for display, e.g. "shared author" */
+	label: string | null;
+}
+
 export interface OllamaStatus {
 	connected: boolean;
 	endpoint: string;
@@ -138,16 +221,45 @@ export interface ProcessingStatus {
 	isPaused: boolean;
 	estimatedTimeRemaining: number | null;
 	booksNeedingMetadata: number;
+	/** Unix seconds the background worker last checked in */
+	lastHeartbeat: number | null;
+	/** Work remains but the worker has gone silent; see `restartWorker` */
+	stalled: boolean;
 }
 
 export interface MetadataParsingResult {
 	processed: number;
 	success: number;
 	failed: number;
+	/** `failed`, broken down by file format */
+	failedByFormat: Partial<Record<BookFormat, number>>;
+	/** Transient failures (locked files, timeouts) that stay queued */
+	retrying: number;
 	remaining: number;
 	durationMs: number;
 }
 
+export interface EnrichmentResult {
+	checked: number;
+	/** Books that gained at least one field */
+	enriched: number;
+	/** Books the provider didn't know or had nothing new for */
+	notFound: number;
+	/** Lookups that errored; these books are tried again next time */
+	failed: number;
+	durationMs: number;
+}
+
+export type SimilarityMetric = 'cosine' | 'dotProduct' | 'negativeEuclidean';
+
+export type EmbeddingField = 'title' | 'author' | 'series' | 'description' | 'publisher' | 'tags' | 'language';
+
+export interface EmbeddingTextConfig {
+	fields: { field: EmbeddingField; weight: number }[];
+	/** Characters of chapter text used in place of a missing description; 0 turns this off */
+	excerptChars?: number;
+}
+
 export interface Settings {
 	ollamaEndpoint: string;
 	ollamaModel: string;
@@ -155,6 +267,36 @@ export interface Settings {
 	maxRecommendations: number;
 	autoScanEnabled: boolean;
 	scanIntervalMinutes: number;
+	similarityMetric: SimilarityMetric;
+	scanArchives: boolean;
+	dbPoolSize: number;
+	ollamaRequestsPerSecond: number;
+	embeddingFields: EmbeddingTextConfig;
+	recentReadsDays: number;
+	coverDir: string;
+	userWpm: number;
+	/** Embedding similarity a neighbor needs to be considered for any edge */
+	edgeCandidateFloor: number;
+	/** Embedding similarity a neighbor needs for a content edge */
+	contentEdgeFloor: number;
+	/** Look up books without a description online by ISBN */
+	enrichmentEnabled: boolean;
+	/** Enrichment provider: empty for OpenLibrary, or a URL with `{isbn}` */
+	enrichmentEndpoint: string;
+	/** Rating at or above which a book seeds personalized recommendations */
+	likedRatingThreshold: number;
+	/** Prune weak edges after each full graph rebuild */
+	edgePruneEnabled: boolean;
+	/** Strongest computed edges each book keeps when pruning */
+	edgePruneMaxPerBook: number;
+	/** Edges below this weight are dropped when pruning */
+	edgePruneMinWeight: number;
+	/** Time budget for interactive recommendations in ms (0 = unbounded) */
+	recommendationTimeoutMs: number;
+	/** Similar books precomputed per book when it's embedded (0 = off) */
+	similarCacheSize: number;
+	/** Candidate count from which similarity search is approximate (0 = always exact) */
+	annThreshold: number;
 }
 
 export interface BookUpdate {
@@ -189,9 +331,98 @@ export async function scanLibrary(id: number): Promise<ScanResult> {
 	return invoke('scan_library', { id });
 }
 
-export async function parseMetadataBatch(batchSize?: number): Promise<MetadataParsingResult> {
+/** Update a Calibre library's books from its metadata.db, keeping user data */
+export async function syncCalibreLibrary(id: number): Promise<CalibreSyncResult> {
+	const invoke = await getInvoke();
+	return invoke('sync_calibre_library', { id });
+}
+
+/** Index an EPUB file or folder without registering it as a library */
+export async function scanPath(path: string): Promise<ScanResult> {
+	const invoke = await getInvoke();
+	return invoke('scan_path', { path });
+}
+
+/** Fully parse one EPUB and add it, ready for embedding */
+export async function addSingleBook(path: string): Promise<Book> {
+	const invoke = await getInvoke();
+	return invoke('add_single_book', { path });
+}
+
+export async function hashLibrary(libraryId: number): Promise<HashResult> {
+	const invoke = await getInvoke();
+	return invoke('hash_library', { libraryId });
+}
+
+/** Hash every unhashed book in the background; progress arrives as `hash:progress` events */
+export async function computeMissingHashes(): Promise<number> {
+	const invoke = await getInvoke();
+	return invoke('compute_missing_hashes');
+}
+
+export async function getScanHistory(libraryId: number, limit?: number): Promise<ScanRecord[]> {
+	const invoke = await getInvoke();
+	return invoke('get_scan_history', { libraryId, limit });
+}
+
+export interface LibraryCoverage {
+	libraryId: number;
+	name: string;
+	total: number;
+	embedded: number;
+	pending: number;
+	failed: number;
+	skipped: number;
+}
+
+export async function getEmbeddingCoverage(): Promise<LibraryCoverage[]> {
+	const invoke = await getInvoke();
+	return invoke('get_embedding_coverage');
+}
+
+export interface TagCount {
+	name: string;
+	count: number;
+	percent: number;
+}
+
+export interface TagNode {
+	name: string;
+	/** Books tagged with this tag itself */
+	count: number;
+	/** Books tagged with this tag or any descendant */
+	total: number;
+	percent: number;
+	children: TagNode[];
+}
+
+export interface TagDistribution {
+	totalBooks: number;
+	top: TagCount[];
+	tree: TagNode[];
+}
+
+export async function getTagDistribution(topN?: number): Promise<TagDistribution> {
+	const invoke = await getInvoke();
+	return invoke('get_tag_distribution', { topN });
+}
+
+export async function parseMetadataBatch(
+	batchSize?: number,
+	concurrency?: number
+): Promise<MetadataParsingResult> {
+	const invoke = await getInvoke();
+	return invoke('parse_metadata_batch', { batchSize, concurrency });
+}
+
+export async function retrySkippedMetadata(): Promise<number> {
+	const invoke = await getInvoke();
+	return invoke('retry_skipped_metadata');
+}
+
+export async function enrichMetadata(limit?: number): Promise<EnrichmentResult> {
 	const invoke = await getInvoke();
-	return invoke('parse_metadata_batch', { batchSize });
+	return invoke('enrich_metadata', { limit });
 }
 
 // ============================================
@@ -218,6 +449,104 @@ export async function deleteBook(id: number): Promise<void> {
 	return invoke('delete_book', { id });
 }
 
+export interface CoverRefetchResult {
+	checked: number;
+	foundFiles: number;
+	extracted: number;
+	stillMissing: number;
+}
+
+export async function getBooksWithoutCover(limit?: number): Promise<Book[]> {
+	const invoke = await getInvoke();
+	return invoke('get_books_without_cover', { limit });
+}
+
+export async function refetchCovers(limit?: number): Promise<CoverRefetchResult> {
+	const invoke = await getInvoke();
+	return invoke('refetch_covers', { limit });
+}
+
+export interface CoverImportResult {
+	/** Image files whose name is an ISBN of a book in the library */
+	matched: number;
+	/** Image files with no matching book (or a name that isn't an ISBN) */
+	unmatched: number;
+	booksUpdated: number;
+}
+
+/** Set covers from a folder of images named by ISBN-10 or ISBN-13 */
+export async function importCoversByIsbn(dir: string): Promise<CoverImportResult> {
+	const invoke = await getInvoke();
+	return invoke('import_covers_by_isbn', { dir });
+}
+
+export interface CoverExtractionProgress {
+	processed: number;
+	total: number;
+}
+
+export interface CoverExtractionResult {
+	checked: number;
+	extracted: number;
+	noCover: number;
+	durationMs: number;
+}
+
+export async function extractAllCovers(): Promise<CoverExtractionResult> {
+	const invoke = await getInvoke();
+	return invoke('extract_all_covers');
+}
+
+export interface SyncDelta<T> {
+	items: T[];
+	serverTime: number;
+}
+
+export interface DeletedBook {
+	bookId: number;
+	path: string;
+	deletedAt: number;
+}
+
+export async function getBooksModifiedSince(timestamp: number): Promise<SyncDelta<Book>> {
+	const invoke = await getInvoke();
+	return invoke('get_books_modified_since', { timestamp });
+}
+
+export async function getDeletedSince(timestamp: number): Promise<SyncDelta<DeletedBook>> {
+	const invoke = await getInvoke();
+	return invoke('get_deleted_since', { timestamp });
+}
+
+export interface DuplicateGroup {
+	reason: 'isbn' | 'fileHash' | 'titleAuthor';
+	books: Book[];
+	suggestedKeepId: number;
+}
+
+export async function findDuplicateEditions(): Promise<DuplicateGroup[]> {
+	const invoke = await getInvoke();
+	return invoke('find_duplicate_editions');
+}
+
+export interface DuplicatesResult {
+	groups: DuplicateGroup[];
+	/** Books queued for hashing; they only match on title and author until hashed */
+	unhashed: number;
+}
+
+/** Find likely duplicate copies by file hash, then by title and author */
+export async function findDuplicates(): Promise<DuplicatesResult> {
+	const invoke = await getInvoke();
+	return invoke('find_duplicates');
+}
+
+export async function mergeDuplicateEditions(keepId: number, duplicateIds: number[]): Promise<number> {
+	const invoke = await getInvoke();
+	return invoke('merge_duplicate_editions', { keepId, duplicateIds });
+}
+
+/** Rate a book from 0.5 to 5 stars in half-star steps */
 export async function setRating(bookId: number, rating: number): Promise<void> {
 	const invoke = await getInvoke();
 	return invoke('set_rating', { bookId, rating });
@@ -228,6 +557,28 @@ export async function setReadStatus(bookId: number, status: ReadStatus): Promise
 	return invoke('set_read_status', { bookId, status });
 }
 
+/** Rate several books at once; returns how many were rated */
+export async function setRatingsBatch(bookIds: number[], rating: number): Promise<number> {
+	const invoke = await getInvoke();
+	return invoke('set_ratings_batch', { bookIds, rating });
+}
+
+/** Set the read status of several books at once; returns how many were updated */
+export async function setReadStatusBatch(bookIds: number[], status: ReadStatus): Promise<number> {
+	const invoke = await getInvoke();
+	return invoke('set_read_status_batch', { bookIds, status });
+}
+
+export async function setBookNotes(bookId: number, notes: string | null): Promise<void> {
+	const invoke = await getInvoke();
+	return invoke('set_book_notes', { bookId, notes });
+}
+
+export async function searchBookNotes(query: string, limit?: number): Promise<Book[]> {
+	const invoke = await getInvoke();
+	return invoke('search_book_notes', { query, limit });
+}
+
 export async function getCoverImage(bookId: number): Promise<string | null> {
 	const invoke = await getInvoke();
 	return invoke('get_cover_image', { bookId });
@@ -239,10 +590,46 @@ export async function getCoverImage(bookId: number): Promise<string | null> {
 
 export async function getRecommendations(
 	bookId?: number,
+	limit?: number,
+	edgeTypes?: string[],
+	sources?: string[],
+	/** 0 = pure relevance (default), 1 = maximum variety */
+	diversity?: number
+): Promise<Recommendation[]> {
+	const invoke = await getInvoke();
+	return invoke('get_recommendations', { bookId, limit, edgeTypes, sources, diversity });
+}
+
+export async function getRecommendationsForSet(
+	bookIds: number[],
 	limit?: number
 ): Promise<Recommendation[]> {
 	const invoke = await getInvoke();
-	return invoke('get_recommendations', { bookId, limit });
+	return invoke('get_recommendations_for_set', { bookIds, limit });
+}
+
+/** Nearest books by embedding, served from the precomputed cache when filled */
+export async function getSimilarBooks(bookId: number, limit?: number): Promise<Recommendation[]> {
+	const invoke = await getInvoke();
+	return invoke('get_similar_books', { bookId, limit });
+}
+
+export type RecommendationStrategy = 'traversal' | 'randomWalk';
+
+export interface AdvancedRecommendationOptions {
+	limit?: number;
+	strategy?: RecommendationStrategy;
+	walks?: number;
+	walkLength?: number;
+	seed?: number;
+}
+
+export async function getAdvancedRecommendations(
+	bookId: number,
+	options: AdvancedRecommendationOptions = {}
+): Promise<Recommendation[]> {
+	const invoke = await getInvoke();
+	return invoke('get_advanced_recommendations', { bookId, ...options });
 }
 
 export async function getPersonalizedRecommendations(limit?: number): Promise<Recommendation[]> {
@@ -250,13 +637,46 @@ export async function getPersonalizedRecommendations(limit?: number): Promise<Re
 	return invoke('get_personalized_recommendations', { limit });
 }
 
+export async function getBooksUnderTime(
+	maxMinutes: number,
+	limit?: number
+): Promise<TimedRecommendation[]> {
+	const invoke = await getInvoke();
+	return invoke('get_books_under_time', { maxMinutes, limit });
+}
+
+export async function getIsolatedBooks(minWeight?: number, limit?: number): Promise<Book[]> {
+	const invoke = await getInvoke();
+	return invoke('get_isolated_books', { minWeight, limit });
+}
+
+export async function getSerendipitousRecommendations(limit?: number): Promise<Recommendation[]> {
+	const invoke = await getInvoke();
+	return invoke('get_serendipitous_recommendations', { limit });
+}
+
+export async function getAuthorNetworkRecommendations(limit?: number): Promise<Recommendation[]> {
+	const invoke = await getInvoke();
+	return invoke('get_author_network_recommendations', { limit });
+}
+
 export async function getBookGraph(
 	centerId: number,
 	depth?: number,
-	maxNodes?: number
+	maxNodes?: number,
+	offset?: number,
+	excludeRead?: boolean
 ): Promise<GraphData> {
 	const invoke = await getInvoke();
-	return invoke('get_book_graph', { centerId, depth, maxNodes });
+	return invoke('get_book_graph', { centerId, depth, maxNodes, offset, excludeRead });
+}
+
+export async function getConnectionPath(
+	sourceId: number,
+	targetId: number
+): Promise<ConnectionStep[] | null> {
+	const invoke = await getInvoke();
+	return invoke('get_connection_path', { sourceId, targetId });
 }
 
 // ============================================
@@ -278,6 +698,12 @@ export async function getProcessingStatus(): Promise<ProcessingStatus> {
 	return invoke('get_processing_status');
 }
 
+/** Replace a stalled background worker */
+export async function restartWorker(): Promise<void> {
+	const invoke = await getInvoke();
+	return invoke('restart_worker');
+}
+
 export async function pauseProcessing(): Promise<void> {
 	const invoke = await getInvoke();
 	return invoke('pause_processing');
@@ -288,27 +714,109 @@ export async function resumeProcessing(): Promise<void> {
 	return invoke('resume_processing');
 }
 
+export async function cancelProcessing(): Promise<void> {
+	const invoke = await getInvoke();
+	return invoke('cancel_processing');
+}
+
 export async function prioritizeBook(bookId: number): Promise<void> {
 	const invoke = await getInvoke();
 	return invoke('prioritize_book', { bookId });
 }
 
+/** Queue embedding jobs that ran out of retries again; returns how many were queued */
+export async function retryFailedJobs(): Promise<number> {
+	const invoke = await getInvoke();
+	return invoke('retry_failed_jobs');
+}
+
 export interface ProcessingResult {
 	processed: number;
 	failed: number;
 	remaining: number;
+	/** Ollama stopped answering; remaining books are still pending */
+	backendUnavailable: boolean;
 	durationMs: number;
 }
 
-export async function processEmbeddingsBatch(batchSize?: number): Promise<ProcessingResult> {
+/** Embed a batch of pending books, up to `concurrency` (1-8) requests at a time */
+export async function processEmbeddingsBatch(batchSize?: number, concurrency?: number): Promise<ProcessingResult> {
 	const invoke = await getInvoke();
-	return invoke('process_embeddings_batch', { batchSize });
+	return invoke('process_embeddings_batch', { batchSize, concurrency });
+}
+
+/** Payload of the `embeddings:progress` event */
+export interface EmbeddingProgress {
+	bookId: number;
+	title: string;
+	processed: number;
+	failed: number;
+	remaining: number;
+	etaSeconds: number | null;
+}
+
+export interface DrainResult {
+	processed: number;
+	failed: number;
+	skipped: number;
+	interrupted: boolean;
+	backendUnavailable: boolean;
+	durationMs: number;
+}
+
+export async function processAllPending(concurrency?: number): Promise<DrainResult> {
+	const invoke = await getInvoke();
+	return invoke('process_all_pending', { concurrency });
+}
+
+export type PipelinePhase = 'metadata' | 'embeddings' | 'edges';
+
+export interface PipelineProgress {
+	phase: PipelinePhase;
+	done: number;
+	total: number;
+}
+
+export interface PipelineResult {
+	metadataParsed: number;
+	metadataFailed: number;
+	embeddings: DrainResult;
+	booksLinked: number;
+	edgesCreated: number;
+	interrupted: boolean;
+	durationMs: number;
+}
+
+/** Parse metadata, embed and link the whole library; listen to `pipeline:progress` */
+export async function rebuildAll(concurrency?: number): Promise<PipelineResult> {
+	const invoke = await getInvoke();
+	return invoke('rebuild_all', { concurrency });
+}
+
+export async function getEmbedding(bookId: number): Promise<number[] | null> {
+	const invoke = await getInvoke();
+	return invoke('get_embedding', { bookId });
 }
 
 // ============================================
 // Settings Commands
 // ============================================
 
+export interface VersionInfo {
+	appVersion: string;
+	schemaVersion: number;
+	latestSchemaVersion: number;
+	migrationPending: boolean;
+	embeddingTextVersion: number;
+	embeddingModel: string;
+	embeddingDimension: number;
+}
+
+export async function getVersionInfo(): Promise<VersionInfo> {
+	const invoke = await getInvoke();
+	return invoke('get_version_info');
+}
+
 export async function getSettings(): Promise<Settings> {
 	const invoke = await getInvoke();
 	return invoke('get_settings');
@@ -329,6 +837,14 @@ export interface DatabaseStats {
 	booksCount: number;
 	embeddingsCount: number;
 	embeddingsSizeBytes: number;
+	embeddingTextVersion: number;
+	staleEmbeddingsCount: number;
+	embeddingsByModel: ModelEmbeddingCount[];
+}
+
+export interface ModelEmbeddingCount {
+	model: string;
+	count: number;
 }
 
 export interface ClearEmbeddingsResult {
@@ -336,11 +852,39 @@ export interface ClearEmbeddingsResult {
 	booksReset: number;
 }
 
+export interface EmbeddingRepairReport {
+	loaded: number;
+	corruptBookIds: number[];
+	deleted: number;
+}
+
+export async function repairEmbeddings(deleteCorrupt?: boolean): Promise<EmbeddingRepairReport> {
+	const invoke = await getInvoke();
+	return invoke('repair_embeddings', { deleteCorrupt });
+}
+
+export async function regenerateSortFields(): Promise<number> {
+	const invoke = await getInvoke();
+	return invoke('regenerate_sort_fields');
+}
+
 export async function getDatabaseStats(): Promise<DatabaseStats> {
 	const invoke = await getInvoke();
 	return invoke('get_database_stats');
 }
 
+export interface PoolStats {
+	maxSize: number;
+	connections: number;
+	idle: number;
+	inUse: number;
+}
+
+export async function getPoolStats(): Promise<PoolStats> {
+	const invoke = await getInvoke();
+	return invoke('get_pool_stats');
+}
+
 export async function resetDatabase(): Promise<void> {
 	const invoke = await getInvoke();
 	return invoke('reset_database');
@@ -351,15 +895,46 @@ export async function clearEmbeddings(): Promise<ClearEmbeddingsResult> {
 	return invoke('clear_embeddings');
 }
 
+export async function clearEmbeddingsForLibrary(libraryId: number): Promise<ClearEmbeddingsResult> {
+	const invoke = await getInvoke();
+	return invoke('clear_embeddings_for_library', { libraryId });
+}
+
+export interface EdgeDistribution {
+	byType: Record<string, number>;
+	/** Counts in ten 0.1-wide weight buckets, lowest first */
+	weightHistogram: number[];
+}
+
 export interface RebuildGraphResult {
 	booksProcessed: number;
 	edgesCreated: number;
 	durationMs: number;
+	dryRun: boolean;
+	distribution: EdgeDistribution;
+	/** Edges removed by the post-rebuild prunes */
+	edgesPruned: number;
+}
+
+export async function rebuildGraphEdges(dryRun?: boolean, maxDegree?: number): Promise<RebuildGraphResult> {
+	const invoke = await getInvoke();
+	return invoke('rebuild_graph_edges', { dryRun, maxDegree });
 }
 
-export async function rebuildGraphEdges(): Promise<RebuildGraphResult> {
+export async function recomputeBookEdges(bookId: number): Promise<number> {
 	const invoke = await getInvoke();
-	return invoke('rebuild_graph_edges');
+	return invoke('recompute_book_edges', { bookId });
+}
+
+/** Link two books by hand; the edge survives graph rebuilds */
+export async function addManualEdge(sourceId: number, targetId: number, weight: number): Promise<void> {
+	const invoke = await getInvoke();
+	return invoke('add_manual_edge', { sourceId, targetId, weight });
+}
+
+export async function removeManualEdge(sourceId: number, targetId: number): Promise<boolean> {
+	const invoke = await getInvoke();
+	return invoke('remove_manual_edge', { sourceId, targetId });
 }
 
 export interface CleanupOrphanedResult {
@@ -383,18 +958,38 @@ export async function setDatabasePathPreference(path: string): Promise<void> {
 	return invoke('set_database_path_preference', { path });
 }
 
+export interface DatabaseMigrationResult {
+	path: string;
+	bytesCopied: number;
+	restartRequired: boolean;
+}
+
+export async function migrateDatabaseTo(newPath: string): Promise<DatabaseMigrationResult> {
+	const invoke = await getInvoke();
+	return invoke('migrate_database_to', { newPath });
+}
+
 // ============================================
 // Export/Backup Commands
 // ============================================
 
-export async function exportLibrary(path: string): Promise<void> {
+export async function exportLibrary(path: string, portable?: boolean): Promise<void> {
+	const invoke = await getInvoke();
+	return invoke('export_library', { path, portable });
+}
+
+export async function importLibrary(
+	path: string,
+	mergeMode: 'replace' | 'skip' | 'merge' = 'merge',
+	libraryRoots?: Record<string, string>
+): Promise<void> {
 	const invoke = await getInvoke();
-	return invoke('export_library', { path });
+	return invoke('import_library', { path, mergeMode, libraryRoots });
 }
 
-export async function importLibrary(path: string): Promise<void> {
+export async function exportEmbeddings(path: string): Promise<number> {
 	const invoke = await getInvoke();
-	return invoke('import_library', { path });
+	return invoke('export_embeddings', { path });
 }
 
 export async function createBackup(path: string): Promise<void> {
@@ -426,6 +1021,18 @@ export async function removeFromUpNext(bookId: number): Promise<void> {
 	return invoke('remove_from_up_next', { bookId });
 }
 
+/** Move a book within Up Next (0 = first; past the end moves it last) */
+export async function moveUpNext(bookId: number, position: number): Promise<void> {
+	const invoke = await getInvoke();
+	return invoke('move_up_next', { bookId, position });
+}
+
+/** Empty Up Next; returns how many books were removed */
+export async function clearUpNext(): Promise<number> {
+	const invoke = await getInvoke();
+	return invoke('clear_up_next');
+}
+
 export async function isInUpNext(bookId: number): Promise<boolean> {
 	const invoke = await getInvoke();
 	return invoke('is_in_up_next', { bookId });
@@ -441,6 +1048,77 @@ export async function getWantToReadBooks(): Promise<Book[]> {
 	return invoke('get_want_to_read_books');
 }
 
+export interface CurrentlyReading {
+	book: Book;
+	progressPercent: number | null;
+	lastReadAt: number | null;
+}
+
+export async function getCurrentlyReading(limit?: number): Promise<CurrentlyReading[]> {
+	const invoke = await getInvoke();
+	return invoke('get_currently_reading', { limit });
+}
+
+/** Record reading progress; returns the new read status if progress changed it */
+export async function setProgress(
+	bookId: number,
+	percent: number,
+	location?: string
+): Promise<ReadStatus | null> {
+	const invoke = await getInvoke();
+	return invoke('set_progress', { bookId, percent, location });
+}
+
+// ============================================
+// Collection Commands
+// ============================================
+
+export interface Collection {
+	id: number;
+	name: string;
+	createdAt: number;
+	bookCount: number;
+}
+
+export async function getCollections(): Promise<Collection[]> {
+	const invoke = await getInvoke();
+	return invoke('get_collections');
+}
+
+export async function getBookCollections(bookId: number): Promise<Collection[]> {
+	const invoke = await getInvoke();
+	return invoke('get_book_collections', { bookId });
+}
+
+/** Create an empty collection; names are unique, ignoring case */
+export async function createCollection(name: string): Promise<Collection> {
+	const invoke = await getInvoke();
+	return invoke('create_collection', { name });
+}
+
+export async function renameCollection(collectionId: number, name: string): Promise<Collection> {
+	const invoke = await getInvoke();
+	return invoke('rename_collection', { collectionId, name });
+}
+
+/** Delete a collection; its books stay in the library */
+export async function deleteCollection(collectionId: number): Promise<void> {
+	const invoke = await getInvoke();
+	return invoke('delete_collection', { collectionId });
+}
+
+/** Add books to a collection; returns how many were added */
+export async function addBooksToCollection(collectionId: number, bookIds: number[]): Promise<number> {
+	const invoke = await getInvoke();
+	return invoke('add_books_to_collection', { collectionId, bookIds });
+}
+
+/** Remove books from a collection; returns how many were removed */
+export async function removeBooksFromCollection(collectionId: number, bookIds: number[]): Promise<number> {
+	const invoke = await getInvoke();
+	return invoke('remove_books_from_collection', { collectionId, bookIds });
+}
+
 // ============================================
 // Utility Functions
 // ============================================
@@ -476,5 +1154,9 @@ export function getReasonText(reason: RecommendationReason): string {
 			return `Readers of "${reason.basedOn}" also liked`;
 		case 'nextInSeries':
 			return `Next after "${reason.previous}"`;
+		case 'relatedAuthor':
+			return `${reason.author} writes like ${reason.relatedTo}`;
+		case 'manuallyLinked':
+			return 'Linked by you';
 	}
 }
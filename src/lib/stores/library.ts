@@ -152,6 +152,12 @@ export async function setupScanEventListeners(): Promise<() => void> {
 	});
 	unlisteners.push(unlistenComplete);
 
+	// The folder watcher added, changed or removed books
+	const unlistenChanged = await listen('library:changed', () => {
+		loadBooks();
+	});
+	unlisteners.push(unlistenChanged);
+
 	return () => {
 		unlisteners.forEach((unlisten) => unlisten());
 	};